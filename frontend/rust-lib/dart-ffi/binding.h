@@ -11,4 +11,70 @@ const uint8_t *sync_event(const uint8_t *input, uintptr_t len);
 
 int32_t set_stream_port(int64_t port);
 
-void link_me_please(void);
\ No newline at end of file
+void link_me_please(void);
+
+typedef struct FlowyOTDocument FlowyOTDocument;
+
+typedef struct FlowyBuffer {
+  uint8_t *data;
+  uintptr_t len;
+} FlowyBuffer;
+
+enum FlowyOTStatus {
+  FlowyOTStatus_Ok = 0,
+  FlowyOTStatus_NullPointer = 1,
+  FlowyOTStatus_InvalidUtf8 = 2,
+  FlowyOTStatus_InvalidDelta = 3,
+  FlowyOTStatus_OutOfBound = 4,
+  FlowyOTStatus_UndoFail = 5,
+  FlowyOTStatus_RedoFail = 6,
+  FlowyOTStatus_Internal = 7,
+  /**
+   * The call panicked, the document may be in an inconsistent state and should be freed.
+   */
+  FlowyOTStatus_Panic = 8,
+  /**
+   * The change would remove the newline at the end of the document.
+   */
+  FlowyOTStatus_MissingTrailingNewline = 9,
+  /**
+   * The change would make the document longer than its limit.
+   */
+  FlowyOTStatus_QuotaExceeded = 10,
+};
+typedef int32_t FlowyOTStatus;
+
+FlowyOTDocument *flowy_ot_document_new(const uint8_t *delta, uintptr_t len);
+
+void flowy_ot_document_free(FlowyOTDocument *document);
+
+FlowyOTStatus flowy_ot_document_edit(FlowyOTDocument *document,
+                                     uintptr_t start,
+                                     uintptr_t end,
+                                     const uint8_t *text,
+                                     uintptr_t len,
+                                     FlowyBuffer *out);
+
+FlowyOTStatus flowy_ot_document_delete(FlowyOTDocument *document,
+                                       uintptr_t start,
+                                       uintptr_t end,
+                                       FlowyBuffer *out);
+
+FlowyOTStatus flowy_ot_document_format(FlowyOTDocument *document,
+                                       uintptr_t start,
+                                       uintptr_t end,
+                                       const uint8_t *attributes,
+                                       uintptr_t len,
+                                       FlowyBuffer *out);
+
+FlowyOTStatus flowy_ot_document_compose_remote(FlowyOTDocument *document,
+                                               const uint8_t *delta,
+                                               uintptr_t len);
+
+FlowyOTStatus flowy_ot_document_undo(FlowyOTDocument *document, FlowyBuffer *out);
+
+FlowyOTStatus flowy_ot_document_redo(FlowyOTDocument *document, FlowyBuffer *out);
+
+FlowyOTStatus flowy_ot_document_to_json(FlowyOTDocument *document, FlowyBuffer *out);
+
+void flowy_ot_buffer_free(FlowyBuffer buffer);
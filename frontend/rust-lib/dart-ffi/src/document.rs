@@ -0,0 +1,213 @@
+//! The documents for dart:ffi, without going through the dispatcher.
+//!
+//! Ownership:
+//! - The document returned by [flowy_ot_document_new] belongs to the caller until it's passed to
+//!   [flowy_ot_document_free], it must not be used from two threads at once.
+//! - The input pointers are only borrowed during the call.
+//! - The buffers written to `out` belong to the caller and are freed with [flowy_ot_buffer_free].
+//!   Nothing is written to `out` if the call fails.
+//!
+//! The deltas in the buffers are their json, in utf8.
+use flowy_collaboration::{
+    client_document::{ClientDocument, NewlineDoc},
+    errors::{CollaborateError, ErrorCode},
+};
+use lib_ot::{
+    core::Interval,
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice, str,
+};
+
+pub struct FlowyOTDocument {
+    document: ClientDocument,
+}
+
+#[repr(C)]
+pub struct FlowyBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowyOTStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidUtf8 = 2,
+    InvalidDelta = 3,
+    OutOfBound = 4,
+    UndoFail = 5,
+    RedoFail = 6,
+    Internal = 7,
+    /// The call panicked, the document may be in an inconsistent state and should be freed.
+    Panic = 8,
+    /// The change would remove the newline at the end of the document.
+    MissingTrailingNewline = 9,
+    /// The change would make the document longer than its limit.
+    QuotaExceeded = 10,
+}
+
+impl std::convert::From<CollaborateError> for FlowyOTStatus {
+    fn from(error: CollaborateError) -> Self {
+        log::error!("[FFI]: {}", error);
+        match error.code {
+            ErrorCode::OutOfBound => FlowyOTStatus::OutOfBound,
+            ErrorCode::UndoFail => FlowyOTStatus::UndoFail,
+            ErrorCode::RedoFail => FlowyOTStatus::RedoFail,
+            ErrorCode::MissingTrailingNewline => FlowyOTStatus::MissingTrailingNewline,
+            ErrorCode::QuotaExceeded => FlowyOTStatus::QuotaExceeded,
+            _ => FlowyOTStatus::Internal,
+        }
+    }
+}
+
+/// Creates a document from the json of its delta, or an empty document if `len` is 0. Returns
+/// null if the delta is invalid.
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_new(delta: *const u8, len: usize) -> *mut FlowyOTDocument {
+    let result = catch_unwind(|| {
+        let document = match len {
+            0 => ClientDocument::new::<NewlineDoc>(),
+            _ => {
+                let json = read_str(delta, len).ok()?;
+                ClientDocument::from_json(json).ok()?
+            }
+        };
+        Some(Box::into_raw(Box::new(FlowyOTDocument { document })))
+    });
+    result.ok().flatten().unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_free(document: *mut FlowyOTDocument) {
+    if !document.is_null() {
+        unsafe { drop(Box::from_raw(document)) };
+    }
+}
+
+/// Replaces the text in `[start, end)` with the utf8 `text`, which inserts it if the range is
+/// empty. The delta that was composed into the document is written to `out`.
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_edit(
+    document: *mut FlowyOTDocument,
+    start: usize,
+    end: usize,
+    text: *const u8,
+    len: usize,
+    out: *mut FlowyBuffer,
+) -> FlowyOTStatus {
+    with_document(document, out, |document| {
+        let text = read_str(text, len)?;
+        let delta = document.replace(Interval::new(start, end), text)?;
+        Ok(delta.to_json())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_delete(
+    document: *mut FlowyOTDocument,
+    start: usize,
+    end: usize,
+    out: *mut FlowyBuffer,
+) -> FlowyOTStatus {
+    with_document(document, out, |document| {
+        let delta = document.delete(Interval::new(start, end))?;
+        Ok(delta.to_json())
+    })
+}
+
+/// Formats `[start, end)` with the json of the attributes, e.g. `{"bold":true,"italic":null}`.
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_format(
+    document: *mut FlowyOTDocument,
+    start: usize,
+    end: usize,
+    attributes: *const u8,
+    len: usize,
+    out: *mut FlowyBuffer,
+) -> FlowyOTStatus {
+    with_document(document, out, |document| {
+        let attributes: RichTextAttributes =
+            serde_json::from_str(read_str(attributes, len)?).map_err(|_| FlowyOTStatus::InvalidDelta)?;
+        let delta = document.format_with(Interval::new(start, end), attributes)?;
+        Ok(delta.to_json())
+    })
+}
+
+/// Composes the json of the delta that was received from the others.
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_compose_remote(
+    document: *mut FlowyOTDocument,
+    delta: *const u8,
+    len: usize,
+) -> FlowyOTStatus {
+    with_document(document, ptr::null_mut(), |document| {
+        let delta = RichTextDelta::from_json(read_str(delta, len)?).map_err(|_| FlowyOTStatus::InvalidDelta)?;
+        document.compose_remote_delta(delta)?;
+        Ok(String::new())
+    })
+}
+
+/// Writes the delta that was composed into the document to `out`.
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_undo(document: *mut FlowyOTDocument, out: *mut FlowyBuffer) -> FlowyOTStatus {
+    with_document(document, out, |document| Ok(document.undo()?.delta.to_json()))
+}
+
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_redo(document: *mut FlowyOTDocument, out: *mut FlowyBuffer) -> FlowyOTStatus {
+    with_document(document, out, |document| Ok(document.redo()?.delta.to_json()))
+}
+
+#[no_mangle]
+pub extern "C" fn flowy_ot_document_to_json(document: *mut FlowyOTDocument, out: *mut FlowyBuffer) -> FlowyOTStatus {
+    with_document(document, out, |document| Ok(document.to_json()))
+}
+
+#[no_mangle]
+pub extern "C" fn flowy_ot_buffer_free(buffer: FlowyBuffer) {
+    if !buffer.data.is_null() {
+        unsafe { drop(Box::from_raw(slice::from_raw_parts_mut(buffer.data, buffer.len))) };
+    }
+}
+
+fn read_str<'a>(data: *const u8, len: usize) -> Result<&'a str, FlowyOTStatus> {
+    if len == 0 {
+        return Ok("");
+    }
+    if data.is_null() {
+        return Err(FlowyOTStatus::NullPointer);
+    }
+    let bytes = unsafe { slice::from_raw_parts(data, len) };
+    str::from_utf8(bytes).map_err(|_| FlowyOTStatus::InvalidUtf8)
+}
+
+// Runs `f` on the document and writes the string it returns to `out`, if `out` isn't null. The
+// panics are caught, they must not unwind into the caller.
+fn with_document<F>(document: *mut FlowyOTDocument, out: *mut FlowyBuffer, f: F) -> FlowyOTStatus
+where
+    F: FnOnce(&mut ClientDocument) -> Result<String, FlowyOTStatus>,
+{
+    if document.is_null() {
+        return FlowyOTStatus::NullPointer;
+    }
+    let document = unsafe { &mut (*document).document };
+    let output = match catch_unwind(AssertUnwindSafe(|| f(document))) {
+        Err(_) => return FlowyOTStatus::Panic,
+        Ok(Err(status)) => return status,
+        Ok(Ok(output)) => output,
+    };
+    if !out.is_null() {
+        let data = Box::into_raw(output.into_bytes().into_boxed_slice());
+        unsafe {
+            *out = FlowyBuffer {
+                data: data as *mut u8,
+                len: (*data).len(),
+            };
+        }
+    }
+    FlowyOTStatus::Ok
+}
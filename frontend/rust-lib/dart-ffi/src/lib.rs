@@ -1,5 +1,6 @@
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 mod c;
+mod document;
 mod model;
 mod protobuf;
 mod util;
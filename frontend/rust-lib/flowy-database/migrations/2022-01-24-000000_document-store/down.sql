@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX rev_table_doc_id_rev_id;
+ALTER TABLE rev_table DROP COLUMN author_id;
+ALTER TABLE rev_table DROP COLUMN device_id;
+ALTER TABLE rev_table DROP COLUMN timestamp;
+ALTER TABLE rev_table DROP COLUMN content_md5;
+ALTER TABLE rev_table DROP COLUMN clock;
+ALTER TABLE rev_table DROP COLUMN metadata;
+ALTER TABLE doc_table DROP COLUMN metadata;
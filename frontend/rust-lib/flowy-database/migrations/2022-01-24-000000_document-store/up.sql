@@ -0,0 +1,12 @@
+-- The fields of the revisions that the document store reads back, the clock and the metadata as json.
+ALTER TABLE rev_table ADD COLUMN author_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE rev_table ADD COLUMN device_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE rev_table ADD COLUMN timestamp BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE rev_table ADD COLUMN content_md5 TEXT NOT NULL DEFAULT '';
+ALTER TABLE rev_table ADD COLUMN clock TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE rev_table ADD COLUMN metadata TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE doc_table ADD COLUMN metadata TEXT NOT NULL DEFAULT '[]';
+
+-- A revision is written once, the inserts of the revisions that are there already are ignored.
+DELETE FROM rev_table WHERE id NOT IN (SELECT MIN(id) FROM rev_table GROUP BY doc_id, rev_id);
+CREATE UNIQUE INDEX rev_table_doc_id_rev_id ON rev_table (doc_id, rev_id);
@@ -18,6 +18,7 @@ table! {
         id -> Text,
         data -> Text,
         rev_id -> BigInt,
+        metadata -> Text,
     }
 }
 
@@ -30,6 +31,12 @@ table! {
         data -> Binary,
         state -> Integer,
         ty -> Integer,
+        author_id -> Text,
+        device_id -> Text,
+        timestamp -> BigInt,
+        content_md5 -> Text,
+        clock -> Text,
+        metadata -> Text,
     }
 }
 
@@ -0,0 +1,32 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use flowy_collaboration::client_document::{ClientDocument, NewlineDoc};
+
+const ONE_MB: usize = 1024 * 1024;
+
+fn paste_text(size: usize) -> String {
+    let line = "The quick brown fox jumps over the lazy dog. 敏捷的棕色狐狸跳过了懒狗。\n";
+    line.repeat(size / line.len() + 1)
+}
+
+fn bulk_insert_benchmark(c: &mut Criterion) {
+    let text = paste_text(ONE_MB);
+    c.bench_function("bulk insert 1MB into empty document", |b| {
+        b.iter(|| {
+            let mut document = ClientDocument::new::<NewlineDoc>();
+            document.bulk_insert(0, black_box(&text)).unwrap();
+        })
+    });
+
+    c.bench_function("bulk insert 1MB into 1MB document", |b| {
+        let mut origin = ClientDocument::new::<NewlineDoc>();
+        origin.bulk_insert(0, &text).unwrap();
+        let delta = origin.delta().clone();
+        b.iter(|| {
+            let mut document = ClientDocument::from_delta(delta.clone());
+            document.bulk_insert(ONE_MB / 2, black_box(&text)).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, bulk_insert_benchmark);
+criterion_main!(benches);
@@ -7,7 +7,17 @@ use crate::{
 };
 use bytes::Bytes;
 use flowy_collaboration::{
-    entities::{document_info::DocumentInfo, revision::Revision},
+    client_document::{
+        diff::DiffView,
+        export::{ExportOptions, Exporter, MarkdownExporter},
+        history::UndoResult,
+        stream::ContentStream,
+        DocumentEvent,
+    },
+    entities::{
+        document_info::{DocumentChunk, DocumentInfo},
+        revision::Revision,
+    },
     errors::CollaborateResult,
     util::make_delta_from_revisions,
 };
@@ -16,11 +26,14 @@ use flowy_sync::{
     RevisionCloudService, RevisionManager, RevisionObjectBuilder, RevisionWebSocket, RevisionWebSocketManager,
 };
 use lib_ot::{
-    core::{Interval, Operation},
+    core::{Interval, IntervalSet, Operation},
     rich_text::{RichTextAttribute, RichTextDelta},
 };
 use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+// The chunks of the content that are sent ahead of the receiver.
+const CONTENT_STREAM_BUFFER: usize = 2;
 
 pub struct ClientDocumentEditor {
     pub doc_id: String,
@@ -76,6 +89,18 @@ impl ClientDocumentEditor {
         Ok(())
     }
 
+    pub async fn bulk_insert<T: ToString>(&self, index: usize, data: T) -> Result<(), FlowyError> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<()>>();
+        let msg = EditorCommand::BulkInsert {
+            index,
+            data: data.to_string(),
+            ret,
+        };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let _ = rx.await.map_err(internal_error)??;
+        Ok(())
+    }
+
     pub async fn delete(&self, interval: Interval) -> Result<(), FlowyError> {
         let (ret, rx) = oneshot::channel::<CollaborateResult<()>>();
         let msg = EditorCommand::Delete { interval, ret };
@@ -96,6 +121,23 @@ impl ClientDocumentEditor {
         Ok(())
     }
 
+    /// Formats all the intervals, e.g. the matches of a search, in a single revision.
+    pub async fn format_intervals(
+        &self,
+        intervals: IntervalSet,
+        attribute: RichTextAttribute,
+    ) -> Result<(), FlowyError> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<()>>();
+        let msg = EditorCommand::FormatIntervals {
+            intervals,
+            attribute,
+            ret,
+        };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let _ = rx.await.map_err(internal_error)??;
+        Ok(())
+    }
+
     pub async fn replace<T: ToString>(&self, interval: Interval, data: T) -> Result<(), FlowyError> {
         let (ret, rx) = oneshot::channel::<CollaborateResult<()>>();
         let msg = EditorCommand::Replace {
@@ -108,6 +150,20 @@ impl ClientDocumentEditor {
         Ok(())
     }
 
+    /// Applies the Markdown mirror of the document that was edited outside of the editor. The
+    /// difference to the current content is saved as a local revision, so it's synced like any
+    /// other edit.
+    pub async fn sync_with_markdown<T: ToString>(&self, markdown: T) -> Result<(), FlowyError> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<()>>();
+        let msg = EditorCommand::SyncWithMarkdown {
+            markdown: markdown.to_string(),
+            ret,
+        };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let _ = rx.await.map_err(internal_error)??;
+        Ok(())
+    }
+
     pub async fn can_undo(&self) -> bool {
         let (ret, rx) = oneshot::channel::<bool>();
         let msg = EditorCommand::CanUndo { ret };
@@ -122,20 +178,38 @@ impl ClientDocumentEditor {
         rx.await.unwrap_or(false)
     }
 
-    pub async fn undo(&self) -> Result<(), FlowyError> {
-        let (ret, rx) = oneshot::channel();
+    pub async fn undo(&self) -> FlowyResult<UndoResult> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<UndoResult>>();
         let msg = EditorCommand::Undo { ret };
         let _ = self.edit_cmd_tx.send(msg).await;
-        let _ = rx.await.map_err(internal_error)??;
-        Ok(())
+        let result = rx.await.map_err(internal_error)??;
+        Ok(result)
     }
 
-    pub async fn redo(&self) -> Result<(), FlowyError> {
-        let (ret, rx) = oneshot::channel();
+    /// Removes the formatting that was applied last, the text typed since is kept.
+    pub async fn undo_last_format(&self) -> FlowyResult<UndoResult> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<UndoResult>>();
+        let msg = EditorCommand::UndoLastFormat { ret };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let result = rx.await.map_err(internal_error)??;
+        Ok(result)
+    }
+
+    pub async fn redo(&self) -> FlowyResult<UndoResult> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<UndoResult>>();
         let msg = EditorCommand::Redo { ret };
         let _ = self.edit_cmd_tx.send(msg).await;
-        let _ = rx.await.map_err(internal_error)??;
-        Ok(())
+        let result = rx.await.map_err(internal_error)??;
+        Ok(result)
+    }
+
+    /// Shows what the delta, e.g. of a merge or an import, would change before it's applied.
+    pub async fn preview_apply(&self, delta: RichTextDelta) -> FlowyResult<DiffView> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<DiffView>>();
+        let msg = EditorCommand::PreviewApply { delta, ret };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let view = rx.await.map_err(internal_error)??;
+        Ok(view)
     }
 
     pub async fn document_json(&self) -> FlowyResult<String> {
@@ -146,6 +220,44 @@ impl ClientDocumentEditor {
         Ok(json)
     }
 
+    /// The document as the Markdown of its mirror file.
+    pub async fn markdown(&self) -> FlowyResult<String> {
+        let delta = RichTextDelta::from_json(&self.document_json().await?)?;
+        let markdown = MarkdownExporter.export(&delta, &ExportOptions::default())?;
+        Ok(String::from_utf8_lossy(&markdown).into_owned())
+    }
+
+    /// Subscribes to the changes of the document, including the ones made by the other
+    /// participants and by undo/redo.
+    pub async fn subscribe(&self) -> FlowyResult<broadcast::Receiver<DocumentEvent>> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<broadcast::Receiver<DocumentEvent>>>();
+        let msg = EditorCommand::Subscribe { ret };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let receiver = rx.await.map_err(internal_error)??;
+        Ok(receiver)
+    }
+
+    /// Sends the content of the document in chunks of whole lines, from the chunk at `start` on,
+    /// so that the first lines are rendered before the rest arrives. The chunks are sent as the
+    /// receiver takes them, a slow receiver holds the next ones back. They are put back together
+    /// with a [ContentAssembler](flowy_collaboration::client_document::stream::ContentAssembler),
+    /// which also tells the chunk to resume from.
+    pub async fn stream_content(&self, chunk_size: usize, start: i64) -> FlowyResult<mpsc::Receiver<DocumentChunk>> {
+        let (ret, rx) = oneshot::channel::<CollaborateResult<ContentStream>>();
+        let msg = EditorCommand::StreamContent { chunk_size, start, ret };
+        let _ = self.edit_cmd_tx.send(msg).await;
+        let stream = rx.await.map_err(internal_error)??;
+        let (sender, receiver) = mpsc::channel(CONTENT_STREAM_BUFFER);
+        tokio::spawn(async move {
+            for chunk in stream {
+                if sender.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
     #[tracing::instrument(level = "trace", skip(self, data), err)]
     pub(crate) async fn compose_local_delta(&self, data: Bytes) -> Result<(), FlowyError> {
         let delta = RichTextDelta::from_bytes(&data)?;
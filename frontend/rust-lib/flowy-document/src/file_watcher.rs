@@ -0,0 +1,244 @@
+use crate::FlowyDocumentManager;
+use dashmap::DashMap;
+use flowy_collaboration::{client_document::DocumentEvent, util::md5};
+use flowy_error::{internal_error, FlowyResult};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone)]
+pub enum WorkspaceEvent {
+    /// The mirror of the document was changed by another program. `text` is the new content.
+    FileChanged {
+        doc_id: String,
+        path: PathBuf,
+        text: String,
+    },
+    FileRemoved {
+        doc_id: String,
+        path: PathBuf,
+    },
+}
+
+struct MirrorFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    md5: String,
+}
+
+/// Watches the files that mirror the documents on disk, e.g. the exported Markdown files or the
+/// notes of an imported vault, and reports the changes made by external editors.
+///
+/// The files are polled, which works the same way on every platform and for network drives.
+/// A change is only reported if the content differs from what was last seen or written by us.
+pub struct MirrorFileWatcher {
+    files: Arc<DashMap<String, MirrorFile>>,
+    notifier: broadcast::Sender<WorkspaceEvent>,
+    interval: Duration,
+}
+
+impl MirrorFileWatcher {
+    pub fn new(interval: Duration) -> Self {
+        let (notifier, _) = broadcast::channel(100);
+        Self {
+            files: Arc::new(DashMap::new()),
+            notifier,
+            interval,
+        }
+    }
+
+    pub fn watch<T: AsRef<str>>(&self, doc_id: T, path: PathBuf) {
+        let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        let md5 = std::fs::read_to_string(&path).map(md5).unwrap_or_default();
+        self.files
+            .insert(doc_id.as_ref().to_owned(), MirrorFile { path, modified, md5 });
+    }
+
+    pub fn unwatch<T: AsRef<str>>(&self, doc_id: T) {
+        self.files.remove(doc_id.as_ref());
+    }
+
+    /// Must be called after the mirror was written by the application, otherwise the write is
+    /// reported as an external edit on the next poll.
+    pub fn did_write_mirror<T: AsRef<str>>(&self, doc_id: T, text: &str) {
+        if let Some(mut file) = self.files.get_mut(doc_id.as_ref()) {
+            file.md5 = md5(text);
+        }
+    }
+
+    /// Writes `text` to the mirror of the document, which isn't reported as an external edit.
+    /// Does nothing if the document isn't watched.
+    pub async fn write_mirror<T: AsRef<str>>(&self, doc_id: T, text: &str) -> FlowyResult<()> {
+        write_file(&self.files, doc_id.as_ref(), text).await
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WorkspaceEvent> {
+        self.notifier.subscribe()
+    }
+
+    /// Starts polling the watched files. The polling stops after the watcher was dropped.
+    pub fn run(&self) {
+        let files = Arc::downgrade(&self.files);
+        let notifier = self.notifier.clone();
+        let mut interval = tokio::time::interval(self.interval);
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                if !poll_files(&files, &notifier).await {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+async fn poll_files(files: &Weak<DashMap<String, MirrorFile>>, notifier: &broadcast::Sender<WorkspaceEvent>) -> bool {
+    let files = match files.upgrade() {
+        None => return false,
+        Some(files) => files,
+    };
+
+    // Don't hold the guards of the map across the awaits.
+    let watched_files = files
+        .iter()
+        .map(|file| (file.key().clone(), file.path.clone(), file.modified))
+        .collect::<Vec<_>>();
+
+    for (doc_id, path, modified) in watched_files {
+        match tokio::fs::metadata(&path).await {
+            Err(_) => {
+                if modified.is_none() {
+                    continue;
+                }
+                if let Some(mut file) = files.get_mut(&doc_id) {
+                    file.modified = None;
+                    file.md5 = "".to_owned();
+                }
+                let _ = notifier.send(WorkspaceEvent::FileRemoved { doc_id, path });
+            }
+            Ok(metadata) => {
+                let new_modified = metadata.modified().ok();
+                if new_modified.is_some() && new_modified == modified {
+                    continue;
+                }
+
+                let text = match tokio::fs::read_to_string(&path).await {
+                    Ok(text) => text,
+                    Err(e) => {
+                        tracing::error!("Read mirror file {:?} failed: {:?}", path, e);
+                        continue;
+                    }
+                };
+                let new_md5 = md5(&text);
+                let is_changed = match files.get_mut(&doc_id) {
+                    None => false,
+                    Some(mut file) => {
+                        file.modified = new_modified;
+                        let is_changed = file.md5 != new_md5;
+                        file.md5 = new_md5;
+                        is_changed
+                    }
+                };
+                if is_changed {
+                    let _ = notifier.send(WorkspaceEvent::FileChanged { doc_id, path, text });
+                }
+            }
+        }
+    }
+    true
+}
+
+async fn write_file(files: &DashMap<String, MirrorFile>, doc_id: &str, text: &str) -> FlowyResult<()> {
+    let path = match files.get_mut(doc_id) {
+        None => return Ok(()),
+        Some(mut file) => {
+            file.md5 = md5(text);
+            file.path.clone()
+        }
+    };
+    tokio::fs::write(&path, text).await.map_err(internal_error)?;
+    let modified = tokio::fs::metadata(&path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if let Some(mut file) = files.get_mut(doc_id) {
+        file.modified = modified;
+    }
+    Ok(())
+}
+
+/// Applies the external edits reported by `watcher` to the documents. The mirrors are read as
+/// Markdown, see [ClientDocumentEditor::sync_with_markdown](crate::editor::ClientDocumentEditor::sync_with_markdown). The documents that aren't
+/// open yet are opened, so the edits are saved as revisions and synced like any other edit.
+pub fn bridge_file_watcher(manager: Arc<FlowyDocumentManager>, watcher: &MirrorFileWatcher) {
+    let mut receiver = watcher.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(WorkspaceEvent::FileChanged { doc_id, text, .. }) => {
+                    if let Err(e) = manager.receive_external_edit(&doc_id, text).await {
+                        tracing::error!("Apply the external edit of {} failed: {:?}", doc_id, e);
+                    }
+                }
+                Ok(WorkspaceEvent::FileRemoved { doc_id, path }) => {
+                    tracing::debug!("The mirror of {} was removed: {:?}", doc_id, path);
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("Skipped {} workspace events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Writes the changes of the document to its mirror as Markdown, the other direction of
+/// [bridge_file_watcher]. The changes made within the polling interval of the watcher are written
+/// at once. The writing stops after the document was closed or the watcher was dropped.
+pub async fn mirror_document<T: AsRef<str>>(
+    manager: &FlowyDocumentManager,
+    watcher: &MirrorFileWatcher,
+    doc_id: T,
+) -> FlowyResult<()> {
+    let doc_id = doc_id.as_ref().to_owned();
+    let editor = manager.open_document(&doc_id).await?;
+    let mut receiver = editor.subscribe().await?;
+    // Holding the editor would keep it open after the document was closed.
+    let editor = Arc::downgrade(&editor);
+    let files = Arc::downgrade(&watcher.files);
+    let interval = watcher.interval;
+    tokio::spawn(async move {
+        while wait_for_changes(&mut receiver, interval).await {
+            let (editor, files) = match (editor.upgrade(), files.upgrade()) {
+                (Some(editor), Some(files)) => (editor, files),
+                _ => break,
+            };
+            let result = match editor.markdown().await {
+                Ok(markdown) => write_file(&files, &doc_id, &markdown).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                tracing::error!("Write the mirror of {} failed: {:?}", doc_id, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+// Waits for a change of the document, then for the changes that follow it within `interval`.
+// Returns false if the document was closed without a change.
+async fn wait_for_changes(receiver: &mut broadcast::Receiver<DocumentEvent>, interval: Duration) -> bool {
+    if let Err(broadcast::error::RecvError::Closed) = receiver.recv().await {
+        return false;
+    }
+    tokio::time::sleep(interval).await;
+    loop {
+        match receiver.try_recv() {
+            Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+            Err(_) => return true,
+        }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod editor;
+#[cfg(feature = "file_watcher")]
+pub mod file_watcher;
 pub mod manager;
 mod queue;
 mod web_socket;
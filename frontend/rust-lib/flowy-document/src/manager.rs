@@ -2,10 +2,14 @@ use crate::{editor::ClientDocumentEditor, errors::FlowyError, DocumentCloudServi
 use async_trait::async_trait;
 use bytes::Bytes;
 use dashmap::DashMap;
-use flowy_collaboration::entities::{
-    document_info::{DocumentDelta, DocumentId},
-    revision::{md5, RepeatedRevision, Revision},
-    ws_data::ServerRevisionWSData,
+use flowy_collaboration::{
+    client_document::sync::SyncMode,
+    encryption::EncryptionProviderRef,
+    entities::{
+        document_info::{DocumentDelta, DocumentId},
+        revision::{md5, RepeatedRevision, Revision},
+        ws_data::ServerRevisionWSData,
+    },
 };
 use flowy_database::ConnectionPool;
 use flowy_error::FlowyResult;
@@ -19,6 +23,12 @@ pub trait DocumentUser: Send + Sync {
     fn user_id(&self) -> Result<String, FlowyError>;
     fn token(&self) -> Result<String, FlowyError>;
     fn db_pool(&self) -> Result<Arc<ConnectionPool>, FlowyError>;
+
+    /// How the local changes of the document are synced, e.g. the documents whose clients must
+    /// all see the same content only apply the changes that the server applied, see [SyncMode].
+    fn sync_mode(&self, _doc_id: &str) -> SyncMode {
+        SyncMode::default()
+    }
 }
 
 #[async_trait]
@@ -33,6 +43,7 @@ pub struct FlowyDocumentManager {
     rev_web_socket: Arc<dyn RevisionWebSocket>,
     document_handlers: Arc<DocumentEditorHandlers>,
     document_user: Arc<dyn DocumentUser>,
+    encryption: Option<EncryptionProviderRef>,
 }
 
 impl FlowyDocumentManager {
@@ -49,9 +60,16 @@ impl FlowyDocumentManager {
             rev_web_socket,
             document_handlers,
             document_user,
+            encryption: None,
         }
     }
 
+    /// Encrypts the revisions of the documents that are opened by the manager on the disk.
+    pub fn with_encryption(mut self, encryption: EncryptionProviderRef) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
     pub fn init(&self) -> FlowyResult<()> {
         listen_ws_state_changed(self.rev_web_socket.clone(), self.ws_data_receivers.clone());
 
@@ -94,6 +112,15 @@ impl FlowyDocumentManager {
         })
     }
 
+    #[tracing::instrument(level = "debug", skip(self, doc_id, markdown), fields(doc_id), err)]
+    pub async fn receive_external_edit<T: AsRef<str>>(&self, doc_id: T, markdown: String) -> FlowyResult<()> {
+        let doc_id = doc_id.as_ref();
+        tracing::Span::current().record("doc_id", &doc_id);
+        let editor = self.get_editor(doc_id).await?;
+        let _ = editor.sync_with_markdown(markdown).await?;
+        Ok(())
+    }
+
     pub async fn reset_with_revisions<T: AsRef<str>>(&self, doc_id: T, revisions: RepeatedRevision) -> FlowyResult<()> {
         let doc_id = doc_id.as_ref().to_owned();
         let db_pool = self.document_user.db_pool()?;
@@ -152,7 +179,12 @@ impl FlowyDocumentManager {
 
     fn make_rev_manager(&self, doc_id: &str, pool: Arc<ConnectionPool>) -> Result<RevisionManager, FlowyError> {
         let user_id = self.document_user.user_id()?;
-        let cache = Arc::new(RevisionCache::new(&user_id, doc_id, pool));
+        let cache = Arc::new(RevisionCache::with_encryption(
+            &user_id,
+            doc_id,
+            pool,
+            self.encryption.clone(),
+        ));
         Ok(RevisionManager::new(&user_id, doc_id, cache))
     }
 }
@@ -196,7 +228,7 @@ impl DocumentEditorHandlers {
 
     pub(crate) fn insert(&self, doc_id: &str, doc: &Arc<ClientDocumentEditor>) {
         if self.inner.contains_key(doc_id) {
-            log::warn!("Doc:{} already exists in cache", doc_id);
+            tracing::warn!("Doc:{} already exists in cache", doc_id);
         }
         self.inner.insert(doc_id.to_string(), doc.clone());
     }
@@ -3,7 +3,10 @@ use crate::DocumentUser;
 use async_stream::stream;
 use flowy_collaboration::util::make_delta_from_revisions;
 use flowy_collaboration::{
-    client_document::{history::UndoResult, ClientDocument},
+    client_document::{
+        clock::ClockRef, command::EditCommand, diff::DiffView, history::UndoResult, stream::ContentStream,
+        sync::SyncMode, ClientDocument, DocumentConfigBuilder, DocumentEvent,
+    },
     entities::revision::{RevId, Revision},
     errors::CollaborateError,
 };
@@ -11,11 +14,11 @@ use flowy_error::{FlowyError, FlowyResult};
 use flowy_sync::{DeltaMD5, RevisionCompact, RevisionManager, TransformDeltas};
 use futures::stream::StreamExt;
 use lib_ot::{
-    core::{Interval, OperationTransformable},
+    core::{Interval, IntervalSet, OperationTransformable},
     rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta},
 };
-use std::sync::Arc;
-use tokio::sync::{oneshot, RwLock};
+use std::{collections::VecDeque, sync::Arc};
+use tokio::sync::{broadcast, oneshot, RwLock};
 
 // The EditorCommandQueue executes each command that will alter the document in
 // serial.
@@ -24,6 +27,12 @@ pub(crate) struct EditorCommandQueue {
     user: Arc<dyn DocumentUser>,
     rev_manager: Arc<RevisionManager>,
     receiver: Option<EditorCommandReceiver>,
+    sync_mode: SyncMode,
+    // The clock of the document, which times the revisions while the document is locked.
+    clock: ClockRef,
+    // The rev_ids of the local revisions whose changes wait for the server in the authoritative
+    // sync mode, one for each intent of the document.
+    intent_rev_ids: RwLock<VecDeque<i64>>,
 }
 
 impl EditorCommandQueue {
@@ -33,12 +42,24 @@ impl EditorCommandQueue {
         delta: RichTextDelta,
         receiver: EditorCommandReceiver,
     ) -> Self {
-        let document = Arc::new(RwLock::new(ClientDocument::from_delta(delta)));
+        let sync_mode = user.sync_mode(&rev_manager.object_id);
+        let mut document = ClientDocument::from_delta(delta);
+        document.set_config(
+            DocumentConfigBuilder::new(&rev_manager.object_id)
+                .author(&user.user_id().unwrap_or_default(), "")
+                .sync_mode(sync_mode)
+                .build(),
+        );
+        let clock = document.config().clock.clone();
+        let document = Arc::new(RwLock::new(document));
         Self {
             document,
             user,
             rev_manager,
             receiver: Some(receiver),
+            sync_mode,
+            clock,
+            intent_rev_ids: RwLock::new(VecDeque::new()),
         }
     }
 
@@ -67,18 +88,33 @@ impl EditorCommandQueue {
         match command {
             EditorCommand::ComposeLocalDelta { delta, ret } => {
                 let mut document = self.document.write().await;
-                let _ = document.compose_delta(delta.clone())?;
+                // The delta that was composed, or the intent that waits for the server.
+                let delta = document.handle(EditCommand::ComposeDelta { delta })?.delta;
                 let md5 = document.md5();
                 drop(document);
                 let _ = self.save_local_delta(delta, md5).await?;
                 let _ = ret.send(Ok(()));
             }
             EditorCommand::ComposeRemoteDelta { client_delta, ret } => {
+                // The delta that doesn't fit makes the document wait for a resync, the error goes
+                // back to the sync.
                 let mut document = self.document.write().await;
-                let _ = document.compose_delta(client_delta.clone())?;
-                let md5 = document.md5();
+                let result = document.compose_remote_delta(client_delta).map(|_| document.md5());
                 drop(document);
-                let _ = ret.send(Ok(md5));
+                let _ = ret.send(result);
+            }
+            EditorCommand::AckRevision { rev_id, ret } => {
+                // The server applied the intents of the revisions up to `rev_id` as they are.
+                let mut intent_rev_ids = self.intent_rev_ids.write().await;
+                let mut document = self.document.write().await;
+                while intent_rev_ids
+                    .front()
+                    .map_or(false, |intent_rev_id| *intent_rev_id <= rev_id)
+                {
+                    let _ = intent_rev_ids.pop_front();
+                    let _ = document.acknowledge_intent()?;
+                }
+                let _ = ret.send(Ok(()));
             }
             EditorCommand::ResetDelta { delta, ret } => {
                 let mut document = self.document.write().await;
@@ -116,6 +152,13 @@ impl EditorCommandQueue {
                 let _ = self.save_local_delta(delta, md5).await?;
                 let _ = ret.send(Ok(()));
             }
+            EditorCommand::BulkInsert { index, data, ret } => {
+                let mut write_guard = self.document.write().await;
+                let delta = write_guard.bulk_insert(index, data)?;
+                let md5 = write_guard.md5();
+                let _ = self.save_local_delta(delta, md5).await?;
+                let _ = ret.send(Ok(()));
+            }
             EditorCommand::Delete { interval, ret } => {
                 let mut write_guard = self.document.write().await;
                 let delta = write_guard.delete(interval)?;
@@ -134,6 +177,19 @@ impl EditorCommandQueue {
                 let _ = self.save_local_delta(delta, md5).await?;
                 let _ = ret.send(Ok(()));
             }
+            EditorCommand::FormatIntervals {
+                intervals,
+                attribute,
+                ret,
+            } => {
+                let mut write_guard = self.document.write().await;
+                let delta = write_guard.format_intervals(&intervals, attribute)?;
+                if !delta.is_empty() {
+                    let md5 = write_guard.md5();
+                    let _ = self.save_local_delta(delta, md5).await?;
+                }
+                let _ = ret.send(Ok(()));
+            }
             EditorCommand::Replace { interval, data, ret } => {
                 let mut write_guard = self.document.write().await;
                 let delta = write_guard.replace(interval, data)?;
@@ -141,6 +197,15 @@ impl EditorCommandQueue {
                 let _ = self.save_local_delta(delta, md5).await?;
                 let _ = ret.send(Ok(()));
             }
+            EditorCommand::SyncWithMarkdown { markdown, ret } => {
+                let mut write_guard = self.document.write().await;
+                let delta = write_guard.sync_with_markdown(&markdown)?;
+                if !delta.is_empty() {
+                    let md5 = write_guard.md5();
+                    let _ = self.save_local_delta(delta, md5).await?;
+                }
+                let _ = ret.send(Ok(()));
+            }
             EditorCommand::CanUndo { ret } => {
                 let _ = ret.send(self.document.read().await.can_undo());
             }
@@ -149,17 +214,27 @@ impl EditorCommandQueue {
             }
             EditorCommand::Undo { ret } => {
                 let mut write_guard = self.document.write().await;
-                let UndoResult { delta } = write_guard.undo()?;
+                let result = write_guard.undo()?;
                 let md5 = write_guard.md5();
-                let _ = self.save_local_delta(delta, md5).await?;
-                let _ = ret.send(Ok(()));
+                let _ = self.save_local_delta(result.delta.clone(), md5).await?;
+                let _ = ret.send(Ok(result));
+            }
+            EditorCommand::UndoLastFormat { ret } => {
+                let mut write_guard = self.document.write().await;
+                let result = write_guard.undo_last_format()?;
+                let md5 = write_guard.md5();
+                let _ = self.save_local_delta(result.delta.clone(), md5).await?;
+                let _ = ret.send(Ok(result));
             }
             EditorCommand::Redo { ret } => {
                 let mut write_guard = self.document.write().await;
-                let UndoResult { delta } = write_guard.redo()?;
+                let result = write_guard.redo()?;
                 let md5 = write_guard.md5();
-                let _ = self.save_local_delta(delta, md5).await?;
-                let _ = ret.send(Ok(()));
+                let _ = self.save_local_delta(result.delta.clone(), md5).await?;
+                let _ = ret.send(Ok(result));
+            }
+            EditorCommand::PreviewApply { delta, ret } => {
+                let _ = ret.send(self.document.read().await.preview_apply(&delta));
             }
             EditorCommand::ReadDocumentAsJson { ret } => {
                 let data = self.document.read().await.to_json();
@@ -169,6 +244,14 @@ impl EditorCommandQueue {
                 let delta = self.document.read().await.delta().clone();
                 let _ = ret.send(Ok(delta));
             }
+            EditorCommand::Subscribe { ret } => {
+                let receiver = self.document.read().await.subscribe();
+                let _ = ret.send(Ok(receiver));
+            }
+            EditorCommand::StreamContent { chunk_size, start, ret } => {
+                let snapshot = self.document.read().await.read_snapshot();
+                let _ = ret.send(Ok(ContentStream::resume(snapshot, chunk_size, start)));
+            }
         }
         Ok(())
     }
@@ -184,11 +267,16 @@ impl EditorCommandQueue {
             delta_data,
             &user_id,
             md5,
-        );
+        )
+        .with_author(&user_id, "")
+        .with_timestamp(self.clock.now_millis());
         let _ = self
             .rev_manager
             .add_local_revision::<DocumentRevisionCompact>(&revision)
             .await?;
+        if self.sync_mode == SyncMode::Authoritative {
+            self.intent_rev_ids.write().await.push_back(rev_id);
+        }
         Ok(rev_id.into())
     }
 }
@@ -226,6 +314,10 @@ pub(crate) enum EditorCommand {
         client_delta: RichTextDelta,
         ret: Ret<DeltaMD5>,
     },
+    AckRevision {
+        rev_id: i64,
+        ret: Ret<()>,
+    },
     ResetDelta {
         delta: RichTextDelta,
         ret: Ret<DeltaMD5>,
@@ -239,6 +331,11 @@ pub(crate) enum EditorCommand {
         data: String,
         ret: Ret<()>,
     },
+    BulkInsert {
+        index: usize,
+        data: String,
+        ret: Ret<()>,
+    },
     Delete {
         interval: Interval,
         ret: Ret<()>,
@@ -248,11 +345,20 @@ pub(crate) enum EditorCommand {
         attribute: RichTextAttribute,
         ret: Ret<()>,
     },
+    FormatIntervals {
+        intervals: IntervalSet,
+        attribute: RichTextAttribute,
+        ret: Ret<()>,
+    },
     Replace {
         interval: Interval,
         data: String,
         ret: Ret<()>,
     },
+    SyncWithMarkdown {
+        markdown: String,
+        ret: Ret<()>,
+    },
     CanUndo {
         ret: oneshot::Sender<bool>,
     },
@@ -260,10 +366,17 @@ pub(crate) enum EditorCommand {
         ret: oneshot::Sender<bool>,
     },
     Undo {
-        ret: Ret<()>,
+        ret: Ret<UndoResult>,
+    },
+    UndoLastFormat {
+        ret: Ret<UndoResult>,
     },
     Redo {
-        ret: Ret<()>,
+        ret: Ret<UndoResult>,
+    },
+    PreviewApply {
+        delta: RichTextDelta,
+        ret: Ret<DiffView>,
     },
     ReadDocumentAsJson {
         ret: Ret<String>,
@@ -272,6 +385,14 @@ pub(crate) enum EditorCommand {
     ReadDocumentAsDelta {
         ret: Ret<RichTextDelta>,
     },
+    Subscribe {
+        ret: Ret<broadcast::Receiver<DocumentEvent>>,
+    },
+    StreamContent {
+        chunk_size: usize,
+        start: i64,
+        ret: Ret<ContentStream>,
+    },
 }
 
 impl std::fmt::Debug for EditorCommand {
@@ -279,18 +400,26 @@ impl std::fmt::Debug for EditorCommand {
         let s = match self {
             EditorCommand::ComposeLocalDelta { .. } => "ComposeLocalDelta",
             EditorCommand::ComposeRemoteDelta { .. } => "ComposeRemoteDelta",
+            EditorCommand::AckRevision { .. } => "AckRevision",
             EditorCommand::ResetDelta { .. } => "ResetDelta",
             EditorCommand::TransformDelta { .. } => "TransformDelta",
             EditorCommand::Insert { .. } => "Insert",
+            EditorCommand::BulkInsert { .. } => "BulkInsert",
             EditorCommand::Delete { .. } => "Delete",
             EditorCommand::Format { .. } => "Format",
+            EditorCommand::FormatIntervals { .. } => "FormatIntervals",
             EditorCommand::Replace { .. } => "Replace",
+            EditorCommand::SyncWithMarkdown { .. } => "SyncWithMarkdown",
             EditorCommand::CanUndo { .. } => "CanUndo",
             EditorCommand::CanRedo { .. } => "CanRedo",
             EditorCommand::Undo { .. } => "Undo",
+            EditorCommand::UndoLastFormat { .. } => "UndoLastFormat",
             EditorCommand::Redo { .. } => "Redo",
+            EditorCommand::PreviewApply { .. } => "PreviewApply",
             EditorCommand::ReadDocumentAsJson { .. } => "ReadDocumentAsJson",
             EditorCommand::ReadDocumentAsDelta { .. } => "ReadDocumentAsDelta",
+            EditorCommand::Subscribe { .. } => "Subscribe",
+            EditorCommand::StreamContent { .. } => "StreamContent",
         };
         f.write_str(s)
     }
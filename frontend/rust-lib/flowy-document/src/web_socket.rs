@@ -31,7 +31,9 @@ pub(crate) async fn make_document_ws_manager(
     rev_web_socket: Arc<dyn RevisionWebSocket>,
 ) -> Arc<RevisionWebSocketManager> {
     let composite_sink_provider = Arc::new(CompositeWSSinkDataProvider::new(&doc_id, rev_manager.clone()));
-    let resolve_target = Arc::new(DocumentRevisionResolveTarget { edit_cmd_tx });
+    let resolve_target = Arc::new(DocumentRevisionResolveTarget {
+        edit_cmd_tx: edit_cmd_tx.clone(),
+    });
     let resolver = RevisionConflictResolver::<RichTextAttributes>::new(
         &user_id,
         resolve_target,
@@ -40,6 +42,7 @@ pub(crate) async fn make_document_ws_manager(
     );
     let ws_stream_consumer = Arc::new(DocumentWSSteamConsumerAdapter {
         resolver: Arc::new(resolver),
+        edit_cmd_tx,
     });
 
     let sink_provider = Arc::new(DocumentWSSinkDataProviderAdapter(composite_sink_provider));
@@ -71,6 +74,7 @@ fn listen_document_ws_state(_user_id: &str, _doc_id: &str, mut subscriber: broad
 
 pub(crate) struct DocumentWSSteamConsumerAdapter {
     resolver: Arc<RevisionConflictResolver<RichTextAttributes>>,
+    edit_cmd_tx: EditorCommandSender,
 }
 
 impl RevisionWSSteamConsumer for DocumentWSSteamConsumerAdapter {
@@ -81,7 +85,19 @@ impl RevisionWSSteamConsumer for DocumentWSSteamConsumerAdapter {
 
     fn receive_ack(&self, id: String, ty: ServerRevisionWSDataType) -> BoxResultFuture<(), FlowyError> {
         let resolver = self.resolver.clone();
-        Box::pin(async move { resolver.ack_revision(id, ty).await })
+        let tx = self.edit_cmd_tx.clone();
+        Box::pin(async move {
+            let rev_id = id.parse::<i64>().map_err(internal_error)?;
+            let _ = resolver.ack_revision(id, ty).await?;
+            // The document applies the changes that waited for the server, if it syncs them in
+            // the authoritative mode.
+            let (ret, rx) = oneshot::channel();
+            tx.send(EditorCommand::AckRevision { rev_id, ret })
+                .await
+                .map_err(internal_error)?;
+            let _ = rx.await.map_err(internal_error)??;
+            Ok(())
+        })
     }
 
     fn receive_new_user_connect(&self, _new_user: NewDocumentUser) -> BoxResultFuture<(), FlowyError> {
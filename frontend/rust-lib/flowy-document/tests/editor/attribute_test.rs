@@ -1,9 +1,12 @@
 #![cfg_attr(rustfmt, rustfmt::skip)]
 use crate::editor::{TestBuilder, TestOp::*};
-use flowy_collaboration::client_document::{NewlineDoc, PlainDoc};
+use flowy_collaboration::client_document::{input_rules::InputRules, ClientDocument, NewlineDoc, PlainDoc, RECORD_THRESHOLD};
 use lib_ot::core::{Interval, OperationTransformable, NEW_LINE, WHITESPACE, FlowyStr};
 use unicode_segmentation::UnicodeSegmentation;
-use lib_ot::rich_text::RichTextDelta;
+use lib_ot::rich_text::{
+    AttributeBuilder, BlockAttribute, RichTextAttribute, RichTextAttributeKey, RichTextDelta, TypedAttributeValue,
+};
+use std::convert::TryFrom;
 
 #[test]
 fn attributes_bold_added() {
@@ -371,7 +374,7 @@ fn attributes_replace_with_text() {
         InsertBold(0, "123456", Interval::new(0, 6)),
         AssertDocJson(0, r#"[{"insert":"123456","attributes":{"bold":"true"}}]"#),
         Replace(0, Interval::new(0, 3), "ab"),
-        AssertDocJson(0, r#"[{"insert":"ab"},{"insert":"456","attributes":{"bold":"true"}}]"#),
+        AssertDocJson(0, r#"[{"insert":"ab456","attributes":{"bold":"true"}}]"#),
     ];
 
     TestBuilder::new().run_scripts::<PlainDoc>(ops);
@@ -575,7 +578,7 @@ fn attributes_link_auto_format_exist() {
         Insert(0, WHITESPACE, site.len()),
         AssertDocJson(
             0,
-            r#"[{"insert":"https://appflowy.io","attributes":{"link":"https://appflowy.io/"}},{"insert":" \n"}]"#,
+            r#"[{"insert":"https://appflowy.io","attributes":{"link":"https://appflowy.io"}},{"insert":" \n"}]"#,
         ),
     ];
 
@@ -598,6 +601,15 @@ fn attributes_link_auto_format_exist2() {
     TestBuilder::new().run_scripts::<NewlineDoc>(ops);
 }
 
+#[test]
+fn attributes_link_auto_format_off() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.set_auto_link(false);
+    document.insert(0, "https://appflowy.io").unwrap();
+    document.insert(19, NEW_LINE).unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"https://appflowy.io\n\n"}]"#);
+}
+
 #[test]
 fn attributes_bullet_added() {
     let ops = vec![
@@ -798,3 +810,241 @@ fn delta_compose() {
 
     TestBuilder::new().run_scripts::<NewlineDoc>(ops);
 }
+
+#[test]
+fn attributes_preserved_after_sync_with_text() {
+    let ops = vec![
+        Insert(0, "123456", 0),
+        Bold(0, Interval::new(3, 5), true),
+        SyncWithText(0, "12a34568"),
+        AssertDocJson(
+            0,
+            r#"[{"insert":"12a3"},{"insert":"45","attributes":{"bold":"true"}},{"insert":"68\n"}]"#,
+        ),
+        SyncWithText(0, "12a34568\n"),
+        AssertDocJson(
+            0,
+            r#"[{"insert":"12a3"},{"insert":"45","attributes":{"bold":"true"}},{"insert":"68\n"}]"#,
+        ),
+    ];
+    TestBuilder::new().run_scripts::<NewlineDoc>(ops);
+}
+
+#[test]
+fn attributes_format_with_several_attributes() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    document.format(Interval::new(0, 3), RichTextAttribute::Bold(true)).unwrap();
+
+    let rev_id = document.rev_id();
+    let attributes = AttributeBuilder::new()
+        .add_attr(RichTextAttribute::Header(1))
+        .remove_attr(RichTextAttributeKey::Bold)
+        .build();
+    document.format_with(Interval::new(0, 3), attributes).unwrap();
+    assert_eq!(document.rev_id(), rev_id + 1);
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"123"},{"insert":"\n","attributes":{"header":1}}]"#
+    );
+}
+
+#[test]
+fn attributes_format_block_replaces_line_kind() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123\n456").unwrap();
+    document.format(Interval::new(0, 1), RichTextAttribute::Header(1)).unwrap();
+    assert_eq!(document.line_at(5).map(|line| line.interval), Some(Interval::new(4, 8)));
+
+    let bullet = BlockAttribute::try_from(RichTextAttribute::Bullet(true)).unwrap();
+    document.format_block(Interval::new(2, 5), bullet).unwrap();
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"123"},{"insert":"\n","attributes":{"list":"bullet"}},{"insert":"456"},{"insert":"\n","attributes":{"list":"bullet"}}]"#
+    );
+    assert!(BlockAttribute::try_from(RichTextAttribute::Bold(true)).is_err());
+}
+
+#[test]
+fn attributes_lines_with_block_attributes() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123\n456\n789").unwrap();
+    document.format(Interval::new(4, 5), RichTextAttribute::Header(1)).unwrap();
+    document.format(Interval::new(4, 5), RichTextAttribute::Bold(true)).unwrap();
+
+    let lines = document.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>(),
+        vec!["123", "456", "789"]
+    );
+    assert_eq!(
+        lines[1].attributes,
+        AttributeBuilder::new().add_attr(RichTextAttribute::Header(1)).build()
+    );
+    assert!(lines[2].attributes.is_empty());
+
+    let line = document.line_at(7).unwrap();
+    assert_eq!((line.index, line.interval), (1, Interval::new(4, 8)));
+    assert_eq!(document.line_at(12), None);
+    assert_eq!(document.interval_of_line(2), Some(Interval::new(8, 12)));
+    assert_eq!(document.interval_of_line(3), None);
+}
+
+fn list_numbers(document: &ClientDocument) -> Vec<i64> {
+    document
+        .lines()
+        .map(
+            |line| match line.attributes.get_typed(&RichTextAttributeKey::ListNumber) {
+                Some(TypedAttributeValue::Int(number)) => number,
+                _ => 0,
+            },
+        )
+        .collect()
+}
+
+#[test]
+fn attributes_ordered_list_renumbered() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "a\nb\nc").unwrap();
+    document.format(Interval::new(0, 5), RichTextAttribute::Ordered(true)).unwrap();
+    assert_eq!(list_numbers(&document), vec![1, 2, 3]);
+    std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+
+    // The renumbering is part of the delta of the edit, so the others get it along.
+    let before = document.delta().clone();
+    let delta = document.insert(1, NEW_LINE).unwrap();
+    assert_eq!(list_numbers(&document), vec![1, 2, 3, 4]);
+    let mut other = ClientDocument::from_delta(before);
+    other.compose_delta(delta).unwrap();
+    assert_eq!(other.to_json(), document.to_json());
+    std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+
+    document.delete(Interval::new(0, 2)).unwrap();
+    assert_eq!(list_numbers(&document), vec![1, 2, 3]);
+    document.undo().unwrap();
+    assert_eq!(list_numbers(&document), vec![1, 2, 3, 4]);
+    document.undo().unwrap();
+    assert_eq!(list_numbers(&document), vec![1, 2, 3]);
+}
+
+#[test]
+fn attributes_toggle_code_block() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "12\n34").unwrap();
+    document.format(Interval::new(0, 2), RichTextAttribute::Bold(true)).unwrap();
+    document.toggle_code_block(Interval::new(1, 4), Some("rust")).unwrap();
+    let code_block = AttributeBuilder::new()
+        .add_attr(RichTextAttribute::CodeBlock(true))
+        .add_attr(RichTextAttribute::CodeLanguage("rust"))
+        .build();
+    assert!(document.lines().all(|line| line.attributes == code_block));
+    assert_eq!(
+        document.delta().to_canonical_json(),
+        r#"[{"insert":"12"},{"attributes":{"code_block":true,"code_language":"rust"},"insert":"\n"},{"insert":"34"},{"attributes":{"code_block":true,"code_language":"rust"},"insert":"\n"}]"#
+    );
+
+    // The text typed in the code block doesn't take the bold of the text before it.
+    document.format(Interval::new(3, 5), RichTextAttribute::Bold(true)).unwrap();
+    document.insert(5, "x").unwrap();
+    assert_eq!(
+        document.delta().to_canonical_json(),
+        r#"[{"insert":"12"},{"attributes":{"code_block":true,"code_language":"rust"},"insert":"\n"},{"attributes":{"bold":true},"insert":"34"},{"insert":"x"},{"attributes":{"code_block":true,"code_language":"rust"},"insert":"\n"}]"#
+    );
+
+    document.toggle_code_block(Interval::new(0, 6), None).unwrap();
+    assert!(document.lines().all(|line| line.attributes.is_empty()));
+}
+
+#[test]
+fn attributes_input_rules() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "abc\n\n").unwrap();
+    document.insert(0, "#").unwrap();
+    let before = document.delta().clone();
+    let delta = document.insert(1, " ").unwrap();
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"abc"},{"insert":"\n","attributes":{"header":1}},{"insert":"\n\n"}]"#
+    );
+    let mut other = ClientDocument::from_delta(before);
+    other.compose_delta(delta).unwrap();
+    assert_eq!(other.to_json(), document.to_json());
+
+    // Undoing the rule keeps the text that was typed.
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "# abc\n\n\n");
+    document.redo().unwrap();
+
+    document.insert(4, "1.").unwrap();
+    document.insert(6, " ").unwrap();
+    assert_eq!(list_numbers(&document), vec![0, 1, 0]);
+
+    // The rules don't apply in the code blocks, or once they are turned off.
+    document.toggle_code_block(Interval::new(5, 5), None).unwrap();
+    document.insert(5, "- ").unwrap();
+    document.set_input_rules(InputRules::empty());
+    document.insert(4, "> ").unwrap();
+    assert_eq!(document.to_plain_string(), "abc\n> \n- \n");
+}
+
+#[test]
+fn attributes_clear_format_keeps_links() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123456").unwrap();
+    document.format(Interval::new(0, 4), RichTextAttribute::Bold(true)).unwrap();
+    document.format(Interval::new(2, 6), RichTextAttribute::Link("https://appflowy.io")).unwrap();
+    document.format(Interval::new(0, 6), RichTextAttribute::Header(1)).unwrap();
+    let formatted = document.delta().clone();
+    std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+
+    document.clear_format(Interval::new(1, 6), &[RichTextAttributeKey::Link]).unwrap();
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"1","attributes":{"bold":true}},{"insert":"2"},{"insert":"3456","attributes":{"link":"https://appflowy.io"}},{"insert":"\n","attributes":{"header":1}}]"#
+    );
+
+    document.undo().unwrap();
+    assert_eq!(document.delta(), &formatted);
+}
+
+#[test]
+fn attributes_toggle_partially_formatted() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123456").unwrap();
+    document.format(Interval::new(0, 2), RichTextAttribute::Bold(true)).unwrap();
+
+    // The text that is partly bold is made all bold, then the bold is removed.
+    document.toggle_format(Interval::new(0, 4), RichTextAttribute::Bold(true)).unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"1234","attributes":{"bold":true}},{"insert":"56\n"}]"#);
+    document.toggle_format(Interval::new(0, 4), RichTextAttribute::Bold(true)).unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"123456\n"}]"#);
+
+    // A heading of another level is replaced instead of removed.
+    document.format(Interval::new(0, 6), RichTextAttribute::Header(2)).unwrap();
+    document.toggle_format(Interval::new(0, 6), RichTextAttribute::Header(1)).unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"123456"},{"insert":"\n","attributes":{"header":1}}]"#);
+    document.toggle_format(Interval::new(0, 6), RichTextAttribute::Header(1)).unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"123456\n"}]"#);
+}
+
+#[test]
+fn attributes_pending_at_collapsed_selection() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    let delta = document.format(Interval::new(3, 3), RichTextAttribute::Bold(true)).unwrap();
+    assert!(delta.is_empty());
+    assert_eq!(document.to_json(), r#"[{"insert":"123\n"}]"#);
+
+    document.insert(3, "4").unwrap();
+    assert!(document.pending_attributes().is_none());
+
+    // The selection moved before typing, so turning the bold off isn't applied.
+    document.format(Interval::new(4, 4), RichTextAttribute::Bold(false)).unwrap();
+    document.set_selection(Interval::new(1, 1));
+    assert!(document.pending_attributes().is_none());
+    document.insert(4, "5").unwrap();
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"123"},{"insert":"45","attributes":{"bold":true}},{"insert":"\n"}]"#
+    );
+}
@@ -0,0 +1,174 @@
+use flowy_collaboration::client_document::{
+    ast::{Block, BlockKind, DocumentTree, Span},
+    ClientDocument, InitialDocumentText,
+};
+use lib_ot::{
+    core::Interval,
+    rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+use std::path::PathBuf;
+
+/// Regenerates the golden files of the scenarios that run, instead of comparing with them:
+/// `UPDATE_GOLDEN=1 cargo test`.
+const UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+/// A step of an edit script, see [GoldenTest].
+#[derive(Clone, Debug)]
+pub enum GoldenOp {
+    Insert(usize, &'static str),
+    Delete(Interval),
+    Replace(Interval, &'static str),
+    Format(Interval, RichTextAttribute),
+    Undo,
+    Redo,
+    // The json of a delta made by another client, composed into the document.
+    Remote(&'static str),
+}
+
+/// Runs an edit script on a document and compares the json and the markdown of the result with
+/// the golden files `golden/<name>.json` and `golden/<name>.md`. The files are checked in, so a
+/// change of the editor's behavior shows up in the diff of the review.
+pub struct GoldenTest {
+    name: &'static str,
+}
+
+impl GoldenTest {
+    pub fn new(name: &'static str) -> Self {
+        Self { name }
+    }
+
+    pub fn run_scripts<C: InitialDocumentText>(self, scripts: Vec<GoldenOp>) {
+        let mut document = ClientDocument::new::<C>();
+        for op in scripts {
+            tracing::trace!("golden {}: {:?}", self.name, op);
+            match op {
+                GoldenOp::Insert(index, s) => {
+                    document.insert(index, s).unwrap();
+                }
+                GoldenOp::Delete(interval) => {
+                    document.replace(interval, "").unwrap();
+                }
+                GoldenOp::Replace(interval, s) => {
+                    document.replace(interval, s).unwrap();
+                }
+                GoldenOp::Format(interval, attribute) => {
+                    document.format(interval, attribute).unwrap();
+                }
+                GoldenOp::Undo => {
+                    document.undo().unwrap();
+                }
+                GoldenOp::Redo => {
+                    document.redo().unwrap();
+                }
+                GoldenOp::Remote(json) => {
+                    let delta = RichTextDelta::from_json(json).unwrap();
+                    document.compose_delta(delta).unwrap();
+                }
+            }
+        }
+        self.assert_golden("json", &document.to_json(), |expected, received| {
+            RichTextDelta::from_json(expected).ok() == RichTextDelta::from_json(received).ok()
+        });
+        self.assert_golden("md", &markdown(document.delta()), |expected, received| {
+            expected.trim_end() == received.trim_end()
+        });
+    }
+
+    fn assert_golden<F>(&self, extension: &str, received: &str, same: F)
+    where
+        F: Fn(&str, &str) -> bool,
+    {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/editor/golden")
+            .join(format!("{}.{}", self.name, extension));
+        if std::env::var(UPDATE_GOLDEN).is_ok() {
+            std::fs::write(&path, format!("{}\n", received)).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Can't read {:?}, run with {}=1 to write it: {}", path, UPDATE_GOLDEN, e));
+        if !same(&expected, received) {
+            log::error!("✅ expect: {}", expected);
+            log::error!("❌ receive: {}", received);
+        }
+        assert!(
+            same(&expected, received),
+            "{:?} doesn't match, run with {}=1 to update it",
+            path,
+            UPDATE_GOLDEN
+        );
+    }
+}
+
+/// The document as markdown, with the formats that markdown has.
+pub fn markdown(document: &RichTextDelta) -> String {
+    let mut lines: Vec<String> = vec![];
+    let mut in_code_block = false;
+    for block in DocumentTree::parse(document).blocks {
+        if !block.terminated && block.spans.is_empty() {
+            continue;
+        }
+        match (&block.kind, in_code_block) {
+            (BlockKind::CodeBlock { language }, false) => {
+                lines.push(format!("```{}", language.as_deref().unwrap_or("")));
+            }
+            (BlockKind::CodeBlock { .. }, true) => {}
+            (_, true) => lines.push("```".to_owned()),
+            (_, false) => {}
+        }
+        in_code_block = matches!(block.kind, BlockKind::CodeBlock { .. });
+        lines.push(markdown_line(&block));
+    }
+    if in_code_block {
+        lines.push("```".to_owned());
+    }
+    lines.join("\n")
+}
+
+fn markdown_line(block: &Block) -> String {
+    if let BlockKind::CodeBlock { .. } = block.kind {
+        return block.text();
+    }
+    let text = block.spans.iter().map(markdown_span).collect::<String>();
+    match &block.kind {
+        BlockKind::Heading(level) => format!("{} {}", "#".repeat(*level), text),
+        BlockKind::ListItem { list, indent, number } => {
+            let marker = match (list.as_str(), number) {
+                (_, Some(number)) => format!("{}.", number),
+                ("checked", None) => "- [x]".to_owned(),
+                ("unchecked", None) => "- [ ]".to_owned(),
+                _ => "-".to_owned(),
+            };
+            format!("{}{} {}", "  ".repeat(*indent), marker, text)
+        }
+        BlockKind::Quote => format!("> {}", text),
+        _ => text,
+    }
+}
+
+fn markdown_span(span: &Span) -> String {
+    let (mut text, attributes) = match span {
+        Span::Text { text, attributes } => (text.clone(), attributes),
+        Span::Embed { embed, attributes } => (format!("![{}]", embed.kind), attributes),
+    };
+    let is_on = |attributes: &RichTextAttributes, key: RichTextAttributeKey| {
+        attributes.get(&key).and_then(|value| value.as_bool()) == Some(true)
+    };
+    for (key, marker) in [
+        (RichTextAttributeKey::InlineCode, "`"),
+        (RichTextAttributeKey::StrikeThrough, "~~"),
+        (RichTextAttributeKey::Italic, "_"),
+        (RichTextAttributeKey::Bold, "**"),
+    ] {
+        if is_on(attributes, key) {
+            text = format!("{}{}{}", marker, text, marker);
+        }
+    }
+    match attributes
+        .get(&RichTextAttributeKey::Link)
+        .and_then(|value| value.as_str())
+    {
+        Some(link) => format!("[{}]({})", text, link),
+        None => text,
+    }
+}
@@ -0,0 +1,16 @@
+use crate::editor::golden::{GoldenOp::*, GoldenTest};
+use flowy_collaboration::client_document::NewlineDoc;
+use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+
+#[test]
+fn golden_format_undo_and_remote_edit() {
+    let ops = vec![
+        Insert(0, "hello world"),
+        Format(Interval::new(0, 5), RichTextAttribute::Bold(true)),
+        Undo,
+        Redo,
+        Remote(r#"[{"retain":11},{"insert":"!"},{"retain":1}]"#),
+        Format(Interval::new(0, 1), RichTextAttribute::Header(1)),
+    ];
+    GoldenTest::new("format_undo_and_remote_edit").run_scripts::<NewlineDoc>(ops);
+}
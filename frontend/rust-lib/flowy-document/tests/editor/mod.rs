@@ -1,5 +1,7 @@
 #![allow(clippy::module_inception)]
 mod attribute_test;
+mod golden;
+mod golden_test;
 mod op_test;
 mod serde_test;
 mod undo_redo_test;
@@ -20,6 +22,13 @@ pub enum TestOp {
     #[display(fmt = "Insert")]
     Insert(usize, &'static str, usize),
 
+    #[display(fmt = "BulkInsert")]
+    BulkInsert(usize, &'static str, usize),
+
+    // delta_i, the text that was edited outside of the editor
+    #[display(fmt = "SyncWithText")]
+    SyncWithText(usize, &'static str),
+
     // delta_i, s, start, length,
     #[display(fmt = "InsertBold")]
     InsertBold(usize, &'static str, Interval),
@@ -112,6 +121,20 @@ impl TestBuilder {
 
                 self.deltas.insert(*delta_i, Some(delta));
             }
+            TestOp::BulkInsert(delta_i, s, index) => {
+                let document = &mut self.documents[*delta_i];
+                let delta = document.bulk_insert(*index, s).unwrap();
+                tracing::debug!("Bulk insert delta: {}", delta.to_json());
+
+                self.deltas.insert(*delta_i, Some(delta));
+            }
+            TestOp::SyncWithText(delta_i, s) => {
+                let document = &mut self.documents[*delta_i];
+                let delta = document.sync_with_text(s).unwrap();
+                tracing::debug!("Sync with text delta: {}", delta.to_json());
+
+                self.deltas.insert(*delta_i, Some(delta));
+            }
             TestOp::Delete(delta_i, iv) => {
                 let document = &mut self.documents[*delta_i];
                 let delta = document.replace(*iv, "").unwrap();
@@ -1,9 +1,31 @@
 #![allow(clippy::all)]
 use crate::editor::{Rng, TestBuilder, TestOp::*};
-use flowy_collaboration::client_document::{NewlineDoc, PlainDoc};
+use flowy_collaboration::{
+    client_document::{
+        anchor::AnchorBias,
+        ast::{BlockChange, BlockKind},
+        diff::DiffKind,
+        normalize::NormalizeConfig,
+        search::FindOptions,
+        segmentation::{Segmenter, TextSegment},
+        spell_check::SpellCheck,
+        terms::{TermChanges, TermIndexer},
+        ClientDocument, DocumentConfig, DocumentConfigBuilder, EditingConfig, ForkPoint, HistoryConfig, NewlineDoc,
+        PlainDoc,
+    },
+    errors::ErrorCode,
+};
 use lib_ot::{
     core::*,
-    rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributes, RichTextDelta},
+    rich_text::{
+        AttributeBuilder, RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta,
+        RichTextDeltaBuilder,
+    },
+};
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 #[test]
@@ -134,6 +156,28 @@ fn delta_get_ops_in_interval_4() {
     );
 }
 
+#[test]
+fn delta_ops_in_interval() {
+    let mut delta = RichTextDelta::default();
+    delta.ops.push(OpBuilder::insert("12").build());
+    delta.ops.push(OpBuilder::insert("34").build());
+    delta.ops.push(OpBuilder::insert("56").build());
+
+    let slices = delta.ops_in(Interval::new(1, 4)).collect::<Vec<_>>();
+    assert_eq!(slices.len(), 2);
+    assert_eq!(slices[0].interval, Interval::new(1, 2));
+    assert!(!slices[0].is_whole());
+    assert!(slices[1].is_whole());
+    assert_eq!(
+        slices.iter().filter_map(|slice| slice.to_op()).collect::<Vec<_>>(),
+        DeltaIter::from_interval(&delta, Interval::new(1, 4)).ops()
+    );
+
+    let mut iter = DeltaIter::from_offset(&delta, 3);
+    assert_eq!(iter.peek_op(), Some(&OpBuilder::insert("4").build()));
+    assert_eq!(iter.next_op(), Some(OpBuilder::insert("4").build()));
+}
+
 #[test]
 fn delta_get_ops_in_interval_5() {
     let mut delta = RichTextDelta::default();
@@ -520,6 +564,43 @@ fn transform_with_two_delta() {
     );
 }
 
+#[test]
+fn merge_with_conflicting_attributes() {
+    let mut base = RichTextDelta::default();
+    base.insert("1234", RichTextAttributes::default());
+
+    let mut ours = RichTextDelta::default();
+    ours.insert("a", RichTextAttributes::default());
+    ours.retain(2, RichTextAttributes::default());
+    ours.retain(
+        2,
+        AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Color("red".to_owned()))
+            .build(),
+    );
+
+    let mut theirs = RichTextDelta::default();
+    theirs.retain(1, RichTextAttributes::default());
+    theirs.delete(1);
+    theirs.retain(
+        2,
+        AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Color("blue".to_owned()))
+            .build(),
+    );
+
+    let result = RichTextDelta::merge(&base, &ours, &theirs).unwrap();
+    assert_eq!(
+        r#"[{"insert":"a1"},{"insert":"34","attributes":{"color":"blue"}}]"#,
+        serde_json::to_string(&result.delta).unwrap()
+    );
+    assert_eq!(result.conflicts, vec![Interval::new(2, 4)]);
+
+    let mut other_base = RichTextDelta::default();
+    other_base.insert("12", RichTextAttributes::default());
+    assert!(RichTextDelta::merge(&other_base, &ours, &theirs).is_err());
+}
+
 #[test]
 fn transform_two_plain_delta() {
     let ops = vec![
@@ -532,6 +613,20 @@ fn transform_two_plain_delta() {
     TestBuilder::new().run_scripts::<PlainDoc>(ops);
 }
 
+#[test]
+fn transform_with_priority() {
+    let a = PlainDeltaBuilder::new().insert("abc").build();
+    let b = PlainDeltaBuilder::new().insert("123").build();
+    for (priority, expected) in vec![(Priority::Left, "abc123"), (Priority::Right, "123abc")] {
+        let (a_prime, b_prime) = a.transform_with_priority(&b, priority).unwrap();
+        assert_eq!(a.compose(&b_prime).unwrap().apply("").unwrap(), expected);
+        assert_eq!(b.compose(&a_prime).unwrap().apply("").unwrap(), expected);
+    }
+    // The replicas that order the deltas by the id of their users agree on the winner.
+    assert_eq!(Priority::by_id("alice", "bob"), Priority::Left);
+    assert_eq!(Priority::by_id("bob", "alice"), Priority::Right);
+}
+
 #[test]
 fn transform_two_plain_delta2() {
     let ops = vec![
@@ -727,6 +822,50 @@ fn delta_invert_attribute_delta_with_attribute_delta() {
     TestBuilder::new().run_scripts::<PlainDoc>(ops);
 }
 
+#[test]
+fn delta_invert_delete_and_format_across_split_ops() {
+    let bold = AttributeBuilder::new().add_attr(RichTextAttribute::Bold(true)).build();
+    let italic = AttributeBuilder::new()
+        .add_attr(RichTextAttribute::Italic(true))
+        .build();
+    let delta = DeltaBuilder::new()
+        .insert_with_attributes("12", bold.clone())
+        .insert_with_attributes("34", italic)
+        .insert("56")
+        .build();
+
+    let change = DeltaBuilder::new()
+        .retain(1)
+        .delete(2)
+        .retain_with_attributes(2, bold)
+        .insert("7")
+        .build();
+    let undo = change.invert(&delta);
+
+    let new_delta = delta.compose(&change).unwrap();
+    assert_eq!(new_delta.compose(&undo).unwrap(), delta);
+}
+
+#[test]
+fn delta_compose_and_invert_share_text() {
+    let bold = AttributeBuilder::new().add_attr(RichTextAttribute::Bold(true)).build();
+    let delta: RichTextDelta = DeltaBuilder::new()
+        .insert_with_attributes("123", bold)
+        .insert("456")
+        .build();
+    let text_of = |delta: &RichTextDelta, i: usize| match &delta.ops[i] {
+        Operation::Insert(insert) => insert.s.clone(),
+        op => panic!("{} isn't an insert", op),
+    };
+
+    let change = DeltaBuilder::new().retain(3).insert("7").build();
+    let new_delta = delta.compose(&change).unwrap();
+    assert!(text_of(&new_delta, 0).is_shared_with(&text_of(&delta, 0)));
+
+    let undo = DeltaBuilder::new().delete(3).build().invert(&delta);
+    assert!(text_of(&undo, 0).is_shared_with(&text_of(&delta, 0)));
+}
+
 #[test]
 fn delta_compose_str() {
     let ops = vec![
@@ -749,3 +888,663 @@ fn delta_compose_with_missing_delta() {
     ];
     TestBuilder::new().run_scripts::<NewlineDoc>(ops);
 }
+
+#[test]
+fn delta_embed_compose_transform_invert() {
+    let image = Embed::new("image", serde_json::json!({"src": "a.png"}));
+    let base: RichTextDelta = DeltaBuilder::new().insert("ab\n").build();
+    let a: RichTextDelta = DeltaBuilder::new()
+        .retain(1)
+        .insert_embed(image.clone())
+        .retain(2)
+        .build();
+    let b: RichTextDelta = DeltaBuilder::new().retain(2).insert("c").retain(1).build();
+
+    let composed = base.compose(&a).unwrap();
+    assert_eq!(
+        composed.to_json(),
+        r#"[{"insert":"a"},{"insert":{"image":{"src":"a.png"}}},{"insert":"b\n"}]"#
+    );
+    assert_eq!(composed.utf16_target_len, 4);
+
+    let (a_prime, b_prime) = a.transform(&b).unwrap();
+    assert_eq!(
+        composed.compose(&b_prime).unwrap(),
+        base.compose(&b).unwrap().compose(&a_prime).unwrap()
+    );
+
+    // The embed keeps its payload when it's formatted and when its delete is undone.
+    let bold = DeltaBuilder::new()
+        .retain(1)
+        .retain_with_attributes(1, RichTextAttribute::Bold(true).into())
+        .build();
+    let formatted = composed.compose(&bold).unwrap();
+    assert_eq!(formatted.ops[1].get_embed(), Some(&image));
+
+    let delete = DeltaBuilder::new().retain(1).delete(1).build();
+    let deleted = formatted.compose(&delete).unwrap();
+    assert_eq!(deleted.compose(&delete.invert(&formatted)).unwrap(), formatted);
+    assert_eq!(RichTextDelta::from_json(&formatted.to_json()).unwrap(), formatted);
+}
+
+#[test]
+fn document_remote_revision_integrity() {
+    let mut author = ClientDocument::new::<NewlineDoc>();
+    author.insert(0, "abc").unwrap();
+    let revision = author.revision(author.rev_id()).unwrap();
+    assert!(!revision.content_md5.is_empty());
+
+    let mut other = ClientDocument::new::<NewlineDoc>();
+    other.compose_remote_revision(&revision).unwrap();
+    assert_eq!(other.to_json(), author.to_json());
+
+    // The document that diverged from the author's rejects the revision and is left as it was.
+    let mut diverged = ClientDocument::new::<NewlineDoc>();
+    diverged.insert(0, "x").unwrap();
+    let before = diverged.to_json();
+    let error = diverged.compose_remote_revision(&revision).unwrap_err();
+    assert_eq!(error.code, ErrorCode::DocumentIntegrity);
+    assert_eq!(diverged.to_json(), before);
+}
+
+#[test]
+fn document_keeps_trailing_newline() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    let error = document.delete(Interval::new(2, 4)).unwrap_err();
+    assert_eq!(error.code, ErrorCode::MissingTrailingNewline);
+    let error = document
+        .compose_delta(DeltaBuilder::new().retain(3).delete(1).insert("4").build())
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::MissingTrailingNewline);
+    assert!(document.replace(Interval::new(0, 4), "abc").is_err());
+    assert_eq!(document.to_plain_string(), "123\n");
+
+    document.delete(Interval::new(0, 3)).unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"\n"}]"#);
+
+    // The documents that don't start with a newline aren't held to it.
+    let mut document = ClientDocument::new::<PlainDoc>();
+    document.insert(0, "123").unwrap();
+    document.delete(Interval::new(0, 3)).unwrap();
+    assert_eq!(document.to_json(), "[]");
+}
+
+#[test]
+fn document_composition_is_committed_once() {
+    let mut document = ClientDocument::from_delta(DeltaBuilder::new().insert("123\n").build());
+    document.begin_composition(3).unwrap();
+    document.update_composition("ni").unwrap();
+    document.update_composition("你").unwrap();
+    assert_eq!(document.to_plain_string(), "123\n");
+    assert!(!document.can_undo());
+
+    // The edits of the others move the composition along.
+    document
+        .compose_remote_delta(DeltaBuilder::new().insert("ab").build())
+        .unwrap();
+    assert_eq!(document.composition().unwrap().index, 5);
+
+    let delta = document.commit_composition().unwrap();
+    assert_eq!(delta.to_json(), r#"[{"retain":5},{"insert":"你"}]"#);
+    assert_eq!(document.to_plain_string(), "ab123你\n");
+    assert!(document.composition().is_none());
+    assert!(document.commit_composition().unwrap().is_empty());
+
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "ab123\n");
+    assert!(document.update_composition("a").is_err());
+}
+
+#[test]
+fn document_edit_at_many() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "foo bar foo").unwrap();
+    document
+        .format(Interval::new(8, 11), RichTextAttribute::Bold(true))
+        .unwrap();
+    let before = document.delta().clone();
+    let delta = document
+        .edit_at_many(vec![
+            (Interval::new(8, 11), "baz"),
+            (Interval::new(0, 3), "x"),
+            (Interval::new(4, 4), "_"),
+        ])
+        .unwrap();
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"x _bar "},{"insert":"baz","attributes":{"bold":true}},{"insert":"\n"}]"#
+    );
+    let mut other = ClientDocument::from_delta(before);
+    other.compose_delta(delta).unwrap();
+    assert_eq!(other.to_json(), document.to_json());
+    assert!(document
+        .edit_at_many(vec![(Interval::new(0, 2), "a"), (Interval::new(1, 3), "b")])
+        .is_err());
+
+    let intervals = vec![Interval::new(0, 1), Interval::new(7, 10)]
+        .into_iter()
+        .collect::<IntervalSet>();
+    document
+        .format_intervals(&intervals, RichTextAttribute::Italic(true))
+        .unwrap();
+    assert_eq!(
+        document.delta().to_canonical_json(),
+        r#"[{"attributes":{"italic":true},"insert":"x"},{"insert":" _bar "},{"attributes":{"bold":true,"italic":true},"insert":"baz"},{"insert":"\n"}]"#
+    );
+    document.undo().unwrap();
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "foo bar foo\n");
+}
+
+#[test]
+fn document_max_len() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.set_config(DocumentConfig {
+        max_len: Some(5),
+        ..DocumentConfig::default()
+    });
+    document.insert(0, "1234").unwrap();
+    let error = document.insert(4, "56").unwrap_err();
+    assert_eq!(error.code, ErrorCode::QuotaExceeded);
+    assert_eq!(
+        error.msg,
+        "The document would be 7 characters long, 2 over its limit of 5"
+    );
+    assert_eq!(document.to_plain_string(), "1234\n");
+
+    // The documents that are already over the limit can still be shortened.
+    document.set_config(DocumentConfig {
+        max_len: Some(2),
+        ..DocumentConfig::default()
+    });
+    assert!(document.insert(0, "0").is_err());
+    document.delete(Interval::new(0, 1)).unwrap();
+    assert_eq!(document.to_plain_string(), "234\n");
+}
+
+#[test]
+fn document_with_config() {
+    let config = DocumentConfigBuilder::new("page")
+        .author("user", "device")
+        .history(HistoryConfig {
+            capacity: 5,
+            ..HistoryConfig::default()
+        })
+        .editing(EditingConfig {
+            auto_link: false,
+            ..EditingConfig::default()
+        })
+        .build();
+    let mut document = ClientDocument::with_config::<NewlineDoc>(config.clone());
+    assert_eq!(document.config(), &config);
+    document.insert(0, "https://appflowy.io ").unwrap();
+    assert!(!document.to_json().contains(r#""link""#));
+
+    // The subsets are changed while the document is open, the rest of the config is kept.
+    document.set_editing_config(EditingConfig {
+        suggestion_mode: true,
+        ..EditingConfig::default()
+    });
+    assert!(document.is_suggestion_mode());
+    document.set_max_history_entry_size(1024);
+    assert_eq!(document.config().history.capacity, 5);
+    assert_eq!(document.config().history.max_entry_size, 1024);
+    assert_eq!(document.config().author_id, "user");
+}
+
+#[test]
+fn document_default_attributes() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    document
+        .format(Interval::new(0, 1), RichTextAttribute::Font("monospace"))
+        .unwrap();
+    let defaults = AttributeBuilder::new()
+        .add_attr(RichTextAttribute::Font("serif"))
+        .add_attr(RichTextAttribute::LineHeight(150))
+        .build();
+    document.set_default_attributes(defaults.clone());
+
+    // The un-styled text has the default font, so the fonts of the whole text differ.
+    let summary = document.attributes_in(Interval::new(1, 3));
+    assert_eq!(summary.value(&RichTextAttributeKey::Font), Some(&"serif".into()));
+    assert_eq!(
+        summary.value(&RichTextAttributeKey::LineHeight),
+        Some(&150_usize.into())
+    );
+    let summary = document.attributes_in(Interval::new(0, 3));
+    assert_eq!(summary.value(&RichTextAttributeKey::Font), None);
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"1","attributes":{"font":"monospace"}},{"insert":"23\n"}]"#
+    );
+
+    let bytes = document.encode_default_attributes().unwrap();
+    let mut restored = ClientDocument::from_delta(document.delta().clone());
+    restored.restore_default_attributes(&bytes).unwrap();
+    assert_eq!(restored.default_attributes(), &defaults);
+}
+
+#[test]
+fn document_words_agree_with_stats() {
+    // Splits the words at the whitespace only, like the rules of a locale would.
+    struct WhitespaceSegmenter();
+    impl Segmenter for WhitespaceSegmenter {
+        fn words(&self, text: &str) -> Vec<Range<usize>> {
+            text.split_whitespace()
+                .map(|word| {
+                    let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                    start..start + word.len()
+                })
+                .collect()
+        }
+
+        fn sentences(&self, text: &str) -> Vec<Range<usize>> {
+            vec![0..text.len()]
+        }
+    }
+
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "state-of-the-art editor").unwrap();
+    assert_eq!(document.word_at(2), Some(Interval::new(0, 5)));
+    assert_eq!(document.word_at(16), Some(Interval::new(13, 16)));
+    assert_eq!(document.word_at(17), Some(Interval::new(17, 23)));
+    assert_eq!(document.stats().words, 5);
+    assert_eq!(document.words_in(Interval::new(0, 23)).len(), 5);
+
+    document.set_segmenter(Arc::new(WhitespaceSegmenter()));
+    assert_eq!(document.word_at(2), Some(Interval::new(0, 16)));
+    assert_eq!(document.stats().words, 2);
+    assert_eq!(document.words_in(Interval::new(0, 23)).len(), 2);
+}
+
+#[test]
+fn document_spell_check() {
+    struct Dictionary();
+    impl SpellCheck for Dictionary {
+        fn check(&self, words: &[TextSegment]) -> Vec<Interval> {
+            words
+                .iter()
+                .filter(|word| !["hello", "world"].contains(&word.text.as_str()))
+                .map(|word| word.interval)
+                .collect()
+        }
+    }
+
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "helo world").unwrap();
+    document.set_spell_check(Arc::new(Dictionary()));
+    assert_eq!(document.misspellings(), &[Interval::new(0, 4)]);
+
+    // The misspellings move with the edits of the other words.
+    document.insert(5, "big ").unwrap();
+    assert_eq!(document.misspellings(), &[Interval::new(0, 4), Interval::new(5, 8)]);
+    document.insert(3, "l").unwrap();
+    assert_eq!(document.misspellings(), &[Interval::new(6, 9)]);
+    document.delete(Interval::new(0, 6)).unwrap();
+    assert_eq!(document.misspellings(), &[Interval::new(0, 3)]);
+}
+
+#[test]
+fn document_term_indexer() {
+    #[derive(Default)]
+    struct Index(Mutex<Vec<String>>);
+    impl TermIndexer for Index {
+        fn did_change_terms(&self, _doc_id: &str, changes: &TermChanges) {
+            let mut terms = self.0.lock().unwrap();
+            for term in &changes.removed {
+                terms.retain(|t| t != &term.text);
+            }
+            terms.extend(changes.added.iter().map(|term| term.text.clone()));
+        }
+    }
+
+    let index = Arc::new(Index::default());
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.set_term_indexer(index.clone());
+    document.insert(0, "hello world").unwrap();
+    document.insert(5, ",").unwrap();
+    document.replace(Interval::new(7, 12), "there").unwrap();
+    assert_eq!(*index.0.lock().unwrap(), vec!["hello", "there"]);
+
+    // Typing whitespace next to a word doesn't change the terms.
+    document.insert(12, " ").unwrap();
+    document.delete(Interval::new(0, 1)).unwrap();
+    assert_eq!(*index.0.lock().unwrap(), vec!["there", "ello"]);
+}
+
+#[test]
+fn document_fork() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.set_config(DocumentConfig {
+        doc_id: "page".to_owned(),
+        max_len: Some(20),
+        ..DocumentConfig::default()
+    });
+    document.insert(0, "123").unwrap();
+    document.insert(3, "456").unwrap();
+    let rev_id = document.rev_id();
+
+    let mut fork = document.fork("copy");
+    assert_eq!(fork.to_plain_string(), "123456\n");
+    assert_eq!(fork.rev_id(), 0);
+    assert!(!fork.can_undo());
+    assert_eq!(fork.config().doc_id, "copy");
+    assert_eq!(fork.config().max_len, Some(20));
+    assert_eq!(
+        fork.fork_point(),
+        Some(&ForkPoint {
+            doc_id: "page".to_owned(),
+            rev_id,
+        })
+    );
+    assert!(document.fork_point().is_none());
+
+    // The edits of the fork and of the document don't change each other.
+    fork.insert(0, "abc").unwrap();
+    document.delete(Interval::new(0, 3)).unwrap();
+    assert_eq!(fork.to_plain_string(), "abc123456\n");
+    assert_eq!(document.to_plain_string(), "456\n");
+    fork.undo().unwrap();
+    assert_eq!(fork.to_plain_string(), "123456\n");
+}
+
+#[test]
+fn document_slice_and_cut() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "12345").unwrap();
+    document
+        .format(Interval::new(1, 4), RichTextAttribute::Bold(true))
+        .unwrap();
+    document
+        .format(Interval::new(0, 5), RichTextAttribute::Header(1))
+        .unwrap();
+
+    let slice = document.slice(Interval::new(2, 6)).unwrap();
+    assert_eq!(
+        slice.to_json(),
+        r#"[{"insert":"34","attributes":{"bold":true}},{"insert":"5"},{"insert":"\n","attributes":{"header":1}}]"#
+    );
+
+    let (slice, delta) = document.cut(Interval::new(0, 3)).unwrap();
+    assert_eq!(
+        slice.to_json(),
+        r#"[{"insert":"1"},{"insert":"23","attributes":{"bold":true}}]"#
+    );
+    assert_eq!(delta.to_json(), r#"[{"delete":3}]"#);
+    assert_eq!(document.to_plain_string(), "45\n");
+}
+
+#[test]
+fn document_anchor_follows_the_changes() {
+    let mut document = ClientDocument::from_json(r#"[{"insert":"123\n"}]"#).unwrap();
+    let bookmark = document.create_anchor(1, AnchorBias::Left).unwrap();
+    let last_edit = document.create_anchor(1, AnchorBias::Right).unwrap();
+    assert!(document.create_anchor(10, AnchorBias::Left).is_err());
+
+    document.insert(1, "ab").unwrap();
+    assert_eq!(document.resolve_anchor(bookmark), Some(1));
+    assert_eq!(document.resolve_anchor(last_edit), Some(3));
+
+    // A remote change in front of the anchors.
+    document
+        .compose_remote_delta(RichTextDeltaBuilder::new().insert("x").build())
+        .unwrap();
+    assert_eq!(document.resolve_anchor(bookmark), Some(2));
+    assert_eq!(document.resolve_anchor(last_edit), Some(4));
+
+    document.undo().unwrap();
+    assert_eq!(document.resolve_anchor(bookmark), Some(2));
+    assert_eq!(document.resolve_anchor(last_edit), Some(2));
+
+    document.remove_anchor(bookmark);
+    assert_eq!(document.resolve_anchor(bookmark), None);
+}
+
+#[test]
+fn document_block_events() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "Title\ntext").unwrap();
+    let mut receiver = document.subscribe_blocks();
+    let ids = document
+        .blocks()
+        .unwrap()
+        .blocks
+        .iter()
+        .map(|block| block.id)
+        .collect::<Vec<_>>();
+
+    document
+        .format(Interval::new(0, 5), RichTextAttribute::Header(1))
+        .unwrap();
+    let event = receiver.try_recv().unwrap();
+    assert_eq!(
+        event.changes,
+        vec![BlockChange::KindChanged {
+            id: ids[0],
+            line: 0,
+            old: BlockKind::Paragraph,
+            new: BlockKind::Heading(1),
+        }]
+    );
+
+    document.insert(6, "more\n").unwrap();
+    let event = receiver.try_recv().unwrap();
+    assert!(matches!(
+        event.changes[..],
+        [BlockChange::Inserted {
+            line: 1,
+            kind: BlockKind::Paragraph,
+            ..
+        }]
+    ));
+    assert_eq!(event.rev_id, document.rev_id());
+    assert_eq!(document.blocks().unwrap().blocks[2].id, ids[1]);
+}
+
+#[test]
+fn document_outline() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "Title\ntext\nPart").unwrap();
+    document
+        .format(Interval::new(0, 5), RichTextAttribute::Header(1))
+        .unwrap();
+    let outline = document.outline();
+    assert_eq!(outline.len(), 1);
+    assert_eq!(outline[0].text, "Title");
+    assert_eq!(document.resolve_anchor(outline[0].anchor), Some(0));
+
+    document
+        .format(Interval::new(11, 15), RichTextAttribute::Header(2))
+        .unwrap();
+    document.insert(6, "intro\n").unwrap();
+    let outline = document
+        .outline()
+        .into_iter()
+        .map(|entry| (entry.level, entry.text, entry.interval))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outline,
+        vec![
+            (1, "Title".to_owned(), Interval::new(0, 5)),
+            (2, "Part".to_owned(), Interval::new(17, 21)),
+        ]
+    );
+}
+
+#[test]
+fn document_history_branches() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.set_history_config(HistoryConfig {
+        branching: true,
+        ..HistoryConfig::default()
+    });
+    document.insert(0, "a").unwrap();
+    document.undo().unwrap();
+    document.insert(0, "b").unwrap();
+    assert_eq!(document.history_branches().len(), 1);
+    assert!(!document.can_redo());
+
+    let id = document.history_branches()[0].id;
+    let results = document.switch_history_branch(id).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(document.to_plain_string(), "a\n");
+    // The change that was left is a branch now.
+    let id = document.history_branches()[0].id;
+    document.switch_history_branch(id).unwrap();
+    assert_eq!(document.to_plain_string(), "b\n");
+
+    document.linearize_history();
+    assert!(document.history_branches().is_empty());
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "\n");
+}
+
+#[test]
+fn document_toggle_checkbox() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "task\nnote").unwrap();
+    document
+        .format(Interval::new(0, 4), RichTextAttribute::UnChecked(true))
+        .unwrap();
+    let checked = |document: &ClientDocument| document.lines().next().unwrap().attributes;
+
+    document.toggle_checkbox(0).unwrap();
+    assert_eq!(checked(&document), RichTextAttribute::Checked(true).into());
+    document.toggle_checkbox(0).unwrap();
+    assert_eq!(checked(&document), RichTextAttribute::UnChecked(true).into());
+
+    assert!(document.toggle_checkbox(1).is_err());
+    assert!(document.toggle_checkbox(5).is_err());
+}
+
+#[test]
+fn document_format_search_matches() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "foo bar foo").unwrap();
+    let matches = document
+        .find("foo", &FindOptions::default())
+        .into_iter()
+        .collect::<IntervalSet>();
+    let delta = document
+        .format_intervals(&matches, RichTextAttribute::Bold(true))
+        .unwrap();
+    assert_eq!(
+        delta.to_canonical_json(),
+        r#"[{"attributes":{"bold":true},"retain":3},{"retain":5},{"attributes":{"bold":true},"retain":3}]"#
+    );
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"foo","attributes":{"bold":true}},{"insert":" bar "},{"insert":"foo","attributes":{"bold":true}},{"insert":"\n"}]"#
+    );
+
+    document.undo().unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"foo bar foo\n"}]"#);
+}
+
+#[test]
+fn document_normalize_typed_text() {
+    let mut document = ClientDocument::from_delta(RichTextDeltaBuilder::new().insert("say  \n").build());
+    document.set_editing_config(EditingConfig {
+        normalize: NormalizeConfig {
+            smart_quotes: true,
+            tab_width: Some(2),
+            trim_trailing_spaces: true,
+            ..NormalizeConfig::default()
+        },
+        ..EditingConfig::default()
+    });
+    let rev_id = document.rev_id();
+    document.insert(5, "\n\t\"hi\"").unwrap();
+    assert_eq!(document.to_plain_string(), "say\n  “hi”\n");
+    assert_eq!(document.rev_id(), rev_id + 1);
+
+    // The trimmed spaces come back with the undo of the insert.
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "say  \n");
+}
+
+#[test]
+fn document_preview_apply() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "hello world").unwrap();
+    let (json, rev_id) = (document.to_json(), document.rev_id());
+
+    let delta = RichTextDeltaBuilder::new().retain(6).delete(5).insert("there").build();
+    let view = document.preview_apply(&delta).unwrap();
+    let spans = view
+        .spans()
+        .iter()
+        .map(|span| (span.kind, span.text.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        vec![
+            (DiffKind::Unchanged, "hello "),
+            (DiffKind::Inserted, "there"),
+            (DiffKind::Deleted, "world"),
+            (DiffKind::Unchanged, "\n"),
+        ]
+    );
+    assert_eq!(document.to_json(), json);
+    assert_eq!(document.rev_id(), rev_id);
+
+    // The delta that can't be composed can't be previewed either.
+    let delta = RichTextDeltaBuilder::new().retain(11).delete(1).build();
+    assert!(document.preview_apply(&delta).is_err());
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "\n");
+}
+
+#[test]
+fn document_resync_from_snapshot() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "hello").unwrap();
+    let mut resync = document.subscribe_resync();
+
+    // The change of the server doesn't fit the document, which diverged.
+    let delta = RichTextDeltaBuilder::new().retain(20).insert("!").build();
+    assert!(document.compose_remote_delta(delta).is_err());
+    assert!(document.is_desynced());
+    let event = resync.try_recv().unwrap();
+    assert_eq!(event.rev_id, document.rev_id());
+    let delta = RichTextDeltaBuilder::new().insert("!").build();
+    let error = document.compose_remote_delta(delta).unwrap_err();
+    assert_eq!(error.code, ErrorCode::RevisionConflict);
+
+    // The local edits made while the document waits are rebased on the content of the server.
+    document.insert(5, " world").unwrap();
+    let snapshot = RichTextDeltaBuilder::new().insert("oh hello\n").build();
+    assert!(document
+        .reset_from_snapshot(snapshot.clone(), event.rev_id + 1)
+        .is_err());
+    let rebased = document.reset_from_snapshot(snapshot.clone(), event.rev_id).unwrap();
+    assert!(!document.is_desynced());
+    assert_eq!(document.to_plain_string(), "oh hello world\n");
+    assert_eq!(snapshot.compose(&rebased).unwrap().to_json(), document.to_json());
+}
+
+// Run with `cargo test --release -p flowy-document -- --ignored bulk_insert_1mb`, the debug build
+// is an order of magnitude slower. The best of a few runs is checked, which leaves out the runs
+// that the machine happened to slow down.
+#[test]
+#[ignore]
+fn bulk_insert_1mb_within_100ms() {
+    let line = "The quick brown fox jumps over the lazy dog. 敏捷的棕色狐狸跳过了懒狗。\n";
+    let text = line.repeat(1024 * 1024 / line.len() + 1);
+    let best = (0..5)
+        .map(|_| {
+            let mut document = ClientDocument::new::<NewlineDoc>();
+            let start = Instant::now();
+            document.bulk_insert(0, &text).unwrap();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    assert!(
+        best < Duration::from_millis(100),
+        "bulk insert 1MB took {:?}, expected < 100ms",
+        best
+    );
+}
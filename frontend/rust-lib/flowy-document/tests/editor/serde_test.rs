@@ -2,7 +2,10 @@ use flowy_collaboration::client_document::{ClientDocument, PlainDoc};
 use lib_ot::rich_text::RichTextOperation;
 use lib_ot::{
     core::*,
-    rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributeValue, RichTextDelta},
+    rich_text::{
+        AttributeBuilder, RichTextAttribute, RichTextAttributeKey, RichTextAttributeValue, RichTextDelta,
+        TypedAttributeValue,
+    },
 };
 
 #[test]
@@ -115,3 +118,71 @@ fn document_insert_serde_test() {
         ClientDocument::from_json(&json).unwrap().to_json()
     );
 }
+
+#[test]
+fn delta_serde_typed_attribute_test() {
+    let attributes = AttributeBuilder::new()
+        .add_attr(RichTextAttribute::typed(
+            RichTextAttributeKey::Size,
+            TypedAttributeValue::Int(14),
+        ))
+        .add_attr(RichTextAttribute::Link("https://appflowy.io"))
+        .add_attr(RichTextAttribute::Font("serif"))
+        .add_attr(RichTextAttribute::Bold(true))
+        .build();
+    let delta: RichTextDelta = DeltaBuilder::new().insert_with_attributes("123", attributes).build();
+
+    let delta = RichTextDelta::from_json(&delta.to_json()).unwrap();
+    let attributes = delta.ops[0].get_attributes();
+    assert_eq!(
+        attributes.get_typed(&RichTextAttributeKey::Size),
+        Some(TypedAttributeValue::Int(14))
+    );
+    assert_eq!(
+        attributes.get_typed(&RichTextAttributeKey::Link),
+        Some(TypedAttributeValue::String("https://appflowy.io".to_owned()))
+    );
+    assert_eq!(
+        attributes.get_typed(&RichTextAttributeKey::Font),
+        Some(TypedAttributeValue::String("serif".to_owned()))
+    );
+    assert_eq!(
+        attributes.get_typed(&RichTextAttributeKey::Bold),
+        Some(TypedAttributeValue::Bool(true))
+    );
+    assert_eq!(attributes.get_typed(&RichTextAttributeKey::Italic), None);
+}
+
+#[test]
+fn delta_deserialize_malformed_test() {
+    assert!(RichTextDelta::from_json(r#"[{"insert":"123","unknown":1}]"#).is_err());
+    assert!(RichTextDelta::from_json(r#"[{"retain":18446744073709551615},{"retain":1}]"#).is_err());
+    assert!(RichTextDelta::from_json(r#"[{"delete":2147483647},{"insert":"1"}]"#).is_ok());
+}
+
+#[test]
+fn delta_canonical_json_test() {
+    let bold = RichTextAttribute::Bold(true);
+    let italic = RichTextAttribute::Italic(true);
+    let mut delta1 = RichTextDelta::new();
+    delta1.insert(
+        "1\"2\n3",
+        AttributeBuilder::new()
+            .add_attr(bold.clone())
+            .add_attr(italic.clone())
+            .build(),
+    );
+    let mut delta2 = RichTextDelta::new();
+    delta2.insert(
+        "1\"2\n3",
+        AttributeBuilder::new().add_attr(italic).add_attr(bold).build(),
+    );
+
+    let json = delta1.to_canonical_json();
+    assert_eq!(
+        json,
+        r#"[{"attributes":{"bold":true,"italic":true},"insert":"1\"2\n3"}]"#
+    );
+    assert_eq!(json, delta2.to_canonical_json());
+    assert_eq!(RichTextDelta::from_json(&json).unwrap(), delta1);
+}
@@ -1,6 +1,13 @@
-use crate::editor::{TestBuilder, TestOp::*};
-use flowy_collaboration::client_document::{NewlineDoc, PlainDoc, RECORD_THRESHOLD};
-use lib_ot::core::{Interval, NEW_LINE, WHITESPACE};
+use crate::editor::{Rng, TestBuilder, TestOp::*};
+use flowy_collaboration::client_document::{
+    revision_log::DocumentSnapshot, search::FindOptions, ClientDocument, DocumentEventSource, NewlineDoc, PlainDoc,
+    CHUNK_LEN, RECORD_THRESHOLD,
+};
+use lib_ot::{
+    core::{Interval, OpBuilder, OperationTransformable, NEW_LINE, WHITESPACE},
+    rich_text::{RichTextAttribute, RichTextDeltaBuilder},
+};
+use rand::Rng as _;
 
 #[test]
 fn history_insert_undo() {
@@ -186,8 +193,7 @@ fn history_replace_undo() {
         AssertDocJson(
             0,
             r#"[
-            {"insert":"ab"},
-            {"insert":"3","attributes":{"bold":"true"}},{"insert":"\n"}]
+            {"insert":"ab3","attributes":{"bold":"true"}},{"insert":"\n"}]
             "#,
         ),
         Undo(0),
@@ -207,8 +213,7 @@ fn history_replace_undo_with_lagging() {
         AssertDocJson(
             0,
             r#"[
-            {"insert":"ab"},
-            {"insert":"3","attributes":{"bold":"true"}},{"insert":"\n"}]
+            {"insert":"ab3","attributes":{"bold":"true"}},{"insert":"\n"}]
             "#,
         ),
         Undo(0),
@@ -228,8 +233,7 @@ fn history_replace_redo() {
         AssertDocJson(
             0,
             r#"[
-            {"insert":"ab"},
-            {"insert":"3","attributes":{"bold":"true"}},{"insert":"\n"}]
+            {"insert":"ab3","attributes":{"bold":"true"}},{"insert":"\n"}]
             "#,
         ),
     ];
@@ -287,6 +291,8 @@ fn history_link_auto_format_undo_with_lagging() {
             r#"[{"insert":"https://appflowy.io","attributes":{"link":"https://appflowy.io/"}},{"insert":" \n"}]"#,
         ),
         Undo(0),
+        AssertDocJson(0, r#"[{"insert":"https://appflowy.io \n"}]"#),
+        Undo(0),
         AssertDocJson(0, r#"[{"insert":"https://appflowy.io\n"}]"#),
     ];
 
@@ -370,3 +376,294 @@ fn history_undo_attribute_on_merge_between_line() {
 
     TestBuilder::new().run_scripts::<NewlineDoc>(ops);
 }
+
+#[test]
+fn history_bulk_insert_undo() {
+    let ops = vec![
+        Insert(0, "123", 0),
+        Wait(RECORD_THRESHOLD),
+        BulkInsert(0, "456\n789", 3),
+        AssertDocJson(0, r#"[{"insert":"123456\n789\n"}]"#),
+        Undo(0),
+        AssertDocJson(0, r#"[{"insert":"123\n"}]"#),
+        Redo(0),
+        AssertDocJson(0, r#"[{"insert":"123456\n789\n"}]"#),
+    ];
+    TestBuilder::new().run_scripts::<NewlineDoc>(ops);
+}
+
+#[test]
+fn history_bulk_insert_undo_without_wait() {
+    // The bulk insert is an undo step of its own, even right after or before another edit.
+    let ops = vec![
+        Insert(0, "123", 0),
+        BulkInsert(0, "456\n789", 3),
+        Insert(0, "0", 10),
+        AssertDocJson(0, r#"[{"insert":"123456\n7890\n"}]"#),
+        Undo(0),
+        AssertDocJson(0, r#"[{"insert":"123456\n789\n"}]"#),
+        Undo(0),
+        AssertDocJson(0, r#"[{"insert":"123\n"}]"#),
+    ];
+    TestBuilder::new().run_scripts::<NewlineDoc>(ops);
+}
+
+#[test]
+fn history_apply_chunked_undo() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    let initial_delta = document.delta().clone();
+
+    let text = "a".repeat(CHUNK_LEN);
+    let ops = vec![
+        OpBuilder::retain(1).build(),
+        OpBuilder::insert(&text).build(),
+        OpBuilder::delete(1).build(),
+        OpBuilder::insert(&text).build(),
+    ];
+    let mut progress = vec![];
+    document.apply_chunked(ops, |offset| progress.push(offset)).unwrap();
+    assert_eq!(progress, vec![1 + CHUNK_LEN, 1 + 2 * CHUNK_LEN]);
+    assert_eq!(document.to_plain_string(), format!("1{}{}3\n", text, text));
+
+    // The chunks are undone at once.
+    document.undo().unwrap();
+    assert_eq!(document.delta(), &initial_delta);
+}
+
+#[test]
+fn history_large_entry_keeps_revision() {
+    let text = "a".repeat(4096);
+    let inserted = RichTextDeltaBuilder::new().insert(&format!("{}\n", text)).build();
+    let mut document = ClientDocument::from_delta(inserted.clone());
+    document.set_max_history_entry_size(1024);
+
+    // The inverse of the delete holds the deleted text, so the entry keeps the revision instead.
+    document.delete(Interval::new(0, 4096)).unwrap();
+    assert_eq!(document.to_plain_string(), "\n");
+    document.undo().unwrap();
+    assert_eq!(document.delta(), &inserted);
+    document.redo().unwrap();
+    assert_eq!(document.to_plain_string(), "\n");
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), format!("{}\n", text));
+}
+
+#[test]
+fn history_undo_redo_notify_subscribers() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    let mut receiver = document.subscribe();
+    let initial_delta = document.delta().clone();
+    document.insert(0, "123").unwrap();
+    document.undo().unwrap();
+    document.redo().unwrap();
+
+    let mut events = vec![];
+    while let Ok(event) = receiver.try_recv() {
+        events.push(event);
+    }
+    let sources = events.iter().map(|event| event.source).collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        vec![
+            DocumentEventSource::Local,
+            DocumentEventSource::Undo,
+            DocumentEventSource::Redo
+        ]
+    );
+    assert_eq!(events.last().unwrap().rev_id, document.rev_id());
+
+    // Composing the inverted delta of the first change brings back the initial document.
+    let inserted = initial_delta.compose(&events[0].delta).unwrap();
+    assert_eq!(inserted.compose(&events[0].inverted).unwrap(), initial_delta);
+}
+
+#[test]
+fn history_replace_all_undo() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "foo bar foo").unwrap();
+    document
+        .format(Interval::new(8, 11), RichTextAttribute::Bold(true))
+        .unwrap();
+
+    document.replace_all("foo", "baz", &FindOptions::default()).unwrap();
+    assert_eq!(
+        document.to_json(),
+        r#"[{"insert":"baz bar "},{"insert":"baz","attributes":{"bold":true}},{"insert":"\n"}]"#
+    );
+
+    // All the replacements are undone at once.
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "foo bar foo\n");
+}
+
+#[test]
+fn history_replace_single_revision() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+
+    let rev_id = document.rev_id();
+    document.replace(Interval::new(1, 3), "ab").unwrap();
+    assert_eq!(document.rev_id(), rev_id + 1);
+    assert_eq!(document.to_plain_string(), "1ab\n");
+
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "123\n");
+}
+
+#[test]
+fn history_undo_only_local_changes() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    let remote = RichTextDeltaBuilder::new().insert("abc").build();
+    document.compose_remote_delta(remote).unwrap();
+    assert_eq!(document.to_plain_string(), "abc123\n");
+
+    // The text of the other participant is kept.
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "abc\n");
+    assert!(!document.can_undo());
+    document.redo().unwrap();
+    assert_eq!(document.to_plain_string(), "abc123\n");
+}
+
+#[test]
+fn history_undo_result_caret() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+    document.insert(3, "45").unwrap();
+
+    let result = document.undo().unwrap();
+    assert_eq!(result.delta, RichTextDeltaBuilder::new().retain(3).delete(2).build());
+    assert_eq!(result.caret, 3);
+
+    let result = document.redo().unwrap();
+    assert_eq!(result.delta, RichTextDeltaBuilder::new().retain(3).insert("45").build());
+    assert_eq!(result.caret, 5);
+}
+
+#[test]
+fn history_random_undo_redo() {
+    let mut rng = Rng::default();
+    for _ in 0..100 {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_check_history(true);
+        for _ in 0..20 {
+            // The length of the text, without the newline at the end of the document.
+            let len = document.delta().utf16_target_len - 1;
+            match rng.0.gen_range(0, 5) {
+                0 if document.can_undo() => {
+                    document.undo().unwrap();
+                }
+                1 if document.can_redo() => {
+                    document.redo().unwrap();
+                }
+                2 if len > 0 => {
+                    let start = rng.0.gen_range(0, len);
+                    let end = rng.0.gen_range(start + 1, len + 1);
+                    document.delete(Interval::new(start, end)).unwrap();
+                }
+                3 if len > 0 => {
+                    let start = rng.0.gen_range(0, len);
+                    let end = rng.0.gen_range(start + 1, len + 1);
+                    let bold = rng.0.gen_range(0, 2) == 0;
+                    document
+                        .format(Interval::new(start, end), RichTextAttribute::Bold(bold))
+                        .unwrap();
+                }
+                _ => {
+                    let index = rng.0.gen_range(0, len + 1);
+                    let len = rng.0.gen_range(1, 5);
+                    document.insert(index, rng.gen_string(len)).unwrap();
+                }
+            }
+        }
+
+        // Undoing everything gives back the empty document and redoing it gives back the text.
+        let text = document.to_plain_string();
+        while document.can_undo() {
+            document.undo().unwrap();
+        }
+        assert_eq!(document.to_plain_string(), "\n");
+        while document.can_redo() {
+            document.redo().unwrap();
+        }
+        assert_eq!(document.to_plain_string(), text);
+    }
+}
+
+#[test]
+fn history_restore() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    document.insert(0, "123").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+    document.insert(3, "456").unwrap();
+    document.undo().unwrap();
+
+    let snapshot = DocumentSnapshot::new(document.rev_id(), document.delta());
+    let history = document.encode_history().unwrap();
+    let mut document = ClientDocument::restore(&snapshot, &history).unwrap();
+    assert_eq!(document.to_plain_string(), "123\n");
+    document.redo().unwrap();
+    assert_eq!(document.to_plain_string(), "123456\n");
+    document.undo().unwrap();
+    document.undo().unwrap();
+    assert_eq!(document.to_plain_string(), "\n");
+
+    // The history doesn't apply to another content.
+    let snapshot = DocumentSnapshot::new(0, &RichTextDeltaBuilder::new().insert("abc\n").build());
+    let document = ClientDocument::restore(&snapshot, &history).unwrap();
+    assert!(!document.can_undo());
+    assert!(!document.can_redo());
+}
+
+#[test]
+fn history_undo_restores_selection() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    let wait = || std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+    document.insert(0, "hello world").unwrap();
+    wait();
+    document.delete(Interval::new(0, 6)).unwrap();
+    wait();
+    document
+        .format(Interval::new(0, 5), RichTextAttribute::Bold(true))
+        .unwrap();
+
+    assert_eq!(document.undo().unwrap().selection, Interval::new(0, 5));
+    assert_eq!(document.undo().unwrap().selection, Interval::new(0, 6));
+    assert_eq!(document.redo().unwrap().selection, Interval::new(0, 0));
+
+    // The selections move over the changes of the others.
+    let remote = RichTextDeltaBuilder::new().insert("> ").retain(6).build();
+    document.compose_remote_delta(remote).unwrap();
+    assert_eq!(document.undo().unwrap().selection, Interval::new(2, 8));
+    assert_eq!(document.undo().unwrap().selection, Interval::new(2, 2));
+    assert_eq!(document.to_plain_string(), "> \n");
+}
+
+#[test]
+fn history_undo_last_format() {
+    let mut document = ClientDocument::new::<NewlineDoc>();
+    let wait = || std::thread::sleep(std::time::Duration::from_millis(RECORD_THRESHOLD as u64));
+    document.insert(0, "hello world").unwrap();
+    wait();
+    document
+        .format(Interval::new(6, 11), RichTextAttribute::Bold(true))
+        .unwrap();
+    wait();
+    document.insert(0, "> ").unwrap();
+    wait();
+    document.insert(7, ",").unwrap();
+
+    // The format is removed, the text typed since is kept.
+    let result = document.undo_last_format().unwrap();
+    assert_eq!(result.selection, Interval::new(9, 14));
+    assert_eq!(document.to_json(), r#"[{"insert":"> hello, world\n"}]"#);
+    assert!(document.undo_last_format().is_err());
+
+    document.undo().unwrap();
+    document.undo().unwrap();
+    assert_eq!(document.to_json(), r#"[{"insert":"hello world\n"}]"#);
+}
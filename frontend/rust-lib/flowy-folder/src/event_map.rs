@@ -74,7 +74,9 @@ pub fn create(folder: Arc<FolderManager>) -> Module {
         .event(FolderEvent::RestoreAllTrash, restore_all_trash_handler)
         .event(FolderEvent::DeleteAllTrash, delete_all_trash_handler);
 
-    module = module.event(FolderEvent::ExportDocument, export_handler);
+    module = module
+        .event(FolderEvent::ExportDocument, export_handler)
+        .event(FolderEvent::ReadDocumentCapabilities, read_document_capabilities_handler);
 
     module
 }
@@ -156,6 +158,9 @@ pub enum FolderEvent {
 
     #[event(input = "ExportPayload", output = "ExportData")]
     ExportDocument = 500,
+
+    #[event(output = "DocumentCapabilities")]
+    ReadDocumentCapabilities = 501,
 }
 
 pub trait FolderCouldServiceV1: Send + Sync {
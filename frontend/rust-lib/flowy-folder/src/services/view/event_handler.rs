@@ -8,7 +8,7 @@ use crate::{
     errors::FlowyError,
     services::{TrashController, ViewController},
 };
-use flowy_collaboration::entities::document_info::DocumentDelta;
+use flowy_collaboration::entities::document_info::{DocumentCapabilities, DocumentDelta};
 use flowy_folder_data_model::entities::share::{ExportData, ExportParams, ExportPayload};
 use lib_dispatch::prelude::{data_result, Data, DataResult, Unit};
 use std::{convert::TryInto, sync::Arc};
@@ -112,3 +112,7 @@ pub(crate) async fn export_handler(
     let data = controller.export_doc(params).await?;
     data_result(data)
 }
+
+pub(crate) async fn read_document_capabilities_handler() -> DataResult<DocumentCapabilities, FlowyError> {
+    data_result(DocumentCapabilities::current())
+}
@@ -0,0 +1,113 @@
+use flowy_collaboration::util::md5;
+use flowy_error::{internal_error, FlowyError, FlowyResult};
+use std::{
+    convert::TryInto,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+// The data of a compressed revision is: the magic, the length of the data before it was
+// compressed, the md5 of that data, then the zstd frame. The data of the revisions that were
+// written before the compression, or that didn't get smaller, is the delta itself, which is a json
+// array, so it can't start with the magic.
+const MAGIC: &[u8; 4] = b"FZR1";
+const MD5_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 4 + MD5_LEN;
+const COMPRESSION_LEVEL: i32 = 3;
+
+static UNCOMPRESSED_BYTES: AtomicU64 = AtomicU64::new(0);
+static STORED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The bytes of the revisions that were written to the disk since the app started, before and
+/// after they were compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub uncompressed_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+impl CompressionStats {
+    pub fn saved_bytes(&self) -> u64 {
+        self.uncompressed_bytes.saturating_sub(self.stored_bytes)
+    }
+
+    /// The size on the disk relative to the size of the revisions, 1.0 if nothing was written.
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_bytes == 0 {
+            return 1.0;
+        }
+        self.stored_bytes as f64 / self.uncompressed_bytes as f64
+    }
+}
+
+pub fn compression_stats() -> CompressionStats {
+    CompressionStats {
+        uncompressed_bytes: UNCOMPRESSED_BYTES.load(Ordering::Relaxed),
+        stored_bytes: STORED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+pub(crate) fn compress_revision_data(data: &[u8]) -> Vec<u8> {
+    let stored = match zstd::encode_all(data, COMPRESSION_LEVEL) {
+        Ok(frame) if HEADER_LEN + frame.len() < data.len() => {
+            let mut stored = Vec::with_capacity(HEADER_LEN + frame.len());
+            stored.extend_from_slice(MAGIC);
+            stored.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            stored.extend_from_slice(md5(data).as_bytes());
+            stored.extend_from_slice(&frame);
+            stored
+        }
+        Ok(_) => data.to_vec(),
+        Err(e) => {
+            tracing::error!("Compress the revision failed: {:?}", e);
+            data.to_vec()
+        }
+    };
+    UNCOMPRESSED_BYTES.fetch_add(data.len() as u64, Ordering::Relaxed);
+    STORED_BYTES.fetch_add(stored.len() as u64, Ordering::Relaxed);
+    stored
+}
+
+/// Returns the data of the revision that was written by [compress_revision_data]. Fails if the
+/// data doesn't match its header.
+pub(crate) fn decompress_revision_data(stored: Vec<u8>) -> FlowyResult<Vec<u8>> {
+    if !stored.starts_with(MAGIC) {
+        return Ok(stored);
+    }
+    if stored.len() < HEADER_LEN {
+        return Err(FlowyError::internal().context("The header of the revision is truncated"));
+    }
+    let len_bytes: [u8; 4] = stored[MAGIC.len()..MAGIC.len() + 4]
+        .try_into()
+        .map_err(internal_error)?;
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    let checksum = &stored[MAGIC.len() + 4..HEADER_LEN];
+
+    let data = zstd::decode_all(&stored[HEADER_LEN..]).map_err(internal_error)?;
+    if data.len() != len || md5(&data).as_bytes() != checksum {
+        return Err(FlowyError::internal().context("The revision is corrupted"));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::disk::compression::{compress_revision_data, decompress_revision_data};
+
+    #[test]
+    fn revision_data_compression() {
+        let data = r#"[{"insert":"Hello world. "}]"#.repeat(20).into_bytes();
+        let stored = compress_revision_data(&data);
+        assert!(stored.len() < data.len());
+        assert_eq!(decompress_revision_data(stored.clone()).unwrap(), data);
+
+        // The data that isn't worth compressing and the data written before are kept as they are.
+        let small = br#"[{"insert":"a"}]"#.to_vec();
+        assert_eq!(compress_revision_data(&small), small);
+        assert_eq!(decompress_revision_data(small.clone()).unwrap(), small);
+
+        let mut corrupted = stored;
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        assert!(decompress_revision_data(corrupted).is_err());
+    }
+}
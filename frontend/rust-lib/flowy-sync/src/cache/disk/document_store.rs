@@ -0,0 +1,261 @@
+use crate::{
+    cache::disk::{
+        sql_impl::{metadata_from_json, metadata_to_json},
+        RevisionTableSql,
+    },
+    RevisionRecord,
+};
+use diesel::{OptionalExtension, SqliteConnection};
+use flowy_collaboration::{
+    client_document::{
+        revision_log::DocumentSnapshot,
+        store::{DocumentStore, RevisionStore, SnapshotStore},
+    },
+    encryption::EncryptionProviderRef,
+    entities::revision::{Revision, RevisionState},
+    errors::CollaborateError,
+};
+use flowy_database::{prelude::*, schema::doc_table, ConnectionPool};
+use flowy_error::{internal_error, FlowyError, FlowyResult};
+use lib_ot::rich_text::RichTextDelta;
+use std::sync::Arc;
+
+#[derive(Queryable, Insertable)]
+#[table_name = "doc_table"]
+struct DocTable {
+    id: String,
+    // The delta of the snapshot as json.
+    data: String,
+    rev_id: i64,
+    // The metadata of the snapshot as json.
+    metadata: String,
+}
+
+/// Stores the documents of the [DocumentManager](flowy_collaboration::client_document::manager::DocumentManager)
+/// in the database of the user. The revisions go to the `rev_table` that the [RevisionManager](crate::RevisionManager)
+/// keeps them in, and the snapshots to the `doc_table`. [DocumentStore::save] writes both in one
+/// transaction.
+pub struct SQLiteDocumentStore {
+    user_id: String,
+    pool: Arc<ConnectionPool>,
+    encryption: Option<EncryptionProviderRef>,
+}
+
+impl SQLiteDocumentStore {
+    pub fn new(user_id: &str, pool: Arc<ConnectionPool>) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            pool,
+            encryption: None,
+        }
+    }
+
+    /// Encrypts the revisions, like the ones written by the [RevisionManager](crate::RevisionManager).
+    pub fn with_encryption(mut self, encryption: EncryptionProviderRef) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    fn write_revisions(&self, doc_id: &str, revisions: &[Revision], conn: &SqliteConnection) -> FlowyResult<()> {
+        // The revisions that the revision manager already wrote are ignored by the unique index on
+        // (doc_id, rev_id).
+        let records = revisions
+            .iter()
+            .map(|revision| {
+                let mut revision = revision.clone();
+                revision.object_id = doc_id.to_owned();
+                RevisionRecord {
+                    revision,
+                    state: RevisionState::Sync,
+                    write_to_disk: true,
+                }
+            })
+            .collect::<Vec<_>>();
+        RevisionTableSql::create(records, self.encryption.as_deref(), conn)
+    }
+
+    fn write_snapshot(&self, doc_id: &str, snapshot: &DocumentSnapshot, conn: &SqliteConnection) -> FlowyResult<()> {
+        let row = DocTable {
+            id: doc_id.to_owned(),
+            data: RichTextDelta::from_bytes(&snapshot.delta_data)?.to_json(),
+            rev_id: snapshot.rev_id,
+            metadata: metadata_to_json(&snapshot.metadata)?,
+        };
+        let _ = diesel::replace_into(doc_table::table).values(&row).execute(conn)?;
+        Ok(())
+    }
+}
+
+impl RevisionStore for SQLiteDocumentStore {
+    fn read_revisions(&self, doc_id: &str, rev_id: i64) -> Result<Vec<Revision>, CollaborateError> {
+        let read = || -> FlowyResult<Vec<Revision>> {
+            let conn = self.pool.get().map_err(internal_error)?;
+            let records = RevisionTableSql::read(&self.user_id, doc_id, None, self.encryption.as_deref(), &*conn)?;
+            Ok(records
+                .into_iter()
+                .map(|record| record.revision)
+                .filter(|revision| revision.rev_id > rev_id)
+                .collect())
+        };
+        read().map_err(collaborate_error)
+    }
+
+    fn write_revisions(&self, doc_id: &str, revisions: &[Revision]) -> Result<(), CollaborateError> {
+        let write = || -> FlowyResult<()> {
+            let conn = self.pool.get().map_err(internal_error)?;
+            conn.immediate_transaction::<_, FlowyError, _>(|| self.write_revisions(doc_id, revisions, &*conn))
+        };
+        write().map_err(collaborate_error)
+    }
+}
+
+impl SnapshotStore for SQLiteDocumentStore {
+    fn read_snapshot(&self, doc_id: &str) -> Result<Option<DocumentSnapshot>, CollaborateError> {
+        let read = || -> FlowyResult<Option<DocumentSnapshot>> {
+            let conn = self.pool.get().map_err(internal_error)?;
+            let row = doc_table::dsl::doc_table
+                .filter(doc_table::dsl::id.eq(doc_id))
+                .first::<DocTable>(&*conn)
+                .optional()?;
+            match row {
+                None => Ok(None),
+                Some(row) => {
+                    let delta = RichTextDelta::from_json(&row.data)?;
+                    let metadata = metadata_from_json(&row.metadata)?;
+                    Ok(Some(DocumentSnapshot::new(row.rev_id, &delta).with_metadata(metadata)))
+                }
+            }
+        };
+        read().map_err(collaborate_error)
+    }
+
+    fn write_snapshot(&self, doc_id: &str, snapshot: &DocumentSnapshot) -> Result<(), CollaborateError> {
+        let write = || -> FlowyResult<()> {
+            let conn = self.pool.get().map_err(internal_error)?;
+            self.write_snapshot(doc_id, snapshot, &*conn)
+        };
+        write().map_err(collaborate_error)
+    }
+
+    fn doc_ids(&self) -> Result<Vec<String>, CollaborateError> {
+        let read = || -> FlowyResult<Vec<String>> {
+            let conn = self.pool.get().map_err(internal_error)?;
+            let doc_ids = doc_table::dsl::doc_table
+                .select(doc_table::dsl::id)
+                .order(doc_table::dsl::id.asc())
+                .load::<String>(&*conn)?;
+            Ok(doc_ids)
+        };
+        read().map_err(collaborate_error)
+    }
+}
+
+impl DocumentStore for SQLiteDocumentStore {
+    fn save(&self, doc_id: &str, snapshot: &DocumentSnapshot, revisions: &[Revision]) -> Result<(), CollaborateError> {
+        let save = || -> FlowyResult<()> {
+            let conn = self.pool.get().map_err(internal_error)?;
+            conn.immediate_transaction::<_, FlowyError, _>(|| {
+                let _ = self.write_revisions(doc_id, revisions, &*conn)?;
+                self.write_snapshot(doc_id, snapshot, &*conn)
+            })
+        };
+        save().map_err(collaborate_error)
+    }
+}
+
+fn collaborate_error(error: FlowyError) -> CollaborateError {
+    CollaborateError::internal().context(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::disk::SQLiteDocumentStore;
+    use flowy_collaboration::{
+        client_document::{
+            revision_log::DocumentSnapshot,
+            store::{load_document, DocumentStore, RevisionStore, SnapshotStore},
+            ClientDocument, NewlineDoc,
+        },
+        entities::revision::{ClockEntry, MetadataEntry, Revision, VectorClock},
+        util::{content_md5, md5},
+    };
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+
+    #[test]
+    fn sqlite_document_store() {
+        let dir = std::env::temp_dir().join(format!("flowy-sync-document-store-{}", std::process::id()));
+        let database = flowy_database::init(dir.to_str().unwrap()).unwrap();
+        let store = SQLiteDocumentStore::new("user", database.get_pool());
+        assert!(load_document(&store, "a").unwrap().is_none());
+
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "abc").unwrap();
+        let snapshot = document.snapshot();
+        document.insert(3, "d").unwrap();
+        let revisions = document.revision_log().revisions().to_vec();
+        store.save("a", &snapshot, &revisions[..1]).unwrap();
+        assert_eq!(store.read_snapshot("a").unwrap(), Some(snapshot));
+        assert_eq!(store.doc_ids().unwrap(), vec!["a".to_owned()]);
+
+        // The revisions after the snapshot are composed into it when the document is read, the
+        // ones that were already written are skipped.
+        store.write_revisions("a", &revisions).unwrap();
+        assert_eq!(store.read_revisions("a", 0).unwrap().len(), revisions.len());
+        let reopened = load_document(&store, "a").unwrap().unwrap();
+        assert_eq!(reopened.to_json(), document.to_json());
+        assert_eq!(reopened.rev_id(), 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn sqlite_document_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("flowy-sync-document-round-trip-{}", std::process::id()));
+        let database = flowy_database::init(dir.to_str().unwrap()).unwrap();
+        let store = SQLiteDocumentStore::new("user", database.get_pool());
+
+        let delta = RichTextDeltaBuilder::new().insert("abc\n").build();
+        let delta_data = delta.to_bytes();
+        let clock = VectorClock::from_entries(&[
+            ClockEntry {
+                peer_id: "device".to_owned(),
+                counter: 1,
+            },
+            ClockEntry {
+                peer_id: "other".to_owned(),
+                counter: 3,
+            },
+        ]);
+        let metadata = vec![
+            MetadataEntry {
+                key: "title".to_owned(),
+                value: "abc".to_owned(),
+                is_removed: false,
+                timestamp: 42,
+                device_id: "device".to_owned(),
+            },
+            MetadataEntry {
+                key: "icon".to_owned(),
+                value: "".to_owned(),
+                is_removed: true,
+                timestamp: 43,
+                device_id: "other".to_owned(),
+            },
+        ];
+        let revision = Revision::new("b", 0, 1, delta_data.clone(), "user", md5(&delta_data))
+            .with_author("author", "device")
+            .with_timestamp(42)
+            .with_content_md5(content_md5(&delta))
+            .with_vector_clock(&clock)
+            .with_metadata(metadata.clone());
+        let snapshot = DocumentSnapshot::new(1, &delta).with_metadata(metadata);
+
+        store.save("b", &snapshot, &[revision.clone()]).unwrap();
+        assert_eq!(store.read_snapshot("b").unwrap(), Some(snapshot.clone()));
+        assert_eq!(store.read_revisions("b", 0).unwrap(), vec![revision.clone()]);
+
+        // Saving the same revision again doesn't duplicate its row.
+        store.save("b", &snapshot, &[revision.clone()]).unwrap();
+        assert_eq!(store.read_revisions("b", 0).unwrap(), vec![revision]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
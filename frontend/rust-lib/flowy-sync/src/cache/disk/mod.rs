@@ -1,6 +1,12 @@
+mod compression;
+#[cfg(feature = "sqlite")]
+mod document_store;
 mod sql_impl;
 use crate::RevisionRecord;
+pub use compression::{compression_stats, CompressionStats};
 use diesel::SqliteConnection;
+#[cfg(feature = "sqlite")]
+pub use document_store::*;
 use flowy_collaboration::entities::revision::RevisionRange;
 pub use sql_impl::*;
 
@@ -1,8 +1,15 @@
-use crate::{cache::disk::RevisionDiskCache, RevisionRecord};
+use crate::{
+    cache::disk::{
+        compression::{compress_revision_data, decompress_revision_data},
+        RevisionDiskCache,
+    },
+    RevisionRecord,
+};
 use bytes::Bytes;
 use diesel::{sql_types::Integer, update, SqliteConnection};
 use flowy_collaboration::{
-    entities::revision::{RevId, RevType, Revision, RevisionRange, RevisionState},
+    encryption::{decrypt, encrypt, EncryptionProvider, EncryptionProviderRef},
+    entities::revision::{ClockEntry, MetadataEntry, RevId, RevType, Revision, RevisionRange, RevisionState},
     util::md5,
 };
 use flowy_database::{
@@ -12,11 +19,13 @@ use flowy_database::{
     ConnectionPool,
 };
 use flowy_error::{internal_error, FlowyError, FlowyResult};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 pub struct SQLitePersistence {
     user_id: String,
     pub(crate) pool: Arc<ConnectionPool>,
+    encryption: Option<EncryptionProviderRef>,
 }
 
 impl RevisionDiskCache for SQLitePersistence {
@@ -27,7 +36,7 @@ impl RevisionDiskCache for SQLitePersistence {
         revision_records: Vec<RevisionRecord>,
         conn: &SqliteConnection,
     ) -> Result<(), Self::Error> {
-        let _ = RevisionTableSql::create(revision_records, conn)?;
+        let _ = RevisionTableSql::create(revision_records, self.encryption.as_deref(), conn)?;
         Ok(())
     }
 
@@ -37,7 +46,7 @@ impl RevisionDiskCache for SQLitePersistence {
         rev_ids: Option<Vec<i64>>,
     ) -> Result<Vec<RevisionRecord>, Self::Error> {
         let conn = self.pool.get().map_err(internal_error)?;
-        let records = RevisionTableSql::read(&self.user_id, object_id, rev_ids, &*conn)?;
+        let records = RevisionTableSql::read(&self.user_id, object_id, rev_ids, self.encryption.as_deref(), &*conn)?;
         Ok(records)
     }
 
@@ -47,7 +56,13 @@ impl RevisionDiskCache for SQLitePersistence {
         range: &RevisionRange,
     ) -> Result<Vec<RevisionRecord>, Self::Error> {
         let conn = &*self.pool.get().map_err(internal_error)?;
-        let revisions = RevisionTableSql::read_with_range(&self.user_id, object_id, range.clone(), conn)?;
+        let revisions = RevisionTableSql::read_with_range(
+            &self.user_id,
+            object_id,
+            range.clone(),
+            self.encryption.as_deref(),
+            conn,
+        )?;
         Ok(revisions)
     }
 
@@ -84,10 +99,11 @@ impl RevisionDiskCache for SQLitePersistence {
 }
 
 impl SQLitePersistence {
-    pub(crate) fn new(user_id: &str, pool: Arc<ConnectionPool>) -> Self {
+    pub(crate) fn new(user_id: &str, pool: Arc<ConnectionPool>, encryption: Option<EncryptionProviderRef>) -> Self {
         Self {
             user_id: user_id.to_owned(),
             pool,
+            encryption,
         }
     }
 }
@@ -95,7 +111,11 @@ impl SQLitePersistence {
 pub struct RevisionTableSql {}
 
 impl RevisionTableSql {
-    pub(crate) fn create(revision_records: Vec<RevisionRecord>, conn: &SqliteConnection) -> Result<(), FlowyError> {
+    pub(crate) fn create(
+        revision_records: Vec<RevisionRecord>,
+        encryption: Option<&dyn EncryptionProvider>,
+        conn: &SqliteConnection,
+    ) -> Result<(), FlowyError> {
         // Batch insert: https://diesel.rs/guides/all-about-inserts.html
 
         let records = revision_records
@@ -107,16 +127,30 @@ impl RevisionTableSql {
                     record.revision.rev_id
                 );
                 let rev_state: RevisionTableState = record.state.into();
-                (
-                    dsl::doc_id.eq(record.revision.object_id),
-                    dsl::base_rev_id.eq(record.revision.base_rev_id),
-                    dsl::rev_id.eq(record.revision.rev_id),
-                    dsl::data.eq(record.revision.delta_data),
+                // The data is compressed before it's encrypted, the encrypted data doesn't compress.
+                let mut data = compress_revision_data(&record.revision.delta_data);
+                if let Some(encryption) = encryption {
+                    data = encrypt(encryption, &data).map_err(internal_error)?;
+                }
+                let clock = clock_to_json(&record.revision.clock)?;
+                let metadata = metadata_to_json(&record.revision.metadata)?;
+                let revision = record.revision;
+                Ok((
+                    dsl::doc_id.eq(revision.object_id),
+                    dsl::base_rev_id.eq(revision.base_rev_id),
+                    dsl::rev_id.eq(revision.rev_id),
+                    dsl::data.eq(data),
                     dsl::state.eq(rev_state),
                     dsl::ty.eq(RevTableType::Local),
-                )
+                    dsl::author_id.eq(revision.author_id),
+                    dsl::device_id.eq(revision.device_id),
+                    dsl::timestamp.eq(revision.timestamp),
+                    dsl::content_md5.eq(revision.content_md5),
+                    dsl::clock.eq(clock),
+                    dsl::metadata.eq(metadata),
+                ))
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, FlowyError>>()?;
 
         let _ = insert_or_ignore_into(dsl::rev_table).values(&records).execute(conn)?;
         Ok(())
@@ -139,6 +173,7 @@ impl RevisionTableSql {
         user_id: &str,
         object_id: &str,
         rev_ids: Option<Vec<i64>>,
+        encryption: Option<&dyn EncryptionProvider>,
         conn: &SqliteConnection,
     ) -> Result<Vec<RevisionRecord>, FlowyError> {
         let mut sql = dsl::rev_table.filter(dsl::doc_id.eq(object_id)).into_boxed();
@@ -148,8 +183,8 @@ impl RevisionTableSql {
         let rows = sql.order(dsl::rev_id.asc()).load::<RevisionTable>(conn)?;
         let records = rows
             .into_iter()
-            .map(|row| mk_revision_record_from_table(user_id, row))
-            .collect::<Vec<_>>();
+            .map(|row| mk_revision_record_from_table(user_id, row, encryption))
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(records)
     }
@@ -158,6 +193,7 @@ impl RevisionTableSql {
         user_id: &str,
         object_id: &str,
         range: RevisionRange,
+        encryption: Option<&dyn EncryptionProvider>,
         conn: &SqliteConnection,
     ) -> Result<Vec<RevisionRecord>, FlowyError> {
         let rev_tables = dsl::rev_table
@@ -169,8 +205,8 @@ impl RevisionTableSql {
 
         let revisions = rev_tables
             .into_iter()
-            .map(|table| mk_revision_record_from_table(user_id, table))
-            .collect::<Vec<_>>();
+            .map(|table| mk_revision_record_from_table(user_id, table, encryption))
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(revisions)
     }
 
@@ -203,6 +239,13 @@ pub(crate) struct RevisionTable {
     pub(crate) data: Vec<u8>,
     pub(crate) state: RevisionTableState,
     pub(crate) ty: RevTableType, // Deprecated
+    pub(crate) author_id: String,
+    pub(crate) device_id: String,
+    pub(crate) timestamp: i64,
+    pub(crate) content_md5: String,
+    // The clock and the metadata of the revision as json, see [ClockRow] and [MetadataRow].
+    pub(crate) clock: String,
+    pub(crate) metadata: String,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, FromSqlRow, AsExpression)]
@@ -257,21 +300,100 @@ impl std::convert::From<RevisionState> for RevisionTableState {
     }
 }
 
-pub(crate) fn mk_revision_record_from_table(user_id: &str, table: RevisionTable) -> RevisionRecord {
-    let md5 = md5(&table.data);
-    let revision = Revision::new(
+pub(crate) fn mk_revision_record_from_table(
+    user_id: &str,
+    table: RevisionTable,
+    encryption: Option<&dyn EncryptionProvider>,
+) -> FlowyResult<RevisionRecord> {
+    let data = decrypt(encryption, &table.data).map_err(internal_error)?;
+    let data = decompress_revision_data(data)?;
+    let md5 = md5(&data);
+    let mut revision = Revision::new(
         &table.doc_id,
         table.base_rev_id,
         table.rev_id,
-        Bytes::from(table.data),
+        Bytes::from(data),
         user_id,
         md5,
-    );
-    RevisionRecord {
+    )
+    .with_author(&table.author_id, &table.device_id)
+    .with_timestamp(table.timestamp)
+    .with_content_md5(table.content_md5)
+    .with_metadata(metadata_from_json(&table.metadata)?);
+    revision.clock = clock_from_json(&table.clock)?;
+    Ok(RevisionRecord {
         revision,
         state: table.state.into(),
         write_to_disk: false,
-    }
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct ClockRow {
+    peer_id: String,
+    counter: i64,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Serialize, Deserialize)]
+struct MetadataRow {
+    key: String,
+    value: String,
+    is_removed: bool,
+    timestamp: i64,
+    device_id: String,
+}
+
+fn clock_to_json(clock: &[ClockEntry]) -> FlowyResult<String> {
+    let rows = clock
+        .iter()
+        .map(|entry| ClockRow {
+            peer_id: entry.peer_id.clone(),
+            counter: entry.counter,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string(&rows).map_err(internal_error)
+}
+
+fn clock_from_json(json: &str) -> FlowyResult<Vec<ClockEntry>> {
+    let rows: Vec<ClockRow> = serde_json::from_str(json).map_err(internal_error)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ClockEntry {
+            peer_id: row.peer_id,
+            counter: row.counter,
+        })
+        .collect())
+}
+
+#[cfg(feature = "sqlite")]
+pub(crate) fn metadata_to_json(metadata: &[MetadataEntry]) -> FlowyResult<String> {
+    let rows = metadata
+        .iter()
+        .map(|entry| MetadataRow {
+            key: entry.key.clone(),
+            value: entry.value.clone(),
+            is_removed: entry.is_removed,
+            timestamp: entry.timestamp,
+            device_id: entry.device_id.clone(),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string(&rows).map_err(internal_error)
+}
+
+#[cfg(feature = "sqlite")]
+pub(crate) fn metadata_from_json(json: &str) -> FlowyResult<Vec<MetadataEntry>> {
+    let rows: Vec<MetadataRow> = serde_json::from_str(json).map_err(internal_error)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| MetadataEntry {
+            key: row.key,
+            value: row.value,
+            is_removed: row.is_removed,
+            timestamp: row.timestamp,
+            device_id: row.device_id,
+        })
+        .collect())
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, FromSqlRow, AsExpression)]
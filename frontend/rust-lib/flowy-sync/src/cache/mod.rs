@@ -1,12 +1,19 @@
 mod disk;
 mod memory;
 
+#[cfg(feature = "sqlite")]
+pub use disk::SQLiteDocumentStore;
+pub use disk::{compression_stats, CompressionStats};
+
 use crate::cache::{
     disk::{RevisionChangeset, RevisionDiskCache, RevisionTableState, SQLitePersistence},
     memory::{RevisionMemoryCache, RevisionMemoryCacheDelegate},
 };
 
-use flowy_collaboration::entities::revision::{Revision, RevisionRange, RevisionState};
+use flowy_collaboration::{
+    encryption::EncryptionProviderRef,
+    entities::revision::{Revision, RevisionRange, RevisionState},
+};
 use flowy_database::ConnectionPool;
 use flowy_error::{internal_error, FlowyError, FlowyResult};
 
@@ -27,7 +34,17 @@ pub struct RevisionCache {
 }
 impl RevisionCache {
     pub fn new(user_id: &str, object_id: &str, pool: Arc<ConnectionPool>) -> RevisionCache {
-        let disk_cache = Arc::new(SQLitePersistence::new(user_id, pool));
+        Self::with_encryption(user_id, object_id, pool, None)
+    }
+
+    /// Creates the cache whose revisions are encrypted by `encryption` on the disk.
+    pub fn with_encryption(
+        user_id: &str,
+        object_id: &str,
+        pool: Arc<ConnectionPool>,
+        encryption: Option<EncryptionProviderRef>,
+    ) -> RevisionCache {
+        let disk_cache = Arc::new(SQLitePersistence::new(user_id, pool, encryption));
         let memory_cache = Arc::new(RevisionMemoryCache::new(object_id, Arc::new(disk_cache.clone())));
         let object_id = object_id.to_owned();
         let user_id = user_id.to_owned();
@@ -216,7 +233,7 @@ pub fn mk_revision_disk_cache(
     user_id: &str,
     pool: Arc<ConnectionPool>,
 ) -> Arc<dyn RevisionDiskCache<Error = FlowyError>> {
-    Arc::new(SQLitePersistence::new(user_id, pool))
+    Arc::new(SQLitePersistence::new(user_id, pool, None))
 }
 
 impl RevisionMemoryCacheDelegate for Arc<SQLitePersistence> {
@@ -112,7 +112,13 @@ where
                 // // server, and it needs to override the client delta.
                 let md5 = self.target.reset_delta(client_prime).await?;
                 let repeated_revision = RepeatedRevision::new(revisions);
-                assert_eq!(repeated_revision.last().unwrap().md5, md5);
+                let server_md5 = &repeated_revision.last().unwrap().md5;
+                if server_md5 != &md5 {
+                    return Err(FlowyError::internal().context(format!(
+                        "The document was reset to {} but the server has {}",
+                        md5, server_md5
+                    )));
+                }
                 let _ = self.rev_manager.reset_object(repeated_revision).await?;
                 Ok(None)
             }
@@ -3,12 +3,15 @@ use async_stream::stream;
 use bytes::Bytes;
 use flowy_collaboration::entities::{
     revision::{RevId, Revision, RevisionRange},
-    ws_data::{ClientRevisionWSData, NewDocumentUser, ServerRevisionWSData, ServerRevisionWSDataType},
+    ws_data::{
+        ClientRevisionWSData, ClientRevisionWSDataType, NewDocumentUser, ServerRevisionWSData, ServerRevisionWSDataType,
+    },
 };
 use flowy_error::{FlowyError, FlowyResult};
 use futures_util::{future::BoxFuture, stream::StreamExt};
 use lib_infra::future::{BoxResultFuture, FutureResult};
 use lib_ws::WSConnectState;
+use parking_lot::Mutex;
 use std::{collections::VecDeque, convert::TryFrom, fmt::Formatter, sync::Arc};
 use tokio::{
     sync::{
@@ -16,9 +19,12 @@ use tokio::{
         mpsc::{Receiver, Sender},
         RwLock,
     },
-    time::{interval, Duration},
+    time::{interval, Duration, Instant},
 };
 
+// The longest wait before the revisions that the server didn't ack are sent again.
+const MAX_RESEND_DELAY: Duration = Duration::from_secs(30);
+
 // The consumer consumes the messages pushed by the web socket.
 pub trait RevisionWSSteamConsumer: Send + Sync {
     fn receive_push_revision(&self, bytes: Bytes) -> BoxResultFuture<(), FlowyError>;
@@ -233,6 +239,7 @@ pub struct RevisionWSSink {
     ws_sender: Arc<dyn RevisionWebSocket>,
     stop_rx: Option<SinkStopRx>,
     ping_duration: Duration,
+    backoff: Mutex<ResendBackoff>,
 }
 
 impl RevisionWSSink {
@@ -251,6 +258,7 @@ impl RevisionWSSink {
             ws_sender,
             stop_rx: Some(stop_rx),
             ping_duration,
+            backoff: Mutex::new(ResendBackoff::new(ping_duration)),
         }
     }
 
@@ -293,6 +301,9 @@ impl RevisionWSSink {
                 Ok(())
             }
             Some(data) => {
+                if !self.backoff.lock().should_send(&data, Instant::now()) {
+                    return Ok(());
+                }
                 tracing::trace!("[{}]: send {}:{}-{:?}", self, data.object_id, data.id(), data.ty);
                 self.ws_sender.send(data).await
             }
@@ -300,6 +311,54 @@ impl RevisionWSSink {
     }
 }
 
+// Holds back the revisions that were sent but not acked yet. They are sent again after a delay
+// that doubles with each resend, up to MAX_RESEND_DELAY, so that a flaky connection isn't flooded
+// with them. The server ignores the revisions that it has applied already.
+struct ResendBackoff {
+    initial_delay: Duration,
+    delay: Duration,
+    data_id: Option<String>,
+    sent_at: Instant,
+}
+
+impl ResendBackoff {
+    fn new(initial_delay: Duration) -> Self {
+        Self {
+            initial_delay,
+            delay: initial_delay,
+            data_id: None,
+            sent_at: Instant::now(),
+        }
+    }
+
+    fn should_send(&mut self, data: &ClientRevisionWSData, now: Instant) -> bool {
+        if data.ty != ClientRevisionWSDataType::ClientPushRev {
+            return true;
+        }
+
+        let data_id = data.id();
+        if self.data_id.as_ref() != Some(&data_id) {
+            // The previous revisions were acked, or these are the first ones.
+            self.data_id = Some(data_id);
+            self.delay = self.initial_delay;
+            self.sent_at = now;
+            return true;
+        }
+
+        if now.duration_since(self.sent_at) < self.delay {
+            return false;
+        }
+        tracing::debug!(
+            "Resend the revisions {} that weren't acked after {:?}",
+            data_id,
+            self.delay
+        );
+        self.delay = (self.delay * 2).min(MAX_RESEND_DELAY);
+        self.sent_at = now;
+        true
+    }
+}
+
 async fn tick(sender: mpsc::Sender<()>, duration: Duration) {
     let mut interval = interval(duration);
     while sender.send(()).await.is_ok() {
@@ -422,3 +481,57 @@ impl ResolverRevisionSink for Arc<CompositeWSSinkDataProvider> {
         Box::pin(async move { sink.ack_data(rev_id, ty).await })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ResendBackoff, MAX_RESEND_DELAY};
+    use bytes::Bytes;
+    use flowy_collaboration::entities::{revision::Revision, ws_data::ClientRevisionWSData};
+    use tokio::time::{Duration, Instant};
+
+    fn push(rev_id: i64) -> ClientRevisionWSData {
+        let revision = Revision::new("doc", rev_id - 1, rev_id, Bytes::new(), "user", "".to_owned());
+        ClientRevisionWSData::from_revisions("doc", vec![revision])
+    }
+
+    #[test]
+    fn unacked_revisions_are_resent_with_a_doubling_delay() {
+        let second = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut backoff = ResendBackoff::new(second);
+        assert!(backoff.should_send(&push(1), start));
+
+        // The delays between the resends are 1s, 2s, 4s, ... up to MAX_RESEND_DELAY.
+        let mut sent_at = start;
+        let mut delay = second;
+        for _ in 0..8 {
+            assert!(!backoff.should_send(&push(1), sent_at + delay / 2));
+            assert!(!backoff.should_send(&push(1), sent_at + delay - Duration::from_millis(1)));
+            sent_at += delay;
+            assert!(backoff.should_send(&push(1), sent_at));
+            delay = (delay * 2).min(MAX_RESEND_DELAY);
+        }
+        assert_eq!(delay, MAX_RESEND_DELAY);
+        assert!(!backoff.should_send(&push(1), sent_at + MAX_RESEND_DELAY - second));
+        assert!(backoff.should_send(&push(1), sent_at + MAX_RESEND_DELAY));
+
+        // The pings aren't held back.
+        assert!(backoff.should_send(&ClientRevisionWSData::ping("doc", 1), sent_at));
+    }
+
+    #[test]
+    fn backoff_resets_once_the_revisions_are_acked() {
+        let second = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut backoff = ResendBackoff::new(second);
+        assert!(backoff.should_send(&push(1), start));
+        assert!(backoff.should_send(&push(1), start + second));
+        assert!(backoff.should_send(&push(1), start + second * 3));
+
+        // The next revisions are sent at once, and resent after the initial delay again.
+        let acked = start + second * 4;
+        assert!(backoff.should_send(&push(2), acked));
+        assert!(!backoff.should_send(&push(2), acked + second / 2));
+        assert!(backoff.should_send(&push(2), acked + second));
+    }
+}
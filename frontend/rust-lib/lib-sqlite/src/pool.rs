@@ -143,8 +143,13 @@ impl DatabaseCustomizer {
 impl CustomizeConnection<SqliteConnection, crate::Error> for DatabaseCustomizer {
     fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<()> {
         conn.pragma_set_busy_timeout(self.config.busy_timeout)?;
-        if self.config.journal_mode != SQLiteJournalMode::WAL {
-            conn.pragma_set_journal_mode(self.config.journal_mode, None)?;
+        let journal_mode = conn.pragma_set_journal_mode(self.config.journal_mode, None)?;
+        if journal_mode != self.config.journal_mode {
+            log::warn!(
+                "SQLITE journal mode is {}, expected {}",
+                journal_mode,
+                self.config.journal_mode
+            );
         }
         conn.pragma_set_synchronous(self.config.synchronous, None)?;
 
@@ -57,8 +57,11 @@ pub trait PragmaExtension: ConnectionExtension {
         self.pragma_get::<Integer, i32>("busy_timeout", None)
     }
 
-    fn pragma_set_journal_mode(&self, mode: SQLiteJournalMode, schema: Option<&str>) -> Result<i32> {
-        self.pragma_ret::<Integer, i32, SQLiteJournalMode>("journal_mode", mode, schema)
+    // Returns the journal mode the database is in, which stays the same if it can't be changed,
+    // e.g. an in-memory database can't be in WAL mode.
+    fn pragma_set_journal_mode(&self, mode: SQLiteJournalMode, schema: Option<&str>) -> Result<SQLiteJournalMode> {
+        self.pragma_ret::<Text, String, SQLiteJournalMode>("journal_mode", mode, schema)?
+            .parse()
     }
 
     fn pragma_get_journal_mode(&self, schema: Option<&str>) -> Result<SQLiteJournalMode> {
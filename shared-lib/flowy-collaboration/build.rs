@@ -2,4 +2,7 @@ use lib_infra::code_gen;
 
 fn main() {
     code_gen::protobuf_file::gen(env!("CARGO_PKG_NAME"), "./src/protobuf/proto");
+
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("./src/grpc/document_sync.proto").unwrap();
 }
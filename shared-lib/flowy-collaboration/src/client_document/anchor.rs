@@ -0,0 +1,122 @@
+use lib_ot::rich_text::RichTextDelta;
+use std::collections::BTreeMap;
+
+pub type AnchorId = u64;
+
+/// Which side of the text inserted at an anchor the anchor stays on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AnchorBias {
+    /// The anchor stays in front of the inserted text, e.g. the start of a bookmark.
+    Left,
+    /// The anchor moves behind the inserted text, e.g. the end of the last edit.
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Anchor {
+    pub id: AnchorId,
+    pub index: usize,
+    pub bias: AnchorBias,
+}
+
+/// Keeps the anchors of the document, the positions that the bookmarks, the scroll positions or
+/// the last edit are restored to. The index of each anchor is transformed by every delta composed
+/// into the document, local, remote or undone, so it stays next to the same text.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Anchors {
+    next_id: AnchorId,
+    anchors: BTreeMap<AnchorId, Anchor>,
+}
+
+impl Anchors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create(&mut self, index: usize, bias: AnchorBias) -> AnchorId {
+        self.next_id += 1;
+        let anchor = Anchor {
+            id: self.next_id,
+            index,
+            bias,
+        };
+        self.anchors.insert(anchor.id, anchor);
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: AnchorId) -> Option<Anchor> {
+        self.anchors.remove(&id)
+    }
+
+    pub fn get(&self, id: AnchorId) -> Option<&Anchor> {
+        self.anchors.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Anchor> {
+        self.anchors.values()
+    }
+
+    /// Splits the anchors at `index`. The ones from it on go to the second, moved by `index`.
+    /// Both keep the ids of the anchors.
+    pub fn split_at(&self, index: usize) -> (Anchors, Anchors) {
+        let mut first = Anchors {
+            next_id: self.next_id,
+            anchors: BTreeMap::new(),
+        };
+        let mut second = first.clone();
+        for anchor in self.anchors.values() {
+            match anchor.index < index {
+                true => first.anchors.insert(anchor.id, *anchor),
+                false => second.anchors.insert(
+                    anchor.id,
+                    Anchor {
+                        index: anchor.index - index,
+                        ..*anchor
+                    },
+                ),
+            };
+        }
+        (first, second)
+    }
+
+    /// Adds the anchors of `other`, moved by `offset`, with new ids. Returns the new ids by the
+    /// ids in `other`.
+    pub fn append(&mut self, other: &Anchors, offset: usize) -> BTreeMap<AnchorId, AnchorId> {
+        other
+            .iter()
+            .map(|anchor| (anchor.id, self.create(anchor.index + offset, anchor.bias)))
+            .collect()
+    }
+
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        if delta.is_noop() {
+            return;
+        }
+
+        for anchor in self.anchors.values_mut() {
+            anchor.index = delta.transform_position(anchor.index, anchor.bias == AnchorBias::Left);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::anchor::{AnchorBias, Anchors};
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+
+    #[test]
+    fn anchor_bias_at_insert() {
+        let mut anchors = Anchors::new();
+        let left = anchors.create(2, AnchorBias::Left);
+        let right = anchors.create(2, AnchorBias::Right);
+
+        anchors.transform(&RichTextDeltaBuilder::new().retain(2).insert("ab").build());
+        assert_eq!(anchors.get(left).unwrap().index, 2);
+        assert_eq!(anchors.get(right).unwrap().index, 4);
+
+        // The anchors in a deleted range move to its start.
+        anchors.transform(&RichTextDeltaBuilder::new().retain(1).delete(4).build());
+        assert_eq!(anchors.get(left).unwrap().index, 1);
+        assert_eq!(anchors.get(right).unwrap().index, 1);
+    }
+}
@@ -0,0 +1,142 @@
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+use std::collections::BTreeMap;
+
+pub type AnnotationId = u64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub id: AnnotationId,
+    pub interval: Interval,
+    pub payload: String,
+    /// The annotated text was deleted. The interval is collapsed to where the text used to be.
+    pub orphaned: bool,
+}
+
+/// Keeps the annotations, e.g. the comments, of the document. The interval of each annotation is
+/// transformed by every delta composed into the document, so it keeps covering the same text.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    next_id: AnnotationId,
+    annotations: BTreeMap<AnnotationId, Annotation>,
+}
+
+impl Annotations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create<T: ToString>(&mut self, interval: Interval, payload: T) -> AnnotationId {
+        self.next_id += 1;
+        let annotation = Annotation {
+            id: self.next_id,
+            interval,
+            payload: payload.to_string(),
+            orphaned: false,
+        };
+        self.annotations.insert(annotation.id, annotation);
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: AnnotationId) -> Option<Annotation> {
+        self.annotations.remove(&id)
+    }
+
+    pub fn get(&self, id: AnnotationId) -> Option<&Annotation> {
+        self.annotations.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Annotation> {
+        self.annotations.values()
+    }
+
+    /// Splits the annotations at `index`. The ones from it on go to the second, moved by
+    /// `index`, and the ones across it are clipped to each side and go to both. Both keep the ids
+    /// of the annotations.
+    pub fn split_at(&self, index: usize) -> (Annotations, Annotations) {
+        let mut first = Annotations {
+            next_id: self.next_id,
+            annotations: BTreeMap::new(),
+        };
+        let mut second = first.clone();
+        for annotation in self.annotations.values() {
+            let Interval { start, end } = annotation.interval;
+            if start < index {
+                let interval = Interval::new(start, end.min(index));
+                first.annotations.insert(
+                    annotation.id,
+                    Annotation {
+                        interval,
+                        ..annotation.clone()
+                    },
+                );
+            }
+            if start >= index || end > index {
+                let interval = Interval::new(start.max(index) - index, end - index);
+                second.annotations.insert(
+                    annotation.id,
+                    Annotation {
+                        interval,
+                        ..annotation.clone()
+                    },
+                );
+            }
+        }
+        (first, second)
+    }
+
+    /// Adds the annotations of `other`, moved by `offset`, with new ids. Returns the new ids by
+    /// the ids in `other`.
+    pub fn append(&mut self, other: &Annotations, offset: usize) -> BTreeMap<AnnotationId, AnnotationId> {
+        let mut ids = BTreeMap::new();
+        for annotation in other.iter() {
+            let Interval { start, end } = annotation.interval;
+            let id = self.create(Interval::new(start + offset, end + offset), &annotation.payload);
+            if let Some(appended) = self.annotations.get_mut(&id) {
+                appended.orphaned = annotation.orphaned;
+            }
+            ids.insert(annotation.id, id);
+        }
+        ids
+    }
+
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        if delta.is_noop() {
+            return;
+        }
+
+        for annotation in self.annotations.values_mut() {
+            let Interval { start, end } = annotation.interval;
+            // The text typed at the edges of the annotation isn't annotated.
+            let new_start = delta.transform_position(start, false);
+            let new_end = delta.transform_position(end, true).max(new_start);
+            if start != end && new_start == new_end {
+                annotation.orphaned = true;
+            }
+            annotation.interval = Interval::new(new_start, new_end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::annotation::Annotations;
+    use lib_ot::{core::Interval, rich_text::RichTextDeltaBuilder};
+
+    #[test]
+    fn annotation_orphaned_after_delete() {
+        let mut annotations = Annotations::new();
+        let id_1 = annotations.create(Interval::new(2, 4), "comment 1");
+        let id_2 = annotations.create(Interval::new(3, 5), "comment 2");
+
+        let delta = RichTextDeltaBuilder::new().insert("ab").retain(3).delete(2).build();
+        annotations.transform(&delta);
+
+        let annotation_1 = annotations.get(id_1).unwrap();
+        assert_eq!(annotation_1.interval, Interval::new(4, 5));
+        assert!(!annotation_1.orphaned);
+
+        let annotation_2 = annotations.get(id_2).unwrap();
+        assert_eq!(annotation_2.interval, Interval::new(5, 5));
+        assert!(annotation_2.orphaned);
+    }
+}
@@ -0,0 +1,630 @@
+use lib_ot::{
+    core::{count_utf16_code_units, Embed, Interval, Operation, EMBED_CHAR, NEW_LINE},
+    rich_text::{RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+use std::{collections::VecDeque, ops::Range};
+
+/// Identifies a block of a [DocumentTree] across its updates, see [DocumentTree::apply].
+pub type BlockId = u64;
+
+/// The document as a tree of blocks made of inline spans, for the exporters, the accessibility
+/// and the renderers, which would otherwise find the lines and their formats in the delta
+/// themselves. The tree keeps all the content: [DocumentTree::to_delta] gives the delta back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentTree {
+    pub blocks: Vec<Block>,
+    next_id: BlockId,
+}
+
+/// The blocks that an update of the tree changed, which the renderer paints again.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeChanges {
+    /// The blocks whose content or format changed, the blocks that moved and the new blocks, in
+    /// order.
+    pub changed: Vec<BlockId>,
+    pub removed: Vec<BlockId>,
+    /// The changes of the structure of the document, e.g. for the outline, the removed blocks
+    /// first and then the other blocks in order.
+    pub blocks: Vec<BlockChange>,
+}
+
+/// How an update changed a block. The lines are the indexes of the blocks in the tree, before the
+/// update for the removed blocks and where the moved blocks were, after it otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockChange {
+    Inserted {
+        id: BlockId,
+        line: usize,
+        kind: BlockKind,
+    },
+    Removed {
+        id: BlockId,
+        line: usize,
+    },
+    /// The block was cut and inserted elsewhere in the same change, without changing it.
+    Moved {
+        id: BlockId,
+        from: usize,
+        to: usize,
+    },
+    /// The kind of the block changed, e.g. the level of a heading or a paragraph that became a
+    /// list item.
+    KindChanged {
+        id: BlockId,
+        line: usize,
+        old: BlockKind,
+        new: BlockKind,
+    },
+    /// The text or the inline format of the block changed, but not its kind.
+    ContentChanged {
+        id: BlockId,
+        line: usize,
+    },
+}
+
+/// A line of the document. The lines of a list or of a code block are blocks of their own, like
+/// in the delta, the exporters group them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub id: BlockId,
+    pub kind: BlockKind,
+    pub spans: Vec<Span>,
+    /// The attributes of the newline that ends the block, which has the block attributes.
+    pub attributes: RichTextAttributes,
+    /// `false` for the text after the last newline of the document, which has no newline.
+    pub terminated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockKind {
+    Paragraph,
+    Heading(usize),
+    ListItem {
+        /// The kind of the list, e.g. "bullet" or "ordered".
+        list: String,
+        indent: usize,
+        number: Option<usize>,
+    },
+    CodeBlock {
+        language: Option<String>,
+    },
+    Quote,
+}
+
+/// A run of the text of a block that has the same inline attributes, or an embed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Span {
+    Text {
+        text: String,
+        attributes: RichTextAttributes,
+    },
+    Embed {
+        embed: Embed,
+        attributes: RichTextAttributes,
+    },
+}
+
+impl DocumentTree {
+    /// Parses `document`, a delta of inserts.
+    pub fn parse(document: &RichTextDelta) -> Self {
+        let mut tree = Self::default();
+        let blocks = tree.parse_blocks(document);
+        tree.blocks = blocks;
+        tree
+    }
+
+    /// Updates the tree after `delta` was composed into the document, `document` is the new
+    /// content of the document. Only the blocks that `delta` touches are parsed again. They keep
+    /// the ids of the old blocks with the same content, the blocks that moved, then the ids of
+    /// the other old blocks in order, and the blocks that it added get new ones.
+    pub fn apply(&mut self, delta: &RichTextDelta, document: &RichTextDelta) -> TreeChanges {
+        let changed = match changed_interval(delta) {
+            None => return TreeChanges::default(),
+            Some(changed) => changed,
+        };
+        let old_len: usize = self.blocks.iter().map(Block::utf16_size).sum();
+        let (first, first_start) = self.block_at(changed.start);
+        let (last, last_start) = self.block_at(changed.end);
+        let old_end = last_start + self.blocks.get(last).map(Block::utf16_size).unwrap_or(0);
+        let last = (last + 1).min(self.blocks.len());
+        let new_end = (old_end + document.utf16_target_len).saturating_sub(old_len);
+        self.reparse(first..last, Interval::new(first_start, new_end), document)
+    }
+
+    /// Parses the new content of the document, which wasn't changed by a delta, again. The blocks
+    /// keep their ids like in [DocumentTree::apply].
+    pub fn replace(&mut self, document: &RichTextDelta) -> TreeChanges {
+        let blocks = 0..self.blocks.len();
+        self.reparse(blocks, Interval::new(0, document.utf16_target_len), document)
+    }
+
+    // Replaces the blocks in `range` by the blocks of the `interval` of the new document.
+    fn reparse(&mut self, range: Range<usize>, interval: Interval, document: &RichTextDelta) -> TreeChanges {
+        let first = range.start;
+        let mut part = RichTextDelta::new();
+        for slice in document.ops_in(interval) {
+            if let Some(op) = slice.to_op() {
+                part.add(op);
+            }
+        }
+        let mut blocks = self.parse_blocks(&part);
+        let old_blocks = self.blocks.drain(range).collect::<Vec<_>>();
+
+        // The old block of each new block, and whether it has the same content.
+        let mut old_indexes: Vec<Option<(usize, bool)>> = vec![None; blocks.len()];
+        let mut is_used = vec![false; old_blocks.len()];
+        for (i, block) in blocks.iter().enumerate() {
+            if let Some(j) = (0..old_blocks.len()).find(|j| !is_used[*j] && block.same_content(&old_blocks[*j])) {
+                is_used[j] = true;
+                old_indexes[i] = Some((j, true));
+            }
+        }
+        let mut unused = (0..old_blocks.len()).filter(|j| !is_used[*j]).collect::<VecDeque<_>>();
+        for old_index in old_indexes.iter_mut().filter(|old_index| old_index.is_none()) {
+            *old_index = unused.pop_front().map(|j| (j, false));
+        }
+        // The blocks with the same content that keep their order didn't move.
+        let same = old_indexes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, old_index)| match old_index {
+                Some((j, true)) => Some((i, *j)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut moved = vec![true; blocks.len()];
+        let old_order = same.iter().map(|(_, j)| *j).collect::<Vec<_>>();
+        for position in longest_increasing(&old_order) {
+            moved[same[position].0] = false;
+        }
+
+        let mut changes = TreeChanges::default();
+        for j in unused {
+            changes.removed.push(old_blocks[j].id);
+            changes.blocks.push(BlockChange::Removed {
+                id: old_blocks[j].id,
+                line: first + j,
+            });
+        }
+        for (i, block) in blocks.iter_mut().enumerate() {
+            let line = first + i;
+            let change = match old_indexes[i] {
+                None => Some(BlockChange::Inserted {
+                    id: block.id,
+                    line,
+                    kind: block.kind.clone(),
+                }),
+                Some((j, true)) => {
+                    block.id = old_blocks[j].id;
+                    match moved[i] {
+                        false => None,
+                        true => Some(BlockChange::Moved {
+                            id: block.id,
+                            from: first + j,
+                            to: line,
+                        }),
+                    }
+                }
+                Some((j, false)) if block.kind != old_blocks[j].kind => {
+                    block.id = old_blocks[j].id;
+                    Some(BlockChange::KindChanged {
+                        id: block.id,
+                        line,
+                        old: old_blocks[j].kind.clone(),
+                        new: block.kind.clone(),
+                    })
+                }
+                Some((j, false)) => {
+                    block.id = old_blocks[j].id;
+                    Some(BlockChange::ContentChanged { id: block.id, line })
+                }
+            };
+            if let Some(change) = change {
+                changes.changed.push(block.id);
+                changes.blocks.push(change);
+            }
+        }
+        self.blocks.splice(first..first, blocks);
+        changes
+    }
+
+    /// The offset of the block at `line` in the document.
+    pub fn offset_of(&self, line: usize) -> usize {
+        self.blocks.iter().take(line).map(Block::utf16_size).sum()
+    }
+
+    // Returns the index of the block that contains `index` and the offset of the block. The index
+    // at the end of the document belongs to the last block.
+    fn block_at(&self, index: usize) -> (usize, usize) {
+        let mut start = 0;
+        for (i, block) in self.blocks.iter().enumerate() {
+            if index < start + block.utf16_size() || i + 1 == self.blocks.len() {
+                return (i, start);
+            }
+            start += block.utf16_size();
+        }
+        (0, 0)
+    }
+
+    fn parse_blocks(&mut self, document: &RichTextDelta) -> Vec<Block> {
+        let mut blocks = vec![];
+        let mut spans = vec![];
+        for op in &document.ops {
+            let insert = match op {
+                Operation::Insert(insert) => insert,
+                _ => continue,
+            };
+            if let Some(embed) = &insert.embed {
+                spans.push(Span::Embed {
+                    embed: embed.clone(),
+                    attributes: insert.attributes.clone(),
+                });
+                continue;
+            }
+            for piece in insert.s.split_inclusive(NEW_LINE) {
+                let text = piece.strip_suffix(NEW_LINE);
+                if !text.unwrap_or(piece).is_empty() {
+                    spans.push(Span::Text {
+                        text: text.unwrap_or(piece).to_owned(),
+                        attributes: insert.attributes.clone(),
+                    });
+                }
+                if text.is_some() {
+                    let spans = std::mem::take(&mut spans);
+                    blocks.push(Block::new(self.gen_id(), spans, insert.attributes.clone(), true));
+                }
+            }
+        }
+        if !spans.is_empty() {
+            blocks.push(Block::new(self.gen_id(), spans, RichTextAttributes::default(), false));
+        }
+        blocks
+    }
+
+    fn gen_id(&mut self) -> BlockId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn to_delta(&self) -> RichTextDelta {
+        let mut delta = RichTextDelta::new();
+        for block in &self.blocks {
+            block.write(&mut delta);
+        }
+        delta
+    }
+}
+
+impl Block {
+    fn new(id: BlockId, spans: Vec<Span>, attributes: RichTextAttributes, terminated: bool) -> Self {
+        Self {
+            id,
+            kind: BlockKind::from_attributes(&attributes),
+            spans,
+            attributes,
+            terminated,
+        }
+    }
+
+    /// The text of the block, without its newline. An embed is its placeholder character.
+    pub fn text(&self) -> String {
+        self.spans
+            .iter()
+            .map(|span| match span {
+                Span::Text { text, .. } => text.clone(),
+                Span::Embed { .. } => EMBED_CHAR.to_owned(),
+            })
+            .collect()
+    }
+
+    /// The utf16 length of the block in the document, including its newline.
+    pub fn utf16_size(&self) -> usize {
+        let len: usize = self
+            .spans
+            .iter()
+            .map(|span| match span {
+                Span::Text { text, .. } => count_utf16_code_units(text),
+                Span::Embed { .. } => 1,
+            })
+            .sum();
+        len + self.terminated as usize
+    }
+
+    fn same_content(&self, other: &Block) -> bool {
+        self.spans == other.spans && self.attributes == other.attributes && self.terminated == other.terminated
+    }
+
+    fn write(&self, delta: &mut RichTextDelta) {
+        for span in &self.spans {
+            match span {
+                Span::Text { text, attributes } => delta.insert(text, attributes.clone()),
+                Span::Embed { embed, attributes } => delta.insert_embed(embed.clone(), attributes.clone()),
+            }
+        }
+        if self.terminated {
+            delta.insert(NEW_LINE, self.attributes.clone());
+        }
+    }
+}
+
+// The interval of the document before `delta` that `delta` changes, its text or its format.
+fn changed_interval(delta: &RichTextDelta) -> Option<Interval> {
+    let mut changed: Option<Interval> = None;
+    let mut offset = 0;
+    for op in &delta.ops {
+        let len = match op {
+            Operation::Retain(retain) if retain.attributes.is_empty() => {
+                offset += retain.n;
+                continue;
+            }
+            Operation::Retain(retain) => retain.n,
+            Operation::Delete(n) => *n,
+            Operation::Insert(_) => 0,
+        };
+        let start = changed.map(|interval| interval.start).unwrap_or(offset);
+        changed = Some(Interval::new(start, offset + len));
+        offset += len;
+    }
+    changed
+}
+
+// Returns the positions in `values` of one of their longest increasing subsequences.
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // The position of the last value of the lowest subsequence of each length, and the position
+    // of the value before each value in its subsequence.
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; values.len()];
+    for (i, value) in values.iter().enumerate() {
+        let len = tails.partition_point(|tail| values[*tail] < *value);
+        if len > 0 {
+            previous[i] = Some(tails[len - 1]);
+        }
+        match tails.get_mut(len) {
+            None => tails.push(i),
+            Some(tail) => *tail = i,
+        }
+    }
+    let mut positions = vec![];
+    let mut position = tails.last().copied();
+    while let Some(i) = position {
+        positions.push(i);
+        position = previous[i];
+    }
+    positions.reverse();
+    positions
+}
+
+impl BlockKind {
+    /// The kind of the line with the block `attributes`. A code block wins over the other
+    /// formats of the line, then the header, the list and the quote.
+    pub fn from_attributes(attributes: &RichTextAttributes) -> Self {
+        let get = |key: &RichTextAttributeKey| attributes.get(key).filter(|value| value.0.is_some());
+        let int = |key: &RichTextAttributeKey| get(key).and_then(|value| value.as_int()).map(|n| n.max(0) as usize);
+        if get(&RichTextAttributeKey::CodeBlock).and_then(|value| value.as_bool()) == Some(true) {
+            let language = get(&RichTextAttributeKey::CodeLanguage).and_then(|value| value.as_str());
+            return BlockKind::CodeBlock {
+                language: language.map(str::to_owned),
+            };
+        }
+        if let Some(level) = int(&RichTextAttributeKey::Header) {
+            return BlockKind::Heading(level);
+        }
+        if let Some(list) = get(&RichTextAttributeKey::List).and_then(|value| value.as_str()) {
+            return BlockKind::ListItem {
+                list: list.to_owned(),
+                indent: int(&RichTextAttributeKey::Indent).unwrap_or(0),
+                number: int(&RichTextAttributeKey::ListNumber),
+            };
+        }
+        if get(&RichTextAttributeKey::BlockQuote).and_then(|value| value.as_bool()) == Some(true) {
+            return BlockKind::Quote;
+        }
+        BlockKind::Paragraph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::ast::{BlockChange, BlockKind, DocumentTree, Span, TreeChanges};
+    use lib_ot::{
+        core::{Embed, OperationTransformable},
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributes, RichTextDelta, RichTextDeltaBuilder},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn parse_blocks_and_spans() {
+        let bold: RichTextAttributes = RichTextAttribute::Bold(true).into();
+        let document = RichTextDeltaBuilder::new()
+            .insert("Title")
+            .insert_with_attributes("\n", RichTextAttribute::Header(1).into())
+            .insert("Some ")
+            .insert_with_attributes("bold", bold.clone())
+            .insert(" text\nitem")
+            .insert_with_attributes(
+                "\n",
+                AttributeBuilder::new()
+                    .add_attr(RichTextAttribute::List("ordered"))
+                    .add_attr(RichTextAttribute::ListNumber(1))
+                    .build(),
+            )
+            .insert("tail")
+            .build();
+        let tree = DocumentTree::parse(&document);
+        let kinds = tree.blocks.iter().map(|block| block.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                BlockKind::Heading(1),
+                BlockKind::Paragraph,
+                BlockKind::ListItem {
+                    list: "ordered".to_owned(),
+                    indent: 0,
+                    number: Some(1),
+                },
+                BlockKind::Paragraph,
+            ]
+        );
+        assert_eq!(
+            tree.blocks[1].spans[1],
+            Span::Text {
+                text: "bold".to_owned(),
+                attributes: bold,
+            }
+        );
+        assert_eq!(tree.blocks[1].text(), "Some bold text");
+        assert!(!tree.blocks[3].terminated);
+        assert_eq!(tree.to_delta(), document);
+    }
+
+    // Every block format, with and without inline formats and embeds, in every position of the
+    // document, gives the same delta back.
+    #[test]
+    fn round_trip_every_format() {
+        let block_formats: Vec<RichTextAttributes> = vec![
+            RichTextAttributes::default(),
+            RichTextAttribute::Header(2).into(),
+            RichTextAttribute::List("bullet").into(),
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::List("ordered"))
+                .add_attr(RichTextAttribute::Indent(1))
+                .add_attr(RichTextAttribute::ListNumber(3))
+                .build(),
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::CodeBlock(true))
+                .add_attr(RichTextAttribute::CodeLanguage("rust"))
+                .build(),
+            RichTextAttribute::BlockQuote(true).into(),
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::Header(1))
+                .add_attr(RichTextAttribute::Bold(true))
+                .build(),
+        ];
+        let inline_formats: Vec<RichTextAttributes> = vec![
+            RichTextAttributes::default(),
+            RichTextAttribute::Italic(true).into(),
+            RichTextAttribute::Link("https://appflowy.io").into(),
+        ];
+        let embed = Embed::new("image", json!({ "src": "a.png" }));
+
+        for block in &block_formats {
+            for inline in &inline_formats {
+                for with_embed in [false, true] {
+                    for terminated in [false, true] {
+                        let mut document = RichTextDelta::new();
+                        document.insert("a\n", RichTextAttributes::default());
+                        document.insert("b", inline.clone());
+                        if with_embed {
+                            document.insert_embed(embed.clone(), inline.clone());
+                        }
+                        document.insert("c", RichTextAttributes::default());
+                        document.insert("\n", block.clone());
+                        document.insert("\n", block.clone());
+                        if !terminated {
+                            document.insert("d", inline.clone());
+                        }
+                        let tree = DocumentTree::parse(&document);
+                        assert_eq!(tree.to_delta(), document, "{}", document.to_json());
+                        assert_eq!(tree.blocks.len(), 3 + !terminated as usize);
+                    }
+                }
+            }
+        }
+        assert_eq!(DocumentTree::parse(&RichTextDelta::new()).blocks, vec![]);
+    }
+
+    #[test]
+    fn apply_parses_touched_blocks_again() {
+        let mut document = RichTextDeltaBuilder::new().insert("one\ntwo\nthree\n").build();
+        let mut tree = DocumentTree::parse(&document);
+        let ids = tree.blocks.iter().map(|block| block.id).collect::<Vec<_>>();
+        let mut apply = |delta: RichTextDelta| {
+            document = document.compose(&delta).unwrap();
+            let changes = tree.apply(&delta, &document);
+            assert_eq!(tree.to_delta(), document);
+            changes
+        };
+
+        // Type in the second line.
+        let changes = apply(RichTextDeltaBuilder::new().retain(5).insert("w").build());
+        assert_eq!(
+            changes,
+            TreeChanges {
+                changed: vec![ids[1]],
+                removed: vec![],
+                blocks: vec![BlockChange::ContentChanged { id: ids[1], line: 1 }],
+            }
+        );
+        // Split the first line.
+        let changes = apply(RichTextDeltaBuilder::new().retain(2).insert("\n").build());
+        assert_eq!(changes.changed.len(), 2);
+        assert_eq!(changes.changed[0], ids[0]);
+        assert!(!ids.contains(&changes.changed[1]));
+        // Join the last two lines.
+        let changes = apply(RichTextDeltaBuilder::new().retain(9).delete(1).build());
+        assert_eq!(
+            changes,
+            TreeChanges {
+                changed: vec![ids[1]],
+                removed: vec![ids[2]],
+                blocks: vec![
+                    BlockChange::Removed { id: ids[2], line: 3 },
+                    BlockChange::ContentChanged { id: ids[1], line: 2 },
+                ],
+            }
+        );
+        // Make the last line a heading.
+        let changes = apply(
+            RichTextDeltaBuilder::new()
+                .retain(14)
+                .retain_with_attributes(1, RichTextAttribute::Header(1).into())
+                .build(),
+        );
+        assert_eq!(changes.changed, vec![ids[1]]);
+        assert_eq!(changes.removed, vec![]);
+        assert_eq!(tree.blocks[2].kind, BlockKind::Heading(1));
+        assert_eq!(
+            changes.blocks,
+            vec![BlockChange::KindChanged {
+                id: ids[1],
+                line: 2,
+                old: BlockKind::Paragraph,
+                new: BlockKind::Heading(1),
+            }]
+        );
+    }
+
+    #[test]
+    fn apply_finds_the_moved_blocks() {
+        let mut document = RichTextDeltaBuilder::new().insert("a\nb\nc\nd\n").build();
+        let mut tree = DocumentTree::parse(&document);
+        let ids = tree.blocks.iter().map(|block| block.id).collect::<Vec<_>>();
+
+        // Move the last line to the front.
+        let delta = RichTextDeltaBuilder::new().insert("d\n").retain(6).delete(2).build();
+        document = document.compose(&delta).unwrap();
+        let changes = tree.apply(&delta, &document);
+        assert_eq!(tree.to_delta(), document);
+        assert_eq!(
+            tree.blocks.iter().map(|block| block.id).collect::<Vec<_>>(),
+            vec![ids[3], ids[0], ids[1], ids[2]]
+        );
+        assert_eq!(
+            changes.blocks,
+            vec![BlockChange::Moved {
+                id: ids[3],
+                from: 3,
+                to: 0,
+            }]
+        );
+
+        let changes = tree.replace(&RichTextDeltaBuilder::new().insert("d\nb\nx\n").build());
+        assert_eq!(
+            changes.blocks,
+            vec![
+                BlockChange::Removed { id: ids[2], line: 3 },
+                BlockChange::ContentChanged { id: ids[0], line: 2 },
+            ]
+        );
+    }
+}
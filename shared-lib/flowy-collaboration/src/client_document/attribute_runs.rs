@@ -0,0 +1,256 @@
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+};
+use lib_ot::{
+    core::{Attributes, Interval, Operation, OperationTransformable},
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+
+/// Remembers the attributes of the document as runs of text with the same attributes, so the
+/// attributes at an index are found with a binary search instead of walking the operations of the
+/// document. The runs are updated by every delta composed into the document, only around the text
+/// that the delta changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AttributeRuns {
+    // The utf16 offset where each run ends and the attributes of its text.
+    runs: Vec<(usize, RichTextAttributes)>,
+}
+
+impl AttributeRuns {
+    pub fn new(document: &RichTextDelta) -> Self {
+        let mut lens = vec![];
+        for op in &document.ops {
+            if let Operation::Insert(insert) = op {
+                lens.push((insert.utf16_size(), insert.attributes.clone()));
+            }
+        }
+        let mut runs = Self::default();
+        runs.runs = to_runs(0, lens);
+        runs
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.last().map(|(end, _)| *end).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of the runs, the text between two changes of the attributes.
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Returns the attributes of the character at `index`, none if `index` is past the end of the
+    /// document.
+    pub fn attributes_at(&self, index: usize) -> Option<&RichTextAttributes> {
+        let i = self.runs.partition_point(|(end, _)| *end <= index);
+        self.runs.get(i).map(|(_, attributes)| attributes)
+    }
+
+    /// Updates the runs after `delta` was composed into the document.
+    pub fn apply(&mut self, delta: &RichTextDelta) {
+        let changed = match changed_interval(delta) {
+            None => return,
+            Some(changed) => changed,
+        };
+
+        // The runs around the change are built again, the one before and the one after it are
+        // included so that the new runs are merged with them.
+        let first = self
+            .runs
+            .partition_point(|(end, _)| *end < changed.start)
+            .saturating_sub(1);
+        let last = (self.runs.partition_point(|(end, _)| *end < changed.end) + 2).min(self.runs.len());
+        let start = match first {
+            0 => 0,
+            _ => self.runs[first - 1].0,
+        };
+        let end = match last {
+            0 => 0,
+            _ => self.runs[last - 1].0,
+        };
+
+        let mut old = self.runs[first..last]
+            .iter()
+            .scan(start, |run_start, (run_end, attributes)| {
+                let len = run_end - *run_start;
+                *run_start = *run_end;
+                Some((len, attributes.clone()))
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut current: Option<(usize, RichTextAttributes)> = None;
+        let mut next_run =
+            |n: usize, current: &mut Option<(usize, RichTextAttributes)>| -> Option<(usize, RichTextAttributes)> {
+                let (len, attributes) = match current.take() {
+                    None => old.next()?,
+                    Some(run) => run,
+                };
+                if len > n {
+                    *current = Some((len - n, attributes.clone()));
+                    Some((n, attributes))
+                } else {
+                    Some((len, attributes))
+                }
+            };
+
+        let mut lens = vec![];
+        let mut offset = 0;
+        for op in &delta.ops {
+            match op {
+                Operation::Retain(retain) => {
+                    // Only the part of the retain that falls in the runs being built matters, the
+                    // rest of it keeps the attributes of the text.
+                    let skipped = start.saturating_sub(offset).min(retain.n);
+                    let mut n = (retain.n - skipped).min(end.saturating_sub(offset + skipped));
+                    offset += retain.n;
+                    while n > 0 {
+                        match next_run(n, &mut current) {
+                            None => break,
+                            Some((len, attributes)) => {
+                                let mut attributes = attributes.compose(&retain.attributes).unwrap_or(attributes);
+                                attributes.remove_empty();
+                                lens.push((len, attributes));
+                                n -= len;
+                            }
+                        }
+                    }
+                }
+                Operation::Delete(n) => {
+                    let mut n = *n;
+                    offset += n;
+                    while n > 0 {
+                        match next_run(n, &mut current) {
+                            None => break,
+                            Some((len, _)) => n -= len,
+                        }
+                    }
+                }
+                Operation::Insert(insert) => lens.push((insert.utf16_size(), insert.attributes.clone())),
+            }
+        }
+        while let Some(run) = next_run(usize::MAX, &mut current) {
+            lens.push(run);
+        }
+
+        let new_runs = to_runs(start, lens);
+        let new_end = new_runs.last().map(|(end, _)| *end).unwrap_or(start);
+        let tail = self.runs.split_off(last);
+        self.runs.truncate(first);
+        self.runs.extend(new_runs);
+        self.runs.extend(
+            tail.into_iter()
+                .map(|(run_end, attributes)| (run_end + new_end - end, attributes)),
+        );
+    }
+}
+
+impl DocumentObserver for AttributeRuns {
+    fn did_reset(&mut self, document: &ObservedDocument, _replaced: Option<&RichTextDelta>) {
+        *self = AttributeRuns::new(document.delta);
+    }
+
+    fn did_change(&mut self, _document: &ObservedDocument, event: &DocumentEvent) {
+        self.apply(&event.delta);
+    }
+}
+
+// The range of the old content that `delta` changes, the text or the attributes.
+fn changed_interval(delta: &RichTextDelta) -> Option<Interval> {
+    let mut changed: Option<Interval> = None;
+    let mut offset = 0;
+    for op in &delta.ops {
+        let len = match op {
+            Operation::Retain(retain) if retain.attributes.is_empty() => {
+                offset += retain.n;
+                continue;
+            }
+            Operation::Retain(retain) => retain.n,
+            Operation::Delete(n) => *n,
+            Operation::Insert(_) => 0,
+        };
+        let start = changed.map(|interval| interval.start).unwrap_or(offset);
+        changed = Some(Interval::new(start, offset + len));
+        offset += len;
+    }
+    changed
+}
+
+// Turns the lengths of the runs into their ends, starting at `start`. The empty runs are dropped
+// and the adjacent runs with the same attributes are merged.
+fn to_runs(start: usize, lens: Vec<(usize, RichTextAttributes)>) -> Vec<(usize, RichTextAttributes)> {
+    let mut runs: Vec<(usize, RichTextAttributes)> = vec![];
+    let mut end = start;
+    for (len, attributes) in lens {
+        if len == 0 {
+            continue;
+        }
+        end += len;
+        match runs.last_mut() {
+            Some((last_end, last_attributes)) if *last_attributes == attributes => *last_end = end,
+            _ => runs.push((end, attributes)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::attribute_runs::AttributeRuns;
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{
+            AttributeBuilder, RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta,
+            RichTextDeltaBuilder,
+        },
+    };
+
+    #[test]
+    fn attribute_runs_follow_delta() {
+        let bold = AttributeBuilder::new().add_attr(RichTextAttribute::Bold(true)).build();
+        let italic = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Italic(true))
+            .build();
+        let mut document = RichTextDeltaBuilder::new()
+            .insert("123")
+            .insert_with_attributes("456", bold.clone())
+            .insert("789\n")
+            .build();
+        let mut runs = AttributeRuns::new(&document);
+        assert_eq!(runs.attributes_at(4), Some(&bold));
+        assert_eq!(runs.attributes_at(9), Some(&RichTextAttributes::default()));
+        assert_eq!(runs.attributes_at(10), None);
+
+        let deltas: Vec<RichTextDelta> = vec![
+            // Type in the bold text.
+            RichTextDeltaBuilder::new()
+                .retain(4)
+                .insert_with_attributes("a", bold.clone())
+                .build(),
+            // Make the middle of the text italic, across the runs.
+            RichTextDeltaBuilder::new()
+                .retain(2)
+                .retain_with_attributes(5, italic)
+                .build(),
+            // Remove the bold, then delete the text around the runs.
+            RichTextDeltaBuilder::new()
+                .retain(3)
+                .retain_with_attributes(
+                    4,
+                    AttributeBuilder::new().remove_attr(RichTextAttributeKey::Bold).build(),
+                )
+                .build(),
+            RichTextDeltaBuilder::new().retain(1).delete(7).insert("b").build(),
+            RichTextDeltaBuilder::new().delete(3).build(),
+        ];
+        for delta in deltas {
+            document = document.compose(&delta).unwrap();
+            runs.apply(&delta);
+            assert_eq!(runs, AttributeRuns::new(&document));
+        }
+        assert_eq!(runs.len(), document.utf16_target_len);
+    }
+}
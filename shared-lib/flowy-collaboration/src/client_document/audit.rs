@@ -0,0 +1,229 @@
+use crate::errors::{internal_error, CollaborateError};
+use lib_ot::{core::Operation, rich_text::RichTextDelta};
+use serde::{de::IgnoredAny, Deserialize};
+use std::ops::RangeInclusive;
+
+/// The number of operations of each kind in a delta.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCounts {
+    pub inserts: usize,
+    pub deletes: usize,
+    /// The retains that format the text, the ones without attributes are only counted in
+    /// `retains`.
+    pub formats: usize,
+    pub retains: usize,
+}
+
+impl OpCounts {
+    pub fn from_delta(delta: &RichTextDelta) -> Self {
+        let mut counts = Self::default();
+        for op in &delta.ops {
+            match op {
+                Operation::Insert(_) => counts.inserts += 1,
+                Operation::Delete(_) => counts.deletes += 1,
+                Operation::Retain(retain) => {
+                    counts.retains += 1;
+                    if !retain.attributes.is_empty() {
+                        counts.formats += 1;
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// Counts the operations of the json of a delta without reading their text and attributes.
+    pub fn scan(delta_data: &[u8]) -> Result<Self, CollaborateError> {
+        let ops: Vec<OpProbe> = serde_json::from_slice(delta_data).map_err(internal_error)?;
+        let mut counts = Self::default();
+        for op in ops {
+            match op {
+                OpProbe { insert: Some(_), .. } => counts.inserts += 1,
+                OpProbe { delete: Some(_), .. } => counts.deletes += 1,
+                OpProbe {
+                    retain: Some(_),
+                    attributes,
+                    ..
+                } => {
+                    counts.retains += 1;
+                    if attributes.is_some() {
+                        counts.formats += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Whether the delta changes the text or its attributes.
+    pub fn is_change(&self) -> bool {
+        self.inserts + self.deletes + self.formats > 0
+    }
+}
+
+// An operation of the json of a delta, see [OpCounts::scan].
+#[derive(Deserialize)]
+struct OpProbe {
+    insert: Option<IgnoredAny>,
+    delete: Option<IgnoredAny>,
+    retain: Option<IgnoredAny>,
+    attributes: Option<IgnoredAny>,
+}
+
+/// What [RevisionLog::query](crate::client_document::revision_log::RevisionLog::query) tells of a
+/// revision, e.g. for an audit view of who changed what and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionMeta {
+    pub rev_id: i64,
+    pub base_rev_id: i64,
+    pub author_id: String,
+    pub device_id: String,
+    /// The time the revision was made, in milliseconds since the epoch, 0 if unknown.
+    pub timestamp: i64,
+    /// The size of the json of its delta.
+    pub size: usize,
+    pub ops: OpCounts,
+    /// Whether it changes the metadata of the document instead of its content.
+    pub is_metadata: bool,
+}
+
+/// Selects the revisions of
+/// [RevisionLog::query](crate::client_document::revision_log::RevisionLog::query). The revisions
+/// match the filter if they match each of its conditions that is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevisionFilter {
+    pub author_id: Option<String>,
+    pub device_id: Option<String>,
+    pub rev_ids: Option<RangeInclusive<i64>>,
+    /// The times the revisions were made in, in milliseconds since the epoch.
+    pub timestamps: Option<RangeInclusive<i64>>,
+    /// Only the revisions that insert, delete or format text.
+    pub changes_only: bool,
+}
+
+impl RevisionFilter {
+    pub fn author(mut self, author_id: &str) -> Self {
+        self.author_id = Some(author_id.to_owned());
+        self
+    }
+
+    pub fn device(mut self, device_id: &str) -> Self {
+        self.device_id = Some(device_id.to_owned());
+        self
+    }
+
+    pub fn rev_ids(mut self, rev_ids: RangeInclusive<i64>) -> Self {
+        self.rev_ids = Some(rev_ids);
+        self
+    }
+
+    pub fn timestamps(mut self, timestamps: RangeInclusive<i64>) -> Self {
+        self.timestamps = Some(timestamps);
+        self
+    }
+
+    pub fn changes_only(mut self) -> Self {
+        self.changes_only = true;
+        self
+    }
+
+    pub fn matches(&self, meta: &RevisionMeta) -> bool {
+        self.author_id
+            .as_ref()
+            .map_or(true, |author_id| &meta.author_id == author_id)
+            && self
+                .device_id
+                .as_ref()
+                .map_or(true, |device_id| &meta.device_id == device_id)
+            && self
+                .rev_ids
+                .as_ref()
+                .map_or(true, |rev_ids| rev_ids.contains(&meta.rev_id))
+            && self
+                .timestamps
+                .as_ref()
+                .map_or(true, |timestamps| timestamps.contains(&meta.timestamp))
+            && (!self.changes_only || meta.ops.is_change())
+    }
+}
+
+/// The stack of the history that an entry is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryStack {
+    Undo,
+    Redo,
+}
+
+/// What [History::entries_meta](crate::client_document::history::History::entries_meta) tells of
+/// an entry, e.g. for the storage diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntryMeta {
+    pub stack: HistoryStack,
+    /// The revision the entry reverts to, if it's known.
+    pub rev_id: Option<i64>,
+    /// The bytes that the entry takes, compressed if it is.
+    pub size: usize,
+    /// The operations of the entry, none if its delta is compressed or is rebuilt from its
+    /// revision when it's applied.
+    pub ops: Option<OpCounts>,
+    pub is_compressed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        audit::{HistoryStack, OpCounts, RevisionFilter},
+        clock::ManualClock,
+        ClientDocument, DocumentConfigBuilder, NewlineDoc,
+    };
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+    use std::sync::Arc;
+
+    #[test]
+    fn query_who_changed_what_and_when() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        let config = |author: &str| {
+            DocumentConfigBuilder::new("doc")
+                .author(author, "laptop")
+                .clock(clock.clone())
+                .build()
+        };
+        document.set_config(config("alice"));
+        document.insert(0, "hello").unwrap();
+        clock.advance(60_000);
+        document.set_config(config("bob"));
+        document
+            .format(Interval::new(0, 5), RichTextAttribute::Bold(true))
+            .unwrap();
+        document.set_metadata("title", "Hello").unwrap();
+
+        let log = document.revision_log();
+        let all = log.query(&RevisionFilter::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(
+            all[1].ops,
+            OpCounts {
+                formats: 1,
+                retains: 2,
+                ..OpCounts::default()
+            }
+        );
+        assert_eq!(all[1].size, log.revisions()[1].delta_data.len());
+
+        let bob = log
+            .query(&RevisionFilter::default().author("bob").changes_only())
+            .unwrap();
+        assert_eq!(bob.iter().map(|meta| meta.rev_id).collect::<Vec<_>>(), vec![2]);
+        let early = log.query(&RevisionFilter::default().timestamps(0..=1_000)).unwrap();
+        assert_eq!(early.len(), 1);
+        assert_eq!(early[0].author_id, "alice");
+
+        document.undo().unwrap();
+        let entries = document.history_entries_meta();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].stack, HistoryStack::Redo);
+        assert_eq!(entries[1].ops.unwrap().formats, 1);
+    }
+}
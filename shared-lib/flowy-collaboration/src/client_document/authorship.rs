@@ -0,0 +1,138 @@
+use crate::client_document::{
+    config::AuthorId,
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+};
+use lib_ot::{
+    core::{Interval, Operation},
+    rich_text::RichTextDelta,
+};
+
+/// Remembers who wrote each part of the document. The document is covered by runs of text that
+/// were written by the same author, the runs are transformed by every delta composed into it.
+///
+/// The text whose author is unknown, e.g. the content before the authorship observed the document,
+/// belongs to the empty author.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Authorship {
+    // The utf16 length of the run and its author.
+    runs: Vec<(usize, AuthorId)>,
+}
+
+impl Authorship {
+    pub fn new(len: usize) -> Self {
+        let mut authorship = Self::default();
+        authorship.push(len, "");
+        authorship
+    }
+
+    /// Transforms the runs by `delta`, the inserted text is attributed to `author_id`.
+    pub fn apply(&mut self, delta: &RichTextDelta, author_id: &str) {
+        let mut runs = std::mem::take(&mut self.runs).into_iter();
+        let mut current: Option<(usize, AuthorId)> = None;
+        let mut next_run = |n: usize, current: &mut Option<(usize, AuthorId)>| -> Option<(usize, AuthorId)> {
+            let (len, author_id) = match current.take() {
+                None => runs.next()?,
+                Some(run) => run,
+            };
+            if len > n {
+                *current = Some((len - n, author_id.clone()));
+                Some((n, author_id))
+            } else {
+                Some((len, author_id))
+            }
+        };
+
+        for op in &delta.ops {
+            match op {
+                Operation::Retain(retain) => {
+                    let mut n = retain.n;
+                    while n > 0 {
+                        match next_run(n, &mut current) {
+                            None => break,
+                            Some((len, author_id)) => {
+                                self.push(len, &author_id);
+                                n -= len;
+                            }
+                        }
+                    }
+                }
+                Operation::Delete(n) => {
+                    let mut n = *n;
+                    while n > 0 {
+                        match next_run(n, &mut current) {
+                            None => break,
+                            Some((len, _)) => n -= len,
+                        }
+                    }
+                }
+                Operation::Insert(insert) => {
+                    self.push(insert.utf16_size(), author_id);
+                }
+            }
+        }
+
+        while let Some((len, author_id)) = next_run(usize::MAX, &mut current) {
+            self.push(len, &author_id);
+        }
+    }
+
+    pub fn spans(&self) -> Vec<(Interval, AuthorId)> {
+        let mut start = 0;
+        self.runs
+            .iter()
+            .map(|(len, author_id)| {
+                let interval = Interval::new(start, start + len);
+                start += len;
+                (interval, author_id.clone())
+            })
+            .collect()
+    }
+
+    fn push(&mut self, len: usize, author_id: &str) {
+        if len == 0 {
+            return;
+        }
+        match self.runs.last_mut() {
+            Some((last_len, last_author_id)) if last_author_id.as_str() == author_id => *last_len += len,
+            _ => self.runs.push((len, author_id.to_owned())),
+        }
+    }
+}
+
+impl DocumentObserver for Authorship {
+    fn did_reset(&mut self, document: &ObservedDocument, _replaced: Option<&RichTextDelta>) {
+        *self = Authorship::new(document.delta.utf16_target_len);
+    }
+
+    fn did_change(&mut self, _document: &ObservedDocument, event: &DocumentEvent) {
+        self.apply(&event.delta, &event.author_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::authorship::Authorship;
+    use lib_ot::{core::Interval, rich_text::RichTextDeltaBuilder};
+
+    #[test]
+    fn authorship_follow_delta() {
+        let mut authorship = Authorship::new(1);
+        authorship.apply(&RichTextDeltaBuilder::new().insert("abc").retain(1).build(), "a");
+        authorship.apply(
+            &RichTextDeltaBuilder::new().retain(1).insert("12").retain(3).build(),
+            "b",
+        );
+        authorship.apply(&RichTextDeltaBuilder::new().retain(2).delete(2).retain(2).build(), "b");
+
+        assert_eq!(
+            authorship.spans(),
+            vec![
+                (Interval::new(0, 1), "a".to_owned()),
+                (Interval::new(1, 2), "b".to_owned()),
+                (Interval::new(2, 3), "a".to_owned()),
+                (Interval::new(3, 4), "".to_owned()),
+            ]
+        );
+    }
+}
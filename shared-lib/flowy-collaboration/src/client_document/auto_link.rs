@@ -0,0 +1,96 @@
+use crate::util::{is_newline, is_whitespace, line_at};
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation},
+    rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+use url::Url;
+
+/// Returns the delta that links the word before `index` of `document` if it's a url, once `text`
+/// was typed at `index` to end it, e.g. `"https://appflowy.io"` when a space is typed after it.
+/// The `www.` addresses are linked to their https url. The words that are already linked, even
+/// partly, are left as they are.
+pub fn link_before(document: &RichTextDelta, index: usize, text: &str) -> Option<RichTextDelta> {
+    if !(is_whitespace(text) || is_newline(text)) || index == 0 {
+        return None;
+    }
+    let line = line_at(document, index - 1)?;
+    let mut word = String::new();
+    let mut word_start = index;
+    let mut linked = false;
+    // The operations of the line before `index` are walked backwards up to the whitespace that
+    // starts the word.
+    let slices = document.ops_in(Interval::new(line.start, index)).collect::<Vec<_>>();
+    for slice in slices.iter().rev() {
+        let insert = match slice.op {
+            Operation::Insert(insert) => insert,
+            _ => continue,
+        };
+        let s = match slice.is_whole() {
+            true => insert.s.to_string(),
+            false => insert.s.sub_str(slice.interval).unwrap_or_default(),
+        };
+        let (s, is_start) = match s.rfind(char::is_whitespace) {
+            None => (s.as_str(), false),
+            Some(i) => (&s[i + 1..], true),
+        };
+        if !s.is_empty() {
+            word.insert_str(0, s);
+            word_start -= count_utf16_code_units(s);
+            linked |= insert.attributes.contains_key(&RichTextAttributeKey::Link);
+        }
+        if is_start {
+            break;
+        }
+    }
+
+    if linked || word.is_empty() {
+        return None;
+    }
+    let url = parse_url(&word)?;
+    let mut delta = RichTextDelta::new();
+    delta.retain(word_start, RichTextAttributes::default());
+    delta.retain(index - word_start, RichTextAttribute::Link(url.as_str()).into());
+    Some(delta)
+}
+
+fn parse_url(word: &str) -> Option<Url> {
+    let url = match word.starts_with("www.") {
+        true => Url::parse(&format!("https://{}", word)).ok()?,
+        false => Url::parse(word).ok()?,
+    };
+    match url.scheme() {
+        "http" | "https" if url.host().is_some() => Some(url),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::auto_link::link_before;
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn link_the_url_before_whitespace() {
+        let document = RichTextDeltaBuilder::new().insert("see www.appflowy.io \n").build();
+        let delta = link_before(&document, 19, " ").unwrap();
+        let link = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Link("https://www.appflowy.io/"))
+            .build();
+        assert_eq!(
+            document.compose(&delta).unwrap(),
+            RichTextDeltaBuilder::new()
+                .insert("see ")
+                .insert_with_attributes("www.appflowy.io", link)
+                .insert(" \n")
+                .build()
+        );
+
+        assert!(link_before(&document, 3, " ").is_none());
+        assert!(link_before(&document, 19, "a").is_none());
+        let document = RichTextDeltaBuilder::new().insert("mailto:a@appflowy.io \n").build();
+        assert!(link_before(&document, 20, " ").is_none());
+    }
+}
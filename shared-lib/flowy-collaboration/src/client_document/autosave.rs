@@ -0,0 +1,324 @@
+use crate::{
+    client_document::{
+        store::{DocumentStoreRef, SavePoint},
+        wal::WriteAheadLog,
+        DocumentEvent, DocumentHandle,
+    },
+    errors::CollaborateError,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot,
+    },
+    task::JoinHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct AutosaveConfig {
+    /// The document is saved once it stopped changing for `delay`.
+    pub delay: Duration,
+    /// The document is saved without waiting for the `delay` once the deltas composed since it
+    /// was saved sum to `max_dirty_bytes`, so a long typing session is saved along the way.
+    pub max_dirty_bytes: usize,
+}
+
+impl std::default::Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(1000),
+            max_dirty_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Called with the id of the document after each save, with the revision it was saved at.
+pub type AutosaveCallback = Arc<dyn Fn(&str, &Result<i64, CollaborateError>) + Send + Sync>;
+
+/// Saves the document to the store in the background while it changes. The changes that aren't
+/// saved yet are saved when the autosaver stops.
+pub struct Autosaver {
+    stop: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Autosaver {
+    /// Starts saving the document, which is at `saved_rev_id` in the store. It must be called
+    /// from a tokio runtime.
+    pub fn spawn(
+        doc_id: &str,
+        handle: DocumentHandle,
+        saved_rev_id: i64,
+        store: DocumentStoreRef,
+        config: AutosaveConfig,
+        callback: AutosaveCallback,
+    ) -> Self {
+        Self::start(
+            doc_id,
+            handle,
+            SavePoint::new(saved_rev_id),
+            store,
+            config,
+            callback,
+            None,
+        )
+    }
+
+    /// Like [Autosaver::spawn], from the save point that the other saves of the document share,
+    /// e.g. the ones of a [DocumentManager](crate::client_document::manager::DocumentManager).
+    pub fn spawn_at(
+        doc_id: &str,
+        handle: DocumentHandle,
+        save_point: SavePoint,
+        store: DocumentStoreRef,
+        config: AutosaveConfig,
+        callback: AutosaveCallback,
+    ) -> Self {
+        Self::start(doc_id, handle, save_point, store, config, callback, None)
+    }
+
+    /// Like [Autosaver::spawn], and appends the revisions to `wal` as soon as they are made, so
+    /// the ones that weren't saved when the app crashed can be recovered with
+    /// [crate::client_document::wal::load_document_with_wal]. The log is truncated after each save.
+    pub fn spawn_with_wal(
+        doc_id: &str,
+        handle: DocumentHandle,
+        saved_rev_id: i64,
+        store: DocumentStoreRef,
+        config: AutosaveConfig,
+        callback: AutosaveCallback,
+        wal: WriteAheadLog,
+    ) -> Self {
+        Self::start(
+            doc_id,
+            handle,
+            SavePoint::new(saved_rev_id),
+            store,
+            config,
+            callback,
+            Some(wal),
+        )
+    }
+
+    fn start(
+        doc_id: &str,
+        handle: DocumentHandle,
+        save_point: SavePoint,
+        store: DocumentStoreRef,
+        config: AutosaveConfig,
+        callback: AutosaveCallback,
+        wal: Option<WriteAheadLog>,
+    ) -> Self {
+        let (stop, stop_rx) = oneshot::channel();
+        // It subscribes before it returns, so the changes made right after are saved.
+        let receiver = handle.subscribe();
+        let driver = AutosaveDriver {
+            doc_id: doc_id.to_owned(),
+            handle,
+            save_point,
+            store,
+            config,
+            callback,
+            wal,
+        };
+        let task = tokio::spawn(driver.run(receiver, stop_rx));
+        Self {
+            stop: Some(stop),
+            task: Some(task),
+        }
+    }
+
+    /// Stops saving the document, and returns after the last changes were saved.
+    pub async fn stop(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl std::ops::Drop for Autosaver {
+    fn drop(&mut self) {
+        // The task still saves the last changes.
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+struct AutosaveDriver {
+    doc_id: String,
+    handle: DocumentHandle,
+    save_point: SavePoint,
+    store: DocumentStoreRef,
+    config: AutosaveConfig,
+    callback: AutosaveCallback,
+    wal: Option<WriteAheadLog>,
+}
+
+impl AutosaveDriver {
+    async fn run(mut self, mut receiver: broadcast::Receiver<DocumentEvent>, mut stop: oneshot::Receiver<()>) {
+        let mut dirty_bytes = 0;
+        let mut is_dirty = false;
+        loop {
+            let delay = tokio::time::sleep(self.config.delay);
+            tokio::select! {
+                _ = &mut stop => break,
+                _ = delay, if is_dirty => {
+                    self.save().await;
+                    dirty_bytes = 0;
+                    is_dirty = false;
+                }
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        self.log().await;
+                        is_dirty = true;
+                        dirty_bytes += event.delta.to_json().len();
+                        if dirty_bytes >= self.config.max_dirty_bytes {
+                            self.save().await;
+                            dirty_bytes = 0;
+                            is_dirty = false;
+                        }
+                    }
+                    // The changes that were missed are saved along with the next ones.
+                    Err(RecvError::Lagged(_)) => {
+                        self.log().await;
+                        is_dirty = true;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+        // The changes whose events weren't received yet are saved too.
+        if is_dirty || self.handle.rev_id().await != self.save_point.rev_id().await {
+            self.save().await;
+        }
+    }
+
+    async fn save(&mut self) {
+        let result = self
+            .save_point
+            .save(self.store.as_ref(), &self.doc_id, &self.handle)
+            .await;
+        match &result {
+            Ok(rev_id) => {
+                if let Some(wal) = self.wal.as_mut() {
+                    if let Err(e) = wal.truncate(*rev_id) {
+                        tracing::error!("Truncate the log of the document {} failed: {}", self.doc_id, e);
+                    }
+                }
+            }
+            Err(e) => tracing::error!("Autosave the document {} failed: {}", self.doc_id, e),
+        }
+        (self.callback)(&self.doc_id, &result);
+    }
+
+    // Appends the revisions that the log doesn't have yet. The events only tell that the document
+    // changed, the revisions are read from the document.
+    async fn log(&mut self) {
+        if self.wal.is_none() {
+            return;
+        }
+        let saved_rev_id = self.save_point.rev_id().await;
+        let logged_rev_id = match self.wal.as_ref() {
+            None => return,
+            Some(wal) => wal.rev_id().unwrap_or(saved_rev_id).max(saved_rev_id),
+        };
+        let revisions = self
+            .handle
+            .read(|document| document.revisions_after(logged_rev_id))
+            .await;
+        // The revisions after the log were compacted away, the snapshot of a save replaces them.
+        if revisions
+            .first()
+            .map_or(false, |revision| revision.rev_id > logged_rev_id + 1)
+        {
+            self.save().await;
+            return;
+        }
+        let wal = match self.wal.as_mut() {
+            None => return,
+            Some(wal) => wal,
+        };
+        for revision in &revisions {
+            if let Err(e) = wal.append(revision) {
+                tracing::error!(
+                    "Log the revision {} of the document {} failed: {}",
+                    revision.rev_id,
+                    self.doc_id,
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        autosave::{AutosaveConfig, Autosaver},
+        store::{MemoryDocumentStore, SnapshotStore},
+        ClientDocument, DocumentHandle, NewlineDoc,
+    };
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn autosave_debounces_the_changes() {
+        let store = Arc::new(MemoryDocumentStore::default());
+        let handle = DocumentHandle::new(ClientDocument::new::<NewlineDoc>());
+        let (saved, mut saved_rx) = mpsc::unbounded_channel();
+        let config = AutosaveConfig {
+            delay: Duration::from_millis(50),
+            max_dirty_bytes: 1024,
+        };
+        let autosaver = Autosaver::spawn(
+            "a",
+            handle.clone(),
+            0,
+            store.clone(),
+            config,
+            Arc::new(move |_, result| {
+                let _ = saved.send(result.as_ref().map(|rev_id| *rev_id).unwrap_or(-1));
+            }),
+        );
+
+        handle.insert(0, "a").await.unwrap();
+        handle.insert(1, "b").await.unwrap();
+        // The two changes are saved together.
+        assert_eq!(saved_rx.recv().await, Some(2));
+        assert_eq!(store.read_snapshot("a").unwrap().unwrap().rev_id, 2);
+
+        // The changes that are left are saved when it stops.
+        handle.insert(2, "c").await.unwrap();
+        autosaver.stop().await;
+        assert_eq!(saved_rx.recv().await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn autosave_after_dirty_bytes() {
+        let store = Arc::new(MemoryDocumentStore::default());
+        let handle = DocumentHandle::new(ClientDocument::new::<NewlineDoc>());
+        let (saved, mut saved_rx) = mpsc::unbounded_channel();
+        let config = AutosaveConfig {
+            delay: Duration::from_secs(3600),
+            max_dirty_bytes: 1,
+        };
+        let _autosaver = Autosaver::spawn(
+            "a",
+            handle.clone(),
+            0,
+            store,
+            config,
+            Arc::new(move |_, result| {
+                let _ = saved.send(result.is_ok());
+            }),
+        );
+        handle.insert(0, "a").await.unwrap();
+        assert_eq!(saved_rx.recv().await, Some(true));
+    }
+}
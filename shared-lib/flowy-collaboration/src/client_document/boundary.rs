@@ -0,0 +1,110 @@
+use crate::util::line_intervals;
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+use serde::{Deserialize, Serialize};
+
+/// Which lines a block format of an interval applies to, when the interval ends right after a
+/// newline, at the start of a line. See
+/// [ClientDocument::set_boundary_policy](crate::client_document::ClientDocument::set_boundary_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryPolicy {
+    /// The lines that the interval touches, the line it ends at the start of included. A
+    /// selection that was made by dragging over whole lines formats the line after them too.
+    ExpandToLine,
+    /// The lines that have text in the interval, and the line of an empty interval. The line that
+    /// the interval ends at the start of isn't formatted. It's the default.
+    ClipToLine,
+    /// The lines whose newline is in the interval, the line that the interval ends in isn't
+    /// formatted unless its newline is selected. An empty interval formats nothing.
+    Exact,
+}
+
+impl std::default::Default for BoundaryPolicy {
+    fn default() -> Self {
+        BoundaryPolicy::ClipToLine
+    }
+}
+
+/// Returns the intervals of the lines of `delta` that a block format of `interval` applies to,
+/// each one including the newline that ends it.
+pub fn lines_in(delta: &RichTextDelta, interval: Interval, policy: BoundaryPolicy) -> Vec<Interval> {
+    match policy {
+        BoundaryPolicy::ClipToLine => line_intervals(delta, interval),
+        BoundaryPolicy::ExpandToLine => match interval.is_empty() {
+            true => line_intervals(delta, interval),
+            false => line_intervals(delta, Interval::new(interval.start, interval.end + 1)),
+        },
+        BoundaryPolicy::Exact => line_intervals(delta, interval)
+            .into_iter()
+            .filter(|line| line.end > interval.start && line.end <= interval.end)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        boundary::{lines_in, BoundaryPolicy},
+        ClientDocument, NewlineDoc,
+    };
+    use lib_ot::{
+        core::Interval,
+        rich_text::{BlockAttribute, RichTextAttribute, RichTextDeltaBuilder},
+    };
+    use std::convert::TryFrom;
+
+    #[test]
+    fn lines_of_each_policy() {
+        let delta = RichTextDeltaBuilder::new().insert("ab\ncd\nef\n").build();
+        let lines = |start: usize, end: usize, policy: BoundaryPolicy| {
+            lines_in(&delta, Interval::new(start, end), policy)
+                .into_iter()
+                .map(|line| (line.start, line.end))
+                .collect::<Vec<_>>()
+        };
+        // The selection of the first line with its newline ends at the start of the second one.
+        assert_eq!(lines(0, 3, BoundaryPolicy::ExpandToLine), vec![(0, 3), (3, 6)]);
+        assert_eq!(lines(0, 3, BoundaryPolicy::ClipToLine), vec![(0, 3)]);
+        assert_eq!(lines(0, 3, BoundaryPolicy::Exact), vec![(0, 3)]);
+        // The selection ends before the newline of the second line.
+        assert_eq!(lines(1, 5, BoundaryPolicy::ExpandToLine), vec![(0, 3), (3, 6)]);
+        assert_eq!(lines(1, 5, BoundaryPolicy::ClipToLine), vec![(0, 3), (3, 6)]);
+        assert_eq!(lines(1, 5, BoundaryPolicy::Exact), vec![(0, 3)]);
+        // A caret.
+        assert_eq!(lines(4, 4, BoundaryPolicy::ExpandToLine), vec![(3, 6)]);
+        assert_eq!(lines(4, 4, BoundaryPolicy::ClipToLine), vec![(3, 6)]);
+        assert_eq!(lines(4, 4, BoundaryPolicy::Exact), vec![]);
+    }
+
+    #[test]
+    fn format_and_format_block_follow_the_policy() {
+        let header = r#"[{"insert":"ab"},{"insert":"\n","attributes":{"header":1}},{"insert":"cd\n"}]"#;
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "ab\ncd").unwrap();
+        document
+            .format(Interval::new(0, 3), RichTextAttribute::Header(1))
+            .unwrap();
+        assert_eq!(document.to_json(), header);
+
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "ab\ncd").unwrap();
+        document.set_boundary_policy(BoundaryPolicy::ExpandToLine);
+        document
+            .format(Interval::new(0, 3), RichTextAttribute::Header(1))
+            .unwrap();
+        assert_eq!(
+            document.to_json(),
+            r#"[{"insert":"ab"},{"insert":"\n","attributes":{"header":1}},{"insert":"cd"},{"insert":"\n","attributes":{"header":1}}]"#
+        );
+
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "ab\ncd").unwrap();
+        document.set_boundary_policy(BoundaryPolicy::Exact);
+        let block = BlockAttribute::try_from(RichTextAttribute::Header(1)).unwrap();
+        assert!(document
+            .format_block(Interval::new(0, 2), block.clone())
+            .unwrap()
+            .is_empty());
+        document.format_block(Interval::new(0, 3), block).unwrap();
+        assert_eq!(document.to_json(), header);
+    }
+}
@@ -0,0 +1,143 @@
+//! The changes that rewrite a whole document at once, e.g. for the maintenance jobs of the server
+//! that migrate the attributes or the embeds of every document. Each of them is a single delta, so
+//! the migration of a document is one revision, which can be audited and undone like the others.
+//! The delta is empty if the document has nothing to migrate.
+use lib_ot::{
+    core::{trim, Embed, Operation},
+    rich_text::{RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+
+/// Returns the delta that moves the values of the attribute `from` to the attribute `to`, e.g. to
+/// rename a deprecated attribute. The text that has both attributes keeps the value of `from`.
+pub fn rename_attribute(
+    document: &RichTextDelta,
+    from: &RichTextAttributeKey,
+    to: &RichTextAttributeKey,
+) -> RichTextDelta {
+    if from == to {
+        return RichTextDelta::new();
+    }
+    map_attributes(document, |attributes| {
+        let mut changed = RichTextAttributes::new();
+        if let Some(value) = attributes.get(from).filter(|value| value.0.is_some()) {
+            changed.delete(from);
+            changed.add_kv(to.clone(), value.clone());
+        }
+        changed
+    })
+}
+
+/// Returns the delta that removes the attribute `key` from the whole document.
+pub fn strip_attribute(document: &RichTextDelta, key: &RichTextAttributeKey) -> RichTextDelta {
+    map_attributes(document, |attributes| {
+        let mut changed = RichTextAttributes::new();
+        if attributes.get(key).map_or(false, |value| value.0.is_some()) {
+            changed.delete(key);
+        }
+        changed
+    })
+}
+
+/// Returns the delta that replaces the embeds that `rewrite` returns a new embed for, e.g. to move
+/// the images to another host. The new embeds keep the attributes of the old ones.
+pub fn rewrite_embeds<F>(document: &RichTextDelta, mut rewrite: F) -> RichTextDelta
+where
+    F: FnMut(&Embed) -> Option<Embed>,
+{
+    let mut delta = RichTextDelta::new();
+    for op in &document.ops {
+        let embed = op
+            .get_embed()
+            .and_then(|embed| rewrite(embed).filter(|new_embed| new_embed != embed));
+        match embed {
+            None => delta.retain(op.len(), RichTextAttributes::default()),
+            Some(embed) => {
+                delta.delete(op.len());
+                delta.insert_embed(embed, op.get_attributes());
+            }
+        }
+    }
+    trim(&mut delta);
+    delta
+}
+
+// Retains each operation of the document with the attributes that `f` returns for it.
+fn map_attributes<F>(document: &RichTextDelta, mut f: F) -> RichTextDelta
+where
+    F: FnMut(&RichTextAttributes) -> RichTextAttributes,
+{
+    let mut delta = RichTextDelta::new();
+    for op in &document.ops {
+        if let Operation::Insert(insert) = op {
+            delta.retain(op.len(), f(&insert.attributes));
+        }
+    }
+    trim(&mut delta);
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::bulk::{rename_attribute, rewrite_embeds, strip_attribute};
+    use lib_ot::{
+        core::{Embed, OperationTransformable},
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributeKey, RichTextDelta, RichTextDeltaBuilder},
+    };
+    use serde_json::json;
+
+    fn document() -> RichTextDelta {
+        RichTextDeltaBuilder::new()
+            .insert("a")
+            .insert_with_attributes("b", RichTextAttribute::Background("red".to_owned()).into())
+            .insert_embed(Embed::new("image", json!({ "url": "http://old/1.png" })))
+            .insert_with_attributes("c", RichTextAttribute::Bold(true).into())
+            .insert("\n")
+            .build()
+    }
+
+    #[test]
+    fn attributes_are_renamed_and_stripped() {
+        let document = document();
+        let renamed = document
+            .compose(&rename_attribute(
+                &document,
+                &RichTextAttributeKey::Background,
+                &RichTextAttributeKey::Color,
+            ))
+            .unwrap();
+        assert_eq!(
+            renamed.ops[1].get_attributes(),
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::Color("red".to_owned()))
+                .build()
+        );
+
+        let delta = strip_attribute(&document, &RichTextAttributeKey::Bold);
+        assert_eq!(delta.ops.len(), 2);
+        let stripped = document.compose(&delta).unwrap();
+        assert_eq!(stripped.ops[3].get_attributes(), Default::default());
+        assert!(strip_attribute(&stripped, &RichTextAttributeKey::Bold).is_empty());
+    }
+
+    #[test]
+    fn embeds_are_rewritten() {
+        let document = document();
+        let rewrite = |embed: &Embed| {
+            let url = embed
+                .data
+                .to_value()
+                .get("url")?
+                .as_str()?
+                .replace("http://old", "https://new");
+            Some(Embed::new(&embed.kind, json!({ "url": url })))
+        };
+        let delta = rewrite_embeds(&document, rewrite);
+        let document = document.compose(&delta).unwrap();
+        assert_eq!(
+            document.ops[2].get_embed().unwrap().data.to_value(),
+            json!({ "url": "https://new/1.png" })
+        );
+        // The embeds that are rewritten already are left alone.
+        assert!(rewrite_embeds(&document, rewrite).is_empty());
+    }
+}
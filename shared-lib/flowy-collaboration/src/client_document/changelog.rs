@@ -0,0 +1,241 @@
+//! The changelog of a document: its [RevisionLog] as newline-delimited json, a first line with the
+//! snapshot that the log starts from and a line per revision after it. It doesn't depend on the
+//! protobuf of the revisions, so it can be kept and read by other tools, e.g. for the backups and
+//! the audits, or to move a document to another server.
+use crate::{
+    client_document::{
+        format::PayloadKind,
+        revision_log::{DocumentSnapshot, RevisionLog},
+        ClientDocument, DocumentConfig,
+    },
+    entities::revision::{md5, Revision},
+    errors::{internal_error, CollaborateError},
+    util::verify_content_md5,
+};
+use lib_ot::{core::OperationTransformable, rich_text::RichTextDelta};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ChangelogLine {
+    Snapshot {
+        version: u32,
+        doc_id: String,
+        rev_id: i64,
+        delta: RichTextDelta,
+        content_md5: String,
+    },
+    Revision(RevisionLine),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RevisionLine {
+    rev_id: i64,
+    base_rev_id: i64,
+    user_id: String,
+    author_id: String,
+    device_id: String,
+    // In milliseconds since the epoch.
+    timestamp: i64,
+    delta: RichTextDelta,
+    // The md5 of the canonical json of the delta, which doesn't depend on the order the attributes
+    // are encoded in.
+    checksum: String,
+    content_md5: String,
+}
+
+/// Writes the revision log of the document `doc_id` as a changelog, from its oldest snapshot on.
+pub fn export_changelog<W: Write>(doc_id: &str, log: &RevisionLog, mut writer: W) -> Result<(), CollaborateError> {
+    // The log always starts with a snapshot.
+    let snapshot = &log.snapshots()[0];
+    write_line(
+        &mut writer,
+        &ChangelogLine::Snapshot {
+            version: PayloadKind::Changelog.current_version(),
+            doc_id: doc_id.to_owned(),
+            rev_id: snapshot.rev_id,
+            delta: snapshot.delta()?,
+            content_md5: snapshot.content_md5.clone(),
+        },
+    )?;
+    for revision in log
+        .revisions()
+        .iter()
+        .filter(|revision| revision.rev_id > snapshot.rev_id)
+    {
+        let delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+        let line = RevisionLine {
+            rev_id: revision.rev_id,
+            base_rev_id: revision.base_rev_id,
+            user_id: revision.user_id.clone(),
+            author_id: revision.author_id.clone(),
+            device_id: revision.device_id.clone(),
+            timestamp: revision.timestamp,
+            checksum: md5(delta.to_canonical_json()),
+            delta,
+            content_md5: revision.content_md5.clone(),
+        };
+        write_line(&mut writer, &ChangelogLine::Revision(line))?;
+    }
+    writer.flush().map_err(internal_error)
+}
+
+fn write_line<W: Write>(writer: &mut W, line: &ChangelogLine) -> Result<(), CollaborateError> {
+    serde_json::to_writer(&mut *writer, line).map_err(internal_error)?;
+    writer.write_all(b"\n").map_err(internal_error)
+}
+
+/// A changelog that was read by [import_changelog]. The revisions are checked against their
+/// checksums and the content of the document after each of them.
+#[derive(Debug, Clone)]
+pub struct Changelog {
+    pub doc_id: String,
+    pub snapshot: DocumentSnapshot,
+    pub revisions: Vec<Revision>,
+    // The content after the last revision.
+    delta: RichTextDelta,
+}
+
+impl Changelog {
+    /// The content of the document after the last revision.
+    pub fn delta(&self) -> &RichTextDelta {
+        &self.delta
+    }
+
+    pub fn rev_id(&self) -> i64 {
+        self.revisions
+            .last()
+            .map(|revision| revision.rev_id)
+            .unwrap_or(self.snapshot.rev_id)
+    }
+
+    /// Rebuilds the document at the last revision, with the snapshot and the revisions of the
+    /// changelog in its revision log.
+    pub fn into_document(self) -> Result<ClientDocument, CollaborateError> {
+        let mut document = ClientDocument::restore(&DocumentSnapshot::new(self.rev_id(), &self.delta), &[])?;
+        let mut log = RevisionLog::new(self.snapshot.rev_id, &self.snapshot.delta()?);
+        for revision in self.revisions {
+            log.push(revision);
+        }
+        *document.revision_log_mut() = log;
+        document.set_config(DocumentConfig {
+            doc_id: self.doc_id,
+            ..document.config().clone()
+        });
+        Ok(document)
+    }
+
+    fn push(&mut self, line: RevisionLine) -> Result<(), CollaborateError> {
+        if line.rev_id <= self.rev_id() {
+            return Err(CollaborateError::internal().context(format!(
+                "The revision {} doesn't come after the revision {}",
+                line.rev_id,
+                self.rev_id()
+            )));
+        }
+        if md5(line.delta.to_canonical_json()) != line.checksum {
+            return Err(CollaborateError::internal().context(format!("The revision {} is corrupted", line.rev_id)));
+        }
+        let delta_data = line.delta.to_bytes();
+        let mut revision = Revision::new(
+            &self.doc_id,
+            line.base_rev_id,
+            line.rev_id,
+            delta_data.clone(),
+            &line.user_id,
+            md5(&delta_data),
+        )
+        .with_content_md5(line.content_md5);
+        revision.author_id = line.author_id;
+        revision.device_id = line.device_id;
+        revision.timestamp = line.timestamp;
+
+        self.delta = self.delta.compose(&line.delta)?;
+        verify_content_md5(&revision, &self.delta)?;
+        self.revisions.push(revision);
+        Ok(())
+    }
+}
+
+/// Reads the changelog that was written by [export_changelog], by this version or an older one.
+/// The empty lines are skipped.
+pub fn import_changelog<R: BufRead>(reader: R) -> Result<Changelog, CollaborateError> {
+    let mut changelog: Option<Changelog> = None;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(internal_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: ChangelogLine = serde_json::from_str(&line)
+            .map_err(|e| CollaborateError::internal().context(format!("The line {} is invalid: {}", index + 1, e)))?;
+        changelog = match (line, changelog.take()) {
+            (
+                ChangelogLine::Snapshot {
+                    version,
+                    doc_id,
+                    rev_id,
+                    delta,
+                    content_md5,
+                },
+                None,
+            ) => {
+                PayloadKind::Changelog.check_version(version)?;
+                let snapshot = DocumentSnapshot::new(rev_id, &delta);
+                snapshot.verify_content(&content_md5)?;
+                Some(Changelog {
+                    doc_id,
+                    snapshot,
+                    revisions: vec![],
+                    delta,
+                })
+            }
+            (ChangelogLine::Revision(line), Some(mut changelog)) => {
+                changelog.push(line)?;
+                Some(changelog)
+            }
+            (_, _) => {
+                return Err(CollaborateError::internal().context(format!(
+                    "The line {} is out of place, the changelog starts with its snapshot",
+                    index + 1
+                )))
+            }
+        };
+    }
+    changelog.ok_or_else(|| CollaborateError::internal().context("The changelog is empty"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        changelog::{export_changelog, import_changelog},
+        ClientDocument, NewlineDoc,
+    };
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+
+    #[test]
+    fn changelog_rebuilds_the_document() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "hello").unwrap();
+        document
+            .format(Interval::new(0, 5), RichTextAttribute::Bold(true))
+            .unwrap();
+        document.delete(Interval::new(0, 1)).unwrap();
+
+        let mut data = vec![];
+        export_changelog("doc", document.revision_log(), &mut data).unwrap();
+        assert_eq!(data.iter().filter(|c| **c == b'\n').count(), 4);
+        let changelog = import_changelog(&data[..]).unwrap();
+        assert_eq!(changelog.rev_id(), document.rev_id());
+        let imported = changelog.into_document().unwrap();
+        assert_eq!(imported.to_json(), document.to_json());
+        assert_eq!(
+            imported.revision_log().delta_at(1).unwrap().apply("").unwrap(),
+            "hello\n"
+        );
+
+        // A revision that was changed after it was exported.
+        let text = String::from_utf8(data).unwrap().replace("hello", "hallo");
+        assert!(import_changelog(text.as_bytes()).is_err());
+    }
+}
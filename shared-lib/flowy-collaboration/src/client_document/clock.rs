@@ -0,0 +1,215 @@
+use lib_infra::uuid_string;
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Where the document takes the time from, e.g. for the timestamps of the revisions and for
+/// grouping the edits that are close in time into one undo entry. The tests and the simulations
+/// use a [ManualClock] so that they run the same every time.
+pub trait Clock: Send + Sync {
+    /// The current time in milliseconds since the epoch.
+    fn now_millis(&self) -> i64;
+}
+
+/// Where the document takes the ids it makes up from, e.g. the ids of the suggestions.
+pub trait IdGenerator: Send + Sync {
+    fn next_id(&self) -> String;
+}
+
+/// The time of the system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+}
+
+/// Random uuids.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidGenerator;
+
+impl IdGenerator for UuidGenerator {
+    fn next_id(&self) -> String {
+        uuid_string()
+    }
+}
+
+/// A clock that only moves when it's told to, and by `step` after each reading.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicI64,
+    step: i64,
+}
+
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        Self::with_step(now, 0)
+    }
+
+    pub fn with_step(now: i64, step: i64) -> Self {
+        Self {
+            now: AtomicI64::new(now),
+            step,
+        }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, millis: i64) {
+        self.now.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> i64 {
+        self.now.fetch_add(self.step, Ordering::SeqCst)
+    }
+}
+
+/// The ids `<prefix>-1`, `<prefix>-2` and so on.
+#[derive(Debug, Default)]
+pub struct SequentialIdGenerator {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialIdGenerator {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+            next: AtomicU64::new(1),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn next_id(&self) -> String {
+        format!("{}-{}", self.prefix, self.next.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+/// The [Clock] of a [DocumentConfig](crate::client_document::DocumentConfig), the
+/// [SystemClock] by default. Two of them are equal if they are the same clock.
+#[derive(Clone)]
+pub struct ClockRef(pub Arc<dyn Clock>);
+
+impl std::default::Default for ClockRef {
+    fn default() -> Self {
+        Self(Arc::new(SystemClock))
+    }
+}
+
+impl Deref for ClockRef {
+    type Target = dyn Clock;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl PartialEq for ClockRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ClockRef {}
+
+impl std::fmt::Debug for ClockRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ClockRef")
+    }
+}
+
+/// The [IdGenerator] of a [DocumentConfig](crate::client_document::DocumentConfig), the
+/// [UuidGenerator] by default. Two of them are equal if they are the same generator.
+#[derive(Clone)]
+pub struct IdGeneratorRef(pub Arc<dyn IdGenerator>);
+
+impl std::default::Default for IdGeneratorRef {
+    fn default() -> Self {
+        Self(Arc::new(UuidGenerator))
+    }
+}
+
+impl Deref for IdGeneratorRef {
+    type Target = dyn IdGenerator;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl PartialEq for IdGeneratorRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for IdGeneratorRef {}
+
+impl std::fmt::Debug for IdGeneratorRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("IdGeneratorRef")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        clock::{ManualClock, SequentialIdGenerator},
+        ClientDocument, DocumentConfigBuilder, EditingConfig, NewlineDoc,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn documents_with_the_same_clock_and_ids_make_the_same_revisions() {
+        let run = || {
+            let clock = Arc::new(ManualClock::new(1_000));
+            let mut document = ClientDocument::new::<NewlineDoc>();
+            document.set_config(
+                DocumentConfigBuilder::new("doc")
+                    .author("alice", "laptop")
+                    .editing(EditingConfig {
+                        suggestion_mode: true,
+                        ..EditingConfig::default()
+                    })
+                    .clock(clock.clone())
+                    .id_generator(Arc::new(SequentialIdGenerator::new("suggestion")))
+                    .build(),
+            );
+            document.insert(0, "a").unwrap();
+            clock.advance(10_000);
+            document.insert(1, "b").unwrap();
+            (document.to_json(), document.revision_log().revisions().to_vec())
+        };
+
+        let (json, revisions) = run();
+        assert_eq!(run(), (json.clone(), revisions.clone()));
+        assert!(json.contains("suggestion-1"));
+        assert!(json.contains("suggestion-2"));
+        let timestamps = revisions.iter().map(|revision| revision.timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![1_000, 11_000]);
+    }
+
+    #[test]
+    fn edit_after_the_clock_went_back_starts_an_undo_entry() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(DocumentConfigBuilder::new("doc").clock(clock.clone()).build());
+        document.insert(0, "a").unwrap();
+        clock.set(5_000);
+        document.insert(1, "b").unwrap();
+
+        document.undo().unwrap();
+        assert_eq!(document.to_plain_string(), "a\n");
+    }
+}
@@ -0,0 +1,119 @@
+use lib_ot::rich_text::{RichTextAttributes, RichTextDelta};
+use serde::{Deserialize, Serialize};
+
+/// A change of the document, made by [ClientDocument::handle](crate::client_document::ClientDocument::handle).
+/// The commands are serializable, so the bindings, the replay log, the intents of the
+/// authoritative sync and the tests all change the documents through the same calls. The
+/// intervals are in utf16 code units, like the ones of the methods of the document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum EditCommand {
+    Insert {
+        index: usize,
+        text: String,
+    },
+    /// Types the text, which waits in the typing buffer, see
+    /// [ClientDocument::type_text](crate::client_document::ClientDocument::type_text).
+    TypeText {
+        index: usize,
+        text: String,
+    },
+    Delete {
+        start: usize,
+        end: usize,
+    },
+    Replace {
+        start: usize,
+        end: usize,
+        text: String,
+    },
+    /// Formats the text with all the attributes, the ones with a null value are removed.
+    Format {
+        start: usize,
+        end: usize,
+        attributes: RichTextAttributes,
+    },
+    /// Toggles each of the attributes on the text, like the buttons of a toolbar.
+    ToggleFormat {
+        start: usize,
+        end: usize,
+        attributes: RichTextAttributes,
+    },
+    ToggleCodeBlock {
+        start: usize,
+        end: usize,
+        language: Option<String>,
+    },
+    /// Toggles the checkbox of the `line`th line.
+    ToggleCheckbox {
+        line: usize,
+    },
+    /// Composes the delta made by the local user.
+    ComposeDelta {
+        delta: RichTextDelta,
+    },
+    SetSelection {
+        start: usize,
+        end: usize,
+    },
+    Undo,
+    Redo,
+    SetMetadata {
+        key: String,
+        value: String,
+    },
+    RemoveMetadata {
+        key: String,
+    },
+}
+
+/// What a document did with an [EditCommand].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditResponse {
+    /// The delta that was composed into the document, which the editor can apply in place of
+    /// rendering the whole document again. It's empty if the command didn't change the content,
+    /// e.g. the text that waits in the typing buffer.
+    pub delta: RichTextDelta,
+    /// The revision of the document after the command.
+    pub rev_id: i64,
+    /// The start and the end of the selection to restore, after an undo or a redo.
+    pub selection: Option<(usize, usize)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        command::{EditCommand, EditResponse},
+        ClientDocument, NewlineDoc,
+    };
+
+    #[test]
+    fn commands_from_json_edit_the_document() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        let commands = r#"[
+            {"command":"insert","index":0,"text":"hello world"},
+            {"command":"toggle_format","start":0,"end":5,"attributes":{"bold":true}},
+            {"command":"replace","start":6,"end":11,"text":"there"},
+            {"command":"set_metadata","key":"title","value":"Greeting"},
+            {"command":"undo"}
+        ]"#;
+        let commands: Vec<EditCommand> = serde_json::from_str(commands).unwrap();
+        let responses = commands
+            .into_iter()
+            .map(|command| document.handle(command).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            responses[1].delta.to_json(),
+            r#"[{"retain":5,"attributes":{"bold":true}}]"#
+        );
+        assert_eq!(responses[3].rev_id, responses[2].rev_id + 1);
+        assert!(responses[4].selection.is_some());
+        assert_eq!(document.to_plain_string(), "hello world\n");
+        assert_eq!(document.metadata().get("title"), Some("Greeting"));
+
+        let json = serde_json::to_string(&responses[2]).unwrap();
+        assert_eq!(serde_json::from_str::<EditResponse>(&json).unwrap(), responses[2]);
+        assert!(document.handle(EditCommand::Delete { start: 5, end: 50 }).is_err());
+    }
+}
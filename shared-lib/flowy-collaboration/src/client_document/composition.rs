@@ -0,0 +1,33 @@
+use lib_ot::{
+    core::{count_utf16_code_units, Interval},
+    rich_text::RichTextDelta,
+};
+
+/// The text that an input method, e.g. the CJK ones, is composing at `index`. The text is
+/// provisional until it's committed: it isn't in the document, the history or the revisions, the
+/// editor shows it at `index`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Composition {
+    pub index: usize,
+    pub text: String,
+}
+
+impl Composition {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            text: String::new(),
+        }
+    }
+
+    /// The interval that the text takes in the editor, where the document has nothing.
+    pub fn interval(&self) -> Interval {
+        Interval::new(self.index, self.index + count_utf16_code_units(&self.text))
+    }
+
+    /// Moves the composition over a change of the document, e.g. the edit of another user that
+    /// arrived while the text was composed. The text inserted at `index` goes before it.
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        self.index = delta.transform_position(self.index, false);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::client_document::{
+    boundary::BoundaryPolicy,
+    clock::{Clock, ClockRef, IdGenerator, IdGeneratorRef},
+    history::{HistoryCompression, MAX_ENTRY_SIZE, MAX_UNDOES},
+    normalize::NormalizeConfig,
+    revision_log::MAX_LOG_REVISIONS,
+    snapshot_policy::SnapshotPolicy,
+    sync::SyncMode,
+    typing::TypingConfig,
+};
+use lib_ot::rich_text::ComposeConfig;
+use std::sync::Arc;
+
+pub type AuthorId = String;
+
+/// Describes the document and who is editing it. The revisions made by the local user are
+/// attributed to `author_id` on `device_id`. It's built with a [DocumentConfigBuilder].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentConfig {
+    pub doc_id: String,
+    pub author_id: AuthorId,
+    pub device_id: String,
+    /// The length that the changes can't make the document go over, in utf16 code units. The
+    /// changes that shorten a document that is already over it are accepted.
+    pub max_len: Option<usize>,
+    pub history: HistoryConfig,
+    pub editing: EditingConfig,
+    /// See [crate::client_document::sync::ClientSync].
+    pub sync_mode: SyncMode,
+    /// See [crate::client_document::clock::Clock].
+    pub clock: ClockRef,
+    /// See [crate::client_document::clock::IdGenerator].
+    pub ids: IdGeneratorRef,
+    /// See [SnapshotPolicy].
+    pub snapshot_policy: SnapshotPolicy,
+    /// The number of revisions that the revision log of the document keeps in memory, `None` keeps
+    /// them all. The older ones are compacted into a snapshot, see
+    /// [crate::client_document::revision_log::RevisionLog::compact].
+    pub max_log_revisions: Option<usize>,
+    /// How the attributes of the changes are composed and their concurrent values resolved, the
+    /// same for all the participants of the document.
+    pub compose: ComposeConfig,
+}
+
+impl std::default::Default for DocumentConfig {
+    fn default() -> Self {
+        Self {
+            doc_id: String::default(),
+            author_id: AuthorId::default(),
+            device_id: String::default(),
+            max_len: None,
+            history: HistoryConfig::default(),
+            editing: EditingConfig::default(),
+            sync_mode: SyncMode::default(),
+            clock: ClockRef::default(),
+            ids: IdGeneratorRef::default(),
+            snapshot_policy: SnapshotPolicy::default(),
+            max_log_revisions: Some(MAX_LOG_REVISIONS),
+            compose: ComposeConfig::default(),
+        }
+    }
+}
+
+/// How the document keeps the changes it undoes. It can be changed while the document is open,
+/// see [crate::client_document::ClientDocument::set_history_config].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryConfig {
+    /// The number of changes that can be undone.
+    pub capacity: usize,
+    /// See [crate::client_document::history::History::set_max_entry_size].
+    pub max_entry_size: usize,
+    /// See [crate::client_document::history::History::set_compression].
+    pub compression: Option<HistoryCompression>,
+    /// See [crate::client_document::history::History::set_check_invariants].
+    pub check_invariants: bool,
+    /// See [crate::client_document::history::History::set_branching].
+    pub branching: bool,
+}
+
+impl std::default::Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            capacity: MAX_UNDOES,
+            max_entry_size: MAX_ENTRY_SIZE,
+            compression: None,
+            check_invariants: false,
+            branching: false,
+        }
+    }
+}
+
+/// How the changes of the local user are made. It can be changed while the document is open, see
+/// [crate::client_document::ClientDocument::set_editing_config].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditingConfig {
+    /// See [crate::client_document::ClientDocument::set_suggestion_mode].
+    pub suggestion_mode: bool,
+    /// See [crate::client_document::ClientDocument::set_auto_link].
+    pub auto_link: bool,
+    /// See [crate::client_document::ClientDocument::set_typing_buffer].
+    pub typing: Option<TypingConfig>,
+    /// See [crate::client_document::ClientDocument::set_normalize_config].
+    pub normalize: NormalizeConfig,
+    /// See [crate::client_document::ClientDocument::set_boundary_policy].
+    pub boundary_policy: BoundaryPolicy,
+}
+
+impl std::default::Default for EditingConfig {
+    fn default() -> Self {
+        Self {
+            suggestion_mode: false,
+            auto_link: true,
+            typing: None,
+            normalize: NormalizeConfig::default(),
+            boundary_policy: BoundaryPolicy::default(),
+        }
+    }
+}
+
+/// Builds a [DocumentConfig], the settings that aren't given keep their default.
+#[derive(Debug, Default)]
+pub struct DocumentConfigBuilder {
+    config: DocumentConfig,
+}
+
+impl DocumentConfigBuilder {
+    pub fn new(doc_id: &str) -> Self {
+        Self {
+            config: DocumentConfig {
+                doc_id: doc_id.to_owned(),
+                ..DocumentConfig::default()
+            },
+        }
+    }
+
+    pub fn author(mut self, author_id: &str, device_id: &str) -> Self {
+        self.config.author_id = author_id.to_owned();
+        self.config.device_id = device_id.to_owned();
+        self
+    }
+
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.config.max_len = Some(max_len);
+        self
+    }
+
+    pub fn history(mut self, history: HistoryConfig) -> Self {
+        self.config.history = history;
+        self
+    }
+
+    pub fn editing(mut self, editing: EditingConfig) -> Self {
+        self.config.editing = editing;
+        self
+    }
+
+    pub fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.config.sync_mode = sync_mode;
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = ClockRef(clock);
+        self
+    }
+
+    pub fn id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.config.ids = IdGeneratorRef(ids);
+        self
+    }
+
+    pub fn snapshot_policy(mut self, snapshot_policy: SnapshotPolicy) -> Self {
+        self.config.snapshot_policy = snapshot_policy;
+        self
+    }
+
+    pub fn max_log_revisions(mut self, max_log_revisions: Option<usize>) -> Self {
+        self.config.max_log_revisions = max_log_revisions;
+        self
+    }
+
+    pub fn compose(mut self, compose: ComposeConfig) -> Self {
+        self.config.compose = compose;
+        self
+    }
+
+    pub fn build(self) -> DocumentConfig {
+        self.config
+    }
+}
+
+/// The document and the revision that a document was forked from, see
+/// [crate::client_document::ClientDocument::fork]. The fork starts from the content of `doc_id`
+/// at `rev_id`, which is the base to merge the changes of the fork back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkPoint {
+    pub doc_id: String,
+    pub rev_id: i64,
+}
@@ -0,0 +1,423 @@
+use crate::errors::{internal_error, CollaborateError, ContentRejectedError};
+use fancy_regex::Regex;
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation},
+    rich_text::{RichTextAttribute, RichTextDelta},
+};
+use std::sync::Arc;
+
+/// What is done with the text that breaks a [ContentPolicy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyAction {
+    /// The text is kept, the violation is only reported.
+    Flag,
+    /// The text is replaced with the placeholder of [ContentPolicies], marked with the
+    /// [RichTextAttribute::Redacted] attribute.
+    Redact,
+    /// The change is rejected with
+    /// [ErrorCode::ContentRejected](crate::errors::ErrorCode::ContentRejected).
+    Reject,
+}
+
+/// Checks the text that the changes insert into the document, e.g. to keep the secrets or the
+/// banned words out of a shared workspace, see
+/// [ClientDocument::set_content_policies](crate::client_document::ClientDocument::set_content_policies).
+pub trait ContentPolicy: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Returns the utf16 intervals of the text of `text` that breaks the policy, with what is done
+    /// with each of them.
+    fn check(&self, text: &str) -> Vec<(Interval, PolicyAction)>;
+}
+
+/// The text that matches a regex breaks the policy.
+pub struct RegexPolicy {
+    name: String,
+    regex: Regex,
+    action: PolicyAction,
+}
+
+impl RegexPolicy {
+    pub fn new(name: &str, pattern: &str, action: PolicyAction) -> Result<Self, CollaborateError> {
+        Ok(Self {
+            name: name.to_owned(),
+            regex: Regex::new(pattern).map_err(internal_error)?,
+            action,
+        })
+    }
+}
+
+impl ContentPolicy for RegexPolicy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, text: &str) -> Vec<(Interval, PolicyAction)> {
+        let mut matches = vec![];
+        let mut pos = 0;
+        while pos < text.len() {
+            let captures = match self.regex.captures_from_pos(text, pos) {
+                Ok(Some(captures)) => captures,
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("Run the content policy {} failed: {}", self.name, e);
+                    break;
+                }
+            };
+            // The whole match always exists.
+            let whole = captures.get(0).unwrap();
+            if whole.end() == whole.start() {
+                pos = whole.end() + text[whole.end()..].chars().next().map_or(1, |c| c.len_utf8());
+                continue;
+            }
+            pos = whole.end();
+            let start = count_utf16_code_units(&text[..whole.start()]);
+            let end = start + count_utf16_code_units(whole.as_str());
+            matches.push((Interval::new(start, end), self.action));
+        }
+        matches
+    }
+}
+
+/// The policy is a function, e.g. one that calls the moderation service of the organization.
+pub struct CallbackPolicy<F> {
+    name: String,
+    callback: F,
+}
+
+impl<F> CallbackPolicy<F>
+where
+    F: Fn(&str) -> Vec<(Interval, PolicyAction)> + Send + Sync,
+{
+    pub fn new(name: &str, callback: F) -> Self {
+        Self {
+            name: name.to_owned(),
+            callback,
+        }
+    }
+}
+
+impl<F> ContentPolicy for CallbackPolicy<F>
+where
+    F: Fn(&str) -> Vec<(Interval, PolicyAction)> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, text: &str) -> Vec<(Interval, PolicyAction)> {
+        (self.callback)(text)
+    }
+}
+
+/// Text of a change that broke a policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    pub policy: String,
+    pub action: PolicyAction,
+    /// The interval of the text in the document after the change. The interval of a redacted text
+    /// is the one of its placeholder.
+    pub interval: Interval,
+    /// The text as it was in the change.
+    pub text: String,
+}
+
+/// The content policies of a document. Each run of the inserted text with the same attributes is
+/// checked on its own, the embeds aren't checked.
+#[derive(Clone)]
+pub struct ContentPolicies {
+    policies: Vec<Arc<dyn ContentPolicy>>,
+    placeholder: String,
+}
+
+impl std::default::Default for ContentPolicies {
+    fn default() -> Self {
+        Self {
+            policies: vec![],
+            placeholder: "[redacted]".to_owned(),
+        }
+    }
+}
+
+impl ContentPolicies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the text that replaces the redacted text, "[redacted]" by default.
+    pub fn with_placeholder(mut self, placeholder: &str) -> Self {
+        self.placeholder = placeholder.to_owned();
+        self
+    }
+
+    /// Adds `policy`, in place of the policy with the same name.
+    pub fn register(&mut self, policy: Arc<dyn ContentPolicy>) {
+        self.policies.retain(|registered| registered.name() != policy.name());
+        self.policies.push(policy);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.policies.iter().map(|policy| policy.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Checks the text that `delta` inserts. Returns the delta with the redacted text replaced by
+    /// the placeholder, and the violations. Fails with the first text that a policy rejects.
+    pub fn apply(&self, delta: RichTextDelta) -> Result<(RichTextDelta, Vec<PolicyViolation>), CollaborateError> {
+        if self.policies.is_empty() {
+            return Ok((delta, vec![]));
+        }
+        let mut new_delta = RichTextDelta::new();
+        let mut violations = vec![];
+        let mut offset = 0;
+        for op in delta.ops {
+            let insert = match op {
+                Operation::Insert(insert) if insert.embed.is_none() => insert,
+                op => {
+                    if !op.is_delete() {
+                        offset += op.len();
+                    }
+                    new_delta.add(op);
+                    continue;
+                }
+            };
+            let text = insert.s.to_string();
+            let mut matches = self.matches(&text);
+            if let Some(rejected) = matches.iter().find(|m| m.action == PolicyAction::Reject) {
+                return Err(ContentRejectedError {
+                    policy: rejected.policy.clone(),
+                    text: rejected.text.clone(),
+                }
+                .into());
+            }
+
+            // The overlapping redactions are merged into the first one, the flags of the redacted
+            // text are dropped with it.
+            matches.sort_by_key(|m| m.interval.start);
+            let mut redacted: Vec<PolicyViolation> = vec![];
+            for m in matches.iter().filter(|m| m.action == PolicyAction::Redact) {
+                match redacted.last_mut() {
+                    Some(last) if m.interval.start < last.interval.end => {
+                        if m.interval.end > last.interval.end {
+                            last.interval = Interval::new(last.interval.start, m.interval.end);
+                            last.text = utf16_slice(&text, last.interval);
+                        }
+                    }
+                    _ => redacted.push(m.clone()),
+                }
+            }
+            let placeholder_len = count_utf16_code_units(&self.placeholder);
+            let new_offset = |index: usize| -> usize {
+                let before = redacted.iter().filter(|r| r.interval.end <= index);
+                offset + before.fold(index, |index, r| index + placeholder_len - r.interval.size())
+            };
+            for m in matches.iter().filter(|m| m.action == PolicyAction::Flag) {
+                let is_redacted = redacted
+                    .iter()
+                    .any(|r| r.interval.start < m.interval.end && m.interval.start < r.interval.end);
+                if !is_redacted {
+                    let start = new_offset(m.interval.start);
+                    violations.push(PolicyViolation {
+                        interval: Interval::new(start, start + m.interval.size()),
+                        ..m.clone()
+                    });
+                }
+            }
+
+            let mut start = 0;
+            for r in &redacted {
+                if start < r.interval.start {
+                    let s = insert
+                        .s
+                        .sub_str(Interval::new(start, r.interval.start))
+                        .unwrap_or_default();
+                    new_delta.insert(&s, insert.attributes.clone());
+                }
+                let mut attributes = insert.attributes.clone();
+                attributes.add(RichTextAttribute::Redacted(r.policy.clone()));
+                new_delta.insert(&self.placeholder, attributes);
+                let at = new_offset(r.interval.start);
+                violations.push(PolicyViolation {
+                    interval: Interval::new(at, at + placeholder_len),
+                    ..r.clone()
+                });
+                start = r.interval.end;
+            }
+            let len = insert.utf16_size();
+            if start < len {
+                let s = insert.s.sub_str(Interval::new(start, len)).unwrap_or_default();
+                new_delta.insert(&s, insert.attributes.clone());
+            }
+            let end = new_offset(len);
+            offset = end;
+        }
+        violations.sort_by_key(|violation| violation.interval.start);
+        Ok((new_delta, violations))
+    }
+
+    /// Checks the text that `delta` inserts without changing it, e.g. the changes of the other
+    /// participants, which the document can't change without diverging from them. The text that
+    /// the policies reject or redact is reported like the flagged text.
+    pub fn check(&self, delta: &RichTextDelta) -> Vec<PolicyViolation> {
+        if self.policies.is_empty() {
+            return vec![];
+        }
+        let mut violations = vec![];
+        let mut offset = 0;
+        for op in &delta.ops {
+            match op {
+                Operation::Insert(insert) if insert.embed.is_none() => {
+                    let mut matches = self.matches(&insert.s);
+                    matches.iter_mut().for_each(|m| {
+                        m.interval = Interval::new(offset + m.interval.start, offset + m.interval.end);
+                    });
+                    violations.extend(matches);
+                    offset += insert.utf16_size();
+                }
+                Operation::Insert(_) | Operation::Retain(_) => offset += op.len(),
+                Operation::Delete(_) => {}
+            }
+        }
+        violations.sort_by_key(|violation| violation.interval.start);
+        violations
+    }
+
+    // The matches of all the policies in `text`, the intervals are in the text.
+    fn matches(&self, text: &str) -> Vec<PolicyViolation> {
+        let len = count_utf16_code_units(text);
+        let mut matches = vec![];
+        for policy in &self.policies {
+            for (interval, action) in policy.check(text) {
+                if interval.is_empty() || interval.end > len {
+                    continue;
+                }
+                matches.push(PolicyViolation {
+                    policy: policy.name().to_owned(),
+                    action,
+                    interval,
+                    text: utf16_slice(text, interval),
+                });
+            }
+        }
+        matches
+    }
+}
+
+fn utf16_slice(text: &str, interval: Interval) -> String {
+    let mut offset = 0;
+    text.chars()
+        .filter(|c| {
+            let at = offset;
+            offset += c.len_utf16();
+            at >= interval.start && at < interval.end
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client_document::{
+            content_policy::{CallbackPolicy, ContentPolicies, PolicyAction, RegexPolicy},
+            ClientDocument, NewlineDoc, CHUNK_LEN,
+        },
+        errors::ErrorCode,
+    };
+    use lib_ot::{
+        core::{Interval, OpBuilder},
+        rich_text::RichTextDeltaBuilder,
+    };
+    use std::sync::Arc;
+
+    fn policies() -> ContentPolicies {
+        let mut policies = ContentPolicies::new().with_placeholder("***");
+        policies.register(Arc::new(
+            RegexPolicy::new("secrets", r"sk-[a-z0-9]+", PolicyAction::Redact).unwrap(),
+        ));
+        policies.register(Arc::new(
+            RegexPolicy::new("banned", r"\bforbidden\b", PolicyAction::Reject).unwrap(),
+        ));
+        policies.register(Arc::new(CallbackPolicy::new("shouting", |text: &str| {
+            match text.find("HEY") {
+                None => vec![],
+                Some(start) => vec![(Interval::new(start, start + 3), PolicyAction::Flag)],
+            }
+        })));
+        policies
+    }
+
+    #[test]
+    fn policies_reject_redact_and_flag() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_content_policies(policies());
+        let mut receiver = document.subscribe_policy_violations();
+
+        let error = document.insert(0, "a forbidden word").unwrap_err();
+        assert_eq!(error.code, ErrorCode::ContentRejected);
+        assert_eq!(document.to_plain_string(), "\n");
+
+        let delta = document.insert(0, "key sk-abc1 HEY").unwrap();
+        assert_eq!(
+            delta.to_json(),
+            r#"[{"insert":"key "},{"insert":"***","attributes":{"redacted":"secrets"}},{"insert":" HEY"}]"#
+        );
+        assert_eq!(document.to_plain_string(), "key *** HEY\n");
+        let event = receiver.try_recv().unwrap();
+        let violations = event
+            .violations
+            .iter()
+            .map(|violation| (violation.policy.as_str(), violation.interval, violation.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            violations,
+            vec![
+                ("secrets", Interval::new(4, 7), "sk-abc1"),
+                ("shouting", Interval::new(8, 11), "HEY"),
+            ]
+        );
+
+        // The changes of the others are only reported.
+        let remote = RichTextDeltaBuilder::new()
+            .retain(11)
+            .insert(" sk-xyz")
+            .retain(1)
+            .build();
+        document.compose_remote_delta(remote).unwrap();
+        assert_eq!(document.to_plain_string(), "key *** HEY sk-xyz\n");
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.violations[0].interval, Interval::new(12, 18));
+        assert_eq!(event.violations[0].action, PolicyAction::Redact);
+    }
+
+    #[test]
+    fn policies_check_the_bulk_changes() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_content_policies(policies());
+        let mut receiver = document.subscribe_policy_violations();
+
+        let error = document.bulk_insert(0, "a forbidden paste").unwrap_err();
+        assert_eq!(error.code, ErrorCode::ContentRejected);
+        let delta = document.bulk_insert(0, "key sk-abc1").unwrap();
+        assert_eq!(
+            delta.to_json(),
+            r#"[{"insert":"key "},{"insert":"***","attributes":{"redacted":"secrets"}}]"#
+        );
+        assert_eq!(receiver.try_recv().unwrap().violations[0].text, "sk-abc1");
+
+        // The chunks after a redacted one go after its placeholder.
+        let text = "a".repeat(CHUNK_LEN);
+        let ops = vec![
+            OpBuilder::retain(7).build(),
+            OpBuilder::insert(&format!(" sk-xyz {}", text)).build(),
+            OpBuilder::insert("end").build(),
+        ];
+        let mut progress = vec![];
+        document.apply_chunked(ops, |offset| progress.push(offset)).unwrap();
+        assert_eq!(progress, vec![12 + CHUNK_LEN, 15 + CHUNK_LEN]);
+        assert_eq!(document.to_plain_string(), format!("key *** *** {}end\n", text));
+        assert_eq!(receiver.try_recv().unwrap().violations[0].text, "sk-xyz");
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A snapshot of the internals of a document, for the debug panel of the editor. The sizes are
+/// estimates of the heap memory, they don't count the allocator overhead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugStats {
+    pub doc_id: String,
+    pub rev_id: i64,
+    /// The utf16 length of the document.
+    pub len: usize,
+    /// The number of the operations of the document.
+    pub op_count: usize,
+    /// The number of the runs of text with the same attributes, zero unless the document observes
+    /// its [AttributeRuns](crate::client_document::attribute_runs::AttributeRuns).
+    pub attribute_run_count: usize,
+    pub undo_depth: usize,
+    pub redo_depth: usize,
+    /// The number of the branches of the history, see
+    /// [History::set_branching](crate::client_document::history::History::set_branching).
+    pub branch_count: usize,
+    /// How long the last change took to compose into the document, in microseconds.
+    pub last_compose_micros: Option<u64>,
+    /// How long the history took to transform over the last change of another participant, in
+    /// microseconds.
+    pub last_transform_micros: Option<u64>,
+    pub document_bytes: usize,
+    pub history_bytes: usize,
+}
+
+impl DebugStats {
+    pub fn total_bytes(&self) -> usize {
+        self.document_bytes + self.history_bytes
+    }
+}
+
+pub(crate) fn micros(duration: Option<Duration>) -> Option<u64> {
+    duration.map(|duration| duration.as_micros() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{attribute_runs::AttributeRuns, ClientDocument, NewlineDoc};
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+
+    #[test]
+    fn debug_stats_follow_the_document() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        let stats = document.debug_stats();
+        assert_eq!(stats.op_count, 1);
+        assert_eq!(stats.undo_depth, 0);
+        assert_eq!(stats.last_compose_micros, None);
+        assert_eq!(stats.attribute_run_count, 0);
+
+        document.add_observer(AttributeRuns::default());
+        document.insert(0, "hello world").unwrap();
+        document
+            .format(Interval::new(0, 5), RichTextAttribute::Bold(true))
+            .unwrap();
+        let stats = document.debug_stats();
+        assert_eq!(stats.len, 12);
+        assert_eq!(stats.op_count, 2);
+        assert_eq!(stats.attribute_run_count, 2);
+        // The changes made right after one another are undone together.
+        assert_eq!(stats.undo_depth, 1);
+        assert_eq!(stats.redo_depth, 0);
+        assert!(stats.last_compose_micros.is_some());
+        assert!(stats.document_bytes > 0 && stats.history_bytes > 0);
+        assert_eq!(stats.total_bytes(), stats.document_bytes + stats.history_bytes);
+
+        let json = serde_json::to_string(&stats).unwrap();
+        assert!(json.contains(r#""attributeRunCount":2"#));
+    }
+}
@@ -0,0 +1,165 @@
+use lib_ot::{
+    core::Interval,
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+use std::collections::BTreeMap;
+
+pub type DecorationId = u64;
+
+/// What a decoration is for, which the editor picks its style by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DecorationKind {
+    SearchHighlight,
+    /// The selection of another participant.
+    SelectionHighlight,
+    SpellCheck,
+    Custom(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoration {
+    pub id: DecorationId,
+    pub kind: DecorationKind,
+    pub interval: Interval,
+    /// The attributes the text is rendered with, on top of its own.
+    pub attributes: RichTextAttributes,
+}
+
+/// The decorations of the document, the attributes that the editor shows on the text for a while,
+/// e.g. the search highlights. They are kept outside of the delta, so they are never undone, synced
+/// or saved. The interval of each decoration is transformed by every change like the annotations,
+/// and the decoration is removed once its text is deleted.
+#[derive(Debug, Clone, Default)]
+pub struct Decorations {
+    next_id: DecorationId,
+    decorations: BTreeMap<DecorationId, Decoration>,
+}
+
+impl Decorations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, kind: DecorationKind, interval: Interval, attributes: RichTextAttributes) -> DecorationId {
+        self.next_id += 1;
+        let decoration = Decoration {
+            id: self.next_id,
+            kind,
+            interval,
+            attributes,
+        };
+        self.decorations.insert(decoration.id, decoration);
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: DecorationId) -> Option<Decoration> {
+        self.decorations.remove(&id)
+    }
+
+    pub fn get(&self, id: DecorationId) -> Option<&Decoration> {
+        self.decorations.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Decoration> {
+        self.decorations.values()
+    }
+
+    /// Removes the decorations of `kind`, e.g. the highlights of the last search.
+    pub fn clear_kind(&mut self, kind: &DecorationKind) {
+        self.decorations.retain(|_, decoration| &decoration.kind != kind);
+    }
+
+    pub fn clear(&mut self) {
+        self.decorations.clear();
+    }
+
+    /// Returns the decorations that overlap `interval`, ordered by their start, for rendering the
+    /// text in it. An empty decoration, e.g. a caret, overlaps the intervals it's in or at the
+    /// edge of.
+    pub fn in_interval(&self, interval: Interval) -> Vec<&Decoration> {
+        let mut decorations = self
+            .decorations
+            .values()
+            .filter(|decoration| {
+                let Interval { start, end } = decoration.interval;
+                match start == end {
+                    true => interval.start <= start && start <= interval.end,
+                    false => start < interval.end && interval.start < end,
+                }
+            })
+            .collect::<Vec<_>>();
+        decorations.sort_by_key(|decoration| (decoration.interval.start, decoration.interval.end, decoration.id));
+        decorations
+    }
+
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        if delta.is_noop() {
+            return;
+        }
+
+        self.decorations.retain(|_, decoration| {
+            let Interval { start, end } = decoration.interval;
+            // The text typed at the edges of the decoration isn't decorated.
+            let new_start = delta.transform_position(start, false);
+            let new_end = delta.transform_position(end, true).max(new_start);
+            decoration.interval = Interval::new(new_start, new_end);
+            start == end || new_start != new_end
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{decoration::DecorationKind, ClientDocument, NewlineDoc};
+    use lib_ot::{
+        core::Interval,
+        rich_text::{RichTextAttribute, RichTextAttributes},
+    };
+
+    #[test]
+    fn decorations_follow_the_edits_but_stay_out_of_the_history() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "hello world").unwrap();
+        let json = document.to_json();
+        let highlight: RichTextAttributes = RichTextAttribute::Background("yellow".to_owned()).into();
+        let search = document
+            .add_decoration(DecorationKind::SearchHighlight, Interval::new(6, 11), highlight)
+            .unwrap();
+        let caret = document
+            .add_decoration(
+                DecorationKind::SelectionHighlight,
+                Interval::new(5, 5),
+                RichTextAttributes::default(),
+            )
+            .unwrap();
+        assert!(document
+            .add_decoration(
+                DecorationKind::SpellCheck,
+                Interval::new(0, 20),
+                RichTextAttributes::default()
+            )
+            .is_err());
+        assert_eq!(document.to_json(), json);
+
+        document.insert(0, "oh ").unwrap();
+        assert_eq!(
+            document.decorations().get(search).unwrap().interval,
+            Interval::new(9, 14)
+        );
+        let ids = document
+            .decorations_in(Interval::new(0, 10))
+            .into_iter()
+            .map(|decoration| decoration.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![caret, search]);
+
+        // The decoration of the deleted text is removed, and isn't brought back by the undo.
+        document.delete(Interval::new(9, 14)).unwrap();
+        assert!(document.decorations().get(search).is_none());
+        document.undo().unwrap();
+        assert!(document.decorations().get(search).is_none());
+
+        document.clear_decorations(&DecorationKind::SelectionHighlight);
+        assert_eq!(document.decorations().iter().count(), 0);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    client_document::{
+        revision_log::DocumentSnapshot,
+        store::{DocumentStore, DocumentStoreRef, RevisionStore, SnapshotStore},
+    },
+    entities::revision::{md5, Revision},
+    errors::CollaborateError,
+};
+use dashmap::DashMap;
+use lib_ot::{core::Embed, rich_text::RichTextDelta};
+use serde_json::json;
+use std::sync::Arc;
+
+/// The kind of the embeds that take the place of the deduplicated text in the stored deltas. The
+/// kind is reserved, the documents can't have embeds of their own of this kind.
+pub const CHUNK_EMBED: &str = "dedup_chunk";
+
+/// Stores the chunks of text by their hash, see [DedupDocumentStore].
+pub trait ChunkStore: Send + Sync {
+    fn read_chunk(&self, hash: &str) -> Result<Option<String>, CollaborateError>;
+
+    /// Keeps the chunk under its hash, a chunk that is already stored isn't written again.
+    fn write_chunk(&self, hash: &str, text: &str) -> Result<(), CollaborateError>;
+}
+
+pub type ChunkStoreRef = Arc<dyn ChunkStore>;
+
+#[derive(Default)]
+pub struct MemoryChunkStore {
+    chunks: DashMap<String, String>,
+}
+
+impl MemoryChunkStore {
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn read_chunk(&self, hash: &str) -> Result<Option<String>, CollaborateError> {
+        Ok(self.chunks.get(hash).map(|chunk| chunk.clone()))
+    }
+
+    fn write_chunk(&self, hash: &str, text: &str) -> Result<(), CollaborateError> {
+        if !self.chunks.contains_key(hash) {
+            self.chunks.insert(hash.to_owned(), text.to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// How the inserted text is cut into chunks. The text is cut at the ends of its lines, at the
+/// first line after `min_len` bytes whose hash is a multiple of `divisor`, or after `max_len`
+/// bytes. The cuts only depend on the lines around them, so the same paragraphs are cut into the
+/// same chunks wherever they are pasted. The text shorter than `min_len` is kept in the delta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkingConfig {
+    pub min_len: usize,
+    pub max_len: usize,
+    pub divisor: u64,
+}
+
+impl std::default::Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            min_len: 1024,
+            max_len: 16 * 1024,
+            divisor: 8,
+        }
+    }
+}
+
+/// Stores the documents in `inner` with the large blocks of text kept once in a [ChunkStore],
+/// e.g. the boilerplate that is pasted in many documents. The snapshots and the revisions are
+/// stored with an embed of [CHUNK_EMBED] in the place of each chunk, and read back with the text
+/// of the chunks, so the store is used like any other one.
+pub struct DedupDocumentStore {
+    inner: DocumentStoreRef,
+    chunks: ChunkStoreRef,
+    config: ChunkingConfig,
+}
+
+impl DedupDocumentStore {
+    pub fn new(inner: DocumentStoreRef, chunks: ChunkStoreRef) -> Self {
+        Self::with_config(inner, chunks, ChunkingConfig::default())
+    }
+
+    pub fn with_config(inner: DocumentStoreRef, chunks: ChunkStoreRef, config: ChunkingConfig) -> Self {
+        Self { inner, chunks, config }
+    }
+
+    fn dedup_snapshot(&self, snapshot: &DocumentSnapshot) -> Result<DocumentSnapshot, CollaborateError> {
+        match dedup_delta(&snapshot.delta()?, self.chunks.as_ref(), &self.config)? {
+            None => Ok(snapshot.clone()),
+            Some(delta) => Ok(DocumentSnapshot::new(snapshot.rev_id, &delta).with_metadata(snapshot.metadata.clone())),
+        }
+    }
+
+    fn dedup_revisions(&self, revisions: &[Revision]) -> Result<Vec<Revision>, CollaborateError> {
+        revisions
+            .iter()
+            .map(|revision| {
+                let delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+                match dedup_delta(&delta, self.chunks.as_ref(), &self.config)? {
+                    None => Ok(revision.clone()),
+                    Some(delta) => Ok(Revision {
+                        delta_data: delta.to_bytes().to_vec(),
+                        ..revision.clone()
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+impl RevisionStore for DedupDocumentStore {
+    fn read_revisions(&self, doc_id: &str, rev_id: i64) -> Result<Vec<Revision>, CollaborateError> {
+        let mut revisions = self.inner.read_revisions(doc_id, rev_id)?;
+        for revision in &mut revisions {
+            let delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+            if let Some(delta) = hydrate_delta(&delta, self.chunks.as_ref())? {
+                revision.delta_data = delta.to_bytes().to_vec();
+            }
+        }
+        Ok(revisions)
+    }
+
+    fn write_revisions(&self, doc_id: &str, revisions: &[Revision]) -> Result<(), CollaborateError> {
+        self.inner.write_revisions(doc_id, &self.dedup_revisions(revisions)?)
+    }
+}
+
+impl SnapshotStore for DedupDocumentStore {
+    fn read_snapshot(&self, doc_id: &str) -> Result<Option<DocumentSnapshot>, CollaborateError> {
+        match self.inner.read_snapshot(doc_id)? {
+            None => Ok(None),
+            Some(snapshot) => match hydrate_delta(&snapshot.delta()?, self.chunks.as_ref())? {
+                None => Ok(Some(snapshot)),
+                Some(delta) => Ok(Some(
+                    DocumentSnapshot::new(snapshot.rev_id, &delta).with_metadata(snapshot.metadata),
+                )),
+            },
+        }
+    }
+
+    fn write_snapshot(&self, doc_id: &str, snapshot: &DocumentSnapshot) -> Result<(), CollaborateError> {
+        self.inner.write_snapshot(doc_id, &self.dedup_snapshot(snapshot)?)
+    }
+
+    fn doc_ids(&self) -> Result<Vec<String>, CollaborateError> {
+        self.inner.doc_ids()
+    }
+}
+
+impl DocumentStore for DedupDocumentStore {
+    // The chunks are written first, so the inner store writes both or neither as it does.
+    fn save(&self, doc_id: &str, snapshot: &DocumentSnapshot, revisions: &[Revision]) -> Result<(), CollaborateError> {
+        let snapshot = self.dedup_snapshot(snapshot)?;
+        let revisions = self.dedup_revisions(revisions)?;
+        self.inner.save(doc_id, &snapshot, &revisions)
+    }
+}
+
+/// Moves the large blocks of text of the inserts of `delta` to `chunks`. Returns none if there
+/// was no text to move.
+pub fn dedup_delta(
+    delta: &RichTextDelta,
+    chunks: &dyn ChunkStore,
+    config: &ChunkingConfig,
+) -> Result<Option<RichTextDelta>, CollaborateError> {
+    let mut deduped = RichTextDelta::new();
+    let mut changed = false;
+    for op in &delta.ops {
+        let text = op.get_data();
+        if !op.is_insert() || op.get_embed().is_some() || text.len() < config.min_len {
+            deduped.add(op.clone());
+            continue;
+        }
+        for chunk in split_chunks(text, config) {
+            match chunk.len() < config.min_len {
+                true => deduped.insert(chunk, op.get_attributes()),
+                false => {
+                    let hash = md5(chunk);
+                    chunks.write_chunk(&hash, chunk)?;
+                    let embed = Embed::new(CHUNK_EMBED, json!({ "hash": hash }));
+                    deduped.insert_embed(embed, op.get_attributes());
+                    changed = true;
+                }
+            }
+        }
+    }
+    Ok(if changed { Some(deduped) } else { None })
+}
+
+/// Puts the text of the chunks back in the place of their embeds. Returns none if `delta` has no
+/// chunks. Fails if a chunk is missing or doesn't have the text it was stored with.
+pub fn hydrate_delta(
+    delta: &RichTextDelta,
+    chunks: &dyn ChunkStore,
+) -> Result<Option<RichTextDelta>, CollaborateError> {
+    let mut hydrated = RichTextDelta::new();
+    let mut changed = false;
+    for op in &delta.ops {
+        let hash = match op.get_embed() {
+            Some(embed) if embed.kind == CHUNK_EMBED => {
+                embed.data.to_value()["hash"].as_str().unwrap_or_default().to_owned()
+            }
+            _ => {
+                hydrated.add(op.clone());
+                continue;
+            }
+        };
+        let text = chunks
+            .read_chunk(&hash)?
+            .ok_or_else(|| CollaborateError::internal().context(format!("The chunk {} is missing", hash)))?;
+        if md5(&text) != hash {
+            return Err(CollaborateError::integrity().context(format!("The chunk {} is corrupted", hash)));
+        }
+        hydrated.insert(&text, op.get_attributes());
+        changed = true;
+    }
+    Ok(if changed { Some(hydrated) } else { None })
+}
+
+fn split_chunks<'a>(text: &'a str, config: &ChunkingConfig) -> Vec<&'a str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    for line in text.split_inclusive('\n') {
+        end += line.len();
+        let len = end - start;
+        if len >= config.max_len || (len >= config.min_len && line_hash(line) % config.divisor.max(1) == 0) {
+            chunks.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+}
+
+// The FNV-1a hash of the line, which is the same on every platform and version.
+fn line_hash(line: &str) -> u64 {
+    line.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        dedup::{ChunkingConfig, DedupDocumentStore, MemoryChunkStore, CHUNK_EMBED},
+        store::{load_document, save_document, MemoryDocumentStore, SnapshotStore},
+        ClientDocument, DocumentHandle, NewlineDoc,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn pasted_boilerplate_is_stored_once() {
+        let inner = Arc::new(MemoryDocumentStore::default());
+        let chunks = Arc::new(MemoryChunkStore::default());
+        let config = ChunkingConfig {
+            min_len: 16,
+            max_len: 64,
+            divisor: 1,
+        };
+        let store = DedupDocumentStore::with_config(inner.clone(), chunks.clone(), config);
+        let boilerplate = "Confidential.\nDo not share this document.\nAll rights reserved.\n";
+
+        let mut saved = vec![];
+        let mut chunk_counts = vec![];
+        for (doc_id, intro) in vec![("a", "First\n"), ("b", "Second one\n")] {
+            let handle = DocumentHandle::new(ClientDocument::new::<NewlineDoc>());
+            handle.insert(0, intro).await.unwrap();
+            handle.insert(intro.len(), boilerplate).await.unwrap();
+            save_document(&store, doc_id, &handle, 0).await.unwrap();
+            saved.push((doc_id, handle.to_json().await));
+            chunk_counts.push(chunks.len());
+        }
+
+        // Only the first chunk of the snapshot, which starts with the intro, is new in the second
+        // document.
+        assert_eq!(chunk_counts[1], chunk_counts[0] + 1);
+        let stored = inner.read_snapshot("a").unwrap().unwrap().delta().unwrap();
+        assert!(stored.to_json().contains(CHUNK_EMBED));
+        for (doc_id, json) in saved {
+            let document = load_document(&store, doc_id).unwrap().unwrap();
+            assert_eq!(document.to_json(), json);
+        }
+    }
+}
@@ -0,0 +1,521 @@
+use dissimilar::Chunk;
+use lib_ot::{
+    core::{trim, FlowyStr, Interval, Operation, NEW_LINE},
+    rich_text::{RichTextAttributes, RichTextDelta, RichTextOperation},
+};
+use std::collections::HashMap;
+
+/// Calculates the delta that turns the document `old` into `new`. The text is diffed first, then
+/// the attributes of the text that both documents share are compared, so the delta only touches
+/// what changed, e.g. a word that was made bold is retained with the bold attribute.
+pub fn diff(old: &RichTextDelta, new: &RichTextDelta) -> RichTextDelta {
+    let old_text = old.apply("").unwrap_or_default();
+    let new_text = new.apply("").unwrap_or_default();
+
+    let mut delta = RichTextDelta::new();
+    let mut old_offset = 0;
+    let mut new_offset = 0;
+    for chunk in dissimilar::diff(&old_text, &new_text) {
+        match chunk {
+            Chunk::Equal(s) => {
+                let len = FlowyStr::from(s).utf16_size();
+                let old_ops = ops_in(old, Interval::new(old_offset, old_offset + len));
+                let new_ops = ops_in(new, Interval::new(new_offset, new_offset + len));
+                diff_ops(&mut delta, &old_ops, &new_ops);
+                old_offset += len;
+                new_offset += len;
+            }
+            Chunk::Delete(s) => {
+                let len = FlowyStr::from(s).utf16_size();
+                delta.delete(len);
+                old_offset += len;
+            }
+            Chunk::Insert(s) => {
+                let len = FlowyStr::from(s).utf16_size();
+                for op in ops_in(new, Interval::new(new_offset, new_offset + len)) {
+                    delta.add(op);
+                }
+                new_offset += len;
+            }
+        }
+    }
+    trim(&mut delta);
+    delta
+}
+
+fn ops_in(document: &RichTextDelta, interval: Interval) -> Vec<RichTextOperation> {
+    document.ops_in(interval).filter_map(|slice| slice.to_op()).collect()
+}
+
+// Compares two runs of the same text. The attributes that differ are retained, the embeds that
+// differ, which have the same text, are replaced.
+fn diff_ops(delta: &mut RichTextDelta, old_ops: &[RichTextOperation], new_ops: &[RichTextOperation]) {
+    zip_ops(old_ops, new_ops, |old_op, new_op, interval| {
+        if old_op.get_embed() != new_op.get_embed() {
+            if let Some(op) = new_op.shrink(interval) {
+                delta.add(op);
+            }
+            delta.delete(interval.size());
+        } else {
+            let attributes = diff_attributes(&old_op.get_attributes(), &new_op.get_attributes());
+            delta.retain(interval.size(), attributes);
+        }
+    });
+}
+
+// Walks two runs of the same text, split at the bounds of the operations of both. `f` takes the
+// operations that cover each part and the interval of the part in the new operation.
+fn zip_ops<F>(old_ops: &[RichTextOperation], new_ops: &[RichTextOperation], mut f: F)
+where
+    F: FnMut(&RichTextOperation, &RichTextOperation, Interval),
+{
+    let (mut i, mut old_start) = (0, 0);
+    let (mut j, mut new_start) = (0, 0);
+    while i < old_ops.len() && j < new_ops.len() {
+        let (old_op, new_op) = (&old_ops[i], &new_ops[j]);
+        let len = (old_op.len() - old_start).min(new_op.len() - new_start);
+        f(old_op, new_op, Interval::new(new_start, new_start + len));
+
+        old_start += len;
+        if old_start == old_op.len() {
+            i += 1;
+            old_start = 0;
+        }
+        new_start += len;
+        if new_start == new_op.len() {
+            j += 1;
+            new_start = 0;
+        }
+    }
+}
+
+// The attributes that turn `old` into `new` when they are composed. An attribute without a value
+// is the same as a missing one.
+fn diff_attributes(old: &RichTextAttributes, new: &RichTextAttributes) -> RichTextAttributes {
+    let mut attributes = RichTextAttributes::new();
+    for (key, value) in new.iter() {
+        if value.0.is_some() && old.get(key) != Some(value) {
+            attributes.add_kv(key.clone(), value.clone());
+        }
+    }
+    for (key, value) in old.iter() {
+        let removed = new.get(key).map(|value| value.0.is_none()).unwrap_or(true);
+        if value.0.is_some() && removed {
+            attributes.delete(key);
+        }
+    }
+    attributes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Unchanged,
+    Inserted,
+    Deleted,
+}
+
+/// A run of text of a [DiffView]. The unchanged text has both the old and the new attributes, which
+/// differ if it was formatted. The inserted text only has the new ones and the deleted text the
+/// old ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffSpan {
+    pub kind: DiffKind,
+    pub text: String,
+    pub old_attributes: Option<RichTextAttributes>,
+    pub new_attributes: Option<RichTextAttributes>,
+}
+
+impl DiffSpan {
+    pub fn is_formatted(&self) -> bool {
+        self.kind == DiffKind::Unchanged && self.old_attributes != self.new_attributes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffGranularity {
+    /// The lines that changed are deleted and inserted as a whole. The documents with more
+    /// distinct lines than [MAX_DIFF_LINES] are diffed by [DiffGranularity::Char] instead.
+    Line,
+    /// The changes are found inside of the lines.
+    Char,
+}
+
+/// The comparison of two documents, as the spans the frontend renders, either inline or side by
+/// side with [DiffView::old_spans] and [DiffView::new_spans].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffView {
+    spans: Vec<DiffSpan>,
+}
+
+impl DiffView {
+    pub fn spans(&self) -> &[DiffSpan] {
+        &self.spans
+    }
+
+    /// The spans of the old document, which are the unchanged and the deleted ones.
+    pub fn old_spans(&self) -> impl Iterator<Item = &DiffSpan> {
+        self.spans.iter().filter(|span| span.kind != DiffKind::Inserted)
+    }
+
+    /// The spans of the new document, which are the unchanged and the inserted ones.
+    pub fn new_spans(&self) -> impl Iterator<Item = &DiffSpan> {
+        self.spans.iter().filter(|span| span.kind != DiffKind::Deleted)
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.spans
+            .iter()
+            .any(|span| span.kind != DiffKind::Unchanged || span.is_formatted())
+    }
+}
+
+pub struct DiffViewBuilder<'a> {
+    old: &'a RichTextDelta,
+    new: &'a RichTextDelta,
+    granularity: DiffGranularity,
+    change: Option<&'a RichTextDelta>,
+}
+
+impl<'a> DiffViewBuilder<'a> {
+    pub fn new(old: &'a RichTextDelta, new: &'a RichTextDelta) -> Self {
+        Self {
+            old,
+            new,
+            granularity: DiffGranularity::Char,
+            change: None,
+        }
+    }
+
+    pub fn granularity(mut self, granularity: DiffGranularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// Sets the delta that turned `old` into `new`. The spans follow its operations instead of
+    /// a diff of the texts, so they show the change exactly as it was made, and the granularity
+    /// is ignored.
+    pub fn change(mut self, delta: &'a RichTextDelta) -> Self {
+        self.change = Some(delta);
+        self
+    }
+
+    pub fn build(self) -> DiffView {
+        let chunks: Vec<(DiffKind, usize)> = match self.change {
+            Some(delta) => change_chunks(self.old, delta),
+            None => self.text_chunks(),
+        };
+
+        let mut view = DiffView::default();
+        let mut old_offset = 0;
+        let mut new_offset = 0;
+        for (kind, len) in chunks {
+            let old_ops = ops_in(self.old, Interval::new(old_offset, old_offset + len));
+            let new_ops = ops_in(self.new, Interval::new(new_offset, new_offset + len));
+            match kind {
+                DiffKind::Unchanged => {
+                    push_unchanged(&mut view, &old_ops, &new_ops);
+                    old_offset += len;
+                    new_offset += len;
+                }
+                DiffKind::Deleted => {
+                    for op in &old_ops {
+                        push_span(&mut view, kind, op_text(op), Some(op.get_attributes()), None);
+                    }
+                    old_offset += len;
+                }
+                DiffKind::Inserted => {
+                    for op in &new_ops {
+                        push_span(&mut view, kind, op_text(op), None, Some(op.get_attributes()));
+                    }
+                    new_offset += len;
+                }
+            }
+        }
+        view
+    }
+
+    fn text_chunks(&self) -> Vec<(DiffKind, usize)> {
+        let old_text = self.old.apply("").unwrap_or_default();
+        let new_text = self.new.apply("").unwrap_or_default();
+        let chunks: Vec<(DiffKind, String)> = match self.granularity {
+            DiffGranularity::Char => char_chunks(&old_text, &new_text),
+            DiffGranularity::Line => {
+                line_chunks(&old_text, &new_text).unwrap_or_else(|| char_chunks(&old_text, &new_text))
+            }
+        };
+        chunks
+            .into_iter()
+            .map(|(kind, s)| (kind, FlowyStr::from(s).utf16_size()))
+            .collect()
+    }
+}
+
+// The text that `delta` keeps, inserts and deletes, the text after its last operation is kept.
+fn change_chunks(old: &RichTextDelta, delta: &RichTextDelta) -> Vec<(DiffKind, usize)> {
+    let mut chunks = delta
+        .ops
+        .iter()
+        .map(|op| match op {
+            Operation::Retain(retain) => (DiffKind::Unchanged, retain.n),
+            Operation::Insert(insert) => (DiffKind::Inserted, insert.utf16_size()),
+            Operation::Delete(n) => (DiffKind::Deleted, *n),
+        })
+        .collect::<Vec<_>>();
+    if old.utf16_target_len > delta.utf16_base_len {
+        chunks.push((DiffKind::Unchanged, old.utf16_target_len - delta.utf16_base_len));
+    }
+    chunks
+}
+
+fn char_chunks(old_text: &str, new_text: &str) -> Vec<(DiffKind, String)> {
+    dissimilar::diff(old_text, new_text)
+        .into_iter()
+        .map(|chunk| match chunk {
+            Chunk::Equal(s) => (DiffKind::Unchanged, s.to_owned()),
+            Chunk::Delete(s) => (DiffKind::Deleted, s.to_owned()),
+            Chunk::Insert(s) => (DiffKind::Inserted, s.to_owned()),
+        })
+        .collect()
+}
+
+// Diffs the lines as a whole: each line is replaced by a character, the strings of characters are
+// diffed, then the characters are replaced by their lines again. Returns None if the texts have
+// more distinct lines than [MAX_DIFF_LINES].
+fn line_chunks(old_text: &str, new_text: &str) -> Option<Vec<(DiffKind, String)>> {
+    let mut lines = vec![];
+    let mut chars = HashMap::new();
+    let old_chars = encode_lines(old_text, &mut lines, &mut chars, MAX_DIFF_LINES)?;
+    let new_chars = encode_lines(new_text, &mut lines, &mut chars, MAX_DIFF_LINES)?;
+    let decode = |s: &str| -> String {
+        s.chars()
+            .map(|c| lines[(c as u32 - FIRST_LINE_CHAR) as usize])
+            .collect()
+    };
+    let chunks = dissimilar::diff(&old_chars, &new_chars)
+        .into_iter()
+        .map(|chunk| match chunk {
+            Chunk::Equal(s) => (DiffKind::Unchanged, decode(s)),
+            Chunk::Delete(s) => (DiffKind::Deleted, decode(s)),
+            Chunk::Insert(s) => (DiffKind::Inserted, decode(s)),
+        })
+        .collect();
+    Some(chunks)
+}
+
+// The characters that stand for the lines start after the surrogates, so any of them is valid.
+const FIRST_LINE_CHAR: u32 = 0x10000;
+
+/// The number of the distinct lines that [DiffGranularity::Line] diffs, one for each character
+/// from [FIRST_LINE_CHAR] to [char::MAX].
+pub const MAX_DIFF_LINES: usize = (char::MAX as u32 - FIRST_LINE_CHAR + 1) as usize;
+
+// Returns None once there are more distinct lines than `max_lines`.
+fn encode_lines<'a>(
+    text: &'a str,
+    lines: &mut Vec<&'a str>,
+    chars: &mut HashMap<&'a str, char>,
+    max_lines: usize,
+) -> Option<String> {
+    let mut encoded = String::new();
+    for line in text.split_inclusive(NEW_LINE) {
+        let c = match chars.get(line) {
+            Some(c) => *c,
+            None if lines.len() >= max_lines => return None,
+            None => {
+                let c = char::from_u32(FIRST_LINE_CHAR + lines.len() as u32)?;
+                lines.push(line);
+                chars.insert(line, c);
+                c
+            }
+        };
+        encoded.push(c);
+    }
+    Some(encoded)
+}
+
+// Splits the unchanged text where the old or the new attributes change.
+fn push_unchanged(view: &mut DiffView, old_ops: &[RichTextOperation], new_ops: &[RichTextOperation]) {
+    zip_ops(old_ops, new_ops, |old_op, new_op, interval| {
+        if let Some(op) = new_op.shrink(interval) {
+            let (old_attributes, new_attributes) = (old_op.get_attributes(), new_op.get_attributes());
+            push_span(
+                view,
+                DiffKind::Unchanged,
+                op_text(&op),
+                Some(old_attributes),
+                Some(new_attributes),
+            );
+        }
+    });
+}
+
+// Appends the span, or extends the last one if it's the same kind of span with the same attributes.
+fn push_span(
+    view: &mut DiffView,
+    kind: DiffKind,
+    text: String,
+    old_attributes: Option<RichTextAttributes>,
+    new_attributes: Option<RichTextAttributes>,
+) {
+    if let Some(last) = view.spans.last_mut() {
+        if last.kind == kind && last.old_attributes == old_attributes && last.new_attributes == new_attributes {
+            last.text.push_str(&text);
+            return;
+        }
+    }
+    view.spans.push(DiffSpan {
+        kind,
+        text,
+        old_attributes,
+        new_attributes,
+    });
+}
+
+fn op_text(op: &RichTextOperation) -> String {
+    match op {
+        Operation::Insert(insert) => insert.s.to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::diff::{diff, encode_lines, DiffGranularity, DiffKind, DiffViewBuilder};
+    use lib_ot::{
+        core::{Embed, OperationTransformable},
+        rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta, RichTextDeltaBuilder},
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn diff_text_and_attributes() {
+        let old = RichTextDeltaBuilder::new()
+            .insert("Hello ")
+            .insert_with_attributes("world", RichTextAttribute::Italic(true).into())
+            .insert("\n")
+            .build();
+        let new = RichTextDeltaBuilder::new()
+            .insert_with_attributes("Hello", RichTextAttribute::Bold(true).into())
+            .insert(" big world\n")
+            .build();
+
+        // The unchanged text is retained, with only the attributes that changed.
+        let delta = diff(&old, &new);
+        assert_eq!(
+            delta.ops.first(),
+            RichTextDeltaBuilder::new()
+                .retain_with_attributes(5, RichTextAttribute::Bold(true).into())
+                .build()
+                .ops
+                .first()
+        );
+        assert_eq!(
+            delta
+                .ops
+                .iter()
+                .filter(|op| op.is_insert())
+                .map(|op| op.len())
+                .sum::<usize>(),
+            4
+        );
+        assert!(delta.ops.iter().all(|op| !op.is_delete()));
+        assert_eq!(old.compose(&delta).unwrap(), new);
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn diff_replaces_embed() {
+        let image = |src: &str| Embed::new("image", serde_json::json!({ "src": src }));
+        let old = RichTextDeltaBuilder::new()
+            .insert_embed(image("a"))
+            .insert("\n")
+            .build();
+        let new = RichTextDeltaBuilder::new()
+            .insert_embed(image("b"))
+            .insert("\n")
+            .build();
+
+        let delta = diff(&old, &new);
+        let expected: RichTextDelta = RichTextDeltaBuilder::new().insert_embed(image("b")).delete(1).build();
+        assert_eq!(delta, expected);
+        assert_eq!(old.compose(&delta).unwrap(), new);
+    }
+
+    #[test]
+    fn diff_view_spans() {
+        let old = RichTextDeltaBuilder::new().insert("Hello world\nfoo\n").build();
+        let new = RichTextDeltaBuilder::new()
+            .insert("Hello World\n")
+            .insert_with_attributes("foo", RichTextAttribute::Bold(true).into())
+            .insert("\n")
+            .build();
+
+        let view = DiffViewBuilder::new(&old, &new).build();
+        assert!(view.has_changes());
+        let old_text: String = view.old_spans().map(|span| span.text.as_str()).collect();
+        let new_text: String = view.new_spans().map(|span| span.text.as_str()).collect();
+        assert_eq!(old_text, "Hello world\nfoo\n");
+        assert_eq!(new_text, "Hello World\nfoo\n");
+        // The change is found inside of the first line.
+        assert!(view.spans()[0].kind == DiffKind::Unchanged);
+        let formatted = view.spans().iter().find(|span| span.is_formatted()).unwrap();
+        assert_eq!(formatted.text, "foo");
+        assert_eq!(formatted.old_attributes, Some(RichTextAttributes::default()));
+        assert_eq!(formatted.new_attributes, Some(RichTextAttribute::Bold(true).into()));
+
+        let view = DiffViewBuilder::new(&old, &new)
+            .granularity(DiffGranularity::Line)
+            .build();
+        let spans = view
+            .spans()
+            .iter()
+            .map(|span| (span.kind, span.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                (DiffKind::Deleted, "Hello world\n"),
+                (DiffKind::Inserted, "Hello World\n"),
+                (DiffKind::Unchanged, "foo"),
+                (DiffKind::Unchanged, "\n"),
+            ]
+        );
+        assert!(!DiffViewBuilder::new(&new, &new).build().has_changes());
+    }
+
+    #[test]
+    fn encode_lines_within_the_limit() {
+        let (mut lines, mut chars) = (vec![], HashMap::new());
+        assert!(encode_lines("a\nb\na\n", &mut lines, &mut chars, 2).is_some());
+        assert_eq!(lines, vec!["a\n", "b\n"]);
+        // The diff of the lines falls back to the diff of the characters past the limit.
+        assert_eq!(encode_lines("c\n", &mut lines, &mut chars, 2), None);
+    }
+
+    #[test]
+    fn diff_view_follows_the_change() {
+        let old = RichTextDeltaBuilder::new().insert("aa\n").build();
+        // The text diff would keep the first "a", the change inserts before it.
+        let delta = RichTextDeltaBuilder::new()
+            .insert("a")
+            .retain_with_attributes(1, RichTextAttribute::Bold(true).into())
+            .delete(1)
+            .build();
+        let new = old.compose(&delta).unwrap();
+        let view = DiffViewBuilder::new(&old, &new).change(&delta).build();
+        let spans = view
+            .spans()
+            .iter()
+            .map(|span| (span.kind, span.text.as_str(), span.is_formatted()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                (DiffKind::Inserted, "a", false),
+                (DiffKind::Unchanged, "a", true),
+                (DiffKind::Deleted, "a", false),
+                (DiffKind::Unchanged, "\n", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::client_document::lines::DocumentLine;
+use lib_ot::rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes};
+use unicode_bidi::{bidi_class, BidiClass};
+
+/// The direction of the text of a line, the value of the
+/// [RichTextAttributeKey::Direction] block attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextDirection {
+    Ltr,
+    Rtl,
+    /// The direction of the first strong character of the line, see [detect_direction].
+    Auto,
+}
+
+impl TextDirection {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "ltr" => Some(TextDirection::Ltr),
+            "rtl" => Some(TextDirection::Rtl),
+            "auto" => Some(TextDirection::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextDirection::Ltr => "ltr",
+            TextDirection::Rtl => "rtl",
+            TextDirection::Auto => "auto",
+        }
+    }
+
+    /// The attribute that gives the direction to a line, see
+    /// [crate::client_document::ClientDocument::format].
+    pub fn to_attribute(&self) -> RichTextAttribute {
+        RichTextAttribute::Direction(self.as_str())
+    }
+
+    /// Returns the direction in `attributes`, `None` if it has none or an unknown one.
+    pub fn from_attributes(attributes: &RichTextAttributes) -> Option<Self> {
+        attributes
+            .get(&RichTextAttributeKey::Direction)
+            .and_then(|value| value.as_str())
+            .and_then(TextDirection::parse)
+    }
+}
+
+/// Returns the direction of the first strong character of `text`, the way the rules P2 and P3 of
+/// the Unicode bidirectional algorithm find the direction of a paragraph. Returns `None` if the
+/// text has no strong character, e.g. it's empty or only has digits and punctuation.
+pub fn detect_direction(text: &str) -> Option<TextDirection> {
+    text.chars().find_map(|c| match bidi_class(c) {
+        BidiClass::L => Some(TextDirection::Ltr),
+        BidiClass::R | BidiClass::AL => Some(TextDirection::Rtl),
+        _ => None,
+    })
+}
+
+/// Returns the direction that `line` is laid out in, either [TextDirection::Ltr] or
+/// [TextDirection::Rtl]. The lines without a direction have `default`, the automatic ones the
+/// direction of their text, and left to right if the text doesn't tell.
+pub fn line_direction(line: &DocumentLine, default: TextDirection) -> TextDirection {
+    match TextDirection::from_attributes(&line.attributes).unwrap_or(default) {
+        TextDirection::Auto => detect_direction(&line.text).unwrap_or(TextDirection::Ltr),
+        direction => direction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        direction::{detect_direction, line_direction, TextDirection},
+        lines::Lines,
+    };
+    use lib_ot::{core::OperationTransformable, rich_text::RichTextDeltaBuilder};
+
+    #[test]
+    fn detect_direction_of_first_strong_character() {
+        assert_eq!(detect_direction("123 abc"), Some(TextDirection::Ltr));
+        assert_eq!(detect_direction("1. שלום world"), Some(TextDirection::Rtl));
+        assert_eq!(detect_direction("مرحبا"), Some(TextDirection::Rtl));
+        assert_eq!(detect_direction("123 !?"), None);
+    }
+
+    #[test]
+    fn line_direction_follows_attribute_or_text() {
+        let document = RichTextDeltaBuilder::new()
+            .insert("abc")
+            .insert_with_attributes("\n", TextDirection::Rtl.to_attribute().into())
+            .insert("שלום")
+            .insert_with_attributes("\n", TextDirection::Auto.to_attribute().into())
+            .insert("123\n")
+            .build();
+        let directions = Lines::new(&document)
+            .map(|line| line_direction(&line, TextDirection::Auto))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            directions,
+            vec![TextDirection::Rtl, TextDirection::Rtl, TextDirection::Ltr]
+        );
+    }
+
+    #[test]
+    fn direction_replaces_the_direction_of_the_line() {
+        let document = RichTextDeltaBuilder::new()
+            .insert("abc")
+            .insert_with_attributes("\n", TextDirection::Ltr.to_attribute().into())
+            .build();
+        let rtl = RichTextDeltaBuilder::new()
+            .retain(3)
+            .retain_with_attributes(1, TextDirection::Rtl.to_attribute().into())
+            .build();
+        let auto = RichTextDeltaBuilder::new()
+            .retain(3)
+            .retain_with_attributes(1, TextDirection::Auto.to_attribute().into())
+            .build();
+        let composed = document.compose(&rtl).unwrap();
+        let line = Lines::new(&composed).next().unwrap();
+        assert_eq!(line.attributes, TextDirection::Rtl.to_attribute().into());
+
+        // The concurrent directions converge to one of them instead of cancelling each other.
+        let (rtl_prime, auto_prime) = rtl.transform(&auto).unwrap();
+        let a = composed.compose(&auto_prime).unwrap();
+        let b = document.compose(&auto).unwrap().compose(&rtl_prime).unwrap();
+        assert_eq!(a, b);
+        let line = Lines::new(&a).next().unwrap();
+        assert_eq!(
+            TextDirection::from_attributes(&line.attributes),
+            Some(TextDirection::Rtl)
+        );
+    }
+}
@@ -1,16 +1,106 @@
 use crate::{
     client_document::{
+        anchor::{Anchor, AnchorBias, AnchorId, Anchors},
+        annotation::{Annotation, AnnotationId, Annotations},
+        ast::{DocumentTree, TreeChanges},
+        attribute_runs::AttributeRuns,
+        audit::HistoryEntryMeta,
+        auto_link::link_before,
+        boundary::{lines_in, BoundaryPolicy},
+        command::{EditCommand, EditResponse},
+        composition::Composition,
+        config::{DocumentConfig, EditingConfig, ForkPoint, HistoryConfig},
+        content_policy::{ContentPolicies, PolicyViolation},
+        debug_stats::{micros, DebugStats},
+        decoration::{Decoration, DecorationId, DecorationKind, Decorations},
         default::initial_delta,
-        history::{History, UndoResult},
+        diff::{diff, DiffView, DiffViewBuilder},
+        direction::{line_direction, TextDirection},
+        embed_handler::{embed_at, EmbedHandler, EmbedHandlers},
+        event::{
+            BlockEvent, ContentChangeEvent, DocumentEvent, DocumentEventSource, MetadataEvent, PolicyEvent,
+            ResyncRequired,
+        },
+        export::{ExportOptions, Exporter, ExporterRegistry, MarkdownExporter},
+        format::{decode_json, encode_json, PayloadKind},
+        hibernation::{DocumentState, MetadataEntryData},
+        history::{
+            pad, BranchId, History, HistoryBranch, HistoryCompression, HistoryEntry, SelectionChange, UndoResult,
+        },
+        import::{Importer, MarkdownImporter},
+        input_rules::InputRules,
+        lines::{index_to_position, position_to_index, ColumnUnit, DocumentLine, Lines, TextPosition},
+        list::{may_change_lists, renumber_lists},
+        lsp::{content_changes, content_changes_to_delta, ContentChange},
+        mention::{mentions, Mention, MentionResolver, MentionTracker},
+        metadata::Metadata,
+        metrics::OTMetricsRef,
+        normalize::{normalize_input, NormalizeConfig},
+        observer::{DocumentObserver, ObservedDocument, Observers},
+        outline::{Outline, OutlineEntry},
+        paste::{paste_delta, PasteStrategy},
+        playback::Playback,
+        presence::Presence,
+        protection::{check_protection, protected_intervals, OverrideToken},
+        read_snapshot::ReadSnapshot,
+        replay::{ReplayCall, ReplayGuard, ReplayLog, ReplayRecorder},
+        revision_log::{DocumentSnapshot, RevisionLog},
+        search::{FindOptions, SearchMatch, SearchQuery, TextIndex},
+        segmentation::{sentences_in, words_in, Segmenter, TextSegment, UnicodeSegmenter},
+        snapshot_policy::PendingReplay,
+        spell_check::{SpellCheck, SpellChecker},
+        split::{split_document, AppendedDocument},
+        stats::{DocumentStats, StatsTracker},
+        stream::ContentStream,
+        suggestion::{resolve_suggestion, suggest_delta, suggestions, Suggestion, SuggestionId},
+        summary::{attributes_in, AttributeSummary},
+        sync::SyncMode,
+        table::{table_at, Table, TableOp},
+        terms::{TermIndexer, TermTracker},
+        transclusion::{transclusions, LinkIndex, LinkTracker, Transclusion, TransclusionProvider},
+        typing::{TypingBuffer, TypingConfig},
+        verify::{verify_document, VerifyReport},
         view::{ViewExtensions, RECORD_THRESHOLD},
     },
-    errors::CollaborateError,
+    entities::revision::{MetadataEntry, Revision},
+    errors::{CollaborateError, QuotaExceededError},
+    util::{cal_diff, content_md5, line_at, verify_content_md5},
 };
+use bytes::Bytes;
+use instant::Instant;
 use lib_ot::{
     core::*,
-    rich_text::{RichTextAttribute, RichTextDelta},
+    engine::CollaborationEngine,
+    rich_text::{
+        AttributeScope, BlockAttribute, RichTextAttribute, RichTextAttributeKey, RichTextAttributeValue,
+        RichTextAttributes, RichTextDelta, RichTextDeltaBuilder,
+    },
+};
+use parking_lot::Mutex;
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+    time::Duration,
 };
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
+
+pub type DocumentEngine = Box<dyn CollaborationEngine<RichTextAttributes>>;
+
+/// The utf16 length of the chunks that [ClientDocument::apply_chunked] composes at a time.
+pub const CHUNK_LEN: usize = 64 * 1024;
+
+// How a local change is recorded in the history.
+enum UndoRecording<'a> {
+    // Merged into the entry of the edit before it, within [RECORD_THRESHOLD], like the typing.
+    Merge,
+    // An entry of its own, which the next edit isn't merged into either, e.g. a paste.
+    Separate,
+    // Composed into the inverse of the changes before it, which the caller records, e.g. the
+    // chunks of [ClientDocument::apply_chunked].
+    Collect(&'a mut RichTextDelta),
+}
 
 pub trait InitialDocumentText {
     fn initial_delta() -> RichTextDelta;
@@ -23,6 +113,9 @@ impl InitialDocumentText for PlainDoc {
     }
 }
 
+/// The document that starts with a newline. Like the documents of Quill, it always ends with a
+/// newline afterwards, which keeps the block attributes of its last line, see
+/// [ClientDocument::from_delta].
 pub struct NewlineDoc();
 impl InitialDocumentText for NewlineDoc {
     fn initial_delta() -> RichTextDelta {
@@ -34,8 +127,67 @@ pub struct ClientDocument {
     delta: RichTextDelta,
     history: History,
     view: ViewExtensions,
-    last_edit_time: usize,
+    // The time of the clock of the config the current undo entry started at.
+    last_edit_time: i64,
     notify: Option<mpsc::UnboundedSender<()>>,
+    engine: Option<DocumentEngine>,
+    engine_updates: Vec<Bytes>,
+    presence: Presence,
+    annotations: Annotations,
+    anchors: Anchors,
+    decorations: Decorations,
+    config: DocumentConfig,
+    rev_id: i64,
+    revision_log: RevisionLog,
+    // The revisions since the last snapshot, see [crate::client_document::snapshot_policy::SnapshotPolicy].
+    pending_replay: PendingReplay,
+    notifier: broadcast::Sender<DocumentEvent>,
+    block_notifier: broadcast::Sender<BlockEvent>,
+    content_change_notifier: broadcast::Sender<ContentChangeEvent>,
+    policy_notifier: broadcast::Sender<PolicyEvent>,
+    resync_notifier: broadcast::Sender<ResyncRequired>,
+    metadata_notifier: broadcast::Sender<MetadataEvent>,
+    // The local revision and the content of the document when it diverged from the server.
+    desync: Option<(i64, RichTextDelta)>,
+    // The local changes that wait for the server in the authoritative sync mode, each one made
+    // after the ones before it, see [SyncMode::Authoritative].
+    intents: VecDeque<RichTextDelta>,
+    metadata: Metadata,
+    content_policies: ContentPolicies,
+    embed_handlers: EmbedHandlers,
+    // Lets the local changes write the locked text while it's set.
+    override_token: Option<OverrideToken>,
+    // The blocks of the document while there are subscribers to their changes or an outline.
+    block_tree: Option<DocumentTree>,
+    outline: Option<Outline>,
+    suggestion_id: Option<SuggestionId>,
+    text_index: Mutex<Option<Arc<TextIndex>>>,
+    // The snapshot of the current revision, shared by the readers until the next change.
+    read_snapshot: Mutex<Option<ReadSnapshot>>,
+    // The content that the document replaced last, emptied, whose memory the next compose reuses.
+    spare_delta: RichTextDelta,
+    transclusion_provider: Option<Arc<dyn TransclusionProvider>>,
+    metrics: Option<OTMetricsRef>,
+    // How long the last compose and the last transform of the history took.
+    last_compose: Option<Duration>,
+    last_transform: Option<Duration>,
+    // The inline attributes set at a collapsed selection, with the position of the selection.
+    pending_attributes: Option<(usize, RichTextAttributes)>,
+    // Whether the changes must keep the newline at the end of the document.
+    trailing_newline: bool,
+    input_rules: InputRules,
+    composition: Option<Composition>,
+    // The text typed at the caret that isn't composed yet, when the typing is buffered.
+    typing: Option<TypingBuffer>,
+    default_attributes: RichTextAttributes,
+    segmenter: Arc<dyn Segmenter>,
+    // The opt-in work that each change feeds, e.g. the stats or the spell check.
+    observers: Observers,
+    exporters: Arc<ExporterRegistry>,
+    fork_point: Option<ForkPoint>,
+    replay: Option<Arc<Mutex<ReplayRecorder>>>,
+    // The time of the calls instead of the current time while a replay runs them.
+    clock: Option<i64>,
 }
 
 impl ClientDocument {
@@ -43,14 +195,246 @@ impl ClientDocument {
         Self::from_delta(C::initial_delta())
     }
 
+    /// Creates a document like [ClientDocument::new], with the settings of `config`.
+    pub fn with_config<C: InitialDocumentText>(config: DocumentConfig) -> Self {
+        let mut document = Self::new::<C>();
+        document.set_config(config);
+        document
+    }
+
+    /// Creates a document with the content of `delta`. If the content ends with a newline, the
+    /// changes that would remove it fail with
+    /// [ErrorCode::MissingTrailingNewline](crate::errors::ErrorCode::MissingTrailingNewline).
     pub fn from_delta(delta: RichTextDelta) -> Self {
+        let (notifier, _) = broadcast::channel(1000);
+        let (block_notifier, _) = broadcast::channel(1000);
+        let (content_change_notifier, _) = broadcast::channel(1000);
+        let (policy_notifier, _) = broadcast::channel(1000);
+        let (resync_notifier, _) = broadcast::channel(1000);
+        let (metadata_notifier, _) = broadcast::channel(1000);
+        let revision_log = RevisionLog::new(0, &delta);
+        let trailing_newline = ends_with_newline(&delta);
         ClientDocument {
             delta,
             history: History::new(),
             view: ViewExtensions::new(),
             last_edit_time: 0,
             notify: None,
+            engine: None,
+            engine_updates: vec![],
+            presence: Presence::new(),
+            annotations: Annotations::new(),
+            anchors: Anchors::new(),
+            decorations: Decorations::new(),
+            config: DocumentConfig::default(),
+            rev_id: 0,
+            revision_log,
+            pending_replay: PendingReplay::default(),
+            notifier,
+            block_notifier,
+            content_change_notifier,
+            policy_notifier,
+            resync_notifier,
+            metadata_notifier,
+            desync: None,
+            intents: VecDeque::new(),
+            metadata: Metadata::new(),
+            content_policies: ContentPolicies::default(),
+            embed_handlers: EmbedHandlers::default(),
+            override_token: None,
+            block_tree: None,
+            outline: None,
+            suggestion_id: None,
+            text_index: Mutex::new(None),
+            read_snapshot: Mutex::new(None),
+            spare_delta: RichTextDelta::new(),
+            transclusion_provider: None,
+            metrics: None,
+            last_compose: None,
+            last_transform: None,
+            pending_attributes: None,
+            trailing_newline,
+            input_rules: InputRules::default(),
+            composition: None,
+            typing: None,
+            default_attributes: RichTextAttributes::default(),
+            segmenter: Arc::new(UnicodeSegmenter()),
+            observers: Observers::default(),
+            exporters: Arc::new(ExporterRegistry::default()),
+            fork_point: None,
+            replay: None,
+            clock: None,
+        }
+    }
+
+    /// Reports the composes, the transforms and the growth of the history of the document to
+    /// `metrics`.
+    pub fn with_metrics(mut self, metrics: OTMetricsRef) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Creates a document that replicates its changes through `engine` instead of sending the
+    /// deltas to the server to be transformed.
+    pub fn from_engine(engine: DocumentEngine) -> Self {
+        let mut document = Self::from_delta(engine.snapshot());
+        document.engine = Some(engine);
+        document
+    }
+
+    /// Takes the updates produced by the engine for the local changes, in the order they should
+    /// be sent to the other replicas.
+    pub fn take_engine_updates(&mut self) -> Vec<Bytes> {
+        std::mem::take(&mut self.engine_updates)
+    }
+
+    /// Integrates the update that was produced by the engine of another replica and returns the
+    /// delta that was composed into the document.
+    pub fn apply_engine_update(&mut self, update: &[u8]) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let delta = match self.engine.as_mut() {
+            None => return Err(CollaborateError::internal().context("The document isn't backed by an engine")),
+            Some(engine) => engine.apply_remote(update)?,
+        };
+        let new_delta = self.compose_timed(&delta)?;
+        let inverted = delta.invert(&self.delta);
+        self.transform_history(&delta);
+        self.transform_positions(&delta);
+        self.update_delta(new_delta);
+        self.notify_change(&delta, inverted, DocumentEventSource::Remote, None);
+        Ok(delta)
+    }
+
+    /// Reopens the document from its snapshot and the history that was encoded by
+    /// [ClientDocument::encode_history] when it was closed. The document opens with an empty
+    /// history if `history` is empty or can't be restored, e.g. it was made on an older content.
+    pub fn restore(snapshot: &DocumentSnapshot, history: &[u8]) -> Result<Self, CollaborateError> {
+        let delta = snapshot.delta()?;
+        let mut document = Self::from_delta(delta);
+        document.rev_id = snapshot.rev_id;
+        document.revision_log = RevisionLog::new(snapshot.rev_id, &document.delta);
+        for entry in &snapshot.metadata {
+            document.metadata.merge(entry.clone());
+        }
+        if history.is_empty() {
+            return Ok(document);
+        }
+        match History::decode(history, &document.delta) {
+            Ok(history) => document.history = history,
+            Err(e) => tracing::warn!("Restore the history of the document failed: {}", e),
+        }
+        Ok(document)
+    }
+
+    /// Sets the attributes that the text without a value of its own has, e.g. the font, the
+    /// direction and the line height of the theme of the document. They aren't part of the
+    /// content: the editor renders them and [ClientDocument::attributes_in] reports them.
+    pub fn set_default_attributes(&mut self, attributes: RichTextAttributes) {
+        self.default_attributes = attributes;
+    }
+
+    pub fn default_attributes(&self) -> &RichTextAttributes {
+        &self.default_attributes
+    }
+
+    /// Encodes the default attributes, which are saved along the snapshot of the document and
+    /// read back by [ClientDocument::restore_default_attributes].
+    pub fn encode_default_attributes(&self) -> Result<Bytes, CollaborateError> {
+        let json = encode_json(PayloadKind::DefaultAttributes, &self.default_attributes)?;
+        Ok(Bytes::from(json))
+    }
+
+    pub fn restore_default_attributes(&mut self, bytes: &[u8]) -> Result<(), CollaborateError> {
+        let attributes: Option<RichTextAttributes> = decode_json(PayloadKind::DefaultAttributes, bytes)?;
+        self.default_attributes = attributes.unwrap_or_default();
+        Ok(())
+    }
+
+    /// Returns a copy of the document as `doc_id`, e.g. to duplicate a page. The copy starts at the
+    /// revision 0 with an empty history and remembers the revision it was forked at, see
+    /// [ClientDocument::fork_point]. It keeps the settings of the document, e.g. its config and
+    /// its default attributes, but not its subscribers.
+    pub fn fork(&self, doc_id: &str) -> Self {
+        let mut fork = Self::from_delta(self.delta.clone());
+        fork.set_config(DocumentConfig {
+            doc_id: doc_id.to_owned(),
+            ..self.config.clone()
+        });
+        fork.default_attributes = self.default_attributes.clone();
+        fork.input_rules = self.input_rules.clone();
+        if let Some(tracker) = self.observer::<MentionTracker>() {
+            fork.set_mention_resolver(tracker.resolver().clone());
+        }
+        fork.transclusion_provider = self.transclusion_provider.clone();
+        if let Some(tracker) = self.observer::<LinkTracker>() {
+            fork.set_link_index(tracker.link_index().clone());
+        }
+        fork.set_segmenter(self.segmenter.clone());
+        fork.fork_point = Some(ForkPoint {
+            doc_id: self.config.doc_id.clone(),
+            rev_id: self.rev_id,
+        });
+        fork
+    }
+
+    /// Returns where the document was forked from, `None` if it isn't a fork.
+    pub fn fork_point(&self) -> Option<&ForkPoint> {
+        self.fork_point.as_ref()
+    }
+
+    /// Encodes the undo history, which is saved along the snapshot of the document to be restored
+    /// by [ClientDocument::restore].
+    pub fn encode_history(&self) -> Result<Bytes, CollaborateError> {
+        self.history.encode(&self.delta)
+    }
+
+    /// Encodes the state that the document keeps in memory, for the document to be freed and
+    /// restored as it was by [ClientDocument::restore_state], e.g. while the app is in the
+    /// background: the revisions of its log, which may not be synced yet, its tags, its history,
+    /// its anchors, its metadata and its default attributes. The typed text is composed first.
+    pub fn encode_state(&mut self) -> Result<Bytes, CollaborateError> {
+        self.flush_typing()?;
+        let base = &self.revision_log.snapshots()[0];
+        let mut revisions = vec![];
+        for revision in self.revision_log.revisions() {
+            let data: Bytes = revision.clone().try_into()?;
+            revisions.push(data.to_vec());
+        }
+        let state = DocumentState {
+            rev_id: self.rev_id,
+            base_rev_id: base.rev_id,
+            base: base.delta()?,
+            revisions,
+            tags: self.revision_log.encode_tags()?.to_vec(),
+            history: self.encode_history()?.to_vec(),
+            anchors: self.anchors.clone(),
+            metadata: self.metadata.entries().map(MetadataEntryData::from).collect(),
+            default_attributes: self.default_attributes.clone(),
+        };
+        Ok(Bytes::from(encode_json(PayloadKind::State, &state)?))
+    }
+
+    /// Restores the document from the state that was encoded by [ClientDocument::encode_state].
+    /// The content is rebuilt from the revisions of the state, so it doesn't need the store.
+    pub fn restore_state(bytes: &[u8]) -> Result<Self, CollaborateError> {
+        let state: DocumentState = decode_json(PayloadKind::State, bytes)?;
+        let mut revision_log = RevisionLog::new(state.base_rev_id, &state.base);
+        let mut delta = state.base;
+        for data in state.revisions {
+            let revision = Revision::try_from(Bytes::from(data))?;
+            delta = delta.compose(&RichTextDelta::from_bytes(&revision.delta_data)?)?;
+            revision_log.push(revision);
+        }
+        revision_log.load_tags(&state.tags)?;
+
+        let mut document = Self::restore(&DocumentSnapshot::new(state.rev_id, &delta), &state.history)?;
+        document.revision_log = revision_log;
+        document.anchors = state.anchors;
+        for entry in state.metadata {
+            document.metadata.merge(entry.into());
         }
+        document.default_attributes = state.default_attributes;
+        Ok(document)
     }
 
     pub fn from_json(json: &str) -> Result<Self, CollaborateError> {
@@ -67,160 +451,2900 @@ impl ClientDocument {
     }
 
     pub fn to_plain_string(&self) -> String {
-        self.delta.apply("").unwrap()
+        // The document always applies to the empty text, it's built from inserts only.
+        self.delta.apply("").unwrap_or_else(|e| {
+            tracing::error!("Document {} has an invalid delta: {}", self.config.doc_id, e);
+            String::new()
+        })
     }
 
     pub fn delta(&self) -> &RichTextDelta {
         &self.delta
     }
 
+    /// The md5 of the json of the document, which is the checksum of the revisions. The server
+    /// hashes the documents the same way, so it must not change.
     pub fn md5(&self) -> String {
         let bytes = self.to_bytes();
         format!("{:x}", md5::compute(bytes))
     }
 
-    pub fn set_notify(&mut self, notify: mpsc::UnboundedSender<()>) {
-        self.notify = Some(notify);
+    /// The md5 of the canonical json of the document, see [Delta::to_canonical_json], which
+    /// doesn't depend on the order the attributes are encoded in, e.g. to compare two documents.
+    pub fn canonical_md5(&self) -> String {
+        let json = self.delta.to_canonical_json();
+        format!("{:x}", md5::compute(json))
     }
 
-    pub fn set_delta(&mut self, data: RichTextDelta) {
-        tracing::trace!("document: {}", data.to_json());
-        self.delta = data;
+    /// The selections of the other participants, transformed by every change of the document.
+    pub fn presence(&self) -> &Presence {
+        &self.presence
+    }
 
-        match &self.notify {
-            None => {}
-            Some(notify) => {
-                let _ = notify.send(());
-            }
+    pub fn presence_mut(&mut self) -> &mut Presence {
+        &mut self.presence
+    }
+
+    /// Subscribes to the changes of the document. The changes are delivered in the order they
+    /// were applied, the receiver lags behind if it doesn't keep up.
+    pub fn subscribe(&self) -> broadcast::Receiver<DocumentEvent> {
+        self.notifier.subscribe()
+    }
+
+    /// Subscribes to the changes of the blocks of the document, e.g. a heading that was inserted
+    /// or a paragraph that became a list item. The blocks are kept up to date while there are
+    /// subscribers, so only the blocks that a change touches are parsed again.
+    pub fn subscribe_blocks(&mut self) -> broadcast::Receiver<BlockEvent> {
+        if self.block_tree.is_none() {
+            self.block_tree = Some(DocumentTree::parse(&self.delta));
         }
+        self.block_notifier.subscribe()
     }
 
-    pub fn compose_delta(&mut self, delta: RichTextDelta) -> Result<(), CollaborateError> {
-        tracing::trace!("{} compose {}", &self.delta.to_json(), delta.to_json());
-        let composed_delta = self.delta.compose(&delta)?;
-        let mut undo_delta = delta.invert(&self.delta);
+    /// Subscribes to the changes of the text of the document in the form of the language server
+    /// protocol, e.g. for a grammar checker. The changes are only computed while there are
+    /// subscribers.
+    pub fn subscribe_content_changes(&self) -> broadcast::Receiver<ContentChangeEvent> {
+        self.content_change_notifier.subscribe()
+    }
 
-        let now = chrono::Utc::now().timestamp_millis() as usize;
-        if now - self.last_edit_time < RECORD_THRESHOLD {
-            if let Some(last_delta) = self.history.undo() {
-                tracing::trace!("compose previous change");
-                tracing::trace!("current = {}", undo_delta);
-                tracing::trace!("previous = {}", last_delta);
-                undo_delta = undo_delta.compose(&last_delta)?;
-            }
-        } else {
-            self.last_edit_time = now;
+    /// Subscribes to the text of the changes that broke the content policies of the document, see
+    /// [ClientDocument::set_content_policies].
+    pub fn subscribe_policy_violations(&self) -> broadcast::Receiver<PolicyEvent> {
+        self.policy_notifier.subscribe()
+    }
+
+    /// Subscribes to the divergences of the document from the server, see
+    /// [ClientDocument::reset_from_snapshot].
+    pub fn subscribe_resync(&self) -> broadcast::Receiver<ResyncRequired> {
+        self.resync_notifier.subscribe()
+    }
+
+    /// Subscribes to the changes of the metadata of the document, see [ClientDocument::set_metadata].
+    pub fn subscribe_metadata(&self) -> broadcast::Receiver<MetadataEvent> {
+        self.metadata_notifier.subscribe()
+    }
+
+    /// Sets the policies that check the text of the changes. The local changes are rejected or
+    /// redacted as the policies say, the changes of the other participants are only reported to
+    /// [ClientDocument::subscribe_policy_violations], the server enforces the policies on them.
+    /// There are none by default.
+    pub fn set_content_policies(&mut self, policies: ContentPolicies) {
+        self.content_policies = policies;
+    }
+
+    pub fn content_policies(&self) -> &ContentPolicies {
+        &self.content_policies
+    }
+
+    /// Lets the local changes write and format the locked text, the text with the
+    /// [RichTextAttribute::Protected] attribute, and lock or unlock text, until
+    /// [ClientDocument::lock_regions]. Without the token, the changes that touch the locked text
+    /// fail with [ErrorCode::RegionLocked](crate::errors::ErrorCode::RegionLocked).
+    pub fn unlock_regions(&mut self, token: OverrideToken) {
+        self.override_token = Some(token);
+    }
+
+    pub fn lock_regions(&mut self) {
+        self.override_token = None;
+    }
+
+    /// The intervals of the locked text, in order.
+    pub fn protected_intervals(&self) -> Vec<Interval> {
+        protected_intervals(&self.delta)
+    }
+
+    /// Makes the changes of a language server protocol client, with their columns in utf16 code
+    /// units, as one local change. See [content_changes_to_delta].
+    pub fn apply_content_changes(&mut self, changes: &[ContentChange]) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let delta = content_changes_to_delta(&self.delta, changes, ColumnUnit::Utf16)?;
+        self.compose_delta(delta.clone())?;
+        Ok(delta)
+    }
+
+    /// The blocks of the document, if there are subscribers to their changes.
+    pub fn blocks(&self) -> Option<&DocumentTree> {
+        self.block_tree.as_ref()
+    }
+
+    /// Returns the headings of the document, e.g. for the table of contents. The outline is made
+    /// on the first call, and then kept up to date with the changes of the blocks.
+    pub fn outline(&mut self) -> Vec<OutlineEntry> {
+        let anchors = &mut self.anchors;
+        let delta = &self.delta;
+        let tree = self.block_tree.get_or_insert_with(|| DocumentTree::parse(delta));
+        self.outline
+            .get_or_insert_with(|| Outline::new(tree, anchors))
+            .entries(&self.anchors)
+    }
+
+    pub(crate) fn notifier(&self) -> broadcast::Sender<DocumentEvent> {
+        self.notifier.clone()
+    }
+
+    pub fn rev_id(&self) -> i64 {
+        self.rev_id
+    }
+
+    /// Takes a snapshot of the current content and adds it to the revision log, which allows the
+    /// revisions before it to be compacted.
+    pub fn snapshot(&mut self) -> DocumentSnapshot {
+        self.flush_typing_or_log();
+        let snapshot = self.current_snapshot();
+        if self.revision_log.latest_snapshot().rev_id != snapshot.rev_id {
+            self.revision_log.add_snapshot(snapshot.clone());
         }
+        self.pending_replay = PendingReplay::default();
+        snapshot
+    }
 
-        if !undo_delta.is_empty() {
-            tracing::trace!("add history delta: {}", undo_delta);
-            self.history.record(undo_delta);
+    /// The revision `rev_id` of the log, with the checksums of the content of the document after
+    /// it, to send it or to persist it.
+    pub fn revision(&self, rev_id: i64) -> Result<Revision, CollaborateError> {
+        let revision = self.revision_log.get_revision(rev_id).ok_or_else(|| {
+            CollaborateError::record_not_found().context(format!("The revision {} doesn't exist", rev_id))
+        })?;
+        let delta = match rev_id == self.rev_id {
+            true => self.delta.clone(),
+            false => self.revision_log.delta_at(rev_id)?,
+        };
+        Ok(with_checksums(revision.clone(), &delta))
+    }
+
+    /// The revisions of the log after `rev_id`, to send them or to persist them. Only the last one
+    /// gets the checksums of the content of the document, the ones before it would have to
+    /// replay the log, and they are verified along with it.
+    pub fn revisions_after(&self, rev_id: i64) -> Vec<Revision> {
+        let mut revisions = self
+            .revision_log
+            .revisions()
+            .iter()
+            .filter(|revision| revision.rev_id > rev_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(last) = revisions.pop() {
+            revisions.push(match last.rev_id == self.rev_id {
+                true => with_checksums(last, &self.delta),
+                false => last,
+            });
         }
+        revisions
+    }
 
-        self.set_delta(composed_delta);
-        Ok(())
+    /// Whether the snapshot policy of the document says it's time for a snapshot, see
+    /// [DocumentConfig::snapshot_policy].
+    pub fn is_snapshot_due(&self) -> bool {
+        self.config.snapshot_policy.is_due(&self.pending_replay)
     }
 
-    pub fn insert<T: ToString>(&mut self, index: usize, data: T) -> Result<RichTextDelta, CollaborateError> {
-        let text = data.to_string();
-        let interval = Interval::new(index, index);
-        let _ = validate_interval(&self.delta, &interval)?;
-        let delta = self.view.insert(&self.delta, &text, interval)?;
-        self.compose_delta(delta.clone())?;
+    /// The revisions since the last snapshot, which opening the document would replay.
+    pub fn pending_replay(&self) -> PendingReplay {
+        self.pending_replay
+    }
+
+    // The document was opened by replaying `pending_replay` on the stored snapshot.
+    pub(crate) fn set_pending_replay(&mut self, pending_replay: PendingReplay) {
+        self.pending_replay = pending_replay;
+    }
+
+    /// Returns the content of the document at `rev_id`, as long as the revision wasn't compacted.
+    pub fn at_revision(&self, rev_id: i64) -> Result<RichTextDelta, CollaborateError> {
+        if rev_id == self.rev_id {
+            return Ok(self.delta.clone());
+        }
+        self.revision_log.delta_at(rev_id)
+    }
+
+    /// Returns the word, character and paragraph count of the document. The stats are kept up to
+    /// date by the [StatsTracker] and sent along with the [DocumentEvent] once it observes the
+    /// document, they are counted on each call otherwise.
+    pub fn stats(&self) -> DocumentStats {
+        match self.observer::<StatsTracker>() {
+            Some(tracker) => tracker.stats(),
+            None => StatsTracker::new(&self.delta, self.segmenter.as_ref()).stats(),
+        }
+    }
+
+    /// Adds the observer, in place of the one of its type, see [DocumentObserver].
+    pub fn add_observer<O: DocumentObserver>(&mut self, observer: O) {
+        self.notify_observers(|observers, document| observers.add(document, observer));
+    }
+
+    /// Returns the observer of the type, e.g. the
+    /// [Authorship](crate::client_document::authorship::Authorship) or the
+    /// [TombstoneStore](crate::client_document::tombstone::TombstoneStore) of the document.
+    pub fn observer<O: DocumentObserver>(&self) -> Option<&O> {
+        self.observers.get::<O>()
+    }
+
+    pub fn observer_mut<O: DocumentObserver>(&mut self) -> Option<&mut O> {
+        self.observers.get_mut::<O>()
+    }
+
+    pub fn remove_observer<O: DocumentObserver>(&mut self) -> Option<O> {
+        self.observers.remove::<O>(&self.config.doc_id)
+    }
+
+    /// Returns the sizes and the timings of the internals of the document, for a debug panel.
+    pub fn debug_stats(&self) -> DebugStats {
+        DebugStats {
+            doc_id: self.config.doc_id.clone(),
+            rev_id: self.rev_id,
+            len: self.delta.utf16_target_len,
+            op_count: self.delta.ops.len(),
+            attribute_run_count: self
+                .observer::<AttributeRuns>()
+                .map(|runs| runs.run_count())
+                .unwrap_or(0),
+            undo_depth: self.history.undo_len(),
+            redo_depth: self.history.redo_len(),
+            branch_count: self.history.branches().len(),
+            last_compose_micros: micros(self.last_compose),
+            last_transform_micros: micros(self.last_transform),
+            document_bytes: self.delta.memory_usage() + self.spare_delta.memory_usage(),
+            history_bytes: self.history.memory_usage(),
+        }
+    }
+
+    pub fn revision_log(&self) -> &RevisionLog {
+        &self.revision_log
+    }
+
+    pub fn revision_log_mut(&mut self) -> &mut RevisionLog {
+        &mut self.revision_log
+    }
+
+    /// Plays the revisions of the document back at the pace they were made, from its oldest
+    /// snapshot on.
+    pub fn playback(&self) -> Playback<'_> {
+        Playback::new(&self.revision_log)
+    }
+
+    /// Starts to record the calls that change the document in a [ReplayLog], which replays them
+    /// into the same document, e.g. to find out how a user's document got corrupted. The document
+    /// should start recording when it's opened, the replay starts with an empty history.
+    pub fn start_recording(&mut self) {
+        let log = ReplayLog {
+            doc_id: self.config.doc_id.clone(),
+            author_id: self.config.author_id.clone(),
+            device_id: self.config.device_id.clone(),
+            max_len: self.config.max_len,
+            suggestion_mode: self.config.editing.suggestion_mode,
+            auto_link: self.config.editing.auto_link,
+            normalize: self.config.editing.normalize.clone(),
+            boundary_policy: self.config.editing.boundary_policy,
+            history_branching: self.config.history.branching,
+            rev_id: self.rev_id,
+            document: self.delta.clone(),
+            entries: vec![],
+        };
+        self.replay = Some(ReplayRecorder::new(log));
+    }
+
+    /// Stops the recording and returns what was recorded.
+    pub fn stop_recording(&mut self) -> Option<ReplayLog> {
+        let recorder = self.replay.take()?;
+        match Arc::try_unwrap(recorder) {
+            Ok(recorder) => Some(recorder.into_inner().into_log()),
+            Err(recorder) => Some(recorder.lock().log().clone()),
+        }
+    }
+
+    /// Returns what was recorded so far, see [ClientDocument::start_recording].
+    pub fn replay_log(&self) -> Option<ReplayLog> {
+        self.replay.as_ref().map(|recorder| recorder.lock().log().clone())
+    }
+
+    /// Composes the edits of the bundle that was exported on another machine, see
+    /// [RevisionLog::export_bundle]. The edits made here after the base of the bundle are kept.
+    pub fn import_bundle(&mut self, bytes: Bytes) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let delta = self.revision_log.import_bundle(bytes)?;
+        if delta.is_noop() {
+            return Ok(delta);
+        }
+        let undo_delta = delta.invert(&self.delta);
+        self.compose_delta_with_undo(delta.clone(), undo_delta, DocumentEventSource::Remote, None)?;
         Ok(delta)
     }
 
-    pub fn delete(&mut self, interval: Interval) -> Result<RichTextDelta, CollaborateError> {
-        let _ = validate_interval(&self.delta, &interval)?;
-        debug_assert!(!interval.is_empty());
-        let delete = self.view.delete(&self.delta, interval)?;
-        if !delete.is_empty() {
-            let _ = self.compose_delta(delete.clone())?;
+    pub fn config(&self) -> &DocumentConfig {
+        &self.config
+    }
+
+    /// Sets the config of the document. Its history and editing configs take effect right away,
+    /// like [ClientDocument::set_history_config] and [ClientDocument::set_editing_config] do.
+    pub fn set_config(&mut self, config: DocumentConfig) {
+        self.set_history_config(config.history.clone());
+        self.set_editing_config(config.editing.clone());
+        self.config = config;
+    }
+
+    /// Sets how the history keeps the changes it undoes. The entries over the new capacity are
+    /// dropped.
+    pub fn set_history_config(&mut self, history: HistoryConfig) {
+        self.history.set_capacity(history.capacity);
+        self.history.set_max_entry_size(history.max_entry_size);
+        self.history.set_compression(history.compression);
+        self.history.set_check_invariants(history.check_invariants);
+        self.history.set_branching(history.branching);
+        self.config.history = history;
+    }
+
+    /// Sets how the changes of the local user are made, like the setter of each of its settings.
+    pub fn set_editing_config(&mut self, editing: EditingConfig) {
+        if editing.suggestion_mode != self.config.editing.suggestion_mode {
+            self.set_suggestion_mode(editing.suggestion_mode);
+        }
+        if editing.auto_link != self.config.editing.auto_link {
+            self.set_auto_link(editing.auto_link);
+        }
+        if editing.normalize != self.config.editing.normalize {
+            self.set_normalize_config(editing.normalize);
+        }
+        if editing.boundary_policy != self.config.editing.boundary_policy {
+            self.set_boundary_policy(editing.boundary_policy);
+        }
+        if let Err(e) = self.set_typing_buffer(editing.typing) {
+            tracing::error!("Compose the typed text failed: {}", e);
         }
-        Ok(delete)
     }
 
-    pub fn format(
+    /// Annotates the text in `interval`, e.g. with a comment. The annotation keeps covering the
+    /// same text while the document changes, and is marked as orphaned if the text is deleted.
+    pub fn create_annotation<T: ToString>(
         &mut self,
         interval: Interval,
-        attribute: RichTextAttribute,
-    ) -> Result<RichTextDelta, CollaborateError> {
+        payload: T,
+    ) -> Result<AnnotationId, CollaborateError> {
         let _ = validate_interval(&self.delta, &interval)?;
-        tracing::trace!("format {} with {}", interval, attribute);
-        let format_delta = self.view.format(&self.delta, attribute, interval).unwrap();
-        self.compose_delta(format_delta.clone())?;
-        Ok(format_delta)
+        Ok(self.annotations.create(interval, payload))
     }
 
-    pub fn replace<T: ToString>(&mut self, interval: Interval, data: T) -> Result<RichTextDelta, CollaborateError> {
-        let _ = validate_interval(&self.delta, &interval)?;
-        let mut delta = RichTextDelta::default();
-        let text = data.to_string();
-        if !text.is_empty() {
-            delta = self.view.insert(&self.delta, &text, interval)?;
-            self.compose_delta(delta.clone())?;
-        }
+    pub fn remove_annotation(&mut self, id: AnnotationId) -> Option<Annotation> {
+        self.annotations.remove(id)
+    }
 
-        if !interval.is_empty() {
-            let delete = self.delete(interval)?;
-            delta = delta.compose(&delete)?;
-        }
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
 
-        Ok(delta)
+    /// Creates an anchor at `index` that follows the text around it while the document changes,
+    /// see [ClientDocument::resolve_anchor]. The bias says where the anchor goes when text is
+    /// inserted at it.
+    pub fn create_anchor(&mut self, index: usize, bias: AnchorBias) -> Result<AnchorId, CollaborateError> {
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        Ok(self.anchors.create(index, bias))
     }
 
-    pub fn can_undo(&self) -> bool {
-        self.history.can_undo()
+    /// Returns the position of the anchor in the current document.
+    pub fn resolve_anchor(&self, id: AnchorId) -> Option<usize> {
+        self.anchors.get(id).map(|anchor| anchor.index)
     }
 
-    pub fn can_redo(&self) -> bool {
-        self.history.can_redo()
+    pub fn remove_anchor(&mut self, id: AnchorId) -> Option<Anchor> {
+        self.anchors.remove(id)
     }
 
-    pub fn undo(&mut self) -> Result<UndoResult, CollaborateError> {
-        match self.history.undo() {
-            None => Err(CollaborateError::undo().context("Undo stack is empty")),
-            Some(undo_delta) => {
-                let (new_delta, inverted_delta) = self.invert(&undo_delta)?;
-                self.set_delta(new_delta);
-                self.history.add_redo(inverted_delta);
-                Ok(UndoResult { delta: undo_delta })
-            }
+    pub fn anchors(&self) -> &Anchors {
+        &self.anchors
+    }
+
+    /// Splits the document before the line `line`, e.g. to turn a heading and the text under it
+    /// into a page of its own. The first document has the lines before it, the second one the line
+    /// and the ones after it, with their block attributes, anchors and annotations. An annotation
+    /// across the split is in both, with the same id. Both documents have the config of this one
+    /// and no history. Fails if the line is the first one or isn't in the document.
+    pub fn split_at_line(&mut self, line: usize) -> Result<(ClientDocument, ClientDocument), CollaborateError> {
+        self.flush_typing()?;
+        let index = match line {
+            0 => None,
+            line => self.lines().nth(line - 1).map(|line| line.interval.end),
         }
+        .filter(|index| *index < self.delta.utf16_target_len)
+        .ok_or_else(|| {
+            CollaborateError::out_of_bound().context(format!("The document can't be split at the line {}", line))
+        })?;
+
+        let (first, second) = split_document(&self.delta, index);
+        let (first_anchors, second_anchors) = self.anchors.split_at(index);
+        let (first_annotations, second_annotations) = self.annotations.split_at(index);
+        let part = |delta: RichTextDelta, anchors: Anchors, annotations: Annotations| {
+            let mut document = ClientDocument::from_delta(delta);
+            document.set_config(self.config.clone());
+            document.anchors = anchors;
+            document.annotations = annotations;
+            document
+        };
+        Ok((
+            part(first, first_anchors, first_annotations),
+            part(second, second_anchors, second_annotations),
+        ))
     }
 
-    pub fn redo(&mut self) -> Result<UndoResult, CollaborateError> {
-        match self.history.redo() {
-            None => Err(CollaborateError::redo()),
-            Some(redo_delta) => {
-                let (new_delta, inverted_delta) = self.invert(&redo_delta)?;
-                self.set_delta(new_delta);
-                self.history.add_undo(inverted_delta);
-                Ok(UndoResult { delta: redo_delta })
+    /// Appends the content of `other` with its block attributes, anchors and annotations, as one
+    /// local change that can be undone, e.g. to merge a page back into the one it was split from.
+    pub fn append(&mut self, other: &ClientDocument) -> Result<AppendedDocument, CollaborateError> {
+        self.flush_typing()?;
+        let offset = self.delta.utf16_target_len;
+        let mut delta = RichTextDelta::new();
+        delta.retain(offset, RichTextAttributes::default());
+        other.delta.ops.iter().for_each(|op| delta.add(op.clone()));
+        let delta = self.compose_local_delta(delta)?;
+        let anchors = self.anchors.append(&other.anchors, offset);
+        let annotations = self.annotations.append(&other.annotations, offset);
+        Ok(AppendedDocument {
+            delta,
+            anchors,
+            annotations,
+        })
+    }
+
+    /// Decorates the text in `interval` with `attributes` for a while, e.g. to highlight the
+    /// matches of a search. The decoration isn't a change of the document, see [Decorations].
+    pub fn add_decoration(
+        &mut self,
+        kind: DecorationKind,
+        interval: Interval,
+        attributes: RichTextAttributes,
+    ) -> Result<DecorationId, CollaborateError> {
+        let _ = validate_interval(&self.delta, &interval)?;
+        Ok(self.decorations.add(kind, interval, attributes))
+    }
+
+    pub fn remove_decoration(&mut self, id: DecorationId) -> Option<Decoration> {
+        self.decorations.remove(id)
+    }
+
+    pub fn clear_decorations(&mut self, kind: &DecorationKind) {
+        self.decorations.clear_kind(kind);
+    }
+
+    /// The decorations that overlap `interval`, ordered by their start, for rendering it.
+    pub fn decorations_in(&self, interval: Interval) -> Vec<&Decoration> {
+        self.decorations.in_interval(interval)
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Sets the value of `key` in the metadata of the document. The change is recorded as a
+    /// revision of the document, after the changes of the content made before it.
+    pub fn set_metadata(&mut self, key: &str, value: &str) -> Result<(), CollaborateError> {
+        self.write_metadata(key, value, false)
+    }
+
+    pub fn remove_metadata(&mut self, key: &str) -> Result<(), CollaborateError> {
+        self.write_metadata(key, "", true)
+    }
+
+    fn write_metadata(&mut self, key: &str, value: &str, is_removed: bool) -> Result<(), CollaborateError> {
+        self.flush_typing()?;
+        let entry = MetadataEntry {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            is_removed,
+            timestamp: self.now(),
+            device_id: self.config.device_id.clone(),
+        };
+        let author_id = self.config.author_id.clone();
+        let device_id = self.config.device_id.clone();
+        self.apply_metadata(vec![entry], DocumentEventSource::Local, &author_id, &device_id);
+        Ok(())
+    }
+
+    // Merges the entries into the metadata and records them as a revision, which retains the
+    // whole content.
+    fn apply_metadata(
+        &mut self,
+        entries: Vec<MetadataEntry>,
+        source: DocumentEventSource,
+        author_id: &str,
+        device_id: &str,
+    ) {
+        let mut changed = vec![];
+        for entry in &entries {
+            if self.metadata.merge(entry.clone()) {
+                changed.push(entry.clone());
             }
         }
+        let base_rev_id = self.rev_id;
+        self.rev_id += 1;
+        let delta = RichTextDeltaBuilder::new().retain(self.delta.utf16_target_len).build();
+        let revision = Revision::new(
+            &self.config.doc_id,
+            base_rev_id,
+            self.rev_id,
+            delta.to_bytes(),
+            author_id,
+            String::new(),
+        )
+        .with_author(author_id, device_id)
+        .with_timestamp(self.now())
+        .with_metadata(entries);
+        self.pending_replay.record(revision.delta_data.len(), Duration::ZERO);
+        self.revision_log.push(revision);
+        self.bound_revision_log();
+
+        if !changed.is_empty() && self.metadata_notifier.receiver_count() > 0 {
+            let _ = self.metadata_notifier.send(MetadataEvent {
+                entries: changed,
+                source,
+                rev_id: self.rev_id,
+            });
+        }
     }
 
-    pub fn is_empty(&self) -> bool {
-        // The document is empty if its text is equal to the initial text.
-        self.delta == NewlineDoc::initial_delta()
+    pub fn decorations(&self) -> &Decorations {
+        &self.decorations
     }
-}
 
-impl ClientDocument {
-    fn invert(&self, delta: &RichTextDelta) -> Result<(RichTextDelta, RichTextDelta), CollaborateError> {
-        // c = a.compose(b)
-        // d = b.invert(a)
-        // a = c.compose(d)
-        let new_delta = self.delta.compose(delta)?;
-        let inverted_delta = delta.invert(&self.delta);
-        Ok((new_delta, inverted_delta))
+    /// In suggestion mode the text inserted and deleted by the local user isn't changed right away.
+    /// The changes are kept in the document as suggestions, see [ClientDocument::accept_suggestion]
+    /// and [ClientDocument::reject_suggestion]. The formatting isn't affected by the mode.
+    pub fn set_suggestion_mode(&mut self, suggestion_mode: bool) {
+        self.flush_typing_or_log();
+        let _call = self.record(|| ReplayCall::SetSuggestionMode { suggestion_mode });
+        self.config.editing.suggestion_mode = suggestion_mode;
+        self.suggestion_id = None;
+    }
+
+    pub fn is_suggestion_mode(&self) -> bool {
+        self.config.editing.suggestion_mode
+    }
+
+    pub fn suggestions(&self) -> Vec<(Interval, Suggestion)> {
+        suggestions(&self.delta)
+    }
+
+    /// Applies the suggestion: the suggested text is kept and the text suggested to be deleted is
+    /// deleted. Returns the delta that was composed into the document.
+    pub fn accept_suggestion(&mut self, id: &str) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        self.resolve_suggestion(id, true)
+    }
+
+    /// Discards the suggestion: the suggested text is deleted and the text suggested to be deleted
+    /// is kept. Returns the delta that was composed into the document.
+    pub fn reject_suggestion(&mut self, id: &str) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        self.resolve_suggestion(id, false)
+    }
+
+    /// Sets the rules that format a line when its start is typed, e.g. `"# "` into a header. The
+    /// document has the [InputRules::default] ones, [InputRules::empty] turns them off.
+    pub fn set_input_rules(&mut self, input_rules: InputRules) {
+        self.input_rules = input_rules;
+    }
+
+    pub fn input_rules(&self) -> &InputRules {
+        &self.input_rules
+    }
+
+    /// Sets whether the urls are linked once they are typed, when the space or the newline after
+    /// them is typed. It's on by default.
+    pub fn set_auto_link(&mut self, auto_link: bool) {
+        self.flush_typing_or_log();
+        let _call = self.record(|| ReplayCall::SetAutoLink { auto_link });
+        self.config.editing.auto_link = auto_link;
+    }
+
+    /// Sets how the text is normalized as it's typed with [ClientDocument::insert], e.g. with the
+    /// smart quotes. The changes of the normalization are part of the insert, they are undone
+    /// with it. It's off by default.
+    pub fn set_normalize_config(&mut self, config: NormalizeConfig) {
+        self.flush_typing_or_log();
+        let _call = self.record(|| ReplayCall::SetNormalizeConfig { config: config.clone() });
+        self.config.editing.normalize = config;
+    }
+
+    /// Sets which lines the block formats of [ClientDocument::format], [ClientDocument::format_block]
+    /// and the like apply to when the interval ends at the start of a line, see [BoundaryPolicy].
+    /// It's [BoundaryPolicy::ClipToLine] by default.
+    pub fn set_boundary_policy(&mut self, policy: BoundaryPolicy) {
+        let _call = self.record(|| ReplayCall::SetBoundaryPolicy { policy });
+        self.config.editing.boundary_policy = policy;
+    }
+
+    /// Sets the resolver that names the mentions and is told when they are inserted or deleted.
+    pub fn set_mention_resolver(&mut self, resolver: Arc<dyn MentionResolver>) {
+        self.add_observer(MentionTracker::new(resolver));
+    }
+
+    pub fn insert_mention(&mut self, index: usize, mention: Mention) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        let delta = RichTextDeltaBuilder::new()
+            .retain(index)
+            .insert_embed(mention.to_embed())
+            .build();
+        self.compose_local_delta(delta)
+    }
+
+    pub fn mentions(&self) -> Vec<(usize, Mention)> {
+        mentions(&self.delta)
+    }
+
+    /// Inserts an empty table of `rows` by `columns` cells at `index`.
+    pub fn insert_table(
+        &mut self,
+        index: usize,
+        rows: usize,
+        columns: usize,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        let delta = RichTextDeltaBuilder::new()
+            .retain(index)
+            .insert_embed(Table::new(rows, columns).to_embed())
+            .build();
+        self.compose_local_delta(delta)
+    }
+
+    /// Changes the table at `index`. The delta replaces the embed of the table, the changes of
+    /// other users to the same table are merged with [crate::client_document::table::transform].
+    pub fn edit_table(&mut self, index: usize, op: &TableOp) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let mut table = table_at(&self.delta, index)
+            .ok_or_else(|| CollaborateError::out_of_bound().context(format!("There is no table at {}", index)))?;
+        let _ = table.apply(op)?;
+        let delta = RichTextDeltaBuilder::new()
+            .retain(index)
+            .delete(1)
+            .insert_embed(table.to_embed())
+            .build();
+        self.compose_local_delta(delta)
+    }
+
+    /// Registers the handler of the embeds of its kind, once it passes the properties of
+    /// [check_embed_handler](crate::client_document::embed_handler::check_embed_handler).
+    pub fn register_embed_handler(&mut self, handler: Arc<dyn EmbedHandler>) -> Result<(), CollaborateError> {
+        self.embed_handlers.register(handler)
+    }
+
+    pub fn embed_handlers(&self) -> &EmbedHandlers {
+        &self.embed_handlers
+    }
+
+    pub fn insert_embed(&mut self, index: usize, embed: Embed) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        let delta = RichTextDeltaBuilder::new().retain(index).insert_embed(embed).build();
+        self.compose_local_delta(delta)
+    }
+
+    /// Changes the embed at `index` with the handler of its kind. The delta replaces the embed,
+    /// the changes of other users to the same embed are merged with [EmbedHandlers::transform].
+    pub fn edit_embed(&mut self, index: usize, change: &EmbedData) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let embed = embed_at(&self.delta, index)
+            .ok_or_else(|| CollaborateError::out_of_bound().context(format!("There is no embed at {}", index)))?;
+        let embed = self.embed_handlers.apply(embed, change)?;
+        let delta = RichTextDeltaBuilder::new()
+            .retain(index)
+            .delete(1)
+            .insert_embed(embed)
+            .build();
+        self.compose_local_delta(delta)
+    }
+
+    /// Returns the name of the mentioned user or page, if a resolver is set.
+    pub fn resolve_mention(&self, mention: &Mention) -> Option<String> {
+        self.observer::<MentionTracker>()?.resolver().resolve(mention)
+    }
+
+    /// Sets the provider that resolves the content of the transclusions.
+    pub fn set_transclusion_provider(&mut self, provider: Arc<dyn TransclusionProvider>) {
+        self.transclusion_provider = Some(provider);
+    }
+
+    /// Shares the reverse links of the workspace with the document. The transclusions of the
+    /// document are added to the index, and the index is told about the changes of the document
+    /// so the documents that link to it are invalidated.
+    pub fn set_link_index(&mut self, link_index: Arc<LinkIndex>) {
+        self.add_observer(LinkTracker::new(link_index));
+    }
+
+    /// Inserts the content of `transclusion.interval` of the document `transclusion.doc_id` at
+    /// `index`, as a reference that is resolved when it's displayed.
+    pub fn insert_transclusion(
+        &mut self,
+        index: usize,
+        transclusion: Transclusion,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        if transclusion.doc_id == self.config.doc_id {
+            return Err(CollaborateError::internal().context("The document can't transclude itself"));
+        }
+        let delta = RichTextDeltaBuilder::new()
+            .retain(index)
+            .insert_embed(transclusion.to_embed())
+            .build();
+        self.compose_local_delta(delta)
+    }
+
+    pub fn transclusions(&self) -> Vec<(usize, Transclusion)> {
+        transclusions(&self.delta)
+    }
+
+    /// Returns the content of the transclusion, if a provider is set.
+    pub fn resolve_transclusion(&self, transclusion: &Transclusion) -> Option<RichTextDelta> {
+        self.transclusion_provider.as_ref()?.resolve(transclusion)
+    }
+
+    /// Tells the document where the selection of the local user is. The attributes that were set
+    /// at a collapsed selection are dropped once the selection moves.
+    pub fn set_selection(&mut self, interval: Interval) {
+        self.flush_typing_or_log();
+        let _call = self.record(|| ReplayCall::SetSelection {
+            start: interval.start,
+            end: interval.end,
+        });
+        let is_moved = match &self.pending_attributes {
+            None => false,
+            Some((index, _)) => !interval.is_empty() || interval.start != *index,
+        };
+        if is_moved {
+            self.pending_attributes = None;
+        }
+    }
+
+    /// The attributes that the next insert at the collapsed selection takes.
+    pub fn pending_attributes(&self) -> Option<&RichTextAttributes> {
+        self.pending_attributes.as_ref().map(|(_, attributes)| attributes)
+    }
+
+    pub fn set_notify(&mut self, notify: mpsc::UnboundedSender<()>) {
+        self.notify = Some(notify);
+    }
+
+    /// Replaces the content of the document. The revision log continues from a snapshot of the
+    /// new content, because the change can't be expressed as a revision.
+    pub fn set_delta(&mut self, data: RichTextDelta) {
+        self.flush_typing_or_log();
+        let _call = self.record(|| ReplayCall::SetDelta { delta: data.clone() });
+        let replaced = match self.observers.is_empty() {
+            true => None,
+            false => Some(self.delta.clone()),
+        };
+        self.update_delta(data);
+        self.trailing_newline = ends_with_newline(&self.delta);
+        self.decorations.clear();
+        self.rev_id += 1;
+        self.revision_log.add_snapshot(self.current_snapshot());
+        if let Some(replaced) = replaced {
+            self.notify_observers(|observers, document| observers.did_reset(document, &replaced));
+        }
+        self.notify_blocks(DocumentEventSource::Local, |tree, document| tree.replace(document));
+        if let (Some(outline), Some(tree)) = (self.outline.as_mut(), &self.block_tree) {
+            // The anchors of the headings weren't moved by a delta.
+            outline.rebuild(tree, &mut self.anchors);
+        }
+    }
+
+    pub fn compose_delta(&mut self, delta: RichTextDelta) -> Result<(), CollaborateError> {
+        self.compose_checked_delta(delta).map(|_| ())
+    }
+
+    /// Shows what composing `delta` would change, e.g. before a merge or an import is accepted.
+    /// The document, its history and its revision are left as they are. Fails like the compose
+    /// would if `delta` doesn't apply to the document. The text buffered by the typing isn't part
+    /// of the document that `delta` is previewed on.
+    pub fn preview_apply(&self, delta: &RichTextDelta) -> Result<DiffView, CollaborateError> {
+        delta.validate_against(self.delta.utf16_target_len)?;
+        let new_delta = self.delta.compose_with(delta, &self.config.compose)?;
+        self.check_trailing_newline(&new_delta)?;
+        self.check_max_len(&new_delta)?;
+        Ok(DiffViewBuilder::new(&self.delta, &new_delta).change(delta).build())
+    }
+
+    /// Composes the delta that was received from the other participants of the document. A delta
+    /// that doesn't fit the document means that it diverged from the server, it waits for a resync
+    /// then, see [ClientDocument::reset_from_snapshot].
+    pub fn compose_remote_delta(&mut self, delta: RichTextDelta) -> Result<(), CollaborateError> {
+        self.flush_typing()?;
+        self.check_synced()?;
+        let _call = self.record(|| ReplayCall::ComposeRemoteDelta { delta: delta.clone() });
+        tracing::trace!("{} compose remote {}", &self.delta.to_json(), delta.to_json());
+        if let Err(e) = delta.validate_against(self.delta.utf16_target_len) {
+            return Err(self.desync(e.into()));
+        }
+        self.transform_intents(&delta)?;
+        let violations = self.content_policies.check(&delta);
+        let undo_delta = delta.invert(&self.delta);
+        self.compose_delta_with_undo(delta, undo_delta, DocumentEventSource::Remote, None)?;
+        self.notify_violations(violations, DocumentEventSource::Remote);
+        Ok(())
+    }
+
+    /// Composes the revision that was received from the other participants of the document. The
+    /// change is attributed to the author of the revision. Fails with
+    /// [ErrorCode::DocumentIntegrity](crate::errors::ErrorCode::DocumentIntegrity) if the document
+    /// doesn't have the author's content after the revision.
+    pub fn compose_remote_revision(&mut self, revision: &Revision) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        self.check_synced()?;
+        if revision.is_metadata() {
+            self.apply_metadata(
+                revision.metadata.clone(),
+                DocumentEventSource::Remote,
+                &revision.author_id,
+                &revision.device_id,
+            );
+            return Ok(RichTextDelta::default());
+        }
+        let delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+        if let Err(e) = delta.validate_against(self.delta.utf16_target_len) {
+            return Err(self.desync(e.into()));
+        }
+        // The revision is checked before it's applied, so a document that diverged from the
+        // author's is left as it was.
+        if let Err(e) = verify_content_md5(revision, &self.delta.compose_with(&delta, &self.config.compose)?) {
+            let error = CollaborateError::from(e);
+            self.report_error(&error);
+            return Err(self.desync(error));
+        }
+        // The revision of the first intent is the change that the server made of it, the other
+        // revisions go before the intents.
+        match self.is_own_intent(revision) {
+            true => {
+                let _ = self.intents.pop_front();
+            }
+            false => self.transform_intents(&delta)?,
+        }
+        let violations = self.content_policies.check(&delta);
+        let undo_delta = delta.invert(&self.delta);
+        let author = Some((revision.author_id.as_str(), revision.device_id.as_str()));
+        self.compose_delta_with_undo(delta.clone(), undo_delta, DocumentEventSource::Remote, author)?;
+        self.notify_violations(violations, DocumentEventSource::Remote);
+        Ok(delta)
+    }
+
+    /// The local changes that wait for the server in the authoritative sync mode, the first one
+    /// first, see [SyncMode::Authoritative]. They aren't in the content of the document until the
+    /// server applies them.
+    pub fn intents(&self) -> &VecDeque<RichTextDelta> {
+        &self.intents
+    }
+
+    /// Applies the first intent, which the server acknowledged. The server applies the revision
+    /// that follows its latest one as it is, so the intent is the change that it made. Returns
+    /// the delta that was composed into the document.
+    pub fn acknowledge_intent(&mut self) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let intent = self
+            .intents
+            .pop_front()
+            .ok_or_else(|| CollaborateError::internal().context("No change waits for the server"))?;
+        let undo_delta = intent.invert(&self.delta);
+        let (author_id, device_id) = (self.config.author_id.clone(), self.config.device_id.clone());
+        self.compose_delta_with_undo(
+            intent.clone(),
+            undo_delta,
+            DocumentEventSource::Remote,
+            Some((&author_id, &device_id)),
+        )?;
+        Ok(intent)
+    }
+
+    /// Whether the document diverged from the server and waits for its content, see
+    /// [ClientDocument::subscribe_resync].
+    pub fn is_desynced(&self) -> bool {
+        self.desync.is_some()
+    }
+
+    /// Resyncs the document that diverged with `delta`, the content of the server, in answer to
+    /// the [ResyncRequired] of the local revision `rev_id`. The changes made here since the
+    /// document diverged are rebased on the content of the server, so the local edits that the
+    /// server doesn't have yet are kept, as are the history, the anchors and the annotations.
+    /// Returns the rebased local edits, the change of the content of the server to send it.
+    pub fn reset_from_snapshot(
+        &mut self,
+        delta: RichTextDelta,
+        rev_id: i64,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let base = match &self.desync {
+            None => return Err(CollaborateError::internal().context("The document isn't waiting for a resync")),
+            Some((desync_rev_id, _)) if *desync_rev_id != rev_id => {
+                return Err(CollaborateError::revision_conflict().context(format!(
+                    "The snapshot is the answer to the revision {}, but the document diverged at {}",
+                    rev_id, desync_rev_id
+                )));
+            }
+            Some((_, base)) => base,
+        };
+
+        // Both the local edits and the content of the server are changes of the content the
+        // document had when it diverged.
+        let len = base.utf16_target_len;
+        let mut local = diff(base, &self.delta);
+        let mut server = diff(base, &delta);
+        pad(&mut local, len);
+        pad(&mut server, len);
+        let (local_prime, server_prime) = local.transform_with(&server, Priority::Right, &self.config.compose)?;
+
+        self.desync = None;
+        let _call = self.record(|| ReplayCall::ComposeRemoteDelta {
+            delta: server_prime.clone(),
+        });
+        let undo_delta = server_prime.invert(&self.delta);
+        self.compose_delta_with_undo(server_prime, undo_delta, DocumentEventSource::Remote, None)?;
+        Ok(local_prime)
+    }
+
+    fn check_synced(&self) -> Result<(), CollaborateError> {
+        match &self.desync {
+            None => Ok(()),
+            Some((rev_id, _)) => Err(CollaborateError::revision_conflict().context(format!(
+                "The document diverged from the server at the revision {}, it waits for a resync",
+                rev_id
+            ))),
+        }
+    }
+
+    // Marks the document as diverged from the server because of `error`, and asks for the
+    // content of the server.
+    fn desync(&mut self, error: CollaborateError) -> CollaborateError {
+        tracing::warn!("{} diverged from the server: {}", self.config.doc_id, error);
+        let md5 = content_md5(&self.delta);
+        self.desync = Some((self.rev_id, self.delta.clone()));
+        let _ = self.resync_notifier.send(ResyncRequired {
+            rev_id: self.rev_id,
+            md5,
+            reason: error.msg.clone(),
+        });
+        error
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "compose",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, source = ?source),
+            err
+        )
+    )]
+    fn compose_delta_with_undo(
+        &mut self,
+        delta: RichTextDelta,
+        undo_delta: RichTextDelta,
+        source: DocumentEventSource,
+        author: Option<(&str, &str)>,
+    ) -> Result<(), CollaborateError> {
+        self.compose_recorded_delta(delta, undo_delta, source, author, UndoRecording::Merge)
+    }
+
+    // Composes `delta`, whose inverse is `undo_delta`. The local changes are recorded in the
+    // history as `recording` says.
+    fn compose_recorded_delta(
+        &mut self,
+        delta: RichTextDelta,
+        mut undo_delta: RichTextDelta,
+        source: DocumentEventSource,
+        author: Option<(&str, &str)>,
+        recording: UndoRecording,
+    ) -> Result<(), CollaborateError> {
+        let composed_delta = self.compose_timed(&delta)?;
+        self.check_trailing_newline(&composed_delta)?;
+        self.check_max_len(&composed_delta)?;
+        let inverted = undo_delta.clone();
+        self.history.check_inversion(&self.delta, &composed_delta, &inverted);
+
+        // Only the local changes are undone, the history is moved over the changes of the others.
+        if source == DocumentEventSource::Remote {
+            self.transform_history(&delta);
+        } else if let UndoRecording::Collect(collected) = recording {
+            *collected = undo_delta.compose(collected)?;
+        } else {
+            let mut rev_id = Some(self.rev_id);
+            let mut selection = SelectionChange::from_delta(&delta);
+            let now = self.now();
+            let separate = matches!(recording, UndoRecording::Separate);
+            if !separate && self.is_recording(now) {
+                match self.history.undo() {
+                    None => {}
+                    Some(HistoryEntry {
+                        rev_id: last_rev_id,
+                        delta: Some(last_delta),
+                        selection: last_selection,
+                    }) => {
+                        tracing::trace!("compose previous change");
+                        tracing::trace!("current = {}", undo_delta);
+                        tracing::trace!("previous = {}", last_delta);
+                        undo_delta = undo_delta.compose(&last_delta)?;
+                        rev_id = last_rev_id;
+                        // The merged entry restores the selection before the previous change.
+                        selection = match (last_selection, selection) {
+                            (Some(last), Some(current)) => Some(SelectionChange {
+                                before: last.before,
+                                after: current.after,
+                            }),
+                            (last, current) => last.or(current),
+                        };
+                    }
+                    // The entry that keeps a revision can't be composed, the change gets an entry
+                    // of its own.
+                    Some(entry) => self.history.push_undo(entry),
+                }
+            } else {
+                self.last_edit_time = now;
+            }
+
+            if !undo_delta.is_empty() {
+                tracing::trace!("add history delta: {}", undo_delta);
+                self.history.record(undo_delta, rev_id, selection);
+                self.history_did_grow();
+            }
+            if separate {
+                self.last_edit_time = 0;
+            }
+        }
+
+        self.apply_composed(&delta, composed_delta, inverted, source, author)
+    }
+
+    // Fails if `composed_delta`, the new content of the document, lost the newline at its end.
+    fn check_trailing_newline(&self, composed_delta: &RichTextDelta) -> Result<(), CollaborateError> {
+        if self.trailing_newline && !ends_with_newline(composed_delta) {
+            return Err(CollaborateError::missing_trailing_newline());
+        }
+        Ok(())
+    }
+
+    // Fails if `composed_delta`, the new content of the document, is longer than the limit of the
+    // document and than the content it replaces.
+    fn check_max_len(&self, composed_delta: &RichTextDelta) -> Result<(), CollaborateError> {
+        let len = composed_delta.utf16_target_len;
+        match self.config.max_len {
+            Some(max_len) if len > max_len && len > self.delta.utf16_target_len => {
+                Err(QuotaExceededError { max_len, len }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Makes `composed_delta`, the document composed with `delta`, the content of the document.
+    fn apply_composed(
+        &mut self,
+        delta: &RichTextDelta,
+        composed_delta: RichTextDelta,
+        inverted: RichTextDelta,
+        source: DocumentEventSource,
+        author: Option<(&str, &str)>,
+    ) -> Result<(), CollaborateError> {
+        let _ = self.apply_to_engine(delta)?;
+        self.transform_positions(delta);
+        self.update_delta(composed_delta);
+        self.notify_change(delta, inverted, source, author);
+        Ok(())
+    }
+
+    /// Makes the change of `command` with the method of the document that does it, e.g.
+    /// [ClientDocument::insert] for [EditCommand::Insert], and returns what it did.
+    pub fn handle(&mut self, command: EditCommand) -> Result<EditResponse, CollaborateError> {
+        let mut selection = None;
+        let delta = match command {
+            EditCommand::Insert { index, text } => self.insert(index, text)?,
+            EditCommand::TypeText { index, text } => {
+                self.type_text(index, text)?;
+                RichTextDelta::default()
+            }
+            EditCommand::Delete { start, end } => self.delete(Interval::new(start, end))?,
+            EditCommand::Replace { start, end, text } => self.replace(Interval::new(start, end), text)?,
+            EditCommand::Format { start, end, attributes } => {
+                self.format_with(Interval::new(start, end), attributes)?
+            }
+            EditCommand::ToggleFormat { start, end, attributes } => {
+                let mut delta = RichTextDelta::default();
+                for (key, value) in attributes.iter() {
+                    let attribute = RichTextAttribute::new(key.clone(), value.clone());
+                    delta = delta.compose(&self.toggle_format(Interval::new(start, end), attribute)?)?;
+                }
+                delta
+            }
+            EditCommand::ToggleCodeBlock { start, end, language } => {
+                self.toggle_code_block(Interval::new(start, end), language.as_deref())?
+            }
+            EditCommand::ToggleCheckbox { line } => self.toggle_checkbox(line)?,
+            EditCommand::ComposeDelta { delta } => self.compose_checked_delta(delta)?,
+            EditCommand::SetSelection { start, end } => {
+                self.set_selection(Interval::new(start, end));
+                RichTextDelta::default()
+            }
+            EditCommand::Undo => {
+                let result = self.undo()?;
+                selection = Some((result.selection.start, result.selection.end));
+                result.delta
+            }
+            EditCommand::Redo => {
+                let result = self.redo()?;
+                selection = Some((result.selection.start, result.selection.end));
+                result.delta
+            }
+            EditCommand::SetMetadata { key, value } => {
+                self.set_metadata(&key, &value)?;
+                RichTextDelta::default()
+            }
+            EditCommand::RemoveMetadata { key } => {
+                self.remove_metadata(&key)?;
+                RichTextDelta::default()
+            }
+        };
+        Ok(EditResponse {
+            delta,
+            rev_id: self.rev_id,
+            selection,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "insert"),
+            err
+        )
+    )]
+    pub fn insert<T: ToString>(&mut self, index: usize, data: T) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let text = data.to_string();
+        let _call = self.record(|| ReplayCall::Insert {
+            index,
+            text: text.clone(),
+        });
+        let interval = Interval::new(index, index);
+        let _ = validate_interval(&self.delta, &interval)?;
+        // The text is inserted where the trailing spaces that it trims started.
+        let normalized = normalize_input(&self.delta, index, &text, &self.config.editing.normalize);
+        let (at, text) = (index - normalized.trimmed, normalized.text);
+        let mut delta = match normalized.trimmed {
+            0 => self.view.insert(&self.delta, &text, interval)?,
+            trimmed => {
+                let trim = RichTextDeltaBuilder::new().retain(at).delete(trimmed).build();
+                let insert = self
+                    .view
+                    .insert(&self.delta.compose(&trim)?, &text, Interval::new(at, at))?;
+                trim.compose(&insert)?
+            }
+        };
+        if let Some((pending_index, attributes)) = self.pending_attributes.take() {
+            if pending_index == index {
+                delta = with_attributes(delta, &attributes)?;
+            }
+        }
+        let delta = self.compose_local_delta(delta)?;
+        // The text that a content policy redacted isn't the text that was typed.
+        let is_redacted = delta
+            .ops
+            .iter()
+            .any(|op| op.get_attributes().contains_key(&RichTextAttributeKey::Redacted));
+        if self.config.editing.suggestion_mode || is_redacted {
+            return Ok(delta);
+        }
+        let follow_up = match self.input_rules.apply(&self.delta, at, &text) {
+            None if self.config.editing.auto_link => link_before(&self.delta, at, &text),
+            follow_up => follow_up,
+        };
+        match follow_up {
+            None => Ok(delta),
+            Some(follow_up) => {
+                // The format that the text triggered is an undo entry of its own, undoing it
+                // brings back the text as it was typed.
+                self.last_edit_time = 0;
+                let follow_up = self.compose_local_delta(follow_up)?;
+                self.last_edit_time = 0;
+                Ok(delta.compose(&follow_up)?)
+            }
+        }
+    }
+
+    /// Starts the composition of an input method at `index`, see [Composition]. The composition
+    /// that wasn't committed is dropped.
+    pub fn begin_composition(&mut self, index: usize) -> Result<(), CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        self.composition = Some(Composition::new(index));
+        Ok(())
+    }
+
+    /// Replaces the provisional text of the composition. The document doesn't change.
+    pub fn update_composition<T: ToString>(&mut self, data: T) -> Result<(), CollaborateError> {
+        match self.composition.as_mut() {
+            None => Err(CollaborateError::record_not_found().context("There is no composition to update")),
+            Some(composition) => {
+                composition.text = data.to_string();
+                Ok(())
+            }
+        }
+    }
+
+    /// Inserts the text of the composition like [ClientDocument::insert] and ends the composition.
+    /// The returned delta is the only change that the composition makes, it's empty if there is no
+    /// composition or its text is empty.
+    pub fn commit_composition(&mut self) -> Result<RichTextDelta, CollaborateError> {
+        match self.composition.take() {
+            Some(composition) if !composition.text.is_empty() => self.insert(composition.index, composition.text),
+            _ => Ok(RichTextDelta::default()),
+        }
+    }
+
+    /// Ends the composition without inserting its text.
+    pub fn cancel_composition(&mut self) {
+        self.composition = None;
+    }
+
+    pub fn composition(&self) -> Option<&Composition> {
+        self.composition.as_ref()
+    }
+
+    /// Buffers the text typed with [ClientDocument::type_text] as `config` says, or composes each
+    /// keystroke right away if `config` is `None`, which composes the text that is buffered.
+    pub fn set_typing_buffer(&mut self, config: Option<TypingConfig>) -> Result<(), CollaborateError> {
+        self.config.editing.typing = config;
+        self.flush_typing()?;
+        Ok(())
+    }
+
+    /// Types `text` at `index` like [ClientDocument::insert], but the text typed at the end of the
+    /// text typed before it is buffered and composed as one insert, instead of composing a delta per
+    /// keystroke. The buffer is composed when it's full or old, see [TypingConfig], before the other
+    /// changes of the document and before a keystroke that isn't typed at its end.
+    ///
+    /// The reads of the document don't see the buffered text, see [ClientDocument::typed_delta].
+    /// The text that an input rule, an auto link or the suggestion mode could change is inserted
+    /// right away, so the document ends up as if each keystroke was inserted.
+    pub fn type_text<T: ToString>(&mut self, index: usize, data: T) -> Result<(), CollaborateError> {
+        let text = data.to_string();
+        let config = match &self.config.editing.typing {
+            None => return self.insert(index, text).map(|_| ()),
+            Some(config) => config.clone(),
+        };
+        if text.is_empty() {
+            return Ok(());
+        }
+        let is_appended = match &self.typing {
+            None => true,
+            Some(buffer) => {
+                let elapsed = self.now().saturating_sub(buffer.started_at);
+                buffer.end() == index && (elapsed as u128) < config.max_delay.as_millis()
+            }
+        };
+        if !is_appended {
+            self.flush_typing()?;
+        }
+        let may_buffer = !self.config.editing.suggestion_mode
+            && self.composition.is_none()
+            && self.pending_attributes.is_none()
+            && !text.contains(EMBED_CHAR)
+            && !text.chars().any(char::is_whitespace);
+        if !may_buffer {
+            return self.insert(index, text).map(|_| ());
+        }
+
+        let mut buffer = match self.typing.take() {
+            Some(buffer) => buffer,
+            None => {
+                let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+                if self.override_token.is_none() {
+                    let delta = RichTextDeltaBuilder::new().retain(index).insert(&text).build();
+                    let _ = check_protection(&self.delta, &delta)?;
+                }
+                let line_start = line_at(&self.delta, index).map(|line| line.start).unwrap_or(index);
+                TypingBuffer::new(index, line_start, self.now())
+            }
+        };
+        buffer.text.push_str(&text);
+        // The keystroke that may complete the trigger of an input rule is composed, so the rule
+        // applies like it does to the keystrokes that are inserted.
+        let is_due = buffer.len() >= config.max_len || self.input_rules.may_trigger(buffer.line_start, buffer.end());
+        self.typing = Some(buffer);
+        if is_due {
+            self.flush_typing()?;
+        }
+        Ok(())
+    }
+
+    /// Composes the text buffered by [ClientDocument::type_text], and returns the delta that
+    /// inserted it. The delta is empty if there is no text.
+    pub fn flush_typing(&mut self) -> Result<RichTextDelta, CollaborateError> {
+        match self.typing.take() {
+            Some(buffer) if !buffer.is_empty() => self.insert(buffer.index, buffer.text),
+            _ => Ok(RichTextDelta::default()),
+        }
+    }
+
+    /// The text buffered by [ClientDocument::type_text].
+    pub fn pending_typing(&self) -> Option<&TypingBuffer> {
+        self.typing.as_ref()
+    }
+
+    /// When the buffered text should be composed, in milliseconds of the clock of the config, e.g.
+    /// for the owner of the document to flush it with a timer.
+    pub fn typing_deadline(&self) -> Option<i64> {
+        let max_delay = self.config.editing.typing.as_ref()?.max_delay;
+        self.typing
+            .as_ref()
+            .map(|buffer| buffer.started_at.saturating_add(max_delay.as_millis() as i64))
+    }
+
+    /// The document with the buffered text, as it will be once the text is composed. It's built on
+    /// each call, the reads that are repeated should flush the text instead.
+    pub fn typed_delta(&self) -> Result<Cow<'_, RichTextDelta>, CollaborateError> {
+        let buffer = match &self.typing {
+            Some(buffer) if !buffer.is_empty() => buffer,
+            _ => return Ok(Cow::Borrowed(&self.delta)),
+        };
+        let insert = self
+            .view
+            .insert(&self.delta, &buffer.text, Interval::new(buffer.index, buffer.index))?;
+        Ok(Cow::Owned(self.delta.compose(&insert)?))
+    }
+
+    /// Inserts a large chunk of text, e.g. a paste or an import, at `index`.
+    ///
+    /// Unlike [ClientDocument::insert], the text doesn't go through the insert extensions:
+    /// the delta is built directly, so the cost is linear in the size of the text instead of
+    /// the size of the document. It's checked like the other local changes, see
+    /// [ClientDocument::compose_delta]. The inserted text carries no attributes and the whole
+    /// insert is recorded as a single undo entry.
+    pub fn bulk_insert<T: ToString>(&mut self, index: usize, data: T) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let text = data.to_string();
+        let _call = self.record(|| ReplayCall::BulkInsert {
+            index,
+            text: text.clone(),
+        });
+        let interval = Interval::new(index, index);
+        let _ = validate_interval(&self.delta, &interval)?;
+        if text.is_empty() {
+            return Ok(RichTextDelta::default());
+        }
+
+        let delta = RichTextDeltaBuilder::new().retain(index).insert(&text).build();
+        self.compose_local(delta, UndoRecording::Separate)
+    }
+
+    /// Composes a delta that is given as a stream of operations, e.g. the paste or the import of a
+    /// large document, without building the whole delta in memory.
+    ///
+    /// The operations are composed in chunks of about [CHUNK_LEN], each chunk is a revision of its
+    /// own and `progress` is called after each of them with the length of the new content composed
+    /// so far. The chunks are checked like the other local changes and recorded as a single undo
+    /// entry. If a chunk can't be composed, the chunks before it stay in the document and can be
+    /// undone. In the [SyncMode::Authoritative] mode the chunks are queued as a single intent.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "apply_chunked"),
+            err
+        )
+    )]
+    pub fn apply_chunked<I, F>(&mut self, ops: I, mut progress: F) -> Result<(), CollaborateError>
+    where
+        I: IntoIterator<Item = Operation<RichTextAttributes>>,
+        F: FnMut(usize),
+    {
+        self.flush_typing()?;
+        if self.config.sync_mode == SyncMode::Authoritative {
+            // The intents apply to the content of the document, which the chunks before don't
+            // change, so the chunks are composed into one.
+            let mut delta = RichTextDelta::default();
+            ops.into_iter().for_each(|op| delta.add(op));
+            let _ = self.compose_local(delta.clone(), UndoRecording::Separate)?;
+            progress(delta.utf16_target_len);
+            return Ok(());
+        }
+
+        let rev_id = self.rev_id;
+        let mut undo_delta = RichTextDelta::default();
+        let mut chunk = RichTextDelta::default();
+        let mut chunk_len = 0;
+        let mut offset = 0;
+        let mut result = Ok(());
+        for op in ops {
+            chunk_len += op.len();
+            chunk.add(op);
+            if chunk_len >= CHUNK_LEN {
+                result = self.apply_chunk(&mut offset, std::mem::take(&mut chunk), &mut undo_delta);
+                if result.is_err() {
+                    break;
+                }
+                progress(offset);
+                chunk_len = 0;
+            }
+        }
+        if result.is_ok() && !chunk.is_empty() {
+            result = self.apply_chunk(&mut offset, chunk, &mut undo_delta);
+            if result.is_ok() {
+                progress(offset);
+            }
+        }
+
+        if !undo_delta.is_empty() {
+            // The chunks don't know the selection, the undo puts the caret after the change.
+            self.history.record(undo_delta, Some(rev_id), None);
+            self.history_did_grow();
+        }
+        // Keeps the chunks apart from the edits around them in the history.
+        self.last_edit_time = 0;
+        result
+    }
+
+    // Composes the operations of `chunk` at `offset`, which is moved past them, and adds their
+    // inverse to `undo_delta`.
+    fn apply_chunk(
+        &mut self,
+        offset: &mut usize,
+        chunk: RichTextDelta,
+        undo_delta: &mut RichTextDelta,
+    ) -> Result<(), CollaborateError> {
+        let mut delta = RichTextDeltaBuilder::new().retain(*offset).build();
+        let mut end = *offset;
+        for op in chunk.ops {
+            if !op.is_insert() {
+                end += op.len();
+            }
+            delta.add(op);
+        }
+        // The policies and the suggestion mode can change the length of the chunk, its end is
+        // moved by the delta that was composed.
+        let delta = self.compose_local(delta, UndoRecording::Collect(undo_delta))?;
+        *offset = delta.transform_position(end, false);
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "delete"),
+            err
+        )
+    )]
+    pub fn delete(&mut self, interval: Interval) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::Delete {
+            start: interval.start,
+            end: interval.end,
+        });
+        let _ = validate_interval(&self.delta, &interval)?;
+        debug_assert!(!interval.is_empty());
+        if self.trailing_newline && interval.end == self.delta.utf16_target_len {
+            return Err(CollaborateError::missing_trailing_newline()
+                .context(format!("{} deletes the newline at the end of the document", interval)));
+        }
+        let delete = self.view.delete(&self.delta, interval)?;
+        if delete.is_empty() {
+            return Ok(delete);
+        }
+        self.compose_local_delta(delete)
+    }
+
+    /// Returns the content of the document in `interval`, with its attributes and embeds, e.g. to
+    /// copy it to the clipboard. The attributes of a line are only in the slice if its newline is.
+    pub fn slice(&self, interval: Interval) -> Result<RichTextDelta, CollaborateError> {
+        let _ = validate_interval(&self.delta, &interval)?;
+        let mut slice = RichTextDelta::new();
+        for op in self.delta.ops_in(interval).filter_map(|op_slice| op_slice.to_op()) {
+            slice.add(op);
+        }
+        Ok(slice)
+    }
+
+    /// Inserts the content of `fragment` at `index` in one revision, e.g. to paste what
+    /// [ClientDocument::slice] copied. `strategy` decides the attributes of the pasted text, see
+    /// [PasteStrategy]. The line that `index` is in keeps its attributes for the text after
+    /// `index`.
+    pub fn insert_delta(
+        &mut self,
+        index: usize,
+        fragment: &RichTextDelta,
+        strategy: PasteStrategy,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &Interval::new(index, index))?;
+        let delta = paste_delta(&self.delta, index, fragment, strategy);
+        if delta.is_noop() {
+            return Ok(RichTextDelta::default());
+        }
+        self.compose_local_delta(delta)
+    }
+
+    /// Deletes the text in `interval` and returns its content, see [ClientDocument::slice], along
+    /// with the delta that deleted it.
+    pub fn cut(&mut self, interval: Interval) -> Result<(RichTextDelta, RichTextDelta), CollaborateError> {
+        self.flush_typing()?;
+        let slice = self.slice(interval)?;
+        if interval.is_empty() {
+            return Ok((slice, RichTextDelta::default()));
+        }
+        let delete = self.delete(interval)?;
+        Ok((slice, delete))
+    }
+
+    /// Formats the text in `interval`. If the interval is empty, the inline attributes are kept
+    /// until the next insert at the same position instead, see [ClientDocument::set_selection]. A
+    /// block attribute applies to the lines of the interval, see
+    /// [ClientDocument::set_boundary_policy].
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "format"),
+            err
+        )
+    )]
+    pub fn format(
+        &mut self,
+        interval: Interval,
+        attribute: RichTextAttribute,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::Format {
+            start: interval.start,
+            end: interval.end,
+            attributes: attribute.clone().into(),
+        });
+        let _ = validate_interval(&self.delta, &interval)?;
+        tracing::trace!("format {} with {}", interval, attribute);
+        if interval.is_empty() && attribute.scope == AttributeScope::Inline {
+            self.add_pending_attribute(interval.start, attribute);
+            return Ok(RichTextDelta::default());
+        }
+        let interval = match self.format_interval(&self.delta, interval, &attribute) {
+            None => return Ok(RichTextDelta::default()),
+            Some(interval) => interval,
+        };
+        let format_delta = self.view.format(&self.delta, attribute, interval)?;
+        let format_delta = self.with_renumbered_lists(format_delta)?;
+        self.compose_delta(format_delta.clone())?;
+        Ok(format_delta)
+    }
+
+    /// Toggles `attribute` on the text in `interval`, like the bold button of a toolbar: it's removed
+    /// if all the text has it with the same value, see [ClientDocument::attributes_in], and applied
+    /// to all the text otherwise, in a single delta.
+    pub fn toggle_format(
+        &mut self,
+        interval: Interval,
+        attribute: RichTextAttribute,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &interval)?;
+        let summary = self.attributes_in(interval);
+        let attribute = match summary.value(&attribute.key) == Some(&attribute.value) {
+            true => RichTextAttribute::new(attribute.key, RichTextAttributeValue(None)),
+            false => attribute,
+        };
+        self.format(interval, attribute)
+    }
+
+    /// Formats the text of all the intervals of `intervals` in a single delta, e.g. the selections
+    /// of a multi-cursor edit or all the matches of a search. The delta is one undo entry.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "format_intervals"),
+            err
+        )
+    )]
+    pub fn format_intervals(
+        &mut self,
+        intervals: &IntervalSet,
+        attribute: RichTextAttribute,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        for interval in intervals.iter() {
+            let _ = validate_interval(&self.delta, interval)?;
+        }
+        // The formats don't move the text, so each interval is formatted in the document as the
+        // intervals before it left it.
+        let mut document = self.delta.clone();
+        let mut format_delta = RichTextDelta::default();
+        for interval in intervals.iter() {
+            let interval = match self.format_interval(&document, *interval, &attribute) {
+                None => continue,
+                Some(interval) => interval,
+            };
+            let delta = self.view.format(&document, attribute.clone(), interval)?;
+            document = document.compose(&delta)?;
+            format_delta = format_delta.compose(&delta)?;
+        }
+        let mut format_delta = self.with_renumbered_lists(format_delta)?;
+        trim(&mut format_delta);
+        if !format_delta.is_empty() {
+            self.compose_delta(format_delta.clone())?;
+        }
+        Ok(format_delta)
+    }
+
+    /// Tells which attributes apply to all, some or none of `interval`. The default attributes of
+    /// the document and the ones that are pending at the collapsed selection are included.
+    pub fn attributes_in(&self, interval: Interval) -> AttributeSummary {
+        let mut summary = attributes_in(&self.delta, interval);
+        summary.apply_defaults(&self.default_attributes);
+        if let Some((index, attributes)) = &self.pending_attributes {
+            if interval.is_empty() && interval.start == *index {
+                summary.apply_pending(attributes);
+            }
+        }
+        summary
+    }
+
+    /// Formats the text in `interval` with all the `attributes` in a single revision, e.g. to make
+    /// the line a heading and clear the bold of the text. The attributes with an empty value are
+    /// removed, see [lib_ot::rich_text::AttributeBuilder::remove_attr].
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "format"),
+            err
+        )
+    )]
+    pub fn format_with(
+        &mut self,
+        interval: Interval,
+        attributes: RichTextAttributes,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &interval)?;
+        tracing::trace!("format {} with {}", interval, attributes);
+        let mut attributes = attributes.to_attributes();
+        // The block attributes are applied last, so the extensions see the inline format first.
+        attributes.sort_by_key(|attribute| (attribute.scope == AttributeScope::Block, attribute.key.to_string()));
+
+        let mut document = self.delta.clone();
+        let mut format_delta: Option<RichTextDelta> = None;
+        for attribute in attributes {
+            let interval = match self.format_interval(&document, interval, &attribute) {
+                None => continue,
+                Some(interval) => interval,
+            };
+            let delta = self.view.format(&document, attribute, interval)?;
+            document = document.compose(&delta)?;
+            format_delta = match format_delta {
+                None => Some(delta),
+                Some(format_delta) => Some(format_delta.compose(&delta)?),
+            };
+        }
+
+        let format_delta = self.with_renumbered_lists(format_delta.unwrap_or_default())?;
+        if !format_delta.is_empty() {
+            self.compose_delta(format_delta.clone())?;
+        }
+        Ok(format_delta)
+    }
+
+    /// Removes the inline attributes of the text in `interval` in one undoable change, except the
+    /// ones in `keep`, e.g. the links. The attributes of the lines aren't touched.
+    pub fn clear_format(
+        &mut self,
+        interval: Interval,
+        keep: &[RichTextAttributeKey],
+    ) -> Result<RichTextDelta, CollaborateError> {
+        let _ = validate_interval(&self.delta, &interval)?;
+        let mut format_delta = RichTextDeltaBuilder::new().retain(interval.start).build();
+        for slice in self.delta.ops_in(interval) {
+            let mut attributes = slice.op.get_attributes();
+            attributes.retain(|key, _| key.scope() == AttributeScope::Inline && !keep.contains(key));
+            format_delta.retain(slice.len(), attributes.removed_all());
+        }
+
+        trim(&mut format_delta);
+        if format_delta.is_empty() {
+            return Ok(format_delta);
+        }
+        self.compose_delta(format_delta.clone())?;
+        Ok(format_delta)
+    }
+
+    /// Sets the block attribute of the lines of `interval`, see
+    /// [ClientDocument::set_boundary_policy]. Unlike [ClientDocument::format], the kind of the line
+    /// is replaced, e.g. a heading that is made a list isn't a heading anymore, see
+    /// [BlockAttribute::to_line_attributes].
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "format_block"),
+            err
+        )
+    )]
+    pub fn format_block(
+        &mut self,
+        interval: Interval,
+        attribute: BlockAttribute,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        let _ = validate_interval(&self.delta, &interval)?;
+        tracing::trace!("format block {} with {}", interval, attribute.attribute());
+        let attributes = attribute.to_line_attributes();
+        let mut format_delta = RichTextDelta::new();
+        let mut offset = 0;
+        for line in lines_in(&self.delta, interval, self.config.editing.boundary_policy) {
+            format_delta.retain(line.end - 1 - offset, RichTextAttributes::default());
+            format_delta.retain(1, attributes.clone());
+            offset = line.end;
+        }
+
+        let format_delta = self.with_renumbered_lists(format_delta)?;
+        if !format_delta.is_empty() {
+            self.compose_delta(format_delta.clone())?;
+        }
+        Ok(format_delta)
+    }
+
+    /// Turns the lines that `interval` touches into a code block of `language`, or back into
+    /// paragraphs if they all are in a code block already. The text that is moved into the code
+    /// block loses its inline attributes.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "toggle_code_block"),
+            err
+        )
+    )]
+    pub fn toggle_code_block(
+        &mut self,
+        interval: Interval,
+        language: Option<&str>,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _ = validate_interval(&self.delta, &interval)?;
+        let lines = lines_in(&self.delta, interval, self.config.editing.boundary_policy);
+        let is_code_block = !lines.is_empty()
+            && lines.iter().all(|line| {
+                self.attributes_at(line.end - 1)
+                    .contains_key(&RichTextAttributeKey::CodeBlock)
+            });
+
+        let mut line_attributes = RichTextAttributes::new();
+        if is_code_block {
+            line_attributes.delete(&RichTextAttributeKey::CodeBlock);
+            line_attributes.delete(&RichTextAttributeKey::CodeLanguage);
+        } else {
+            line_attributes = BlockAttribute::try_from(RichTextAttribute::CodeBlock(true))?.to_line_attributes();
+            match language {
+                Some(language) => line_attributes.add(RichTextAttribute::CodeLanguage(language)),
+                None => line_attributes.delete(&RichTextAttributeKey::CodeLanguage),
+            }
+        }
+
+        let mut format_delta = RichTextDelta::new();
+        let mut offset = 0;
+        for line in lines {
+            format_delta.retain(line.start - offset, RichTextAttributes::default());
+            let text = Interval::new(line.start, line.end - 1);
+            if is_code_block {
+                format_delta.retain(text.size(), RichTextAttributes::default());
+            } else {
+                for slice in self.delta.ops_in(text) {
+                    let mut attributes = slice.op.get_attributes();
+                    attributes.retain(|key, _| key.scope() == AttributeScope::Inline);
+                    format_delta.retain(slice.len(), attributes.removed_all());
+                }
+            }
+            format_delta.retain(1, line_attributes.clone());
+            offset = line.end;
+        }
+
+        let format_delta = self.with_renumbered_lists(format_delta)?;
+        if !format_delta.is_empty() {
+            self.compose_delta(format_delta.clone())?;
+        }
+        Ok(format_delta)
+    }
+
+    /// Checks the checkbox of the `n`th line, or unchecks it if it's checked. The concurrent
+    /// toggles of the checkbox are resolved by the
+    /// [CheckboxConflictPolicy](lib_ot::rich_text::CheckboxConflictPolicy) of the compose config
+    /// of the document, see [DocumentConfig::compose](crate::client_document::DocumentConfig::compose).
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "toggle_checkbox"),
+            err
+        )
+    )]
+    pub fn toggle_checkbox(&mut self, n: usize) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let line = self
+            .lines()
+            .nth(n)
+            .ok_or_else(|| CollaborateError::out_of_bound().context(format!("There is no line {}", n)))?;
+        let checkbox = match line
+            .attributes
+            .get(&RichTextAttributeKey::List)
+            .and_then(|value| value.0.as_deref())
+        {
+            Some("checked") => RichTextAttribute::UnChecked(true),
+            Some("unchecked") => RichTextAttribute::Checked(true),
+            _ => {
+                return Err(CollaborateError::internal().context(format!("The line {} isn't a checkbox", n)));
+            }
+        };
+
+        let format_delta = RichTextDeltaBuilder::new()
+            .retain(line.interval.end - 1)
+            .retain_with_attributes(1, checkbox.into())
+            .build();
+        self.compose_delta(format_delta.clone())?;
+        Ok(format_delta)
+    }
+
+    /// Returns the lines of the document in order, with their text and their block attributes.
+    pub fn lines(&self) -> Lines<'_> {
+        Lines::new(&self.delta)
+    }
+
+    /// Returns the line that contains the character at `index`.
+    pub fn line_at(&self, index: usize) -> Option<DocumentLine> {
+        self.lines()
+            .take_while(|line| line.interval.start <= index)
+            .find(|line| line.interval.contains(index))
+    }
+
+    /// Returns the index of the `column` of the `line`, counted in `unit`, e.g. in utf16 code units
+    /// for Flutter. The lines are split at the newlines, see [TextPosition].
+    pub fn position_to_index(&self, line: usize, column: usize, unit: ColumnUnit) -> Option<usize> {
+        position_to_index(&self.delta, TextPosition::new(line, column), unit)
+    }
+
+    /// Returns the line and the column, counted in `unit`, of `index`.
+    pub fn index_to_position(&self, index: usize, unit: ColumnUnit) -> Option<TextPosition> {
+        index_to_position(&self.delta, index, unit)
+    }
+
+    /// Returns the direction that the line with the character at `index` is laid out in. The
+    /// lines without a direction take the one of the default attributes, see
+    /// [ClientDocument::set_default_attributes], and are left to right without one.
+    pub fn direction_at(&self, index: usize) -> Option<TextDirection> {
+        let default = TextDirection::from_attributes(&self.default_attributes).unwrap_or(TextDirection::Ltr);
+        self.line_at(index).map(|line| line_direction(&line, default))
+    }
+
+    /// Replaces the rules that split the text in words and sentences, e.g. with the ones of the
+    /// locale of the document. The word count is counted again with them.
+    pub fn set_segmenter(&mut self, segmenter: Arc<dyn Segmenter>) {
+        self.segmenter = segmenter;
+        self.notify_observers(|observers, document| observers.did_change_segmenter(document));
+    }
+
+    /// Checks the spelling of the document with `spell_check`, and then the words of every change,
+    /// see [SpellChecker].
+    pub fn set_spell_check(&mut self, spell_check: Arc<dyn SpellCheck>) {
+        self.add_observer(SpellChecker::new(spell_check));
+    }
+
+    pub fn remove_spell_check(&mut self) {
+        let _ = self.remove_observer::<SpellChecker>();
+    }
+
+    /// The intervals of the misspelled text, in order, which the editor underlines.
+    pub fn misspellings(&self) -> &[Interval] {
+        match self.observer::<SpellChecker>() {
+            None => &[],
+            Some(checker) => checker.misspellings(),
+        }
+    }
+
+    /// Reports the terms that each change removes and adds to `indexer`, which keeps the search
+    /// index of the document up to date, see [TermTracker]. The document must be indexed once
+    /// before.
+    pub fn set_term_indexer(&mut self, indexer: Arc<dyn TermIndexer>) {
+        self.add_observer(TermTracker::new(indexer));
+    }
+
+    /// Returns the words that overlap `interval`, see [words_in].
+    pub fn words_in(&self, interval: Interval) -> Vec<TextSegment> {
+        words_in(&self.delta, interval, self.segmenter.as_ref())
+    }
+
+    /// Returns the interval of the word at `index`, which a double click selects. The word that
+    /// starts at `index` is preferred over the one that ends there.
+    pub fn word_at(&self, index: usize) -> Option<Interval> {
+        let words = self.words_in(Interval::new(index, index));
+        words.last().map(|word| word.interval)
+    }
+
+    /// Returns the sentences that overlap `interval`, see [sentences_in].
+    pub fn sentences_in(&self, interval: Interval) -> Vec<TextSegment> {
+        sentences_in(&self.delta, interval, self.segmenter.as_ref())
+    }
+
+    /// Returns the interval of the `n`th line, including its newline.
+    pub fn interval_of_line(&self, n: usize) -> Option<Interval> {
+        self.lines().nth(n).map(|line| line.interval)
+    }
+
+    /// Replaces the text in `interval` with `data` in a single delta, so the change is one revision
+    /// and one undo entry. The new text takes the attributes of the first character it replaces.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "replace"),
+            err
+        )
+    )]
+    pub fn replace<T: ToString>(&mut self, interval: Interval, data: T) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let text = data.to_string();
+        let _call = self.record(|| ReplayCall::Replace {
+            start: interval.start,
+            end: interval.end,
+            text: text.clone(),
+        });
+        let _ = validate_interval(&self.delta, &interval)?;
+        if interval.is_empty() {
+            return self.insert(interval.start, text);
+        }
+        if text.is_empty() {
+            return self.delete(interval);
+        }
+
+        let attributes = self.attributes_at(interval.start);
+        let delete = self.view.delete(&self.delta, interval)?;
+        let insert = RichTextDeltaBuilder::new()
+            .retain(interval.start)
+            .insert_with_attributes(&text, attributes)
+            .build();
+        let delta = match delete.is_empty() {
+            true => insert,
+            false => delete.compose(&insert)?,
+        };
+        self.compose_local_delta(delta)
+    }
+
+    /// Returns the content of the current revision, which the readers can keep without holding
+    /// the document, see [ReadSnapshot]. The content is copied once per revision, the next
+    /// snapshots of the revision share it. The text that is buffered by the typing isn't in it.
+    pub fn read_snapshot(&self) -> ReadSnapshot {
+        let mut snapshot = self.read_snapshot.lock();
+        match &*snapshot {
+            Some(snapshot) if snapshot.rev_id() == self.rev_id => snapshot.clone(),
+            _ => snapshot
+                .insert(ReadSnapshot::new(
+                    &self.config.doc_id,
+                    self.rev_id,
+                    Arc::new(self.delta.clone()),
+                    self.text_index.lock().clone(),
+                ))
+                .clone(),
+        }
+    }
+
+    /// Returns the content of the current revision in chunks of whole lines that are at least
+    /// `chunk_size` long, see [ContentStream].
+    pub fn stream_content(&self, chunk_size: usize) -> ContentStream {
+        ContentStream::new(self.read_snapshot(), chunk_size)
+    }
+
+    /// Replaces the exporters of the document, e.g. with the registry of the
+    /// [DocumentManager](crate::client_document::manager::DocumentManager) that has the formats of
+    /// the other crates.
+    pub fn set_exporters(&mut self, exporters: Arc<ExporterRegistry>) {
+        self.exporters = exporters;
+    }
+
+    pub fn exporters(&self) -> &Arc<ExporterRegistry> {
+        &self.exporters
+    }
+
+    /// Exports the document with the exporter named `format`, see [ExporterRegistry::export].
+    pub fn export(&self, format: &str, options: &ExportOptions) -> Result<Vec<u8>, CollaborateError> {
+        self.exporters.export(format, &self.delta, options)
+    }
+
+    pub fn find(&self, pattern: &str, options: &FindOptions) -> Vec<Interval> {
+        self.text_index().find(pattern, options)
+    }
+
+    /// Searches the document with a regex, optionally only in the text that has some attributes.
+    /// The text of the document is indexed on the first search after a change.
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchMatch>, CollaborateError> {
+        self.text_index().search(query)
+    }
+
+    /// Replaces every occurrence of `pattern` in a single delta, which is undone at once. The
+    /// replacement takes the attributes of the first character of the text it replaces.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "replace_all"),
+            err
+        )
+    )]
+    pub fn replace_all(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        options: &FindOptions,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        let intervals = self.find(pattern, options);
+        if intervals.is_empty() {
+            return Ok(RichTextDelta::default());
+        }
+
+        let mut delta = RichTextDelta::default();
+        let mut offset = 0;
+        for interval in intervals {
+            let attributes = self.attributes_at(interval.start);
+            delta.retain(interval.start - offset, RichTextAttributes::default());
+            delta.insert(replacement, attributes);
+            delta.delete(interval.size());
+            offset = interval.end;
+        }
+
+        // Keeps the replacements apart from the edits around them in the history.
+        self.last_edit_time = 0;
+        let delta = self.compose_local_delta(delta)?;
+        self.last_edit_time = 0;
+        Ok(delta)
+    }
+
+    /// Replaces the text of each interval with its text in a single delta, which is undone at
+    /// once, e.g. the typing at several cursors. The intervals are positions in the document before
+    /// the edit, the offsets that the edits add or remove are taken into account. Fails if two
+    /// intervals overlap. The text takes the inline attributes of the text it replaces, or of the
+    /// character before it if the interval is empty.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "edit",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id, op = "edit_at_many"),
+            err
+        )
+    )]
+    pub fn edit_at_many(&mut self, edits: Vec<(Interval, &str)>) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let mut edits = edits;
+        edits.sort_by_key(|(interval, _)| interval.start);
+        for (interval, _) in &edits {
+            let _ = validate_interval(&self.delta, interval)?;
+        }
+        if let Some(pair) = edits.windows(2).find(|pair| pair[0].0.end > pair[1].0.start) {
+            return Err(CollaborateError::out_of_bound().context(format!("{} overlaps {}", pair[0].0, pair[1].0)));
+        }
+
+        let mut delta = RichTextDelta::default();
+        let mut offset = 0;
+        for (interval, text) in edits {
+            let mut attributes = match interval.is_empty() {
+                true if interval.start > 0 => self.attributes_at(interval.start - 1),
+                true => RichTextAttributes::default(),
+                false => self.attributes_at(interval.start),
+            };
+            attributes.retain(|key, _| key.scope() == AttributeScope::Inline);
+            delta.retain(interval.start - offset, RichTextAttributes::default());
+            delta.insert(text, attributes);
+            delta.delete(interval.size());
+            offset = interval.end;
+        }
+        trim(&mut delta);
+        if delta.is_empty() {
+            return Ok(delta);
+        }
+
+        // Keeps the edits apart from the edits around them in the history.
+        self.last_edit_time = 0;
+        let delta = self.compose_local_delta(delta)?;
+        self.last_edit_time = 0;
+        Ok(delta)
+    }
+
+    /// Brings the document in line with `text`, which was edited outside of the editor, e.g. in
+    /// the file mirror of the document. Only the difference is applied, so the formatting of the
+    /// text that wasn't touched is kept. Returns an empty delta if nothing changed.
+    pub fn sync_with_text(&mut self, text: &str) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::SyncWithText { text: text.to_owned() });
+        let mut text = text.replace("\r\n", NEW_LINE);
+        if !text.ends_with(NEW_LINE) {
+            text.push_str(NEW_LINE);
+        }
+
+        let delta: RichTextDelta = cal_diff(&self.to_plain_string(), &text);
+        if delta.ops.iter().all(|op| op.is_retain()) {
+            return Ok(RichTextDelta::default());
+        }
+        self.compose_delta(delta.clone())?;
+        Ok(delta)
+    }
+
+    /// Brings the document in line with `document`, e.g. a version of the file that was modified
+    /// outside of the editor. The same as [ClientDocument::sync_with_text] with the formatting
+    /// compared too, the change can be undone.
+    pub fn sync_with_delta(&mut self, document: &RichTextDelta) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let delta = diff(&self.delta, document);
+        if delta.is_empty() {
+            return Ok(delta);
+        }
+        self.compose_delta(delta.clone())?;
+        Ok(delta)
+    }
+
+    /// Brings the document in line with `markdown`, the Markdown mirror of the document that was
+    /// edited outside of the editor. The mirror is compared with the Markdown of the document, so
+    /// only what was edited in the file is changed, and what Markdown can't write, e.g. the colors,
+    /// the empty lines and the embeds other than the images, is kept. The change can be undone.
+    /// Returns an empty delta if nothing changed.
+    pub fn sync_with_markdown(&mut self, markdown: &str) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::SyncWithMarkdown {
+            markdown: markdown.to_owned(),
+        });
+        let (edited, _) = MarkdownImporter.import(markdown.as_bytes())?;
+        let mirror = MarkdownExporter.export(&self.delta, &ExportOptions::default())?;
+        let (shown, _) = MarkdownImporter.import(&mirror)?;
+
+        // The edit of the file is made on the document as it's shown in the file, then transformed
+        // against what the file doesn't show, which is added back.
+        let mut edit = diff(&shown, &edited);
+        if edit.is_empty() {
+            return Ok(edit);
+        }
+        let mut hidden = diff(&shown, &self.delta);
+        edit.retain(
+            shown.utf16_target_len - edit.utf16_base_len,
+            RichTextAttributes::default(),
+        );
+        hidden.retain(
+            shown.utf16_target_len - hidden.utf16_base_len,
+            RichTextAttributes::default(),
+        );
+        let (mut delta, _) = edit.transform(&hidden)?;
+        trim(&mut delta);
+        if delta.is_empty() {
+            return Ok(delta);
+        }
+
+        // Keeps the edit of the file apart from the edits around it in the history.
+        self.last_edit_time = 0;
+        self.compose_delta(delta.clone())?;
+        self.last_edit_time = 0;
+        Ok(delta)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "undo",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id),
+            err
+        )
+    )]
+    pub fn undo(&mut self) -> Result<UndoResult, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::Undo);
+        match self.history.undo() {
+            None => Err(CollaborateError::undo().context("Undo stack is empty")),
+            Some(entry) => {
+                let rev_id = self.rev_id;
+                let selection = entry.selection;
+                let undo_delta = self.history_entry_delta(entry)?;
+                let inverted_delta = self.apply_history_delta(&undo_delta, DocumentEventSource::Undo)?;
+                self.history.add_redo(
+                    inverted_delta,
+                    Some(rev_id),
+                    selection.map(|selection| selection.inverted()),
+                );
+                self.history_did_grow();
+                Ok(UndoResult::new(undo_delta, selection.map(|selection| selection.before)))
+            }
+        }
+    }
+
+    /// Undoes the last format of the undo stack, without undoing the changes made after it, e.g.
+    /// to remove the formatting that was applied last. See [History::undo_matching], the redo
+    /// stack is cleared and the undone format goes on it.
+    pub fn undo_last_format(&mut self) -> Result<UndoResult, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::UndoLastFormat);
+        match self.history.undo_matching(&self.delta, HistoryEntry::is_format)? {
+            None => Err(CollaborateError::undo().context("There is no format to undo")),
+            Some(entry) => {
+                let rev_id = self.rev_id;
+                let selection = entry.selection;
+                let undo_delta = self.history_entry_delta(entry)?;
+                let inverted_delta = self.apply_history_delta(&undo_delta, DocumentEventSource::Undo)?;
+                self.history.add_redo(
+                    inverted_delta,
+                    Some(rev_id),
+                    selection.map(|selection| selection.inverted()),
+                );
+                self.history_did_grow();
+                Ok(UndoResult::new(undo_delta, selection.map(|selection| selection.before)))
+            }
+        }
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "redo",
+            level = "debug",
+            skip_all,
+            fields(doc_id = %self.config.doc_id, rev_id = self.rev_id),
+            err
+        )
+    )]
+    pub fn redo(&mut self) -> Result<UndoResult, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::Redo);
+        match self.history.redo() {
+            None => Err(CollaborateError::redo()),
+            Some(entry) => {
+                let rev_id = self.rev_id;
+                let selection = entry.selection;
+                let redo_delta = self.history_entry_delta(entry)?;
+                let inverted_delta = self.apply_history_delta(&redo_delta, DocumentEventSource::Redo)?;
+                self.history.add_undo(
+                    inverted_delta,
+                    Some(rev_id),
+                    selection.map(|selection| selection.inverted()),
+                );
+                self.history_did_grow();
+                Ok(UndoResult::new(redo_delta, selection.map(|selection| selection.before)))
+            }
+        }
+    }
+
+    /// Describes the entries of the history, see [History::entries_meta].
+    pub fn history_entries_meta(&self) -> Vec<HistoryEntryMeta> {
+        self.history.entries_meta()
+    }
+
+    /// The branches of the history, when it keeps them, see [HistoryConfig::branching].
+    pub fn history_branches(&self) -> &[HistoryBranch] {
+        self.history.branches()
+    }
+
+    /// Moves the document to the last change of the branch `id` of the history: the changes are
+    /// undone or redone down to the start of the branch, then the changes of the branch are
+    /// redone. The branch that was left becomes a branch in its place.
+    pub fn switch_history_branch(&mut self, id: BranchId) -> Result<Vec<UndoResult>, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::SwitchHistoryBranch { id });
+        let depth = self
+            .history
+            .branches()
+            .iter()
+            .find(|branch| branch.id == id)
+            .map(|branch| branch.depth)
+            .ok_or_else(|| CollaborateError::undo().context(format!("There is no branch {}", id)))?;
+        let mut results = vec![];
+        while self.history.undo_len() > depth {
+            results.push(self.undo()?);
+        }
+        while self.history.undo_len() < depth {
+            results.push(self.redo()?);
+        }
+        self.history.switch_branch(id)?;
+        while self.history.can_redo() {
+            results.push(self.redo()?);
+        }
+        Ok(results)
+    }
+
+    /// Drops the branches of the history, see [History::linearize].
+    pub fn linearize_history(&mut self) {
+        self.history.linearize();
+    }
+
+    /// Checks the document, its history and its revision log for the corruptions that would make
+    /// the next change fail or lose text, e.g. to quarantine a document when it's opened instead of
+    /// failing in the middle of an edit. It composes the whole history, it's not meant for every
+    /// change.
+    pub fn verify(&self) -> VerifyReport {
+        verify_document(
+            &self.delta,
+            self.rev_id,
+            self.trailing_newline,
+            self.observer::<AttributeRuns>(),
+            &self.history,
+            &self.revision_log,
+        )
+    }
+
+    /// See [History::set_check_invariants].
+    pub fn set_check_history(&mut self, check: bool) {
+        self.set_history_config(HistoryConfig {
+            check_invariants: check,
+            ..self.config.history.clone()
+        });
+    }
+
+    /// See [History::set_max_entry_size].
+    pub fn set_max_history_entry_size(&mut self, max_entry_size: usize) {
+        self.set_history_config(HistoryConfig {
+            max_entry_size,
+            ..self.config.history.clone()
+        });
+    }
+
+    /// See [History::set_compression].
+    pub fn set_history_compression(&mut self, compression: Option<HistoryCompression>) {
+        self.set_history_config(HistoryConfig {
+            compression,
+            ..self.config.history.clone()
+        });
+    }
+
+    // The delta of an entry of the history. The delta of an entry that keeps a revision is the
+    // difference between the document and its content at the revision.
+    fn history_entry_delta(&self, entry: HistoryEntry) -> Result<RichTextDelta, CollaborateError> {
+        match (entry.delta, entry.rev_id) {
+            (Some(delta), _) => Ok(delta),
+            (None, Some(rev_id)) => Ok(diff(&self.delta, &self.at_revision(rev_id)?)),
+            (None, None) => Err(CollaborateError::internal().context("The history entry has no delta")),
+        }
+    }
+
+    // Applies an entry of the history and returns the delta that reverts it. The delta must be
+    // inverted against the document it applies to, before the document is updated.
+    fn apply_history_delta(
+        &mut self,
+        delta: &RichTextDelta,
+        source: DocumentEventSource,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        let (new_delta, inverted_delta) = self.invert(delta)?;
+        self.history.check_inversion(&self.delta, &new_delta, &inverted_delta);
+        let _ = self.apply_to_engine(delta)?;
+        self.transform_positions(delta);
+        self.update_delta(new_delta);
+        self.notify_change(delta, inverted_delta.clone(), source, None);
+        // The next edit starts a new entry, it can't be merged with the entry below the one that
+        // was just moved to the other stack.
+        self.last_edit_time = 0;
+        Ok(inverted_delta)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // The document is empty if its text is equal to the initial text.
+        self.delta == NewlineDoc::initial_delta()
+    }
+}
+
+impl ClientDocument {
+    fn apply_to_engine(&mut self, delta: &RichTextDelta) -> Result<(), CollaborateError> {
+        if let Some(engine) = self.engine.as_mut() {
+            let update = engine.apply_local(delta)?;
+            self.engine_updates.push(update);
+        }
+        Ok(())
+    }
+
+    // Records the call if the document is recording, see [ClientDocument::start_recording]. The
+    // call lasts until the returned guard is dropped.
+    fn record<F: FnOnce() -> ReplayCall>(&self, call: F) -> Option<ReplayGuard> {
+        let recorder = self.replay.as_ref()?;
+        Some(ReplayRecorder::record(recorder, self.rev_id, self.now(), call))
+    }
+
+    // Composes the buffered text before a change that doesn't fail, the error is logged.
+    fn flush_typing_or_log(&mut self) {
+        if let Err(e) = self.flush_typing() {
+            tracing::error!("Compose the typed text of {} failed: {}", self.config.doc_id, e);
+        }
+    }
+
+    // The current time in milliseconds since the epoch by the clock of the config, or the time of
+    // the call that a replay runs.
+    fn now(&self) -> i64 {
+        self.clock.unwrap_or_else(|| self.config.clock.now_millis())
+    }
+
+    // Whether an edit made `now` joins the current undo entry. The clock can go backwards, e.g. a
+    // [ManualClock](crate::client_document::clock::ManualClock) that is set back, the edit starts an
+    // entry of its own then.
+    fn is_recording(&self, now: i64) -> bool {
+        (0..RECORD_THRESHOLD as i64).contains(&now.saturating_sub(self.last_edit_time))
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Option<i64>) {
+        self.clock = clock;
+    }
+
+    // Composes the delta made by the local user, which is turned into a suggestion first in
+    // suggestion mode. The edits that are close in time share the same suggestion, like they share
+    // the same undo entry.
+    fn compose_local_delta(&mut self, delta: RichTextDelta) -> Result<RichTextDelta, CollaborateError> {
+        self.compose_local(delta, UndoRecording::Merge)
+    }
+
+    fn compose_local(
+        &mut self,
+        delta: RichTextDelta,
+        recording: UndoRecording,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        if !self.config.editing.suggestion_mode {
+            let delta = self.with_renumbered_lists(delta)?;
+            return self.compose_checked(delta, recording);
+        }
+
+        let now = self.now();
+        let id = match self.suggestion_id.take() {
+            Some(id) if self.is_recording(now) => id,
+            _ => self.config.ids.next_id(),
+        };
+        let delta = suggest_delta(&self.delta, &delta, &id, &self.config.author_id);
+        self.suggestion_id = Some(id);
+        self.compose_checked(delta, recording)
+    }
+
+    fn compose_checked_delta(&mut self, delta: RichTextDelta) -> Result<RichTextDelta, CollaborateError> {
+        self.compose_checked(delta, UndoRecording::Merge)
+    }
+
+    // Composes the local `delta` once the protection and the content policies checked it, and
+    // returns the delta that was composed, with the redacted text replaced. Every local change
+    // goes through it, the checks that apply to all of them belong here.
+    fn compose_checked(
+        &mut self,
+        delta: RichTextDelta,
+        recording: UndoRecording,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.flush_typing()?;
+        let _call = self.record(|| ReplayCall::ComposeDelta { delta: delta.clone() });
+        if self.override_token.is_none() {
+            let _ = check_protection(&self.delta, &delta)?;
+        }
+        let (delta, violations) = self.content_policies.apply(delta)?;
+        if self.config.sync_mode == SyncMode::Authoritative {
+            let intent = self.add_intent(delta)?;
+            self.notify_violations(violations, DocumentEventSource::Local);
+            return Ok(intent);
+        }
+        tracing::trace!("{} compose {}", &self.delta.to_json(), delta.to_json());
+        let undo_delta = delta.invert(&self.delta);
+        self.compose_recorded_delta(delta.clone(), undo_delta, DocumentEventSource::Local, None, recording)?;
+        self.notify_violations(violations, DocumentEventSource::Local);
+        Ok(delta)
+    }
+
+    // Queues the local `delta` until the server applies it, in the authoritative sync mode. The
+    // change is made on the content of the document, it goes after the intents that are waiting.
+    // Returns the intent to send to the server.
+    fn add_intent(&mut self, delta: RichTextDelta) -> Result<RichTextDelta, CollaborateError> {
+        let composed_delta = self.delta.compose_with(&delta, &self.config.compose)?;
+        self.check_trailing_newline(&composed_delta)?;
+        self.check_max_len(&composed_delta)?;
+        let mut intent = delta;
+        for pending in &self.intents {
+            let (intent_prime, _) = intent.transform_with(pending, Priority::Right, &self.config.compose)?;
+            intent = intent_prime;
+        }
+        self.intents.push_back(intent.clone());
+        Ok(intent)
+    }
+
+    // Whether `revision` of the server is the first intent, which the server acknowledges by
+    // sending it back, attributed to the author and the device of the document.
+    fn is_own_intent(&self, revision: &Revision) -> bool {
+        !self.intents.is_empty()
+            && revision.author_id == self.config.author_id
+            && revision.device_id == self.config.device_id
+    }
+
+    // Rebases the intents on `delta`, a change of the others that the server applied before them.
+    // The server gives the priority to the changes it applied.
+    fn transform_intents(&mut self, delta: &RichTextDelta) -> Result<(), CollaborateError> {
+        let mut server_delta = delta.clone();
+        for intent in self.intents.iter_mut() {
+            let (intent_prime, server_prime) =
+                intent.transform_with(&server_delta, Priority::Right, &self.config.compose)?;
+            *intent = intent_prime;
+            server_delta = server_prime;
+        }
+        Ok(())
+    }
+
+    fn notify_violations(&self, violations: Vec<PolicyViolation>, source: DocumentEventSource) {
+        if violations.is_empty() || self.policy_notifier.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.policy_notifier.send(PolicyEvent {
+            violations,
+            source,
+            rev_id: self.rev_id,
+        });
+    }
+
+    // The interval to format `attribute` in. The block formats apply to the newline at and after
+    // the end of the interval, so the interval of a block attribute is made to end at the newline
+    // of the last line of the boundary policy, none if there's no line to format.
+    fn format_interval(
+        &self,
+        document: &RichTextDelta,
+        interval: Interval,
+        attribute: &RichTextAttribute,
+    ) -> Option<Interval> {
+        if attribute.scope != AttributeScope::Block {
+            return Some(interval);
+        }
+        let lines = lines_in(document, interval, self.config.editing.boundary_policy);
+        let (first, last) = (lines.first()?, lines.last()?);
+        Some(Interval::new(first.start, last.end - 1))
+    }
+
+    // Adds to `delta` the changes that keep the lists in order after it, see [renumber_lists], so
+    // the edit and the renumbering are one revision and one undo entry.
+    fn with_renumbered_lists(&self, delta: RichTextDelta) -> Result<RichTextDelta, CollaborateError> {
+        if !may_change_lists(&delta) {
+            return Ok(delta);
+        }
+        let renumber = renumber_lists(&self.delta.compose(&delta)?);
+        if renumber.is_empty() {
+            return Ok(delta);
+        }
+        let mut delta = delta.compose(&renumber)?;
+        trim(&mut delta);
+        Ok(delta)
+    }
+
+    fn resolve_suggestion(&mut self, id: &str, accept: bool) -> Result<RichTextDelta, CollaborateError> {
+        let delta = match resolve_suggestion(&self.delta, id, accept) {
+            None => {
+                return Err(CollaborateError::record_not_found().context(format!("The suggestion {} doesn't exist", id)))
+            }
+            Some(delta) => delta,
+        };
+        self.compose_delta(delta.clone())?;
+        Ok(delta)
+    }
+
+    // The attributes of the character at `index`, which the text that replaces it takes.
+    fn attributes_at(&self, index: usize) -> RichTextAttributes {
+        if let Some(runs) = self.observer::<AttributeRuns>() {
+            return runs.attributes_at(index).cloned().unwrap_or_default();
+        }
+        let mut offset = 0;
+        for op in self.delta.ops.iter() {
+            offset += op.len();
+            if offset > index {
+                return op.get_attributes();
+            }
+        }
+        RichTextAttributes::default()
+    }
+
+    fn add_pending_attribute(&mut self, index: usize, attribute: RichTextAttribute) {
+        match self.pending_attributes.as_mut() {
+            Some((pending_index, attributes)) if *pending_index == index => attributes.add(attribute),
+            _ => self.pending_attributes = Some((index, attribute.into())),
+        }
+    }
+
+    fn transform_positions(&mut self, delta: &RichTextDelta) {
+        self.presence.transform(delta);
+        self.annotations.transform(delta);
+        self.anchors.transform(delta);
+        self.decorations.transform(delta);
+        if !self.observers.is_empty() {
+            self.notify_observers(|observers, document| observers.will_change(document, delta));
+        }
+        if let Some((index, _)) = self.pending_attributes.as_mut() {
+            *index = delta.transform_position(*index, false);
+        }
+        if let Some(composition) = self.composition.as_mut() {
+            composition.transform(delta);
+        }
+    }
+
+    // Lends the observers the document, which they can't borrow from it while they're changed.
+    fn notify_observers<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Observers, &ObservedDocument),
+    {
+        let mut observers = std::mem::take(&mut self.observers);
+        let document = ObservedDocument {
+            doc_id: &self.config.doc_id,
+            delta: &self.delta,
+            rev_id: self.rev_id,
+            segmenter: self.segmenter.as_ref(),
+        };
+        f(&mut observers, &document);
+        self.observers = observers;
+    }
+
+    // The snapshot of the content and the metadata at the current revision.
+    fn current_snapshot(&self) -> DocumentSnapshot {
+        DocumentSnapshot::new(self.rev_id, &self.delta).with_metadata(self.metadata.entries().cloned().collect())
+    }
+
+    fn text_index(&self) -> Arc<TextIndex> {
+        self.text_index
+            .lock()
+            .get_or_insert_with(|| Arc::new(TextIndex::new(&self.delta)))
+            .clone()
+    }
+
+    fn update_delta(&mut self, data: RichTextDelta) {
+        tracing::trace!("document: {}", data.to_json());
+        self.spare_delta = std::mem::replace(&mut self.delta, data);
+        self.spare_delta.clear();
+        *self.text_index.get_mut() = None;
+        *self.read_snapshot.get_mut() = None;
+
+        match &self.notify {
+            None => {}
+            Some(notify) => {
+                let _ = notify.send(());
+            }
+        }
+    }
+
+    // Must be called after the delta was composed into the document. The change is attributed to
+    // `author`, or to the local user if it wasn't received from the other participants.
+    // Keeps the revision log within [DocumentConfig::max_log_revisions]: a snapshot is added
+    // every `max` revisions, and the revisions before the one `max` revisions ago are compacted
+    // away, unless the history still rebuilds its entries from them.
+    fn bound_revision_log(&mut self) {
+        let max = match self.config.max_log_revisions {
+            None => return,
+            Some(max) => max.max(1) as i64,
+        };
+        if self.rev_id - self.revision_log.latest_snapshot().rev_id < max {
+            return;
+        }
+        self.revision_log.add_snapshot(self.current_snapshot());
+        let before_rev = match self.history.oldest_rebuilt_rev_id() {
+            None => self.rev_id - max,
+            Some(rev_id) => (self.rev_id - max).min(rev_id),
+        };
+        self.revision_log.compact(before_rev);
+    }
+
+    fn notify_change(
+        &mut self,
+        delta: &RichTextDelta,
+        inverted: RichTextDelta,
+        source: DocumentEventSource,
+        author: Option<(&str, &str)>,
+    ) {
+        let (author_id, device_id) = match (author, source) {
+            (Some((author_id, device_id)), _) => (author_id.to_owned(), device_id.to_owned()),
+            (None, DocumentEventSource::Remote) => ("".to_owned(), "".to_owned()),
+            (None, _) => (self.config.author_id.clone(), self.config.device_id.clone()),
+        };
+        let base_rev_id = self.rev_id;
+        self.rev_id += 1;
+        let timestamp = self.now();
+        // The checksums hash the whole document, they are computed when the revision is sent or
+        // persisted, see [ClientDocument::revisions_after].
+        let revision = Revision::new(
+            &self.config.doc_id,
+            base_rev_id,
+            self.rev_id,
+            delta.to_bytes(),
+            &author_id,
+            String::new(),
+        )
+        .with_author(&author_id, &device_id)
+        .with_timestamp(timestamp);
+        self.pending_replay
+            .record(revision.delta_data.len(), self.last_compose.unwrap_or_default());
+        self.revision_log.push(revision);
+        self.bound_revision_log();
+
+        self.notify_blocks(source, |tree, document| tree.apply(delta, document));
+
+        if self.content_change_notifier.receiver_count() > 0 {
+            let _ = self.content_change_notifier.send(ContentChangeEvent {
+                changes: content_changes(&self.delta, delta, &inverted, ColumnUnit::Utf16),
+                source,
+                rev_id: self.rev_id,
+            });
+        }
+
+        let has_subscribers = self.notifier.receiver_count() > 0;
+        if self.observers.is_empty() && !has_subscribers {
+            return;
+        }
+        let mut event = DocumentEvent {
+            delta: delta.clone(),
+            inverted,
+            source,
+            rev_id: self.rev_id,
+            author_id,
+            timestamp,
+            stats: None,
+        };
+        self.notify_observers(|observers, document| observers.did_change(document, &event));
+        if has_subscribers {
+            event.stats = self.observer::<StatsTracker>().map(|tracker| tracker.stats());
+            let _ = self.notifier.send(event);
+        }
+    }
+
+    // Updates the blocks and the outline with `update` and sends the changes of the blocks. The
+    // blocks are dropped once the last subscriber is gone, unless the outline is kept.
+    fn notify_blocks<F>(&mut self, source: DocumentEventSource, update: F)
+    where
+        F: FnOnce(&mut DocumentTree, &RichTextDelta) -> TreeChanges,
+    {
+        if self.block_notifier.receiver_count() == 0 && self.outline.is_none() {
+            self.block_tree = None;
+            return;
+        }
+        let tree = match self.block_tree.as_mut() {
+            None => return,
+            Some(tree) => tree,
+        };
+        let changes = update(tree, &self.delta);
+        if changes.blocks.is_empty() {
+            return;
+        }
+        if let Some(outline) = self.outline.as_mut() {
+            outline.apply(tree, &changes.blocks, &mut self.anchors);
+        }
+        if self.block_notifier.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.block_notifier.send(BlockEvent {
+            changes: changes.blocks,
+            source,
+            rev_id: self.rev_id,
+        });
+    }
+
+    fn invert(&mut self, delta: &RichTextDelta) -> Result<(RichTextDelta, RichTextDelta), CollaborateError> {
+        // c = a.compose(b)
+        // d = b.invert(a)
+        // a = c.compose(d)
+        let new_delta = self.compose_timed(delta)?;
+        let inverted_delta = delta.invert(&self.delta);
+        Ok((new_delta, inverted_delta))
+    }
+
+    // Composes `delta` into the document without applying it, the time it takes is kept for the
+    // debug stats and reported to the metrics. The composed delta takes the memory of the content
+    // replaced last.
+    fn compose_timed(&mut self, delta: &RichTextDelta) -> Result<RichTextDelta, CollaborateError> {
+        let mut new_delta = std::mem::take(&mut self.spare_delta);
+        let started = Instant::now();
+        match self
+            .delta
+            .compose_into_with(delta, &mut new_delta, &self.config.compose)
+        {
+            Ok(()) => {
+                let elapsed = started.elapsed();
+                self.last_compose = Some(elapsed);
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_compose(elapsed, delta.ops.len());
+                }
+                Ok(new_delta)
+            }
+            Err(e) => {
+                let error = CollaborateError::from(e);
+                self.report_error(&error);
+                Err(error)
+            }
+        }
+    }
+
+    fn transform_history(&mut self, delta: &RichTextDelta) {
+        let started = Instant::now();
+        self.history.transform(delta, self.delta.utf16_target_len);
+        let elapsed = started.elapsed();
+        self.last_transform = Some(elapsed);
+        if let Some(metrics) = &self.metrics {
+            metrics.on_transform(elapsed, delta.ops.len());
+        }
+    }
+
+    fn history_did_grow(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_history_grow(self.history.undo_len(), self.history.redo_len());
+        }
+    }
+
+    fn report_error(&self, error: &CollaborateError) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_error(error);
+        }
+    }
+}
+
+// Composes `attributes` into the text that `delta` inserts. The attributes that are removed, e.g. a
+// bold that was turned off, are removed from the text.
+fn with_attributes(delta: RichTextDelta, attributes: &RichTextAttributes) -> Result<RichTextDelta, CollaborateError> {
+    let mut new_delta = RichTextDelta::new();
+    for op in delta.ops {
+        match op {
+            Operation::Insert(mut insert) => {
+                insert.attributes = insert.attributes.compose(attributes)?;
+                insert.attributes.remove_empty();
+                new_delta.add(Operation::Insert(insert));
+            }
+            op => new_delta.add(op),
+        }
+    }
+    Ok(new_delta)
+}
+
+// Sets the checksums of the content of the document after the revision, which is `delta`.
+fn with_checksums(mut revision: Revision, delta: &RichTextDelta) -> Revision {
+    revision.md5 = format!("{:x}", md5::compute(delta.to_bytes()));
+    revision.with_content_md5(content_md5(delta))
+}
+
+fn ends_with_newline(delta: &RichTextDelta) -> bool {
+    match delta.ops.last() {
+        Some(Operation::Insert(insert)) => insert.embed.is_none() && insert.s.ends_with(NEW_LINE),
+        _ => false,
     }
 }
 
 fn validate_interval(delta: &RichTextDelta, interval: &Interval) -> Result<(), CollaborateError> {
     if delta.utf16_target_len < interval.end {
-        log::error!("{:?} out of bounds. should 0..{}", interval, delta.utf16_target_len);
+        tracing::error!("{:?} out of bounds. should 0..{}", interval, delta.utf16_target_len);
         return Err(CollaborateError::out_of_bound());
     }
     Ok(())
@@ -0,0 +1,334 @@
+use crate::errors::{CollaborateError, EmbedHandlerError};
+use lib_ot::{
+    core::{Embed, EmbedData, Priority},
+    rich_text::RichTextDelta,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// The changes of a kind of embed that the host defines, e.g. the strokes of a drawing or the
+/// source of an equation. The document keeps the embed as one character, and a change replaces
+/// the embed, the same as [ClientDocument::edit_table](crate::client_document::ClientDocument::edit_table).
+/// The handler tells how the changes compose, how they are undone and how the concurrent changes
+/// of the same embed are merged. A change is a json value of the handler's own format.
+pub trait EmbedHandler: Send + Sync {
+    /// The kind of the embeds, see [Embed::kind].
+    fn kind(&self) -> &str;
+
+    /// The utf16 length of the embed in the text. Every embed takes the place of one
+    /// [EMBED_CHAR](lib_ot::core::EMBED_CHAR), the suite checks that the handler agrees.
+    fn len(&self, _data: &EmbedData) -> usize {
+        1
+    }
+
+    /// Returns the embed that `change` makes of `data`.
+    fn apply(&self, data: &EmbedData, change: &EmbedData) -> Result<EmbedData, CollaborateError>;
+
+    /// Composes `a` and then `b` into one change.
+    fn compose(&self, a: &EmbedData, b: &EmbedData) -> Result<EmbedData, CollaborateError>;
+
+    /// Returns the change that undoes `change` of `data`.
+    fn invert(&self, change: &EmbedData, data: &EmbedData) -> Result<EmbedData, CollaborateError>;
+
+    /// Transforms the concurrent changes `a` and `b` of the same embed into `a'` and `b'`, so that
+    /// `a` and then `b'` make the same embed as `b` and then `a'`. `priority` tells which of them
+    /// wins when they conflict, the same as [Delta::transform_with_priority](lib_ot::core::Delta::transform_with_priority).
+    fn transform(
+        &self,
+        a: &EmbedData,
+        b: &EmbedData,
+        priority: Priority,
+    ) -> Result<(EmbedData, EmbedData), CollaborateError>;
+
+    /// The embeds and their changes that [check_embed_handler] runs the properties against.
+    fn examples(&self) -> Vec<EmbedExample>;
+}
+
+/// An embed and changes that can be made to it, in any order, see [EmbedHandler::examples].
+#[derive(Debug, Clone)]
+pub struct EmbedExample {
+    pub data: EmbedData,
+    pub changes: Vec<EmbedData>,
+}
+
+/// Runs the properties that every [EmbedHandler] must keep against its examples: the embed is
+/// one character long, a change composed with the next one makes the same embed as the two of
+/// them, the inverse of a change restores the embed, and the concurrent changes converge with
+/// either priority. Returns the first property that fails.
+pub fn check_embed_handler(handler: &dyn EmbedHandler) -> Result<(), EmbedHandlerError> {
+    let kind = handler.kind();
+    let fail = |property: &str, detail: String| EmbedHandlerError {
+        kind: kind.to_owned(),
+        property: property.to_owned(),
+        detail,
+    };
+    let examples = handler.examples();
+    if examples.iter().all(|example| example.changes.is_empty()) {
+        return Err(fail("examples", "There are no changes to check".to_owned()));
+    }
+
+    for EmbedExample { data, changes } in &examples {
+        let len = handler.len(data);
+        if len != 1 {
+            return Err(fail("len", format!("{} is {} long", data, len)));
+        }
+
+        for a in changes {
+            let after_a = handler.apply(data, a).map_err(|e| fail("apply", e.msg))?;
+            let undo = handler.invert(a, data).map_err(|e| fail("invert", e.msg))?;
+            let restored = handler.apply(&after_a, &undo).map_err(|e| fail("invert", e.msg))?;
+            if &restored != data {
+                return Err(fail(
+                    "invert",
+                    format!("{} then {} then {} is {}", data, a, undo, restored),
+                ));
+            }
+
+            for b in changes {
+                let composed = handler.compose(a, b).map_err(|e| fail("compose", e.msg))?;
+                let left = handler.apply(data, &composed).map_err(|e| fail("compose", e.msg))?;
+                let right = handler.apply(&after_a, b).map_err(|e| fail("compose", e.msg))?;
+                if left != right {
+                    return Err(fail(
+                        "compose",
+                        format!("{} then {} composed is {}, one after another {}", a, b, left, right),
+                    ));
+                }
+
+                for priority in [Priority::Left, Priority::Right] {
+                    let (a_prime, b_prime) = handler
+                        .transform(a, b, priority)
+                        .map_err(|e| fail("transform", e.msg))?;
+                    let left = handler
+                        .apply(&after_a, &b_prime)
+                        .map_err(|e| fail("transform", e.msg))?;
+                    let after_b = handler.apply(data, b).map_err(|e| fail("transform", e.msg))?;
+                    let right = handler
+                        .apply(&after_b, &a_prime)
+                        .map_err(|e| fail("transform", e.msg))?;
+                    if left != right {
+                        return Err(fail(
+                            "transform",
+                            format!(
+                                "{} and {} with {:?} don't converge on {}: {} and {}",
+                                a, b, priority, data, left, right
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The [EmbedHandler]s of a document by the kind of their embeds. A handler is only registered
+/// once it passes [check_embed_handler].
+#[derive(Clone, Default)]
+pub struct EmbedHandlers {
+    handlers: HashMap<String, Arc<dyn EmbedHandler>>,
+}
+
+impl EmbedHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler, in place of the one of the same kind. It's rejected with
+    /// [ErrorCode::EmbedHandlerRejected](crate::errors::ErrorCode::EmbedHandlerRejected) if it
+    /// breaks a property of the suite.
+    pub fn register(&mut self, handler: Arc<dyn EmbedHandler>) -> Result<(), CollaborateError> {
+        let _ = check_embed_handler(handler.as_ref())?;
+        self.handlers.insert(handler.kind().to_owned(), handler);
+        Ok(())
+    }
+
+    pub fn get(&self, kind: &str) -> Option<&Arc<dyn EmbedHandler>> {
+        self.handlers.get(kind)
+    }
+
+    /// The kinds of the registered handlers, sorted.
+    pub fn kinds(&self) -> Vec<String> {
+        let mut kinds = self.handlers.keys().cloned().collect::<Vec<_>>();
+        kinds.sort();
+        kinds
+    }
+
+    /// Returns the embed that `change` makes of `embed`.
+    pub fn apply(&self, embed: &Embed, change: &EmbedData) -> Result<Embed, CollaborateError> {
+        let data = self.handler(&embed.kind)?.apply(&embed.data, change)?;
+        Ok(Embed::new(&embed.kind, data))
+    }
+
+    /// Transforms the concurrent changes of an embed of `kind`, see [EmbedHandler::transform].
+    pub fn transform(
+        &self,
+        kind: &str,
+        a: &EmbedData,
+        b: &EmbedData,
+        priority: Priority,
+    ) -> Result<(EmbedData, EmbedData), CollaborateError> {
+        self.handler(kind)?.transform(a, b, priority)
+    }
+
+    fn handler(&self, kind: &str) -> Result<&Arc<dyn EmbedHandler>, CollaborateError> {
+        self.get(kind)
+            .ok_or_else(|| CollaborateError::internal().context(format!("There is no handler of the {} embeds", kind)))
+    }
+}
+
+impl std::fmt::Debug for EmbedHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.kinds()).finish()
+    }
+}
+
+/// Returns the embed at `index` of `document`, if there is one.
+pub fn embed_at(document: &RichTextDelta, index: usize) -> Option<&Embed> {
+    let mut offset = 0;
+    for op in &document.ops {
+        if offset == index {
+            return op.get_embed();
+        }
+        offset += op.len();
+        if offset > index {
+            return None;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client_document::{
+            embed_handler::{check_embed_handler, EmbedExample, EmbedHandler},
+            ClientDocument, NewlineDoc,
+        },
+        errors::{CollaborateError, ErrorCode},
+    };
+    use lib_ot::core::{Embed, EmbedData, Priority};
+    use serde_json::{json, Map, Value};
+    use std::sync::Arc;
+
+    // A drawing is the set of the ids of its strokes, a change adds the strokes that map to true
+    // and removes the ones that map to false.
+    struct Drawing {
+        // Whether the concurrent changes of the same stroke are merged by the priority, which a
+        // broken handler doesn't do.
+        merges: bool,
+    }
+
+    fn strokes(data: &EmbedData) -> Map<String, Value> {
+        data.to_value().as_object().cloned().unwrap_or_default()
+    }
+
+    impl EmbedHandler for Drawing {
+        fn kind(&self) -> &str {
+            "drawing"
+        }
+
+        fn apply(&self, data: &EmbedData, change: &EmbedData) -> Result<EmbedData, CollaborateError> {
+            let mut data = strokes(data);
+            for (id, present) in strokes(change) {
+                match present.as_bool() == Some(true) {
+                    true => data.insert(id, Value::Bool(true)),
+                    false => data.remove(&id),
+                };
+            }
+            Ok(Value::Object(data).into())
+        }
+
+        fn compose(&self, a: &EmbedData, b: &EmbedData) -> Result<EmbedData, CollaborateError> {
+            let mut composed = strokes(a);
+            composed.extend(strokes(b));
+            Ok(Value::Object(composed).into())
+        }
+
+        fn invert(&self, change: &EmbedData, data: &EmbedData) -> Result<EmbedData, CollaborateError> {
+            let data = strokes(data);
+            let undo = strokes(change)
+                .into_iter()
+                .map(|(id, _)| {
+                    let present = data.contains_key(&id);
+                    (id, Value::Bool(present))
+                })
+                .collect();
+            Ok(Value::Object(undo).into())
+        }
+
+        fn transform(
+            &self,
+            a: &EmbedData,
+            b: &EmbedData,
+            priority: Priority,
+        ) -> Result<(EmbedData, EmbedData), CollaborateError> {
+            let (mut a, mut b) = (strokes(a), strokes(b));
+            if self.merges {
+                let conflicts = a
+                    .iter()
+                    .filter(|(id, present)| b.get(id.as_str()).map_or(false, |other| other != *present))
+                    .map(|(id, _)| id.clone())
+                    .collect::<Vec<_>>();
+                for id in conflicts {
+                    match priority {
+                        Priority::Left => b.remove(&id),
+                        Priority::Right => a.remove(&id),
+                    };
+                }
+            }
+            Ok((Value::Object(a).into(), Value::Object(b).into()))
+        }
+
+        fn examples(&self) -> Vec<EmbedExample> {
+            vec![EmbedExample {
+                data: json!({"a": true}).into(),
+                changes: vec![
+                    json!({"a": false}).into(),
+                    json!({"b": true}).into(),
+                    json!({"a": true, "b": false}).into(),
+                ],
+            }]
+        }
+    }
+
+    #[test]
+    fn embed_handlers_pass_the_suite_before_use() {
+        assert!(check_embed_handler(&Drawing { merges: true }).is_ok());
+        let error = check_embed_handler(&Drawing { merges: false }).unwrap_err();
+        assert_eq!(error.kind, "drawing");
+        assert_eq!(error.property, "transform");
+
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        let error = document
+            .register_embed_handler(Arc::new(Drawing { merges: false }))
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::EmbedHandlerRejected);
+        document
+            .insert_embed(0, Embed::new("drawing", json!({"a": true})))
+            .unwrap();
+        assert!(document.edit_embed(0, &json!({"b": true}).into()).is_err());
+
+        document
+            .register_embed_handler(Arc::new(Drawing { merges: true }))
+            .unwrap();
+        assert_eq!(document.embed_handlers().kinds(), vec!["drawing".to_owned()]);
+        document.edit_embed(0, &json!({"b": true}).into()).unwrap();
+        assert_eq!(
+            document.to_json(),
+            r#"[{"insert":{"drawing":{"a":true,"b":true}}},{"insert":"\n"}]"#
+        );
+
+        // The concurrent changes of the same stroke converge on the one with the priority.
+        let handlers = document.embed_handlers();
+        let (a, b) = (
+            EmbedData::from(json!({"b": false})),
+            EmbedData::from(json!({"b": true, "c": true})),
+        );
+        let (a_prime, b_prime) = handlers.transform("drawing", &a, &b, Priority::Left).unwrap();
+        let embed = Embed::new("drawing", json!({"a": true, "b": true}));
+        let left = handlers.apply(&handlers.apply(&embed, &a).unwrap(), &b_prime).unwrap();
+        let right = handlers.apply(&handlers.apply(&embed, &b).unwrap(), &a_prime).unwrap();
+        assert_eq!(left, right);
+        assert_eq!(left.data, json!({"a": true, "c": true}).into());
+    }
+}
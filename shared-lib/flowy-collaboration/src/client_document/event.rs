@@ -0,0 +1,87 @@
+use crate::{
+    client_document::{ast::BlockChange, content_policy::PolicyViolation, lsp::ContentChange, stats::DocumentStats},
+    entities::revision::MetadataEntry,
+};
+use lib_ot::rich_text::RichTextDelta;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentEventSource {
+    Local,
+    Remote,
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone)]
+pub struct DocumentEvent {
+    /// The delta that was composed into the document.
+    pub delta: RichTextDelta,
+    /// The delta that reverts the change when composed into the new document.
+    pub inverted: RichTextDelta,
+    pub source: DocumentEventSource,
+    /// The local revision of the document after the change. It grows by one with every change.
+    pub rev_id: i64,
+    /// The author of the change, empty for the remote changes of an unknown author.
+    pub author_id: String,
+    /// The time of the change, in the milliseconds of the clock of the document.
+    pub timestamp: i64,
+    /// The stats of the document after the change, if the document observes them with a
+    /// [StatsTracker](crate::client_document::stats::StatsTracker).
+    pub stats: Option<DocumentStats>,
+}
+
+/// The changes of the blocks of the document, e.g. for the outline, see
+/// [ClientDocument::subscribe_blocks](crate::client_document::ClientDocument::subscribe_blocks).
+#[derive(Debug, Clone)]
+pub struct BlockEvent {
+    pub changes: Vec<BlockChange>,
+    pub source: DocumentEventSource,
+    /// The local revision of the document after the change, like [DocumentEvent::rev_id].
+    pub rev_id: i64,
+}
+
+/// The changes of the text of the document in the form of the language server protocol, see
+/// [ClientDocument::subscribe_content_changes](crate::client_document::ClientDocument::subscribe_content_changes).
+#[derive(Debug, Clone)]
+pub struct ContentChangeEvent {
+    /// The changes, in the order they are applied. The columns are in utf16 code units.
+    pub changes: Vec<ContentChange>,
+    pub source: DocumentEventSource,
+    /// The local revision of the document after the change, the version of the protocol.
+    pub rev_id: i64,
+}
+
+/// The text of a change that broke the content policies of the document, see
+/// [ClientDocument::subscribe_policy_violations](crate::client_document::ClientDocument::subscribe_policy_violations).
+#[derive(Debug, Clone)]
+pub struct PolicyEvent {
+    pub violations: Vec<PolicyViolation>,
+    pub source: DocumentEventSource,
+    /// The local revision of the document after the change, like [DocumentEvent::rev_id].
+    pub rev_id: i64,
+}
+
+/// The keys of the metadata of the document that a change set or removed, see
+/// [ClientDocument::subscribe_metadata](crate::client_document::ClientDocument::subscribe_metadata).
+#[derive(Debug, Clone)]
+pub struct MetadataEvent {
+    /// The entries that won over the values the keys had, a remote change that lost changes nothing.
+    pub entries: Vec<MetadataEntry>,
+    pub source: DocumentEventSource,
+    /// The local revision of the document after the change, like [DocumentEvent::rev_id].
+    pub rev_id: i64,
+}
+
+/// The document can't apply the changes of the server any more, its content diverged from the one
+/// of the server, e.g. a change of the server doesn't fit its length. The document waits for the
+/// content of the server, see
+/// [ClientDocument::reset_from_snapshot](crate::client_document::ClientDocument::reset_from_snapshot).
+#[derive(Debug, Clone)]
+pub struct ResyncRequired {
+    /// The local revision of the document when it diverged.
+    pub rev_id: i64,
+    /// The md5 of the content of the document then, see [crate::util::content_md5].
+    pub md5: String,
+    /// Why the change of the server couldn't be applied.
+    pub reason: String,
+}
@@ -0,0 +1,475 @@
+use crate::{
+    client_document::ast::{Block, BlockKind, DocumentTree, Span},
+    errors::CollaborateError,
+};
+use lib_ot::{
+    core::Embed,
+    rich_text::{RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+
+/// The kind of the embeds that are images, the only embeds that the exporters write.
+pub const IMAGE_EMBED: &str = "image";
+
+/// How an [Exporter] writes the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// The title of the document, written before its content.
+    pub title: Option<String>,
+    /// Whether the export is a whole file, e.g. an html page, or a fragment to paste into another
+    /// file.
+    pub standalone: bool,
+    /// Whether the embeds, e.g. the images, are exported. They are dropped otherwise.
+    pub embeds: bool,
+}
+
+impl std::default::Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            title: None,
+            standalone: true,
+            embeds: true,
+        }
+    }
+}
+
+/// Turns a document into the content of a file of another format.
+pub trait Exporter: Send + Sync {
+    /// The name that the exporter is found by, e.g. `"markdown"`.
+    fn name(&self) -> &str;
+
+    /// The extension of the files of the format, without the dot.
+    fn extension(&self) -> &str;
+
+    fn export(&self, delta: &RichTextDelta, options: &ExportOptions) -> Result<Vec<u8>, CollaborateError>;
+}
+
+/// The exporters of the formats that the documents can be exported to, by their names. The other
+/// crates add their formats with [ExporterRegistry::register].
+pub struct ExporterRegistry {
+    exporters: Vec<Box<dyn Exporter>>,
+}
+
+impl std::default::Default for ExporterRegistry {
+    fn default() -> Self {
+        Self {
+            exporters: vec![
+                Box::new(MarkdownExporter),
+                Box::new(HtmlExporter),
+                Box::new(PlainTextExporter),
+            ],
+        }
+    }
+}
+
+impl ExporterRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an exporter, which replaces the one that was registered with the same name.
+    pub fn register(&mut self, exporter: Box<dyn Exporter>) {
+        self.exporters.retain(|registered| registered.name() != exporter.name());
+        self.exporters.push(exporter);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Exporter> {
+        self.exporters
+            .iter()
+            .find(|exporter| exporter.name() == name)
+            .map(|exporter| exporter.as_ref())
+    }
+
+    /// The names of the exporters, in the order they were registered.
+    pub fn names(&self) -> Vec<&str> {
+        self.exporters.iter().map(|exporter| exporter.name()).collect()
+    }
+
+    pub fn export(
+        &self,
+        name: &str,
+        delta: &RichTextDelta,
+        options: &ExportOptions,
+    ) -> Result<Vec<u8>, CollaborateError> {
+        match self.get(name) {
+            None => Err(CollaborateError::record_not_found().context(format!("There is no {} exporter", name))),
+            Some(exporter) => exporter.export(delta, options),
+        }
+    }
+}
+
+/// Exports the text of the document, with the markers of the list items that
+/// [PlainTextImporter](crate::client_document::import::PlainTextImporter) reads back.
+pub struct PlainTextExporter;
+
+impl Exporter for PlainTextExporter {
+    fn name(&self) -> &str {
+        "plain text"
+    }
+
+    fn extension(&self) -> &str {
+        "txt"
+    }
+
+    fn export(&self, delta: &RichTextDelta, options: &ExportOptions) -> Result<Vec<u8>, CollaborateError> {
+        let mut out = String::new();
+        if let Some(title) = &options.title {
+            out.push_str(title);
+            out.push_str("\n\n");
+        }
+        for block in &DocumentTree::parse(delta).blocks {
+            out.push_str(&list_marker(&block.kind, ""));
+            for span in &block.spans {
+                if let Span::Text { text, .. } = span {
+                    out.push_str(text);
+                }
+            }
+            if block.terminated {
+                out.push('\n');
+            }
+        }
+        Ok(out.into_bytes())
+    }
+}
+
+/// Exports the document as CommonMark, with the strikethrough and the task lists of GitHub. The
+/// attributes that Markdown can't write, e.g. the colors and the underline, are dropped.
+pub struct MarkdownExporter;
+
+impl Exporter for MarkdownExporter {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn extension(&self) -> &str {
+        "md"
+    }
+
+    fn export(&self, delta: &RichTextDelta, options: &ExportOptions) -> Result<Vec<u8>, CollaborateError> {
+        let mut out = String::new();
+        if let Some(title) = &options.title {
+            out.push_str(&format!("# {}\n\n", escape_markdown(title)));
+        }
+        for group in groups(&DocumentTree::parse(delta).blocks) {
+            match &group[0].kind {
+                BlockKind::CodeBlock { language } => {
+                    out.push_str(&format!("```{}\n", language.as_deref().unwrap_or_default()));
+                    group.iter().for_each(|block| {
+                        out.push_str(&block.text());
+                        out.push('\n');
+                    });
+                    out.push_str("```\n");
+                }
+                _ => {
+                    for block in group {
+                        let prefix = match &block.kind {
+                            BlockKind::Heading(level) => format!("{} ", "#".repeat((*level).clamp(1, 6))),
+                            BlockKind::Quote => "> ".to_owned(),
+                            kind => list_marker(kind, "  "),
+                        };
+                        out.push_str(&prefix);
+                        out.push_str(&markdown_spans(&block.spans, options));
+                        out.push('\n');
+                    }
+                }
+            }
+            out.push('\n');
+        }
+        Ok(out.trim_end().to_owned().into_bytes())
+    }
+}
+
+/// Exports the document as html, a page if the export is standalone.
+pub struct HtmlExporter;
+
+impl Exporter for HtmlExporter {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn extension(&self) -> &str {
+        "html"
+    }
+
+    fn export(&self, delta: &RichTextDelta, options: &ExportOptions) -> Result<Vec<u8>, CollaborateError> {
+        let mut out = String::new();
+        if options.standalone {
+            out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+            if let Some(title) = &options.title {
+                out.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+            }
+            out.push_str("</head>\n<body>\n");
+        }
+        if let Some(title) = &options.title {
+            out.push_str(&format!("<h1>{}</h1>\n", escape_html(title)));
+        }
+        for group in groups(&DocumentTree::parse(delta).blocks) {
+            match &group[0].kind {
+                BlockKind::CodeBlock { language } => {
+                    let class = language
+                        .as_ref()
+                        .map(|language| format!(" class=\"language-{}\"", escape_html(language)))
+                        .unwrap_or_default();
+                    let code = group.iter().map(|block| block.text()).collect::<Vec<_>>().join("\n");
+                    out.push_str(&format!("<pre><code{}>{}</code></pre>\n", class, escape_html(&code)));
+                }
+                BlockKind::ListItem { list, .. } => {
+                    let tag = if list == "ordered" { "ol" } else { "ul" };
+                    out.push_str(&format!("<{}>\n", tag));
+                    for block in group {
+                        let checkbox = match &block.kind {
+                            BlockKind::ListItem { list, .. } if list == "checked" => {
+                                "<input type=\"checkbox\" checked disabled> "
+                            }
+                            BlockKind::ListItem { list, .. } if list == "unchecked" => {
+                                "<input type=\"checkbox\" disabled> "
+                            }
+                            _ => "",
+                        };
+                        out.push_str(&format!("<li>{}{}</li>\n", checkbox, html_spans(&block.spans, options)));
+                    }
+                    out.push_str(&format!("</{}>\n", tag));
+                }
+                _ => {
+                    for block in group {
+                        let tag = match &block.kind {
+                            BlockKind::Heading(level) => format!("h{}", (*level).clamp(1, 6)),
+                            BlockKind::Quote => "blockquote".to_owned(),
+                            _ => "p".to_owned(),
+                        };
+                        out.push_str(&format!("<{}>{}</{}>\n", tag, html_spans(&block.spans, options), tag));
+                    }
+                }
+            }
+        }
+        if options.standalone {
+            out.push_str("</body>\n</html>\n");
+        }
+        Ok(out.into_bytes())
+    }
+}
+
+// The runs of blocks that are written together: the lines of a code block, the items of a list,
+// and each of the other blocks on its own. The empty paragraphs are dropped.
+fn groups(blocks: &[Block]) -> Vec<&[Block]> {
+    let mut groups = vec![];
+    let mut start = 0;
+    for index in 0..blocks.len() {
+        let joined = index > start
+            && matches!(
+                (&blocks[start].kind, &blocks[index].kind),
+                (BlockKind::CodeBlock { .. }, BlockKind::CodeBlock { .. })
+                    | (BlockKind::ListItem { .. }, BlockKind::ListItem { .. })
+            );
+        if !joined {
+            if index > start {
+                groups.push(&blocks[start..index]);
+            }
+            start = index;
+        }
+    }
+    if start < blocks.len() {
+        groups.push(&blocks[start..]);
+    }
+    groups.retain(|group| group[0].kind != BlockKind::Paragraph || !group[0].spans.is_empty());
+    groups
+}
+
+fn list_marker(kind: &BlockKind, indent_unit: &str) -> String {
+    match kind {
+        BlockKind::ListItem { list, indent, number } => {
+            let marker = match list.as_str() {
+                "ordered" => format!("{}. ", number.unwrap_or(1)),
+                "checked" => "- [x] ".to_owned(),
+                "unchecked" => "- [ ] ".to_owned(),
+                _ => "- ".to_owned(),
+            };
+            format!("{}{}", indent_unit.repeat(*indent), marker)
+        }
+        _ => String::new(),
+    }
+}
+
+fn markdown_spans(spans: &[Span], options: &ExportOptions) -> String {
+    let mut out = String::new();
+    for span in spans {
+        match span {
+            Span::Text { text, attributes } => {
+                let is = |key: &RichTextAttributeKey| is_on(attributes, key);
+                if is(&RichTextAttributeKey::InlineCode) {
+                    out.push_str(&format!("`{}`", text));
+                    continue;
+                }
+                let mut s = escape_markdown(text);
+                for (key, mark) in [
+                    (RichTextAttributeKey::StrikeThrough, "~~"),
+                    (RichTextAttributeKey::Italic, "*"),
+                    (RichTextAttributeKey::Bold, "**"),
+                ] {
+                    if is(&key) {
+                        s = format!("{}{}{}", mark, s, mark);
+                    }
+                }
+                match link(attributes) {
+                    Some(url) => out.push_str(&format!("[{}]({})", s, url)),
+                    None => out.push_str(&s),
+                }
+            }
+            Span::Embed { embed, .. } if options.embeds => {
+                if let Some(src) = image_src(embed) {
+                    out.push_str(&format!("![]({})", src));
+                }
+            }
+            Span::Embed { .. } => {}
+        }
+    }
+    out
+}
+
+fn html_spans(spans: &[Span], options: &ExportOptions) -> String {
+    let mut out = String::new();
+    for span in spans {
+        match span {
+            Span::Text { text, attributes } => {
+                let mut s = escape_html(text);
+                for (key, tag) in [
+                    (RichTextAttributeKey::InlineCode, "code"),
+                    (RichTextAttributeKey::StrikeThrough, "s"),
+                    (RichTextAttributeKey::Underline, "u"),
+                    (RichTextAttributeKey::Italic, "em"),
+                    (RichTextAttributeKey::Bold, "strong"),
+                ] {
+                    if is_on(attributes, &key) {
+                        s = format!("<{}>{}</{}>", tag, s, tag);
+                    }
+                }
+                match link(attributes) {
+                    Some(url) => out.push_str(&format!("<a href=\"{}\">{}</a>", escape_html(url), s)),
+                    None => out.push_str(&s),
+                }
+            }
+            Span::Embed { embed, .. } if options.embeds => {
+                if let Some(src) = image_src(embed) {
+                    out.push_str(&format!("<img src=\"{}\">", escape_html(&src)));
+                }
+            }
+            Span::Embed { .. } => {}
+        }
+    }
+    out
+}
+
+fn is_on(attributes: &RichTextAttributes, key: &RichTextAttributeKey) -> bool {
+    attributes.get(key).and_then(|value| value.as_bool()) == Some(true)
+}
+
+fn link(attributes: &RichTextAttributes) -> Option<&str> {
+    attributes
+        .get(&RichTextAttributeKey::Link)
+        .and_then(|value| value.as_str())
+}
+
+// The exporters only know the images, the other embeds are dropped.
+fn image_src(embed: &Embed) -> Option<String> {
+    match embed.kind.as_str() {
+        IMAGE_EMBED => embed.data.to_value().get("src")?.as_str().map(|src| src.to_owned()),
+        _ => None,
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::export::{ExportOptions, Exporter, ExporterRegistry};
+    use crate::errors::CollaborateError;
+    use lib_ot::{
+        core::Embed,
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+    use serde_json::json;
+
+    fn document() -> RichTextDelta {
+        RichTextDeltaBuilder::new()
+            .insert("Notes")
+            .insert_with_attributes("\n", RichTextAttribute::Header(2).into())
+            .insert("Some ")
+            .insert_with_attributes("bold", RichTextAttribute::Bold(true).into())
+            .insert(" & ")
+            .insert_with_attributes("link", RichTextAttribute::Link("https://appflowy.io").into())
+            .insert("\n\n")
+            .insert("one")
+            .insert_with_attributes("\n", RichTextAttribute::Bullet(true).into())
+            .insert("two")
+            .insert_with_attributes("\n", RichTextAttribute::Bullet(true).into())
+            .insert_embed(Embed::new("image", json!({ "src": "a.png" })))
+            .insert("\n")
+            .build()
+    }
+
+    fn export(registry: &ExporterRegistry, name: &str, options: &ExportOptions) -> String {
+        String::from_utf8(registry.export(name, &document(), options).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn export_each_format() {
+        let registry = ExporterRegistry::new();
+        assert_eq!(
+            export(&registry, "markdown", &ExportOptions::default()),
+            "## Notes\n\nSome **bold** & [link](https://appflowy.io)\n\n- one\n- two\n\n![](a.png)"
+        );
+        let options = ExportOptions {
+            standalone: false,
+            embeds: false,
+            ..ExportOptions::default()
+        };
+        assert_eq!(
+            export(&registry, "html", &options),
+            "<h2>Notes</h2>\n<p>Some <strong>bold</strong> &amp; <a href=\"https://appflowy.io\">link</a></p>\n\
+             <ul>\n<li>one</li>\n<li>two</li>\n</ul>\n<p></p>\n"
+        );
+        assert_eq!(
+            export(&registry, "plain text", &ExportOptions::default()),
+            "Notes\nSome bold & link\n\n- one\n- two\n\n"
+        );
+    }
+
+    #[test]
+    fn custom_exporter_is_registered() {
+        struct OrgExporter;
+        impl Exporter for OrgExporter {
+            fn name(&self) -> &str {
+                "org"
+            }
+
+            fn extension(&self) -> &str {
+                "org"
+            }
+
+            fn export(&self, delta: &RichTextDelta, _options: &ExportOptions) -> Result<Vec<u8>, CollaborateError> {
+                Ok(format!("* {}", delta.apply("").unwrap_or_default()).into_bytes())
+            }
+        }
+
+        let mut registry = ExporterRegistry::new();
+        assert!(registry.export("org", &document(), &ExportOptions::default()).is_err());
+        registry.register(Box::new(OrgExporter));
+        assert_eq!(registry.names(), vec!["markdown", "html", "plain text", "org"]);
+        assert!(export(&registry, "org", &ExportOptions::default()).starts_with("* Notes"));
+    }
+}
@@ -32,7 +32,7 @@ impl DeleteExt for PreserveLineFormatOnMerge {
 
         while iter.has_next() {
             match iter.next() {
-                None => log::error!("op must be not None when has_next() return true"),
+                None => tracing::error!("op must be not None when has_next() return true"),
                 Some(op) => {
                     //
                     match op.get_data().find(NEW_LINE) {
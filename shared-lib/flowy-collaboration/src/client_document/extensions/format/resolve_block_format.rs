@@ -24,7 +24,7 @@ impl FormatExt for ResolveBlockFormat {
         let mut start = 0;
         let end = interval.size();
         while start < end && iter.has_next() {
-            let next_op = iter.next_op_with_len(end - start).unwrap();
+            let next_op = iter.next_op_with_len(end - start)?;
             match find_newline(next_op.get_data()) {
                 None => new_delta.retain(next_op.len(), plain_attributes()),
                 Some(_) => {
@@ -36,9 +36,7 @@ impl FormatExt for ResolveBlockFormat {
             start += next_op.len();
         }
 
-        while iter.has_next() {
-            let op = iter.next_op().expect("Unexpected None, iter.has_next() must return op");
-
+        while let Some(op) = iter.next_op() {
             match find_newline(op.get_data()) {
                 None => new_delta.retain(op.len(), plain_attributes()),
                 Some(line_break) => {
@@ -24,7 +24,7 @@ impl FormatExt for ResolveInlineFormat {
         let end = interval.size();
 
         while start < end && iter.has_next() {
-            let next_op = iter.next_op_with_len(end - start).unwrap();
+            let next_op = iter.next_op_with_len(end - start)?;
             match find_newline(next_op.get_data()) {
                 None => new_delta.retain(next_op.len(), attribute.clone().into()),
                 Some(_) => {
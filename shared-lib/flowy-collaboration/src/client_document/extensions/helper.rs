@@ -22,7 +22,7 @@ pub(crate) fn line_break(
                 new_delta.retain(1, attribute.clone().into());
             }
             _ => {
-                log::error!("Unsupported parser line break for {:?}", scope);
+                tracing::error!("Unsupported parser line break for {:?}", scope);
             }
         }
 
@@ -34,7 +34,7 @@ pub(crate) fn line_break(
         match scope {
             AttributeScope::Inline => new_delta.retain(end - start, attribute.clone().into()),
             AttributeScope::Block => new_delta.retain(end - start, plain_attributes()),
-            _ => log::error!("Unsupported parser line break for {:?}", scope),
+            _ => tracing::error!("Unsupported parser line break for {:?}", scope),
         }
     }
     new_delta
@@ -1,15 +1,15 @@
 use crate::client_document::InsertExt;
 pub use auto_exit_block::*;
-pub use auto_format::*;
 pub use default_insert::*;
 use lib_ot::rich_text::RichTextDelta;
+pub use plain_text_in_code_block::*;
 pub use preserve_block_format::*;
 pub use preserve_inline_format::*;
 pub use reset_format_on_new_line::*;
 
 mod auto_exit_block;
-mod auto_format;
 mod default_insert;
+mod plain_text_in_code_block;
 mod preserve_block_format;
 mod preserve_inline_format;
 mod reset_format_on_new_line;
@@ -0,0 +1,31 @@
+use crate::{client_document::InsertExt, util::contain_newline};
+use lib_ot::{
+    core::{DeltaBuilder, DeltaIter},
+    rich_text::{RichTextAttributeKey, RichTextDelta},
+};
+
+/// Inserts the text typed in a code block without the inline attributes of the text around it,
+/// e.g. the bold of the text that was pasted in the block.
+pub struct PlainTextInCodeBlock {}
+impl InsertExt for PlainTextInCodeBlock {
+    fn ext_name(&self) -> &str {
+        "PlainTextInCodeBlock"
+    }
+
+    fn apply(&self, delta: &RichTextDelta, replace_len: usize, text: &str, index: usize) -> Option<RichTextDelta> {
+        if contain_newline(text) {
+            return None;
+        }
+
+        let mut iter = DeltaIter::from_offset(delta, index);
+        let (newline_op, _) = iter.next_op_with_newline()?;
+        if !newline_op
+            .get_attributes()
+            .contains_key(&RichTextAttributeKey::CodeBlock)
+        {
+            return None;
+        }
+
+        Some(DeltaBuilder::new().retain(index + replace_len).insert(text).build())
+    }
+}
@@ -50,7 +50,7 @@ impl InsertExt for PreserveBlockFormatOnInsert {
                 });
                 if !reset_attribute.is_empty() {
                     new_delta.retain(offset, plain_attributes());
-                    let len = newline_op.get_data().find(NEW_LINE).unwrap();
+                    let len = newline_op.get_data().find(NEW_LINE)?;
                     new_delta.retain(len, plain_attributes());
                     new_delta.retain(1, reset_attribute);
                 }
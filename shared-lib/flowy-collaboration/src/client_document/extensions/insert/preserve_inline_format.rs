@@ -25,6 +25,8 @@ impl InsertExt for PreserveInlineFormat {
         }
 
         let mut attributes = prev.get_attributes();
+        // The text typed next to the locked text isn't locked.
+        attributes.remove(RichTextAttributeKey::Protected);
         if attributes.is_empty() || !attributes.contains_key(&RichTextAttributeKey::Link) {
             return Some(
                 DeltaBuilder::new()
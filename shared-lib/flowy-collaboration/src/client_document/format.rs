@@ -0,0 +1,262 @@
+use crate::errors::{internal_error, CollaborateError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, convert::TryFrom};
+
+/// The forms that a document is persisted in. Each of them is written with the current version of
+/// its kind, the payloads that were written by an older version are upgraded when they are loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PayloadKind {
+    /// See [crate::client_document::revision_log::DocumentSnapshot::to_stored_bytes].
+    Snapshot,
+    /// See [crate::client_document::history::History::encode].
+    History,
+    /// See [crate::client_document::revision_log::RevisionLog::encode_tags].
+    Tags,
+    /// See [crate::client_document::revision_log::RevisionLog::export_bundle]. The bundle is a
+    /// protobuf message, so its version is a field of the message instead.
+    Bundle,
+    /// See [crate::client_document::ClientDocument::encode_default_attributes], which were added in
+    /// the version 2.
+    DefaultAttributes,
+    /// See [crate::client_document::replay::ReplayLog::to_bytes].
+    ReplayLog,
+    /// See [crate::client_document::changelog::export_changelog]. The changelog isn't a json
+    /// document, its version is a field of its first line instead.
+    Changelog,
+    /// See [crate::client_document::ClientDocument::encode_state].
+    State,
+}
+
+impl PayloadKind {
+    /// The version that the payloads of the kind are written with.
+    ///
+    /// - 1: the payloads before they had a version: the json of the snapshots and the tags, the
+    ///   history with a version of its own and the bundle hashed with the json of its base.
+    /// - 2: the json payloads in a [VersionedPayload], the bundle hashed with the canonical json
+    ///   of its base.
+    pub fn current_version(&self) -> u32 {
+        2
+    }
+
+    /// Fails if the payload was written by a newer version, which this one can't read.
+    pub fn check_version(&self, version: u32) -> Result<(), CollaborateError> {
+        if version > self.current_version() {
+            return Err(CollaborateError::internal().context(format!(
+                "The version {} of the {:?} payload isn't supported, the latest is {}",
+                version,
+                self,
+                self.current_version()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The json payloads from the version 2 on.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionedPayload {
+    pub version: u32,
+    pub data: Value,
+}
+
+/// Upgrades the json of a payload from a version to the next one.
+pub type Migration = fn(Value) -> Result<Value, CollaborateError>;
+
+/// Upgrades the json payloads that were written by the older versions, one version at a time.
+pub struct MigrationRegistry {
+    migrations: HashMap<(PayloadKind, u32), Migration>,
+}
+
+impl std::default::Default for MigrationRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            migrations: HashMap::new(),
+        };
+        // The version 2 only moved the json of the snapshots and the tags in the envelope.
+        registry.register(PayloadKind::Snapshot, 1, Ok);
+        registry.register(PayloadKind::Tags, 1, Ok);
+        registry.register(PayloadKind::History, 1, |mut data| {
+            if let Value::Object(map) = &mut data {
+                map.remove("version");
+            }
+            Ok(data)
+        });
+        registry
+    }
+}
+
+impl MigrationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the migration of the payloads of `kind` from `version` to the next version. It
+    /// replaces the migration that was registered for the same version.
+    pub fn register(&mut self, kind: PayloadKind, version: u32, migration: Migration) {
+        self.migrations.insert((kind, version), migration);
+    }
+
+    /// Upgrades the json of a payload of `kind` from `version` to the current version.
+    pub fn upgrade(&self, kind: PayloadKind, version: u32, data: Value) -> Result<Value, CollaborateError> {
+        kind.check_version(version)?;
+        let mut data = data;
+        for version in version..kind.current_version() {
+            let migration = self.migrations.get(&(kind, version)).ok_or_else(|| {
+                CollaborateError::internal().context(format!(
+                    "There is no migration of the {:?} payload from the version {}",
+                    kind, version
+                ))
+            })?;
+            data = migration(data)?;
+        }
+        Ok(data)
+    }
+
+    /// Reads a json payload of `kind` that was written by [encode_json] or by an older version.
+    pub fn decode<T: DeserializeOwned>(&self, kind: PayloadKind, bytes: &[u8]) -> Result<T, CollaborateError> {
+        let value: Value = serde_json::from_slice(bytes).map_err(internal_error)?;
+        let (version, data) = split_version(value);
+        let data = self.upgrade(kind, version, data)?;
+        serde_json::from_value(data).map_err(internal_error)
+    }
+}
+
+/// Writes `data` as a json payload of `kind`, in the current version.
+pub fn encode_json<T: Serialize>(kind: PayloadKind, data: &T) -> Result<Vec<u8>, CollaborateError> {
+    let payload = VersionedPayload {
+        version: kind.current_version(),
+        data: serde_json::to_value(data).map_err(internal_error)?,
+    };
+    serde_json::to_vec(&payload).map_err(internal_error)
+}
+
+/// Reads a json payload of `kind` with the built-in migrations, see [MigrationRegistry::decode].
+pub fn decode_json<T: DeserializeOwned>(kind: PayloadKind, bytes: &[u8]) -> Result<T, CollaborateError> {
+    MigrationRegistry::new().decode(kind, bytes)
+}
+
+// The payloads that aren't in a [VersionedPayload] were written by the version 1.
+fn split_version(value: Value) -> (u32, Value) {
+    match value {
+        Value::Object(mut map) if map.len() == 2 && map.contains_key("data") => {
+            let version = map.get("version").and_then(Value::as_u64);
+            match version {
+                None => (1, Value::Object(map)),
+                Some(version) => {
+                    let data = map.remove("data").unwrap_or(Value::Null);
+                    (u32::try_from(version).unwrap_or(u32::MAX), data)
+                }
+            }
+        }
+        value => (1, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client_document::{
+            format::{decode_json, encode_json, MigrationRegistry, PayloadKind},
+            history::History,
+            revision_log::{DocumentSnapshot, RevisionLog},
+        },
+        entities::revision::{md5, Revision, RevisionBundle},
+    };
+    use bytes::Bytes;
+    use lib_ot::rich_text::{RichTextDelta, RichTextDeltaBuilder};
+    use serde_json::Value;
+    use std::convert::TryInto;
+
+    // The fixtures of every version, which must keep loading once newer versions are added.
+    const SNAPSHOT_V1: &str = r#"[{"insert":"123\n"}]"#;
+    const SNAPSHOT_V2: &str = r#"{"version":2,"data":[{"insert":"123\n"}]}"#;
+    const TAGS_V1: &str = r#"[{"name":"Draft 1","rev_id":1,"delta":[{"insert":"123\n"}]}]"#;
+    const TAGS_V2: &str = r#"{"version":2,"data":[{"name":"Draft 1","rev_id":1,"delta":[{"insert":"123\n"}]}]}"#;
+    const HISTORY_V1: &str = r#"{"version":1,"md5":"{md5}","undoes":[[{"delete":3}]],"redoes":[]}"#;
+    const HISTORY_V2: &str = r#"{"version":2,"data":{"md5":"{md5}","undoes":[[{"delete":3}]],"redoes":[]}}"#;
+
+    // The json of the base of the bundle, which the version 1 hashed, and its canonical json,
+    // which the version 2 hashes.
+    const BUNDLE_BASE_V1: &str = r#"[{"insert":"123\n"}]"#;
+    const BUNDLE_BASE_V2: &str = r#"[{"insert":"123\n"}]"#;
+
+    fn document() -> RichTextDelta {
+        RichTextDeltaBuilder::new().insert("123\n").build()
+    }
+
+    fn bundle(version: i64, base_md5: String) -> Bytes {
+        let delta_data = RichTextDeltaBuilder::new()
+            .retain(3)
+            .insert("4")
+            .retain(1)
+            .build()
+            .to_bytes();
+        let md5 = md5(&delta_data);
+        let mut bundle = RevisionBundle::new(1, base_md5, vec![Revision::new("", 1, 2, delta_data, "", md5)]);
+        bundle.version = version;
+        bundle.try_into().unwrap()
+    }
+
+    #[test]
+    fn load_snapshot_of_every_version() {
+        for fixture in [SNAPSHOT_V1, SNAPSHOT_V2] {
+            let snapshot = DocumentSnapshot::from_stored_bytes(1, fixture.as_bytes(), None).unwrap();
+            assert_eq!(snapshot.delta().unwrap(), document());
+        }
+        let snapshot = DocumentSnapshot::new(1, &document());
+        assert_eq!(snapshot.to_stored_bytes(None).unwrap(), SNAPSHOT_V2.as_bytes());
+    }
+
+    #[test]
+    fn load_tags_of_every_version() {
+        for fixture in [TAGS_V1, TAGS_V2] {
+            let mut log = RevisionLog::new(1, &document());
+            log.load_tags(fixture.as_bytes()).unwrap();
+            let tag = log.get_tag("Draft 1").unwrap();
+            assert_eq!(tag.rev_id(), 1);
+            assert_eq!(tag.snapshot.delta().unwrap(), document());
+        }
+    }
+
+    #[test]
+    fn load_history_of_every_version() {
+        let text_md5 = md5("123\n");
+        for fixture in [HISTORY_V1, HISTORY_V2] {
+            let bytes = fixture.replace("{md5}", &text_md5);
+            let history = History::decode(bytes.as_bytes(), &document()).unwrap();
+            assert_eq!(history.undo_len(), 1);
+            assert_eq!(history.redo_len(), 0);
+        }
+    }
+
+    #[test]
+    fn load_bundle_of_every_version() {
+        let log = RevisionLog::new(1, &document());
+        // The bundles of the version 1 don't have the field, which is decoded as 0.
+        for bundle in [bundle(0, md5(BUNDLE_BASE_V1)), bundle(2, md5(BUNDLE_BASE_V2))] {
+            let change = log.import_bundle(bundle).unwrap();
+            assert_eq!(change.to_json(), r#"[{"retain":3},{"insert":"4"},{"retain":1}]"#);
+        }
+        assert!(log.import_bundle(bundle(3, md5(BUNDLE_BASE_V2))).is_err());
+    }
+
+    #[test]
+    fn payload_of_newer_version_is_rejected() {
+        let bytes = r#"{"version":3,"data":[]}"#.as_bytes();
+        assert!(decode_json::<RichTextDelta>(PayloadKind::Snapshot, bytes).is_err());
+    }
+
+    #[test]
+    fn registered_migration_upgrades_payload() {
+        let mut registry = MigrationRegistry::new();
+        registry.register(PayloadKind::Snapshot, 1, |_| {
+            Ok(serde_json::from_str(SNAPSHOT_V1).unwrap())
+        });
+        let delta: RichTextDelta = registry.decode(PayloadKind::Snapshot, b"[]").unwrap();
+        assert_eq!(delta, document());
+
+        let bytes = encode_json(PayloadKind::Snapshot, &Value::Null).unwrap();
+        assert_eq!(bytes, br#"{"version":2,"data":null}"#);
+    }
+}
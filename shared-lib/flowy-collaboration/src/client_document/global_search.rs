@@ -0,0 +1,162 @@
+use crate::{
+    client_document::{
+        read_snapshot::ReadSnapshot,
+        search::{SearchMatch, SearchQuery},
+    },
+    errors::CollaborateError,
+};
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+
+// The utf16 length of the text kept on each side of a match in its snippet.
+const SNIPPET_CONTEXT: usize = 32;
+// How much more a match in the first line, the title of the document, weighs than the others.
+const TITLE_WEIGHT: usize = 4;
+
+/// The documents that [DocumentManager::search](crate::client_document::manager::DocumentManager::search)
+/// looks in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchScope {
+    /// The open documents only.
+    Open,
+    /// The open documents, and then the ones of the store, see
+    /// [SnapshotStore::doc_ids](crate::client_document::store::SnapshotStore::doc_ids).
+    All,
+    /// The documents with these ids, open or not.
+    Documents(Vec<String>),
+}
+
+/// A match of a global search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub interval: Interval,
+    /// The text around the match, within its line, with its attributes.
+    pub snippet: RichTextDelta,
+    /// Where the match is in the snippet.
+    pub snippet_interval: Interval,
+}
+
+/// The matches of a global search in a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSearchResult {
+    pub doc_id: String,
+    /// The documents with the higher scores are the better matches. A match in the title of the
+    /// document counts more than the other ones.
+    pub score: usize,
+    /// The matches, in the order of the document.
+    pub hits: Vec<SearchHit>,
+}
+
+/// Searches the content of the document with its text index. Returns `None` if nothing matched.
+pub fn search_snapshot(
+    doc_id: &str,
+    snapshot: &ReadSnapshot,
+    query: &SearchQuery,
+) -> Result<Option<DocumentSearchResult>, CollaborateError> {
+    let matches = snapshot.search(query)?;
+    if matches.is_empty() {
+        return Ok(None);
+    }
+
+    let lines = line_intervals(&snapshot.to_plain_string());
+    let title_end = lines.first().map_or(0, |line| line.end);
+    let mut score = 0;
+    let hits = matches
+        .into_iter()
+        .map(|SearchMatch { interval, .. }| {
+            score += if interval.start < title_end { TITLE_WEIGHT } else { 1 };
+            let line = lines
+                .iter()
+                .find(|line| line.start <= interval.start && interval.start <= line.end)
+                .copied()
+                .unwrap_or(interval);
+            let start = interval.start.saturating_sub(SNIPPET_CONTEXT).max(line.start);
+            let end = (interval.end + SNIPPET_CONTEXT).min(line.end).max(interval.end);
+            SearchHit {
+                interval,
+                snippet: snapshot.delta().between(Interval::new(start, end)),
+                snippet_interval: Interval::new(interval.start - start, interval.end - start),
+            }
+        })
+        .collect();
+    Ok(Some(DocumentSearchResult {
+        doc_id: doc_id.to_owned(),
+        score,
+        hits,
+    }))
+}
+
+// The utf16 intervals of the lines of the text, without their newlines.
+fn line_intervals(text: &str) -> Vec<Interval> {
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut offset = 0;
+    for c in text.chars() {
+        if c == '\n' {
+            lines.push(Interval::new(start, offset));
+            start = offset + 1;
+        }
+        offset += c.len_utf16();
+    }
+    if start < offset {
+        lines.push(Interval::new(start, offset));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        global_search::SearchScope,
+        manager::DocumentManager,
+        search::SearchQuery,
+        store::{save_document, MemoryDocumentStore},
+        ClientDocument, DocumentHandle, NewlineDoc,
+    };
+    use futures::StreamExt;
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn search_the_open_and_the_stored_documents() {
+        let store = Arc::new(MemoryDocumentStore::default());
+        let closed = DocumentHandle::new(ClientDocument::new::<NewlineDoc>());
+        closed.insert(0, "Meeting notes\nthe roadmap").await.unwrap();
+        save_document(store.as_ref(), "closed", &closed, 0).await.unwrap();
+
+        let manager = DocumentManager::new(store, 2);
+        let open = manager.open("open").await.unwrap();
+        open.insert(0, "Groceries\nbuy milk before the meeting").await.unwrap();
+        open.format(Interval::new(19, 25), RichTextAttribute::Bold(true))
+            .await
+            .unwrap();
+
+        let query = SearchQuery {
+            pattern: Some("meeting".to_owned()),
+            ..Default::default()
+        };
+        let results = manager
+            .search(&query, SearchScope::All)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|result| result.doc_id.as_str()).collect::<Vec<_>>(),
+            vec!["open", "closed"]
+        );
+        // The match in the title of the closed document weighs more.
+        assert!(results[1].score > results[0].score);
+        let hit = &results[0].hits[0];
+        assert_eq!(hit.interval, Interval::new(30, 37));
+        assert_eq!(
+            hit.snippet.to_json(),
+            r#"[{"insert":"buy milk "},{"insert":"before","attributes":{"bold":true}},{"insert":" the meeting"}]"#
+        );
+        assert_eq!(hit.snippet_interval, Interval::new(20, 27));
+        assert!(!manager.is_open("closed"));
+
+        let open_only = manager.search(&query, SearchScope::Open).collect::<Vec<_>>().await;
+        assert_eq!(open_only.len(), 1);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::{
+    client_document::{
+        command::{EditCommand, EditResponse},
+        history::UndoResult,
+        read_snapshot::ReadSnapshot,
+        ClientDocument, DocumentEvent,
+    },
+    entities::revision::Revision,
+    errors::CollaborateError,
+};
+use instant::Instant;
+use lib_ot::{
+    core::{Interval, IntervalSet},
+    rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta},
+};
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{broadcast, Notify, RwLock};
+
+/// How long a background job keeps the document before it lets the other writes in, see
+/// [DocumentHandle::run_background].
+pub const BACKGROUND_SLICE: Duration = Duration::from_millis(4);
+
+/// Which writes go first when they wait for the document at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePriority {
+    /// The edits of the user, e.g. a keystroke. They never wait behind the background writes.
+    Interactive,
+    /// The writes that can wait, e.g. the steps of an import or of a replace all. They start once
+    /// no interactive write is waiting.
+    Background,
+}
+
+/// What a [BackgroundJob] does after a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStep {
+    Continue,
+    Done,
+}
+
+/// A large change made in small steps, so the interactive writes get in between them, see
+/// [DocumentHandle::run_background]. Each step is applied to the document as it is then, with
+/// the edits made since the step before, e.g. a replace all that replaces the next match of the
+/// current content at each step, so the document ends up the same as if the job and the edits
+/// ran one after the other.
+pub trait BackgroundJob: Send {
+    fn step(&mut self, document: &mut ClientDocument) -> Result<JobStep, CollaborateError>;
+}
+
+impl<F> BackgroundJob for F
+where
+    F: FnMut(&mut ClientDocument) -> Result<JobStep, CollaborateError> + Send,
+{
+    fn step(&mut self, document: &mut ClientDocument) -> Result<JobStep, CollaborateError> {
+        self(document)
+    }
+}
+
+/// Shares a [ClientDocument] between the tasks of a tokio runtime. The changes are applied one
+/// at a time in the order they were requested, the lock is fair, and every change is broadcast
+/// to the subscribers as a [DocumentEvent] before the next one is applied.
+///
+/// The text typed with [DocumentHandle::type_text] is composed before the next read or change, or
+/// by a timer once it's old, see [ClientDocument::type_text].
+///
+/// The writes are interactive unless they are made with [WritePriority::Background] or by a
+/// [BackgroundJob], which wait for the interactive writes.
+#[derive(Clone)]
+pub struct DocumentHandle {
+    document: Arc<RwLock<ClientDocument>>,
+    notifier: broadcast::Sender<DocumentEvent>,
+    flush_scheduled: Arc<AtomicBool>,
+    // The last snapshot that was read, for the readers that come while a change is applied.
+    last_snapshot: Arc<Mutex<Option<ReadSnapshot>>>,
+    // The interactive writes that wait for the document or change it, and the notification that
+    // there are none anymore.
+    interactive: Arc<AtomicUsize>,
+    interactive_idle: Arc<Notify>,
+}
+
+impl DocumentHandle {
+    pub fn new(document: ClientDocument) -> Self {
+        let notifier = document.notifier();
+        Self {
+            document: Arc::new(RwLock::new(document)),
+            notifier,
+            flush_scheduled: Arc::new(AtomicBool::new(false)),
+            last_snapshot: Arc::new(Mutex::new(None)),
+            interactive: Arc::new(AtomicUsize::new(0)),
+            interactive_idle: Arc::new(Notify::new()),
+        }
+    }
+
+    // The number of the handles to the document, this one included.
+    pub(crate) fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.document)
+    }
+
+    /// See [ClientDocument::subscribe]. It doesn't wait for the change that is being applied.
+    pub fn subscribe(&self) -> broadcast::Receiver<DocumentEvent> {
+        self.notifier.subscribe()
+    }
+
+    /// Runs `f` with the document, the changes wait until it returns.
+    pub async fn read<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&ClientDocument) -> T,
+    {
+        if self.document.read().await.pending_typing().is_some() {
+            flush_typing(&mut *self.document.write().await);
+        }
+        f(&*self.document.read().await)
+    }
+
+    /// Runs `f` with the document, for the changes that don't have their own method. The reads
+    /// and the other changes wait until it returns. It's an interactive write.
+    pub async fn write<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut ClientDocument) -> T,
+    {
+        self.write_with_priority(WritePriority::Interactive, f).await
+    }
+
+    /// Like [DocumentHandle::write], the background writes wait until no interactive write is
+    /// waiting.
+    pub async fn write_with_priority<F, T>(&self, priority: WritePriority, f: F) -> T
+    where
+        F: FnOnce(&mut ClientDocument) -> T,
+    {
+        let _interactive = match priority {
+            WritePriority::Interactive => Some(InteractiveGuard::new(self)),
+            WritePriority::Background => {
+                self.wait_interactive_idle().await;
+                None
+            }
+        };
+        let mut document = self.document.write().await;
+        flush_typing(&mut document);
+        f(&mut document)
+    }
+
+    /// Runs `job` step by step until it's done, and returns it. The job keeps the document for up
+    /// to [BACKGROUND_SLICE] at a time, and lets an interactive write that comes in go first after
+    /// the step it's at. Fails with the error of a step, the steps before it stay applied.
+    pub async fn run_background<J: BackgroundJob>(&self, mut job: J) -> Result<J, CollaborateError> {
+        loop {
+            self.wait_interactive_idle().await;
+            {
+                let mut document = self.document.write().await;
+                flush_typing(&mut document);
+                let started = Instant::now();
+                loop {
+                    if job.step(&mut document)? == JobStep::Done {
+                        return Ok(job);
+                    }
+                    if self.interactive.load(Ordering::SeqCst) > 0 || started.elapsed() >= BACKGROUND_SLICE {
+                        break;
+                    }
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    async fn wait_interactive_idle(&self) {
+        loop {
+            // The notification is received from the moment the future is made.
+            let idle = self.interactive_idle.notified();
+            if self.interactive.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    /// See [ClientDocument::type_text]. It must be called from a tokio runtime, which runs the
+    /// timer that composes the buffered text.
+    pub async fn type_text<T: ToString>(&self, index: usize, data: T) -> Result<(), CollaborateError> {
+        let deadline = {
+            let _interactive = InteractiveGuard::new(self);
+            let mut document = self.document.write().await;
+            document.type_text(index, data)?;
+            document
+                .typing_deadline()
+                .map(|deadline| deadline.saturating_sub(document.config().clock.now_millis()))
+        };
+        if let Some(delay) = deadline {
+            if !self.flush_scheduled.swap(true, Ordering::SeqCst) {
+                let handle = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(delay.max(0) as u64)).await;
+                    handle.flush_scheduled.store(false, Ordering::SeqCst);
+                    flush_typing(&mut *handle.document.write().await);
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// See [ClientDocument::read_snapshot]. It doesn't wait for the change that is being applied,
+    /// the snapshot is then the one of the revision before the change, unless no snapshot was
+    /// read yet. The text buffered by [DocumentHandle::type_text] isn't flushed.
+    pub async fn read_snapshot(&self) -> ReadSnapshot {
+        let snapshot = match self.document.try_read() {
+            Ok(document) => document.read_snapshot(),
+            Err(_) => {
+                let last_snapshot = self.last_snapshot.lock().clone();
+                match last_snapshot {
+                    Some(snapshot) => return snapshot,
+                    None => self.document.read().await.read_snapshot(),
+                }
+            }
+        };
+        let mut last_snapshot = self.last_snapshot.lock();
+        if last_snapshot
+            .as_ref()
+            .map_or(true, |last| last.rev_id() < snapshot.rev_id())
+        {
+            *last_snapshot = Some(snapshot.clone());
+        }
+        snapshot
+    }
+
+    pub async fn to_json(&self) -> String {
+        self.read(|document| document.to_json()).await
+    }
+
+    pub async fn delta(&self) -> RichTextDelta {
+        self.read(|document| document.delta().clone()).await
+    }
+
+    pub async fn rev_id(&self) -> i64 {
+        self.read(|document| document.rev_id()).await
+    }
+
+    pub async fn insert<T: ToString>(&self, index: usize, data: T) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.insert(index, data)).await
+    }
+
+    pub async fn delete(&self, interval: Interval) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.delete(interval)).await
+    }
+
+    pub async fn replace<T: ToString>(&self, interval: Interval, data: T) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.replace(interval, data)).await
+    }
+
+    pub async fn format(
+        &self,
+        interval: Interval,
+        attribute: RichTextAttribute,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.format(interval, attribute)).await
+    }
+
+    /// See [ClientDocument::format_intervals].
+    pub async fn format_intervals(
+        &self,
+        intervals: IntervalSet,
+        attribute: RichTextAttribute,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.format_intervals(&intervals, attribute))
+            .await
+    }
+
+    pub async fn format_with(
+        &self,
+        interval: Interval,
+        attributes: RichTextAttributes,
+    ) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.format_with(interval, attributes)).await
+    }
+
+    /// Composes the delta made by the local user, see [ClientDocument::compose_delta].
+    pub async fn apply(&self, delta: RichTextDelta) -> Result<(), CollaborateError> {
+        self.write(|document| document.compose_delta(delta)).await
+    }
+
+    /// See [ClientDocument::compose_remote_delta].
+    pub async fn apply_remote(&self, delta: RichTextDelta) -> Result<(), CollaborateError> {
+        self.write(|document| document.compose_remote_delta(delta)).await
+    }
+
+    /// See [ClientDocument::compose_remote_revision].
+    pub async fn apply_remote_revision(&self, revision: &Revision) -> Result<RichTextDelta, CollaborateError> {
+        self.write(|document| document.compose_remote_revision(revision)).await
+    }
+
+    pub async fn undo(&self) -> Result<UndoResult, CollaborateError> {
+        self.write(|document| document.undo()).await
+    }
+
+    pub async fn redo(&self) -> Result<UndoResult, CollaborateError> {
+        self.write(|document| document.redo()).await
+    }
+
+    /// See [ClientDocument::handle].
+    pub async fn handle(&self, command: EditCommand) -> Result<EditResponse, CollaborateError> {
+        self.write(|document| document.handle(command)).await
+    }
+}
+
+// Counts an interactive write from before it waits for the document until it's done.
+struct InteractiveGuard<'a>(&'a DocumentHandle);
+
+impl<'a> InteractiveGuard<'a> {
+    fn new(handle: &'a DocumentHandle) -> Self {
+        handle.interactive.fetch_add(1, Ordering::SeqCst);
+        Self(handle)
+    }
+}
+
+impl<'a> std::ops::Drop for InteractiveGuard<'a> {
+    fn drop(&mut self) {
+        if self.0.interactive.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.interactive_idle.notify_waiters();
+        }
+    }
+}
+
+fn flush_typing(document: &mut ClientDocument) {
+    if document.pending_typing().is_none() {
+        return;
+    }
+    if let Err(e) = document.flush_typing() {
+        tracing::error!("Compose the typed text failed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{ClientDocument, DocumentEventSource, DocumentHandle, JobStep, NewlineDoc};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn document_handle_serializes_the_changes() {
+        let handle = DocumentHandle::new(ClientDocument::new::<NewlineDoc>());
+        let mut receiver = handle.subscribe();
+
+        let tasks = (0..10)
+            .map(|_| {
+                let handle = handle.clone();
+                tokio::spawn(async move { handle.insert(0, "a").await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(handle.to_json().await, r#"[{"insert":"aaaaaaaaaa\n"}]"#);
+
+        let mut rev_ids = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            assert_eq!(event.source, DocumentEventSource::Local);
+            rev_ids.push(event.rev_id);
+        }
+        assert_eq!(rev_ids, (1..=10).collect::<Vec<_>>());
+
+        // The snapshot is read while the document is being changed.
+        let snapshot = handle.read_snapshot().await;
+        let guard = handle.document.write().await;
+        assert_eq!(handle.read_snapshot().await.rev_id(), snapshot.rev_id());
+        drop(guard);
+
+        handle.undo().await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap().source, DocumentEventSource::Undo);
+        assert_eq!(handle.rev_id().await, 11);
+    }
+
+    #[tokio::test]
+    async fn interactive_writes_go_between_the_steps_of_a_background_job() {
+        let handle = DocumentHandle::new(ClientDocument::new::<NewlineDoc>());
+        let steps = Arc::new(AtomicUsize::new(0));
+        let job = {
+            let steps = steps.clone();
+            move |document: &mut ClientDocument| {
+                std::thread::sleep(Duration::from_millis(1));
+                let len = document.delta().utf16_target_len;
+                document.insert(len - 1, "b")?;
+                match steps.fetch_add(1, Ordering::SeqCst) + 1 {
+                    50 => Ok(JobStep::Done),
+                    _ => Ok(JobStep::Continue),
+                }
+            }
+        };
+        let task = {
+            let handle = handle.clone();
+            tokio::spawn(async move { handle.run_background(job).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+
+        // The keystroke waits for the slice of the job, not for the whole job.
+        handle.insert(0, "a").await.unwrap();
+        assert!(steps.load(Ordering::SeqCst) < 50);
+        task.await.unwrap().unwrap();
+        assert_eq!(
+            handle.to_json().await,
+            format!(r#"[{{"insert":"a{}\n"}}]"#, "b".repeat(50))
+        );
+    }
+}
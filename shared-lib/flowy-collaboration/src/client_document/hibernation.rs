@@ -0,0 +1,138 @@
+use crate::{client_document::anchor::Anchors, entities::revision::MetadataEntry, errors::CollaborateError};
+use dashmap::DashMap;
+use lib_ot::rich_text::{RichTextAttributes, RichTextDelta};
+use std::sync::Arc;
+
+/// Keeps the states of the hibernated documents, see
+/// [DocumentManager::hibernate](crate::client_document::manager::DocumentManager::hibernate). The
+/// state must outlive the process, e.g. the app that is killed in the background, so the stores
+/// of the apps write it to the disk.
+pub trait HibernationStore: Send + Sync {
+    fn read_state(&self, doc_id: &str) -> Result<Option<Vec<u8>>, CollaborateError>;
+
+    fn write_state(&self, doc_id: &str, state: &[u8]) -> Result<(), CollaborateError>;
+
+    fn remove_state(&self, doc_id: &str) -> Result<(), CollaborateError>;
+}
+
+pub type HibernationStoreRef = Arc<dyn HibernationStore>;
+
+#[derive(Default)]
+pub struct MemoryHibernationStore {
+    states: DashMap<String, Vec<u8>>,
+}
+
+impl HibernationStore for MemoryHibernationStore {
+    fn read_state(&self, doc_id: &str) -> Result<Option<Vec<u8>>, CollaborateError> {
+        Ok(self.states.get(doc_id).map(|state| state.clone()))
+    }
+
+    fn write_state(&self, doc_id: &str, state: &[u8]) -> Result<(), CollaborateError> {
+        self.states.insert(doc_id.to_owned(), state.to_vec());
+        Ok(())
+    }
+
+    fn remove_state(&self, doc_id: &str) -> Result<(), CollaborateError> {
+        self.states.remove(doc_id);
+        Ok(())
+    }
+}
+
+// How the state of a document is encoded by
+// [ClientDocument::encode_state](crate::client_document::ClientDocument::encode_state).
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct DocumentState {
+    pub(crate) rev_id: i64,
+    // The content at the start of the revision log, and the revisions of the log in protobuf,
+    // which the sync may not have sent yet.
+    pub(crate) base_rev_id: i64,
+    pub(crate) base: RichTextDelta,
+    pub(crate) revisions: Vec<Vec<u8>>,
+    pub(crate) tags: Vec<u8>,
+    pub(crate) history: Vec<u8>,
+    pub(crate) anchors: Anchors,
+    pub(crate) metadata: Vec<MetadataEntryData>,
+    pub(crate) default_attributes: RichTextAttributes,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct MetadataEntryData {
+    key: String,
+    value: String,
+    is_removed: bool,
+    timestamp: i64,
+    device_id: String,
+}
+
+impl std::convert::From<&MetadataEntry> for MetadataEntryData {
+    fn from(entry: &MetadataEntry) -> Self {
+        Self {
+            key: entry.key.clone(),
+            value: entry.value.clone(),
+            is_removed: entry.is_removed,
+            timestamp: entry.timestamp,
+            device_id: entry.device_id.clone(),
+        }
+    }
+}
+
+impl std::convert::From<MetadataEntryData> for MetadataEntry {
+    fn from(data: MetadataEntryData) -> Self {
+        MetadataEntry {
+            key: data.key,
+            value: data.value,
+            is_removed: data.is_removed,
+            timestamp: data.timestamp,
+            device_id: data.device_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        anchor::AnchorBias,
+        hibernation::{HibernationStore, MemoryHibernationStore},
+        manager::DocumentManager,
+        store::MemoryDocumentStore,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn hibernated_document_resumes_as_it_was() {
+        let states = Arc::new(MemoryHibernationStore::default());
+        let manager =
+            DocumentManager::new(Arc::new(MemoryDocumentStore::default()), 2).with_hibernation(states.clone());
+        let handle = manager.open("a").await.unwrap();
+        handle.insert(0, "hello").await.unwrap();
+        let anchor = handle
+            .write(|document| {
+                document.set_metadata("title", "Greeting").unwrap();
+                document.revision_log_mut().tag(1, "draft").unwrap();
+                document.create_anchor(5, AnchorBias::Right)
+            })
+            .await
+            .unwrap();
+        // The typed text waits in the typing buffer until the document is hibernated.
+        handle.write(|document| document.type_text(5, " world")).await.unwrap();
+
+        manager.hibernate("a").await.unwrap();
+        assert!(!manager.is_open("a"));
+        assert!(states.read_state("a").unwrap().is_some());
+
+        let handle = manager.resume("a").await.unwrap();
+        assert!(states.read_state("a").unwrap().is_none());
+        handle
+            .read(|document| {
+                assert_eq!(document.to_plain_string(), "hello world\n");
+                assert_eq!(document.rev_id(), 3);
+                assert_eq!(document.revision_log().revisions().len(), 3);
+                assert!(document.revision_log().get_tag("draft").is_some());
+                assert_eq!(document.metadata().get("title"), Some("Greeting"));
+                assert_eq!(document.resolve_anchor(anchor), Some(11));
+            })
+            .await;
+        handle.undo().await.unwrap();
+        assert_eq!(handle.read(|document| document.to_plain_string()).await, "hello\n");
+    }
+}
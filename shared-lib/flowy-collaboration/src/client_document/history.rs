@@ -1,19 +1,290 @@
-use lib_ot::rich_text::RichTextDelta;
+use crate::{
+    client_document::{
+        audit::{HistoryEntryMeta, HistoryStack, OpCounts},
+        format::{decode_json, encode_json, PayloadKind},
+    },
+    entities::revision::md5,
+    errors::{internal_error, CollaborateError},
+};
+use bytes::Bytes;
+use lib_ot::{
+    core::{Attributes, Interval, Operation, OperationTransformable},
+    errors::OTError,
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+use std::borrow::Cow;
 
-const MAX_UNDOES: usize = 20;
+pub(crate) const MAX_UNDOES: usize = 20;
+
+// The size of an entry, see [lib_ot::core::Delta::memory_usage], above which the entry keeps the
+// revision it reverts to instead of its delta.
+pub(crate) const MAX_ENTRY_SIZE: usize = 1024 * 1024;
 
 #[derive(Debug, Clone)]
 pub struct UndoResult {
+    /// The delta that was applied to the document, which the editor can apply in place of
+    /// rendering the whole document again.
     pub delta: RichTextDelta,
+    /// Where the caret goes after the change: the end of the last insert, or the position of the
+    /// last delete.
+    pub caret: usize,
+    /// The selection to restore: the one before the change that was undone, or the one after the
+    /// change that was redone. It's the caret if the history doesn't know it.
+    pub selection: Interval,
+}
+
+impl UndoResult {
+    pub fn new(delta: RichTextDelta, selection: Option<Interval>) -> Self {
+        let mut caret = 0;
+        let mut offset = 0;
+        for op in &delta.ops {
+            match op {
+                Operation::Retain(retain) => {
+                    offset += retain.n;
+                    if !retain.is_plain() {
+                        caret = offset;
+                    }
+                }
+                Operation::Insert(insert) => {
+                    offset += insert.utf16_size();
+                    caret = offset;
+                }
+                Operation::Delete(_) => caret = offset,
+            }
+        }
+        let selection = selection.unwrap_or_else(|| Interval::new(caret, caret));
+        Self {
+            delta,
+            caret,
+            selection,
+        }
+    }
+}
+
+/// The selections around a change. The undo of the change restores `before`, its redo `after`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionChange {
+    pub before: Interval,
+    pub after: Interval,
+}
+
+impl SelectionChange {
+    /// Returns the selections around the change made by `delta`, the way the editor selects the
+    /// text it edits: the text that `delta` replaces or formats before it, and the caret after its
+    /// last insert or delete after it, or the formatted text if it only formats. Returns `None` if
+    /// `delta` doesn't change anything.
+    pub fn from_delta(delta: &RichTextDelta) -> Option<Self> {
+        let (mut before, mut after): (Option<Interval>, Option<Interval>) = (None, None);
+        let (mut old_offset, mut new_offset) = (0, 0);
+        let mut edits_text = false;
+        for op in &delta.ops {
+            let (old_len, new_len) = match op {
+                Operation::Retain(retain) if retain.is_plain() => {
+                    old_offset += retain.n;
+                    new_offset += retain.n;
+                    continue;
+                }
+                Operation::Retain(retain) => (retain.n, retain.n),
+                Operation::Insert(insert) => (0, insert.utf16_size()),
+                Operation::Delete(n) => (*n, 0),
+            };
+            edits_text |= !matches!(op, Operation::Retain(_));
+            before = Some(span(before, old_offset, old_len));
+            after = Some(span(after, new_offset, new_len));
+            old_offset += old_len;
+            new_offset += new_len;
+        }
+        let (before, after) = (before?, after?);
+        let after = match edits_text {
+            true => Interval::new(after.end, after.end),
+            false => after,
+        };
+        Some(Self { before, after })
+    }
+
+    /// Returns the selections around the change that reverts this one.
+    pub fn inverted(&self) -> Self {
+        Self {
+            before: self.after,
+            after: self.before,
+        }
+    }
+}
+
+// Extends `interval`, if there is one, to cover the `len` characters at `offset`.
+fn span(interval: Option<Interval>, offset: usize, len: usize) -> Interval {
+    match interval {
+        None => Interval::new(offset, offset + len),
+        Some(interval) => Interval::new(interval.start.min(offset), interval.end.max(offset + len)),
+    }
+}
+
+/// An entry of the undo or the redo stack.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    /// The revision of the document that the entry reverts to, if it's known. It isn't once the
+    /// entry was transformed by the changes of the others.
+    pub rev_id: Option<i64>,
+    /// The delta that reverts the change. An entry whose delta is too large keeps only the
+    /// revision, the delta is rebuilt from the content of the document at the revision when the
+    /// entry is applied.
+    pub delta: Option<RichTextDelta>,
+    /// The selections around the change that the entry reverts. Applying the entry restores the
+    /// selection before that change. `after` is in the document that the entry applies to,
+    /// `before` in the document that it produces.
+    pub selection: Option<SelectionChange>,
+}
+
+impl HistoryEntry {
+    fn new(delta: RichTextDelta, rev_id: Option<i64>, selection: Option<SelectionChange>, max_size: usize) -> Self {
+        match rev_id {
+            Some(rev_id) if delta.memory_usage() > max_size => Self {
+                rev_id: Some(rev_id),
+                delta: None,
+                selection,
+            },
+            _ => Self {
+                rev_id,
+                delta: Some(delta),
+                selection,
+            },
+        }
+    }
+
+    /// Whether the entry only changes the attributes of the text, e.g. it reverts a format.
+    pub fn is_format(&self) -> bool {
+        self.delta.as_ref().map_or(false, |delta| {
+            delta.ops.iter().all(|op| op.is_retain()) && !delta.is_empty()
+        })
+    }
+}
+
+/// Keeps the older entries of the undo stack compressed, for the devices that can't keep the
+/// deltas of a large document in memory, see [History::set_compression]. The entries are
+/// decompressed when the undoes reach them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryCompression {
+    /// The number of entries at the top of the undo stack that are kept as they are.
+    pub hot_entries: usize,
+    /// The zstd level of the entries below them. The browser builds don't have zstd, they only
+    /// encode the entries.
+    pub level: i32,
+}
+
+impl std::default::Default for HistoryCompression {
+    fn default() -> Self {
+        Self {
+            hot_entries: 4,
+            level: 3,
+        }
+    }
+}
+
+// An entry of the undo stack whose delta is encoded and compressed.
+#[derive(Debug, Clone)]
+struct ColdEntry {
+    rev_id: Option<i64>,
+    data: Option<Vec<u8>>,
+    selection: Option<SelectionChange>,
+}
+
+impl ColdEntry {
+    fn compress(entry: HistoryEntry, level: i32) -> Result<Self, CollaborateError> {
+        let data = match entry.delta {
+            None => None,
+            Some(delta) => Some(compress(&delta.to_bytes(), level)?),
+        };
+        Ok(Self {
+            rev_id: entry.rev_id,
+            data,
+            selection: entry.selection,
+        })
+    }
+
+    fn decompress(&self) -> Result<HistoryEntry, CollaborateError> {
+        let delta = match &self.data {
+            None => None,
+            Some(data) => {
+                let bytes = decompress(data)?;
+                Some(RichTextDelta::from_bytes(&bytes)?)
+            }
+        };
+        Ok(HistoryEntry {
+            rev_id: self.rev_id,
+            delta,
+            selection: self.selection,
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn compress(bytes: &[u8], level: i32) -> Result<Vec<u8>, CollaborateError> {
+    zstd::encode_all(bytes, level).map_err(internal_error)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn decompress(data: &[u8]) -> Result<Vec<u8>, CollaborateError> {
+    zstd::decode_all(data).map_err(internal_error)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn compress(bytes: &[u8], _level: i32) -> Result<Vec<u8>, CollaborateError> {
+    Ok(bytes.to_vec())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn decompress(data: &[u8]) -> Result<Vec<u8>, CollaborateError> {
+    Ok(data.to_vec())
+}
+
+// What is kept of the history when the document is closed. The entries only apply to the document
+// they were made on, the md5 of its text is kept along. The text is used because the attributes
+// aren't encoded in a stable order.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HistoryData {
+    md5: String,
+    undoes: Vec<RichTextDelta>,
+    redoes: Vec<RichTextDelta>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    branches: Vec<BranchData>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BranchData {
+    depth: usize,
+    redoes: Vec<RichTextDelta>,
+}
+
+pub type BranchId = u64;
+
+/// The changes that were undone and then replaced by a new change, when the history keeps its
+/// branches, see [History::set_branching]. The branch starts from the document after the
+/// `depth` oldest changes of the undo stack, its redo stack redoes the changes that were made
+/// from there.
+#[derive(Debug, Clone)]
+pub struct HistoryBranch {
+    pub id: BranchId,
+    pub depth: usize,
+    /// The entries that redo the changes of the branch, the first change last.
+    pub redoes: Vec<HistoryEntry>,
 }
 
 #[derive(Debug, Clone)]
 pub struct History {
     #[allow(dead_code)]
     cur_undo: usize,
-    undoes: Vec<RichTextDelta>,
-    redoes: Vec<RichTextDelta>,
+    undoes: Vec<HistoryEntry>,
+    // The compressed entries below `undoes`, the oldest first.
+    cold_undoes: Vec<ColdEntry>,
+    redoes: Vec<HistoryEntry>,
+    // The branches that fork from the changes of the undo and the redo stacks.
+    branches: Vec<HistoryBranch>,
+    next_branch_id: BranchId,
+    branching: bool,
     capacity: usize,
+    max_entry_size: usize,
+    compression: Option<HistoryCompression>,
+    check_invariants: bool,
 }
 
 impl std::default::Default for History {
@@ -21,8 +292,15 @@ impl std::default::Default for History {
         History {
             cur_undo: 1,
             undoes: Vec::new(),
+            cold_undoes: Vec::new(),
             redoes: Vec::new(),
+            branches: Vec::new(),
+            next_branch_id: 0,
+            branching: false,
             capacity: MAX_UNDOES,
+            max_entry_size: MAX_ENTRY_SIZE,
+            compression: None,
+            check_invariants: false,
         }
     }
 }
@@ -32,49 +310,708 @@ impl History {
         History::default()
     }
 
+    /// Sets the number of changes that can be undone. The oldest entries over it are dropped.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    /// Sets the size of an entry, see [lib_ot::core::Delta::memory_usage], above which the entry
+    /// keeps the revision it reverts to instead of its delta.
+    pub fn set_max_entry_size(&mut self, max_entry_size: usize) {
+        self.max_entry_size = max_entry_size;
+    }
+
+    /// Compresses the entries of the undo stack below the top ones, or decompresses them all if
+    /// `compression` is `None`. The redo stack is cleared on the next change, it isn't compressed.
+    pub fn set_compression(&mut self, compression: Option<HistoryCompression>) {
+        self.compression = compression;
+        self.rehydrate(usize::MAX);
+        self.compress();
+    }
+
+    /// Keeps the changes that were undone when a new change is made, as a branch of the history
+    /// that can be switched to, see [History::switch_branch]. Otherwise the redo stack is
+    /// dropped, and the branches are dropped with it, by the next change.
+    pub fn set_branching(&mut self, branching: bool) {
+        self.branching = branching;
+    }
+
+    /// The branches of the history, from the oldest.
+    pub fn branches(&self) -> &[HistoryBranch] {
+        &self.branches
+    }
+
+    /// Replaces the redo stack by the one of the branch `id`, which becomes a branch in its
+    /// place. The changes of the undo stack above the depth of the branch must be undone, or the
+    /// ones of the redo stack below it redone, first.
+    pub fn switch_branch(&mut self, id: BranchId) -> Result<(), CollaborateError> {
+        let index = self
+            .branches
+            .iter()
+            .position(|branch| branch.id == id)
+            .ok_or_else(|| CollaborateError::undo().context(format!("There is no branch {}", id)))?;
+        if self.branches[index].depth != self.undo_len() {
+            return Err(CollaborateError::undo().context(format!(
+                "The branch {} starts at the depth {}, not {}",
+                id,
+                self.branches[index].depth,
+                self.undo_len()
+            )));
+        }
+        let branch = self.branches.remove(index);
+        self.add_branch(std::mem::replace(&mut self.redoes, branch.redoes));
+        Ok(())
+    }
+
+    /// Drops the branches, only the changes of the undo and the redo stacks are kept.
+    pub fn linearize(&mut self) {
+        self.branches.clear();
+    }
+
+    /// Checks, in the debug builds, that every entry of the history reverts the change it was
+    /// made for. It's meant for the tests, the check composes the whole document on each change.
+    pub fn set_check_invariants(&mut self, check_invariants: bool) {
+        self.check_invariants = check_invariants;
+    }
+
+    /// Asserts that composing `inverted` into `after`, the document that a change turned
+    /// `before` into, gives back `before`.
+    pub(crate) fn check_inversion(&self, before: &RichTextDelta, after: &RichTextDelta, inverted: &RichTextDelta) {
+        if !self.check_invariants || !cfg!(debug_assertions) {
+            return;
+        }
+        let reverted = after.compose(inverted).map(|delta| normalize(&delta));
+        debug_assert_eq!(
+            reverted.ok(),
+            Some(normalize(before)),
+            "{} doesn't revert the change made to {}",
+            inverted,
+            before
+        );
+    }
+
     pub fn can_undo(&self) -> bool {
-        !self.undoes.is_empty()
+        !self.undoes.is_empty() || !self.cold_undoes.is_empty()
     }
 
     pub fn can_redo(&self) -> bool {
         !self.redoes.is_empty()
     }
 
-    pub fn add_undo(&mut self, delta: RichTextDelta) {
-        self.undoes.push(delta);
+    pub fn undo_len(&self) -> usize {
+        self.undoes.len() + self.cold_undoes.len()
     }
 
-    pub fn add_redo(&mut self, delta: RichTextDelta) {
-        self.redoes.push(delta);
+    pub fn redo_len(&self) -> usize {
+        self.redoes.len()
     }
 
-    pub fn record(&mut self, delta: RichTextDelta) {
+    /// Estimates the bytes that the entries take, the compressed ones included.
+    pub fn memory_usage(&self) -> usize {
+        let entries = |entries: &[HistoryEntry]| -> usize {
+            entries
+                .iter()
+                .map(|entry| entry.delta.as_ref().map_or(0, |delta| delta.memory_usage()))
+                .sum()
+        };
+        let cold: usize = self
+            .cold_undoes
+            .iter()
+            .map(|entry| entry.data.as_ref().map_or(0, |data| data.capacity()))
+            .sum();
+        let branches: usize = self.branches.iter().map(|branch| entries(&branch.redoes)).sum();
+        entries(&self.undoes) + entries(&self.redoes) + cold + branches
+    }
+
+    /// The entries of the undo stack, the top one last. The compressed entries below them aren't
+    /// included, see [History::set_compression].
+    pub fn undoes(&self) -> &[HistoryEntry] {
+        &self.undoes
+    }
+
+    /// The entries of the redo stack, the top one last.
+    pub fn redoes(&self) -> &[HistoryEntry] {
+        &self.redoes
+    }
+
+    /// Describes the entries of the undo stack, the compressed ones included, from the oldest to
+    /// the top one, and then the ones of the redo stack from the bottom to the top. The
+    /// compressed entries aren't decompressed.
+    pub fn entries_meta(&self) -> Vec<HistoryEntryMeta> {
+        let meta = |stack: HistoryStack, entry: &HistoryEntry| HistoryEntryMeta {
+            stack,
+            rev_id: entry.rev_id,
+            size: entry.delta.as_ref().map_or(0, |delta| delta.memory_usage()),
+            ops: entry.delta.as_ref().map(OpCounts::from_delta),
+            is_compressed: false,
+        };
+        let cold = self.cold_undoes.iter().map(|entry| HistoryEntryMeta {
+            stack: HistoryStack::Undo,
+            rev_id: entry.rev_id,
+            size: entry.data.as_ref().map_or(0, |data| data.len()),
+            ops: None,
+            is_compressed: entry.data.is_some(),
+        });
+        cold.chain(self.undoes.iter().map(|entry| meta(HistoryStack::Undo, entry)))
+            .chain(self.redoes.iter().map(|entry| meta(HistoryStack::Redo, entry)))
+            .collect()
+    }
+
+    /// The oldest revision that the entries which keep only their revision are rebuilt from, see
+    /// [HistoryEntry::delta]. The revision log must keep the revisions from it on.
+    pub fn oldest_rebuilt_rev_id(&self) -> Option<i64> {
+        let rebuilt = |entry: &HistoryEntry| entry.delta.as_ref().map_or(entry.rev_id, |_| None);
+        let cold = self
+            .cold_undoes
+            .iter()
+            .filter_map(|entry| entry.data.as_ref().map_or(entry.rev_id, |_| None));
+        let branches = self.branches.iter().flat_map(|branch| branch.redoes.iter());
+        cold.chain(self.undoes.iter().filter_map(rebuilt))
+            .chain(self.redoes.iter().filter_map(rebuilt))
+            .chain(branches.filter_map(rebuilt))
+            .min()
+    }
+
+    /// Adds `delta`, which reverts the document to the revision `rev_id`, to the undo stack.
+    /// `selection` is the selections around the change that `delta` reverts.
+    pub fn add_undo(&mut self, delta: RichTextDelta, rev_id: Option<i64>, selection: Option<SelectionChange>) {
+        let entry = HistoryEntry::new(delta, rev_id, selection, self.max_entry_size);
+        self.push_undo(entry);
+    }
+
+    pub fn add_redo(&mut self, delta: RichTextDelta, rev_id: Option<i64>, selection: Option<SelectionChange>) {
+        let entry = HistoryEntry::new(delta, rev_id, selection, self.max_entry_size);
+        self.redoes.push(entry);
+    }
+
+    /// Puts back an entry that was taken by [History::undo].
+    pub fn push_undo(&mut self, entry: HistoryEntry) {
+        self.undoes.push(entry);
+        self.compress();
+    }
+
+    pub fn record(&mut self, delta: RichTextDelta, rev_id: Option<i64>, selection: Option<SelectionChange>) {
         if delta.ops.is_empty() {
             return;
         }
 
+        let redoes = std::mem::take(&mut self.redoes);
+        self.fork(redoes);
+        self.add_undo(delta, rev_id, selection);
+
+        self.trim();
+    }
+
+    pub fn undo(&mut self) -> Option<HistoryEntry> {
+        if self.undoes.is_empty() {
+            let hot_entries = self.compression.map(|compression| compression.hot_entries).unwrap_or(0);
+            self.rehydrate(hot_entries.max(1));
+        }
+        self.undoes.pop()
+    }
+
+    pub fn redo(&mut self) -> Option<HistoryEntry> {
+        self.redoes.pop()
+    }
+
+    /// Takes the newest entry of the undo stack that `predicate` matches, e.g. the last format
+    /// with [HistoryEntry::is_format], to undo it without undoing the changes made after it. The
+    /// entry is rebased over these changes so it applies to `document`, and the entries of these
+    /// changes are rebased over the entry so they don't bring back what it reverts. The redo stack
+    /// and the branches that start above the entry don't apply to the document anymore, they are
+    /// dropped. The entries below one that keeps a revision instead of its delta can't be taken.
+    pub fn undo_matching<F>(
+        &mut self,
+        document: &RichTextDelta,
+        mut predicate: F,
+    ) -> Result<Option<HistoryEntry>, CollaborateError>
+    where
+        F: FnMut(&HistoryEntry) -> bool,
+    {
+        self.rehydrate(usize::MAX);
+        // The changes made after the entry, the newest first, each in the document before it.
+        let mut changes = vec![];
+        let mut document = document.clone();
+        let mut found = None;
+        for index in (0..self.undoes.len()).rev() {
+            let mut delta = match &self.undoes[index].delta {
+                None => break,
+                Some(delta) => delta.clone(),
+            };
+            pad(&mut delta, document.utf16_target_len);
+            if predicate(&self.undoes[index]) {
+                found = Some((index, delta));
+                break;
+            }
+            let before = document.compose(&delta)?;
+            let mut change = delta.invert(&document);
+            pad(&mut change, before.utf16_target_len);
+            changes.push(change);
+            document = before;
+        }
+        let (index, mut undo) = match found {
+            None => return Ok(None),
+            Some(found) => found,
+        };
+
+        // The document without the change that the entry reverts, while its later changes are
+        // rebased, and the entry in the document before each of them.
+        let mut rebased = document.compose(&undo)?;
+        let mut undo_levels = vec![undo.clone()];
+        let mut rebased_changes = vec![];
+        for change in changes.iter().rev() {
+            let (undo_prime, change_prime) = undo.transform(change)?;
+            let mut entry = change_prime.invert(&rebased);
+            rebased = rebased.compose(&change_prime)?;
+            pad(&mut entry, rebased.utf16_target_len);
+            rebased_changes.push((change_prime, entry));
+            undo = undo_prime;
+            undo_levels.push(undo.clone());
+        }
+
+        let mut above = self.undoes.split_off(index);
+        let taken = above.remove(0);
+        for (level, (entry, (_, delta))) in above.into_iter().zip(rebased_changes.iter()).enumerate() {
+            let selection = entry.selection.map(|selection| SelectionChange {
+                before: transform_interval(&undo_levels[level], selection.before),
+                after: transform_interval(&undo_levels[level + 1], selection.after),
+            });
+            self.undoes.push(HistoryEntry {
+                rev_id: None,
+                delta: Some(delta.clone()),
+                selection,
+            });
+        }
+        let selection = taken.selection.map(|selection| SelectionChange {
+            before: rebased_changes.iter().fold(selection.before, |interval, (change, _)| {
+                transform_interval(change, interval)
+            }),
+            after: changes
+                .iter()
+                .rev()
+                .fold(selection.after, |interval, change| transform_interval(change, interval)),
+        });
         self.redoes.clear();
-        self.add_undo(delta);
+        self.branches.retain(|branch| branch.depth <= index);
+        self.compress();
+        Ok(Some(HistoryEntry {
+            rev_id: None,
+            delta: Some(undo),
+            selection,
+        }))
+    }
+
+    /// Encodes the history of `document` so it can be saved along the snapshot of the document.
+    /// Only the last `capacity` entries of each stack are kept, down to the first entry that
+    /// keeps a revision instead of its delta, because the revisions aren't kept along.
+    pub fn encode(&self, document: &RichTextDelta) -> Result<Bytes, CollaborateError> {
+        let undoes = match self.cold_undoes.is_empty() {
+            true => Cow::Borrowed(&self.undoes),
+            false => {
+                let mut undoes = self
+                    .cold_undoes
+                    .iter()
+                    .map(ColdEntry::decompress)
+                    .collect::<Result<Vec<_>, _>>()?;
+                undoes.extend(self.undoes.iter().cloned());
+                Cow::Owned(undoes)
+            }
+        };
+        let (undoes, redoes) = (
+            last_entries(&undoes, self.capacity),
+            last_entries(&self.redoes, self.capacity),
+        );
+        // The depths of the branches are counted from the oldest entry that is kept.
+        let dropped = self.undo_len() - undoes.len();
+        let branches = self
+            .branches
+            .iter()
+            .filter(|branch| branch.depth >= dropped && branch.depth - dropped <= undoes.len() + redoes.len())
+            .map(|branch| BranchData {
+                depth: branch.depth - dropped,
+                redoes: last_entries(&branch.redoes, self.capacity),
+            })
+            .collect();
+        let data = HistoryData {
+            md5: text_md5(document),
+            undoes,
+            redoes,
+            branches,
+        };
+        let json = encode_json(PayloadKind::History, &data)?;
+        Ok(Bytes::from(json))
+    }
+
+    /// Decodes the history that was encoded by [History::encode], by this version or an older one.
+    /// It fails if the history was encoded by a newer version or if it wasn't made on `document`,
+    /// e.g. the document received the changes of the others since.
+    pub fn decode(bytes: &[u8], document: &RichTextDelta) -> Result<History, CollaborateError> {
+        let data: HistoryData = decode_json(PayloadKind::History, bytes)?;
+        if data.md5 != text_md5(document) {
+            return Err(CollaborateError::internal().context("The history was made on another document"));
+        }
+        let entries = |deltas: Vec<RichTextDelta>| {
+            deltas
+                .into_iter()
+                .map(|delta| HistoryEntry {
+                    rev_id: None,
+                    delta: Some(delta),
+                    selection: None,
+                })
+                .collect()
+        };
+        let branches = data
+            .branches
+            .into_iter()
+            .enumerate()
+            .map(|(id, branch)| HistoryBranch {
+                id: id as BranchId + 1,
+                depth: branch.depth,
+                redoes: entries(branch.redoes),
+            })
+            .collect::<Vec<_>>();
+        Ok(History {
+            undoes: entries(data.undoes),
+            redoes: entries(data.redoes),
+            next_branch_id: branches.len() as BranchId,
+            branches,
+            ..History::default()
+        })
+    }
+
+    /// Transforms the undo and redo entries through `delta`, which was made by another participant
+    /// on top of the document of `len`. The entries keep reverting the local changes only, without
+    /// touching the text of the others. The entries that keep a revision can't be transformed, they
+    /// are dropped with the entries below them, the compressed entries are decompressed to be
+    /// transformed. The history is cleared if it can't be transformed.
+    pub fn transform(&mut self, delta: &RichTextDelta, len: usize) {
+        self.rehydrate(usize::MAX);
+        let undo_len = self.undoes.len();
+        let result = transform_stack(&mut self.undoes, delta, len)
+            .and_then(|undo_levels| Ok((undo_levels, transform_stack(&mut self.redoes, delta, len)?)));
+        match result {
+            Ok((undo_levels, redo_levels)) => {
+                let dropped = undo_len - self.undoes.len();
+                for mut branch in std::mem::take(&mut self.branches) {
+                    // The change of the other participant, in the document that the branch starts from.
+                    let level = match branch.depth <= undo_len {
+                        true => undo_levels.get(undo_len - branch.depth),
+                        false => redo_levels.get(branch.depth - undo_len),
+                    };
+                    if let Some((delta, len)) = level {
+                        if branch.depth >= dropped && transform_stack(&mut branch.redoes, delta, *len).is_ok() {
+                            branch.depth -= dropped;
+                            self.branches.push(branch);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!("Transform the history failed: {:?}", e);
+                self.undoes.clear();
+                self.redoes.clear();
+                self.branches.clear();
+            }
+        }
+        self.compress();
+    }
+
+    // Keeps `redoes`, the redo stack that the next change replaces, as a branch when the history
+    // is branching. The branches that start from its changes are moved down to its depth.
+    fn fork(&mut self, redoes: Vec<HistoryEntry>) {
+        let depth = self.undo_len();
+        let mut branches = vec![];
+        for mut branch in std::mem::take(&mut self.branches) {
+            if branch.depth <= depth {
+                branches.push(branch);
+            } else if self.branching && branch.depth - depth <= redoes.len() {
+                // The entries of `redoes` that lead from its start to the start of the branch.
+                let count = branch.depth - depth;
+                branch.redoes.extend(redoes[redoes.len() - count..].iter().cloned());
+                branch.depth = depth;
+                branches.push(branch);
+            }
+        }
+        self.branches = branches;
+        if self.branching {
+            self.add_branch(redoes);
+        }
+    }
 
-        if self.undoes.len() > self.capacity {
-            self.undoes.remove(0);
+    fn add_branch(&mut self, redoes: Vec<HistoryEntry>) {
+        if redoes.is_empty() {
+            return;
+        }
+        self.next_branch_id += 1;
+        self.branches.push(HistoryBranch {
+            id: self.next_branch_id,
+            depth: self.undo_len(),
+            redoes,
+        });
+    }
+
+    // Drops the oldest entries of the undo stack over the capacity.
+    fn trim(&mut self) {
+        while self.undo_len() > self.capacity {
+            if self.cold_undoes.is_empty() {
+                self.undoes.remove(0);
+            } else {
+                self.cold_undoes.remove(0);
+            }
+            // The branches that start from the document before the oldest entry can't be reached.
+            self.branches.retain(|branch| branch.depth > 0);
+            self.branches.iter_mut().for_each(|branch| branch.depth -= 1);
+        }
+    }
+
+    // Compresses the entries of the undo stack below the top ones, see [HistoryCompression].
+    fn compress(&mut self) {
+        let compression = match self.compression {
+            None => return,
+            Some(compression) => compression,
+        };
+        if self.undoes.len() <= compression.hot_entries {
+            return;
+        }
+        let count = self.undoes.len() - compression.hot_entries;
+        for entry in self.undoes.drain(..count) {
+            match ColdEntry::compress(entry, compression.level) {
+                Ok(cold) => self.cold_undoes.push(cold),
+                Err(e) => {
+                    // The entries below the one that is lost can't be undone either.
+                    tracing::error!("Compress the history entry failed: {}", e);
+                    self.cold_undoes.clear();
+                }
+            }
+        }
+    }
+
+    // Decompresses the `count` newest compressed entries, which go below the entries of the undo
+    // stack. The entries below an entry that can't be decompressed are dropped with it.
+    fn rehydrate(&mut self, count: usize) {
+        if self.cold_undoes.is_empty() {
+            return;
+        }
+        let start = self.cold_undoes.len().saturating_sub(count);
+        let mut entries = Vec::with_capacity(self.cold_undoes.len() - start + self.undoes.len());
+        let mut is_lost = false;
+        for cold in self.cold_undoes.drain(start..) {
+            match cold.decompress() {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    tracing::error!("Decompress the history entry failed: {}", e);
+                    entries.clear();
+                    is_lost = true;
+                }
+            }
+        }
+        if is_lost {
+            self.cold_undoes.clear();
+        }
+        entries.append(&mut self.undoes);
+        self.undoes = entries;
+    }
+}
+
+fn text_md5(document: &RichTextDelta) -> String {
+    md5(document.apply("").unwrap_or_default())
+}
+
+fn last_entries(stack: &[HistoryEntry], capacity: usize) -> Vec<RichTextDelta> {
+    let mut deltas = stack
+        .iter()
+        .rev()
+        .take(capacity)
+        .map_while(|entry| entry.delta.clone())
+        .collect::<Vec<_>>();
+    deltas.reverse();
+    deltas
+}
+
+// The last entry of the stack applies to the document of `len`, each entry before it applies to
+// the document that the next entry produces. Returns `delta` and the length of the document at
+// each level of the stack: in the document of `len` first, then in the document that the last
+// entry produces and so on.
+fn transform_stack(
+    stack: &mut Vec<HistoryEntry>,
+    delta: &RichTextDelta,
+    len: usize,
+) -> Result<Vec<(RichTextDelta, usize)>, OTError> {
+    let mut delta = delta.clone();
+    let mut len = len;
+    let mut levels = vec![(delta.clone(), len)];
+    for index in (0..stack.len()).rev() {
+        let entry = match stack[index].delta.as_mut() {
+            None => {
+                stack.drain(..=index);
+                break;
+            }
+            Some(entry) => entry,
+        };
+        pad(entry, len);
+        pad(&mut delta, len);
+        let (entry_prime, delta_prime) = entry.transform(&delta)?;
+        len = entry.utf16_target_len;
+        let selection = stack[index].selection.map(|selection| SelectionChange {
+            before: transform_interval(&delta_prime, selection.before),
+            after: transform_interval(&delta, selection.after),
+        });
+        stack[index] = HistoryEntry {
+            rev_id: None,
+            delta: Some(entry_prime),
+            selection,
+        };
+        delta = delta_prime;
+        levels.push((delta.clone(), len));
+    }
+    Ok(levels)
+}
+
+fn transform_interval(delta: &RichTextDelta, interval: Interval) -> Interval {
+    let start = delta.transform_position(interval.start, false);
+    let end = delta.transform_position(interval.end, true).max(start);
+    Interval::new(start, end)
+}
+
+// A document whose inserts have the attributes that were removed dropped, which doesn't change its
+// content.
+fn normalize(document: &RichTextDelta) -> RichTextDelta {
+    let mut normalized = RichTextDelta::new();
+    for op in &document.ops {
+        let mut op = op.clone();
+        if let Operation::Insert(insert) = &mut op {
+            insert.attributes.remove_empty();
         }
+        normalized.add(op);
     }
+    normalized
+}
+
+// The deltas omit the retain at their end, which the transform needs.
+pub(crate) fn pad(delta: &mut RichTextDelta, len: usize) {
+    if delta.utf16_base_len < len {
+        delta.retain(len - delta.utf16_base_len, RichTextAttributes::default());
+    }
+}
 
-    pub fn undo(&mut self) -> Option<RichTextDelta> {
-        if !self.can_undo() {
-            return None;
+#[cfg(test)]
+mod tests {
+    use crate::client_document::history::{normalize, History, HistoryCompression, HistoryEntry};
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn compressed_entries_are_undone() {
+        let mut history = History::new();
+        history.set_compression(Some(HistoryCompression {
+            hot_entries: 2,
+            ..HistoryCompression::default()
+        }));
+        let deltas = (0..5)
+            .map(|n| RichTextDeltaBuilder::new().retain(n).delete(1).build())
+            .collect::<Vec<RichTextDelta>>();
+        for (n, delta) in deltas.iter().enumerate() {
+            history.record(delta.clone(), Some(n as i64), None);
+        }
+        assert_eq!(history.undo_len(), 5);
+        assert_eq!(history.undoes().len(), 2);
+
+        for (n, delta) in deltas.iter().enumerate().rev() {
+            let entry = history.undo().unwrap();
+            assert_eq!(entry.delta.as_ref(), Some(delta));
+            assert_eq!(entry.rev_id, Some(n as i64));
         }
-        let delta = self.undoes.pop().unwrap();
-        Some(delta)
+        assert!(!history.can_undo());
     }
 
-    pub fn redo(&mut self) -> Option<RichTextDelta> {
-        if !self.can_redo() {
-            return None;
+    #[test]
+    fn format_is_undone_without_the_later_changes() {
+        let mut history = History::new();
+        let mut document = RichTextDeltaBuilder::new().insert("abc\n").build();
+        let mut apply = |history: &mut History, delta: RichTextDelta| {
+            history.record(delta.invert(&document), None, None);
+            document = document.compose(&delta).unwrap();
+        };
+        apply(
+            &mut history,
+            RichTextDeltaBuilder::new()
+                .retain_with_attributes(3, RichTextAttribute::Bold(true).into())
+                .build(),
+        );
+        apply(&mut history, RichTextDeltaBuilder::new().insert("x").build());
+        apply(&mut history, RichTextDeltaBuilder::new().retain(4).insert("y").build());
+
+        let entry = history
+            .undo_matching(&document, HistoryEntry::is_format)
+            .unwrap()
+            .unwrap();
+        document = document.compose(&entry.delta.unwrap()).unwrap();
+        assert_eq!(normalize(&document).to_json(), r#"[{"insert":"xabcy\n"}]"#);
+        assert!(history
+            .undo_matching(&document, HistoryEntry::is_format)
+            .unwrap()
+            .is_none());
+
+        // The changes after the format are still undone in order, and don't bring it back.
+        for json in [r#"[{"insert":"xabc\n"}]"#, r#"[{"insert":"abc\n"}]"#] {
+            let entry = history.undo().unwrap();
+            document = document.compose(&entry.delta.unwrap()).unwrap();
+            assert_eq!(normalize(&document).to_json(), json);
         }
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn branches_keep_the_undone_changes() {
+        let delta = |s: &str| RichTextDeltaBuilder::new().insert(s).build();
+        let deltas = |entries: &[HistoryEntry]| {
+            entries
+                .iter()
+                .map(|entry| entry.delta.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let mut history = History::new();
+        history.set_branching(true);
+        let undo = |history: &mut History, redo: &str| {
+            history.undo().unwrap();
+            history.add_redo(delta(redo), None, None);
+        };
+
+        history.record(delta("u1"), None, None);
+        history.record(delta("u2"), None, None);
+        undo(&mut history, "r2");
+        undo(&mut history, "r1");
+        history.redo().unwrap();
+        history.add_undo(delta("u1"), None, None);
+        history.record(delta("u3"), None, None);
+        assert_eq!(history.branches().len(), 1);
+        assert_eq!(history.branches()[0].depth, 1);
+        assert!(history.switch_branch(history.branches()[0].id).is_err());
+
+        // The branch that starts after the first change moves down with it.
+        undo(&mut history, "r3");
+        undo(&mut history, "r1");
+        history.record(delta("u4"), None, None);
+        let branches = history.branches();
+        assert_eq!(branches.len(), 2);
+        assert_eq!(branches[0].depth, 0);
+        assert_eq!(deltas(&branches[0].redoes), vec![delta("r2"), delta("r1")]);
+        assert_eq!(deltas(&branches[1].redoes), vec![delta("r3"), delta("r1")]);
+
+        let document = delta("document");
+        let decoded = History::decode(&history.encode(&document).unwrap(), &document).unwrap();
+        assert_eq!(decoded.branches().len(), 2);
+        assert_eq!(deltas(&decoded.branches()[1].redoes), vec![delta("r3"), delta("r1")]);
 
-        let delta = self.redoes.pop().unwrap();
-        Some(delta)
+        history.undo().unwrap();
+        let id = history.branches()[0].id;
+        history.switch_branch(id).unwrap();
+        assert_eq!(deltas(history.redoes()), vec![delta("r2"), delta("r1")]);
+        assert_eq!(history.branches().len(), 1);
+        history.linearize();
+        assert!(history.branches().is_empty());
     }
 }
@@ -0,0 +1,983 @@
+use crate::errors::{internal_error, CollaborateError};
+use lib_ot::{
+    core::{Embed, Operation, EMBED_CHAR, NEW_LINE},
+    rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes, RichTextDelta},
+};
+use serde_json::Value;
+use std::{borrow::Cow, collections::BTreeMap, fmt};
+
+/// Something that an [Importer] couldn't bring into the document, for the host to tell the user
+/// what the migration lost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportWarning {
+    /// What was dropped, e.g. `"font size"` or `"attribute author"`.
+    pub dropped: String,
+    /// The number of times it was dropped.
+    pub count: usize,
+}
+
+impl fmt::Display for ImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.count {
+            1 => write!(f, "{} was dropped once", self.dropped),
+            count => write!(f, "{} was dropped {} times", self.dropped, count),
+        }
+    }
+}
+
+/// Counts what an [Importer] dropped, see [ImportReport::into_warnings].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    dropped: BTreeMap<String, usize>,
+}
+
+impl ImportReport {
+    /// Counts one more `what` that was dropped.
+    pub fn record<T: ToString>(&mut self, what: T) {
+        *self.dropped.entry(what.to_string()).or_insert(0) += 1;
+    }
+
+    /// Whether nothing was dropped.
+    pub fn is_lossless(&self) -> bool {
+        self.dropped.is_empty()
+    }
+
+    /// The warnings of what was dropped, by the order of their names.
+    pub fn into_warnings(self) -> Vec<ImportWarning> {
+        self.dropped
+            .into_iter()
+            .map(|(dropped, count)| ImportWarning { dropped, count })
+            .collect()
+    }
+}
+
+/// Turns the content of a file of another app into a document, which ends with a newline like the
+/// documents of the editor, and the warnings of what it dropped.
+pub trait Importer: Send + Sync {
+    /// The name that the importer is found by, e.g. `"rtf"`.
+    fn name(&self) -> &str;
+
+    /// Whether the data looks like the format of the importer, e.g. by its first bytes. The
+    /// importer may still fail on it.
+    fn accepts(&self, data: &[u8]) -> bool;
+
+    fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError>;
+}
+
+/// The importers of the formats that the documents can be imported from. The other crates add
+/// their formats with [ImporterRegistry::register].
+pub struct ImporterRegistry {
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl std::default::Default for ImporterRegistry {
+    fn default() -> Self {
+        Self {
+            importers: vec![
+                Box::new(LegacyJsonImporter),
+                Box::new(RtfImporter),
+                Box::new(MarkdownImporter),
+                Box::new(PlainTextImporter::default()),
+            ],
+        }
+    }
+}
+
+impl ImporterRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an importer, which replaces the one that was registered with the same name. It's tried
+    /// before the ones that were registered before it. The plain text importer, which takes any
+    /// data, comes last unless it's replaced.
+    pub fn register(&mut self, importer: Box<dyn Importer>) {
+        self.importers.retain(|registered| registered.name() != importer.name());
+        self.importers.insert(0, importer);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|importer| importer.name() == name)
+            .map(|importer| importer.as_ref())
+    }
+
+    /// The names of the importers, in the order they are tried.
+    pub fn names(&self) -> Vec<&str> {
+        self.importers.iter().map(|importer| importer.name()).collect()
+    }
+
+    /// Imports the data with the first importer that accepts it and doesn't fail on it.
+    pub fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+        let mut error = None;
+        for importer in self.importers.iter().filter(|importer| importer.accepts(data)) {
+            match importer.import(data) {
+                Ok(import) => return Ok(import),
+                Err(e) => {
+                    tracing::debug!("The {} importer failed: {}", importer.name(), e);
+                    error = Some(e);
+                }
+            }
+        }
+        Err(error.unwrap_or_else(|| CollaborateError::internal().context("No importer accepts the data")))
+    }
+
+    /// Imports the data with the importer named `name`, e.g. the format that the user picked.
+    pub fn import_as(&self, name: &str, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+        match self.get(name) {
+            None => Err(CollaborateError::record_not_found().context(format!("There is no {} importer", name))),
+            Some(importer) => importer.import(data),
+        }
+    }
+}
+
+/// Imports plain text. The lines that are separated by blank lines are paragraphs, the lines that
+/// start with a list marker, e.g. `- ` or `1. `, are the items of a list.
+pub struct PlainTextImporter {
+    /// Whether the lines of a paragraph are joined into one line, for the text that was wrapped at
+    /// a fixed width. The lines are kept otherwise.
+    pub join_wrapped_lines: bool,
+}
+
+impl std::default::Default for PlainTextImporter {
+    fn default() -> Self {
+        Self {
+            join_wrapped_lines: true,
+        }
+    }
+}
+
+impl Importer for PlainTextImporter {
+    fn name(&self) -> &str {
+        "plain text"
+    }
+
+    fn accepts(&self, _data: &[u8]) -> bool {
+        true
+    }
+
+    fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+        let mut sink = DeltaSink::default();
+        let text = String::from_utf8_lossy(data);
+        if matches!(text, Cow::Owned(_)) {
+            sink.report.record("invalid utf-8");
+        }
+        let text = text
+            .trim_start_matches('\u{FEFF}')
+            .replace("\r\n", "\n")
+            .replace('\r', "\n");
+
+        // The text of the current line and the attributes of its newline.
+        let mut line: Option<(String, RichTextAttributes)> = None;
+        for line_text in text.split('\n') {
+            let trimmed = line_text.trim();
+            if trimmed.is_empty() {
+                if let Some((text, attributes)) = line.take() {
+                    sink.push_line(&text, attributes);
+                }
+                continue;
+            }
+            match list_item(trimmed) {
+                Some((attribute, text)) => {
+                    if let Some((text, attributes)) = line.take() {
+                        sink.push_line(&text, attributes);
+                    }
+                    line = Some((text.to_owned(), attribute.into()));
+                }
+                None => match &mut line {
+                    Some((text, _)) if self.join_wrapped_lines => {
+                        text.push(' ');
+                        text.push_str(trimmed);
+                    }
+                    _ => {
+                        if let Some((text, attributes)) = line.take() {
+                            sink.push_line(&text, attributes);
+                        }
+                        line = Some((trimmed.to_owned(), RichTextAttributes::default()));
+                    }
+                },
+            }
+        }
+        if let Some((text, attributes)) = line.take() {
+            sink.push_line(&text, attributes);
+        }
+        Ok(sink.finish())
+    }
+}
+
+// Returns the list attribute of the line and its text after the marker.
+fn list_item(line: &str) -> Option<(RichTextAttribute, &str)> {
+    for (marker, attribute) in [
+        ("- [ ] ", RichTextAttribute::UnChecked(true)),
+        ("- [x] ", RichTextAttribute::Checked(true)),
+        ("- [X] ", RichTextAttribute::Checked(true)),
+        ("- ", RichTextAttribute::Bullet(true)),
+        ("* ", RichTextAttribute::Bullet(true)),
+        ("+ ", RichTextAttribute::Bullet(true)),
+    ] {
+        if let Some(text) = line.strip_prefix(marker) {
+            return Some((attribute, text));
+        }
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    let rest = &line[digits..];
+    if digits == 0 || !(rest.starts_with(". ") || rest.starts_with(") ")) {
+        return None;
+    }
+    Some((RichTextAttribute::Ordered(true), &rest[2..]))
+}
+
+/// Imports the subset of RTF that the editor has: the bold, italic, underlined and struck through
+/// text, the paragraphs and the unicode characters. The other formatting is dropped, and so are
+/// the tables, the pictures and the other destinations.
+pub struct RtfImporter;
+
+impl Importer for RtfImporter {
+    fn name(&self) -> &str {
+        "rtf"
+    }
+
+    fn accepts(&self, data: &[u8]) -> bool {
+        data.starts_with(b"{\\rtf")
+    }
+
+    fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+        let mut parser = RtfParser {
+            data,
+            pos: 0,
+            state: RtfState::default(),
+            stack: vec![],
+            sink: DeltaSink::default(),
+            skip_chars: 0,
+        };
+        parser.parse()?;
+        Ok(parser.sink.finish())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RtfState {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    // Whether the group is a destination whose text isn't part of the document, e.g. the font table.
+    skip: bool,
+    // The number of characters that stand in for a `\u` character for the readers that don't have
+    // unicode, which are skipped.
+    uc: usize,
+}
+
+impl std::default::Default for RtfState {
+    fn default() -> Self {
+        Self {
+            bold: false,
+            italic: false,
+            underline: false,
+            strike: false,
+            skip: false,
+            uc: 1,
+        }
+    }
+}
+
+impl RtfState {
+    fn attributes(&self) -> RichTextAttributes {
+        let mut attributes = RichTextAttributes::default();
+        for (is_on, attribute) in [
+            (self.bold, RichTextAttribute::Bold(true)),
+            (self.italic, RichTextAttribute::Italic(true)),
+            (self.underline, RichTextAttribute::Underline(true)),
+            (self.strike, RichTextAttribute::StrikeThrough(true)),
+        ] {
+            if is_on {
+                attributes.add(attribute);
+            }
+        }
+        attributes
+    }
+}
+
+// The destinations whose text isn't part of the document. The pictures are reported.
+const RTF_SKIPPED_DESTINATIONS: [&str; 10] = [
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "info",
+    "listtable",
+    "listoverridetable",
+    "generator",
+    "header",
+    "footer",
+    "pict",
+];
+
+// The control words that don't change how the text looks.
+const RTF_IGNORED_WORDS: [&str; 14] = [
+    "rtf",
+    "ansi",
+    "mac",
+    "pc",
+    "pca",
+    "ansicpg",
+    "deff",
+    "deflang",
+    "deflangfe",
+    "viewkind",
+    "pard",
+    "cocoartf",
+    "cocoasubrtf",
+    "nowidctlpar",
+];
+
+struct RtfParser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    state: RtfState,
+    stack: Vec<RtfState>,
+    sink: DeltaSink,
+    skip_chars: usize,
+}
+
+impl<'a> RtfParser<'a> {
+    fn parse(&mut self) -> Result<(), CollaborateError> {
+        while let Some(byte) = self.next_byte() {
+            match byte {
+                b'{' => self.stack.push(self.state.clone()),
+                b'}' => {
+                    self.state = self
+                        .stack
+                        .pop()
+                        .ok_or_else(|| CollaborateError::internal().context("The rtf has an unopened group"))?;
+                }
+                b'\\' => self.control()?,
+                b'\r' | b'\n' => {}
+                _ => self.push_char(byte as char),
+            }
+        }
+        Ok(())
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.data.get(self.pos).copied();
+        self.pos += 1;
+        byte
+    }
+
+    fn peek_byte(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn control(&mut self) -> Result<(), CollaborateError> {
+        let byte = self
+            .next_byte()
+            .ok_or_else(|| CollaborateError::internal().context("The rtf ends with a backslash"))?;
+        match byte {
+            b'\\' | b'{' | b'}' => self.push_char(byte as char),
+            b'\'' => {
+                let hex = self.data.get(self.pos..self.pos + 2).unwrap_or_default();
+                self.pos += 2;
+                let byte = std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| CollaborateError::internal().context("The rtf has an invalid \\' escape"))?;
+                // The code page is taken as latin-1, which the common ones agree with on the letters.
+                self.push_char(byte as char);
+            }
+            b'*' => self.state.skip = true,
+            b'~' => self.push_char('\u{A0}'),
+            b'_' => self.push_char('-'),
+            b'\r' | b'\n' => self.push_char('\n'),
+            byte if byte.is_ascii_alphabetic() => {
+                let start = self.pos - 1;
+                while self.peek_byte().map(|byte| byte.is_ascii_alphabetic()).unwrap_or(false) {
+                    self.pos += 1;
+                }
+                let word = String::from_utf8_lossy(&self.data[start..self.pos]).into_owned();
+                let param_start = self.pos;
+                if self.peek_byte() == Some(b'-') {
+                    self.pos += 1;
+                }
+                while self.peek_byte().map(|byte| byte.is_ascii_digit()).unwrap_or(false) {
+                    self.pos += 1;
+                }
+                let param = std::str::from_utf8(&self.data[param_start..self.pos])
+                    .ok()
+                    .and_then(|param| param.parse::<i32>().ok());
+                if self.peek_byte() == Some(b' ') {
+                    self.pos += 1;
+                }
+                self.word(&word, param);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn word(&mut self, word: &str, param: Option<i32>) {
+        let is_on = param != Some(0);
+        match word {
+            "b" => self.state.bold = is_on,
+            "i" => self.state.italic = is_on,
+            "ul" => self.state.underline = is_on,
+            "ulnone" => self.state.underline = false,
+            "strike" => self.state.strike = is_on,
+            "plain" => {
+                let state = RtfState {
+                    skip: self.state.skip,
+                    uc: self.state.uc,
+                    ..RtfState::default()
+                };
+                self.state = state;
+            }
+            "par" | "line" => self.push_char('\n'),
+            "tab" => self.push_char('\t'),
+            "uc" => self.state.uc = param.unwrap_or(1).max(0) as usize,
+            "u" => {
+                // The parameter is a signed 16 bits number.
+                let code = param.unwrap_or(0);
+                let code = if code < 0 { code + 0x10000 } else { code };
+                self.push_char(std::char::from_u32(code as u32).unwrap_or('\u{FFFD}'));
+                self.skip_chars = self.state.uc;
+            }
+            "f" if param == Some(0) => {}
+            "fs" if param == Some(24) => {}
+            "cf" | "cb" | "highlight" if param == Some(0) => {}
+            "f" => self.drop_formatting("font"),
+            "fs" => self.drop_formatting("font size"),
+            "cf" => self.drop_formatting("color"),
+            "cb" | "highlight" => self.drop_formatting("background"),
+            "qc" | "qr" | "qj" => self.drop_formatting("alignment"),
+            word if RTF_SKIPPED_DESTINATIONS.contains(&word) => {
+                if word == "pict" && !self.state.skip {
+                    self.sink.report.record("picture");
+                }
+                self.state.skip = true;
+            }
+            word if RTF_IGNORED_WORDS.contains(&word) => {}
+            word => self.drop_formatting(&format!("\\{}", word)),
+        }
+    }
+
+    fn drop_formatting(&mut self, what: &str) {
+        if !self.state.skip {
+            self.sink.report.record(what);
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        if self.state.skip {
+            return;
+        }
+        if self.skip_chars > 0 && c != '\n' {
+            self.skip_chars -= 1;
+            return;
+        }
+        let mut buf = [0; 4];
+        if c == '\n' {
+            self.sink.push(NEW_LINE, RichTextAttributes::default());
+        } else {
+            self.sink.push(c.encode_utf8(&mut buf), self.state.attributes());
+        }
+    }
+}
+
+/// Imports the Markdown that [MarkdownExporter](crate::client_document::export::MarkdownExporter)
+/// writes, e.g. the mirror of a document that was edited in another editor: the headings, the
+/// quotes, the lists and their indents, the fenced code blocks, and the bold, italic, struck
+/// through, code and linked text. The images are image embeds. The lines of a paragraph are
+/// joined, the blank lines only separate the blocks.
+///
+/// Any text is Markdown, so the importer doesn't accept the data of [ImporterRegistry::import],
+/// it's picked with [ImporterRegistry::import_as].
+pub struct MarkdownImporter;
+
+impl Importer for MarkdownImporter {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn accepts(&self, _data: &[u8]) -> bool {
+        false
+    }
+
+    fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+        let mut sink = DeltaSink::default();
+        let text = String::from_utf8_lossy(data);
+        if matches!(text, Cow::Owned(_)) {
+            sink.report.record("invalid utf-8");
+        }
+        let text = text
+            .trim_start_matches('\u{FEFF}')
+            .replace("\r\n", "\n")
+            .replace('\r', "\n");
+
+        // The attributes of the lines of the code block that is open, and the inline Markdown of
+        // the current line with the attributes of its newline.
+        let mut code_block: Option<RichTextAttributes> = None;
+        let mut line: Option<(String, RichTextAttributes)> = None;
+        for line_text in text.split('\n') {
+            if let Some(attributes) = &code_block {
+                match line_text.trim_end() == "```" {
+                    true => code_block = None,
+                    false => {
+                        sink.push(line_text, RichTextAttributes::default());
+                        sink.push(NEW_LINE, attributes.clone());
+                    }
+                }
+                continue;
+            }
+
+            let trimmed = line_text.trim();
+            let block = markdown_block(line_text);
+            if let (None, Some((text, attributes))) = (&block, &mut line) {
+                if !trimmed.is_empty() && attributes.get(&RichTextAttributeKey::Header).is_none() {
+                    text.push(' ');
+                    text.push_str(trimmed);
+                    continue;
+                }
+            }
+            if let Some((text, attributes)) = line.take() {
+                push_markdown_line(&mut sink, &text, attributes);
+            }
+            match block {
+                Some(MarkdownBlock::Fence(attributes)) => code_block = Some(attributes),
+                Some(MarkdownBlock::Line(text, attributes)) => line = Some((text.to_owned(), attributes)),
+                None if trimmed.is_empty() => {}
+                None => line = Some((trimmed.to_owned(), RichTextAttributes::default())),
+            }
+        }
+        if let Some((text, attributes)) = line.take() {
+            push_markdown_line(&mut sink, &text, attributes);
+        }
+        Ok(sink.finish())
+    }
+}
+
+// The block that a line of Markdown starts, with the attributes of its newline.
+enum MarkdownBlock<'a> {
+    // The opening fence of a code block.
+    Fence(RichTextAttributes),
+    // A heading, a quote or a list item, with its inline Markdown.
+    Line(&'a str, RichTextAttributes),
+}
+
+fn markdown_block(line: &str) -> Option<MarkdownBlock<'_>> {
+    let trimmed = line.trim_start();
+    if let Some(language) = trimmed.strip_prefix("```") {
+        let mut attributes: RichTextAttributes = RichTextAttribute::CodeBlock(true).into();
+        if !language.trim().is_empty() {
+            attributes.add(RichTextAttribute::CodeLanguage(language.trim()));
+        }
+        return Some(MarkdownBlock::Fence(attributes));
+    }
+
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) {
+        if let Some(text) = trimmed[level..].strip_prefix(' ') {
+            return Some(MarkdownBlock::Line(
+                text.trim(),
+                RichTextAttribute::Header(level).into(),
+            ));
+        }
+    }
+    if let Some(text) = trimmed.strip_prefix('>') {
+        return Some(MarkdownBlock::Line(
+            text.trim(),
+            RichTextAttribute::BlockQuote(true).into(),
+        ));
+    }
+
+    let (list, text) = list_item(trimmed)?;
+    let mut attributes: RichTextAttributes = list.into();
+    // The exporter indents the items by two spaces a level.
+    let indent = line[..line.len() - trimmed.len()]
+        .chars()
+        .map(|c| if c == '\t' { 2 } else { 1 })
+        .sum::<usize>()
+        / 2;
+    if indent > 0 {
+        attributes.add(RichTextAttribute::Indent(indent));
+    }
+    let digits = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+    if let Ok(number) = trimmed[..digits].parse::<usize>() {
+        attributes.add(RichTextAttribute::ListNumber(number));
+    }
+    Some(MarkdownBlock::Line(text.trim(), attributes))
+}
+
+fn push_markdown_line(sink: &mut DeltaSink, text: &str, attributes: RichTextAttributes) {
+    let chars = text.chars().collect::<Vec<_>>();
+    push_inline(sink, &chars, &RichTextAttributes::default());
+    sink.push(NEW_LINE, attributes);
+}
+
+// Pushes the inline Markdown with the attributes of the marks around it. The marks that aren't
+// closed are kept as text.
+fn push_inline(sink: &mut DeltaSink, chars: &[char], attributes: &RichTextAttributes) {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1).map_or(false, |c| c.is_ascii_punctuation()) => {
+                text.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                if let Some(end) = (i + 1..chars.len()).find(|j| chars[*j] == '`') {
+                    sink.push(&std::mem::take(&mut text), attributes.clone());
+                    let mut code = attributes.clone();
+                    code.add(RichTextAttribute::InlineCode(true));
+                    sink.push(&chars[i + 1..end].iter().collect::<String>(), code);
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '*' | '~' => {
+                let n = delimiter_run(chars, i);
+                let marks = match (c, n) {
+                    ('*', 1) => vec![RichTextAttribute::Italic(true)],
+                    ('*', 2) => vec![RichTextAttribute::Bold(true)],
+                    ('*', 3) => vec![RichTextAttribute::Bold(true), RichTextAttribute::Italic(true)],
+                    ('~', 1) | ('~', 2) => vec![RichTextAttribute::StrikeThrough(true)],
+                    _ => vec![],
+                };
+                match closing_delimiter(chars, i + n, c, n) {
+                    Some(end) if !marks.is_empty() => {
+                        sink.push(&std::mem::take(&mut text), attributes.clone());
+                        let mut inner = attributes.clone();
+                        marks.into_iter().for_each(|mark| inner.add(mark));
+                        push_inline(sink, &chars[i + n..end], &inner);
+                        i = end + n;
+                    }
+                    _ => {
+                        text.extend(&chars[i..i + n]);
+                        i += n;
+                    }
+                }
+                continue;
+            }
+            '!' if chars.get(i + 1) == Some(&'[') => {
+                if let Some((_, src, end)) = link_at(chars, i + 1) {
+                    sink.push(&std::mem::take(&mut text), attributes.clone());
+                    sink.push_embed(
+                        Embed::new("image", serde_json::json!({ "src": src })),
+                        attributes.clone(),
+                    );
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '[' => {
+                if let Some((label_end, url, end)) = link_at(chars, i) {
+                    sink.push(&std::mem::take(&mut text), attributes.clone());
+                    let mut linked = attributes.clone();
+                    linked.add(RichTextAttribute::Link(&url));
+                    push_inline(sink, &chars[i + 1..label_end], &linked);
+                    i = end + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        text.push(c);
+        i += 1;
+    }
+    sink.push(&text, attributes.clone());
+}
+
+fn delimiter_run(chars: &[char], start: usize) -> usize {
+    chars[start..].iter().take_while(|c| **c == chars[start]).count()
+}
+
+// The start of the run of `n` delimiters `c` that closes the one before `start`, skipping the
+// escaped characters, the code and the runs of other lengths, which are nested marks.
+fn closing_delimiter(chars: &[char], start: usize, c: char, n: usize) -> Option<usize> {
+    let mut j = start;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 2,
+            '`' => j = (j + 1..chars.len()).find(|k| chars[*k] == '`')? + 1,
+            d if d == c => {
+                let run = delimiter_run(chars, j);
+                if run == n && j > start {
+                    return Some(j);
+                }
+                j += run;
+            }
+            _ => j += 1,
+        }
+    }
+    None
+}
+
+// The end of the label, the url and the end of the link `[label](url)` that starts at `start`.
+fn link_at(chars: &[char], start: usize) -> Option<(usize, String, usize)> {
+    let mut depth = 0;
+    let mut j = start;
+    let label_end = loop {
+        match chars.get(j)? {
+            '\\' => j += 1,
+            '[' => depth += 1,
+            ']' if depth == 1 => break j,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        j += 1;
+    };
+    if chars.get(label_end + 1) != Some(&'(') {
+        return None;
+    }
+    let end = (label_end + 2..chars.len()).find(|k| chars[*k] == ')')?;
+    Some((label_end, chars[label_end + 2..end].iter().collect(), end))
+}
+
+/// Imports the json of the documents of the older versions of AppFlowy: the operations of the
+/// delta, bare or under an `ops` or a `data` key. The attributes that the editor doesn't have are
+/// dropped, and so are the operations other than the inserts.
+pub struct LegacyJsonImporter;
+
+impl Importer for LegacyJsonImporter {
+    fn name(&self) -> &str {
+        "legacy json"
+    }
+
+    fn accepts(&self, data: &[u8]) -> bool {
+        let first = data.iter().find(|byte| !byte.is_ascii_whitespace());
+        matches!(first, Some(b'[') | Some(b'{'))
+    }
+
+    fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+        let value: Value = serde_json::from_slice(data).map_err(internal_error)?;
+        let ops = match value {
+            Value::Array(ops) => ops,
+            Value::Object(mut map) => match map.remove("ops").or_else(|| map.remove("data")) {
+                Some(Value::Array(ops)) => ops,
+                _ => return Err(CollaborateError::internal().context("The json has no operations")),
+            },
+            _ => return Err(CollaborateError::internal().context("The json isn't a document")),
+        };
+
+        let mut sink = DeltaSink::default();
+        for mut op in ops {
+            let map = match &mut op {
+                Value::Object(map) if map.contains_key("insert") => map,
+                _ => {
+                    sink.report.record("operation other than an insert");
+                    continue;
+                }
+            };
+            let attributes = match map.remove("attributes") {
+                Some(Value::Object(attributes)) => legacy_attributes(attributes, &mut sink.report),
+                _ => RichTextAttributes::default(),
+            };
+            match serde_json::from_value::<Operation<RichTextAttributes>>(op) {
+                Ok(Operation::Insert(insert)) => match insert.embed {
+                    Some(embed) => sink.push_embed(embed, attributes),
+                    None => sink.push(insert.s.as_str(), attributes),
+                },
+                _ => sink.report.record("invalid operation"),
+            }
+        }
+        Ok(sink.finish())
+    }
+}
+
+fn legacy_attributes(map: serde_json::Map<String, Value>, report: &mut ImportReport) -> RichTextAttributes {
+    let mut attributes = RichTextAttributes::default();
+    for (key, value) in map {
+        if value.is_null() {
+            continue;
+        }
+        let name = key.clone();
+        let key = serde_json::from_value::<RichTextAttributeKey>(Value::String(key));
+        let value = serde_json::from_value::<RichTextAttributeValue>(value);
+        match (key, value) {
+            (Ok(key), Ok(value)) => attributes.add_kv(key, value),
+            _ => report.record(format!("attribute {}", name)),
+        }
+    }
+    attributes
+}
+
+// Builds the document of an import, without the embed characters that aren't embeds.
+#[derive(Default)]
+struct DeltaSink {
+    delta: RichTextDelta,
+    report: ImportReport,
+    ends_with_newline: bool,
+}
+
+impl DeltaSink {
+    fn push(&mut self, text: &str, attributes: RichTextAttributes) {
+        let text = match text.contains(EMBED_CHAR) {
+            false => text.to_owned(),
+            true => {
+                self.report.record("embed character");
+                text.replace(EMBED_CHAR, "")
+            }
+        };
+        if text.is_empty() {
+            return;
+        }
+        self.ends_with_newline = text.ends_with(NEW_LINE);
+        self.delta.insert(&text, attributes);
+    }
+
+    fn push_line(&mut self, text: &str, attributes: RichTextAttributes) {
+        self.push(text, RichTextAttributes::default());
+        self.push(NEW_LINE, attributes);
+    }
+
+    fn push_embed(&mut self, embed: Embed, attributes: RichTextAttributes) {
+        self.ends_with_newline = false;
+        self.delta.insert_embed(embed, attributes);
+    }
+
+    fn finish(mut self) -> (RichTextDelta, Vec<ImportWarning>) {
+        if !self.ends_with_newline {
+            self.delta.insert(NEW_LINE, RichTextAttributes::default());
+        }
+        (self.delta, self.report.into_warnings())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        export::{ExportOptions, Exporter, MarkdownExporter},
+        import::{ImportWarning, Importer, ImporterRegistry, MarkdownImporter},
+        ClientDocument, NewlineDoc,
+    };
+    use crate::errors::CollaborateError;
+    use lib_ot::{
+        core::Interval,
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn import_each_format() {
+        let registry = ImporterRegistry::default();
+
+        let text = "Notes\r\n\r\nA long line\r\nwrapped.\r\n- one\r\n- [x] two\r\n12. twelve\r\n";
+        let (delta, warnings) = registry.import(text.as_bytes()).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(
+            delta.to_json(),
+            r#"[{"insert":"Notes\nA long line wrapped.\none"},{"insert":"\n","attributes":{"list":"bullet"}},{"insert":"two"},{"insert":"\n","attributes":{"list":"checked"}},{"insert":"twelve"},{"insert":"\n","attributes":{"list":"ordered"}}]"#
+        );
+
+        let rtf = r"{\rtf1\ansi{\fonttbl\f0\fswiss Helvetica;}\f0\fs28 Hi {\b bold}\par caf\'e9 \u8364?\par}";
+        let (delta, warnings) = registry.import(rtf.as_bytes()).unwrap();
+        assert_eq!(
+            delta.to_json(),
+            r#"[{"insert":"Hi "},{"insert":"bold","attributes":{"bold":true}},{"insert":"\ncafé €\n"}]"#
+        );
+        assert_eq!(
+            warnings,
+            vec![ImportWarning {
+                dropped: "font size".to_owned(),
+                count: 1,
+            }]
+        );
+        assert_eq!(warnings[0].to_string(), "font size was dropped once");
+
+        let json = r#"{"ops":[{"insert":"a","attributes":{"bold":true,"author":"x"}},{"retain":1},{"insert":"\n"}]}"#;
+        let (delta, warnings) = registry.import(json.as_bytes()).unwrap();
+        assert_eq!(
+            delta.to_json(),
+            r#"[{"insert":"a","attributes":{"bold":true}},{"insert":"\n"}]"#
+        );
+        assert_eq!(warnings.len(), 2);
+
+        // The text that only looks like json is imported as text.
+        let (delta, _) = registry.import(b"[draft] plan").unwrap();
+        assert_eq!(delta.to_json(), r#"[{"insert":"[draft] plan\n"}]"#);
+    }
+
+    #[test]
+    fn custom_importer_is_registered() {
+        struct OrgImporter;
+        impl Importer for OrgImporter {
+            fn name(&self) -> &str {
+                "org"
+            }
+
+            fn accepts(&self, data: &[u8]) -> bool {
+                data.starts_with(b"* ")
+            }
+
+            fn import(&self, data: &[u8]) -> Result<(RichTextDelta, Vec<ImportWarning>), CollaborateError> {
+                let text = String::from_utf8_lossy(&data[2..]);
+                let delta = RichTextDeltaBuilder::new().insert(&text).insert("\n").build();
+                Ok((delta, vec![]))
+            }
+        }
+
+        let mut registry = ImporterRegistry::new();
+        assert!(registry.import_as("org", b"* Notes").is_err());
+        registry.register(Box::new(OrgImporter));
+        assert_eq!(
+            registry.names(),
+            vec!["org", "legacy json", "rtf", "markdown", "plain text"]
+        );
+        let (delta, _) = registry.import(b"* Notes").unwrap();
+        assert_eq!(delta.to_json(), r#"[{"insert":"Notes\n"}]"#);
+        // The plain text importer takes the data it's asked to.
+        let (delta, _) = registry.import_as("plain text", b"* Notes").unwrap();
+        assert_eq!(
+            delta.to_json(),
+            r#"[{"insert":"Notes"},{"insert":"\n","attributes":{"list":"bullet"}}]"#
+        );
+    }
+
+    #[test]
+    fn markdown_is_imported_as_it_was_exported() {
+        let markdown = "# Notes\n\nSome **bold**, *italic* and ~~struck~~ text, with `code` and a [**link**](https://appflowy.io).\n\n> A quote\n\n- one\n  - [x] two\n3. three\n\n```rust\nfn main() {}\n\n```\n\n![](a.png) 1\\*2";
+        let (delta, warnings) = ImporterRegistry::default()
+            .import_as("markdown", markdown.as_bytes())
+            .unwrap();
+        assert!(warnings.is_empty());
+        let json = delta.to_json();
+        assert!(json.starts_with(r#"[{"insert":"Notes"},{"insert":"\n","attributes":{"header":1}},{"insert":"Some "},{"insert":"bold","attributes":{"bold":true}}"#));
+        let link = delta.ops.iter().find(|op| op.get_data() == "link").unwrap();
+        assert!(link
+            .get_attributes()
+            .contain_attribute(&RichTextAttribute::Link("https://appflowy.io")));
+
+        let exported = MarkdownExporter.export(&delta, &ExportOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(exported).unwrap(), markdown);
+        // Markdown isn't picked for the data that could be anything.
+        let (delta, _) = ImporterRegistry::default().import(b"**a**").unwrap();
+        assert_eq!(delta.to_json(), r#"[{"insert":"**a**\n"}]"#);
+    }
+
+    #[test]
+    fn sync_with_markdown_keeps_what_the_mirror_does_not_show() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "Title\n\nhello world").unwrap();
+        document
+            .format(Interval::new(0, 5), RichTextAttribute::Header(1))
+            .unwrap();
+        document
+            .format(Interval::new(7, 12), RichTextAttribute::Underline(true))
+            .unwrap();
+        let mirror = MarkdownExporter
+            .export(document.delta(), &ExportOptions::default())
+            .unwrap();
+        let mirror = String::from_utf8(mirror).unwrap();
+        assert_eq!(mirror, "# Title\n\nhello world");
+
+        assert!(document.sync_with_markdown(&mirror).unwrap().is_empty());
+        document.sync_with_markdown("# Title\n\nhello **big** world").unwrap();
+        assert_eq!(
+            document.delta().to_json(),
+            r#"[{"insert":"Title"},{"insert":"\n","attributes":{"header":1}},{"insert":"\n"},{"insert":"hello","attributes":{"underline":true}},{"insert":" "},{"insert":"big","attributes":{"bold":true}},{"insert":" world\n"}]"#
+        );
+        document.undo().unwrap();
+        assert_eq!(document.to_plain_string(), "Title\n\nhello world\n");
+    }
+}
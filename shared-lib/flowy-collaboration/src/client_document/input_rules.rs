@@ -0,0 +1,163 @@
+use crate::{errors::CollaborateError, util::line_at};
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation, NEW_LINE},
+    rich_text::{BlockAttribute, RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+use std::convert::TryFrom;
+
+/// Turns the text typed at the start of a line into the format of the line, e.g. `"# "` into a
+/// header, the same way as the markdown.
+#[derive(Debug, Clone)]
+pub struct InputRule {
+    trigger: String,
+    attributes: RichTextAttributes,
+}
+
+impl InputRule {
+    /// The rule that gives `attribute` to the line that starts with `trigger` once its last
+    /// character is typed. Fails if `attribute` isn't a block attribute.
+    pub fn new<T: ToString>(trigger: T, attribute: RichTextAttribute) -> Result<Self, CollaborateError> {
+        let attributes = BlockAttribute::try_from(attribute)?.to_line_attributes();
+        Ok(Self {
+            trigger: trigger.to_string(),
+            attributes,
+        })
+    }
+
+    pub fn trigger(&self) -> &str {
+        &self.trigger
+    }
+}
+
+/// The input rules of a document, see [crate::client_document::ClientDocument::set_input_rules].
+/// The default rules are the markdown ones: `"# "` to `"### "` for the headers, `"- "` and `"* "`
+/// for the bullet lists, `"1. "` for the ordered lists, `"> "` for the quotes and `` "```" `` for
+/// the code blocks.
+#[derive(Debug, Clone)]
+pub struct InputRules {
+    rules: Vec<InputRule>,
+}
+
+impl std::default::Default for InputRules {
+    fn default() -> Self {
+        let rules = vec![
+            ("# ", RichTextAttribute::Header(1)),
+            ("## ", RichTextAttribute::Header(2)),
+            ("### ", RichTextAttribute::Header(3)),
+            ("- ", RichTextAttribute::List("bullet")),
+            ("* ", RichTextAttribute::List("bullet")),
+            ("1. ", RichTextAttribute::List("ordered")),
+            ("> ", RichTextAttribute::BlockQuote(true)),
+            ("```", RichTextAttribute::CodeBlock(true)),
+        ];
+        Self {
+            rules: rules
+                .into_iter()
+                .filter_map(|(trigger, attribute)| InputRule::new(trigger, attribute).ok())
+                .collect(),
+        }
+    }
+}
+
+impl InputRules {
+    /// The rules that don't format anything.
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    /// Adds `rule`, it replaces the rule that has the same trigger.
+    pub fn add(&mut self, rule: InputRule) {
+        self.remove(&rule.trigger);
+        self.rules.push(rule);
+    }
+
+    pub fn remove(&mut self, trigger: &str) {
+        self.rules.retain(|rule| rule.trigger != trigger);
+    }
+
+    pub fn rules(&self) -> &[InputRule] {
+        &self.rules
+    }
+
+    /// Whether the text typed up to `end` of the line that starts at `line_start` has the length
+    /// of a trigger, the text itself isn't compared.
+    pub(crate) fn may_trigger(&self, line_start: usize, end: usize) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| line_start + count_utf16_code_units(&rule.trigger) == end)
+    }
+
+    /// Returns the delta that formats the line after `text` was typed at `index` of `document`:
+    /// the trigger is removed and the line gets the attributes of the rule. The rules don't apply
+    /// in the code blocks, where the text is typed as it is.
+    pub fn apply(&self, document: &RichTextDelta, index: usize, text: &str) -> Option<RichTextDelta> {
+        if self.rules.is_empty() || text.is_empty() || text.contains(NEW_LINE) {
+            return None;
+        }
+        let line = line_at(document, index)?;
+        let newline_attributes = document
+            .ops_in(Interval::new(line.end - 1, line.end))
+            .next()
+            .map(|slice| slice.op.get_attributes())
+            .unwrap_or_default();
+        if newline_attributes.contains_key(&RichTextAttributeKey::CodeBlock) {
+            return None;
+        }
+
+        let end = index + count_utf16_code_units(text);
+        // The text of the line is only read when a trigger may match.
+        if !self.may_trigger(line.start, end) {
+            return None;
+        }
+        let typed = text_in(document, Interval::new(line.start, end));
+        let rule = self.rules.iter().find(|rule| rule.trigger == typed)?;
+
+        let mut delta = RichTextDelta::new();
+        delta.retain(line.start, RichTextAttributes::default());
+        delta.delete(end - line.start);
+        delta.retain(line.end - 1 - end, RichTextAttributes::default());
+        delta.retain(1, rule.attributes.clone());
+        Some(delta)
+    }
+}
+
+fn text_in(document: &RichTextDelta, interval: Interval) -> String {
+    let mut text = String::new();
+    for slice in document.ops_in(interval) {
+        if let Operation::Insert(insert) = slice.op {
+            match slice.is_whole() {
+                true => text.push_str(insert.s.as_str()),
+                false => text.push_str(&insert.s.sub_str(slice.interval).unwrap_or_default()),
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::input_rules::InputRules;
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn input_rule_formats_line() {
+        let rules = InputRules::default();
+        let document = RichTextDeltaBuilder::new().insert("# abc\n").build();
+        let delta = rules.apply(&document, 1, " ").unwrap();
+        let header = AttributeBuilder::new().add_attr(RichTextAttribute::Header(1)).build();
+        assert_eq!(
+            document.compose(&delta).unwrap(),
+            RichTextDeltaBuilder::new()
+                .insert("abc")
+                .insert_with_attributes("\n", header)
+                .build()
+        );
+
+        assert!(rules.apply(&document, 0, "#").is_none());
+        assert!(rules.apply(&document, 2, "a").is_none());
+        assert!(InputRules::empty().apply(&document, 1, " ").is_none());
+    }
+}
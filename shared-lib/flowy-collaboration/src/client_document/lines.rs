@@ -0,0 +1,257 @@
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation, NEW_LINE},
+    rich_text::{AttributeScope, RichTextAttributes, RichTextDelta},
+};
+
+/// A line of the document, see [crate::client_document::ClientDocument::lines].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentLine {
+    pub index: usize,
+    /// The utf16 interval of the line, including its newline.
+    pub interval: Interval,
+    /// The text of the line, without its newline. An embed is its placeholder character.
+    pub text: String,
+    /// The block attributes of the line, which are kept on its newline, e.g. the header.
+    pub attributes: RichTextAttributes,
+}
+
+/// Walks the lines of a document in order, without splitting the whole text up front. The text
+/// after the last newline isn't a line.
+pub struct Lines<'a> {
+    ops: std::slice::Iter<'a, Operation<RichTextAttributes>>,
+    // The text of the current insert that isn't in a line yet, and the attributes of the insert.
+    rest: &'a str,
+    attributes: Option<&'a RichTextAttributes>,
+    index: usize,
+    offset: usize,
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(document: &'a RichTextDelta) -> Self {
+        Self {
+            ops: document.ops.iter(),
+            rest: "",
+            attributes: None,
+            index: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = DocumentLine;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let mut text = String::new();
+        loop {
+            if self.rest.is_empty() {
+                match self.ops.next()? {
+                    Operation::Insert(insert) => {
+                        self.rest = insert.s.as_str();
+                        self.attributes = Some(&insert.attributes);
+                    }
+                    _ => continue,
+                }
+            }
+
+            match self.rest.find(NEW_LINE) {
+                None => {
+                    text.push_str(self.rest);
+                    self.offset += count_utf16_code_units(self.rest);
+                    self.rest = "";
+                }
+                Some(i) => {
+                    let (line, rest) = self.rest.split_at(i + NEW_LINE.len());
+                    text.push_str(&line[..i]);
+                    self.offset += count_utf16_code_units(line);
+                    self.rest = rest;
+
+                    let mut attributes = self.attributes.cloned().unwrap_or_default();
+                    attributes.retain(|key, _| key.scope() == AttributeScope::Block);
+                    let line = DocumentLine {
+                        index: self.index,
+                        interval: Interval::new(start, self.offset),
+                        text,
+                        attributes,
+                    };
+                    self.index += 1;
+                    return Some(line);
+                }
+            }
+        }
+    }
+}
+
+/// How the columns of a [TextPosition] are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnUnit {
+    /// In utf16 code units, like the indexes of the document and the strings of Flutter.
+    Utf16,
+    /// In unicode scalar values, e.g. for the error messages.
+    Char,
+}
+
+impl ColumnUnit {
+    pub(crate) fn len(&self, c: char) -> usize {
+        match self {
+            ColumnUnit::Utf16 => c.len_utf16(),
+            ColumnUnit::Char => 1,
+        }
+    }
+}
+
+/// A position in the document as a line and a column, both from zero. The lines are split at the
+/// newlines only, not where the editor wraps them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl TextPosition {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+// The characters of the document. An embed is its placeholder character.
+pub(crate) fn chars(document: &RichTextDelta) -> impl Iterator<Item = char> + '_ {
+    document
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            Operation::Insert(insert) => Some(insert.s.chars()),
+            _ => None,
+        })
+        .flatten()
+}
+
+/// Returns the position of the utf16 `index`, or `None` if it's past the end of the document or
+/// in the middle of a character.
+pub fn index_to_position(document: &RichTextDelta, index: usize, unit: ColumnUnit) -> Option<TextPosition> {
+    let mut position = TextPosition::default();
+    let mut offset = 0;
+    for c in chars(document) {
+        if offset >= index {
+            break;
+        }
+        offset += c.len_utf16();
+        if c == '\n' {
+            position = TextPosition::new(position.line + 1, 0);
+        } else {
+            position.column += unit.len(c);
+        }
+    }
+    match offset == index {
+        true => Some(position),
+        false => None,
+    }
+}
+
+/// Returns the utf16 index of `position`, or `None` if the document doesn't have the line, or if
+/// the column is past the end of the line or in the middle of a character.
+pub fn position_to_index(document: &RichTextDelta, position: TextPosition, unit: ColumnUnit) -> Option<usize> {
+    let mut current = TextPosition::default();
+    let mut offset = 0;
+    for c in chars(document) {
+        if current == position {
+            return Some(offset);
+        }
+        if c == '\n' {
+            if current.line == position.line {
+                return None;
+            }
+            current = TextPosition::new(current.line + 1, 0);
+        } else if current.line == position.line {
+            current.column += unit.len(c);
+            if current.column > position.column {
+                return None;
+            }
+        }
+        offset += c.len_utf16();
+    }
+    match current == position {
+        true => Some(offset),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::lines::{index_to_position, position_to_index, ColumnUnit, Lines, TextPosition};
+    use lib_ot::{
+        core::Interval,
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn lines_across_inserts() {
+        let header = AttributeBuilder::new().add_attr(RichTextAttribute::Header(1)).build();
+        let bold = AttributeBuilder::new().add_attr(RichTextAttribute::Bold(true)).build();
+        let document = RichTextDeltaBuilder::new()
+            .insert("1😀")
+            .insert_with_attributes("2", bold)
+            .insert_with_attributes("\n", header.clone())
+            .insert("\n34\nend")
+            .build();
+        let lines = Lines::new(&document).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+
+        assert_eq!(lines[0].interval, Interval::new(0, 5));
+        assert_eq!(lines[0].text, "1😀2");
+        assert_eq!(lines[0].attributes, header);
+        assert_eq!(lines[1].interval, Interval::new(5, 6));
+        assert_eq!(lines[1].text, "");
+        assert!(lines[1].attributes.is_empty());
+        assert_eq!(lines[2].index, 2);
+        assert_eq!(lines[2].interval, Interval::new(6, 9));
+        assert_eq!(lines[2].text, "34");
+    }
+
+    #[test]
+    fn positions_and_indexes() {
+        let document = RichTextDeltaBuilder::new().insert("a😀b\n\ncd\n").build();
+        let cases = [
+            (0, TextPosition::new(0, 0), TextPosition::new(0, 0)),
+            (3, TextPosition::new(0, 2), TextPosition::new(0, 3)),
+            (5, TextPosition::new(1, 0), TextPosition::new(1, 0)),
+            (8, TextPosition::new(2, 2), TextPosition::new(2, 2)),
+            (9, TextPosition::new(3, 0), TextPosition::new(3, 0)),
+        ];
+        for (index, char_position, utf16_position) in cases {
+            assert_eq!(
+                index_to_position(&document, index, ColumnUnit::Char),
+                Some(char_position)
+            );
+            assert_eq!(
+                index_to_position(&document, index, ColumnUnit::Utf16),
+                Some(utf16_position)
+            );
+            assert_eq!(
+                position_to_index(&document, char_position, ColumnUnit::Char),
+                Some(index)
+            );
+            assert_eq!(
+                position_to_index(&document, utf16_position, ColumnUnit::Utf16),
+                Some(index)
+            );
+        }
+        // In the middle of the emoji.
+        assert_eq!(index_to_position(&document, 2, ColumnUnit::Utf16), None);
+        assert_eq!(
+            position_to_index(&document, TextPosition::new(0, 2), ColumnUnit::Utf16),
+            None
+        );
+        // Past the end of the line and of the document.
+        assert_eq!(
+            position_to_index(&document, TextPosition::new(1, 1), ColumnUnit::Char),
+            None
+        );
+        assert_eq!(
+            position_to_index(&document, TextPosition::new(4, 0), ColumnUnit::Char),
+            None
+        );
+        assert_eq!(index_to_position(&document, 10, ColumnUnit::Char), None);
+    }
+}
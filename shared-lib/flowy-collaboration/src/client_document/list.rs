@@ -0,0 +1,153 @@
+use crate::client_document::lines::Lines;
+use lib_ot::{
+    core::{Operation, NEW_LINE},
+    rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta, TypedAttributeValue},
+};
+
+const ORDERED: &str = "ordered";
+
+/// Returns the delta that brings the lists of `document` back in order after an edit:
+/// - the items of an ordered list are numbered from 1 at each level, a line that isn't in a list
+///   ends the list and a bullet item restarts the numbers of its level.
+/// - an item is at most one level deeper than the item before it, e.g. the items whose parent was
+///   deleted are moved up.
+/// - the lines that aren't ordered items don't keep a number.
+///
+/// The delta is empty if the lists are in order.
+pub fn renumber_lists(document: &RichTextDelta) -> RichTextDelta {
+    let mut delta = RichTextDelta::new();
+    let mut offset = 0;
+    // The number of the last item at each level of the current list, 0 after a bullet item.
+    let mut numbers: Vec<usize> = vec![];
+    for line in Lines::new(document) {
+        let number = int_attribute(&line.attributes, &RichTextAttributeKey::ListNumber);
+        let mut attributes = RichTextAttributes::new();
+        match line
+            .attributes
+            .get(&RichTextAttributeKey::List)
+            .and_then(|value| value.as_str())
+        {
+            None => {
+                numbers.clear();
+                if number.is_some() {
+                    attributes.delete(&RichTextAttributeKey::ListNumber);
+                }
+            }
+            Some(kind) => {
+                let indent = int_attribute(&line.attributes, &RichTextAttributeKey::Indent).unwrap_or(0);
+                let level = indent.min(numbers.len());
+                if level != indent {
+                    match level {
+                        0 => attributes.delete(&RichTextAttributeKey::Indent),
+                        _ => attributes.add(RichTextAttribute::Indent(level)),
+                    }
+                }
+
+                numbers.truncate(level + 1);
+                if numbers.len() == level {
+                    numbers.push(0);
+                }
+                if kind == ORDERED {
+                    numbers[level] += 1;
+                    if number != Some(numbers[level]) {
+                        attributes.add(RichTextAttribute::ListNumber(numbers[level]));
+                    }
+                } else {
+                    numbers[level] = 0;
+                    if number.is_some() {
+                        attributes.delete(&RichTextAttributeKey::ListNumber);
+                    }
+                }
+            }
+        }
+
+        if !attributes.is_empty() {
+            delta.retain(line.interval.end - 1 - offset, RichTextAttributes::default());
+            delta.retain(1, attributes);
+            offset = line.interval.end;
+        }
+    }
+    delta
+}
+
+/// Returns whether `delta` may put the lists out of order: it adds or removes lines, or it changes
+/// the list or the indent of lines.
+pub fn may_change_lists(delta: &RichTextDelta) -> bool {
+    delta.ops.iter().any(|op| match op {
+        Operation::Insert(insert) => insert.s.contains(NEW_LINE),
+        Operation::Delete(_) => true,
+        Operation::Retain(retain) => retain.attributes.keys().any(|key| {
+            matches!(
+                key,
+                RichTextAttributeKey::List | RichTextAttributeKey::ListNumber | RichTextAttributeKey::Indent
+            )
+        }),
+    })
+}
+
+fn int_attribute(attributes: &RichTextAttributes, key: &RichTextAttributeKey) -> Option<usize> {
+    match attributes.get_typed(key)? {
+        TypedAttributeValue::Int(value) => Some(value.max(0) as usize),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::list::renumber_lists;
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributes, RichTextDeltaBuilder},
+    };
+
+    // The line of a list, the indent and the number are left out if they are 0.
+    fn item(kind: &str, indent: usize, number: usize) -> RichTextAttributes {
+        let mut attributes = AttributeBuilder::new().add_attr(RichTextAttribute::List(kind)).build();
+        if indent > 0 {
+            attributes.add(RichTextAttribute::Indent(indent));
+        }
+        if number > 0 {
+            attributes.add(RichTextAttribute::ListNumber(number));
+        }
+        attributes
+    }
+
+    #[test]
+    fn renumber_nested_lists() {
+        let document = RichTextDeltaBuilder::new()
+            .insert("a")
+            .insert_with_attributes("\n", item("ordered", 0, 1))
+            .insert("b")
+            .insert_with_attributes("\n", item("ordered", 2, 0))
+            .insert("c")
+            .insert_with_attributes("\n", item("ordered", 1, 1))
+            .insert("d")
+            .insert_with_attributes("\n", item("ordered", 0, 1))
+            .insert("e")
+            .insert_with_attributes("\n", item("bullet", 0, 3))
+            .insert("f\n")
+            .insert("g")
+            .insert_with_attributes("\n", item("ordered", 0, 5))
+            .build();
+        let renumbered = document.compose(&renumber_lists(&document)).unwrap();
+        assert_eq!(
+            renumbered,
+            RichTextDeltaBuilder::new()
+                .insert("a")
+                .insert_with_attributes("\n", item("ordered", 0, 1))
+                .insert("b")
+                .insert_with_attributes("\n", item("ordered", 1, 1))
+                .insert("c")
+                .insert_with_attributes("\n", item("ordered", 1, 2))
+                .insert("d")
+                .insert_with_attributes("\n", item("ordered", 0, 2))
+                .insert("e")
+                .insert_with_attributes("\n", item("bullet", 0, 0))
+                .insert("f\n")
+                .insert("g")
+                .insert_with_attributes("\n", item("ordered", 0, 1))
+                .build()
+        );
+        assert!(renumber_lists(&renumbered).is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+use crate::{
+    client_document::lines::{chars, position_to_index, ColumnUnit, TextPosition},
+    errors::CollaborateError,
+};
+use lib_ot::{
+    core::{Interval, Operation, OperationTransformable, NEW_LINE},
+    rich_text::{RichTextAttributes, RichTextDelta, RichTextDeltaBuilder},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+/// A position of the language server protocol, a line and a character from zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LspPosition {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl std::convert::From<TextPosition> for LspPosition {
+    fn from(position: TextPosition) -> Self {
+        Self {
+            line: position.line,
+            character: position.column,
+        }
+    }
+}
+
+impl std::convert::From<LspPosition> for TextPosition {
+    fn from(position: LspPosition) -> Self {
+        TextPosition::new(position.line, position.character)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LspRange {
+    pub start: LspPosition,
+    pub end: LspPosition,
+}
+
+impl fmt::Display for LspRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start.line, self.start.character, self.end.line, self.end.character
+        )
+    }
+}
+
+/// A change of the text of the document, serialized like the `TextDocumentContentChangeEvent` of
+/// the language server protocol. The text replaces the range, or the whole document if there's no
+/// range. An embed is its placeholder character.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentChange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<LspRange>,
+    /// The length of the range in utf16 code units. It's deprecated by the protocol, it's written
+    /// for the older clients and ignored when the change is read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range_length: Option<usize>,
+    pub text: String,
+}
+
+/// Returns the changes of the text that `delta` made, in the order the protocol applies them:
+/// the range of each change is a range of the document after the changes before it. `document` is
+/// the document after `delta`, and `inverted` is the delta that reverts it, like in a
+/// [DocumentEvent](crate::client_document::DocumentEvent). The changes of the attributes only
+/// aren't changes of the text and are left out.
+pub fn content_changes(
+    document: &RichTextDelta,
+    delta: &RichTextDelta,
+    inverted: &RichTextDelta,
+    unit: ColumnUnit,
+) -> Vec<ContentChange> {
+    // The text that each replacement of `delta` deleted is inserted back by `inverted`, at the same
+    // index of the document after the change.
+    let mut deleted = replacements(inverted)
+        .into_iter()
+        .map(|replacement| (replacement.base_index, replacement.text))
+        .collect::<HashMap<_, _>>();
+    let mut chars = chars(document);
+    let mut position = TextPosition::default();
+    let mut offset = 0;
+    let mut changes = vec![];
+    for replacement in replacements(delta) {
+        // The document before the index is the same after all the changes.
+        let index = replacement.target_index;
+        while offset < index {
+            match chars.next() {
+                None => break,
+                Some(c) => {
+                    offset += c.len_utf16();
+                    advance(&mut position, c, unit);
+                }
+            }
+        }
+        let mut end = position;
+        deleted
+            .remove(&index)
+            .unwrap_or_default()
+            .chars()
+            .for_each(|c| advance(&mut end, c, unit));
+        changes.push(ContentChange {
+            range: Some(LspRange {
+                start: position.into(),
+                end: end.into(),
+            }),
+            range_length: Some(replacement.deleted_len),
+            text: replacement.text,
+        });
+    }
+    changes
+}
+
+/// Returns the delta that makes the `changes` to `document`, which are applied in order like the
+/// protocol does. The text takes the inline attributes of the text it replaces, or of the
+/// character before it if the range is empty. Fails if a range isn't in the document.
+pub fn content_changes_to_delta(
+    document: &RichTextDelta,
+    changes: &[ContentChange],
+    unit: ColumnUnit,
+) -> Result<RichTextDelta, CollaborateError> {
+    let mut current = document.clone();
+    let mut delta = RichTextDelta::new();
+    for change in changes {
+        let (start, end) = match &change.range {
+            None => (0, current.utf16_target_len),
+            Some(range) => {
+                let start = position_to_index(&current, range.start.into(), unit);
+                let end = position_to_index(&current, range.end.into(), unit);
+                match (start, end) {
+                    (Some(start), Some(end)) if start <= end => (start, end),
+                    _ => {
+                        return Err(CollaborateError::out_of_bound()
+                            .context(format!("The range {} isn't in the document", range)))
+                    }
+                }
+            }
+        };
+        let attributes = text_attributes(&current, start, end);
+        let change_delta = RichTextDeltaBuilder::new()
+            .retain(start)
+            .insert_with_attributes(&change.text, attributes)
+            .delete(end - start)
+            .build();
+        current = current.compose(&change_delta)?;
+        delta = delta.compose(&change_delta)?;
+    }
+    Ok(delta)
+}
+
+// The runs of the inserts and the deletes of the delta, each with the index it's at in the
+// document before and after the delta, the text it inserts and the number of the characters it
+// deletes.
+fn replacements(delta: &RichTextDelta) -> Vec<Replacement> {
+    let mut replacements: Vec<Replacement> = vec![];
+    let (mut base_index, mut target_index) = (0, 0);
+    let mut in_run = false;
+    for op in &delta.ops {
+        if let Operation::Retain(retain) = op {
+            base_index += retain.n;
+            target_index += retain.n;
+            in_run = false;
+            continue;
+        }
+        if !in_run {
+            replacements.push(Replacement {
+                base_index,
+                target_index,
+                ..Replacement::default()
+            });
+            in_run = true;
+        }
+        let replacement = replacements.last_mut().unwrap();
+        match op {
+            Operation::Insert(insert) => {
+                replacement.text.push_str(&insert.s);
+                target_index += insert.utf16_size();
+            }
+            _ => {
+                replacement.deleted_len += op.len();
+                base_index += op.len();
+            }
+        }
+    }
+    replacements
+}
+
+#[derive(Default)]
+struct Replacement {
+    base_index: usize,
+    target_index: usize,
+    text: String,
+    deleted_len: usize,
+}
+
+fn advance(position: &mut TextPosition, c: char, unit: ColumnUnit) {
+    match c == '\n' {
+        true => *position = TextPosition::new(position.line + 1, 0),
+        false => position.column += unit.len(c),
+    }
+}
+
+// The attributes of the text of the change, from the character it starts at if it replaces one.
+// The newlines have the attributes of their line, which aren't taken.
+fn text_attributes(document: &RichTextDelta, start: usize, end: usize) -> RichTextAttributes {
+    let index = match (start < end, start) {
+        (true, start) => start,
+        (false, 0) => return RichTextAttributes::default(),
+        (false, start) => start - 1,
+    };
+    match document.ops_in(Interval::new(index, index + 1)).next() {
+        Some(slice) => match slice.op {
+            Operation::Insert(insert)
+                if insert.embed.is_none() && insert.s.sub_str(slice.interval).as_deref() != Some(NEW_LINE) =>
+            {
+                insert.attributes.clone()
+            }
+            _ => RichTextAttributes::default(),
+        },
+        None => RichTextAttributes::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        lines::ColumnUnit,
+        lsp::{content_changes, content_changes_to_delta, ContentChange, LspPosition, LspRange},
+        ClientDocument, NewlineDoc,
+    };
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{RichTextAttribute, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn content_changes_round_trip() {
+        let document = RichTextDeltaBuilder::new()
+            .insert("one\n")
+            .insert_with_attributes("two", RichTextAttribute::Bold(true).into())
+            .insert("\nthree\n")
+            .build();
+        // Replaces "ne\ntw" with "NE", and inserts "!" after "three".
+        let delta = RichTextDeltaBuilder::new()
+            .retain(1)
+            .insert("NE")
+            .delete(5)
+            .retain(7)
+            .insert("!")
+            .build();
+        let new_document = document.compose(&delta).unwrap();
+        let inverted = delta.invert(&document);
+
+        let changes = content_changes(&new_document, &delta, &inverted, ColumnUnit::Utf16);
+        let range = |start: (usize, usize), end: (usize, usize)| {
+            Some(LspRange {
+                start: LspPosition {
+                    line: start.0,
+                    character: start.1,
+                },
+                end: LspPosition {
+                    line: end.0,
+                    character: end.1,
+                },
+            })
+        };
+        assert_eq!(
+            changes,
+            vec![
+                ContentChange {
+                    range: range((0, 1), (1, 2)),
+                    range_length: Some(5),
+                    text: "NE".to_owned(),
+                },
+                ContentChange {
+                    range: range((1, 5), (1, 5)),
+                    range_length: Some(0),
+                    text: "!".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&changes[1]).unwrap(),
+            r#"{"range":{"start":{"line":1,"character":5},"end":{"line":1,"character":5}},"rangeLength":0,"text":"!"}"#
+        );
+
+        let applied = content_changes_to_delta(&document, &changes, ColumnUnit::Utf16).unwrap();
+        assert_eq!(document.compose(&applied).unwrap().to_json(), new_document.to_json());
+        // The text typed into the bold text is bold.
+        let typed = ContentChange {
+            range: range((1, 1), (1, 1)),
+            range_length: None,
+            text: "w".to_owned(),
+        };
+        let applied = content_changes_to_delta(&document, &[typed], ColumnUnit::Utf16).unwrap();
+        assert_eq!(
+            applied.to_json(),
+            r#"[{"retain":5},{"insert":"w","attributes":{"bold":true}}]"#
+        );
+        let outside = ContentChange {
+            range: range((5, 0), (5, 0)),
+            ..ContentChange::default()
+        };
+        assert!(content_changes_to_delta(&document, &[outside], ColumnUnit::Utf16).is_err());
+    }
+
+    #[test]
+    fn document_sends_and_applies_content_changes() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        let mut receiver = document.subscribe_content_changes();
+        document.insert(0, "ab\ncd").unwrap();
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.rev_id, 1);
+        assert_eq!(event.changes.len(), 1);
+        assert_eq!(event.changes[0].text, "ab\ncd");
+
+        let mut replica = ClientDocument::new::<NewlineDoc>();
+        replica.apply_content_changes(&event.changes).unwrap();
+        assert_eq!(replica.to_plain_string(), "ab\ncd\n");
+    }
+}
@@ -0,0 +1,547 @@
+use crate::{
+    client_document::{
+        autosave::{AutosaveConfig, Autosaver},
+        debug_stats::DebugStats,
+        export::{ExportOptions, ExporterRegistry},
+        global_search::{search_snapshot, DocumentSearchResult, SearchScope},
+        hibernation::HibernationStoreRef,
+        import::{ImportWarning, ImporterRegistry},
+        search::SearchQuery,
+        store::{load_document, DocumentStoreRef, SavePoint},
+        ClientDocument, DocumentConfig, DocumentHandle, NewlineDoc,
+    },
+    entities::revision::Revision,
+    errors::CollaborateError,
+};
+use async_stream::stream;
+use futures::Stream;
+use lib_ot::rich_text::RichTextDelta;
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+struct OpenDocument {
+    handle: DocumentHandle,
+    // The revision the store has the document at, which the autosaver moves too.
+    save_point: SavePoint,
+    autosaver: Option<Autosaver>,
+}
+
+impl OpenDocument {
+    // Whether there are handles to the document outside of the manager and its autosaver, whose
+    // changes would be lost if the document was closed.
+    fn is_held(&self) -> bool {
+        let own = 1 + self.autosaver.is_some() as usize;
+        self.handle.handle_count() > own
+    }
+
+    // Stops the autosaver, which saves the changes that are left.
+    async fn stop_autosave(&mut self) {
+        if let Some(autosaver) = self.autosaver.take() {
+            autosaver.stop().await;
+        }
+    }
+}
+
+#[derive(Default)]
+struct OpenDocuments {
+    documents: HashMap<String, OpenDocument>,
+    // The ids of the documents, from the least to the most recently used.
+    order: VecDeque<String>,
+}
+
+impl OpenDocuments {
+    fn touch(&mut self, doc_id: &str) {
+        self.order.retain(|id| id != doc_id);
+        self.order.push_back(doc_id.to_owned());
+    }
+}
+
+/// Opens the documents of a workspace from a [DocumentStore](crate::client_document::store::DocumentStore)
+/// and keeps up to `capacity` of them open. The least recently used document is saved and closed
+/// when another one is opened past the capacity, unless its handles are still held outside of the
+/// manager: it stays open then, and it's closed by a later open once they are dropped.
+pub struct DocumentManager {
+    store: DocumentStoreRef,
+    capacity: usize,
+    config: DocumentConfig,
+    exporters: Arc<ExporterRegistry>,
+    importers: Arc<ImporterRegistry>,
+    hibernation: Option<HibernationStoreRef>,
+    autosave: Option<AutosaveConfig>,
+    open_documents: Mutex<OpenDocuments>,
+}
+
+impl DocumentManager {
+    pub fn new(store: DocumentStoreRef, capacity: usize) -> Self {
+        Self {
+            store,
+            capacity: capacity.max(1),
+            config: DocumentConfig::default(),
+            exporters: Arc::new(ExporterRegistry::default()),
+            importers: Arc::new(ImporterRegistry::default()),
+            hibernation: None,
+            autosave: None,
+            open_documents: Mutex::new(OpenDocuments::default()),
+        }
+    }
+
+    /// The author and the device of the changes made to the documents, the `doc_id` is replaced by
+    /// the id of each document.
+    pub fn with_config(mut self, config: DocumentConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The exporters that the documents are exported with, the built-in ones and the formats that
+    /// the other crates registered.
+    pub fn with_exporters(mut self, exporters: ExporterRegistry) -> Self {
+        self.exporters = Arc::new(exporters);
+        self
+    }
+
+    pub fn exporters(&self) -> &ExporterRegistry {
+        &self.exporters
+    }
+
+    /// The importers that the documents are imported with, like [DocumentManager::with_exporters].
+    pub fn with_importers(mut self, importers: ImporterRegistry) -> Self {
+        self.importers = Arc::new(importers);
+        self
+    }
+
+    pub fn importers(&self) -> &ImporterRegistry {
+        &self.importers
+    }
+
+    /// The store that the documents are hibernated to, see [DocumentManager::hibernate].
+    pub fn with_hibernation(mut self, hibernation: HibernationStoreRef) -> Self {
+        self.hibernation = Some(hibernation);
+        self
+    }
+
+    /// Saves the open documents in the background while they change, see [Autosaver]. The
+    /// autosaver of a document shares the save point of the manager, so the saves of both write
+    /// the revisions after the last one.
+    pub fn with_autosave(mut self, config: AutosaveConfig) -> Self {
+        self.autosave = Some(config);
+        self
+    }
+
+    /// Returns the document, which is read from the store if it isn't open. The document that was
+    /// never saved opens empty, the one that was hibernated is resumed.
+    pub async fn open(&self, doc_id: &str) -> Result<DocumentHandle, CollaborateError> {
+        if let Some(handle) = self.get(doc_id) {
+            return Ok(handle);
+        }
+
+        let state = match &self.hibernation {
+            Some(hibernation) => hibernation.read_state(doc_id)?,
+            None => None,
+        };
+        let mut document = match &state {
+            Some(state) => ClientDocument::restore_state(state)?,
+            None => load_document(self.store.as_ref(), doc_id)?.unwrap_or_else(ClientDocument::new::<NewlineDoc>),
+        };
+        document.set_config(DocumentConfig {
+            doc_id: doc_id.to_owned(),
+            ..self.config.clone()
+        });
+        document.set_exporters(self.exporters.clone());
+        let save_point = SavePoint::new(document.rev_id());
+        let handle = DocumentHandle::new(document);
+
+        let (handle, evictable) = {
+            let mut open_documents = self.open_documents.lock();
+            // Another task may have opened it while it was read.
+            let handle = match open_documents.documents.get(doc_id) {
+                Some(document) => document.handle.clone(),
+                None => {
+                    let autosaver = self.autosave.clone().map(|config| {
+                        Autosaver::spawn_at(
+                            doc_id,
+                            handle.clone(),
+                            save_point.clone(),
+                            self.store.clone(),
+                            config,
+                            Arc::new(|doc_id, result| {
+                                if let Err(e) = result {
+                                    tracing::error!("Autosave the document {} failed: {}", doc_id, e);
+                                }
+                            }),
+                        )
+                    });
+                    let document = OpenDocument {
+                        handle: handle.clone(),
+                        save_point,
+                        autosaver,
+                    };
+                    open_documents.documents.insert(doc_id.to_owned(), document);
+                    handle
+                }
+            };
+            open_documents.touch(doc_id);
+            (handle, self.evictable(&open_documents))
+        };
+        if let (Some(hibernation), Some(_)) = (&self.hibernation, &state) {
+            hibernation.remove_state(doc_id)?;
+        }
+
+        for id in evictable {
+            if let Err(e) = self.evict(&id).await {
+                tracing::error!("Save the document {} failed: {}", id, e);
+            }
+        }
+        Ok(handle)
+    }
+
+    /// Returns the document if it's open.
+    pub fn get(&self, doc_id: &str) -> Option<DocumentHandle> {
+        let mut open_documents = self.open_documents.lock();
+        let handle = open_documents.documents.get(doc_id)?.handle.clone();
+        open_documents.touch(doc_id);
+        Some(handle)
+    }
+
+    pub fn is_open(&self, doc_id: &str) -> bool {
+        self.open_documents.lock().documents.contains_key(doc_id)
+    }
+
+    /// The ids of the open documents, from the least to the most recently used.
+    pub fn open_ids(&self) -> Vec<String> {
+        self.open_documents.lock().order.iter().cloned().collect()
+    }
+
+    /// Returns the [DebugStats] of the open documents, from the least to the most recently used.
+    pub async fn debug_stats(&self) -> Vec<DebugStats> {
+        let handles = {
+            let open_documents = self.open_documents.lock();
+            open_documents
+                .order
+                .iter()
+                .flat_map(|doc_id| open_documents.documents.get(doc_id))
+                .map(|document| document.handle.clone())
+                .collect::<Vec<_>>()
+        };
+        let mut stats = Vec::with_capacity(handles.len());
+        for handle in handles {
+            stats.push(handle.read(|document| document.debug_stats()).await);
+        }
+        stats
+    }
+
+    /// Composes the revision into the document it was made on, see [Revision::object_id]. The
+    /// document is opened if it isn't.
+    pub async fn receive_revision(&self, revision: &Revision) -> Result<RichTextDelta, CollaborateError> {
+        let handle = self.open(&revision.object_id).await?;
+        handle.apply_remote_revision(revision).await
+    }
+
+    /// Saves the document if it's open and changed since it was saved.
+    pub async fn save(&self, doc_id: &str) -> Result<(), CollaborateError> {
+        let (handle, save_point) = {
+            let open_documents = self.open_documents.lock();
+            match open_documents.documents.get(doc_id) {
+                None => return Ok(()),
+                Some(document) => (document.handle.clone(), document.save_point.clone()),
+            }
+        };
+        save_point.save(self.store.as_ref(), doc_id, &handle).await?;
+        Ok(())
+    }
+
+    /// Saves all the open documents. The documents that fail are reported in the error, after the
+    /// others were saved.
+    pub async fn save_all(&self) -> Result<(), CollaborateError> {
+        let mut failed = vec![];
+        for doc_id in self.open_ids() {
+            if let Err(e) = self.save(&doc_id).await {
+                failed.push(format!("{}: {}", doc_id, e));
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(CollaborateError::internal().context(failed.join(", "))),
+        }
+    }
+
+    /// Saves and closes the document. The handles to the document keep working, but their
+    /// changes aren't saved by the manager anymore.
+    pub async fn close(&self, doc_id: &str) -> Result<(), CollaborateError> {
+        self.save(doc_id).await?;
+        if let Some(mut document) = self.remove(doc_id) {
+            document.stop_autosave().await;
+        }
+        Ok(())
+    }
+
+    /// Saves the document, then encodes the state that it keeps in memory to the hibernation
+    /// store and closes it, e.g. when the app goes to the background. The state keeps the
+    /// revisions that may not be synced yet and the undo history, which
+    /// [DocumentManager::resume] restores as they were. Does nothing if the document isn't open.
+    pub async fn hibernate(&self, doc_id: &str) -> Result<(), CollaborateError> {
+        let hibernation = self
+            .hibernation
+            .as_ref()
+            .ok_or_else(|| CollaborateError::internal().context("The manager has no hibernation store"))?;
+        let handle = match self.get(doc_id) {
+            None => return Ok(()),
+            Some(handle) => handle,
+        };
+        let state = handle.write(|document| document.encode_state()).await?;
+        self.save(doc_id).await?;
+        hibernation.write_state(doc_id, &state)?;
+        if let Some(mut document) = self.remove(doc_id) {
+            document.stop_autosave().await;
+        }
+        Ok(())
+    }
+
+    /// Reopens the document that was hibernated by [DocumentManager::hibernate], like
+    /// [DocumentManager::open].
+    pub async fn resume(&self, doc_id: &str) -> Result<DocumentHandle, CollaborateError> {
+        self.open(doc_id).await
+    }
+
+    /// Exports the document with the exporter named `format`, which is opened if it isn't. The
+    /// document is exported from a [ReadSnapshot](crate::client_document::read_snapshot::ReadSnapshot),
+    /// so the export doesn't hold up the changes.
+    pub async fn export(
+        &self,
+        doc_id: &str,
+        format: &str,
+        options: &ExportOptions,
+    ) -> Result<Vec<u8>, CollaborateError> {
+        let snapshot = self.open(doc_id).await?.read_snapshot().await;
+        self.exporters.export(format, snapshot.delta(), options)
+    }
+
+    /// Replaces the content of the document with the data, imported by the first importer that
+    /// accepts it, see [ImporterRegistry::import]. The document is opened if it isn't. The
+    /// replacement is a local change, which can be undone. Returns what the import dropped.
+    pub async fn import(&self, doc_id: &str, data: &[u8]) -> Result<Vec<ImportWarning>, CollaborateError> {
+        let (mut delta, warnings) = self.importers.import(data)?;
+        let handle = self.open(doc_id).await?;
+        handle
+            .write(|document| {
+                delta.delete(document.delta().utf16_target_len);
+                document.compose_delta(delta)
+            })
+            .await?;
+        Ok(warnings)
+    }
+
+    /// Searches the documents of `scope` for `query`, e.g. for the quick open. The open documents
+    /// are searched first, from the most recently used, with their text indexes; the other ones
+    /// are read from the store without being opened. The result of each document that matched
+    /// is yielded once it's searched, the documents that fail to be read are yielded as errors
+    /// and the search goes on.
+    pub fn search<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+        scope: SearchScope,
+    ) -> impl Stream<Item = Result<DocumentSearchResult, CollaborateError>> + 'a {
+        stream! {
+            let mut open_ids = self.open_ids();
+            open_ids.reverse();
+            let doc_ids = match scope {
+                SearchScope::Open => open_ids,
+                SearchScope::All => match self.store.doc_ids() {
+                    Ok(stored_ids) => {
+                        let closed_ids = stored_ids
+                            .into_iter()
+                            .filter(|id| !open_ids.contains(id))
+                            .collect::<Vec<_>>();
+                        open_ids.into_iter().chain(closed_ids).collect()
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        open_ids
+                    }
+                },
+                SearchScope::Documents(doc_ids) => {
+                    let (mut open, closed): (Vec<_>, Vec<_>) =
+                        doc_ids.into_iter().partition(|id| open_ids.contains(id));
+                    open.sort_by_key(|id| open_ids.iter().position(|open_id| open_id == id));
+                    open.into_iter().chain(closed).collect()
+                }
+            };
+
+            for doc_id in doc_ids {
+                let snapshot = match self.get_without_touch(&doc_id) {
+                    Some(handle) => handle.read_snapshot().await,
+                    None => match load_document(self.store.as_ref(), &doc_id) {
+                        Ok(Some(document)) => document.read_snapshot(),
+                        Ok(None) => continue,
+                        Err(e) => {
+                            let msg = format!("Read the document {} failed: {}", doc_id, e.msg);
+                            yield Err(e.context(msg));
+                            continue;
+                        }
+                    },
+                };
+                match search_snapshot(&doc_id, &snapshot, query) {
+                    Ok(Some(result)) => yield Ok(result),
+                    Ok(None) => {}
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    // Unlike [DocumentManager::get], the document doesn't become the most recently used one.
+    fn get_without_touch(&self, doc_id: &str) -> Option<DocumentHandle> {
+        let open_documents = self.open_documents.lock();
+        open_documents
+            .documents
+            .get(doc_id)
+            .map(|document| document.handle.clone())
+    }
+
+    // The least recently used documents past the capacity that no one holds, which can be closed.
+    fn evictable(&self, open_documents: &OpenDocuments) -> Vec<String> {
+        let excess = open_documents.order.len().saturating_sub(self.capacity);
+        open_documents
+            .order
+            .iter()
+            .filter(|id| {
+                open_documents
+                    .documents
+                    .get(id.as_str())
+                    .map_or(false, |document| !document.is_held())
+            })
+            .take(excess)
+            .cloned()
+            .collect()
+    }
+
+    // Saves the document and closes it, unless it was used or a handle to it was taken while it
+    // was saved. The document is removed from the open documents only once it's saved, so the
+    // open that comes in between gets it instead of reading the store.
+    async fn evict(&self, doc_id: &str) -> Result<(), CollaborateError> {
+        self.save(doc_id).await?;
+        let mut document = {
+            let mut open_documents = self.open_documents.lock();
+            if !self.evictable(&open_documents).iter().any(|id| id == doc_id) {
+                return Ok(());
+            }
+            open_documents.order.retain(|id| id != doc_id);
+            match open_documents.documents.remove(doc_id) {
+                None => return Ok(()),
+                Some(document) => document,
+            }
+        };
+        // No one holds the document anymore, the autosaver saves the changes made since the save.
+        document.stop_autosave().await;
+        document
+            .save_point
+            .save(self.store.as_ref(), doc_id, &document.handle)
+            .await?;
+        Ok(())
+    }
+
+    fn remove(&self, doc_id: &str) -> Option<OpenDocument> {
+        let mut open_documents = self.open_documents.lock();
+        open_documents.order.retain(|id| id != doc_id);
+        open_documents.documents.remove(doc_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        autosave::AutosaveConfig,
+        export::ExportOptions,
+        manager::DocumentManager,
+        store::{MemoryDocumentStore, RevisionStore, SnapshotStore},
+    };
+    use crate::entities::revision::{md5, Revision};
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn document_manager_evicts_and_reopens() {
+        let store = Arc::new(MemoryDocumentStore::default());
+        let manager = DocumentManager::new(store.clone(), 1);
+        manager.open("a").await.unwrap().insert(0, "abc").await.unwrap();
+
+        // Opening "b" evicts "a", which is saved first.
+        manager.open("b").await.unwrap();
+        assert_eq!(manager.open_ids(), vec!["b".to_owned()]);
+        assert_eq!(store.read_snapshot("a").unwrap().unwrap().rev_id, 1);
+        assert_eq!(store.read_revisions("a", 0).unwrap().len(), 1);
+
+        let document = manager.open("a").await.unwrap();
+        assert_eq!(document.to_json().await, r#"[{"insert":"abc\n"}]"#);
+        assert_eq!(document.rev_id().await, 1);
+        assert!(!manager.is_open("b"));
+    }
+
+    #[tokio::test]
+    async fn document_manager_keeps_the_held_documents_open() {
+        let store = Arc::new(MemoryDocumentStore::default());
+        let manager = DocumentManager::new(store.clone(), 1).with_autosave(AutosaveConfig {
+            delay: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let held = manager.open("a").await.unwrap();
+        held.insert(0, "abc").await.unwrap();
+
+        // "a" stays open past the capacity while its handle is held, and its changes are saved.
+        manager.open("b").await.unwrap();
+        assert_eq!(manager.open_ids(), vec!["a".to_owned(), "b".to_owned()]);
+        held.insert(3, "d").await.unwrap();
+        drop(held);
+
+        // Once it's released, the next open closes it, and the save of the manager and the one
+        // of the autosaver write the revisions once.
+        manager.save("a").await.unwrap();
+        manager.open("c").await.unwrap();
+        assert!(!manager.is_open("a"));
+        assert_eq!(store.read_revisions("a", 0).unwrap().len(), 2);
+        let document = manager.open("a").await.unwrap();
+        assert_eq!(document.to_json().await, r#"[{"insert":"abcd\n"}]"#);
+    }
+
+    #[tokio::test]
+    async fn document_manager_routes_revisions() {
+        let store = Arc::new(MemoryDocumentStore::default());
+        let manager = DocumentManager::new(store.clone(), 2);
+        let delta = RichTextDeltaBuilder::new().insert("x").build();
+        let revision = Revision::new("b", 0, 1, delta.to_bytes(), "", md5(&delta.to_bytes()));
+        manager.receive_revision(&revision).await.unwrap();
+
+        assert!(manager.is_open("b"));
+        let document = manager.get("b").unwrap();
+        assert_eq!(document.to_json().await, r#"[{"insert":"x\n"}]"#);
+
+        manager.save_all().await.unwrap();
+        manager.close("b").await.unwrap();
+        assert!(!manager.is_open("b"));
+        assert_eq!(store.read_snapshot("b").unwrap().unwrap().rev_id, 1);
+    }
+
+    #[tokio::test]
+    async fn document_manager_imports_and_exports() {
+        let manager = DocumentManager::new(Arc::new(MemoryDocumentStore::default()), 1);
+        let document = manager.open("a").await.unwrap();
+        document.insert(0, "old").await.unwrap();
+
+        let json = r#"[{"insert":"new","attributes":{"bold":true,"author":"x"}},{"insert":"\n"}]"#;
+        let warnings = manager.import("a", json.as_bytes()).await.unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].dropped, "attribute author");
+        assert_eq!(
+            manager
+                .export("a", "markdown", &ExportOptions::default())
+                .await
+                .unwrap(),
+            b"**new**".to_vec()
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+};
+use lib_ot::{
+    core::{Embed, Operation},
+    rich_text::RichTextDelta,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// The kind of the embeds that are mentions.
+pub const MENTION_EMBED: &str = "mention";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Mention {
+    User(String),
+    Page(String),
+}
+
+impl Mention {
+    /// The mention is kept in the document as `{"insert":{"mention":{"type":"user","id":"..."}}}`.
+    pub fn to_embed(&self) -> Embed {
+        let (ty, id) = match self {
+            Mention::User(id) => ("user", id),
+            Mention::Page(id) => ("page", id),
+        };
+        Embed::new(MENTION_EMBED, json!({ "type": ty, "id": id }))
+    }
+
+    pub fn from_embed(embed: &Embed) -> Option<Self> {
+        if embed.kind != MENTION_EMBED {
+            return None;
+        }
+        let data = embed.data.to_value();
+        let id = data.get("id")?.as_str()?.to_owned();
+        match data.get("type")?.as_str()? {
+            "user" => Some(Mention::User(id)),
+            "page" => Some(Mention::Page(id)),
+            _ => None,
+        }
+    }
+}
+
+/// Lets the application show the mentions and follow them. The document only keeps the ids, the
+/// names are resolved when they are displayed.
+pub trait MentionResolver: Send + Sync {
+    /// Returns the name of the user or the page, or `None` if it doesn't exist anymore.
+    fn resolve(&self, mention: &Mention) -> Option<String>;
+
+    /// Called after a mention was added to the document, by any participant.
+    fn did_insert(&self, _mention: &Mention) {}
+
+    /// Called after a mention was removed from the document, by any participant.
+    fn did_delete(&self, _mention: &Mention) {}
+}
+
+/// Tells the [MentionResolver] about the mentions that each change inserted and deleted while it
+/// observes the document.
+pub struct MentionTracker {
+    resolver: Arc<dyn MentionResolver>,
+}
+
+impl MentionTracker {
+    pub fn new(resolver: Arc<dyn MentionResolver>) -> Self {
+        Self { resolver }
+    }
+
+    pub fn resolver(&self) -> &Arc<dyn MentionResolver> {
+        &self.resolver
+    }
+}
+
+impl DocumentObserver for MentionTracker {
+    fn did_reset(&mut self, document: &ObservedDocument, replaced: Option<&RichTextDelta>) {
+        if let Some(replaced) = replaced {
+            mentions(replaced)
+                .iter()
+                .for_each(|(_, mention)| self.resolver.did_delete(mention));
+            mentions(document.delta)
+                .iter()
+                .for_each(|(_, mention)| self.resolver.did_insert(mention));
+        }
+    }
+
+    fn did_change(&mut self, _document: &ObservedDocument, event: &DocumentEvent) {
+        inserted_mentions(&event.inverted)
+            .iter()
+            .for_each(|mention| self.resolver.did_delete(mention));
+        inserted_mentions(&event.delta)
+            .iter()
+            .for_each(|mention| self.resolver.did_insert(mention));
+    }
+}
+
+/// Returns the mentions of `document` with their positions.
+pub fn mentions(document: &RichTextDelta) -> Vec<(usize, Mention)> {
+    let mut mentions = vec![];
+    let mut offset = 0;
+    for op in &document.ops {
+        if let Some(mention) = op.get_embed().and_then(Mention::from_embed) {
+            mentions.push((offset, mention));
+        }
+        offset += op.len();
+    }
+    mentions
+}
+
+/// Returns the mentions that `delta` inserts. The mentions that a delta deletes are the ones
+/// that its inverted delta inserts.
+pub fn inserted_mentions(delta: &RichTextDelta) -> Vec<Mention> {
+    delta
+        .ops
+        .iter()
+        .filter(|op| op.is_insert())
+        .filter_map(Operation::get_embed)
+        .filter_map(Mention::from_embed)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::mention::{inserted_mentions, mentions, Mention};
+    use lib_ot::{
+        core::{Embed, OperationTransformable},
+        rich_text::{RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn mention_embed() {
+        let user = Mention::User("1".to_owned());
+        let page = Mention::Page("2".to_owned());
+        let document = RichTextDeltaBuilder::new().insert("hi \n").build();
+        let delta = RichTextDeltaBuilder::new()
+            .retain(3)
+            .insert_embed(user.to_embed())
+            .insert(" see ")
+            .insert_embed(page.to_embed())
+            .insert_embed(Embed::new("image", serde_json::json!({})))
+            .build();
+        let document: RichTextDelta = document.compose(&delta).unwrap();
+
+        assert_eq!(mentions(&document), vec![(3, user.clone()), (9, page.clone())]);
+        assert_eq!(inserted_mentions(&delta), vec![user, page]);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::entities::revision::{MetadataEntry, Revision};
+use std::collections::BTreeMap;
+
+/// The key-value metadata of a document, e.g. its title or its icon. The changes of the metadata
+/// are recorded as revisions in the log of the document, so they are ordered with the changes of
+/// its content and synced the same way, see [Revision::is_metadata]. The value of a key that was
+/// written last wins, the ties are broken by the device that wrote it, so the replicas agree on
+/// the metadata whatever the order they receive the changes in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    // The removed keys are kept, so that an older value received later doesn't bring them back.
+    entries: BTreeMap<String, MetadataEntry>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metadata that the metadata revisions of `revisions` leave.
+    pub fn from_revisions<'a>(revisions: impl IntoIterator<Item = &'a Revision>) -> Self {
+        let mut metadata = Self::new();
+        for revision in revisions {
+            for entry in &revision.metadata {
+                metadata.merge(entry.clone());
+            }
+        }
+        metadata
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.is_removed)
+            .map(|entry| entry.value.as_str())
+    }
+
+    /// The keys and their values, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .values()
+            .filter(|entry| !entry.is_removed)
+            .map(|entry| (entry.key.as_str(), entry.value.as_str()))
+    }
+
+    /// The entries of all the keys, including the removed ones, for sending the metadata to
+    /// another replica.
+    pub fn entries(&self) -> impl Iterator<Item = &MetadataEntry> {
+        self.entries.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Keeps `entry` if it was written after the value of its key. Returns whether it was kept.
+    pub fn merge(&mut self, entry: MetadataEntry) -> bool {
+        match self.entries.get(&entry.key) {
+            Some(current) if !is_newer(&entry, current) => false,
+            _ => {
+                self.entries.insert(entry.key.clone(), entry);
+                true
+            }
+        }
+    }
+}
+
+fn is_newer(entry: &MetadataEntry, other: &MetadataEntry) -> bool {
+    (entry.timestamp, &entry.device_id) > (other.timestamp, &other.device_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        clock::ManualClock,
+        metadata::Metadata,
+        store::{load_document, DocumentStore, MemoryDocumentStore, RevisionStore},
+        ClientDocument, DocumentConfigBuilder, NewlineDoc,
+    };
+    use std::sync::Arc;
+
+    fn document(device_id: &str, now: i64) -> ClientDocument {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(
+            DocumentConfigBuilder::new("doc")
+                .author(device_id, device_id)
+                .clock(Arc::new(ManualClock::new(now)))
+                .build(),
+        );
+        document
+    }
+
+    #[test]
+    fn metadata_converges_on_the_last_writer() {
+        let mut alice = document("alice", 2_000);
+        let mut bob = document("bob", 1_000);
+        alice.set_metadata("title", "Alice's").unwrap();
+        bob.insert(0, "hi").unwrap();
+        bob.set_metadata("title", "Bob's").unwrap();
+        bob.set_metadata("icon", "📄").unwrap();
+        alice.remove_metadata("icon").unwrap();
+
+        let from_alice = alice.revision_log().revisions().to_vec();
+        let from_bob = bob.revision_log().revisions().to_vec();
+        assert!(from_alice.iter().all(|revision| revision.is_metadata()));
+        assert!(!from_bob[0].is_metadata() && from_bob[1].is_metadata());
+        for revision in &from_alice {
+            bob.compose_remote_revision(revision).unwrap();
+        }
+        for revision in &from_bob {
+            alice.compose_remote_revision(revision).unwrap();
+        }
+
+        // The later writes of alice win, whatever the order they were received in.
+        assert_eq!(alice.metadata(), bob.metadata());
+        assert_eq!(alice.metadata().iter().collect::<Vec<_>>(), vec![("title", "Alice's")]);
+        assert_eq!(alice.to_plain_string(), "hi\n");
+        assert_eq!(
+            &Metadata::from_revisions(bob.revision_log().revisions()),
+            bob.metadata()
+        );
+    }
+
+    #[test]
+    fn metadata_survives_the_compaction_and_the_reload() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(
+            DocumentConfigBuilder::new("doc")
+                .author("alice", "phone")
+                .clock(Arc::new(ManualClock::new(1_000)))
+                .max_log_revisions(Some(2))
+                .build(),
+        );
+        document.set_metadata("title", "Notes").unwrap();
+        for (index, text) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            document.insert(index, text).unwrap();
+        }
+        // The revision of the title was compacted into a snapshot.
+        assert!(document
+            .revision_log()
+            .revisions()
+            .iter()
+            .all(|revision| !revision.is_metadata()));
+        let snapshot = document.snapshot();
+        assert_eq!(snapshot.metadata.len(), 1);
+
+        let store = MemoryDocumentStore::default();
+        store.save("doc", &snapshot, &document.revisions_after(0)).unwrap();
+        document.set_metadata("icon", "📄").unwrap();
+        store
+            .write_revisions("doc", &document.revisions_after(snapshot.rev_id))
+            .unwrap();
+
+        let reopened = load_document(&store, "doc").unwrap().unwrap();
+        assert_eq!(reopened.to_plain_string(), "abcde\n");
+        assert_eq!(reopened.metadata(), document.metadata());
+        assert_eq!(reopened.metadata().get("title"), Some("Notes"));
+        assert_eq!(reopened.metadata().get("icon"), Some("📄"));
+    }
+}
@@ -0,0 +1,80 @@
+use crate::errors::CollaborateError;
+use std::{sync::Arc, time::Duration};
+
+/// Lets the application export counters about the editing, e.g. to Prometheus. The hooks are
+/// called on the thread that edits the document, so they should only record the values.
+pub trait OTMetrics: Send + Sync {
+    /// Called after a change of `op_count` operations was composed into the document.
+    fn on_compose(&self, _duration: Duration, _op_count: usize) {}
+
+    /// Called after the history was transformed over a change of `op_count` operations that was
+    /// made by another participant.
+    fn on_transform(&self, _duration: Duration, _op_count: usize) {}
+
+    /// Called after an entry was added to the undo or the redo stack, with the sizes of the stacks.
+    fn on_history_grow(&self, _undo_len: usize, _redo_len: usize) {}
+
+    /// Called when a change can't be applied to the document.
+    fn on_error(&self, _error: &CollaborateError) {}
+}
+
+pub type OTMetricsRef = Arc<dyn OTMetrics>;
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{metrics::OTMetrics, ClientDocument, NewlineDoc};
+    use crate::entities::revision::{md5, Revision};
+    use crate::errors::CollaborateError;
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+    use parking_lot::Mutex;
+    use std::{sync::Arc, time::Duration};
+
+    #[derive(Default)]
+    struct Counters {
+        composes: usize,
+        ops: usize,
+        history_len: (usize, usize),
+        errors: usize,
+    }
+
+    #[derive(Default)]
+    struct CountingMetrics(Mutex<Counters>);
+
+    impl OTMetrics for CountingMetrics {
+        fn on_compose(&self, _duration: Duration, op_count: usize) {
+            let mut counters = self.0.lock();
+            counters.composes += 1;
+            counters.ops += op_count;
+        }
+
+        fn on_history_grow(&self, undo_len: usize, redo_len: usize) {
+            self.0.lock().history_len = (undo_len, redo_len);
+        }
+
+        fn on_error(&self, _error: &CollaborateError) {
+            self.0.lock().errors += 1;
+        }
+    }
+
+    #[test]
+    fn document_metrics() {
+        let metrics = Arc::new(CountingMetrics::default());
+        let mut document = ClientDocument::new::<NewlineDoc>().with_metrics(metrics.clone());
+        document.insert(0, "abc").unwrap();
+        assert_eq!(metrics.0.lock().composes, 1);
+        assert!(metrics.0.lock().ops > 0);
+        assert_eq!(metrics.0.lock().history_len, (1, 0));
+
+        document.undo().unwrap();
+        assert_eq!(metrics.0.lock().composes, 2);
+        assert_eq!(metrics.0.lock().history_len, (0, 1));
+
+        let delta = RichTextDeltaBuilder::new().insert("x").retain(1).build();
+        let revision = Revision::new("", 1, 2, delta.to_bytes(), "", md5(&delta.to_bytes()));
+        document.compose_remote_revision(&revision).unwrap();
+        assert_eq!(metrics.0.lock().errors, 0);
+        let revision = revision.with_content_md5(md5("x"));
+        assert!(document.compose_remote_revision(&revision).is_err());
+        assert_eq!(metrics.0.lock().errors, 1);
+    }
+}
@@ -1,12 +1,81 @@
 #![allow(clippy::module_inception)]
 
+pub use config::*;
 pub use document_pad::*;
+pub use event::*;
 pub(crate) use extensions::*;
+pub use handle::*;
 pub use view::*;
 
+pub mod anchor;
+pub mod annotation;
+pub mod ast;
+pub mod attribute_runs;
+pub mod audit;
+pub mod authorship;
+pub mod auto_link;
+pub mod autosave;
+pub mod boundary;
+pub mod bulk;
+pub mod changelog;
+pub mod clock;
+pub mod command;
+pub mod composition;
+mod config;
+pub mod content_policy;
 mod data;
+pub mod debug_stats;
+pub mod decoration;
+pub mod dedup;
 pub mod default;
+pub mod diff;
+pub mod direction;
 mod document_pad;
+pub mod embed_handler;
+mod event;
+pub mod export;
 mod extensions;
+pub mod format;
+pub mod global_search;
+mod handle;
+pub mod hibernation;
 pub mod history;
+pub mod import;
+pub mod input_rules;
+pub mod lines;
+pub mod list;
+pub mod lsp;
+pub mod manager;
+pub mod mention;
+pub mod metadata;
+pub mod metrics;
+pub mod normalize;
+pub mod observer;
+pub mod outline;
+pub mod paste;
+pub mod playback;
+pub mod presence;
+pub mod protection;
+pub mod read_snapshot;
+pub mod replay;
+pub mod revision_log;
+pub mod search;
+pub mod segmentation;
+pub mod snapshot_policy;
+pub mod spell_check;
+pub mod split;
+pub mod stats;
+pub mod store;
+pub mod stream;
+pub mod suggestion;
+pub mod summary;
+pub mod sync;
+pub mod table;
+pub mod template;
+pub mod terms;
+pub mod tombstone;
+pub mod transclusion;
+pub mod typing;
+pub mod verify;
 mod view;
+pub mod wal;
@@ -0,0 +1,166 @@
+use crate::util::line_at;
+use lib_ot::{
+    core::{Interval, Operation, NEW_LINE},
+    rich_text::{RichTextAttributeKey, RichTextDelta},
+};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// How the text is normalized as it's typed, see
+/// [ClientDocument::set_normalize_config](crate::client_document::ClientDocument::set_normalize_config).
+/// Each policy is off by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NormalizeConfig {
+    /// Composes the text to the Unicode normalization form C, e.g. an "e" followed by a combining
+    /// acute accent becomes an "é".
+    pub nfc: bool,
+    /// Replaces the straight quotes with the curly ones, opening after a space or at the start of
+    /// a line and closing otherwise.
+    pub smart_quotes: bool,
+    /// Replaces each tab with this number of spaces.
+    pub tab_width: Option<usize>,
+    /// Removes the spaces at the end of the lines that a typed newline ends.
+    pub trim_trailing_spaces: bool,
+}
+
+impl NormalizeConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.nfc || self.smart_quotes || self.tab_width.is_some() || self.trim_trailing_spaces
+    }
+}
+
+/// The text to insert once it's normalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedInput {
+    pub text: String,
+    /// The number of the trailing spaces just before the index, which the newline at the start of
+    /// the text ends, that are deleted before the text is inserted.
+    pub trimmed: usize,
+}
+
+/// Normalizes `text`, typed at `index` of `document`, with the policies of `config`. The smart
+/// quotes and the trimming don't apply in the code blocks, where the text is typed as it is.
+pub fn normalize_input(
+    document: &RichTextDelta,
+    index: usize,
+    text: &str,
+    config: &NormalizeConfig,
+) -> NormalizedInput {
+    let mut text = match config.nfc {
+        true => text.nfc().collect::<String>(),
+        false => text.to_owned(),
+    };
+    if let Some(width) = config.tab_width {
+        text = text.replace('\t', &" ".repeat(width));
+    }
+    let mut trimmed = 0;
+    if !config.smart_quotes && !config.trim_trailing_spaces {
+        return NormalizedInput { text, trimmed };
+    }
+
+    // The text of the line before the index.
+    let before = match line_at(document, index) {
+        None => String::new(),
+        Some(line) if is_code_block(document, line) => return NormalizedInput { text, trimmed },
+        Some(line) => text_in(document, Interval::new(line.start, index)),
+    };
+    if config.smart_quotes {
+        text = smart_quotes(&text, before.chars().last());
+    }
+    if config.trim_trailing_spaces && text.contains(NEW_LINE) {
+        let mut lines = text.split(NEW_LINE).collect::<Vec<_>>();
+        let last = lines.len() - 1;
+        lines[..last]
+            .iter_mut()
+            .for_each(|line| *line = line.trim_end_matches(' '));
+        if lines[0].is_empty() {
+            trimmed = before.len() - before.trim_end_matches(' ').len();
+        }
+        text = lines.join(NEW_LINE);
+    }
+    NormalizedInput { text, trimmed }
+}
+
+fn smart_quotes(text: &str, mut previous: Option<char>) -> String {
+    let mut quoted = String::with_capacity(text.len());
+    for c in text.chars() {
+        let opening = previous.map_or(true, |p| p.is_whitespace() || matches!(p, '(' | '[' | '{' | '“' | '‘'));
+        let c = match c {
+            '"' if opening => '“',
+            '"' => '”',
+            '\'' if opening => '‘',
+            '\'' => '’',
+            c => c,
+        };
+        quoted.push(c);
+        previous = Some(c);
+    }
+    quoted
+}
+
+fn is_code_block(document: &RichTextDelta, line: Interval) -> bool {
+    document
+        .ops_in(Interval::new(line.end - 1, line.end))
+        .next()
+        .map_or(false, |slice| {
+            slice.op.get_attributes().contains_key(&RichTextAttributeKey::CodeBlock)
+        })
+}
+
+fn text_in(document: &RichTextDelta, interval: Interval) -> String {
+    let mut text = String::new();
+    for slice in document.ops_in(interval) {
+        if let Operation::Insert(insert) = slice.op {
+            match slice.is_whole() {
+                true => text.push_str(insert.s.as_str()),
+                false => text.push_str(&insert.s.sub_str(slice.interval).unwrap_or_default()),
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::normalize::{normalize_input, NormalizeConfig, NormalizedInput};
+    use lib_ot::rich_text::{RichTextAttribute, RichTextDeltaBuilder};
+
+    #[test]
+    fn typed_text_is_normalized() {
+        let config = NormalizeConfig {
+            nfc: true,
+            smart_quotes: true,
+            tab_width: Some(2),
+            trim_trailing_spaces: true,
+        };
+        let document = RichTextDeltaBuilder::new()
+            .insert("say  \n")
+            .insert("code  ")
+            .insert_with_attributes("\n", RichTextAttribute::CodeBlock(true).into())
+            .build();
+        let normalize = |index, text| normalize_input(&document, index, text, &config);
+
+        assert_eq!(
+            normalize(3, " \"cafe\u{301}\" isn't\t"),
+            NormalizedInput {
+                text: " “café” isn’t  ".to_owned(),
+                trimmed: 0,
+            }
+        );
+        // The newline ends the line, its trailing spaces and the typed ones are removed.
+        assert_eq!(
+            normalize(5, " \n'a' \nb "),
+            NormalizedInput {
+                text: "\n‘a’\nb ".to_owned(),
+                trimmed: 2,
+            }
+        );
+        assert_eq!(
+            normalize(12, "\"\t\n"),
+            NormalizedInput {
+                text: "\"  \n".to_owned(),
+                trimmed: 0,
+            }
+        );
+    }
+}
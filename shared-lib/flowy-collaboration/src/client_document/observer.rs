@@ -0,0 +1,176 @@
+use crate::client_document::{event::DocumentEvent, segmentation::Segmenter};
+use lib_ot::rich_text::RichTextDelta;
+use std::any::Any;
+
+/// The document as the observers see it, see [DocumentObserver].
+pub struct ObservedDocument<'a> {
+    pub doc_id: &'a str,
+    pub delta: &'a RichTextDelta,
+    /// The local revision of the document, like [DocumentEvent::rev_id].
+    pub rev_id: i64,
+    /// The segmenter that splits the text of the document in words.
+    pub segmenter: &'a dyn Segmenter,
+}
+
+/// Keeps something that is derived from the document up to date with its changes, e.g. its stats
+/// or the misspelled words. The observers are opt-in: the document does the work of the ones that
+/// were added with [ClientDocument::add_observer](crate::client_document::ClientDocument::add_observer)
+/// only, and they are told about the changes by any participant.
+pub trait DocumentObserver: AsAny + Send + Sync {
+    /// Called when the observer is added to the document, with `replaced` empty, and when the
+    /// content of the document is replaced as a whole by
+    /// [ClientDocument::set_delta](crate::client_document::ClientDocument::set_delta), with the
+    /// content it replaced.
+    fn did_reset(&mut self, document: &ObservedDocument, replaced: Option<&RichTextDelta>);
+
+    /// Called before `delta` is composed into the document, e.g. to move the positions that the
+    /// observer keeps.
+    fn will_change(&mut self, _document: &ObservedDocument, _delta: &RichTextDelta) {}
+
+    /// Called after the change of the event was composed into the document.
+    fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent);
+
+    /// Called when the document splits its text with another segmenter.
+    fn did_change_segmenter(&mut self, _document: &ObservedDocument) {}
+
+    /// Called when the observer is removed from the document, or replaced by another one of its
+    /// type.
+    fn did_remove(&mut self, _doc_id: &str) {}
+}
+
+/// Lets the document find its observers by their types.
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// The observers of a document, one of each type, in the order they were added.
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: Vec<Box<dyn DocumentObserver>>,
+}
+
+impl Observers {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    pub(crate) fn add<O: DocumentObserver>(&mut self, document: &ObservedDocument, mut observer: O) {
+        let _ = self.remove::<O>(document.doc_id);
+        observer.did_reset(document, None);
+        self.observers.push(Box::new(observer));
+    }
+
+    pub(crate) fn get<O: DocumentObserver>(&self) -> Option<&O> {
+        self.observers
+            .iter()
+            .find_map(|observer| observer.as_ref().as_any().downcast_ref::<O>())
+    }
+
+    pub(crate) fn get_mut<O: DocumentObserver>(&mut self) -> Option<&mut O> {
+        self.observers
+            .iter_mut()
+            .find_map(|observer| observer.as_mut().as_any_mut().downcast_mut::<O>())
+    }
+
+    pub(crate) fn remove<O: DocumentObserver>(&mut self, doc_id: &str) -> Option<O> {
+        let index = self
+            .observers
+            .iter()
+            .position(|observer| observer.as_ref().as_any().is::<O>())?;
+        let mut observer = self.observers.remove(index);
+        observer.did_remove(doc_id);
+        observer.into_any().downcast::<O>().ok().map(|observer| *observer)
+    }
+
+    pub(crate) fn did_reset(&mut self, document: &ObservedDocument, replaced: &RichTextDelta) {
+        self.observers
+            .iter_mut()
+            .for_each(|observer| observer.did_reset(document, Some(replaced)));
+    }
+
+    pub(crate) fn will_change(&mut self, document: &ObservedDocument, delta: &RichTextDelta) {
+        self.observers
+            .iter_mut()
+            .for_each(|observer| observer.will_change(document, delta));
+    }
+
+    pub(crate) fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent) {
+        self.observers
+            .iter_mut()
+            .for_each(|observer| observer.did_change(document, event));
+    }
+
+    pub(crate) fn did_change_segmenter(&mut self, document: &ObservedDocument) {
+        self.observers
+            .iter_mut()
+            .for_each(|observer| observer.did_change_segmenter(document));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        event::DocumentEvent,
+        observer::{DocumentObserver, ObservedDocument},
+        stats::StatsTracker,
+        ClientDocument, NewlineDoc,
+    };
+    use lib_ot::rich_text::RichTextDelta;
+
+    #[derive(Default)]
+    struct ChangeCounter {
+        resets: usize,
+        changes: Vec<i64>,
+    }
+
+    impl DocumentObserver for ChangeCounter {
+        fn did_reset(&mut self, _document: &ObservedDocument, _replaced: Option<&RichTextDelta>) {
+            self.resets += 1;
+        }
+
+        fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent) {
+            assert_eq!(document.rev_id, event.rev_id);
+            self.changes.push(event.rev_id);
+        }
+    }
+
+    #[test]
+    fn observers_follow_the_changes_once_added() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "hello").unwrap();
+        assert!(document.observer::<ChangeCounter>().is_none());
+
+        document.add_observer(ChangeCounter::default());
+        document.add_observer(StatsTracker::default());
+        document.insert(5, " world").unwrap();
+        let delta = document.delta().clone();
+        document.set_delta(delta);
+        let counter = document.observer::<ChangeCounter>().unwrap();
+        assert_eq!(counter.resets, 2);
+        assert_eq!(counter.changes, vec![2]);
+        assert_eq!(document.observer::<StatsTracker>().unwrap().stats().words, 2);
+
+        let counter = document.remove_observer::<ChangeCounter>().unwrap();
+        document.insert(0, "!").unwrap();
+        assert_eq!(counter.changes, vec![2]);
+        assert!(document.observer::<ChangeCounter>().is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::client_document::{
+    anchor::{AnchorBias, AnchorId, Anchors},
+    ast::{BlockChange, BlockId, BlockKind, DocumentTree},
+};
+use lib_ot::core::Interval;
+use std::collections::HashMap;
+
+/// A heading of the document, see [ClientDocument::outline](crate::client_document::ClientDocument::outline).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutlineEntry {
+    pub block_id: BlockId,
+    pub level: usize,
+    pub text: String,
+    /// The text of the heading, without its newline.
+    pub interval: Interval,
+    /// The anchor at the start of the heading, which the navigation scrolls to.
+    pub anchor: AnchorId,
+}
+
+#[derive(Debug, Clone)]
+struct Heading {
+    level: usize,
+    text: String,
+    len: usize,
+    anchor: AnchorId,
+}
+
+/// The headings of the document, kept up to date with the changes of its blocks. The start of
+/// each heading is an anchor, which moves with the edits before the heading, and is set again
+/// when the heading itself changes.
+#[derive(Debug, Clone, Default)]
+pub struct Outline {
+    headings: HashMap<BlockId, Heading>,
+}
+
+impl Outline {
+    pub fn new(tree: &DocumentTree, anchors: &mut Anchors) -> Self {
+        let mut outline = Self::default();
+        outline.rebuild(tree, anchors);
+        outline
+    }
+
+    /// Finds the headings of the tree again, e.g. after the content of the document was replaced.
+    pub fn rebuild(&mut self, tree: &DocumentTree, anchors: &mut Anchors) {
+        self.clear(anchors);
+        let mut offset = 0;
+        for block in &tree.blocks {
+            if let BlockKind::Heading(level) = block.kind {
+                self.insert(block.id, level, block.text(), offset, anchors);
+            }
+            offset += block.utf16_size();
+        }
+    }
+
+    /// Updates the headings with the `changes` that made `tree`.
+    pub fn apply(&mut self, tree: &DocumentTree, changes: &[BlockChange], anchors: &mut Anchors) {
+        for change in changes {
+            let (id, line) = match change {
+                BlockChange::Removed { id, .. } => {
+                    self.remove(*id, anchors);
+                    continue;
+                }
+                BlockChange::Inserted { id, line, .. }
+                | BlockChange::KindChanged { id, line, .. }
+                | BlockChange::ContentChanged { id, line } => (*id, *line),
+                BlockChange::Moved { id, to, .. } => (*id, *to),
+            };
+            self.remove(id, anchors);
+            let block = match tree.blocks.get(line) {
+                None => continue,
+                Some(block) => block,
+            };
+            if let BlockKind::Heading(level) = block.kind {
+                self.insert(id, level, block.text(), tree.offset_of(line), anchors);
+            }
+        }
+    }
+
+    /// The headings in the order of the document.
+    pub fn entries(&self, anchors: &Anchors) -> Vec<OutlineEntry> {
+        let mut entries = self
+            .headings
+            .iter()
+            .filter_map(|(block_id, heading)| {
+                let start = anchors.get(heading.anchor)?.index;
+                Some(OutlineEntry {
+                    block_id: *block_id,
+                    level: heading.level,
+                    text: heading.text.clone(),
+                    interval: Interval::new(start, start + heading.len),
+                    anchor: heading.anchor,
+                })
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.interval.start);
+        entries
+    }
+
+    /// Removes the headings and their anchors.
+    pub fn clear(&mut self, anchors: &mut Anchors) {
+        for (_, heading) in self.headings.drain() {
+            anchors.remove(heading.anchor);
+        }
+    }
+
+    fn insert(&mut self, id: BlockId, level: usize, text: String, offset: usize, anchors: &mut Anchors) {
+        // The text inserted at the start of the heading changes the heading, which sets the
+        // anchor again, otherwise it's a line inserted before the heading.
+        let anchor = anchors.create(offset, AnchorBias::Right);
+        let len = text.encode_utf16().count();
+        self.headings.insert(
+            id,
+            Heading {
+                level,
+                text,
+                len,
+                anchor,
+            },
+        );
+    }
+
+    fn remove(&mut self, id: BlockId, anchors: &mut Anchors) {
+        if let Some(heading) = self.headings.remove(&id) {
+            anchors.remove(heading.anchor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{anchor::Anchors, ast::DocumentTree, outline::Outline};
+    use lib_ot::{
+        core::{Interval, OperationTransformable},
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributeKey, RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn outline_follows_the_changes() {
+        let mut document = RichTextDeltaBuilder::new()
+            .insert("Title")
+            .insert_with_attributes("\n", RichTextAttribute::Header(1).into())
+            .insert("text\nPart")
+            .insert_with_attributes("\n", RichTextAttribute::Header(2).into())
+            .build();
+        let mut tree = DocumentTree::parse(&document);
+        let mut anchors = Anchors::new();
+        let mut outline = Outline::new(&tree, &mut anchors);
+        let mut apply = |delta: RichTextDelta| {
+            document = document.compose(&delta).unwrap();
+            anchors.transform(&delta);
+            let changes = tree.apply(&delta, &document);
+            outline.apply(&tree, &changes.blocks, &mut anchors);
+            outline
+                .entries(&anchors)
+                .into_iter()
+                .map(|entry| (entry.level, entry.text, entry.interval))
+                .collect::<Vec<_>>()
+        };
+
+        // A line before the second heading moves it.
+        assert_eq!(
+            apply(RichTextDeltaBuilder::new().retain(11).insert("more\n").build()),
+            vec![
+                (1, "Title".to_owned(), Interval::new(0, 5)),
+                (2, "Part".to_owned(), Interval::new(16, 20)),
+            ]
+        );
+        // Type at the start of the first heading.
+        assert_eq!(
+            apply(RichTextDeltaBuilder::new().insert("A ").build()),
+            vec![
+                (1, "A Title".to_owned(), Interval::new(0, 7)),
+                (2, "Part".to_owned(), Interval::new(18, 22)),
+            ]
+        );
+        // The first heading becomes a paragraph.
+        let delta = RichTextDeltaBuilder::new()
+            .retain(7)
+            .retain_with_attributes(
+                1,
+                AttributeBuilder::new()
+                    .remove_attr(RichTextAttributeKey::Header)
+                    .build(),
+            )
+            .build();
+        assert_eq!(apply(delta), vec![(2, "Part".to_owned(), Interval::new(18, 22))]);
+    }
+}
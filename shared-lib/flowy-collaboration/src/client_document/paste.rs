@@ -0,0 +1,136 @@
+use crate::util::line_at;
+use lib_ot::{
+    core::{Interval, Operation, NEW_LINE},
+    rich_text::{AttributeScope, RichTextAttributes, RichTextDelta, RichTextDeltaBuilder},
+};
+
+/// What becomes of the attributes of the pasted text, see [crate::client_document::ClientDocument::insert_delta].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasteStrategy {
+    /// The pasted text keeps its attributes, and its lines their kind, e.g. a heading.
+    KeepFormatting,
+    /// The pasted text takes the inline attributes of the text before it, like the text typed
+    /// there, and its lines the kind of the line they are pasted into, e.g. they become items of
+    /// the list.
+    MatchSurrounding,
+    /// The pasted text and its lines have no attributes. The embeds are kept.
+    PlainText,
+}
+
+/// Returns the delta that inserts the content of `fragment` at `index` of `document` with the
+/// attributes that `strategy` gives it. The operations of `fragment` other than the inserts are
+/// ignored.
+///
+/// The block attributes of the line that the fragment is pasted into stay with the text after
+/// `index`, because they are on the newline that ends it. The newlines of the fragment end the
+/// lines before it, which take the block attributes of these newlines.
+pub fn paste_delta(
+    document: &RichTextDelta,
+    index: usize,
+    fragment: &RichTextDelta,
+    strategy: PasteStrategy,
+) -> RichTextDelta {
+    let (surrounding_inline, surrounding_block) = match strategy {
+        PasteStrategy::MatchSurrounding => (
+            inline_attributes_before(document, index),
+            line_attributes(document, index),
+        ),
+        _ => (RichTextAttributes::default(), RichTextAttributes::default()),
+    };
+    let mut delta = RichTextDeltaBuilder::new().retain(index).build();
+    for op in &fragment.ops {
+        let insert = match op {
+            Operation::Insert(insert) => insert,
+            _ => continue,
+        };
+        let (inline, block) = match strategy {
+            PasteStrategy::KeepFormatting => (
+                scoped(&insert.attributes, AttributeScope::Inline),
+                scoped(&insert.attributes, AttributeScope::Block),
+            ),
+            _ => (surrounding_inline.clone(), surrounding_block.clone()),
+        };
+        if let Some(embed) = &insert.embed {
+            delta.insert_embed(embed.clone(), inline);
+            continue;
+        }
+        for (i, text) in insert.s.split(NEW_LINE).enumerate() {
+            if i > 0 {
+                delta.insert(NEW_LINE, block.clone());
+            }
+            if !text.is_empty() {
+                delta.insert(text, inline.clone());
+            }
+        }
+    }
+    delta
+}
+
+fn scoped(attributes: &RichTextAttributes, scope: AttributeScope) -> RichTextAttributes {
+    let mut attributes = attributes.clone();
+    attributes.retain(|key, value| key.scope() == scope && value.0.is_some());
+    attributes
+}
+
+// The inline attributes of the character before `index`, which the text typed there takes.
+fn inline_attributes_before(document: &RichTextDelta, index: usize) -> RichTextAttributes {
+    if index == 0 {
+        return RichTextAttributes::default();
+    }
+    match document.ops_in(Interval::new(index - 1, index)).next() {
+        None => RichTextAttributes::default(),
+        Some(slice) => scoped(&slice.op.get_attributes(), AttributeScope::Inline),
+    }
+}
+
+// The block attributes of the line that contains `index`, which are on the newline that ends it.
+fn line_attributes(document: &RichTextDelta, index: usize) -> RichTextAttributes {
+    let line = match line_at(document, index) {
+        None => return RichTextAttributes::default(),
+        Some(line) => line,
+    };
+    match document.ops_in(Interval::new(line.end - 1, line.end)).next() {
+        None => RichTextAttributes::default(),
+        Some(slice) => scoped(&slice.op.get_attributes(), AttributeScope::Block),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::paste::{paste_delta, PasteStrategy};
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{RichTextAttribute, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn paste_with_each_strategy() {
+        let document = RichTextDeltaBuilder::new()
+            .insert_with_attributes("ab", RichTextAttribute::Italic(true).into())
+            .insert_with_attributes("\n", RichTextAttribute::Bullet(true).into())
+            .build();
+        let fragment = RichTextDeltaBuilder::new()
+            .insert_with_attributes("1", RichTextAttribute::Bold(true).into())
+            .insert_with_attributes("\n", RichTextAttribute::Header(1).into())
+            .insert("2")
+            .build();
+        let paste = |strategy| {
+            document
+                .compose(&paste_delta(&document, 1, &fragment, strategy))
+                .unwrap()
+        };
+
+        assert_eq!(
+            paste(PasteStrategy::KeepFormatting).to_json(),
+            r#"[{"insert":"a","attributes":{"italic":true}},{"insert":"1","attributes":{"bold":true}},{"insert":"\n","attributes":{"header":1}},{"insert":"2"},{"insert":"b","attributes":{"italic":true}},{"insert":"\n","attributes":{"list":"bullet"}}]"#
+        );
+        assert_eq!(
+            paste(PasteStrategy::MatchSurrounding).to_json(),
+            r#"[{"insert":"a1","attributes":{"italic":true}},{"insert":"\n","attributes":{"list":"bullet"}},{"insert":"2b","attributes":{"italic":true}},{"insert":"\n","attributes":{"list":"bullet"}}]"#
+        );
+        assert_eq!(
+            paste(PasteStrategy::PlainText).to_json(),
+            r#"[{"insert":"a","attributes":{"italic":true}},{"insert":"1\n2"},{"insert":"b","attributes":{"italic":true}},{"insert":"\n","attributes":{"list":"bullet"}}]"#
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{client_document::revision_log::RevisionLog, entities::revision::Revision, errors::CollaborateError};
+use lib_ot::rich_text::RichTextDelta;
+use std::time::Duration;
+
+/// A revision of the document to animate, after waiting `delay` since the previous one.
+#[derive(Debug, Clone)]
+pub struct PlaybackFrame {
+    pub rev_id: i64,
+    pub delay: Duration,
+    pub delta: RichTextDelta,
+}
+
+/// Plays the revisions of a [RevisionLog] back at the pace they were made, e.g. to show how a
+/// document was written. It starts from the oldest snapshot of the log, the editor shows the
+/// content given by [Playback::seek] and then composes the delta of each frame after its delay.
+pub struct Playback<'a> {
+    log: &'a RevisionLog,
+    // The revisions after the oldest snapshot, and the index of the next one to play.
+    revisions: &'a [Revision],
+    next: usize,
+    // When the last frame was made, none if the next frame is the first one or follows a seek.
+    previous: Option<i64>,
+    speed: f64,
+    max_delay: Option<Duration>,
+}
+
+impl<'a> Playback<'a> {
+    pub fn new(log: &'a RevisionLog) -> Self {
+        let start_rev_id = log.snapshots()[0].rev_id;
+        let start = log
+            .revisions()
+            .partition_point(|revision| revision.rev_id <= start_rev_id);
+        Self {
+            log,
+            revisions: &log.revisions()[start..],
+            next: 0,
+            previous: None,
+            speed: 1.0,
+            max_delay: None,
+        }
+    }
+
+    /// Scales the delays, a speed of 2 plays the revisions twice as fast. The speeds that aren't
+    /// above zero are ignored.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        if speed > 0.0 {
+            self.speed = speed;
+        }
+        self
+    }
+
+    /// Shortens the delays over `max_delay`, the pauses between the editing sessions, to it.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// The revision that the playback starts from.
+    pub fn start_rev_id(&self) -> i64 {
+        self.log.snapshots()[0].rev_id
+    }
+
+    /// The revision that the last frame played.
+    pub fn rev_id(&self) -> i64 {
+        match self.next {
+            0 => self.start_rev_id(),
+            next => self.revisions[next - 1].rev_id,
+        }
+    }
+
+    /// The revisions that the playback can seek to without composing the revisions from the start,
+    /// its snapshots and its tags, in order.
+    pub fn checkpoints(&self) -> Vec<i64> {
+        let mut checkpoints = self
+            .log
+            .snapshots()
+            .iter()
+            .map(|snapshot| snapshot.rev_id)
+            .chain(self.log.list_tags().iter().map(|tag| tag.rev_id()))
+            .collect::<Vec<_>>();
+        checkpoints.sort_unstable();
+        checkpoints.dedup();
+        checkpoints
+    }
+
+    /// Moves the playback to `rev_id` and returns the content of the document at it, which the
+    /// next frames apply to.
+    pub fn seek(&mut self, rev_id: i64) -> Result<RichTextDelta, CollaborateError> {
+        if rev_id < self.start_rev_id() {
+            return Err(CollaborateError::record_not_found().context(format!("The revision {} was compacted", rev_id)));
+        }
+        let delta = self.log.delta_at(rev_id)?;
+        self.next = self.revisions.partition_point(|revision| revision.rev_id <= rev_id);
+        self.previous = None;
+        Ok(delta)
+    }
+
+    // The first frame, and the first one after a seek, are played right away.
+    fn delay(&self, timestamp: i64) -> Duration {
+        let elapsed = match self.previous {
+            None => return Duration::ZERO,
+            Some(previous) => timestamp - previous,
+        };
+        let delay = Duration::from_millis(elapsed.max(0) as u64).div_f64(self.speed);
+        match self.max_delay {
+            Some(max_delay) => delay.min(max_delay),
+            None => delay,
+        }
+    }
+}
+
+impl<'a> Iterator for Playback<'a> {
+    type Item = Result<PlaybackFrame, CollaborateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let revision = self.revisions.get(self.next)?;
+        let delay = self.delay(revision.timestamp);
+        self.next += 1;
+        self.previous = Some(revision.timestamp);
+        let frame = RichTextDelta::from_bytes(&revision.delta_data).map(|delta| PlaybackFrame {
+            rev_id: revision.rev_id,
+            delay,
+            delta,
+        });
+        Some(frame.map_err(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{playback::Playback, revision_log::RevisionLog, ClientDocument, NewlineDoc};
+    use lib_ot::core::OperationTransformable;
+    use std::time::Duration;
+
+    #[test]
+    fn playback_rebuilds_the_document() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "a").unwrap();
+        document.insert(1, "b").unwrap();
+        document.insert(2, "c").unwrap();
+        // The revisions were made 2s and 60s apart.
+        let mut log = RevisionLog::new(0, &document.revision_log().snapshots()[0].delta().unwrap());
+        for (revision, timestamp) in document.revision_log().revisions().iter().zip([1_000, 3_000, 63_000]) {
+            let mut revision = revision.clone();
+            revision.timestamp = timestamp;
+            log.push(revision);
+        }
+
+        let mut playback = Playback::new(&log)
+            .with_speed(2.0)
+            .with_max_delay(Duration::from_secs(10));
+        let mut delta = playback.seek(playback.start_rev_id()).unwrap();
+        let mut delays = vec![];
+        for frame in &mut playback {
+            let frame = frame.unwrap();
+            delta = delta.compose(&frame.delta).unwrap();
+            delays.push(frame.delay);
+        }
+        assert_eq!(delta, *document.delta());
+        assert_eq!(
+            delays,
+            vec![Duration::ZERO, Duration::from_secs(1), Duration::from_secs(10)]
+        );
+
+        assert_eq!(playback.seek(2).unwrap().apply("").unwrap(), "ab\n");
+        let frame = playback.next().unwrap().unwrap();
+        assert_eq!((frame.rev_id, frame.delay), (3, Duration::ZERO));
+        assert!(playback.next().is_none());
+    }
+}
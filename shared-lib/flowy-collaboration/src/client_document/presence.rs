@@ -0,0 +1,138 @@
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSelection {
+    pub user_id: String,
+    pub interval: Interval,
+}
+
+impl RemoteSelection {
+    /// Returns whether the selection is a caret without any selected text.
+    pub fn is_caret(&self) -> bool {
+        self.interval.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceEvent {
+    Changed(RemoteSelection),
+    Removed { user_id: String },
+}
+
+/// Tracks the selections of the other participants of the document.
+///
+/// Every delta that is composed into the document must be passed to [Presence::transform], so
+/// the selections keep pointing at the same text while it changes.
+pub struct Presence {
+    selections: HashMap<String, RemoteSelection>,
+    notifier: broadcast::Sender<PresenceEvent>,
+}
+
+impl std::default::Default for Presence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        let (notifier, _) = broadcast::channel(100);
+        Self {
+            selections: HashMap::new(),
+            notifier,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PresenceEvent> {
+        self.notifier.subscribe()
+    }
+
+    pub fn set_selection<T: AsRef<str>>(&mut self, user_id: T, interval: Interval) {
+        let selection = RemoteSelection {
+            user_id: user_id.as_ref().to_owned(),
+            interval,
+        };
+        if self.selections.get(&selection.user_id) == Some(&selection) {
+            return;
+        }
+        self.selections.insert(selection.user_id.clone(), selection.clone());
+        let _ = self.notifier.send(PresenceEvent::Changed(selection));
+    }
+
+    pub fn remove_selection<T: AsRef<str>>(&mut self, user_id: T) {
+        let user_id = user_id.as_ref().to_owned();
+        if self.selections.remove(&user_id).is_some() {
+            let _ = self.notifier.send(PresenceEvent::Removed { user_id });
+        }
+    }
+
+    pub fn get_selection(&self, user_id: &str) -> Option<&RemoteSelection> {
+        self.selections.get(user_id)
+    }
+
+    pub fn selections(&self) -> impl Iterator<Item = &RemoteSelection> {
+        self.selections.values()
+    }
+
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        if delta.is_noop() {
+            return;
+        }
+
+        for selection in self.selections.values_mut() {
+            let Interval { start, end } = selection.interval;
+            // The text typed at the edges of a selection isn't selected, so the start moves
+            // behind the inserted text and the end stays in front of it.
+            let new_start = delta.transform_position(start, false);
+            let new_end = match selection.is_caret() {
+                true => new_start,
+                false => delta.transform_position(end, true).max(new_start),
+            };
+            if new_start != start || new_end != end {
+                selection.interval = Interval::new(new_start, new_end);
+                let _ = self.notifier.send(PresenceEvent::Changed(selection.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::presence::{Presence, PresenceEvent};
+    use lib_ot::{core::Interval, rich_text::RichTextDeltaBuilder};
+
+    #[test]
+    fn presence_transform_selection() {
+        let mut presence = Presence::new();
+        let mut receiver = presence.subscribe();
+        presence.set_selection("a", Interval::new(2, 4));
+        presence.set_selection("b", Interval::new(6, 6));
+
+        // Insert at the start of a's selection and delete the text in front of b's caret.
+        let delta = RichTextDeltaBuilder::new()
+            .retain(2)
+            .insert("xy")
+            .retain(3)
+            .delete(2)
+            .build();
+        presence.transform(&delta);
+
+        assert_eq!(presence.get_selection("a").unwrap().interval, Interval::new(4, 6));
+        assert_eq!(presence.get_selection("b").unwrap().interval, Interval::new(7, 7));
+
+        presence.remove_selection("a");
+        let mut events = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events.last().unwrap(),
+            &PresenceEvent::Removed {
+                user_id: "a".to_owned()
+            }
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{errors::RegionLockedError, server_document::DocumentAction};
+use lib_ot::{
+    core::{Interval, Operation},
+    rich_text::{RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+
+/// Lets the local changes write and format the locked text, see
+/// [ClientDocument::unlock_regions](crate::client_document::ClientDocument::unlock_regions). The
+/// document doesn't check the token, it's the host's proof that the user may unlock the text, and
+/// the server checks the permission of the user again with
+/// [DocumentAccessControl::can_unlock](crate::server_document::DocumentAccessControl::can_unlock).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverrideToken(pub String);
+
+/// Whether the text with `attributes` is locked.
+pub fn is_protected(attributes: &RichTextAttributes) -> bool {
+    attributes
+        .get(&RichTextAttributeKey::Protected)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+/// The intervals of the locked text of `document`, in order.
+pub fn protected_intervals(document: &RichTextDelta) -> Vec<Interval> {
+    let mut intervals: Vec<Interval> = vec![];
+    let mut offset = 0;
+    for op in &document.ops {
+        let len = op.len();
+        if is_protected(&op.get_attributes()) {
+            match intervals.last_mut() {
+                Some(last) if last.end == offset => last.end += len,
+                _ => intervals.push(Interval::new(offset, offset + len)),
+            }
+        }
+        offset += len;
+    }
+    intervals
+}
+
+/// Checks that `delta` leaves the locked text of `document` as it is: it doesn't delete or format
+/// it, doesn't insert inside of it, and doesn't lock or unlock text. The text can be inserted at
+/// the edges of the locked text.
+pub fn check_protection(document: &RichTextDelta, delta: &RichTextDelta) -> Result<(), RegionLockedError> {
+    let regions = protected_intervals(document);
+    let overlapping = |interval: Interval| {
+        regions
+            .iter()
+            .find(|region| region.start < interval.end && interval.start < region.end)
+            .copied()
+    };
+
+    let mut index = 0;
+    for op in &delta.ops {
+        match op {
+            Operation::Retain(retain) => {
+                let interval = Interval::new(index, index + retain.n);
+                if retain.attributes.contains_key(&RichTextAttributeKey::Protected) {
+                    return Err(locked(interval, DocumentAction::Format));
+                }
+                if !retain.attributes.is_empty() {
+                    if let Some(region) = overlapping(interval) {
+                        return Err(locked(region, DocumentAction::Format));
+                    }
+                }
+                index += retain.n;
+            }
+            Operation::Delete(n) => {
+                if let Some(region) = overlapping(Interval::new(index, index + n)) {
+                    return Err(locked(region, DocumentAction::Write));
+                }
+                index += n;
+            }
+            Operation::Insert(insert) => {
+                if is_protected(&insert.attributes) {
+                    return Err(locked(Interval::new(index, index), DocumentAction::Format));
+                }
+                if let Some(region) = regions.iter().find(|region| region.start < index && index < region.end) {
+                    return Err(locked(*region, DocumentAction::Write));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn locked(interval: Interval, action: DocumentAction) -> RegionLockedError {
+    RegionLockedError { interval, action }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client_document::{protection::OverrideToken, ClientDocument, NewlineDoc},
+        errors::ErrorCode,
+    };
+    use lib_ot::{
+        core::{Interval, OpBuilder},
+        rich_text::RichTextAttribute,
+    };
+
+    #[test]
+    fn locked_regions_need_the_override() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "Template\nbody").unwrap();
+        assert_eq!(
+            document
+                .format(Interval::new(0, 8), RichTextAttribute::Protected(true))
+                .unwrap_err()
+                .code,
+            ErrorCode::RegionLocked
+        );
+
+        document.unlock_regions(OverrideToken("owner".to_owned()));
+        document
+            .format(Interval::new(0, 8), RichTextAttribute::Protected(true))
+            .unwrap();
+        document.lock_regions();
+        assert_eq!(document.protected_intervals(), vec![Interval::new(0, 8)]);
+
+        for result in vec![
+            document.insert(4, "x"),
+            document.delete(Interval::new(7, 10)),
+            document.format(Interval::new(2, 3), RichTextAttribute::Bold(true)),
+        ] {
+            assert_eq!(result.unwrap_err().code, ErrorCode::RegionLocked);
+        }
+
+        // The text typed next to the locked text isn't locked.
+        document.insert(8, "!").unwrap();
+        document.insert(0, "# ").unwrap();
+        assert_eq!(document.protected_intervals(), vec![Interval::new(2, 10)]);
+        document.delete(Interval::new(10, 11)).unwrap();
+        assert_eq!(document.to_plain_string(), "# Template\nbody\n");
+    }
+
+    #[test]
+    fn bulk_changes_respect_the_locked_regions() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "Template\nbody").unwrap();
+        document.unlock_regions(OverrideToken("owner".to_owned()));
+        document
+            .format(Interval::new(0, 8), RichTextAttribute::Protected(true))
+            .unwrap();
+        document.lock_regions();
+
+        let error = document.bulk_insert(4, "pasted").unwrap_err();
+        assert_eq!(error.code, ErrorCode::RegionLocked);
+        let ops = vec![OpBuilder::retain(2).build(), OpBuilder::insert("imported").build()];
+        let error = document.apply_chunked(ops, |_| {}).unwrap_err();
+        assert_eq!(error.code, ErrorCode::RegionLocked);
+        assert_eq!(document.to_plain_string(), "Template\nbody\n");
+
+        document.bulk_insert(9, "pasted ").unwrap();
+        assert_eq!(document.to_plain_string(), "Template\npasted body\n");
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{
+    client_document::search::{FindOptions, SearchMatch, SearchQuery, TextIndex},
+    errors::CollaborateError,
+};
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// The content of a document at a revision, for the readers that mustn't wait for the changes or
+/// see them half applied, e.g. the exporters and the search indexing. It stays valid after the
+/// document changes, and it's cheap to clone: the clones share the content and the text index,
+/// which is built by the first search.
+#[derive(Debug, Clone)]
+pub struct ReadSnapshot {
+    doc_id: String,
+    rev_id: i64,
+    delta: Arc<RichTextDelta>,
+    text_index: Arc<Mutex<Option<Arc<TextIndex>>>>,
+}
+
+impl ReadSnapshot {
+    pub(crate) fn new(
+        doc_id: &str,
+        rev_id: i64,
+        delta: Arc<RichTextDelta>,
+        text_index: Option<Arc<TextIndex>>,
+    ) -> Self {
+        Self {
+            doc_id: doc_id.to_owned(),
+            rev_id,
+            delta,
+            text_index: Arc::new(Mutex::new(text_index)),
+        }
+    }
+
+    pub fn doc_id(&self) -> &str {
+        &self.doc_id
+    }
+
+    pub fn rev_id(&self) -> i64 {
+        self.rev_id
+    }
+
+    pub fn delta(&self) -> &RichTextDelta {
+        &self.delta
+    }
+
+    pub fn to_json(&self) -> String {
+        self.delta.to_json()
+    }
+
+    pub fn to_plain_string(&self) -> String {
+        self.text_index().text().to_owned()
+    }
+
+    /// See [ClientDocument::find](crate::client_document::ClientDocument::find).
+    pub fn find(&self, pattern: &str, options: &FindOptions) -> Vec<Interval> {
+        self.text_index().find(pattern, options)
+    }
+
+    /// See [ClientDocument::search](crate::client_document::ClientDocument::search).
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchMatch>, CollaborateError> {
+        self.text_index().search(query)
+    }
+
+    fn text_index(&self) -> Arc<TextIndex> {
+        self.text_index
+            .lock()
+            .get_or_insert_with(|| Arc::new(TextIndex::new(&self.delta)))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{search::FindOptions, ClientDocument, NewlineDoc};
+    use lib_ot::core::Interval;
+    use std::sync::Arc;
+
+    #[test]
+    fn snapshot_is_independent_of_the_edits() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "abc").unwrap();
+        let snapshot = document.read_snapshot();
+        // The snapshots of a revision share its content.
+        assert!(Arc::ptr_eq(&snapshot.delta, &document.read_snapshot().delta));
+
+        document.insert(3, " abc").unwrap();
+        assert_eq!(snapshot.rev_id(), 1);
+        assert_eq!(snapshot.to_plain_string(), "abc\n");
+        assert_eq!(snapshot.find("abc", &FindOptions::default()), vec![Interval::new(0, 3)]);
+        let snapshot = document.read_snapshot();
+        assert_eq!(snapshot.rev_id(), 2);
+        assert_eq!(snapshot.to_plain_string(), "abc abc\n");
+    }
+}
@@ -0,0 +1,327 @@
+use crate::{
+    client_document::{
+        boundary::BoundaryPolicy,
+        command::EditCommand,
+        format::{decode_json, encode_json, PayloadKind},
+        history::BranchId,
+        normalize::NormalizeConfig,
+        ClientDocument, DocumentConfig, EditingConfig, HistoryConfig,
+    },
+    errors::CollaborateError,
+};
+use lib_ot::{
+    core::Interval,
+    rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta},
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+
+/// A call of the public API of the [ClientDocument] that changes the document. The calls that
+/// aren't listed are recorded as the deltas they compose, see [ReplayCall::ComposeDelta].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum ReplayCall {
+    Insert {
+        index: usize,
+        text: String,
+    },
+    BulkInsert {
+        index: usize,
+        text: String,
+    },
+    Delete {
+        start: usize,
+        end: usize,
+    },
+    Replace {
+        start: usize,
+        end: usize,
+        text: String,
+    },
+    /// The attributes hold the one attribute of the call.
+    Format {
+        start: usize,
+        end: usize,
+        attributes: RichTextAttributes,
+    },
+    SetSelection {
+        start: usize,
+        end: usize,
+    },
+    SyncWithText {
+        text: String,
+    },
+    SyncWithMarkdown {
+        markdown: String,
+    },
+    Undo,
+    Redo,
+    UndoLastFormat,
+    SwitchHistoryBranch {
+        id: BranchId,
+    },
+    SetSuggestionMode {
+        suggestion_mode: bool,
+    },
+    SetAutoLink {
+        auto_link: bool,
+    },
+    SetNormalizeConfig {
+        config: NormalizeConfig,
+    },
+    SetBoundaryPolicy {
+        policy: BoundaryPolicy,
+    },
+    ComposeDelta {
+        delta: RichTextDelta,
+    },
+    ComposeRemoteDelta {
+        delta: RichTextDelta,
+    },
+    SetDelta {
+        delta: RichTextDelta,
+    },
+}
+
+impl ReplayCall {
+    /// The [EditCommand] that makes the call, if there's one.
+    pub fn to_command(&self) -> Option<EditCommand> {
+        let command = match self.clone() {
+            ReplayCall::Insert { index, text } => EditCommand::Insert { index, text },
+            ReplayCall::Delete { start, end } => EditCommand::Delete { start, end },
+            ReplayCall::Replace { start, end, text } => EditCommand::Replace { start, end, text },
+            ReplayCall::SetSelection { start, end } => EditCommand::SetSelection { start, end },
+            ReplayCall::Undo => EditCommand::Undo,
+            ReplayCall::Redo => EditCommand::Redo,
+            ReplayCall::ComposeDelta { delta } => EditCommand::ComposeDelta { delta },
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    fn apply(&self, document: &mut ClientDocument) -> Result<(), CollaborateError> {
+        match self {
+            ReplayCall::BulkInsert { index, text } => document.bulk_insert(*index, text).map(|_| ()),
+            ReplayCall::Format { start, end, attributes } => {
+                for (key, value) in attributes.iter() {
+                    let attribute = RichTextAttribute::new(key.clone(), value.clone());
+                    let _ = document.format(Interval::new(*start, *end), attribute)?;
+                }
+                Ok(())
+            }
+            ReplayCall::SyncWithText { text } => document.sync_with_text(text).map(|_| ()),
+            ReplayCall::SyncWithMarkdown { markdown } => document.sync_with_markdown(markdown).map(|_| ()),
+            ReplayCall::UndoLastFormat => document.undo_last_format().map(|_| ()),
+            ReplayCall::SwitchHistoryBranch { id } => document.switch_history_branch(*id).map(|_| ()),
+            ReplayCall::SetSuggestionMode { suggestion_mode } => {
+                document.set_suggestion_mode(*suggestion_mode);
+                Ok(())
+            }
+            ReplayCall::SetAutoLink { auto_link } => {
+                document.set_auto_link(*auto_link);
+                Ok(())
+            }
+            ReplayCall::SetNormalizeConfig { config } => {
+                document.set_normalize_config(config.clone());
+                Ok(())
+            }
+            ReplayCall::SetBoundaryPolicy { policy } => {
+                document.set_boundary_policy(*policy);
+                Ok(())
+            }
+            ReplayCall::ComposeRemoteDelta { delta } => document.compose_remote_delta(delta.clone()),
+            ReplayCall::SetDelta { delta } => {
+                document.set_delta(delta.clone());
+                Ok(())
+            }
+            // The edits are made by the command of the call.
+            _ => match self.to_command() {
+                Some(command) => document.handle(command).map(|_| ()),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    /// The revision of the document before the call.
+    pub rev_id: i64,
+    /// When the call was made, in milliseconds since the epoch. The replay runs the call at the
+    /// same time, so the changes are merged in the history the same way.
+    pub at: i64,
+    pub call: ReplayCall,
+}
+
+/// The calls made to a document since it started recording, see
+/// [ClientDocument::start_recording], with what the replay needs to make them again: the content
+/// and the config of the document when it started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayLog {
+    pub doc_id: String,
+    pub author_id: String,
+    pub device_id: String,
+    pub max_len: Option<usize>,
+    pub suggestion_mode: bool,
+    pub auto_link: bool,
+    #[serde(default)]
+    pub normalize: NormalizeConfig,
+    #[serde(default)]
+    pub boundary_policy: BoundaryPolicy,
+    #[serde(default)]
+    pub history_branching: bool,
+    /// The revision of the document when it started recording.
+    pub rev_id: i64,
+    pub document: RichTextDelta,
+    pub entries: Vec<ReplayEntry>,
+}
+
+/// The document that a [ReplayLog] was replayed into, with the calls that failed.
+pub struct Replay {
+    pub document: ClientDocument,
+    /// The index of the entry, with its error. A call whose revision isn't the one that was
+    /// recorded, because the replay diverged, is reported before it's made.
+    pub errors: Vec<(usize, CollaborateError)>,
+}
+
+impl fmt::Debug for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replay")
+            .field("document", &self.document.to_json())
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl ReplayLog {
+    /// Returns the log as a versioned json payload, e.g. to attach it to a bug report.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CollaborateError> {
+        encode_json(PayloadKind::ReplayLog, self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CollaborateError> {
+        decode_json(PayloadKind::ReplayLog, bytes)
+    }
+
+    /// Makes the calls of the log again on a new document. The calls run in the same order and at
+    /// the same times, so the document ends up like the one that recorded them, with the same
+    /// history. The spell check, the mentions and the other services of the document aren't
+    /// recorded, the replay runs without them.
+    pub fn replay(&self) -> Replay {
+        let mut document = ClientDocument::from_delta(self.document.clone());
+        document.set_config(DocumentConfig {
+            doc_id: self.doc_id.clone(),
+            author_id: self.author_id.clone(),
+            device_id: self.device_id.clone(),
+            max_len: self.max_len,
+            history: HistoryConfig {
+                branching: self.history_branching,
+                ..HistoryConfig::default()
+            },
+            editing: EditingConfig {
+                suggestion_mode: self.suggestion_mode,
+                auto_link: self.auto_link,
+                typing: None,
+                normalize: self.normalize.clone(),
+                boundary_policy: self.boundary_policy,
+            },
+            ..DocumentConfig::default()
+        });
+
+        let mut errors = vec![];
+        for (index, entry) in self.entries.iter().enumerate() {
+            let rev_id = document.rev_id() + self.rev_id;
+            if rev_id != entry.rev_id {
+                let error = CollaborateError::internal().context(format!(
+                    "The replay is at the revision {} instead of {}",
+                    rev_id, entry.rev_id
+                ));
+                errors.push((index, error));
+            }
+            document.set_clock(Some(entry.at));
+            if let Err(error) = entry.call.apply(&mut document) {
+                errors.push((index, error));
+            }
+        }
+        document.set_clock(None);
+        Replay { document, errors }
+    }
+}
+
+/// Appends the calls of a document to its [ReplayLog]. The calls that the document makes while it
+/// runs a recorded call, e.g. the insert of a replace, aren't recorded.
+pub(crate) struct ReplayRecorder {
+    log: ReplayLog,
+    depth: usize,
+}
+
+impl ReplayRecorder {
+    pub(crate) fn new(log: ReplayLog) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self { log, depth: 0 }))
+    }
+
+    pub(crate) fn log(&self) -> &ReplayLog {
+        &self.log
+    }
+
+    pub(crate) fn into_log(self) -> ReplayLog {
+        self.log
+    }
+
+    /// Records the call, unless it's made by another recorded call, until the returned guard is
+    /// dropped at the end of the call.
+    pub(crate) fn record<F>(recorder: &Arc<Mutex<Self>>, rev_id: i64, at: i64, call: F) -> ReplayGuard
+    where
+        F: FnOnce() -> ReplayCall,
+    {
+        let mut this = recorder.lock();
+        if this.depth == 0 {
+            let call = call();
+            this.log.entries.push(ReplayEntry { rev_id, at, call });
+        }
+        this.depth += 1;
+        ReplayGuard(recorder.clone())
+    }
+}
+
+pub(crate) struct ReplayGuard(Arc<Mutex<ReplayRecorder>>);
+
+impl Drop for ReplayGuard {
+    fn drop(&mut self) {
+        self.0.lock().depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{replay::ReplayLog, ClientDocument, NewlineDoc};
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+
+    #[test]
+    fn replay_the_recorded_calls() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.start_recording();
+        document.insert(0, "hello").unwrap();
+        document.replace(Interval::new(0, 1), "H").unwrap();
+        document
+            .format(Interval::new(0, 5), RichTextAttribute::Bold(true))
+            .unwrap();
+        document.undo().unwrap();
+        assert!(document.redo().is_ok());
+        assert!(document.redo().is_err());
+        let log = document.stop_recording().unwrap();
+        // The replace is one call, its delete and insert aren't recorded.
+        assert_eq!(log.entries.len(), 6);
+
+        let log = ReplayLog::from_bytes(&log.to_bytes().unwrap()).unwrap();
+        let mut replay = log.replay();
+        assert_eq!(replay.document.to_json(), document.to_json());
+        assert_eq!(replay.document.rev_id(), document.rev_id());
+        // The call that failed fails again.
+        assert_eq!(replay.errors.len(), 1);
+        assert_eq!(replay.errors[0].0, 5);
+        assert_eq!(replay.document.undo().is_ok(), document.undo().is_ok());
+        assert_eq!(replay.document.to_json(), document.to_json());
+    }
+}
@@ -0,0 +1,546 @@
+use crate::{
+    client_document::{
+        audit::{OpCounts, RevisionFilter, RevisionMeta},
+        format::{decode_json, encode_json, PayloadKind},
+    },
+    encryption::{decrypt, encrypt, EncryptionProvider},
+    entities::revision::{md5, MetadataEntry, Revision, RevisionBundle},
+    errors::{CollaborateError, DocumentIntegrityError},
+    util::{content_md5, verify_content_md5},
+};
+use bytes::Bytes;
+use lib_ot::{
+    core::OperationTransformable,
+    rich_text::{RichTextDelta, RichTextDeltaBuilder},
+};
+use std::convert::{TryFrom, TryInto};
+
+/// The number of revisions that the revision log of a document keeps by default, see
+/// [crate::client_document::DocumentConfig::max_log_revisions].
+pub const MAX_LOG_REVISIONS: usize = 1000;
+
+/// The content of the document at `rev_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSnapshot {
+    pub rev_id: i64,
+    pub delta_data: Bytes,
+    pub md5: String,
+    /// See [Revision::content_md5].
+    pub content_md5: String,
+    /// The metadata of the document at `rev_id`, which the revisions compacted away no longer
+    /// carry, see [Metadata](crate::client_document::metadata::Metadata).
+    pub metadata: Vec<MetadataEntry>,
+}
+
+impl DocumentSnapshot {
+    pub fn new(rev_id: i64, delta: &RichTextDelta) -> Self {
+        let delta_data = delta.to_bytes();
+        let md5 = md5(&delta_data);
+        Self {
+            rev_id,
+            delta_data,
+            md5,
+            content_md5: content_md5(delta),
+            metadata: vec![],
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Vec<MetadataEntry>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Checks that the snapshot has the content that the other participants have at its revision.
+    pub fn verify_content(&self, expected: &str) -> Result<(), DocumentIntegrityError> {
+        match self.content_md5 == expected {
+            true => Ok(()),
+            false => Err(DocumentIntegrityError {
+                rev_id: self.rev_id,
+                expected: expected.to_owned(),
+                actual: self.content_md5.clone(),
+            }),
+        }
+    }
+
+    /// Returns the bytes the snapshot is stored as, encrypted by `provider` if there is one. The
+    /// bytes hold the content, the stores keep the metadata along with them.
+    pub fn to_stored_bytes(&self, provider: Option<&dyn EncryptionProvider>) -> Result<Vec<u8>, CollaborateError> {
+        let json = encode_json(PayloadKind::Snapshot, &self.delta()?)?;
+        match provider {
+            None => Ok(json),
+            Some(provider) => Ok(encrypt(provider, &json)?),
+        }
+    }
+
+    /// Reads the snapshot that was stored by [DocumentSnapshot::to_stored_bytes], by this version
+    /// or an older one.
+    pub fn from_stored_bytes(
+        rev_id: i64,
+        bytes: &[u8],
+        provider: Option<&dyn EncryptionProvider>,
+    ) -> Result<Self, CollaborateError> {
+        let delta: RichTextDelta = decode_json(PayloadKind::Snapshot, &decrypt(provider, bytes)?)?;
+        Ok(Self::new(rev_id, &delta))
+    }
+
+    pub fn delta(&self) -> Result<RichTextDelta, CollaborateError> {
+        if md5(&self.delta_data) != self.md5 {
+            return Err(CollaborateError::internal().context(format!("The snapshot {} is corrupted", self.rev_id)));
+        }
+        let delta = RichTextDelta::from_bytes(&self.delta_data)?;
+        if content_md5(&delta) != self.content_md5 {
+            return Err(DocumentIntegrityError {
+                rev_id: self.rev_id,
+                expected: self.content_md5.clone(),
+                actual: content_md5(&delta),
+            }
+            .into());
+        }
+        Ok(delta)
+    }
+}
+
+/// A name given to a revision, e.g. "Draft 1". The tag keeps the content of the revision, so it can
+/// be restored or compared against after the revisions were compacted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionTag {
+    pub name: String,
+    pub snapshot: DocumentSnapshot,
+}
+
+impl RevisionTag {
+    pub fn rev_id(&self) -> i64 {
+        self.snapshot.rev_id
+    }
+}
+
+// How a tag is encoded by [RevisionLog::encode_tags].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RevisionTagData {
+    name: String,
+    rev_id: i64,
+    delta: RichTextDelta,
+}
+
+/// Keeps the revisions that were applied to the document, together with the snapshots that were
+/// taken along the way.
+///
+/// The log always starts with a snapshot, so the content at any revision in the log can be
+/// rebuilt by composing the revisions after the nearest snapshot.
+#[derive(Debug, Clone)]
+pub struct RevisionLog {
+    revisions: Vec<Revision>,
+    snapshots: Vec<DocumentSnapshot>,
+    tags: Vec<RevisionTag>,
+}
+
+impl RevisionLog {
+    pub fn new(rev_id: i64, delta: &RichTextDelta) -> Self {
+        Self {
+            revisions: vec![],
+            snapshots: vec![DocumentSnapshot::new(rev_id, delta)],
+            tags: vec![],
+        }
+    }
+
+    pub fn push(&mut self, revision: Revision) {
+        debug_assert!(revision.rev_id > self.latest_rev_id());
+        self.revisions.push(revision);
+    }
+
+    pub fn add_snapshot(&mut self, snapshot: DocumentSnapshot) {
+        debug_assert!(snapshot.rev_id >= self.latest_snapshot().rev_id);
+        self.snapshots.push(snapshot);
+    }
+
+    pub fn latest_snapshot(&self) -> &DocumentSnapshot {
+        // The log is never empty, see [RevisionLog::compact].
+        self.snapshots.last().unwrap()
+    }
+
+    pub fn snapshots(&self) -> &[DocumentSnapshot] {
+        &self.snapshots
+    }
+
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    pub fn get_revision(&self, rev_id: i64) -> Option<&Revision> {
+        self.revisions
+            .binary_search_by(|revision| revision.rev_id.cmp(&rev_id))
+            .ok()
+            .map(|index| &self.revisions[index])
+    }
+
+    /// Describes the revisions of the log that match `filter`, in order, e.g. for an audit view.
+    /// The operations are counted from the json of the deltas, which aren't read into deltas.
+    pub fn query(&self, filter: &RevisionFilter) -> Result<Vec<RevisionMeta>, CollaborateError> {
+        let mut metas = vec![];
+        for revision in &self.revisions {
+            let meta = RevisionMeta {
+                rev_id: revision.rev_id,
+                base_rev_id: revision.base_rev_id,
+                author_id: revision.author_id.clone(),
+                device_id: revision.device_id.clone(),
+                timestamp: revision.timestamp,
+                size: revision.delta_data.len(),
+                ops: OpCounts::scan(&revision.delta_data)?,
+                is_metadata: revision.is_metadata(),
+            };
+            if filter.matches(&meta) {
+                metas.push(meta);
+            }
+        }
+        Ok(metas)
+    }
+
+    pub fn latest_rev_id(&self) -> i64 {
+        let snapshot_rev_id = self.latest_snapshot().rev_id;
+        match self.revisions.last() {
+            None => snapshot_rev_id,
+            Some(revision) => revision.rev_id.max(snapshot_rev_id),
+        }
+    }
+
+    /// Rebuilds the content of the document at `rev_id` by composing the revisions after the
+    /// nearest snapshot.
+    pub fn delta_at(&self, rev_id: i64) -> Result<RichTextDelta, CollaborateError> {
+        if rev_id > self.latest_rev_id() {
+            return Err(CollaborateError::record_not_found().context(format!("The revision {} doesn't exist", rev_id)));
+        }
+        let snapshot = match self.snapshots.iter().rev().find(|snapshot| snapshot.rev_id <= rev_id) {
+            None => {
+                return Err(
+                    CollaborateError::record_not_found().context(format!("The revision {} was compacted", rev_id))
+                )
+            }
+            Some(snapshot) => snapshot,
+        };
+
+        let mut delta = snapshot.delta()?;
+        for revision in self
+            .revisions
+            .iter()
+            .skip_while(|revision| revision.rev_id <= snapshot.rev_id)
+            .take_while(|revision| revision.rev_id <= rev_id)
+        {
+            let revision_delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+            delta = delta.compose(&revision_delta)?;
+            verify_content_md5(revision, &delta)?;
+        }
+        Ok(delta)
+    }
+
+    /// Names the revision `rev_id`. The names are unique, tagging another revision with a name
+    /// that is used fails.
+    pub fn tag<T: ToString>(&mut self, rev_id: i64, name: T) -> Result<&RevisionTag, CollaborateError> {
+        let name = name.to_string();
+        if self.get_tag(&name).is_some() {
+            return Err(CollaborateError::revision_conflict().context(format!("The tag {} already exists", name)));
+        }
+        let delta = self.delta_at(rev_id)?;
+        let tag = RevisionTag {
+            name,
+            snapshot: DocumentSnapshot::new(rev_id, &delta),
+        };
+        let index = self.tags.partition_point(|other| other.rev_id() <= rev_id);
+        self.tags.insert(index, tag);
+        Ok(&self.tags[index])
+    }
+
+    pub fn untag(&mut self, name: &str) -> Option<RevisionTag> {
+        let index = self.tags.iter().position(|tag| tag.name == name)?;
+        Some(self.tags.remove(index))
+    }
+
+    /// Returns the tags ordered by their revisions.
+    pub fn list_tags(&self) -> &[RevisionTag] {
+        &self.tags
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&RevisionTag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
+
+    /// Encodes the tags, which are saved along the revisions of the document and loaded back with
+    /// [RevisionLog::load_tags].
+    pub fn encode_tags(&self) -> Result<Bytes, CollaborateError> {
+        let mut tags = vec![];
+        for tag in &self.tags {
+            tags.push(RevisionTagData {
+                name: tag.name.clone(),
+                rev_id: tag.rev_id(),
+                delta: tag.snapshot.delta()?,
+            });
+        }
+        let json = encode_json(PayloadKind::Tags, &tags)?;
+        Ok(Bytes::from(json))
+    }
+
+    /// Replaces the tags with the ones that were encoded by [RevisionLog::encode_tags], by this
+    /// version or an older one.
+    pub fn load_tags(&mut self, bytes: &[u8]) -> Result<(), CollaborateError> {
+        let tags: Vec<RevisionTagData> = decode_json(PayloadKind::Tags, bytes)?;
+        let mut tags = tags
+            .into_iter()
+            .map(|tag| RevisionTag {
+                name: tag.name,
+                snapshot: DocumentSnapshot::new(tag.rev_id, &tag.delta),
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by_key(|tag| tag.rev_id());
+        self.tags = tags;
+        Ok(())
+    }
+
+    /// Packs the revisions after `from_rev` up to `to_rev` into a bundle, which can be moved to
+    /// another machine by hand and imported there with [RevisionLog::import_bundle].
+    pub fn export_bundle(&self, from_rev: i64, to_rev: i64) -> Result<Bytes, CollaborateError> {
+        if from_rev > to_rev {
+            return Err(CollaborateError::out_of_bound().context(format!("[{},{}] is empty", from_rev, to_rev)));
+        }
+        let base = self.delta_at(from_rev)?;
+        let revisions = self.revisions_between(from_rev, to_rev)?.into_iter().cloned().collect();
+        let bundle = RevisionBundle::new(from_rev, md5(base.to_canonical_json()), revisions);
+        let bytes: Bytes = bundle.try_into()?;
+        Ok(bytes)
+    }
+
+    /// Validates the bundle that was exported by [RevisionLog::export_bundle] and returns its
+    /// change. The change is transformed against the revisions that were applied here after the
+    /// base of the bundle, so it can be composed into the latest content.
+    pub fn import_bundle(&self, bytes: Bytes) -> Result<RichTextDelta, CollaborateError> {
+        let bundle = RevisionBundle::try_from(bytes)?;
+        let version = bundle.format_version();
+        PayloadKind::Bundle.check_version(version)?;
+        if !bundle.is_valid() {
+            return Err(CollaborateError::internal().context("The bundle is corrupted"));
+        }
+        let base = self.delta_at(bundle.base_rev_id)?;
+        // The version 1 hashed the json of the base, whose attributes aren't in a stable order.
+        let base_md5 = match version {
+            1 => md5(base.to_bytes()),
+            _ => md5(base.to_canonical_json()),
+        };
+        if base_md5 != bundle.base_md5 {
+            return Err(CollaborateError::internal().context(format!(
+                "The bundle doesn't start from the content of the revision {}",
+                bundle.base_rev_id
+            )));
+        }
+
+        let mut change = RichTextDeltaBuilder::new().retain(base.utf16_target_len).build();
+        for revision in &bundle.revisions {
+            let revision_delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+            change = change.compose(&revision_delta)?;
+        }
+
+        let mut local = RichTextDeltaBuilder::new().retain(base.utf16_target_len).build();
+        for revision in self.revisions_between(bundle.base_rev_id, self.latest_rev_id())? {
+            let revision_delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+            local = local.compose(&revision_delta)?;
+        }
+        let (_, change) = local.transform(&change)?;
+        Ok(change)
+    }
+
+    /// Drops the revisions and the snapshots that are older than the latest snapshot taken at or
+    /// before `before_rev`. The content of the document isn't affected, but the revisions before
+    /// the snapshot can't be looked up anymore, so `before_rev` must not be after the revisions
+    /// that the undo history rebuilds its entries from, see
+    /// [crate::client_document::history::History::oldest_rebuilt_rev_id].
+    ///
+    /// Returns the number of revisions that were dropped.
+    pub fn compact(&mut self, before_rev: i64) -> usize {
+        let index = match self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.rev_id <= before_rev)
+        {
+            None => return 0,
+            Some(index) => index,
+        };
+        let snapshot_rev_id = self.snapshots[index].rev_id;
+        self.snapshots.drain(..index);
+
+        let count = self
+            .revisions
+            .iter()
+            .take_while(|revision| revision.rev_id <= snapshot_rev_id)
+            .count();
+        self.revisions.drain(..count);
+        count
+    }
+
+    // Returns the revisions after `from_rev` up to `to_rev`. Fails if some of them are missing, e.g.
+    // because the content was replaced without a revision in between.
+    fn revisions_between(&self, from_rev: i64, to_rev: i64) -> Result<Vec<&Revision>, CollaborateError> {
+        let revisions = self
+            .revisions
+            .iter()
+            .skip_while(|revision| revision.rev_id <= from_rev)
+            .take_while(|revision| revision.rev_id <= to_rev)
+            .collect::<Vec<_>>();
+        if revisions.len() as i64 != to_rev - from_rev {
+            return Err(CollaborateError::record_not_found()
+                .context(format!("The revisions in ({},{}] are incomplete", from_rev, to_rev)));
+        }
+        Ok(revisions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::revision_log::{DocumentSnapshot, RevisionLog};
+    use crate::entities::revision::{md5, Revision};
+    use crate::errors::ErrorCode;
+    use crate::util::content_md5;
+    use lib_ot::core::OperationTransformable;
+    use lib_ot::rich_text::{AttributeBuilder, RichTextAttribute, RichTextDelta, RichTextDeltaBuilder};
+
+    fn revision(rev_id: i64, delta: &RichTextDelta) -> Revision {
+        let delta_data = delta.to_bytes();
+        let md5 = md5(&delta_data);
+        Revision::new("", rev_id - 1, rev_id, delta_data, "", md5)
+    }
+
+    #[test]
+    fn revision_log_compact() {
+        let initial = RichTextDeltaBuilder::new().insert("\n").build();
+        let mut log = RevisionLog::new(0, &initial);
+        let insert = RichTextDeltaBuilder::new().insert("a").retain(1).build();
+        log.push(revision(1, &insert));
+        log.push(revision(2, &insert));
+        log.add_snapshot(DocumentSnapshot::new(
+            2,
+            &RichTextDeltaBuilder::new().insert("aa\n").build(),
+        ));
+        log.push(revision(3, &insert));
+
+        // There is no snapshot before the initial one, so nothing is dropped.
+        assert_eq!(log.compact(-1), 0);
+        assert_eq!(log.compact(1), 0);
+        assert_eq!(log.compact(3), 2);
+        assert_eq!(log.snapshots().len(), 1);
+        assert_eq!(
+            log.latest_snapshot().delta().unwrap().to_json(),
+            r#"[{"insert":"aa\n"}]"#
+        );
+        assert!(log.get_revision(2).is_none());
+        assert!(log.get_revision(3).is_some());
+        assert_eq!(log.latest_rev_id(), 3);
+    }
+
+    #[test]
+    fn revision_log_delta_at() {
+        let initial = RichTextDeltaBuilder::new().insert("\n").build();
+        let mut log = RevisionLog::new(0, &initial);
+        log.push(revision(1, &RichTextDeltaBuilder::new().insert("a").retain(1).build()));
+        log.push(revision(
+            2,
+            &RichTextDeltaBuilder::new().retain(1).insert("b").retain(1).build(),
+        ));
+        log.push(revision(3, &RichTextDeltaBuilder::new().delete(1).retain(2).build()));
+
+        assert_eq!(log.delta_at(0).unwrap().to_json(), r#"[{"insert":"\n"}]"#);
+        assert_eq!(log.delta_at(2).unwrap().to_json(), r#"[{"insert":"ab\n"}]"#);
+        assert_eq!(log.delta_at(3).unwrap().to_json(), r#"[{"insert":"b\n"}]"#);
+        assert!(log.delta_at(4).is_err());
+    }
+
+    #[test]
+    fn revision_log_content_integrity() {
+        let mut a = RichTextDelta::default();
+        a.insert(
+            "ab",
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::Bold(true))
+                .add_attr(RichTextAttribute::Italic(true))
+                .build(),
+        );
+        let mut b = RichTextDelta::default();
+        b.insert(
+            "ab",
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::Italic(true))
+                .add_attr(RichTextAttribute::Bold(true))
+                .build(),
+        );
+        assert_eq!(content_md5(&a), content_md5(&b));
+        assert_ne!(
+            content_md5(&a),
+            content_md5(&RichTextDeltaBuilder::new().insert("ab").build())
+        );
+
+        let initial = RichTextDeltaBuilder::new().insert("\n").build();
+        let mut log = RevisionLog::new(0, &initial);
+        let insert = RichTextDeltaBuilder::new().insert("a").retain(1).build();
+        let after = initial.compose(&insert).unwrap();
+        log.push(revision(1, &insert).with_content_md5(content_md5(&after)));
+        log.push(revision(2, &insert).with_content_md5(content_md5(&after)));
+        assert!(log.delta_at(1).is_ok());
+        let error = log.delta_at(2).unwrap_err();
+        assert_eq!(error.code, ErrorCode::DocumentIntegrity);
+
+        let snapshot = DocumentSnapshot::new(1, &after);
+        assert!(snapshot.verify_content(&content_md5(&after)).is_ok());
+        let error = snapshot.verify_content(&content_md5(&initial)).unwrap_err();
+        assert_eq!(error.expected, content_md5(&initial));
+        assert_eq!(error.actual, content_md5(&after));
+    }
+
+    #[test]
+    fn revision_log_import_bundle() {
+        let initial = RichTextDeltaBuilder::new().insert("\n").build();
+        let mut log = RevisionLog::new(0, &initial);
+        log.push(revision(1, &RichTextDeltaBuilder::new().insert("ab").retain(1).build()));
+        let mut other_log = log.clone();
+
+        log.push(revision(
+            2,
+            &RichTextDeltaBuilder::new().retain(2).insert("c").retain(1).build(),
+        ));
+        other_log.push(revision(2, &RichTextDeltaBuilder::new().insert("1").retain(3).build()));
+
+        let bytes = log.export_bundle(1, 2).unwrap();
+        let change = other_log.import_bundle(bytes).unwrap();
+        let delta = other_log.delta_at(2).unwrap().compose(&change).unwrap();
+        assert_eq!(delta.to_json(), r#"[{"insert":"1abc\n"}]"#);
+
+        // The base of the bundle doesn't match the content of the revision 1 here.
+        let mut bundle_log = RevisionLog::new(0, &initial);
+        bundle_log.push(revision(1, &RichTextDeltaBuilder::new().insert("x").retain(1).build()));
+        let bytes = log.export_bundle(1, 2).unwrap();
+        assert!(bundle_log.import_bundle(bytes).is_err());
+        assert!(log.export_bundle(1, 3).is_err());
+    }
+
+    #[test]
+    fn revision_log_tags() {
+        let initial = RichTextDeltaBuilder::new().insert("\n").build();
+        let mut log = RevisionLog::new(0, &initial);
+        log.push(revision(1, &RichTextDeltaBuilder::new().insert("a").retain(1).build()));
+        log.push(revision(2, &RichTextDeltaBuilder::new().insert("b").retain(2).build()));
+
+        log.tag(2, "Final").unwrap();
+        log.tag(1, "Draft 1").unwrap();
+        assert!(log.tag(2, "Draft 1").is_err());
+        assert!(log.tag(3, "Draft 2").is_err());
+        let names = log.list_tags().iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Draft 1", "Final"]);
+
+        // The content of the tags is kept after the compaction.
+        log.add_snapshot(DocumentSnapshot::new(2, &log.delta_at(2).unwrap()));
+        log.compact(2);
+        let draft = log.get_tag("Draft 1").unwrap();
+        assert_eq!(draft.snapshot.delta().unwrap().to_json(), r#"[{"insert":"a\n"}]"#);
+
+        let bytes = log.encode_tags().unwrap();
+        let mut other_log = RevisionLog::new(0, &initial);
+        other_log.load_tags(&bytes).unwrap();
+        assert_eq!(other_log.list_tags(), log.list_tags());
+        assert!(other_log.untag("Final").is_some());
+        assert_eq!(other_log.list_tags().len(), 1);
+    }
+}
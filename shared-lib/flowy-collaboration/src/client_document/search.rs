@@ -0,0 +1,304 @@
+use crate::errors::{internal_error, CollaborateError};
+use fancy_regex::Regex;
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation},
+    rich_text::{is_block_except_header, RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    /// Only matches the pattern that isn't a part of a longer word.
+    pub whole_word: bool,
+}
+
+/// Returns the utf16 intervals of the occurrences of `pattern` in `text`, from the start of the
+/// text. The occurrences don't overlap.
+pub fn find(text: &str, pattern: &str, options: &FindOptions) -> Vec<Interval> {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    if pattern.is_empty() {
+        return vec![];
+    }
+
+    let chars = text.chars().collect::<Vec<_>>();
+    // The utf16 offset of each char, plus the length of the text.
+    let mut offsets = Vec::with_capacity(chars.len() + 1);
+    let mut offset = 0;
+    for c in &chars {
+        offsets.push(offset);
+        offset += c.len_utf16();
+    }
+    offsets.push(offset);
+
+    let mut intervals = vec![];
+    let mut index = 0;
+    while index + pattern.len() <= chars.len() {
+        let end = index + pattern.len();
+        let is_match = chars[index..end]
+            .iter()
+            .zip(pattern.iter())
+            .all(|(a, b)| char_eq(*a, *b, options.case_sensitive));
+        let is_word = !options.whole_word
+            || (!(index > 0 && is_word_char(chars[index - 1])) && !chars.get(end).map_or(false, |c| is_word_char(*c)));
+        if is_match && is_word {
+            intervals.push(Interval::new(offsets[index], offsets[end]));
+            index = end;
+        } else {
+            index += 1;
+        }
+    }
+    intervals
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    /// The regex to look for. If it's `None`, the text that has the `attributes` is returned.
+    pub pattern: Option<String>,
+    pub case_sensitive: bool,
+    /// The attributes that all the matched text must have. The block attributes, e.g. the code
+    /// block, are the ones of the line the text is in.
+    pub attributes: RichTextAttributes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub interval: Interval,
+    /// The groups captured by the pattern, from the first one.
+    pub groups: Vec<Option<(Interval, String)>>,
+}
+
+/// The plain text of a document and the attributes of its parts. It's built once for a version of
+/// the document, so the searches don't walk the operations again.
+#[derive(Debug, Clone)]
+pub struct TextIndex {
+    text: String,
+    // The byte offset and the utf16 offset of each char, plus the ones of the end of the text.
+    offsets: Vec<(usize, usize)>,
+    // The attributes of the text, extended with the block attributes of its line.
+    runs: Vec<(Interval, RichTextAttributes)>,
+}
+
+impl TextIndex {
+    pub fn new(delta: &RichTextDelta) -> Self {
+        let mut text = String::new();
+        let mut runs = vec![];
+        let mut line_runs: Vec<(Interval, RichTextAttributes)> = vec![];
+        let mut offset = 0;
+        for op in &delta.ops {
+            let insert = match op {
+                Operation::Insert(insert) => insert,
+                _ => continue,
+            };
+            text.push_str(&insert.s);
+            for piece in insert.s.split_inclusive('\n') {
+                let len = count_utf16_code_units(piece);
+                line_runs.push((Interval::new(offset, offset + len), insert.attributes.clone()));
+                offset += len;
+                if piece.ends_with('\n') {
+                    let block_attributes = insert
+                        .attributes
+                        .iter()
+                        .filter(|(k, _)| is_block_except_header(k) || *k == &RichTextAttributeKey::Header)
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect::<Vec<_>>();
+                    for (interval, mut attributes) in line_runs.drain(..) {
+                        attributes.extend(block_attributes.clone());
+                        runs.push((interval, attributes));
+                    }
+                }
+            }
+        }
+        runs.extend(line_runs);
+
+        let mut offsets = Vec::with_capacity(text.len() + 1);
+        let mut utf16_offset = 0;
+        for (byte_offset, c) in text.char_indices() {
+            offsets.push((byte_offset, utf16_offset));
+            utf16_offset += c.len_utf16();
+        }
+        offsets.push((text.len(), utf16_offset));
+        Self { text, offsets, runs }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn find(&self, pattern: &str, options: &FindOptions) -> Vec<Interval> {
+        find(&self.text, pattern, options)
+    }
+
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchMatch>, CollaborateError> {
+        let pattern = match &query.pattern {
+            None => {
+                return Ok(self
+                    .runs_with(&query.attributes)
+                    .into_iter()
+                    .map(|interval| SearchMatch {
+                        interval,
+                        groups: vec![],
+                    })
+                    .collect())
+            }
+            Some(pattern) => pattern,
+        };
+        let regex = match query.case_sensitive {
+            true => Regex::new(pattern),
+            false => Regex::new(&format!("(?i){}", pattern)),
+        }
+        .map_err(internal_error)?;
+
+        let mut matches = vec![];
+        let mut pos = 0;
+        while pos <= self.text.len() {
+            let captures = match regex.captures_from_pos(&self.text, pos).map_err(internal_error)? {
+                None => break,
+                Some(captures) => captures,
+            };
+            // The whole match always exists.
+            let whole = captures.get(0).unwrap();
+            pos = match whole.end() > whole.start() {
+                true => whole.end(),
+                false => whole.end() + self.text[whole.end()..].chars().next().map_or(1, |c| c.len_utf8()),
+            };
+
+            let interval = self.interval(whole.start(), whole.end());
+            if !self.has_attributes(interval, &query.attributes) {
+                continue;
+            }
+            let groups = (1..captures.len())
+                .map(|i| {
+                    captures
+                        .get(i)
+                        .map(|group| (self.interval(group.start(), group.end()), group.as_str().to_owned()))
+                })
+                .collect();
+            matches.push(SearchMatch { interval, groups });
+        }
+        Ok(matches)
+    }
+
+    fn interval(&self, start: usize, end: usize) -> Interval {
+        Interval::new(self.utf16_offset(start), self.utf16_offset(end))
+    }
+
+    fn utf16_offset(&self, byte_offset: usize) -> usize {
+        match self.offsets.binary_search_by(|(offset, _)| offset.cmp(&byte_offset)) {
+            Ok(index) => self.offsets[index].1,
+            Err(index) => self.offsets[index.saturating_sub(1)].1,
+        }
+    }
+
+    fn has_attributes(&self, interval: Interval, attributes: &RichTextAttributes) -> bool {
+        if attributes.is_empty() {
+            return true;
+        }
+        self.runs
+            .iter()
+            .filter(|(run, _)| run.start < interval.end && interval.start < run.end)
+            .all(|(_, run_attributes)| contains_attributes(run_attributes, attributes))
+    }
+
+    fn runs_with(&self, attributes: &RichTextAttributes) -> Vec<Interval> {
+        let mut intervals: Vec<Interval> = vec![];
+        for (run, run_attributes) in &self.runs {
+            if !contains_attributes(run_attributes, attributes) {
+                continue;
+            }
+            match intervals.last_mut() {
+                Some(last) if last.end == run.start => last.end = run.end,
+                _ => intervals.push(*run),
+            }
+        }
+        intervals
+    }
+}
+
+fn contains_attributes(attributes: &RichTextAttributes, other: &RichTextAttributes) -> bool {
+    other.iter().all(|(k, v)| attributes.get(k) == Some(v))
+}
+
+fn char_eq(a: char, b: char, case_sensitive: bool) -> bool {
+    if case_sensitive || a == b {
+        return a == b;
+    }
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::search::{find, FindOptions, SearchQuery, TextIndex};
+    use lib_ot::{
+        core::Interval,
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributes, RichTextDelta},
+    };
+
+    #[test]
+    fn find_with_options() {
+        let text = "Foo food 😀foo foo_bar";
+        assert_eq!(
+            find(text, "foo", &FindOptions::default()),
+            vec![
+                Interval::new(0, 3),
+                Interval::new(4, 7),
+                Interval::new(11, 14),
+                Interval::new(15, 18)
+            ]
+        );
+
+        let options = FindOptions {
+            case_sensitive: true,
+            whole_word: true,
+        };
+        assert_eq!(find(text, "foo", &options), vec![Interval::new(11, 14)]);
+        assert!(find(text, "", &options).is_empty());
+    }
+
+    #[test]
+    fn search_with_attributes() {
+        let mut delta = RichTextDelta::default();
+        delta.insert("TODO(a)\nlet b = 1; // TODO(c)", RichTextAttributes::default());
+        delta.insert(
+            "\n",
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::CodeBlock(true))
+                .build(),
+        );
+        delta.insert(
+            "bold",
+            AttributeBuilder::new().add_attr(RichTextAttribute::Bold(true)).build(),
+        );
+        delta.insert("\n", RichTextAttributes::default());
+        let index = TextIndex::new(&delta);
+
+        // Only the TODO inside the code block is matched.
+        let query = SearchQuery {
+            pattern: Some(r"TODO\((\w+)\)".to_owned()),
+            case_sensitive: true,
+            attributes: RichTextAttribute::CodeBlock(true).into(),
+        };
+        let matches = index.search(&query).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].interval, Interval::new(22, 29));
+        assert_eq!(matches[0].groups, vec![Some((Interval::new(27, 28), "c".to_owned()))]);
+
+        let query = SearchQuery {
+            pattern: Some("todo".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(index.search(&query).unwrap().len(), 2);
+
+        let query = SearchQuery {
+            pattern: None,
+            attributes: RichTextAttribute::Bold(true).into(),
+            ..Default::default()
+        };
+        let matches = index.search(&query).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].interval, Interval::new(30, 34));
+    }
+}
@@ -0,0 +1,161 @@
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation, NEW_LINE},
+    rich_text::RichTextDelta,
+};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits the text of a line in words and sentences. The selection of a word, the word count and
+/// the spell check all use the segmenter of the document, so they agree on what a word is, see
+/// [crate::client_document::ClientDocument::set_segmenter].
+///
+/// The default one follows the Unicode rules, the applications may plug in the rules of a locale,
+/// e.g. the dictionary-based ones of Thai or Japanese, which don't separate the words by spaces.
+pub trait Segmenter: Send + Sync {
+    /// Returns the byte ranges of the words of `text`, in order. The whitespace and the
+    /// punctuation between the words aren't words.
+    fn words(&self, text: &str) -> Vec<Range<usize>>;
+
+    /// Returns the byte ranges of the sentences of `text`, in order, without the whitespace that
+    /// follows them.
+    fn sentences(&self, text: &str) -> Vec<Range<usize>>;
+}
+
+/// The word and sentence boundaries of the Unicode Standard Annex #29. The words are the segments
+/// with a letter or a digit, the same ones as [UnicodeSegmentation::unicode_words].
+pub struct UnicodeSegmenter();
+
+impl Segmenter for UnicodeSegmenter {
+    fn words(&self, text: &str) -> Vec<Range<usize>> {
+        text.split_word_bound_indices()
+            .filter(|(_, word)| word.chars().any(char::is_alphanumeric))
+            .map(|(start, word)| start..start + word.len())
+            .collect()
+    }
+
+    fn sentences(&self, text: &str) -> Vec<Range<usize>> {
+        text.split_sentence_bound_indices()
+            .filter(|(_, sentence)| !sentence.trim().is_empty())
+            .map(|(start, sentence)| start..start + sentence.trim_end().len())
+            .collect()
+    }
+}
+
+/// A word or a sentence of the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSegment {
+    /// The utf16 interval of the segment in the document.
+    pub interval: Interval,
+    pub text: String,
+}
+
+/// Returns the words of `document` that overlap `interval`, the ones that `interval` only partly
+/// covers included. An empty interval gets the word it's in or at the edge of.
+pub fn words_in(document: &RichTextDelta, interval: Interval, segmenter: &dyn Segmenter) -> Vec<TextSegment> {
+    segments_in(document, interval, |text| segmenter.words(text))
+}
+
+/// Returns the sentences of `document` that overlap `interval`, in the same way as [words_in]. A
+/// sentence doesn't go past the end of its line.
+pub fn sentences_in(document: &RichTextDelta, interval: Interval, segmenter: &dyn Segmenter) -> Vec<TextSegment> {
+    segments_in(document, interval, |text| segmenter.sentences(text))
+}
+
+fn segments_in<F>(document: &RichTextDelta, interval: Interval, split: F) -> Vec<TextSegment>
+where
+    F: Fn(&str) -> Vec<Range<usize>>,
+{
+    let mut segments = vec![];
+    let mut line = String::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let add_line = |line: &str, start: usize, segments: &mut Vec<TextSegment>| {
+        let mut utf16_offset = start;
+        let mut byte_offset = 0;
+        for range in split(line) {
+            let segment_start = utf16_offset + count_utf16_code_units(&line[byte_offset..range.start]);
+            let segment_end = segment_start + count_utf16_code_units(&line[range.clone()]);
+            utf16_offset = segment_end;
+            byte_offset = range.end;
+            let segment = Interval::new(segment_start, segment_end);
+            if overlaps(&segment, &interval) {
+                segments.push(TextSegment {
+                    interval: segment,
+                    text: line[range].to_owned(),
+                });
+            }
+        }
+    };
+
+    for op in &document.ops {
+        let insert = match op {
+            Operation::Insert(insert) => insert,
+            _ => continue,
+        };
+        for piece in insert.s.split_inclusive(NEW_LINE) {
+            offset += count_utf16_code_units(piece);
+            match piece.strip_suffix(NEW_LINE) {
+                None => line.push_str(piece),
+                Some(piece) => {
+                    line.push_str(piece);
+                    if offset > interval.start {
+                        add_line(&line, start, &mut segments);
+                    }
+                    line.clear();
+                    start = offset;
+                    if start > interval.end {
+                        return segments;
+                    }
+                }
+            }
+        }
+    }
+    // The text after the last newline.
+    add_line(&line, start, &mut segments);
+    segments
+}
+
+fn overlaps(segment: &Interval, interval: &Interval) -> bool {
+    match interval.is_empty() {
+        true => segment.start <= interval.start && interval.start <= segment.end,
+        false => segment.start < interval.end && interval.start < segment.end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::segmentation::{sentences_in, words_in, UnicodeSegmenter};
+    use lib_ot::{core::Interval, rich_text::RichTextDeltaBuilder};
+
+    #[test]
+    fn words_and_sentences_overlapping_interval() {
+        let document = RichTextDeltaBuilder::new()
+            .insert("Hello, wörld! 😀 can't stop.\n")
+            .insert("Two lines")
+            .build();
+        let words = |interval| {
+            words_in(&document, interval, &UnicodeSegmenter())
+                .into_iter()
+                .map(|word| (word.interval, word.text))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            words(Interval::new(3, 9)),
+            vec![
+                (Interval::new(0, 5), "Hello".to_owned()),
+                (Interval::new(7, 12), "wörld".to_owned()),
+            ]
+        );
+        // The emoji takes two utf16 code units.
+        assert_eq!(
+            words(Interval::new(18, 18)),
+            vec![(Interval::new(17, 22), "can't".to_owned())]
+        );
+        assert_eq!(words(Interval::new(30, 40)).len(), 2);
+        assert!(words(Interval::new(13, 16)).is_empty());
+
+        let sentences = sentences_in(&document, Interval::new(0, 40), &UnicodeSegmenter());
+        let sentences = sentences.iter().map(|s| s.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["Hello, wörld!", "😀 can't stop.", "Two lines"]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+/// When a document takes a snapshot, which the document is opened from instead of replaying all
+/// of its revisions, see [crate::client_document::store::save_document]. The policy is evaluated
+/// after each revision, the local and the remote ones, against the [PendingReplay] since the
+/// last snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotPolicy {
+    /// After every revision, so every save writes a snapshot.
+    Always,
+    /// Once there are `n` revisions since the last snapshot.
+    EveryRevisions(u64),
+    /// Once the deltas of the revisions since the last snapshot sum to `n` bytes.
+    EveryBytes(usize),
+    /// Once replaying the revisions since the last snapshot would take `max_replay`, estimated by
+    /// the time their deltas took to compose. The heavily edited large documents are snapshotted
+    /// more often than the small ones, so the time to open a document stays bounded.
+    Adaptive { max_replay: Duration },
+}
+
+impl std::default::Default for SnapshotPolicy {
+    fn default() -> Self {
+        SnapshotPolicy::Always
+    }
+}
+
+impl SnapshotPolicy {
+    pub fn is_due(&self, pending: &PendingReplay) -> bool {
+        if pending.revisions == 0 {
+            return false;
+        }
+        match *self {
+            SnapshotPolicy::Always => true,
+            SnapshotPolicy::EveryRevisions(n) => pending.revisions >= n,
+            SnapshotPolicy::EveryBytes(n) => pending.bytes >= n,
+            SnapshotPolicy::Adaptive { max_replay } => pending.replay_cost >= max_replay,
+        }
+    }
+}
+
+/// The revisions that opening the document would replay on top of its last snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingReplay {
+    pub revisions: u64,
+    /// The size of their deltas.
+    pub bytes: usize,
+    /// The time their deltas took to compose.
+    pub replay_cost: Duration,
+}
+
+impl PendingReplay {
+    pub(crate) fn record(&mut self, bytes: usize, replay_cost: Duration) {
+        self.revisions += 1;
+        self.bytes += bytes;
+        self.replay_cost += replay_cost;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        snapshot_policy::{PendingReplay, SnapshotPolicy},
+        store::{load_document, save_document, MemoryDocumentStore, SnapshotStore},
+        ClientDocument, DocumentConfigBuilder, DocumentHandle, NewlineDoc,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn policies_are_due_after_their_threshold() {
+        let pending = PendingReplay {
+            revisions: 3,
+            bytes: 120,
+            replay_cost: Duration::from_millis(4),
+        };
+        assert!(!SnapshotPolicy::Always.is_due(&PendingReplay::default()));
+        assert!(SnapshotPolicy::Always.is_due(&pending));
+        assert!(SnapshotPolicy::EveryRevisions(3).is_due(&pending));
+        assert!(!SnapshotPolicy::EveryRevisions(4).is_due(&pending));
+        assert!(!SnapshotPolicy::EveryBytes(121).is_due(&pending));
+        let adaptive = |millis| SnapshotPolicy::Adaptive {
+            max_replay: Duration::from_millis(millis),
+        };
+        assert!(adaptive(4).is_due(&pending));
+        assert!(!adaptive(5).is_due(&pending));
+    }
+
+    #[tokio::test]
+    async fn save_writes_the_snapshot_when_it_is_due() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(
+            DocumentConfigBuilder::new("doc")
+                .snapshot_policy(SnapshotPolicy::EveryRevisions(3))
+                .build(),
+        );
+        let handle = DocumentHandle::new(document);
+        let store = MemoryDocumentStore::default();
+
+        handle.insert(0, "a").await.unwrap();
+        handle.insert(1, "b").await.unwrap();
+        let saved_rev_id = save_document(&store, "doc", &handle, 0).await.unwrap();
+        assert_eq!(saved_rev_id, 2);
+        assert!(store.read_snapshot("doc").unwrap().is_none());
+
+        handle.insert(2, "c").await.unwrap();
+        let saved_rev_id = save_document(&store, "doc", &handle, saved_rev_id).await.unwrap();
+        assert_eq!(store.read_snapshot("doc").unwrap().unwrap().rev_id, saved_rev_id);
+        assert_eq!(handle.read(|document| document.pending_replay()).await.revisions, 0);
+    }
+
+    #[tokio::test]
+    async fn save_writes_a_snapshot_when_the_log_was_compacted() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(
+            DocumentConfigBuilder::new("doc")
+                .snapshot_policy(SnapshotPolicy::EveryRevisions(100))
+                .max_log_revisions(Some(2))
+                .build(),
+        );
+        let handle = DocumentHandle::new(document);
+        let store = MemoryDocumentStore::default();
+
+        handle.insert(0, "a").await.unwrap();
+        let saved_rev_id = save_document(&store, "doc", &handle, 0).await.unwrap();
+        for (index, text) in ["b", "c", "d", "e", "f"].iter().enumerate() {
+            handle.insert(index + 1, text).await.unwrap();
+        }
+        let revisions = handle.read(|document| document.revision_log().revisions().len()).await;
+        assert!(revisions <= 4);
+
+        let saved_rev_id = save_document(&store, "doc", &handle, saved_rev_id).await.unwrap();
+        assert_eq!(store.read_snapshot("doc").unwrap().unwrap().rev_id, saved_rev_id);
+        let reopened = load_document(&store, "doc").unwrap().unwrap();
+        assert_eq!(reopened.to_plain_string(), "abcdef\n");
+    }
+}
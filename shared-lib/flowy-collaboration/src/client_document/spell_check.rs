@@ -0,0 +1,195 @@
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+    segmentation::{words_in, TextSegment},
+};
+use lib_ot::{
+    core::{Interval, Operation},
+    rich_text::RichTextDelta,
+};
+use std::sync::Arc;
+
+/// Checks the spelling of the words of the document, see
+/// [crate::client_document::ClientDocument::set_spell_check]. The document gives it the words
+/// that each change touched, by any participant, as the segmenter of the document splits them.
+pub trait SpellCheck: Send + Sync {
+    /// Returns the intervals of the misspelled text of `words`, in the document.
+    fn check(&self, words: &[TextSegment]) -> Vec<Interval>;
+}
+
+/// The misspelled text of the document, which the editor underlines. The misspellings are
+/// transformed by every change like the annotations, but they aren't kept: the words are checked
+/// again when the document is opened.
+#[derive(Debug, Clone, Default)]
+pub struct Misspellings {
+    intervals: Vec<Interval>,
+}
+
+impl Misspellings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The intervals of the misspellings, in order.
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    /// Replaces the misspellings of the `checked` words with `misspelled`, which the check found
+    /// in them.
+    pub fn replace(&mut self, checked: &[TextSegment], misspelled: Vec<Interval>) {
+        self.intervals.retain(|interval| {
+            !checked
+                .iter()
+                .any(|word| word.interval.start < interval.end && interval.start < word.interval.end)
+        });
+        self.intervals
+            .extend(misspelled.into_iter().filter(|interval| !interval.is_empty()));
+        self.intervals.sort_by_key(|interval| (interval.start, interval.end));
+        self.intervals.dedup();
+    }
+
+    pub fn clear(&mut self) {
+        self.intervals.clear();
+    }
+
+    /// Moves the misspellings over `delta`. The ones whose text was deleted are removed, the ones
+    /// whose text was edited stay until their words are checked again.
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        if delta.is_noop() {
+            return;
+        }
+        for interval in self.intervals.iter_mut() {
+            let start = delta.transform_position(interval.start, false);
+            let end = delta.transform_position(interval.end, true).max(start);
+            *interval = Interval::new(start, end);
+        }
+        self.intervals.retain(|interval| !interval.is_empty());
+    }
+}
+
+/// Checks the spelling of the document with the [SpellCheck] while it observes the document, see
+/// [crate::client_document::ClientDocument::set_spell_check]. The whole document is checked when
+/// it starts, and then the words of every change.
+pub struct SpellChecker {
+    spell_check: Arc<dyn SpellCheck>,
+    misspellings: Misspellings,
+}
+
+impl SpellChecker {
+    pub fn new(spell_check: Arc<dyn SpellCheck>) -> Self {
+        Self {
+            spell_check,
+            misspellings: Misspellings::new(),
+        }
+    }
+
+    /// The intervals of the misspelled text, in order.
+    pub fn misspellings(&self) -> &[Interval] {
+        self.misspellings.intervals()
+    }
+
+    // Checks the words that overlap `intervals` and replaces their misspellings.
+    fn check(&mut self, document: &ObservedDocument, intervals: &[Interval]) {
+        let mut words: Vec<TextSegment> = vec![];
+        for interval in intervals {
+            for word in words_in(document.delta, *interval, document.segmenter) {
+                if words.last() != Some(&word) {
+                    words.push(word);
+                }
+            }
+        }
+        if words.is_empty() {
+            return;
+        }
+        let misspelled = self.spell_check.check(&words);
+        self.misspellings.replace(&words, misspelled);
+    }
+
+    fn check_all(&mut self, document: &ObservedDocument) {
+        self.misspellings.clear();
+        self.check(document, &[Interval::new(0, document.delta.utf16_target_len)]);
+    }
+}
+
+impl DocumentObserver for SpellChecker {
+    fn did_reset(&mut self, document: &ObservedDocument, _replaced: Option<&RichTextDelta>) {
+        self.check_all(document);
+    }
+
+    fn will_change(&mut self, _document: &ObservedDocument, delta: &RichTextDelta) {
+        self.misspellings.transform(delta);
+    }
+
+    fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent) {
+        self.check(document, &changed_intervals(&event.delta));
+    }
+
+    fn did_change_segmenter(&mut self, document: &ObservedDocument) {
+        self.check_all(document);
+    }
+}
+
+/// Returns the intervals of the text that `delta` changed, in the document it was composed into:
+/// the text it inserted and the positions where it deleted text. The formatting isn't a change of
+/// the text.
+pub fn changed_intervals(delta: &RichTextDelta) -> Vec<Interval> {
+    let mut intervals = vec![];
+    let mut offset = 0;
+    for op in &delta.ops {
+        match op {
+            Operation::Retain(retain) => offset += retain.n,
+            Operation::Delete(_) => intervals.push(Interval::new(offset, offset)),
+            Operation::Insert(insert) => {
+                let len = insert.utf16_size();
+                intervals.push(Interval::new(offset, offset + len));
+                offset += len;
+            }
+        }
+    }
+    intervals
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        segmentation::{words_in, UnicodeSegmenter},
+        spell_check::{changed_intervals, Misspellings},
+    };
+    use lib_ot::{
+        core::{Interval, OperationTransformable},
+        rich_text::RichTextDeltaBuilder,
+    };
+
+    #[test]
+    fn misspellings_follow_edits() {
+        let document = RichTextDeltaBuilder::new().insert("helo wrld ok\n").build();
+        let mut misspellings = Misspellings::new();
+        let words = words_in(&document, Interval::new(0, 13), &UnicodeSegmenter());
+        misspellings.replace(&words, vec![Interval::new(0, 4), Interval::new(5, 9)]);
+
+        // Fix the first word and delete the second one.
+        let delta = RichTextDeltaBuilder::new()
+            .retain(3)
+            .insert("l")
+            .retain(2)
+            .delete(5)
+            .build();
+        let document = document.compose(&delta).unwrap();
+        misspellings.transform(&delta);
+        assert_eq!(misspellings.intervals(), &[Interval::new(0, 5)]);
+
+        let changed = changed_intervals(&delta);
+        assert_eq!(changed, vec![Interval::new(3, 4), Interval::new(6, 6)]);
+        let words = changed
+            .into_iter()
+            .flat_map(|interval| words_in(&document, interval, &UnicodeSegmenter()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>(),
+            vec!["hello", "ok"]
+        );
+        misspellings.replace(&words, vec![]);
+        assert!(misspellings.intervals().is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::client_document::{anchor::AnchorId, annotation::AnnotationId};
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+use std::collections::BTreeMap;
+
+/// What [ClientDocument::append](crate::client_document::ClientDocument::append) did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppendedDocument {
+    /// The change that appended the other document.
+    pub delta: RichTextDelta,
+    /// The ids of the anchors of the other document, by their ids in it.
+    pub anchors: BTreeMap<AnchorId, AnchorId>,
+    /// The ids of the annotations of the other document, by their ids in it.
+    pub annotations: BTreeMap<AnnotationId, AnnotationId>,
+}
+
+/// Splits the content of `document` at `index` into the content before it and the one from it on.
+/// The block attributes stay on the newlines they are kept on.
+pub fn split_document(document: &RichTextDelta, index: usize) -> (RichTextDelta, RichTextDelta) {
+    let mut first = RichTextDelta::new();
+    let mut second = RichTextDelta::new();
+    let mut offset = 0;
+    for op in &document.ops {
+        let len = op.len();
+        let at = index.saturating_sub(offset).min(len);
+        if let Some(op) = op.shrink(Interval::new(0, at)) {
+            first.add(op);
+        }
+        if let Some(op) = op.shrink(Interval::new(at, len)) {
+            second.add(op);
+        }
+        offset += len;
+    }
+    (first, second)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{anchor::AnchorBias, ClientDocument, NewlineDoc};
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+
+    #[test]
+    fn split_at_line_and_append_back() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "intro\nTitle\nbody").unwrap();
+        document
+            .format(Interval::new(6, 12), RichTextAttribute::Header(1))
+            .unwrap();
+        let anchor = document.create_anchor(8, AnchorBias::Left).unwrap();
+        let annotation = document.create_annotation(Interval::new(2, 9), "comment").unwrap();
+        let json = document.to_json();
+
+        assert!(document.split_at_line(0).is_err());
+        assert!(document.split_at_line(3).is_err());
+        let (first, second) = document.split_at_line(1).unwrap();
+        assert_eq!(first.to_json(), r#"[{"insert":"intro\n"}]"#);
+        assert_eq!(
+            second.to_json(),
+            r#"[{"insert":"Title"},{"insert":"\n","attributes":{"header":1}},{"insert":"body\n"}]"#
+        );
+        assert_eq!(first.resolve_anchor(anchor), None);
+        assert_eq!(second.resolve_anchor(anchor), Some(2));
+        // The annotation across the split is in both documents.
+        assert_eq!(
+            first.annotations().get(annotation).unwrap().interval,
+            Interval::new(2, 6)
+        );
+        assert_eq!(
+            second.annotations().get(annotation).unwrap().interval,
+            Interval::new(0, 3)
+        );
+
+        let mut joined = first;
+        let appended = joined.append(&second).unwrap();
+        assert_eq!(joined.to_json(), json);
+        let anchor = appended.anchors[&anchor];
+        assert_eq!(joined.resolve_anchor(anchor), Some(8));
+        let annotation = appended.annotations[&annotation];
+        assert_eq!(
+            joined.annotations().get(annotation).unwrap().interval,
+            Interval::new(6, 9)
+        );
+        assert!(joined.undo().is_ok());
+        assert_eq!(joined.to_json(), r#"[{"insert":"intro\n"}]"#);
+    }
+}
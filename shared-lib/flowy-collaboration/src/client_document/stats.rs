@@ -0,0 +1,222 @@
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+    segmentation::Segmenter,
+};
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation},
+    rich_text::RichTextDelta,
+};
+use std::time::Duration;
+use unicode_segmentation::UnicodeSegmentation;
+
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    /// The number of words, as the [Segmenter] of the document splits them.
+    pub words: usize,
+    /// The number of user-perceived characters, the newlines aren't counted.
+    pub chars: usize,
+    /// The number of lines that contain more than whitespace.
+    pub paragraphs: usize,
+}
+
+impl DocumentStats {
+    pub fn reading_time(&self) -> Duration {
+        let secs = (self.words * 60 + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE;
+        Duration::from_secs(secs as u64)
+    }
+
+    fn add(&mut self, line: &LineStats) {
+        self.words += line.words;
+        self.chars += line.chars;
+        self.paragraphs += !line.is_blank as usize;
+    }
+
+    fn sub(&mut self, line: &LineStats) {
+        self.words -= line.words;
+        self.chars -= line.chars;
+        self.paragraphs -= !line.is_blank as usize;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineStats {
+    // The utf16 length of the line, including its newline.
+    len: usize,
+    words: usize,
+    chars: usize,
+    is_blank: bool,
+}
+
+impl LineStats {
+    fn new(line: &str, segmenter: &dyn Segmenter) -> Self {
+        let graphemes = line.graphemes(true).filter(|g| *g != "\n" && *g != "\r\n");
+        let (chars, is_blank) = graphemes.fold((0, true), |(chars, is_blank), g| {
+            (chars + 1, is_blank && g.trim().is_empty())
+        });
+        Self {
+            len: count_utf16_code_units(line),
+            words: segmenter.words(line).len(),
+            chars,
+            is_blank,
+        }
+    }
+}
+
+/// Keeps the [DocumentStats] of the document up to date while it observes the document. The stats
+/// are kept per line, so only the lines touched by a delta are counted again.
+#[derive(Debug, Clone, Default)]
+pub struct StatsTracker {
+    lines: Vec<LineStats>,
+    stats: DocumentStats,
+}
+
+impl StatsTracker {
+    pub fn new(document: &RichTextDelta, segmenter: &dyn Segmenter) -> Self {
+        let mut tracker = Self::default();
+        let text = text_in(document, Interval::new(0, document.utf16_target_len));
+        tracker.splice(0..0, &text, segmenter);
+        tracker
+    }
+
+    pub fn stats(&self) -> DocumentStats {
+        self.stats
+    }
+
+    /// Updates the stats after `delta` was composed into the document, `document` is the new
+    /// content of the document.
+    pub fn apply(&mut self, delta: &RichTextDelta, document: &RichTextDelta, segmenter: &dyn Segmenter) {
+        // The range of the old content that was changed, the formatting doesn't matter here.
+        let mut changed: Option<Interval> = None;
+        let mut offset = 0;
+        for op in &delta.ops {
+            let len = match op {
+                Operation::Retain(retain) => {
+                    offset += retain.n;
+                    continue;
+                }
+                Operation::Delete(n) => *n,
+                Operation::Insert(_) => 0,
+            };
+            let start = changed.map(|interval| interval.start).unwrap_or(offset);
+            changed = Some(Interval::new(start, offset + len));
+            offset += len;
+        }
+        let changed = match changed {
+            None => return,
+            Some(changed) => changed,
+        };
+
+        let old_len: usize = self.lines.iter().map(|line| line.len).sum();
+        let (first, first_start) = self.line_at(changed.start);
+        let (last, last_start) = self.line_at(changed.end);
+        let old_end = last_start + self.lines.get(last).map(|line| line.len).unwrap_or(0);
+        let new_end = (old_end + document.utf16_target_len).saturating_sub(old_len);
+
+        let text = text_in(document, Interval::new(first_start, new_end));
+        let end = (last + 1).min(self.lines.len());
+        self.splice(first..end, &text, segmenter);
+    }
+
+    // Returns the index of the line that contains `index` and the offset of the line. The index at
+    // the end of the document belongs to the last line.
+    fn line_at(&self, index: usize) -> (usize, usize) {
+        let mut start = 0;
+        for (i, line) in self.lines.iter().enumerate() {
+            if index < start + line.len || i + 1 == self.lines.len() {
+                return (i, start);
+            }
+            start += line.len;
+        }
+        (0, 0)
+    }
+
+    fn splice(&mut self, range: std::ops::Range<usize>, text: &str, segmenter: &dyn Segmenter) {
+        let lines = text
+            .split_inclusive('\n')
+            .map(|line| LineStats::new(line, segmenter))
+            .collect::<Vec<_>>();
+        lines.iter().for_each(|line| self.stats.add(line));
+        for line in self.lines.splice(range, lines) {
+            self.stats.sub(&line);
+        }
+    }
+}
+
+impl DocumentObserver for StatsTracker {
+    fn did_reset(&mut self, document: &ObservedDocument, _replaced: Option<&RichTextDelta>) {
+        *self = StatsTracker::new(document.delta, document.segmenter);
+    }
+
+    fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent) {
+        self.apply(&event.delta, document.delta, document.segmenter);
+    }
+
+    fn did_change_segmenter(&mut self, document: &ObservedDocument) {
+        *self = StatsTracker::new(document.delta, document.segmenter);
+    }
+}
+
+fn text_in(document: &RichTextDelta, interval: Interval) -> String {
+    let mut text = String::new();
+    for slice in document.ops_in(interval) {
+        if let Operation::Insert(insert) = slice.op {
+            match slice.is_whole() {
+                true => text.push_str(&insert.s),
+                false => text.push_str(&insert.s.sub_str(slice.interval).unwrap_or_default()),
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        segmentation::UnicodeSegmenter,
+        stats::{DocumentStats, StatsTracker},
+    };
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn stats_follow_delta() {
+        let mut document = RichTextDeltaBuilder::new().insert("Hello world\n\nfoo\n").build();
+        let mut tracker = StatsTracker::new(&document, &UnicodeSegmenter());
+        assert_eq!(
+            tracker.stats(),
+            DocumentStats {
+                words: 3,
+                chars: 14,
+                paragraphs: 2,
+            }
+        );
+
+        let deltas: Vec<RichTextDelta> = vec![
+            // Join the first two lines and type a word with a combining character.
+            RichTextDeltaBuilder::new()
+                .retain(11)
+                .delete(1)
+                .insert(" cafe\u{301}")
+                .build(),
+            // Split the last line in the middle of its word.
+            RichTextDeltaBuilder::new().retain(20).insert("\nbar").build(),
+            RichTextDeltaBuilder::new().delete(6).build(),
+        ];
+        for delta in deltas {
+            document = document.compose(&delta).unwrap();
+            tracker.apply(&delta, &document, &UnicodeSegmenter());
+            assert_eq!(
+                tracker.stats(),
+                StatsTracker::new(&document, &UnicodeSegmenter()).stats()
+            );
+        }
+        assert_eq!(document.apply("").unwrap(), "world cafe\u{301}\nfo\nbaro\n");
+        assert_eq!(tracker.stats().words, 4);
+        assert_eq!(tracker.stats().paragraphs, 3);
+    }
+}
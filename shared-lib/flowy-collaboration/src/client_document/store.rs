@@ -0,0 +1,200 @@
+use crate::{
+    client_document::{
+        metadata::Metadata, revision_log::DocumentSnapshot, snapshot_policy::PendingReplay, ClientDocument,
+        DocumentHandle,
+    },
+    entities::revision::Revision,
+    errors::CollaborateError,
+    util::verify_content_md5,
+};
+use dashmap::DashMap;
+use instant::Instant;
+use lib_ot::{core::OperationTransformable, rich_text::RichTextDelta};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Stores the revisions of the documents, in the order of their `rev_id`.
+pub trait RevisionStore: Send + Sync {
+    /// Returns the revisions of the document after `rev_id`.
+    fn read_revisions(&self, doc_id: &str, rev_id: i64) -> Result<Vec<Revision>, CollaborateError>;
+
+    /// Adds the revisions, the ones that are already stored are skipped.
+    fn write_revisions(&self, doc_id: &str, revisions: &[Revision]) -> Result<(), CollaborateError>;
+}
+
+/// Stores the latest snapshot of the documents.
+pub trait SnapshotStore: Send + Sync {
+    fn read_snapshot(&self, doc_id: &str) -> Result<Option<DocumentSnapshot>, CollaborateError>;
+
+    /// Replaces the snapshot of the document. The document is read from the snapshot and the
+    /// revisions after it, so the revisions before it are only kept for the history.
+    fn write_snapshot(&self, doc_id: &str, snapshot: &DocumentSnapshot) -> Result<(), CollaborateError>;
+
+    /// The ids of the stored documents, e.g. for the searches of all the documents. The stores
+    /// that can't list their documents return none.
+    fn doc_ids(&self) -> Result<Vec<String>, CollaborateError> {
+        Ok(vec![])
+    }
+}
+
+pub trait DocumentStore: RevisionStore + SnapshotStore {
+    /// Writes the revisions and then the snapshot. The stores that support transactions should
+    /// write both or neither, the default may leave the revisions without the snapshot, which
+    /// still reads as the same document.
+    fn save(&self, doc_id: &str, snapshot: &DocumentSnapshot, revisions: &[Revision]) -> Result<(), CollaborateError> {
+        self.write_revisions(doc_id, revisions)?;
+        self.write_snapshot(doc_id, snapshot)
+    }
+}
+
+pub type DocumentStoreRef = Arc<dyn DocumentStore>;
+
+/// Reads the document from its snapshot and the revisions after it, with the metadata of both.
+/// Returns `None` if the document was never saved.
+pub fn load_document(store: &dyn DocumentStore, doc_id: &str) -> Result<Option<ClientDocument>, CollaborateError> {
+    let snapshot = store.read_snapshot(doc_id)?;
+    let rev_id = snapshot.as_ref().map(|snapshot| snapshot.rev_id).unwrap_or(0);
+    let mut revisions = store.read_revisions(doc_id, rev_id)?;
+    let (rev_id, mut delta, snapshot_metadata) = match snapshot {
+        None if revisions.is_empty() => return Ok(None),
+        None => (0, RichTextDelta::default(), vec![]),
+        Some(snapshot) => (snapshot.rev_id, snapshot.delta()?, snapshot.metadata),
+    };
+    revisions.retain(|revision| revision.rev_id > rev_id);
+    // The entries are merged by their time, in any order.
+    let mut metadata = Metadata::from_revisions(&revisions);
+    for entry in snapshot_metadata {
+        let _ = metadata.merge(entry);
+    }
+
+    let mut latest_rev_id = rev_id;
+    let mut pending_replay = PendingReplay::default();
+    for revision in &revisions {
+        let started = Instant::now();
+        delta = delta.compose(&RichTextDelta::from_bytes(&revision.delta_data)?)?;
+        pending_replay.record(revision.delta_data.len(), started.elapsed());
+        verify_content_md5(revision, &delta)?;
+        latest_rev_id = revision.rev_id;
+    }
+    let snapshot = DocumentSnapshot::new(latest_rev_id, &delta).with_metadata(metadata.entries().cloned().collect());
+    let mut document = ClientDocument::restore(&snapshot, &[])?;
+    document.set_pending_replay(pending_replay);
+    Ok(Some(document))
+}
+
+/// Writes the revisions of the document after `saved_rev_id`, which is the revision the store has
+/// the document at, and a snapshot if the snapshot policy of the document says it's due, see
+/// [crate::client_document::snapshot_policy::SnapshotPolicy]. Returns the revision the document
+/// was saved at, the document isn't written if it didn't change.
+pub async fn save_document(
+    store: &dyn DocumentStore,
+    doc_id: &str,
+    handle: &DocumentHandle,
+    saved_rev_id: i64,
+) -> Result<i64, CollaborateError> {
+    let (rev_id, snapshot, revisions) = handle
+        .write(|document| {
+            let revisions = document.revisions_after(saved_rev_id);
+            // The revisions after `saved_rev_id` were compacted away, see
+            // [crate::client_document::DocumentConfig::max_log_revisions], so the snapshot
+            // replaces them.
+            let is_compacted = document.revision_log().snapshots()[0].rev_id > saved_rev_id;
+            let snapshot = match is_compacted || document.is_snapshot_due() {
+                true => Some(document.snapshot()),
+                false => None,
+            };
+            (document.rev_id(), snapshot, revisions)
+        })
+        .await;
+    if rev_id == saved_rev_id {
+        return Ok(saved_rev_id);
+    }
+    match snapshot {
+        Some(snapshot) => store.save(doc_id, &snapshot, &revisions)?,
+        None => store.write_revisions(doc_id, &revisions)?,
+    }
+    Ok(rev_id)
+}
+
+/// The revision the store has a document at, shared by the ones that save the document, e.g. a
+/// [DocumentManager](crate::client_document::manager::DocumentManager) and the
+/// [Autosaver](crate::client_document::autosave::Autosaver) of the document, so each save writes
+/// the revisions after the one before it.
+#[derive(Clone)]
+pub struct SavePoint(Arc<Mutex<i64>>);
+
+impl SavePoint {
+    pub fn new(saved_rev_id: i64) -> Self {
+        Self(Arc::new(Mutex::new(saved_rev_id)))
+    }
+
+    pub async fn rev_id(&self) -> i64 {
+        *self.0.lock().await
+    }
+
+    /// Saves the document like [save_document] and moves the save point to the revision it was
+    /// saved at. The saves of the document wait for each other.
+    pub async fn save(
+        &self,
+        store: &dyn DocumentStore,
+        doc_id: &str,
+        handle: &DocumentHandle,
+    ) -> Result<i64, CollaborateError> {
+        let mut saved_rev_id = self.0.lock().await;
+        let rev_id = save_document(store, doc_id, handle, *saved_rev_id).await?;
+        *saved_rev_id = rev_id;
+        Ok(rev_id)
+    }
+}
+
+/// Keeps the documents in memory, e.g. for the tests or the documents that aren't persisted.
+#[derive(Default)]
+pub struct MemoryDocumentStore {
+    snapshots: DashMap<String, DocumentSnapshot>,
+    revisions: DashMap<String, Vec<Revision>>,
+}
+
+impl RevisionStore for MemoryDocumentStore {
+    fn read_revisions(&self, doc_id: &str, rev_id: i64) -> Result<Vec<Revision>, CollaborateError> {
+        let revisions = match self.revisions.get(doc_id) {
+            None => vec![],
+            Some(revisions) => revisions.iter().filter(|r| r.rev_id > rev_id).cloned().collect(),
+        };
+        Ok(revisions)
+    }
+
+    fn write_revisions(&self, doc_id: &str, revisions: &[Revision]) -> Result<(), CollaborateError> {
+        let mut stored = self.revisions.entry(doc_id.to_owned()).or_default();
+        for revision in revisions {
+            if stored.last().map(|last| last.rev_id < revision.rev_id).unwrap_or(true) {
+                stored.push(revision.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SnapshotStore for MemoryDocumentStore {
+    fn read_snapshot(&self, doc_id: &str) -> Result<Option<DocumentSnapshot>, CollaborateError> {
+        Ok(self.snapshots.get(doc_id).map(|snapshot| snapshot.clone()))
+    }
+
+    fn write_snapshot(&self, doc_id: &str, snapshot: &DocumentSnapshot) -> Result<(), CollaborateError> {
+        self.snapshots.insert(doc_id.to_owned(), snapshot.clone());
+        Ok(())
+    }
+
+    fn doc_ids(&self) -> Result<Vec<String>, CollaborateError> {
+        let mut doc_ids = self
+            .snapshots
+            .iter()
+            .map(|entry| entry.key().clone())
+            .chain(self.revisions.iter().map(|entry| entry.key().clone()))
+            .collect::<Vec<_>>();
+        doc_ids.sort();
+        doc_ids.dedup();
+        Ok(doc_ids)
+    }
+}
+
+impl DocumentStore for MemoryDocumentStore {}
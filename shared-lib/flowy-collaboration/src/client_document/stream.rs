@@ -0,0 +1,273 @@
+use crate::{
+    client_document::read_snapshot::ReadSnapshot, entities::document_info::DocumentChunk, errors::CollaborateError,
+    util::content_md5,
+};
+use lib_ot::{core::Interval, rich_text::RichTextDelta};
+
+/// The content of a document cut into chunks of whole lines, in order, so that the frontend
+/// renders the first lines of a large document before the rest is transferred. A chunk ends at
+/// the first line end that is at least `chunk_size` after its start, a line is never cut, so the
+/// block attributes of each line come with its text. The chunks are taken from a [ReadSnapshot],
+/// the document can change while they are sent. A transfer that is cut off goes on with
+/// [ContentStream::resume] from the [ContentAssembler::next_index] of the receiver.
+#[derive(Debug, Clone)]
+pub struct ContentStream {
+    snapshot: ReadSnapshot,
+    md5: String,
+    chunk_size: usize,
+    // The utf16 index after each newline of the document.
+    line_ends: Vec<usize>,
+    len: usize,
+    index: i64,
+    offset: usize,
+    // The first op that ends after the offset, and where it starts.
+    op_index: usize,
+    op_start: usize,
+    done: bool,
+}
+
+impl ContentStream {
+    pub fn new(snapshot: ReadSnapshot, chunk_size: usize) -> Self {
+        let delta = snapshot.delta();
+        Self {
+            md5: content_md5(delta),
+            chunk_size: chunk_size.max(1),
+            line_ends: line_ends(delta),
+            len: delta.utf16_target_len,
+            index: 0,
+            offset: 0,
+            op_index: 0,
+            op_start: 0,
+            done: false,
+            snapshot,
+        }
+    }
+
+    /// Returns the stream from the chunk at `index` on, the chunks before it were received.
+    pub fn resume(snapshot: ReadSnapshot, chunk_size: usize, index: i64) -> Self {
+        let mut stream = Self::new(snapshot, chunk_size);
+        while stream.index < index && !stream.done {
+            let end = stream.next_cut();
+            stream.skip_to(end);
+        }
+        stream
+    }
+
+    pub fn doc_id(&self) -> &str {
+        self.snapshot.doc_id()
+    }
+
+    pub fn rev_id(&self) -> i64 {
+        self.snapshot.rev_id()
+    }
+
+    fn next_cut(&self) -> usize {
+        let at = self.offset + self.chunk_size;
+        let i = self.line_ends.partition_point(|end| *end < at);
+        self.line_ends.get(i).copied().unwrap_or(self.len)
+    }
+
+    fn skip_to(&mut self, end: usize) {
+        self.index += 1;
+        self.offset = end;
+        self.done = end >= self.len;
+    }
+}
+
+impl Iterator for ContentStream {
+    type Item = DocumentChunk;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let end = self.next_cut();
+        let mut delta = RichTextDelta::new();
+        let ops = &self.snapshot.delta().ops;
+        while let Some(op) = ops.get(self.op_index) {
+            let op_end = self.op_start + op.len();
+            if self.op_start >= end {
+                break;
+            }
+            let interval = Interval::new(self.offset.max(self.op_start), end.min(op_end));
+            if let Some(op) = op.shrink(interval.translate_neg(self.op_start)) {
+                delta.add(op);
+            }
+            if op_end > end {
+                break;
+            }
+            self.op_index += 1;
+            self.op_start = op_end;
+        }
+
+        let chunk = DocumentChunk {
+            doc_id: self.doc_id().to_owned(),
+            rev_id: self.rev_id(),
+            index: self.index,
+            offset: self.offset as i64,
+            delta_json: delta.to_json(),
+            is_last: end >= self.len,
+            md5: self.md5.clone(),
+        };
+        self.skip_to(end);
+        Some(chunk)
+    }
+}
+
+/// Puts the chunks of a [ContentStream] back together on the receiving side. The chunks must come
+/// in order and from one revision: a chunk of another revision means the document changed
+/// between two transfers, the receiver drops what it has and starts over. The content is checked
+/// against the md5 of the last chunk.
+#[derive(Debug, Clone)]
+pub struct ContentAssembler {
+    doc_id: String,
+    rev_id: Option<i64>,
+    next_index: i64,
+    offset: usize,
+    delta: RichTextDelta,
+    complete: bool,
+}
+
+impl ContentAssembler {
+    pub fn new(doc_id: &str) -> Self {
+        Self {
+            doc_id: doc_id.to_owned(),
+            rev_id: None,
+            next_index: 0,
+            offset: 0,
+            delta: RichTextDelta::new(),
+            complete: false,
+        }
+    }
+
+    /// Appends the chunk and returns its content, which can be rendered at the end of the ones
+    /// before it.
+    pub fn push(&mut self, chunk: DocumentChunk) -> Result<RichTextDelta, CollaborateError> {
+        if chunk.doc_id != self.doc_id {
+            return Err(CollaborateError::internal()
+                .context(format!("The chunk of {} isn't one of {}", chunk.doc_id, self.doc_id)));
+        }
+        if let Some(rev_id) = self.rev_id {
+            if rev_id != chunk.rev_id {
+                return Err(CollaborateError::revision_conflict().context(format!(
+                    "The chunk is of the revision {}, the ones before it of {}",
+                    chunk.rev_id, rev_id
+                )));
+            }
+        }
+        if self.complete || chunk.index != self.next_index || chunk.offset != self.offset as i64 {
+            return Err(CollaborateError::out_of_bound().context(format!(
+                "Expected the chunk {} at {}, but it's the chunk {} at {}",
+                self.next_index, self.offset, chunk.index, chunk.offset
+            )));
+        }
+
+        let delta = RichTextDelta::from_json(&chunk.delta_json)?;
+        for op in &delta.ops {
+            self.delta.add(op.clone());
+        }
+        self.rev_id = Some(chunk.rev_id);
+        self.next_index += 1;
+        self.offset += delta.utf16_target_len;
+        if chunk.is_last {
+            let md5 = content_md5(&self.delta);
+            if md5 != chunk.md5 {
+                return Err(CollaborateError::integrity()
+                    .context(format!("The md5 of the content is {}, expected {}", md5, chunk.md5)));
+            }
+            self.complete = true;
+        }
+        Ok(delta)
+    }
+
+    /// The index of the chunk to resume the transfer from.
+    pub fn next_index(&self) -> i64 {
+        self.next_index
+    }
+
+    pub fn rev_id(&self) -> Option<i64> {
+        self.rev_id
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the content of the document once the last chunk is in.
+    pub fn finish(self) -> Result<RichTextDelta, CollaborateError> {
+        match self.complete {
+            true => Ok(self.delta),
+            false => Err(CollaborateError::internal().context(format!(
+                "The content of {} is missing the chunks from {} on",
+                self.doc_id, self.next_index
+            ))),
+        }
+    }
+}
+
+fn line_ends(delta: &RichTextDelta) -> Vec<usize> {
+    let mut ends = vec![];
+    let mut offset = 0;
+    for op in &delta.ops {
+        if op.get_embed().is_none() {
+            let mut index = offset;
+            for c in op.get_data().chars() {
+                index += c.len_utf16();
+                if c == '\n' {
+                    ends.push(index);
+                }
+            }
+        }
+        offset += op.len();
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client_document::{
+            stream::{ContentAssembler, ContentStream},
+            ClientDocument, NewlineDoc,
+        },
+        errors::ErrorCode,
+    };
+    use lib_ot::{core::Interval, rich_text::RichTextAttribute};
+
+    #[test]
+    fn stream_and_reassemble_whole_lines() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "one\ntwo\nthree four").unwrap();
+        document
+            .format(Interval::new(8, 18), RichTextAttribute::Header(1))
+            .unwrap();
+        document
+            .format(Interval::new(0, 11), RichTextAttribute::Bold(true))
+            .unwrap();
+
+        let chunks = document.stream_content(5).collect::<Vec<_>>();
+        let offsets = chunks.iter().map(|chunk| chunk.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 8]);
+        assert!(chunks[1].is_last);
+        assert!(chunks[1].delta_json.contains(r#""header":1"#));
+
+        // The transfer that is cut off goes on from the next chunk.
+        let mut assembler = ContentAssembler::new(&document.config().doc_id);
+        assembler.push(chunks[0].clone()).unwrap();
+        let resumed = ContentStream::resume(document.read_snapshot(), 5, assembler.next_index()).collect::<Vec<_>>();
+        assert_eq!(resumed, chunks[1..].to_vec());
+        assert!(assembler.push(chunks[0].clone()).is_err());
+        assembler.push(resumed[0].clone()).unwrap();
+        assert!(assembler.is_complete());
+        assert_eq!(assembler.finish().unwrap().to_json(), document.to_json());
+
+        // The chunks of another revision don't fit.
+        let mut assembler = ContentAssembler::new(&document.config().doc_id);
+        assembler.push(chunks[0].clone()).unwrap();
+        document.insert(0, "zero\n").unwrap();
+        let changed = document.stream_content(5).collect::<Vec<_>>();
+        let error = assembler.push(changed[1].clone()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::RevisionConflict);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::client_document::config::AuthorId;
+use lib_ot::{
+    core::{DeltaIter, Interval, Operation},
+    rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+
+pub type SuggestionId = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    Insert,
+    Delete,
+}
+
+impl SuggestionKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SuggestionKind::Insert => "insert",
+            SuggestionKind::Delete => "delete",
+        }
+    }
+}
+
+/// A change that was made in suggestion mode and waits to be accepted or rejected. It's kept in
+/// the `suggestion` attribute of the suggested text, so it's synced like any other attribute.
+///
+/// The suggested inserts are normal text, while the suggested deletes are kept in the document as
+/// struck through text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub id: SuggestionId,
+    pub kind: SuggestionKind,
+    pub author_id: AuthorId,
+}
+
+impl Suggestion {
+    pub fn to_attribute(&self) -> RichTextAttribute {
+        RichTextAttribute::Suggestion(format!("{}:{}:{}", self.kind.as_str(), self.id, self.author_id))
+    }
+
+    pub fn from_attributes(attributes: &RichTextAttributes) -> Option<Self> {
+        let value = attributes.get(&RichTextAttributeKey::Suggestion)?.0.as_ref()?;
+        let mut parts = value.splitn(3, ':');
+        let kind = match parts.next()? {
+            "insert" => SuggestionKind::Insert,
+            "delete" => SuggestionKind::Delete,
+            _ => return None,
+        };
+        let id = parts.next()?.to_owned();
+        let author_id = parts.next().unwrap_or("").to_owned();
+        Some(Suggestion { id, kind, author_id })
+    }
+}
+
+/// Turns the `delta` that is about to be composed into `document` into a suggestion: the inserted
+/// text is tagged as a suggested insert and the deletes become struck through retains.
+///
+/// Deleting text that is a pending insert suggestion of the same author removes it for real.
+pub fn suggest_delta(document: &RichTextDelta, delta: &RichTextDelta, id: &str, author_id: &str) -> RichTextDelta {
+    let insert_attribute = Suggestion {
+        id: id.to_owned(),
+        kind: SuggestionKind::Insert,
+        author_id: author_id.to_owned(),
+    }
+    .to_attribute();
+    let mut delete_attributes = RichTextAttributes::from(RichTextAttribute::StrikeThrough(true));
+    delete_attributes.add(
+        Suggestion {
+            id: id.to_owned(),
+            kind: SuggestionKind::Delete,
+            author_id: author_id.to_owned(),
+        }
+        .to_attribute(),
+    );
+
+    let mut iter = DeltaIter::new(document);
+    let mut suggested = RichTextDelta::new();
+    for op in &delta.ops {
+        match op {
+            Operation::Insert(insert) => {
+                let mut attributes = insert.attributes.clone();
+                attributes.add(insert_attribute.clone());
+                suggested.insert(&insert.s, attributes);
+            }
+            Operation::Retain(retain) => {
+                let mut n = retain.n;
+                while n > 0 {
+                    let len = match iter.next_op_with_len(n) {
+                        None => n,
+                        Some(op) => op.len(),
+                    };
+                    suggested.retain(len, retain.attributes.clone());
+                    n -= len;
+                }
+            }
+            Operation::Delete(n) => {
+                let mut n = *n;
+                while n > 0 {
+                    let op = match iter.next_op_with_len(n) {
+                        None => break,
+                        Some(op) => op,
+                    };
+                    let is_own_insert = match Suggestion::from_attributes(&op.get_attributes()) {
+                        None => false,
+                        Some(suggestion) => {
+                            suggestion.kind == SuggestionKind::Insert && suggestion.author_id == author_id
+                        }
+                    };
+                    match is_own_insert {
+                        true => suggested.delete(op.len()),
+                        false => suggested.retain(op.len(), delete_attributes.clone()),
+                    }
+                    n -= op.len();
+                }
+            }
+        }
+    }
+    suggested
+}
+
+/// Returns the pending suggestions of `document`, each one with the range of the text it covers.
+pub fn suggestions(document: &RichTextDelta) -> Vec<(Interval, Suggestion)> {
+    let mut suggestions: Vec<(Interval, Suggestion)> = vec![];
+    let mut offset = 0;
+    for op in &document.ops {
+        let len = op.len();
+        if let Some(suggestion) = Suggestion::from_attributes(&op.get_attributes()) {
+            match suggestions.last_mut() {
+                Some((interval, last)) if interval.end == offset && last == &suggestion => interval.end += len,
+                _ => suggestions.push((Interval::new(offset, offset + len), suggestion)),
+            }
+        }
+        offset += len;
+    }
+    suggestions
+}
+
+/// Builds the delta that accepts or rejects the suggestion `id` of `document`. Returns `None` if
+/// the document doesn't contain the suggestion.
+pub fn resolve_suggestion(document: &RichTextDelta, id: &str, accept: bool) -> Option<RichTextDelta> {
+    let mut found = false;
+    let mut resolved = RichTextDelta::new();
+    for op in &document.ops {
+        let len = op.len();
+        let suggestion = match Suggestion::from_attributes(&op.get_attributes()) {
+            Some(suggestion) if suggestion.id == id => suggestion,
+            _ => {
+                resolved.retain(len, RichTextAttributes::default());
+                continue;
+            }
+        };
+
+        found = true;
+        match (suggestion.kind, accept) {
+            (SuggestionKind::Insert, false) | (SuggestionKind::Delete, true) => resolved.delete(len),
+            (SuggestionKind::Insert, true) => {
+                let mut attributes = RichTextAttributes::new();
+                attributes.delete(&RichTextAttributeKey::Suggestion);
+                resolved.retain(len, attributes);
+            }
+            (SuggestionKind::Delete, false) => {
+                let mut attributes = RichTextAttributes::new();
+                attributes.delete(&RichTextAttributeKey::Suggestion);
+                attributes.delete(&RichTextAttributeKey::StrikeThrough);
+                resolved.retain(len, attributes);
+            }
+        }
+    }
+
+    match found {
+        true => Some(resolved),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::suggestion::{resolve_suggestion, suggest_delta, suggestions, SuggestionKind};
+    use lib_ot::{
+        core::OperationTransformable,
+        rich_text::{RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    fn plain_text(delta: &RichTextDelta) -> String {
+        delta.apply("").unwrap()
+    }
+
+    #[test]
+    fn suggestion_accept_and_reject() {
+        let document = RichTextDeltaBuilder::new().insert("abc\n").build();
+        let delta = RichTextDeltaBuilder::new().retain(1).delete(1).insert("12").build();
+        let suggested = suggest_delta(&document, &delta, "1", "a");
+        let document = document.compose(&suggested).unwrap();
+        assert_eq!(plain_text(&document), "ab12c\n");
+
+        let pending = suggestions(&document);
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].1.kind, SuggestionKind::Delete);
+        assert_eq!(pending[1].1.kind, SuggestionKind::Insert);
+
+        let accepted = document
+            .compose(&resolve_suggestion(&document, "1", true).unwrap())
+            .unwrap();
+        assert_eq!(accepted.to_json(), r#"[{"insert":"a12c\n"}]"#);
+
+        let rejected = document
+            .compose(&resolve_suggestion(&document, "1", false).unwrap())
+            .unwrap();
+        assert_eq!(rejected.to_json(), r#"[{"insert":"abc\n"}]"#);
+        assert!(resolve_suggestion(&document, "2", true).is_none());
+    }
+
+    #[test]
+    fn suggestion_delete_own_insert() {
+        let document = RichTextDeltaBuilder::new().insert("\n").build();
+        let insert = RichTextDeltaBuilder::new().insert("ab").build();
+        let document = document.compose(&suggest_delta(&document, &insert, "1", "a")).unwrap();
+
+        // Another author can only suggest to delete the text, the author removes it for real.
+        let delete = RichTextDeltaBuilder::new().delete(1).build();
+        let other = document.compose(&suggest_delta(&document, &delete, "2", "b")).unwrap();
+        assert_eq!(plain_text(&other), "ab\n");
+        let own = document.compose(&suggest_delta(&document, &delete, "3", "a")).unwrap();
+        assert_eq!(plain_text(&own), "b\n");
+    }
+}
@@ -0,0 +1,206 @@
+use crate::util::line_intervals;
+use lib_ot::{
+    core::{count_utf16_code_units, Interval, Operation, NEW_LINE},
+    rich_text::{AttributeScope, RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes, RichTextDelta},
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeCoverage {
+    None,
+    Partial,
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeySummary {
+    len: usize,
+    // The value that all the text with the key shares, `None` if the values differ.
+    value: Option<RichTextAttributeValue>,
+}
+
+/// Tells, for each attribute, whether it applies to all, some or none of a range, e.g. to show a
+/// toolbar button as partially on. The inline attributes are counted on the text of the range and
+/// the block attributes on its lines.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttributeSummary {
+    inline_len: usize,
+    lines: usize,
+    keys: HashMap<RichTextAttributeKey, KeySummary>,
+}
+
+impl AttributeSummary {
+    pub fn coverage(&self, key: &RichTextAttributeKey) -> AttributeCoverage {
+        let total = match key.scope() {
+            AttributeScope::Block => self.lines,
+            _ => self.inline_len,
+        };
+        match self.keys.get(key) {
+            None => AttributeCoverage::None,
+            Some(summary) if summary.len >= total => AttributeCoverage::All,
+            Some(_) => AttributeCoverage::Partial,
+        }
+    }
+
+    /// Returns the value of `key` if it applies to all the range with the same value, e.g. the
+    /// font size of the selection.
+    pub fn value(&self, key: &RichTextAttributeKey) -> Option<&RichTextAttributeValue> {
+        match self.coverage(key) {
+            AttributeCoverage::All => self.keys.get(key)?.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    // The attributes that were set at a collapsed selection override the ones of the text.
+    pub(crate) fn apply_pending(&mut self, attributes: &RichTextAttributes) {
+        for (key, value) in attributes.iter() {
+            match value.0 {
+                None => {
+                    self.keys.remove(key);
+                }
+                Some(_) => {
+                    let summary = KeySummary {
+                        len: self.inline_len,
+                        value: Some(value.clone()),
+                    };
+                    self.keys.insert(key.clone(), summary);
+                }
+            }
+        }
+    }
+
+    // The text without a value of a key has the default value of the document, so the key covers
+    // all the range. Its value is shared only if the text that has the key has the default too.
+    pub(crate) fn apply_defaults(&mut self, defaults: &RichTextAttributes) {
+        for (key, value) in defaults.iter() {
+            if value.0.is_none() {
+                continue;
+            }
+            let len = match key.scope() {
+                AttributeScope::Block => self.lines,
+                _ => self.inline_len,
+            };
+            let summary = self.keys.entry(key.clone()).or_insert_with(|| KeySummary {
+                len,
+                value: Some(value.clone()),
+            });
+            if summary.len < len && summary.value.as_ref() != Some(value) {
+                summary.value = None;
+            }
+            summary.len = len;
+        }
+    }
+
+    fn add(&mut self, attributes: &RichTextAttributes, len: usize, scope: AttributeScope) {
+        let is_block = scope == AttributeScope::Block;
+        for (key, value) in attributes.iter() {
+            if value.0.is_none() || (key.scope() == AttributeScope::Block) != is_block {
+                continue;
+            }
+            let summary = self.keys.entry(key.clone()).or_insert_with(|| KeySummary {
+                len: 0,
+                value: Some(value.clone()),
+            });
+            summary.len += len;
+            if summary.value.as_ref() != Some(value) {
+                summary.value = None;
+            }
+        }
+    }
+}
+
+/// Summarizes the attributes of `interval`. The attributes of an empty interval are the ones of
+/// the character before it, which the text typed there takes.
+pub fn attributes_in(delta: &RichTextDelta, interval: Interval) -> AttributeSummary {
+    let mut summary = AttributeSummary::default();
+    let text_interval = match interval.is_empty() && interval.start > 0 {
+        true => Interval::new(interval.start - 1, interval.start),
+        false => interval,
+    };
+    for slice in delta.ops_in(text_interval) {
+        let insert = match slice.op {
+            Operation::Insert(insert) => insert,
+            _ => continue,
+        };
+        let text = match slice.is_whole() {
+            true => insert.s.to_string(),
+            false => insert.s.sub_str(slice.interval).unwrap_or_default(),
+        };
+        let len = count_utf16_code_units(&text.replace(NEW_LINE, ""));
+        summary.inline_len += len;
+        summary.add(&insert.attributes, len, AttributeScope::Inline);
+    }
+
+    for line in line_intervals(delta, interval) {
+        summary.lines += 1;
+        if let Some(slice) = delta.ops_in(Interval::new(line.end - 1, line.end)).next() {
+            summary.add(&slice.op.get_attributes(), 1, AttributeScope::Block);
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::summary::{attributes_in, AttributeCoverage};
+    use lib_ot::{
+        core::Interval,
+        rich_text::{AttributeBuilder, RichTextAttribute, RichTextAttributeKey, RichTextAttributeValue, RichTextDelta},
+    };
+
+    #[test]
+    fn attribute_summary_tri_state() {
+        let mut delta = RichTextDelta::default();
+        let bold = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Bold(true))
+            .add_attr(RichTextAttribute::Size(12))
+            .build();
+        delta.insert("12", bold);
+        delta.insert("34", RichTextAttribute::Size(12).into());
+        delta.insert("\n", RichTextAttribute::Header(1).into());
+        delta.insert("56", RichTextAttribute::Size(14).into());
+        delta.insert("\n", Default::default());
+
+        let summary = attributes_in(&delta, Interval::new(0, 5));
+        assert_eq!(
+            summary.coverage(&RichTextAttributeKey::Bold),
+            AttributeCoverage::Partial
+        );
+        assert_eq!(summary.coverage(&RichTextAttributeKey::Size), AttributeCoverage::All);
+        assert_eq!(
+            summary.value(&RichTextAttributeKey::Size),
+            Some(&RichTextAttributeValue::from(12_usize))
+        );
+        assert_eq!(summary.coverage(&RichTextAttributeKey::Header), AttributeCoverage::All);
+        assert_eq!(summary.coverage(&RichTextAttributeKey::Italic), AttributeCoverage::None);
+
+        let summary = attributes_in(&delta, Interval::new(3, 7));
+        assert_eq!(summary.coverage(&RichTextAttributeKey::Size), AttributeCoverage::All);
+        assert_eq!(summary.value(&RichTextAttributeKey::Size), None);
+        assert_eq!(
+            summary.coverage(&RichTextAttributeKey::Header),
+            AttributeCoverage::Partial
+        );
+
+        // The collapsed selection takes the attributes of the character before it.
+        let summary = attributes_in(&delta, Interval::new(2, 2));
+        assert_eq!(summary.coverage(&RichTextAttributeKey::Bold), AttributeCoverage::All);
+
+        // The default size is the size of the text that has none.
+        let mut summary = attributes_in(&delta, Interval::new(0, 7));
+        summary.apply_defaults(&RichTextAttribute::Size(12).into());
+        assert_eq!(summary.coverage(&RichTextAttributeKey::Size), AttributeCoverage::All);
+        assert_eq!(summary.value(&RichTextAttributeKey::Size), None);
+        let mut summary = attributes_in(&delta, Interval::new(0, 5));
+        summary.apply_defaults(&RichTextAttribute::Size(12).into());
+        summary.apply_defaults(&RichTextAttribute::LineHeight(150).into());
+        assert_eq!(
+            summary.value(&RichTextAttributeKey::Size),
+            Some(&RichTextAttributeValue::from(12_usize))
+        );
+        assert_eq!(
+            summary.value(&RichTextAttributeKey::LineHeight),
+            Some(&RichTextAttributeValue::from(150_usize))
+        );
+    }
+}
@@ -0,0 +1,424 @@
+use crate::{
+    client_document::{clock::ClockRef, DocumentConfig},
+    entities::revision::Revision,
+    errors::CollaborateError,
+    util::content_md5,
+};
+use lib_ot::{
+    core::Priority,
+    rich_text::{ComposeConfig, RichTextDelta},
+};
+use std::collections::VecDeque;
+
+/// How a client applies the changes of its user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// The changes are applied at once, and rebased on the revisions of the others as they arrive.
+    Optimistic,
+    /// The changes are sent to the server as intents, and they are only applied once they come
+    /// back in the revisions of the server, in the order that the server applied them. The user
+    /// only ever sees the document of the server, e.g. for a shared counter that every client
+    /// must agree on.
+    Authoritative,
+}
+
+impl std::default::Default for SyncMode {
+    fn default() -> Self {
+        SyncMode::Optimistic
+    }
+}
+
+/// The client side of the revision sync protocol, in the [SyncMode] of the document. The changes
+/// of the user wait until the server confirms them, the first one is sent as the revision after
+/// the one of the server that the client has, and the others once it's confirmed. The server
+/// confirms a change by sending its revision back, attributed to the user and the device of the
+/// client.
+pub struct ClientSync {
+    doc_id: String,
+    user_id: String,
+    device_id: String,
+    clock: ClockRef,
+    mode: SyncMode,
+    compose: ComposeConfig,
+    /// The document at `server_rev_id`, without the pending changes.
+    synced: RichTextDelta,
+    server_rev_id: i64,
+    /// The changes that the server hasn't confirmed yet, each one made after the ones before it.
+    pending: VecDeque<RichTextDelta>,
+    document: RichTextDelta,
+}
+
+impl ClientSync {
+    /// Starts from `document`, the document of the server at `rev_id`. The changes are attributed
+    /// to the author of `config` and made in its sync mode.
+    pub fn new(config: &DocumentConfig, document: RichTextDelta, rev_id: i64) -> Self {
+        Self {
+            doc_id: config.doc_id.clone(),
+            user_id: config.author_id.clone(),
+            device_id: config.device_id.clone(),
+            clock: config.clock.clone(),
+            mode: config.sync_mode,
+            compose: config.compose.clone(),
+            synced: document.clone(),
+            server_rev_id: rev_id,
+            pending: VecDeque::new(),
+            document,
+        }
+    }
+
+    pub fn mode(&self) -> SyncMode {
+        self.mode
+    }
+
+    /// The document that the user sees. In the authoritative mode, it's the one of the server.
+    pub fn document(&self) -> &RichTextDelta {
+        &self.document
+    }
+
+    /// The rev_id of the last revision of the server that the client has.
+    pub fn server_rev_id(&self) -> i64 {
+        self.server_rev_id
+    }
+
+    /// Whether there are changes that the server hasn't confirmed yet.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Makes a change of the user, `delta` is a change of [ClientSync::document]. Returns the delta
+    /// to compose into the document that the user sees: `delta` itself in the optimistic mode, and
+    /// none in the authoritative mode, where the change waits for the server.
+    pub fn edit(&mut self, delta: RichTextDelta) -> Result<Option<RichTextDelta>, CollaborateError> {
+        match self.mode {
+            SyncMode::Optimistic => {
+                self.document = self.document.compose_with(&delta, &self.compose)?;
+                self.pending.push_back(delta.clone());
+                Ok(Some(delta))
+            }
+            SyncMode::Authoritative => {
+                // The change is made on the document of the server, it goes after the changes
+                // that are waiting.
+                let mut intent = delta;
+                for pending in &self.pending {
+                    let (intent_prime, _) = intent.transform_with(pending, Priority::Right, &self.compose)?;
+                    intent = intent_prime;
+                }
+                self.pending.push_back(intent);
+                Ok(None)
+            }
+        }
+    }
+
+    /// The revision to send to the server, the first pending change, made at the time of the clock
+    /// of the config.
+    pub fn next_revision(&self) -> Option<Revision> {
+        let delta = self.pending.front()?;
+        let md5 = content_md5(&self.synced.compose_with(delta, &self.compose).ok()?);
+        Some(
+            Revision::new(
+                &self.doc_id,
+                self.server_rev_id,
+                self.server_rev_id + 1,
+                delta.to_bytes(),
+                &self.user_id,
+                md5,
+            )
+            .with_author(&self.user_id, &self.device_id)
+            .with_timestamp(self.clock.now_millis()),
+        )
+    }
+
+    /// Receives the revisions of the server. The ones the client has are skipped, and the ones
+    /// after a missing revision wait for it, which comes with the next ping. Returns the deltas
+    /// composed into the document that the user sees, in order.
+    pub fn receive_revisions(&mut self, mut revisions: Vec<Revision>) -> Result<Vec<RichTextDelta>, CollaborateError> {
+        revisions.sort_by_key(|revision| revision.rev_id);
+        let mut applied = vec![];
+        for revision in revisions {
+            if revision.rev_id <= self.server_rev_id {
+                continue;
+            }
+            if revision.rev_id != self.server_rev_id + 1 {
+                break;
+            }
+
+            let delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+            // The changes of the others may have the same content, e.g. the same text typed at
+            // the same place, so the revision is matched on who made it.
+            let is_own =
+                self.has_pending() && revision.author_id == self.user_id && revision.device_id == self.device_id;
+            let visible = match is_own {
+                true => {
+                    let _ = self.pending.pop_front();
+                    match self.mode {
+                        SyncMode::Optimistic => None,
+                        SyncMode::Authoritative => Some(delta.clone()),
+                    }
+                }
+                false => {
+                    let mut server_delta = delta.clone();
+                    for local in self.pending.iter_mut() {
+                        // The server gives the priority to its own revisions.
+                        let (local_prime, server_prime) =
+                            local.transform_with(&server_delta, Priority::Right, &self.compose)?;
+                        *local = local_prime;
+                        server_delta = server_prime;
+                    }
+                    match self.mode {
+                        SyncMode::Optimistic => Some(server_delta),
+                        SyncMode::Authoritative => Some(delta.clone()),
+                    }
+                }
+            };
+            if let Some(visible) = visible {
+                self.document = self.document.compose_with(&visible, &self.compose)?;
+                applied.push(visible);
+            }
+            self.synced = self.synced.compose_with(&delta, &self.compose)?;
+            self.server_rev_id = revision.rev_id;
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        sync::{ClientSync, SyncMode},
+        ClientDocument, DocumentConfigBuilder, NewlineDoc,
+    };
+    use lib_ot::{
+        core::{Interval, OpBuilder},
+        rich_text::{
+            AttributeBuilder, AttributeComposeRule, AttributeConflictPolicy, ComposeConfig, RichTextAttribute,
+            RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes, RichTextDelta, RichTextDeltaBuilder,
+        },
+    };
+    use std::sync::Arc;
+
+    fn client(author: &str, mode: SyncMode) -> ClientSync {
+        let config = DocumentConfigBuilder::new("doc")
+            .author(author, author)
+            .sync_mode(mode)
+            .build();
+        ClientSync::new(&config, RichTextDeltaBuilder::new().insert("0\n").build(), 1)
+    }
+
+    // Inserts `text` at `index` of a document of `len`.
+    fn insert(index: usize, text: &str, len: usize) -> RichTextDelta {
+        RichTextDeltaBuilder::new()
+            .retain(index)
+            .insert(text)
+            .retain(len - index)
+            .build()
+    }
+
+    #[test]
+    fn authoritative_changes_wait_for_the_server() {
+        let mut optimistic = client("a", SyncMode::Optimistic);
+        let mut authoritative = client("b", SyncMode::Authoritative);
+        assert!(optimistic.edit(insert(0, "a", 2)).unwrap().is_some());
+        assert_eq!(authoritative.edit(insert(0, "b", 2)).unwrap(), None);
+        assert_eq!(authoritative.edit(insert(0, "!", 2)).unwrap(), None);
+        assert_eq!(optimistic.document().to_json(), r#"[{"insert":"a0\n"}]"#);
+        assert_eq!(authoritative.document().to_json(), r#"[{"insert":"0\n"}]"#);
+
+        // The server applies the change of "a" first, then the intents of "b" rebased on it.
+        let first = optimistic.next_revision().unwrap();
+        assert_eq!(optimistic.receive_revisions(vec![first.clone()]).unwrap(), vec![]);
+        assert_eq!(authoritative.receive_revisions(vec![first]).unwrap().len(), 1);
+        assert_eq!(authoritative.document().to_json(), r#"[{"insert":"a0\n"}]"#);
+
+        for _ in 0..2 {
+            let revision = authoritative.next_revision().unwrap();
+            authoritative.receive_revisions(vec![revision.clone()]).unwrap();
+            optimistic.receive_revisions(vec![revision]).unwrap();
+        }
+        assert!(!authoritative.has_pending());
+        assert_eq!(authoritative.server_rev_id(), 4);
+        assert_eq!(authoritative.document().to_json(), r#"[{"insert":"ab!0\n"}]"#);
+        assert_eq!(optimistic.document().to_json(), authoritative.document().to_json());
+    }
+
+    #[test]
+    fn same_change_of_another_client_is_not_confirmation() {
+        let mut a = client("a", SyncMode::Optimistic);
+        let mut b = client("b", SyncMode::Optimistic);
+        a.edit(insert(0, "x", 2)).unwrap();
+        b.edit(insert(0, "x", 2)).unwrap();
+
+        // The server applies the change of "a" first, "b" made the same change.
+        let first = a.next_revision().unwrap();
+        a.receive_revisions(vec![first.clone()]).unwrap();
+        assert_eq!(b.receive_revisions(vec![first]).unwrap().len(), 1);
+        assert!(b.has_pending());
+
+        let second = b.next_revision().unwrap();
+        b.receive_revisions(vec![second.clone()]).unwrap();
+        a.receive_revisions(vec![second]).unwrap();
+        assert!(!b.has_pending());
+        assert_eq!(a.document().to_json(), r#"[{"insert":"xx0\n"}]"#);
+        assert_eq!(b.document().to_json(), a.document().to_json());
+    }
+
+    #[test]
+    fn concurrent_formats_follow_the_compose_config_of_the_clients() {
+        let color = |color: &str| {
+            RichTextDeltaBuilder::new()
+                .retain_with_attributes(1, RichTextAttribute::Color(color.to_owned()).into())
+                .retain(1)
+                .build()
+        };
+        let policies = vec![
+            (AttributeConflictPolicy::LastWriterWins, Some("blue")),
+            (AttributeConflictPolicy::MergeNonOverlapping, None),
+        ];
+        for (policy, expected) in policies {
+            let client = |author: &str| {
+                let config = DocumentConfigBuilder::new("doc")
+                    .author(author, author)
+                    .compose(ComposeConfig::new().with_conflict_policy(policy))
+                    .build();
+                ClientSync::new(&config, RichTextDeltaBuilder::new().insert("0\n").build(), 1)
+            };
+            let mut a = client("a");
+            let mut b = client("b");
+            a.edit(color("red")).unwrap();
+            b.edit(color("blue")).unwrap();
+
+            // The server applies the change of "a" first, the one of "b" is written last.
+            let first = a.next_revision().unwrap();
+            a.receive_revisions(vec![first.clone()]).unwrap();
+            b.receive_revisions(vec![first]).unwrap();
+            let second = b.next_revision().unwrap();
+            b.receive_revisions(vec![second.clone()]).unwrap();
+            a.receive_revisions(vec![second]).unwrap();
+
+            let expected = match expected {
+                Some(color) => RichTextDeltaBuilder::new()
+                    .insert_with_attributes("0", RichTextAttribute::Color(color.to_owned()).into())
+                    .insert("\n")
+                    .build(),
+                None => RichTextDeltaBuilder::new().insert("0\n").build(),
+            };
+            assert_eq!(a.document(), &expected, "{:?}", policy);
+            assert_eq!(b.document(), &expected, "{:?}", policy);
+        }
+    }
+
+    // The font clears the background of the text.
+    struct FontClearsBackground();
+    impl AttributeComposeRule for FontClearsBackground {
+        fn compose(
+            &self,
+            attributes: &mut RichTextAttributes,
+            key: &RichTextAttributeKey,
+            value: &RichTextAttributeValue,
+        ) {
+            attributes.insert(key.clone(), value.clone());
+            attributes.delete(&RichTextAttributeKey::Background);
+        }
+    }
+
+    #[test]
+    fn changes_are_composed_by_the_rules_of_the_compose_config() {
+        let document = RichTextDeltaBuilder::new()
+            .insert_with_attributes("0", RichTextAttribute::Background("red".to_owned()).into())
+            .insert("\n")
+            .build();
+        let font = RichTextDeltaBuilder::new()
+            .retain_with_attributes(1, RichTextAttribute::Font("serif").into())
+            .retain(1)
+            .build();
+        let compose = ComposeConfig::new().with_rule(RichTextAttributeKey::Font, Arc::new(FontClearsBackground()));
+        let config = DocumentConfigBuilder::new("doc")
+            .author("a", "a")
+            .compose(compose)
+            .build();
+        let mut with_rule = ClientSync::new(&config, document.clone(), 1);
+        let config = DocumentConfigBuilder::new("doc").author("b", "b").build();
+        let mut without_rule = ClientSync::new(&config, document, 1);
+
+        with_rule.edit(font.clone()).unwrap();
+        without_rule.edit(font).unwrap();
+        assert_eq!(
+            with_rule.document(),
+            &RichTextDeltaBuilder::new()
+                .insert_with_attributes("0", RichTextAttribute::Font("serif").into())
+                .insert("\n")
+                .build()
+        );
+        let both = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Background("red".to_owned()))
+            .add_attr(RichTextAttribute::Font("serif"))
+            .build();
+        assert_eq!(
+            without_rule.document(),
+            &RichTextDeltaBuilder::new()
+                .insert_with_attributes("0", both)
+                .insert("\n")
+                .build()
+        );
+    }
+
+    #[test]
+    fn authoritative_document_applies_its_changes_from_the_server() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(
+            DocumentConfigBuilder::new("doc")
+                .author("a", "phone")
+                .sync_mode(SyncMode::Authoritative)
+                .build(),
+        );
+        let intent = document.insert(0, "a").unwrap();
+        document.insert(0, "b").unwrap();
+        assert_eq!(document.to_plain_string(), "\n");
+        assert_eq!(document.intents().len(), 2);
+
+        // The change of another client goes before the intents.
+        let mut server = ClientDocument::new::<NewlineDoc>();
+        server.insert(0, "x").unwrap();
+        let foreign = server.revision(server.rev_id()).unwrap();
+        document.compose_remote_revision(&foreign).unwrap();
+        assert_ne!(document.intents()[0], intent);
+
+        let applied = document.acknowledge_intent().unwrap();
+        assert_eq!(document.to_plain_string(), "xa\n");
+        assert_eq!(applied, insert(1, "a", 2));
+        document.acknowledge_intent().unwrap();
+        assert_eq!(document.to_plain_string(), "xab\n");
+        assert!(document.acknowledge_intent().is_err());
+    }
+
+    #[test]
+    fn bulk_changes_follow_the_sync_and_the_suggestion_modes() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_config(
+            DocumentConfigBuilder::new("doc")
+                .author("a", "phone")
+                .sync_mode(SyncMode::Authoritative)
+                .build(),
+        );
+        document.bulk_insert(0, "pasted").unwrap();
+        let mut progress = vec![];
+        document
+            .apply_chunked(vec![OpBuilder::insert("imported").build()], |offset| {
+                progress.push(offset)
+            })
+            .unwrap();
+        assert_eq!(progress, vec![8]);
+        assert_eq!(document.to_plain_string(), "\n");
+        assert_eq!(document.intents().len(), 2);
+        document.acknowledge_intent().unwrap();
+        document.acknowledge_intent().unwrap();
+        assert_eq!(document.to_plain_string(), "pastedimported\n");
+
+        // The text pasted in the suggestion mode is suggested.
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_suggestion_mode(true);
+        document.bulk_insert(0, "pasted").unwrap();
+        let suggestions = document.suggestions();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].0, Interval::new(0, 6));
+    }
+}
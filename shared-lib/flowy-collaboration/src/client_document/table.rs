@@ -0,0 +1,281 @@
+use crate::errors::{internal_error, CollaborateError};
+use lib_ot::{
+    core::{Embed, OperationTransformable},
+    rich_text::RichTextDelta,
+};
+use serde::{Deserialize, Serialize};
+
+/// The kind of the embeds that are tables.
+pub const TABLE_EMBED: &str = "table";
+
+/// A table of a fixed number of columns, whose cells hold rich text. The table is kept in the
+/// document as one embed, `{"insert":{"table":{"columns":2,"cells":[[[...],[...]]]}}}`, and is
+/// changed with [TableOp]s, which address the cells by their row and their column.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    columns: usize,
+    // The cells row by row, each row has `columns` cells.
+    cells: Vec<Vec<RichTextDelta>>,
+}
+
+impl Table {
+    pub fn new(rows: usize, columns: usize) -> Self {
+        Self {
+            columns,
+            cells: vec![vec![RichTextDelta::new(); columns]; rows],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn cell(&self, row: usize, column: usize) -> Option<&RichTextDelta> {
+        self.cells.get(row)?.get(column)
+    }
+
+    pub fn to_embed(&self) -> Embed {
+        Embed::new(TABLE_EMBED, serde_json::to_value(self).unwrap_or_default())
+    }
+
+    pub fn from_embed(embed: &Embed) -> Option<Self> {
+        if embed.kind != TABLE_EMBED {
+            return None;
+        }
+        let table: Table = serde_json::from_value(embed.data.to_value()).ok()?;
+        match table.cells.iter().all(|row| row.len() == table.columns) {
+            true => Some(table),
+            false => None,
+        }
+    }
+
+    pub fn apply(&mut self, op: &TableOp) -> Result<(), CollaborateError> {
+        let rows = self.rows();
+        let columns = self.columns;
+        let out_of_bound =
+            || CollaborateError::out_of_bound().context(format!("{:?} in a {}x{} table", op, rows, columns));
+        match op {
+            TableOp::EditCell { row, column, delta } => {
+                let cell = self
+                    .cells
+                    .get_mut(*row)
+                    .and_then(|cells| cells.get_mut(*column))
+                    .ok_or_else(out_of_bound)?;
+                *cell = cell.compose(delta).map_err(internal_error)?;
+            }
+            TableOp::InsertRow(row) if *row <= rows => self.cells.insert(*row, vec![RichTextDelta::new(); columns]),
+            TableOp::DeleteRow(row) if *row < rows => {
+                self.cells.remove(*row);
+            }
+            TableOp::InsertColumn(column) if *column <= columns => {
+                self.cells
+                    .iter_mut()
+                    .for_each(|cells| cells.insert(*column, RichTextDelta::new()));
+                self.columns += 1;
+            }
+            TableOp::DeleteColumn(column) if *column < columns => {
+                self.cells.iter_mut().for_each(|cells| {
+                    cells.remove(*column);
+                });
+                self.columns -= 1;
+            }
+            _ => return Err(out_of_bound()),
+        }
+        Ok(())
+    }
+}
+
+/// A change of a [Table].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableOp {
+    /// Composes `delta` into the text of the cell.
+    EditCell {
+        row: usize,
+        column: usize,
+        delta: RichTextDelta,
+    },
+    /// Inserts an empty row before `row`, or after the last row if it's the number of rows.
+    InsertRow(usize),
+    DeleteRow(usize),
+    /// Inserts an empty column before `column`, or after the last column if it's the number of
+    /// columns.
+    InsertColumn(usize),
+    DeleteColumn(usize),
+}
+
+/// Transforms two changes made on the same table, the same as [OperationTransformable::transform]:
+/// the table that `a` and then `b'` are applied to is the one that `b` and then `a'` are applied
+/// to. The edits of different cells don't affect each other, the edits of the same cell are
+/// transformed like the deltas of the document. A change is dropped if the row or the column it
+/// addresses was deleted by the other, and `a` goes first when both insert at the same place.
+pub fn transform(a: &TableOp, b: &TableOp) -> Result<(Option<TableOp>, Option<TableOp>), CollaborateError> {
+    if let (
+        TableOp::EditCell { row, column, delta },
+        TableOp::EditCell {
+            row: other_row,
+            column: other_column,
+            delta: other_delta,
+        },
+    ) = (a, b)
+    {
+        if row == other_row && column == other_column {
+            let (a_prime, b_prime) = delta.transform(other_delta).map_err(internal_error)?;
+            let edit = |delta| TableOp::EditCell {
+                row: *row,
+                column: *column,
+                delta,
+            };
+            return Ok((Some(edit(a_prime)), Some(edit(b_prime))));
+        }
+    }
+    Ok((transform_op(a, b, false), transform_op(b, a, true)))
+}
+
+// Moves `op` over `other`, which was applied before it. `after` tells whether an insert at the same
+// place as the insert of `other` goes after it.
+fn transform_op(op: &TableOp, other: &TableOp, after: bool) -> Option<TableOp> {
+    match other {
+        TableOp::EditCell { .. } => Some(op.clone()),
+        TableOp::InsertRow(at) => map_rows(op, |row, is_insert| {
+            match row > *at || (row == *at && (!is_insert || after)) {
+                true => Some(row + 1),
+                false => Some(row),
+            }
+        }),
+        TableOp::DeleteRow(at) => map_rows(op, |row, is_insert| match row.cmp(at) {
+            std::cmp::Ordering::Less => Some(row),
+            std::cmp::Ordering::Equal if is_insert => Some(row),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(row - 1),
+        }),
+        TableOp::InsertColumn(at) => map_columns(op, |column, is_insert| {
+            match column > *at || (column == *at && (!is_insert || after)) {
+                true => Some(column + 1),
+                false => Some(column),
+            }
+        }),
+        TableOp::DeleteColumn(at) => map_columns(op, |column, is_insert| match column.cmp(at) {
+            std::cmp::Ordering::Less => Some(column),
+            std::cmp::Ordering::Equal if is_insert => Some(column),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(column - 1),
+        }),
+    }
+}
+
+// Maps the row that `op` addresses with `f`, which is told whether `op` inserts the row. The op is
+// dropped if `f` returns `None`.
+fn map_rows<F: Fn(usize, bool) -> Option<usize>>(op: &TableOp, f: F) -> Option<TableOp> {
+    let op = match op {
+        TableOp::EditCell { row, column, delta } => TableOp::EditCell {
+            row: f(*row, false)?,
+            column: *column,
+            delta: delta.clone(),
+        },
+        TableOp::InsertRow(row) => TableOp::InsertRow(f(*row, true)?),
+        TableOp::DeleteRow(row) => TableOp::DeleteRow(f(*row, false)?),
+        op => op.clone(),
+    };
+    Some(op)
+}
+
+fn map_columns<F: Fn(usize, bool) -> Option<usize>>(op: &TableOp, f: F) -> Option<TableOp> {
+    let op = match op {
+        TableOp::EditCell { row, column, delta } => TableOp::EditCell {
+            row: *row,
+            column: f(*column, false)?,
+            delta: delta.clone(),
+        },
+        TableOp::InsertColumn(column) => TableOp::InsertColumn(f(*column, true)?),
+        TableOp::DeleteColumn(column) => TableOp::DeleteColumn(f(*column, false)?),
+        op => op.clone(),
+    };
+    Some(op)
+}
+
+/// Returns the table at `index` of `document`, if there is one.
+pub fn table_at(document: &RichTextDelta, index: usize) -> Option<Table> {
+    let mut offset = 0;
+    for op in &document.ops {
+        if offset == index {
+            return op.get_embed().and_then(Table::from_embed);
+        }
+        offset += op.len();
+        if offset > index {
+            return None;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::table::{transform, Table, TableOp};
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+
+    fn edit(row: usize, column: usize, text: &str) -> TableOp {
+        TableOp::EditCell {
+            row,
+            column,
+            delta: RichTextDeltaBuilder::new().insert(text).build(),
+        }
+    }
+
+    // Applies `a` and `b` in both orders and checks that the tables are the same.
+    fn converge(table: &Table, a: TableOp, b: TableOp) -> Table {
+        let (a_prime, b_prime) = transform(&a, &b).unwrap();
+        let mut left = table.clone();
+        left.apply(&a).unwrap();
+        if let Some(b_prime) = b_prime {
+            left.apply(&b_prime).unwrap();
+        }
+        let mut right = table.clone();
+        right.apply(&b).unwrap();
+        if let Some(a_prime) = a_prime {
+            right.apply(&a_prime).unwrap();
+        }
+        assert_eq!(left, right);
+        left
+    }
+
+    #[test]
+    fn table_embed() {
+        let mut table = Table::new(2, 2);
+        table.apply(&edit(1, 0, "a")).unwrap();
+        assert_eq!(Table::from_embed(&table.to_embed()), Some(table.clone()));
+        assert!(table.apply(&edit(2, 0, "a")).is_err());
+        assert!(table.apply(&TableOp::DeleteColumn(2)).is_err());
+    }
+
+    #[test]
+    fn table_transform_converges() {
+        let table = Table::new(2, 2);
+        let text = |table: &Table, row, column| table.cell(row, column).unwrap().apply("").unwrap();
+
+        let result = converge(&table, edit(0, 0, "a"), edit(1, 1, "b"));
+        assert_eq!(
+            (text(&result, 0, 0), text(&result, 1, 1)),
+            ("a".to_owned(), "b".to_owned())
+        );
+
+        let result = converge(&table, edit(0, 1, "a"), edit(0, 1, "b"));
+        assert_eq!(text(&result, 0, 1), "ab");
+
+        let result = converge(&table, TableOp::InsertRow(0), edit(0, 1, "b"));
+        assert_eq!((result.rows(), text(&result, 1, 1)), (3, "b".to_owned()));
+
+        let result = converge(&table, edit(1, 1, "a"), TableOp::DeleteColumn(1));
+        assert_eq!((result.columns(), text(&result, 1, 0)), (1, "".to_owned()));
+
+        let result = converge(&table, TableOp::InsertColumn(1), TableOp::InsertColumn(1));
+        assert_eq!(result.columns(), 4);
+        let result = converge(&table, TableOp::DeleteRow(0), TableOp::DeleteRow(0));
+        assert_eq!(result.rows(), 1);
+        let result = converge(&table, TableOp::DeleteRow(1), TableOp::InsertRow(1));
+        assert_eq!(result.rows(), 2);
+    }
+}
@@ -0,0 +1,154 @@
+use lib_ot::{
+    core::{Embed, Operation},
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// The kind of the embeds that are placeholders.
+pub const PLACEHOLDER_EMBED: &str = "placeholder";
+
+/// The placeholder is kept in the template as `{"insert":{"placeholder":{"name":"title"}}}`.
+pub fn placeholder_embed(name: &str) -> Embed {
+    Embed::new(PLACEHOLDER_EMBED, json!({ "name": name }))
+}
+
+pub fn placeholder_name(embed: &Embed) -> Option<String> {
+    if embed.kind != PLACEHOLDER_EMBED {
+        return None;
+    }
+    embed.data.to_value().get("name")?.as_str().map(|name| name.to_owned())
+}
+
+/// The content of a new page, with placeholders for the values that are only known when the page
+/// is created, e.g. its title or the date. A placeholder is an embed, so it's one character long
+/// and has the attributes of the text it's in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    delta: RichTextDelta,
+}
+
+impl Template {
+    /// Creates the template from `delta`, in which the placeholders are either embeds, see
+    /// [placeholder_embed], or written as `{{name}}`. The written ones are turned into embeds with
+    /// the attributes of their text.
+    pub fn new(delta: &RichTextDelta) -> Self {
+        let mut template = RichTextDelta::new();
+        for op in &delta.ops {
+            let insert = match op {
+                Operation::Insert(insert) => insert,
+                _ => continue,
+            };
+            match &insert.embed {
+                Some(embed) => template.insert_embed(embed.clone(), insert.attributes.clone()),
+                None => insert_text(&mut template, &insert.s, &insert.attributes),
+            }
+        }
+        Self { delta: template }
+    }
+
+    pub fn delta(&self) -> &RichTextDelta {
+        &self.delta
+    }
+
+    /// Returns the names of the placeholders, in the order of their first use.
+    pub fn placeholders(&self) -> Vec<String> {
+        let mut names = vec![];
+        for name in self
+            .delta
+            .ops
+            .iter()
+            .filter_map(|op| op.get_embed())
+            .filter_map(placeholder_name)
+        {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Returns the content of the template with the placeholders replaced by their `values`. Each
+    /// value is inserted as text with the attributes of its placeholder. The placeholders without a
+    /// value are kept, for the user to fill in.
+    pub fn instantiate(&self, values: &HashMap<String, String>) -> RichTextDelta {
+        let mut delta = RichTextDelta::new();
+        for op in &self.delta.ops {
+            let insert = match op {
+                Operation::Insert(insert) => insert,
+                _ => continue,
+            };
+            let value = insert
+                .embed
+                .as_ref()
+                .and_then(placeholder_name)
+                .and_then(|name| values.get(&name));
+            match (value, &insert.embed) {
+                (Some(value), _) => delta.insert(value, insert.attributes.clone()),
+                (None, Some(embed)) => delta.insert_embed(embed.clone(), insert.attributes.clone()),
+                (None, None) => delta.insert(&insert.s, insert.attributes.clone()),
+            }
+        }
+        delta
+    }
+}
+
+fn insert_text(delta: &mut RichTextDelta, mut s: &str, attributes: &RichTextAttributes) {
+    while let Some((before, name, after)) = split_placeholder(s) {
+        delta.insert(before, attributes.clone());
+        delta.insert_embed(placeholder_embed(name), attributes.clone());
+        s = after;
+    }
+    delta.insert(s, attributes.clone());
+}
+
+/// Splits `s` around its first `{{name}}`, whose name is made of letters, digits and underscores.
+fn split_placeholder(s: &str) -> Option<(&str, &str, &str)> {
+    let mut offset = 0;
+    while let Some(start) = s[offset..].find("{{").map(|start| offset + start) {
+        let name_start = start + 2;
+        let name_len = s[name_start..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(s.len() - name_start);
+        let name_end = name_start + name_len;
+        if name_len > 0 && s[name_end..].starts_with("}}") {
+            return Some((&s[..start], &s[name_start..name_end], &s[name_end + 2..]));
+        }
+        offset = start + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::template::{placeholder_embed, Template};
+    use lib_ot::rich_text::{RichTextAttribute, RichTextDeltaBuilder};
+    use std::collections::HashMap;
+
+    #[test]
+    fn instantiate_keeps_attributes_of_placeholders() {
+        let delta = RichTextDeltaBuilder::new()
+            .insert_with_attributes("{{title}}", RichTextAttribute::Bold(true).into())
+            .insert_with_attributes("\n", RichTextAttribute::Header(1).into())
+            .insert("Created {{date}} by {{ author}}, ")
+            .insert_embed(placeholder_embed("title"))
+            .insert(" {{}}\n")
+            .build();
+        let template = Template::new(&delta);
+        assert_eq!(template.placeholders(), vec!["title", "date"]);
+        assert_eq!(template.delta().utf16_target_len, 35);
+
+        let mut values = HashMap::new();
+        values.insert("title".to_owned(), "Meeting".to_owned());
+        let instance = template.instantiate(&values);
+        let expected = RichTextDeltaBuilder::new()
+            .insert_with_attributes("Meeting", RichTextAttribute::Bold(true).into())
+            .insert_with_attributes("\n", RichTextAttribute::Header(1).into())
+            .insert("Created ")
+            .insert_embed(placeholder_embed("date"))
+            .insert(" by {{ author}}, Meeting {{}}\n")
+            .build();
+        assert_eq!(instance, expected);
+        assert_eq!(instance.utf16_target_len, 47);
+    }
+}
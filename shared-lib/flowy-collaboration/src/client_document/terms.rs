@@ -0,0 +1,195 @@
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+    segmentation::{words_in, Segmenter, TextSegment},
+    spell_check::changed_intervals,
+};
+use lib_ot::{
+    core::{Interval, Operation},
+    rich_text::RichTextDelta,
+};
+use std::sync::Arc;
+
+/// The terms, i.e. the words, that a change of the document removed and added. A search index,
+/// e.g. a tantivy or a SQLite FTS one, updates the terms of the document with them instead of
+/// indexing all of it again.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TermChanges {
+    /// The local revision of the document after the change.
+    pub rev_id: i64,
+    /// The terms that the change removed, at their positions before the change.
+    pub removed: Vec<TextSegment>,
+    /// The terms that the change added, at their positions after the change.
+    pub added: Vec<TextSegment>,
+}
+
+impl TermChanges {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Receives the terms that each change of the document removed and added, by any participant,
+/// see [TermTracker].
+pub trait TermIndexer: Send + Sync {
+    fn did_change_terms(&self, doc_id: &str, changes: &TermChanges);
+}
+
+/// Reports the terms that each change removed and added to the [TermIndexer] while it observes the
+/// document. The document must be indexed once before.
+pub struct TermTracker {
+    indexer: Arc<dyn TermIndexer>,
+    // The terms next to the change that is being applied, before it's composed into the document.
+    removed: Vec<TextSegment>,
+}
+
+impl TermTracker {
+    pub fn new(indexer: Arc<dyn TermIndexer>) -> Self {
+        Self {
+            indexer,
+            removed: vec![],
+        }
+    }
+}
+
+impl DocumentObserver for TermTracker {
+    fn did_reset(&mut self, document: &ObservedDocument, replaced: Option<&RichTextDelta>) {
+        let replaced = match replaced {
+            None => return,
+            Some(replaced) => replaced,
+        };
+        let whole = |delta: &RichTextDelta| [Interval::new(0, delta.utf16_target_len)];
+        let changes = TermChanges {
+            rev_id: document.rev_id,
+            removed: terms_touching(replaced, &whole(replaced), document.segmenter),
+            added: terms_touching(document.delta, &whole(document.delta), document.segmenter),
+        };
+        self.indexer.did_change_terms(document.doc_id, &changes);
+    }
+
+    fn will_change(&mut self, document: &ObservedDocument, delta: &RichTextDelta) {
+        self.removed = terms_touching(document.delta, &replaced_intervals(delta), document.segmenter);
+    }
+
+    fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent) {
+        let removed = std::mem::take(&mut self.removed);
+        let added = terms_touching(document.delta, &changed_intervals(&event.delta), document.segmenter);
+        let changes = diff_terms(&event.delta, event.rev_id, removed, added);
+        if !changes.is_empty() {
+            self.indexer.did_change_terms(document.doc_id, &changes);
+        }
+    }
+}
+
+/// Returns the intervals of the document that `delta` replaces, before it's composed into the
+/// document: the text it deletes and the positions where it inserts text.
+pub fn replaced_intervals(delta: &RichTextDelta) -> Vec<Interval> {
+    let mut intervals = vec![];
+    let mut offset = 0;
+    for op in &delta.ops {
+        match op {
+            Operation::Retain(retain) => offset += retain.n,
+            Operation::Insert(_) => intervals.push(Interval::new(offset, offset)),
+            Operation::Delete(n) => {
+                intervals.push(Interval::new(offset, offset + n));
+                offset += n;
+            }
+        }
+    }
+    intervals
+}
+
+/// Returns the words of `document` that overlap or touch `intervals`, in order. The words next to
+/// a change are included, because the change may have joined or split them.
+pub fn terms_touching(document: &RichTextDelta, intervals: &[Interval], segmenter: &dyn Segmenter) -> Vec<TextSegment> {
+    let mut terms: Vec<TextSegment> = vec![];
+    for interval in intervals {
+        let interval = Interval::new(interval.start.saturating_sub(1), interval.end + 1);
+        for term in words_in(document, interval, segmenter) {
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+    terms
+}
+
+/// Returns the changes of the terms from `removed`, the terms next to the change before `delta`,
+/// to `added`, the ones after it. The terms that `delta` only moved aren't changes.
+pub fn diff_terms(
+    delta: &RichTextDelta,
+    rev_id: i64,
+    removed: Vec<TextSegment>,
+    added: Vec<TextSegment>,
+) -> TermChanges {
+    let moved = |term: &TextSegment, added: &[TextSegment]| {
+        let start = delta.transform_position(term.interval.start, false);
+        added
+            .iter()
+            .position(|other| other.interval.start == start && other.text == term.text)
+    };
+    let mut changes = TermChanges {
+        rev_id,
+        removed: vec![],
+        added,
+    };
+    for term in removed {
+        match moved(&term, &changes.added) {
+            Some(i) => {
+                changes.added.remove(i);
+            }
+            None => changes.removed.push(term),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        segmentation::{TextSegment, UnicodeSegmenter},
+        spell_check::changed_intervals,
+        terms::{diff_terms, replaced_intervals, terms_touching},
+    };
+    use lib_ot::{
+        core::{Interval, OperationTransformable},
+        rich_text::RichTextDeltaBuilder,
+    };
+
+    #[test]
+    fn terms_of_change() {
+        let document = RichTextDeltaBuilder::new().insert("one two three\n").build();
+        // Type a space in the middle of "two" and delete "three".
+        let delta = RichTextDeltaBuilder::new()
+            .retain(5)
+            .insert(" ")
+            .retain(2)
+            .delete(6)
+            .build();
+        let new_document = document.compose(&delta).unwrap();
+
+        let removed = terms_touching(&document, &replaced_intervals(&delta), &UnicodeSegmenter());
+        let added = terms_touching(&new_document, &changed_intervals(&delta), &UnicodeSegmenter());
+        let changes = diff_terms(&delta, 1, removed, added);
+        let terms = |terms: &[TextSegment]| {
+            terms
+                .iter()
+                .map(|term| (term.text.clone(), term.interval))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            terms(&changes.removed),
+            vec![
+                ("two".to_owned(), Interval::new(4, 7)),
+                ("three".to_owned(), Interval::new(8, 13))
+            ]
+        );
+        assert_eq!(
+            terms(&changes.added),
+            vec![
+                ("t".to_owned(), Interval::new(4, 5)),
+                ("wo".to_owned(), Interval::new(6, 8))
+            ]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+};
+use lib_ot::{
+    core::{Interval, Operation},
+    rich_text::RichTextDelta,
+};
+use std::{ops::Range, time::Duration};
+
+/// The text that a revision deleted, kept to show it in the version history after the revisions
+/// were compacted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    /// The revision that deleted the text.
+    pub rev_id: i64,
+    pub author_id: String,
+    /// Where the text used to be in the current document. It's transformed by every delta composed
+    /// into the document after the deletion.
+    pub position: usize,
+    /// The deleted text, with its attributes.
+    pub deleted: RichTextDelta,
+    /// When the text was deleted, in milliseconds since the epoch.
+    pub deleted_at: i64,
+}
+
+/// How long the [Tombstone]s are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TombstoneRetention {
+    pub max_age: Duration,
+}
+
+impl std::default::Default for TombstoneRetention {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Keeps the text deleted from the document for the time of the [TombstoneRetention], while it
+/// observes the document. The text deleted before isn't in it.
+#[derive(Debug, Clone, Default)]
+pub struct TombstoneStore {
+    retention: TombstoneRetention,
+    tombstones: Vec<Tombstone>,
+}
+
+impl TombstoneStore {
+    pub fn new(retention: TombstoneRetention) -> Self {
+        Self {
+            retention,
+            tombstones: vec![],
+        }
+    }
+
+    pub fn retention(&self) -> TombstoneRetention {
+        self.retention
+    }
+
+    pub fn set_retention(&mut self, retention: TombstoneRetention) {
+        self.retention = retention;
+    }
+
+    /// Keeps the text deleted by the revision `rev_id`. `inverted` is the inverse of its delta, which
+    /// inserts the deleted text back into the document after the revision.
+    pub fn record(&mut self, rev_id: i64, author_id: &str, inverted: &RichTextDelta, deleted_at: i64) {
+        let mut position = 0;
+        for op in &inverted.ops {
+            match op {
+                Operation::Retain(retain) => position += retain.n,
+                Operation::Delete(n) => position += n,
+                Operation::Insert(_) => match self.tombstones.last_mut() {
+                    // The deletions next to each other, e.g. of the text with different attributes,
+                    // are one.
+                    Some(last) if last.rev_id == rev_id && last.position == position => last.deleted.add(op.clone()),
+                    _ => {
+                        let mut deleted = RichTextDelta::new();
+                        deleted.add(op.clone());
+                        self.tombstones.push(Tombstone {
+                            rev_id,
+                            author_id: author_id.to_owned(),
+                            position,
+                            deleted,
+                            deleted_at,
+                        });
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn transform(&mut self, delta: &RichTextDelta) {
+        if delta.is_noop() {
+            return;
+        }
+        for tombstone in &mut self.tombstones {
+            tombstone.position = delta.transform_position(tombstone.position, false);
+        }
+    }
+
+    /// Returns the tombstones whose position is in `interval`, ends included, and that were deleted
+    /// in `time`, in the order they were deleted.
+    pub fn query(&self, interval: Interval, time: Range<i64>) -> Vec<&Tombstone> {
+        self.tombstones
+            .iter()
+            .filter(|tombstone| interval.start <= tombstone.position && tombstone.position <= interval.end)
+            .filter(|tombstone| time.contains(&tombstone.deleted_at))
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tombstone> {
+        self.tombstones.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tombstones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tombstones.is_empty()
+    }
+
+    /// Drops the tombstones that are older than the retention at `now`, in milliseconds since the
+    /// epoch. Returns the number of tombstones that were dropped.
+    pub fn collect_garbage(&mut self, now: i64) -> usize {
+        let oldest = now - self.retention.max_age.as_millis() as i64;
+        let count = self.tombstones.len();
+        self.tombstones.retain(|tombstone| tombstone.deleted_at >= oldest);
+        count - self.tombstones.len()
+    }
+}
+
+impl DocumentObserver for TombstoneStore {
+    // The tombstones are kept when the content is replaced, like the ones of the text deleted by
+    // the changes.
+    fn did_reset(&mut self, _document: &ObservedDocument, _replaced: Option<&RichTextDelta>) {}
+
+    fn will_change(&mut self, _document: &ObservedDocument, delta: &RichTextDelta) {
+        self.transform(delta);
+    }
+
+    fn did_change(&mut self, _document: &ObservedDocument, event: &DocumentEvent) {
+        self.record(event.rev_id, &event.author_id, &event.inverted, event.timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::tombstone::{TombstoneRetention, TombstoneStore};
+    use lib_ot::{
+        core::Interval,
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn tombstones_follow_the_document_until_collected() {
+        let mut store = TombstoneStore::new(TombstoneRetention {
+            max_age: Duration::from_millis(1000),
+        });
+        let document = RichTextDeltaBuilder::new()
+            .insert("one ")
+            .insert_with_attributes("two", RichTextAttribute::Bold(true).into())
+            .insert(" three\n")
+            .build();
+        let delete_two = RichTextDeltaBuilder::new().retain(4).delete(4).build();
+        store.record(1, "a", &delete_two.invert(&document), 100);
+        let delete_one = RichTextDeltaBuilder::new().delete(4).build();
+        store.transform(&delete_one);
+        store.record(
+            2,
+            "b",
+            &delete_one.invert(&RichTextDeltaBuilder::new().insert("one three\n").build()),
+            600,
+        );
+        store.transform(&RichTextDeltaBuilder::new().insert("zero ").build());
+
+        let positions = store
+            .iter()
+            .map(|tombstone| (tombstone.rev_id, tombstone.position))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(1, 5), (2, 5)]);
+        let expected: RichTextDelta = RichTextDeltaBuilder::new()
+            .insert_with_attributes("two", RichTextAttribute::Bold(true).into())
+            .insert(" ")
+            .build();
+        assert_eq!(store.iter().next().unwrap().deleted, expected);
+        assert_eq!(store.query(Interval::new(0, 5), 0..500).len(), 1);
+        assert!(store.query(Interval::new(6, 10), 0..1000).is_empty());
+
+        assert_eq!(store.collect_garbage(1200), 1);
+        assert_eq!(store.iter().next().unwrap().author_id, "b");
+    }
+}
@@ -0,0 +1,361 @@
+//! The transclusions show a part of another document, e.g. a block that is linked from several
+//! pages. The document only keeps the id of the source document and the interval of its content,
+//! the content is resolved by the [TransclusionProvider] when it is displayed. The [LinkIndex]
+//! knows which documents link to a source and tells them when the linked content changes.
+use crate::client_document::{
+    event::DocumentEvent,
+    observer::{DocumentObserver, ObservedDocument},
+};
+use lib_ot::{
+    core::{Attributes, Embed, Interval, Operation},
+    rich_text::RichTextDelta,
+};
+use parking_lot::RwLock;
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::sync::broadcast;
+
+/// The kind of the embeds that are transclusions.
+pub const TRANSCLUSION_EMBED: &str = "transclusion";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Transclusion {
+    /// The id of the source document.
+    pub doc_id: String,
+    /// The content of the source document that is shown, in utf16 code units.
+    pub interval: Interval,
+}
+
+impl Transclusion {
+    pub fn new(doc_id: &str, interval: Interval) -> Self {
+        Self {
+            doc_id: doc_id.to_owned(),
+            interval,
+        }
+    }
+
+    /// The transclusion is kept in the document as
+    /// `{"insert":{"transclusion":{"doc_id":"...","start":0,"end":5}}}`.
+    pub fn to_embed(&self) -> Embed {
+        Embed::new(
+            TRANSCLUSION_EMBED,
+            json!({ "doc_id": self.doc_id, "start": self.interval.start, "end": self.interval.end }),
+        )
+    }
+
+    pub fn from_embed(embed: &Embed) -> Option<Self> {
+        if embed.kind != TRANSCLUSION_EMBED {
+            return None;
+        }
+        let data = embed.data.to_value();
+        let doc_id = data.get("doc_id")?.as_str()?;
+        let start = data.get("start")?.as_u64()? as usize;
+        let end = data.get("end")?.as_u64()? as usize;
+        if start > end {
+            return None;
+        }
+        Some(Transclusion::new(doc_id, Interval::new(start, end)))
+    }
+}
+
+/// Lets the application show the content of the transclusions, which lives in other documents.
+pub trait TransclusionProvider: Send + Sync {
+    /// Returns the content of the interval of the source document, or `None` if the document
+    /// doesn't exist anymore or doesn't have the interval.
+    fn resolve(&self, transclusion: &Transclusion) -> Option<RichTextDelta>;
+}
+
+/// Returns the transclusions of `document` with their positions.
+pub fn transclusions(document: &RichTextDelta) -> Vec<(usize, Transclusion)> {
+    let mut transclusions = vec![];
+    let mut offset = 0;
+    for op in &document.ops {
+        if let Some(transclusion) = op.get_embed().and_then(Transclusion::from_embed) {
+            transclusions.push((offset, transclusion));
+        }
+        offset += op.len();
+    }
+    transclusions
+}
+
+/// Returns the transclusions that `delta` inserts. The transclusions that a delta deletes are the
+/// ones that its inverted delta inserts.
+pub fn inserted_transclusions(delta: &RichTextDelta) -> Vec<Transclusion> {
+    delta
+        .ops
+        .iter()
+        .filter(|op| op.is_insert())
+        .filter_map(Operation::get_embed)
+        .filter_map(Transclusion::from_embed)
+        .collect()
+}
+
+/// Sent by the [LinkIndex] when the source of a transclusion changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransclusionInvalidated {
+    /// The id of the document that has the transclusion.
+    pub doc_id: String,
+    pub transclusion: Transclusion,
+    /// The interval of the linked content in the source after the change. It differs from the
+    /// interval of the transclusion if the content moved or its length changed.
+    pub interval: Interval,
+    /// Whether the linked content itself changed, which is false if it only moved.
+    pub content_changed: bool,
+}
+
+/// The reverse links of a workspace: the documents that have a transclusion of each source
+/// document. The documents that share the index through a [LinkTracker] keep their links in it
+/// and tell it about their changes.
+pub struct LinkIndex {
+    // The transclusions of each source document, with the documents that have them.
+    links: RwLock<HashMap<String, HashMap<Transclusion, HashSet<String>>>>,
+    notifier: broadcast::Sender<TransclusionInvalidated>,
+}
+
+impl std::default::Default for LinkIndex {
+    fn default() -> Self {
+        let (notifier, _) = broadcast::channel(1000);
+        Self {
+            links: RwLock::new(HashMap::new()),
+            notifier,
+        }
+    }
+}
+
+impl LinkIndex {
+    pub fn subscribe(&self) -> broadcast::Receiver<TransclusionInvalidated> {
+        self.notifier.subscribe()
+    }
+
+    /// Remembers that the document `doc_id` has the transclusion.
+    pub fn add_link(&self, doc_id: &str, transclusion: &Transclusion) {
+        self.links
+            .write()
+            .entry(transclusion.doc_id.clone())
+            .or_default()
+            .entry(transclusion.clone())
+            .or_default()
+            .insert(doc_id.to_owned());
+    }
+
+    /// Forgets that the document `doc_id` has the transclusion.
+    pub fn remove_link(&self, doc_id: &str, transclusion: &Transclusion) {
+        let mut links = self.links.write();
+        let source = match links.get_mut(&transclusion.doc_id) {
+            None => return,
+            Some(source) => source,
+        };
+        if let Some(doc_ids) = source.get_mut(transclusion) {
+            doc_ids.remove(doc_id);
+            if doc_ids.is_empty() {
+                source.remove(transclusion);
+            }
+        }
+        if source.is_empty() {
+            links.remove(&transclusion.doc_id);
+        }
+    }
+
+    /// Forgets the links of the document `doc_id`, e.g. when it's deleted.
+    pub fn remove_document(&self, doc_id: &str) {
+        let mut links = self.links.write();
+        links.values_mut().for_each(|source| {
+            source.values_mut().for_each(|doc_ids| {
+                doc_ids.remove(doc_id);
+            });
+            source.retain(|_, doc_ids| !doc_ids.is_empty());
+        });
+        links.retain(|_, source| !source.is_empty());
+    }
+
+    /// Returns the ids of the documents that have a transclusion of the document `source_id`,
+    /// sorted.
+    pub fn backlinks(&self, source_id: &str) -> Vec<String> {
+        let mut doc_ids = self
+            .links
+            .read()
+            .get(source_id)
+            .map(|source| source.values().flatten().cloned().collect::<HashSet<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<Vec<_>>();
+        doc_ids.sort();
+        doc_ids
+    }
+
+    /// Tells the subscribers about the transclusions of the document `source_id` that the delta
+    /// moved or changed. The delta was composed into the source.
+    pub fn did_change_source(&self, source_id: &str, delta: &RichTextDelta) {
+        if self.notifier.receiver_count() == 0 {
+            return;
+        }
+        let links = self.links.read();
+        let source = match links.get(source_id) {
+            None => return,
+            Some(source) => source,
+        };
+        for (transclusion, doc_ids) in source {
+            let interval = transform_interval(delta, transclusion.interval);
+            let content_changed = touches(delta, transclusion.interval);
+            if interval == transclusion.interval && !content_changed {
+                continue;
+            }
+            for doc_id in doc_ids {
+                let _ = self.notifier.send(TransclusionInvalidated {
+                    doc_id: doc_id.clone(),
+                    transclusion: transclusion.clone(),
+                    interval,
+                    content_changed,
+                });
+            }
+        }
+    }
+}
+
+// The text that is inserted at the edges of the interval is left out of it.
+fn transform_interval(delta: &RichTextDelta, interval: Interval) -> Interval {
+    let start = delta.transform_position(interval.start, false);
+    let end = delta.transform_position(interval.end, true).max(start);
+    Interval::new(start, end)
+}
+
+// Whether the delta deletes, formats or inserts text inside the interval.
+fn touches(delta: &RichTextDelta, interval: Interval) -> bool {
+    let mut offset = 0;
+    for op in &delta.ops {
+        if offset >= interval.end {
+            break;
+        }
+        match op {
+            Operation::Retain(retain) => {
+                let end = offset + retain.n;
+                if !retain.attributes.is_empty() && end > interval.start {
+                    return true;
+                }
+                offset = end;
+            }
+            Operation::Delete(n) => {
+                if offset + n > interval.start {
+                    return true;
+                }
+                offset += n;
+            }
+            Operation::Insert(_) => {
+                if offset > interval.start {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Keeps the links of the document in the [LinkIndex] while it observes the document, see
+/// [ClientDocument::set_link_index](crate::client_document::ClientDocument::set_link_index).
+pub struct LinkTracker {
+    link_index: Arc<LinkIndex>,
+}
+
+impl LinkTracker {
+    pub fn new(link_index: Arc<LinkIndex>) -> Self {
+        Self { link_index }
+    }
+
+    pub fn link_index(&self) -> &Arc<LinkIndex> {
+        &self.link_index
+    }
+}
+
+impl DocumentObserver for LinkTracker {
+    fn did_reset(&mut self, document: &ObservedDocument, replaced: Option<&RichTextDelta>) {
+        if replaced.is_some() {
+            self.link_index.remove_document(document.doc_id);
+        }
+        transclusions(document.delta)
+            .iter()
+            .for_each(|(_, transclusion)| self.link_index.add_link(document.doc_id, transclusion));
+    }
+
+    fn did_change(&mut self, document: &ObservedDocument, event: &DocumentEvent) {
+        let doc_id = document.doc_id;
+        inserted_transclusions(&event.inverted)
+            .iter()
+            .for_each(|transclusion| self.link_index.remove_link(doc_id, transclusion));
+        inserted_transclusions(&event.delta)
+            .iter()
+            .for_each(|transclusion| self.link_index.add_link(doc_id, transclusion));
+        self.link_index.did_change_source(doc_id, &event.delta);
+    }
+
+    fn did_remove(&mut self, doc_id: &str) {
+        self.link_index.remove_document(doc_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::transclusion::{
+        inserted_transclusions, transclusions, LinkIndex, Transclusion, TransclusionInvalidated,
+    };
+    use lib_ot::{
+        core::{Interval, OperationTransformable},
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn transclusion_embed() {
+        let transclusion = Transclusion::new("source", Interval::new(2, 5));
+        let document = RichTextDeltaBuilder::new().insert("see \n").build();
+        let delta = RichTextDeltaBuilder::new()
+            .retain(4)
+            .insert_embed(transclusion.to_embed())
+            .build();
+        let document: RichTextDelta = document.compose(&delta).unwrap();
+
+        assert_eq!(transclusions(&document), vec![(4, transclusion.clone())]);
+        assert_eq!(inserted_transclusions(&delta), vec![transclusion]);
+    }
+
+    #[test]
+    fn link_index_invalidates_the_changed_links() {
+        let index = LinkIndex::default();
+        let mut receiver = index.subscribe();
+        let transclusion = Transclusion::new("source", Interval::new(2, 5));
+        index.add_link("a", &transclusion);
+        index.add_link("b", &transclusion);
+        assert_eq!(index.backlinks("source"), vec!["a".to_owned(), "b".to_owned()]);
+
+        // A change after the linked content.
+        index.did_change_source("source", &RichTextDeltaBuilder::new().retain(6).insert("x").build());
+        assert!(receiver.try_recv().is_err());
+
+        // A change in front of it moves it.
+        index.did_change_source("source", &RichTextDeltaBuilder::new().insert("xy").build());
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.interval, Interval::new(4, 7));
+        assert!(!event.content_changed);
+
+        index.remove_link("b", &transclusion);
+        let delta = RichTextDeltaBuilder::new()
+            .retain(3)
+            .retain_with_attributes(1, RichTextAttribute::Bold(true).into())
+            .build();
+        index.did_change_source("source", &delta);
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            TransclusionInvalidated {
+                doc_id: "a".to_owned(),
+                transclusion,
+                interval: Interval::new(2, 5),
+                content_changed: true,
+            }
+        );
+        assert!(receiver.try_recv().is_err());
+
+        index.remove_document("a");
+        assert!(index.backlinks("source").is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use lib_ot::core::{count_utf16_code_units, Interval};
+use std::time::Duration;
+
+/// When the text typed with [crate::client_document::ClientDocument::type_text] is composed into
+/// the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingConfig {
+    /// The text is composed once it's been buffered for `max_delay`.
+    pub max_delay: Duration,
+    /// The text is composed once it has `max_len` utf16 code units.
+    pub max_len: usize,
+}
+
+impl std::default::Default for TypingConfig {
+    fn default() -> Self {
+        Self {
+            max_delay: Duration::from_millis(300),
+            max_len: 256,
+        }
+    }
+}
+
+/// The text typed at the caret that isn't composed into the document yet. The keystrokes are
+/// appended to it as long as each one is typed at the end of the ones before, the whole text is
+/// then composed as one insert at `index`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingBuffer {
+    pub index: usize,
+    pub text: String,
+    // The start of the line of `index`, where the triggers of the input rules start.
+    pub(crate) line_start: usize,
+    // When the first keystroke was buffered, in milliseconds of the clock of the document.
+    pub(crate) started_at: i64,
+}
+
+impl TypingBuffer {
+    pub(crate) fn new(index: usize, line_start: usize, started_at: i64) -> Self {
+        Self {
+            index,
+            text: String::new(),
+            line_start,
+            started_at,
+        }
+    }
+
+    /// The interval that the text takes in the document once it's composed.
+    pub fn interval(&self) -> Interval {
+        Interval::new(self.index, self.end())
+    }
+
+    /// Where the next keystroke is typed to be appended to the text.
+    pub fn end(&self) -> usize {
+        self.index + count_utf16_code_units(&self.text)
+    }
+
+    pub fn len(&self) -> usize {
+        count_utf16_code_units(&self.text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{typing::TypingConfig, ClientDocument, NewlineDoc};
+    use lib_ot::core::Interval;
+
+    #[test]
+    fn typed_text_is_composed_at_once() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.set_typing_buffer(Some(TypingConfig::default())).unwrap();
+        for (index, c) in ["a", "b", "c"].iter().enumerate() {
+            document.type_text(index, c).unwrap();
+        }
+        assert_eq!(document.to_plain_string(), "\n");
+        assert_eq!(document.typed_delta().unwrap().apply("").unwrap(), "abc\n");
+        assert_eq!(document.pending_typing().unwrap().interval(), Interval::new(0, 3));
+
+        document.set_selection(Interval::new(3, 3));
+        assert!(document.pending_typing().is_none());
+        assert_eq!(document.to_plain_string(), "abc\n");
+        assert_eq!(document.rev_id(), 1);
+
+        // The whitespace is inserted, so the input rule that it completes applies.
+        document.type_text(3, "\n").unwrap();
+        document.type_text(4, "#").unwrap();
+        document.type_text(5, " ").unwrap();
+        assert_eq!(document.to_plain_string(), "abc\n\n");
+        assert!(document.to_json().contains(r#""header":1"#));
+        assert!(document.flush_typing().unwrap().is_empty());
+    }
+}
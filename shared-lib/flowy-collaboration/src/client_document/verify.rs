@@ -0,0 +1,199 @@
+use crate::{
+    client_document::{attribute_runs::AttributeRuns, history::History, revision_log::RevisionLog},
+    util::verify_content_md5,
+};
+use lib_ot::{
+    core::{DeltaViolation, Operation, OperationTransformable, NEW_LINE},
+    rich_text::RichTextDelta,
+};
+use std::fmt;
+
+/// A part of the document that isn't what the changes made of it, see [VerifyReport].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Corruption {
+    /// The document breaks a rule of the canonical form of the deltas, e.g. its length doesn't
+    /// match the text of its inserts.
+    Delta(DeltaViolation),
+    /// The operation of the document isn't an insert, the document applies to the empty text.
+    NotInsert { index: usize },
+    /// The document doesn't end with a newline, which the changes keep.
+    MissingTrailingNewline,
+    /// The attributes that the document remembers for the text, see [AttributeRuns], differ from
+    /// the attributes of its operations from `index` on.
+    AttributeRuns { index: usize },
+    /// The data of the snapshot isn't valid utf8, its md5 or the md5 of its content don't match.
+    Snapshot { rev_id: i64, reason: String },
+    /// The content md5 of the latest revision doesn't match the document.
+    Revision { rev_id: i64, reason: String },
+    /// The entry of the undo or the redo stack, counted from the top, doesn't compose with the
+    /// document that it applies to, so undoing it would fail.
+    History {
+        is_undo: bool,
+        index: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corruption::Delta(violation) => write!(f, "the document is invalid: {}", violation),
+            Corruption::NotInsert { index } => write!(f, "the operation {} isn't an insert", index),
+            Corruption::MissingTrailingNewline => write!(f, "the document doesn't end with a newline"),
+            Corruption::AttributeRuns { index } => write!(f, "the attribute runs differ at {}", index),
+            Corruption::Snapshot { rev_id, reason } => write!(f, "the snapshot {} is corrupted: {}", rev_id, reason),
+            Corruption::Revision { rev_id, reason } => write!(f, "the revision {} is corrupted: {}", rev_id, reason),
+            Corruption::History { is_undo, index, reason } => {
+                let stack = if *is_undo { "undo" } else { "redo" };
+                write!(f, "the {} entry {} doesn't apply: {}", stack, index, reason)
+            }
+        }
+    }
+}
+
+/// What [crate::client_document::ClientDocument::verify] found wrong with a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub rev_id: i64,
+    pub corruptions: Vec<Corruption>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corruptions.is_empty()
+    }
+}
+
+/// Checks the document and what is kept along with it. The text of the document is valid utf8 by
+/// construction, the data of the snapshots is checked instead.
+pub(crate) fn verify_document(
+    document: &RichTextDelta,
+    rev_id: i64,
+    trailing_newline: bool,
+    attribute_runs: Option<&AttributeRuns>,
+    history: &History,
+    revision_log: &RevisionLog,
+) -> VerifyReport {
+    let mut corruptions = document
+        .invariants()
+        .into_iter()
+        .map(Corruption::Delta)
+        .collect::<Vec<_>>();
+    for (index, op) in document.ops.iter().enumerate() {
+        if !op.is_insert() {
+            corruptions.push(Corruption::NotInsert { index });
+        }
+    }
+    let ends_with_newline = match document.ops.last() {
+        Some(Operation::Insert(insert)) => insert.s.ends_with(NEW_LINE),
+        _ => false,
+    };
+    if trailing_newline && !ends_with_newline {
+        corruptions.push(Corruption::MissingTrailingNewline);
+    }
+    // The runs are checked if the document observes them.
+    if let Some(index) = attribute_runs.and_then(|runs| diff_attribute_runs(document, runs)) {
+        corruptions.push(Corruption::AttributeRuns { index });
+    }
+
+    for snapshot in revision_log.snapshots() {
+        let reason = match std::str::from_utf8(&snapshot.delta_data) {
+            Err(e) => Some(e.to_string()),
+            Ok(_) => snapshot.delta().err().map(|e| e.to_string()),
+        };
+        if let Some(reason) = reason {
+            corruptions.push(Corruption::Snapshot {
+                rev_id: snapshot.rev_id,
+                reason,
+            });
+        }
+    }
+    if let Some(revision) = revision_log.get_revision(rev_id) {
+        if let Err(e) = verify_content_md5(revision, document) {
+            corruptions.push(Corruption::Revision {
+                rev_id,
+                reason: e.to_string(),
+            });
+        }
+    }
+
+    for (is_undo, entries) in [(true, history.undoes()), (false, history.redoes())] {
+        // The top entry applies to the document, each entry below it to what the one above makes.
+        let mut delta = document.clone();
+        for (index, entry) in entries.iter().rev().enumerate() {
+            // The entries that keep a revision are rebuilt when they are applied.
+            let entry_delta = match &entry.delta {
+                None => break,
+                Some(entry_delta) => entry_delta,
+            };
+            let result = if entry_delta.utf16_base_len != delta.utf16_target_len {
+                Err(format!(
+                    "it applies to {} characters, not {}",
+                    entry_delta.utf16_base_len, delta.utf16_target_len
+                ))
+            } else {
+                delta.compose(entry_delta).map_err(|e| e.to_string())
+            };
+            match result {
+                Ok(composed) => delta = composed,
+                Err(reason) => {
+                    corruptions.push(Corruption::History { is_undo, index, reason });
+                    break;
+                }
+            }
+        }
+    }
+    VerifyReport { rev_id, corruptions }
+}
+
+// Returns the first index whose attributes in `attribute_runs` aren't the ones in the document.
+fn diff_attribute_runs(document: &RichTextDelta, attribute_runs: &AttributeRuns) -> Option<usize> {
+    let expected = AttributeRuns::new(document);
+    if &expected == attribute_runs {
+        return None;
+    }
+    let len = expected.len().max(attribute_runs.len());
+    (0..len)
+        .find(|index| expected.attributes_at(*index) != attribute_runs.attributes_at(*index))
+        .or(Some(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{verify::Corruption, ClientDocument, NewlineDoc};
+    use lib_ot::{
+        core::{DeltaViolation, Interval, Operation},
+        rich_text::{RichTextAttribute, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn verify_a_corrupted_document() {
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        document.insert(0, "abc").unwrap();
+        document
+            .format(Interval::new(0, 2), RichTextAttribute::Bold(true))
+            .unwrap();
+        assert!(document.verify().is_ok());
+
+        // The history was made on the longer document.
+        let mut delta = RichTextDeltaBuilder::new().insert("x\n").build();
+        delta.ops.push(Operation::Retain(2.into()));
+        document.set_delta(delta);
+        let report = document.verify();
+        assert!(report.corruptions.contains(&Corruption::NotInsert { index: 1 }));
+        assert!(report
+            .corruptions
+            .contains(&Corruption::Delta(DeltaViolation::BaseLenMismatch {
+                recorded: 0,
+                actual: 2
+            })));
+        assert!(report.corruptions.iter().any(|corruption| matches!(
+            corruption,
+            Corruption::History {
+                is_undo: true,
+                index: 0,
+                ..
+            }
+        )));
+    }
+}
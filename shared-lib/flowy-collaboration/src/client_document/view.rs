@@ -92,7 +92,7 @@ fn construct_insert_exts() -> Vec<InsertExtension> {
         Box::new(PreserveBlockFormatOnInsert {}),
         Box::new(PreserveLineFormatOnSplit {}),
         Box::new(ResetLineFormatOnNewLine {}),
-        Box::new(AutoFormatExt {}),
+        Box::new(PlainTextInCodeBlock {}),
         Box::new(PreserveInlineFormat {}),
         Box::new(DefaultInsertAttribute {}),
     ]
@@ -0,0 +1,271 @@
+use crate::{
+    client_document::{
+        revision_log::DocumentSnapshot,
+        store::{load_document, DocumentStore},
+        ClientDocument,
+    },
+    entities::revision::Revision,
+    errors::{internal_error, CollaborateError},
+    util::verify_content_md5,
+};
+use bytes::Bytes;
+use instant::Instant;
+use lib_ot::{core::OperationTransformable, rich_text::RichTextDelta};
+use std::{
+    convert::{TryFrom, TryInto},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// A record is the length of the revision, the md5 of the revision and the revision in protobuf.
+const LEN_SIZE: usize = 4;
+const CHECKSUM_SIZE: usize = 16;
+
+/// When the [WriteAheadLog] makes its writes durable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalSyncPolicy {
+    /// Every revision is synced before [WriteAheadLog::append] returns, a crash loses nothing.
+    EveryOp,
+    /// The revisions are synced at most once per interval, a crash loses the revisions of the
+    /// last interval at worst.
+    Interval(Duration),
+}
+
+impl std::default::Default for WalSyncPolicy {
+    fn default() -> Self {
+        WalSyncPolicy::EveryOp
+    }
+}
+
+/// Keeps the revisions of a document in a file until the snapshot that has them is saved, so a
+/// crash before the save doesn't lose them, see [load_document_with_wal]. Each document has a log
+/// file of its own.
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: File,
+    policy: WalSyncPolicy,
+    synced_at: Instant,
+    is_synced: bool,
+    revisions: Vec<Revision>,
+}
+
+impl WriteAheadLog {
+    /// Opens the log at `path`, which is created if it doesn't exist. The record that a crash cut
+    /// in the middle of its write, at the end of the file, is dropped.
+    pub fn open<P: AsRef<Path>>(path: P, policy: WalSyncPolicy) -> Result<Self, CollaborateError> {
+        let path = path.as_ref().to_owned();
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(internal_error(e)),
+        };
+        let (revisions, valid_len) = decode_records(&data)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(internal_error)?;
+        if valid_len < data.len() {
+            tracing::warn!("Drop the torn record at the end of {:?}", path);
+            file.set_len(valid_len as u64).map_err(internal_error)?;
+            file.sync_data().map_err(internal_error)?;
+        }
+        Ok(Self {
+            path,
+            file,
+            policy,
+            synced_at: Instant::now(),
+            is_synced: true,
+            revisions,
+        })
+    }
+
+    /// The revisions in the log, in the order they were appended.
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    /// The latest revision in the log.
+    pub fn rev_id(&self) -> Option<i64> {
+        self.revisions.last().map(|revision| revision.rev_id)
+    }
+
+    /// Appends the revision, and syncs the log if the [WalSyncPolicy] says so. The revisions that
+    /// aren't after the latest one are skipped.
+    pub fn append(&mut self, revision: &Revision) -> Result<(), CollaborateError> {
+        if self.rev_id().map(|rev_id| revision.rev_id <= rev_id).unwrap_or(false) {
+            return Ok(());
+        }
+        let record = encode_record(revision)?;
+        self.file.write_all(&record).map_err(internal_error)?;
+        self.revisions.push(revision.clone());
+        self.is_synced = false;
+        let is_due = match self.policy {
+            WalSyncPolicy::EveryOp => true,
+            WalSyncPolicy::Interval(interval) => self.synced_at.elapsed() >= interval,
+        };
+        if is_due {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Makes the revisions that were appended durable.
+    pub fn sync(&mut self) -> Result<(), CollaborateError> {
+        if self.is_synced {
+            return Ok(());
+        }
+        self.file.sync_data().map_err(internal_error)?;
+        self.synced_at = Instant::now();
+        self.is_synced = true;
+        Ok(())
+    }
+
+    /// Drops the revisions up to `rev_id`, once a snapshot at `rev_id` or later was saved. The log
+    /// is written to a new file that replaces the old one, so a crash leaves one or the other.
+    pub fn truncate(&mut self, rev_id: i64) -> Result<(), CollaborateError> {
+        if self
+            .revisions
+            .first()
+            .map(|first| first.rev_id > rev_id)
+            .unwrap_or(true)
+        {
+            return Ok(());
+        }
+        let revisions = self
+            .revisions
+            .iter()
+            .filter(|revision| revision.rev_id > rev_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut data = vec![];
+        for revision in &revisions {
+            data.extend(encode_record(revision)?);
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut tmp = File::create(&tmp_path).map_err(internal_error)?;
+            tmp.write_all(&data).map_err(internal_error)?;
+            tmp.sync_all().map_err(internal_error)?;
+        }
+        fs::rename(&tmp_path, &self.path).map_err(internal_error)?;
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(internal_error)?;
+        self.synced_at = Instant::now();
+        self.is_synced = true;
+        self.revisions = revisions;
+        Ok(())
+    }
+}
+
+/// Reads the document like [load_document] and composes the revisions of the log that are after
+/// its snapshot and its revisions, the ones that weren't saved before a crash.
+pub fn load_document_with_wal(
+    store: &dyn DocumentStore,
+    doc_id: &str,
+    wal: &WriteAheadLog,
+) -> Result<Option<ClientDocument>, CollaborateError> {
+    let document = load_document(store, doc_id)?;
+    let (rev_id, mut delta) = match &document {
+        None if wal.revisions().is_empty() => return Ok(None),
+        None => (0, RichTextDelta::default()),
+        Some(document) => (document.rev_id(), document.delta().clone()),
+    };
+    let revisions = wal
+        .revisions()
+        .iter()
+        .filter(|revision| revision.rev_id > rev_id)
+        .collect::<Vec<_>>();
+    if revisions.is_empty() {
+        return Ok(document);
+    }
+    let mut latest_rev_id = rev_id;
+    for revision in revisions {
+        delta = delta.compose(&RichTextDelta::from_bytes(&revision.delta_data)?)?;
+        verify_content_md5(revision, &delta)?;
+        latest_rev_id = revision.rev_id;
+    }
+    tracing::info!("Recovered the document {} up to {} from its log", doc_id, latest_rev_id);
+    let document = ClientDocument::restore(&DocumentSnapshot::new(latest_rev_id, &delta), &[])?;
+    Ok(Some(document))
+}
+
+fn encode_record(revision: &Revision) -> Result<Vec<u8>, CollaborateError> {
+    let data: Bytes = revision.clone().try_into()?;
+    let mut record = Vec::with_capacity(LEN_SIZE + CHECKSUM_SIZE + data.len());
+    record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    record.extend_from_slice(&md5::compute(&data).0);
+    record.extend_from_slice(&data);
+    Ok(record)
+}
+
+// Returns the revisions of the records and the length of the data they take. The data after it is
+// a record that wasn't written in full.
+fn decode_records(data: &[u8]) -> Result<(Vec<Revision>, usize), CollaborateError> {
+    let mut revisions = vec![];
+    let mut offset = 0;
+    while data.len() - offset >= LEN_SIZE + CHECKSUM_SIZE {
+        let mut len = [0; LEN_SIZE];
+        len.copy_from_slice(&data[offset..offset + LEN_SIZE]);
+        let start = offset + LEN_SIZE + CHECKSUM_SIZE;
+        let end = start + u32::from_le_bytes(len) as usize;
+        if end > data.len() {
+            break;
+        }
+        let payload = &data[start..end];
+        if md5::compute(payload).0[..] != data[offset + LEN_SIZE..start] {
+            break;
+        }
+        revisions.push(Revision::try_from(Bytes::copy_from_slice(payload))?);
+        offset = end;
+    }
+    Ok((revisions, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client_document::{
+        store::{DocumentStore, MemoryDocumentStore},
+        wal::{load_document_with_wal, WalSyncPolicy, WriteAheadLog},
+        ClientDocument, NewlineDoc,
+    };
+    use lib_infra::uuid_string;
+    use std::{fs::OpenOptions, io::Write};
+
+    #[test]
+    fn wal_recovers_the_unsaved_revisions() {
+        let path = std::env::temp_dir().join(format!("{}.wal", uuid_string()));
+        let store = MemoryDocumentStore::default();
+        let mut document = ClientDocument::new::<NewlineDoc>();
+        let mut wal = WriteAheadLog::open(&path, WalSyncPolicy::EveryOp).unwrap();
+        document.insert(0, "a").unwrap();
+        document.insert(1, "b").unwrap();
+        for revision in document.revision_log().revisions() {
+            wal.append(revision).unwrap();
+        }
+        store
+            .save("a", &document.snapshot(), document.revision_log().revisions())
+            .unwrap();
+        wal.truncate(2).unwrap();
+        assert!(wal.revisions().is_empty());
+
+        document.insert(2, "c").unwrap();
+        wal.append(document.revision_log().revisions().last().unwrap()).unwrap();
+        drop(wal);
+        // A crash in the middle of the next record.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[7, 0, 0]).unwrap();
+        drop(file);
+
+        let wal = WriteAheadLog::open(&path, WalSyncPolicy::EveryOp).unwrap();
+        assert_eq!(wal.rev_id(), Some(3));
+        let recovered = load_document_with_wal(&store, "a", &wal).unwrap().unwrap();
+        assert_eq!(recovered.to_json(), document.to_json());
+        assert_eq!(recovered.rev_id(), 3);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -5,10 +5,10 @@ use crate::{
         revision::{md5, Revision},
     },
     errors::{CollaborateError, CollaborateResult},
+    util::cal_diff,
 };
-use dissimilar::*;
 use flowy_folder_data_model::entities::{app::App, trash::Trash, view::View, workspace::Workspace};
-use lib_ot::core::{Delta, FlowyStr, OperationTransformable, PlainAttributes, PlainDeltaBuilder};
+use lib_ot::core::{OperationTransformable, PlainAttributes, PlainDeltaBuilder};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -309,7 +309,7 @@ impl FolderPad {
             Some(_) => {
                 let old = cloned_self.to_json()?;
                 let new = self.to_json()?;
-                let delta = cal_diff(old, new);
+                let delta = cal_diff::<PlainAttributes>(&old, &new);
                 self.root = self.root.compose(&delta)?;
                 Ok(Some(FolderChange { delta, md5: self.md5() }))
             }
@@ -340,7 +340,7 @@ impl FolderPad {
             Some(_) => {
                 let old = cloned_self.to_json()?;
                 let new = self.to_json()?;
-                let delta = cal_diff(old, new);
+                let delta = cal_diff::<PlainAttributes>(&old, &new);
                 self.root = self.root.compose(&delta)?;
                 Ok(Some(FolderChange { delta, md5: self.md5() }))
             }
@@ -385,25 +385,6 @@ impl FolderPad {
     }
 }
 
-fn cal_diff(old: String, new: String) -> Delta<PlainAttributes> {
-    let chunks = dissimilar::diff(&old, &new);
-    let mut delta_builder = PlainDeltaBuilder::new();
-    for chunk in &chunks {
-        match chunk {
-            Chunk::Equal(s) => {
-                delta_builder = delta_builder.retain(FlowyStr::from(*s).utf16_size());
-            }
-            Chunk::Delete(s) => {
-                delta_builder = delta_builder.delete(FlowyStr::from(*s).utf16_size());
-            }
-            Chunk::Insert(s) => {
-                delta_builder = delta_builder.insert(*s);
-            }
-        }
-    }
-    delta_builder.build()
-}
-
 #[cfg(test)]
 mod tests {
     #![allow(clippy::all)]
@@ -0,0 +1,164 @@
+use crate::errors::CollaborateError;
+use lib_infra::uuid_string;
+use std::{fmt, sync::Arc};
+
+/// Encrypts the documents and the revisions before they are stored. The provider only does the
+/// cryptography, the nonce and the id of the key are framed with the data here, see [encrypt].
+pub trait EncryptionProvider: Send + Sync {
+    /// The id of the key that [EncryptionProvider::encrypt] uses, it's kept with the data so the
+    /// keys can be rotated.
+    fn key_id(&self) -> String;
+
+    fn encrypt(&self, nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, EncryptionError>;
+
+    fn decrypt(&self, key_id: &str, nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, EncryptionError>;
+}
+
+pub type EncryptionProviderRef = Arc<dyn EncryptionProvider>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionError {
+    /// The data is encrypted but no provider was given to decrypt it.
+    MissingProvider,
+    /// The frame of the data is truncated or was written by an unknown version.
+    Malformed(String),
+    /// The provider doesn't have the key the data was encrypted with.
+    UnknownKey(String),
+    /// The data doesn't decrypt with its key, e.g. it was tampered with.
+    DecryptFail(String),
+    EncryptFail(String),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::MissingProvider => write!(f, "The data is encrypted but there is no provider"),
+            EncryptionError::Malformed(msg) => write!(f, "The encrypted data is malformed: {}", msg),
+            EncryptionError::UnknownKey(key_id) => write!(f, "The key {} is unknown", key_id),
+            EncryptionError::DecryptFail(msg) => write!(f, "Decrypt failed: {}", msg),
+            EncryptionError::EncryptFail(msg) => write!(f, "Encrypt failed: {}", msg),
+        }
+    }
+}
+
+impl std::convert::From<EncryptionError> for CollaborateError {
+    fn from(error: EncryptionError) -> Self {
+        CollaborateError::internal().context(error)
+    }
+}
+
+// The encrypted data is: the magic, the version of the frame, the length of the key id, the key
+// id, the nonce, then the data encrypted by the provider.
+const MAGIC: &[u8; 4] = b"FENC";
+const VERSION: u8 = 1;
+pub const NONCE_LEN: usize = 12;
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts `data` with a new nonce and frames it with the nonce and the id of the key.
+pub fn encrypt(provider: &dyn EncryptionProvider, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    let key_id = provider.key_id();
+    if key_id.len() > u8::MAX as usize {
+        return Err(EncryptionError::EncryptFail(format!(
+            "The key id {} is too long",
+            key_id
+        )));
+    }
+    let nonce = new_nonce();
+    let encrypted = provider.encrypt(&nonce, data)?;
+
+    let mut framed = Vec::with_capacity(MAGIC.len() + 2 + key_id.len() + NONCE_LEN + encrypted.len());
+    framed.extend_from_slice(MAGIC);
+    framed.push(VERSION);
+    framed.push(key_id.len() as u8);
+    framed.extend_from_slice(key_id.as_bytes());
+    framed.extend_from_slice(&nonce);
+    framed.extend_from_slice(&encrypted);
+    Ok(framed)
+}
+
+/// Decrypts the data that was framed by [encrypt]. The data that isn't encrypted is returned as
+/// it is, so the documents stored before the encryption was turned on can still be read.
+pub fn decrypt(provider: Option<&dyn EncryptionProvider>, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    if !is_encrypted(data) {
+        return Ok(data.to_vec());
+    }
+    let provider = provider.ok_or(EncryptionError::MissingProvider)?;
+    let rest = &data[MAGIC.len()..];
+    let (version, key_id_len) = match rest {
+        [version, key_id_len, ..] => (*version, *key_id_len as usize),
+        _ => return Err(EncryptionError::Malformed("The header is truncated".to_owned())),
+    };
+    if version != VERSION {
+        return Err(EncryptionError::Malformed(format!(
+            "The version {} isn't supported",
+            version
+        )));
+    }
+    let rest = &rest[2..];
+    if rest.len() < key_id_len + NONCE_LEN {
+        return Err(EncryptionError::Malformed("The header is truncated".to_owned()));
+    }
+    let key_id =
+        std::str::from_utf8(&rest[..key_id_len]).map_err(|e| EncryptionError::Malformed(format!("{:?}", e)))?;
+    let nonce = &rest[key_id_len..key_id_len + NONCE_LEN];
+    provider.decrypt(key_id, nonce, &rest[key_id_len + NONCE_LEN..])
+}
+
+// The nonce is taken from a random uuid, which has more random bits than the nonce needs.
+fn new_nonce() -> [u8; NONCE_LEN] {
+    let hex = uuid_string().replace('-', "");
+    let mut nonce = [0; NONCE_LEN];
+    for (i, byte) in nonce.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap_or_default();
+    }
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encryption::{decrypt, encrypt, is_encrypted, EncryptionError, EncryptionProvider};
+
+    // Xors the data with the key and the nonce, which is enough to check the framing.
+    struct XorProvider(u8);
+
+    impl EncryptionProvider for XorProvider {
+        fn key_id(&self) -> String {
+            format!("key-{}", self.0)
+        }
+
+        fn encrypt(&self, nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            Ok(data.iter().map(|b| b ^ self.0 ^ nonce[0]).collect())
+        }
+
+        fn decrypt(&self, key_id: &str, nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            if key_id != self.key_id() {
+                return Err(EncryptionError::UnknownKey(key_id.to_owned()));
+            }
+            Ok(data.iter().map(|b| b ^ self.0 ^ nonce[0]).collect())
+        }
+    }
+
+    #[test]
+    fn encryption_framing() {
+        let provider = XorProvider(7);
+        let data = br#"[{"insert":"secret\n"}]"#;
+        let encrypted = encrypt(&provider, data).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt(Some(&provider), &encrypted).unwrap(), data.to_vec());
+
+        // The data that isn't encrypted is read as it is.
+        assert_eq!(decrypt(None, data).unwrap(), data.to_vec());
+        assert_eq!(decrypt(None, &encrypted), Err(EncryptionError::MissingProvider));
+        assert_eq!(
+            decrypt(Some(&XorProvider(8)), &encrypted),
+            Err(EncryptionError::UnknownKey("key-7".to_owned()))
+        );
+        assert!(matches!(
+            decrypt(Some(&provider), &encrypted[..8]),
+            Err(EncryptionError::Malformed(_))
+        ));
+    }
+}
@@ -1,9 +1,18 @@
 use crate::{
+    client_document::{
+        embed_handler::EmbedHandlers,
+        export::{ExporterRegistry, IMAGE_EMBED},
+        mention::MENTION_EMBED,
+    },
     entities::revision::{RepeatedRevision, Revision},
     errors::CollaborateError,
+    synchronizer::SYNC_PROTOCOL_VERSION,
 };
 use flowy_derive::ProtoBuf;
-use lib_ot::{errors::OTError, rich_text::RichTextDelta};
+use lib_ot::{
+    errors::OTError,
+    rich_text::{RichTextAttributeKey, RichTextDelta},
+};
 
 #[derive(ProtoBuf, Default, Debug, Clone)]
 pub struct CreateDocParams {
@@ -75,6 +84,38 @@ pub struct DocumentDelta {
     pub delta_json: String,
 }
 
+/// A part of the content of a document, whole lines of it, which the frontend renders before the
+/// rest arrives. See [ContentStream](crate::client_document::stream::ContentStream) and
+/// [ContentAssembler](crate::client_document::stream::ContentAssembler).
+#[derive(ProtoBuf, Default, Debug, Clone, PartialEq, Eq)]
+pub struct DocumentChunk {
+    #[pb(index = 1)]
+    pub doc_id: String,
+
+    // The revision of the document that the chunks are taken from. The chunks of another revision
+    // don't fit, the transfer starts over.
+    #[pb(index = 2)]
+    pub rev_id: i64,
+
+    // The position of the chunk, counted from 0.
+    #[pb(index = 3)]
+    pub index: i64,
+
+    // The utf16 index of the document where the chunk starts.
+    #[pb(index = 4)]
+    pub offset: i64,
+
+    #[pb(index = 5)]
+    pub delta_json: String,
+
+    #[pb(index = 6)]
+    pub is_last: bool,
+
+    // The md5 of the content of the whole document, see [crate::util::content_md5].
+    #[pb(index = 7)]
+    pub md5: String,
+}
+
 #[derive(ProtoBuf, Default, Debug, Clone)]
 pub struct NewDocUser {
     #[pb(index = 1)]
@@ -106,3 +147,81 @@ impl std::convert::From<&String> for DocumentId {
         }
     }
 }
+
+#[derive(ProtoBuf, Default, Debug, Clone, Eq, PartialEq)]
+pub struct DocumentCapabilities {
+    #[pb(index = 1)]
+    pub attributes: Vec<String>,
+
+    #[pb(index = 2)]
+    pub embed_types: Vec<String>,
+
+    #[pb(index = 3)]
+    pub formats: Vec<String>,
+
+    #[pb(index = 4)]
+    pub sync_protocol_version: i64,
+
+    #[pb(index = 5)]
+    pub features: Vec<String>,
+}
+
+impl DocumentCapabilities {
+    /// The capabilities of the document engine that this crate was compiled with, with the
+    /// default exporters and no embed handlers.
+    pub fn current() -> Self {
+        Self::new(&ExporterRegistry::default(), &EmbedHandlers::default())
+    }
+
+    /// The capabilities of the documents that use the exporters and the embed handlers. The
+    /// formats are the json of the delta and the names of the exporters, the embed types are the
+    /// kinds that this crate knows and the kinds of the handlers.
+    pub fn new(exporters: &ExporterRegistry, embed_handlers: &EmbedHandlers) -> Self {
+        let attributes = RichTextAttributeKey::all()
+            .iter()
+            .flat_map(|key| match serde_json::to_value(key) {
+                Ok(serde_json::Value::String(key)) => Some(key),
+                _ => None,
+            })
+            .collect::<Vec<String>>();
+
+        let mut embed_types = vec![IMAGE_EMBED.to_owned(), MENTION_EMBED.to_owned()];
+        for kind in embed_handlers.kinds() {
+            if !embed_types.contains(&kind) {
+                embed_types.push(kind);
+            }
+        }
+
+        let mut formats = vec!["delta_json".to_owned()];
+        formats.extend(exporters.names().into_iter().map(|name| name.to_owned()));
+
+        let mut features = vec![];
+        if cfg!(feature = "crdt") {
+            features.push("crdt".to_owned());
+        }
+
+        DocumentCapabilities {
+            attributes,
+            embed_types,
+            formats,
+            sync_protocol_version: SYNC_PROTOCOL_VERSION,
+            features,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::document_info::DocumentCapabilities;
+
+    #[test]
+    fn capabilities_come_from_the_registries() {
+        let capabilities = DocumentCapabilities::current();
+        assert_eq!(capabilities.embed_types, vec!["image".to_owned(), "mention".to_owned()]);
+        assert_eq!(
+            capabilities.formats,
+            vec!["delta_json", "markdown", "html", "plain text"]
+        );
+        assert!(capabilities.attributes.contains(&"bold".to_owned()));
+    }
+}
@@ -1,7 +1,11 @@
+use crate::{client_document::format::PayloadKind, errors::CollaborateError};
 use bytes::Bytes;
 use flowy_derive::{ProtoBuf, ProtoBuf_Enum};
-use lib_ot::rich_text::RichTextDelta;
-use std::{convert::TryFrom, fmt::Formatter, ops::RangeInclusive};
+use lib_ot::{
+    core::Priority,
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+use std::{cmp::Ordering, collections::BTreeMap, convert::TryFrom, fmt::Formatter, ops::RangeInclusive};
 
 #[derive(PartialEq, Eq, Clone, Default, ProtoBuf)]
 pub struct Revision {
@@ -25,6 +29,32 @@ pub struct Revision {
 
     #[pb(index = 7)]
     pub user_id: String,
+
+    #[pb(index = 8)]
+    pub author_id: String,
+
+    #[pb(index = 9)]
+    pub device_id: String,
+
+    // The time the revision was made, in milliseconds since the epoch.
+    #[pb(index = 10)]
+    pub timestamp: i64,
+
+    // The md5 of the content of the document after the revision, see [crate::util::content_md5].
+    // It's empty if unknown, e.g. the delta was transformed after it was hashed.
+    #[pb(index = 11)]
+    pub content_md5: String,
+
+    // The vector clock of the revision, see [Revision::vector_clock]. It's empty for the revisions
+    // that were made before the peers kept one.
+    #[pb(index = 12)]
+    pub clock: Vec<ClockEntry>,
+
+    // The changes of the metadata of the document that the revision makes, see
+    // [crate::client_document::metadata::Metadata]. The delta of such a revision retains the
+    // whole document.
+    #[pb(index = 13)]
+    pub metadata: Vec<MetadataEntry>,
 }
 
 impl std::convert::From<Vec<u8>> for Revision {
@@ -78,8 +108,225 @@ impl Revision {
             object_id,
             ty: RevType::DeprecatedLocal,
             user_id,
+            author_id: "".to_owned(),
+            device_id: "".to_owned(),
+            timestamp: 0,
+            content_md5: "".to_owned(),
+            clock: vec![],
+            metadata: vec![],
         }
     }
+
+    /// Attributes the revision to the author on the device. The time is set by
+    /// [Revision::with_timestamp], from the clock of the document.
+    pub fn with_author(mut self, author_id: &str, device_id: &str) -> Self {
+        self.author_id = author_id.to_owned();
+        self.device_id = device_id.to_owned();
+        self
+    }
+
+    /// Sets the time the revision was made at, e.g. by the clock of the document.
+    pub fn with_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Vec<MetadataEntry>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Whether the revision changes the metadata of the document instead of its content.
+    pub fn is_metadata(&self) -> bool {
+        !self.metadata.is_empty()
+    }
+
+    pub fn with_content_md5(mut self, content_md5: String) -> Self {
+        self.content_md5 = content_md5;
+        self
+    }
+
+    pub fn with_vector_clock(mut self, clock: &VectorClock) -> Self {
+        self.clock = clock.to_entries();
+        self
+    }
+
+    /// The revisions that the peer had applied when it made the revision, itself included.
+    pub fn vector_clock(&self) -> VectorClock {
+        VectorClock::from_entries(&self.clock)
+    }
+
+    /// Returns whether `other` was made by a peer that had applied the revision.
+    pub fn happened_before(&self, other: &Revision) -> bool {
+        self.vector_clock().happened_before(&other.vector_clock())
+    }
+
+    /// Returns whether the revision and `other` were made without knowing about each other, which
+    /// means one has to be transformed against the other.
+    pub fn is_concurrent(&self, other: &Revision) -> bool {
+        self.vector_clock().is_concurrent(&other.vector_clock())
+    }
+
+    /// Orders the revisions in the same way on every peer: the revisions that happened before go
+    /// first, the concurrent ones by their Lamport timestamp and then by their device. The one that
+    /// goes first has the priority when they are transformed against each other.
+    pub fn causal_cmp(&self, other: &Revision) -> Ordering {
+        let (clock, other_clock) = (self.vector_clock(), other.vector_clock());
+        match clock.partial_cmp(&other_clock) {
+            Some(Ordering::Equal) | None => clock
+                .lamport()
+                .cmp(&other_clock.lamport())
+                .then_with(|| self.device_id.cmp(&other.device_id))
+                .then_with(|| self.user_id.cmp(&other.user_id))
+                .then_with(|| self.rev_id.cmp(&other.rev_id)),
+            Some(ordering) => ordering,
+        }
+    }
+
+    /// The priority of the revision when it's transformed against `other`, see
+    /// [lib_ot::core::Delta::transform_with_priority].
+    pub fn transform_priority(&self, other: &Revision) -> Priority {
+        match self.causal_cmp(other) {
+            Ordering::Greater => Priority::Right,
+            _ => Priority::Left,
+        }
+    }
+
+    /// Minimizes the local revisions that the server hasn't acked yet, before they are pushed. The
+    /// consecutive revisions of the same author are composed into one, whose retains that change
+    /// nothing are merged into their neighbours. The composed revision is only split again if its
+    /// delta is larger than `max_size` bytes, into at most as many revisions as it was made of.
+    /// The last revision of each run keeps its rev_id, so the revisions after it still apply.
+    pub fn minimize(revisions: Vec<Revision>, max_size: usize) -> Result<Vec<Revision>, CollaborateError> {
+        let mut minimized = vec![];
+        let mut run: Vec<Revision> = vec![];
+        for revision in revisions {
+            let continues = run.last().map_or(true, |last| last.is_followed_by(&revision));
+            if !continues {
+                minimized.extend(minimize_run(std::mem::take(&mut run), max_size)?);
+            }
+            run.push(revision);
+        }
+        if !run.is_empty() {
+            minimized.extend(minimize_run(run, max_size)?);
+        }
+        Ok(minimized)
+    }
+
+    // Whether `next` was made right after the revision, by the same author on the same device.
+    fn is_followed_by(&self, next: &Revision) -> bool {
+        next.base_rev_id == self.rev_id
+            && next.object_id == self.object_id
+            && next.user_id == self.user_id
+            && next.author_id == self.author_id
+            && next.device_id == self.device_id
+    }
+}
+
+// Composes the run of revisions, see [Revision::minimize].
+fn minimize_run(run: Vec<Revision>, max_size: usize) -> Result<Vec<Revision>, CollaborateError> {
+    if run.len() < 2 {
+        return Ok(run);
+    }
+
+    let deltas = run
+        .iter()
+        .map(|revision| RichTextDelta::from_bytes(&revision.delta_data))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut pieces = vec![strip_redundant_retains(RichTextDelta::compose_all(&deltas)?)];
+    while pieces.len() < run.len() {
+        let (index, size) = pieces
+            .iter()
+            .enumerate()
+            .map(|(index, piece)| (index, piece.to_bytes().len()))
+            .max_by_key(|(_, size)| *size)
+            .unwrap();
+        if size <= max_size {
+            break;
+        }
+        match split_in_half(&pieces[index]) {
+            None => break,
+            Some((first, second)) => {
+                pieces[index] = second;
+                pieces.insert(index, first);
+            }
+        }
+    }
+
+    // The pieces take the rev_ids of the last revisions of the run.
+    let rev_ids = run[run.len() - pieces.len()..]
+        .iter()
+        .map(|revision| revision.rev_id)
+        .collect::<Vec<_>>();
+    let mut base_rev_id = run[0].base_rev_id;
+    let last = run.last().unwrap();
+    let last_index = pieces.len() - 1;
+    let minimized = pieces
+        .into_iter()
+        .zip(rev_ids)
+        .enumerate()
+        .map(|(index, (piece, rev_id))| {
+            let delta_data = piece.to_bytes().to_vec();
+            let revision = match index == last_index {
+                true => Revision {
+                    base_rev_id,
+                    rev_id,
+                    delta_data,
+                    ..last.clone()
+                },
+                // The content of the document after the piece is unknown.
+                false => Revision {
+                    base_rev_id,
+                    rev_id,
+                    md5: md5(&delta_data),
+                    delta_data,
+                    content_md5: "".to_owned(),
+                    ..last.clone()
+                },
+            };
+            base_rev_id = rev_id;
+            revision
+        })
+        .collect();
+    Ok(minimized)
+}
+
+// Rebuilds the delta, which merges the retains whose attributes are empty into the plain retains
+// next to them.
+fn strip_redundant_retains(delta: RichTextDelta) -> RichTextDelta {
+    let mut stripped = RichTextDelta::with_capacity(delta.ops.len());
+    for op in delta.ops {
+        stripped.add(op);
+    }
+    stripped
+}
+
+// Splits the change before its middle operation that changes something, into two changes that make
+// it one after another. Both of them retain the rest of the document, so they still transform
+// against the others.
+fn split_in_half(delta: &RichTextDelta) -> Option<(RichTextDelta, RichTextDelta)> {
+    let changes = delta
+        .ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !(op.is_retain() && op.is_plain()))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if changes.len() < 2 {
+        return None;
+    }
+
+    let (head, tail) = delta.ops.split_at(changes[changes.len() / 2]);
+    let mut first = RichTextDelta::new();
+    head.iter().for_each(|op| first.add(op.clone()));
+    let mut second = RichTextDelta::new();
+    second.retain(first.utf16_target_len, RichTextAttributes::default());
+    tail.iter().for_each(|op| second.add(op.clone()));
+    first.retain(
+        delta.utf16_base_len.saturating_sub(first.utf16_base_len),
+        RichTextAttributes::default(),
+    );
+    Some((first, second))
 }
 
 impl std::convert::From<Revision> for RepeatedRevision {
@@ -93,6 +340,9 @@ impl std::fmt::Debug for Revision {
         let _ = f.write_fmt(format_args!("object_id {}, ", self.object_id))?;
         let _ = f.write_fmt(format_args!("base_rev_id {}, ", self.base_rev_id))?;
         let _ = f.write_fmt(format_args!("rev_id {}, ", self.rev_id))?;
+        if !self.author_id.is_empty() {
+            let _ = f.write_fmt(format_args!("author {}@{}, ", self.author_id, self.device_id))?;
+        }
         match RichTextDelta::from_bytes(&self.delta_data) {
             Ok(delta) => {
                 let _ = f.write_fmt(format_args!("delta {:?}", delta.to_json()))?;
@@ -105,6 +355,130 @@ impl std::fmt::Debug for Revision {
     }
 }
 
+/// The counter of a peer in a [VectorClock], as it's kept in the [Revision].
+#[derive(PartialEq, Eq, Debug, Default, ProtoBuf, Clone)]
+pub struct ClockEntry {
+    #[pb(index = 1)]
+    pub peer_id: String,
+
+    #[pb(index = 2)]
+    pub counter: i64,
+}
+
+/// The value of a key of the metadata of a document, as it's kept in the [Revision]. The value
+/// that was written last wins, see [crate::client_document::metadata::Metadata::merge].
+#[derive(PartialEq, Eq, Debug, Default, ProtoBuf, Clone)]
+pub struct MetadataEntry {
+    #[pb(index = 1)]
+    pub key: String,
+
+    #[pb(index = 2)]
+    pub value: String,
+
+    // The key was removed, the value is empty.
+    #[pb(index = 3)]
+    pub is_removed: bool,
+
+    // The time the value was written, in milliseconds since the epoch.
+    #[pb(index = 4)]
+    pub timestamp: i64,
+
+    // The device that wrote the value, which breaks the ties between the values written at the
+    // same time.
+    #[pb(index = 5)]
+    pub device_id: String,
+}
+
+/// The number of revisions of each peer that happened before an event, which tells the
+/// revisions that are causally ordered from the concurrent ones. The peers that aren't in the
+/// clock have a counter of 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VectorClock {
+    counters: BTreeMap<String, i64>,
+}
+
+impl VectorClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_entries(entries: &[ClockEntry]) -> Self {
+        let mut clock = Self::new();
+        for entry in entries {
+            clock.set(&entry.peer_id, entry.counter);
+        }
+        clock
+    }
+
+    /// The entries of the clock, ordered by peer.
+    pub fn to_entries(&self) -> Vec<ClockEntry> {
+        self.counters
+            .iter()
+            .map(|(peer_id, counter)| ClockEntry {
+                peer_id: peer_id.clone(),
+                counter: *counter,
+            })
+            .collect()
+    }
+
+    pub fn get(&self, peer_id: &str) -> i64 {
+        self.counters.get(peer_id).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, peer_id: &str, counter: i64) {
+        match counter > 0 {
+            true => self.counters.insert(peer_id.to_owned(), counter),
+            false => self.counters.remove(peer_id),
+        };
+    }
+
+    /// Counts a new revision of `peer_id`, and returns its counter.
+    pub fn tick(&mut self, peer_id: &str) -> i64 {
+        let counter = self.get(peer_id) + 1;
+        self.set(peer_id, counter);
+        counter
+    }
+
+    /// Takes in the revisions that `other` counts, when a revision with the clock is applied.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (peer_id, counter) in &other.counters {
+            if *counter > self.get(peer_id) {
+                self.set(peer_id, *counter);
+            }
+        }
+    }
+
+    /// The Lamport timestamp of the clock, which is larger than the ones of all the clocks that
+    /// happened before it.
+    pub fn lamport(&self) -> i64 {
+        self.counters.values().sum()
+    }
+
+    pub fn happened_before(&self, other: &VectorClock) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Less)
+    }
+
+    pub fn is_concurrent(&self, other: &VectorClock) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+}
+
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let peers = self.counters.keys().chain(other.counters.keys());
+        let mut ordering = Ordering::Equal;
+        for peer_id in peers {
+            match (ordering, self.get(peer_id).cmp(&other.get(peer_id))) {
+                (_, Ordering::Equal) => {}
+                (Ordering::Equal, peer_ordering) => ordering = peer_ordering,
+                (ordering, peer_ordering) if ordering != peer_ordering => return None,
+                _ => {}
+            }
+        }
+        Some(ordering)
+    }
+}
+
 #[derive(PartialEq, Debug, Default, ProtoBuf, Clone)]
 pub struct RepeatedRevision {
     #[pb(index = 1)]
@@ -140,6 +514,63 @@ impl RepeatedRevision {
     }
 }
 
+/// The revisions that are moved between machines by hand, e.g. as a file. The bundle starts from
+/// the content of the document at `base_rev_id`, whose md5 is `base_md5`.
+#[derive(PartialEq, Debug, Default, ProtoBuf, Clone)]
+pub struct RevisionBundle {
+    #[pb(index = 1)]
+    pub base_rev_id: i64,
+
+    #[pb(index = 2)]
+    pub base_md5: String,
+
+    #[pb(index = 3)]
+    pub revisions: Vec<Revision>,
+
+    // The md5 of the deltas of the revisions, in order.
+    #[pb(index = 4)]
+    pub md5: String,
+
+    // See [RevisionBundle::format_version].
+    #[pb(index = 5)]
+    pub version: i64,
+}
+
+impl RevisionBundle {
+    pub fn new(base_rev_id: i64, base_md5: String, revisions: Vec<Revision>) -> Self {
+        let md5 = Self::checksum(&revisions);
+        Self {
+            base_rev_id,
+            base_md5,
+            revisions,
+            md5,
+            version: PayloadKind::Bundle.current_version() as i64,
+        }
+    }
+
+    /// The version the bundle was exported by, see [PayloadKind::current_version]. The bundles
+    /// that were exported before the field was added are of the version 1.
+    pub fn format_version(&self) -> u32 {
+        match self.version {
+            0 => 1,
+            version => u32::try_from(version).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Returns whether the revisions are the ones the bundle was created with.
+    pub fn is_valid(&self) -> bool {
+        Self::checksum(&self.revisions) == self.md5
+    }
+
+    fn checksum(revisions: &[Revision]) -> String {
+        let mut context = md5::Context::new();
+        for revision in revisions {
+            context.consume(&revision.delta_data);
+        }
+        format!("{:x}", context.compute())
+    }
+}
+
 #[derive(Clone, Debug, ProtoBuf, Default)]
 pub struct RevId {
     #[pb(index = 1)]
@@ -247,3 +678,118 @@ impl std::default::Default for RevType {
         RevType::DeprecatedLocal
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::revision::{Revision, VectorClock};
+    use bytes::Bytes;
+    use lib_ot::{
+        core::{OperationTransformable, Priority},
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+    use std::cmp::Ordering;
+
+    fn revision(device_id: &str, clock: &VectorClock) -> Revision {
+        Revision::new("doc", 0, 1, Bytes::new(), "user", "".to_owned())
+            .with_author("user", device_id)
+            .with_vector_clock(clock)
+    }
+
+    #[test]
+    fn vector_clock_orders_revisions() {
+        let mut a = VectorClock::new();
+        a.tick("a");
+        let mut b = a.clone();
+        b.tick("b");
+        let mut c = a.clone();
+        c.tick("c");
+        assert!(a.happened_before(&b));
+        assert!(!b.happened_before(&a));
+        assert!(b.is_concurrent(&c));
+
+        let mut merged = b.clone();
+        merged.merge(&c);
+        assert_eq!(merged.get("a"), 1);
+        assert_eq!(merged.lamport(), 3);
+        assert!(b.happened_before(&merged) && c.happened_before(&merged));
+
+        let (a, b, c) = (revision("a", &a), revision("b", &b), revision("c", &c));
+        assert_eq!(b.vector_clock(), VectorClock::from_entries(&b.clock));
+        assert!(a.happened_before(&b));
+        assert!(b.is_concurrent(&c));
+        // The concurrent revisions are ordered in the same way on both peers.
+        assert_eq!(b.causal_cmp(&c), Ordering::Less);
+        assert_eq!(c.causal_cmp(&b), Ordering::Greater);
+        assert_eq!(c.causal_cmp(&a), Ordering::Greater);
+        assert_eq!(b.transform_priority(&c), Priority::Left);
+        assert_eq!(c.transform_priority(&b), Priority::Right);
+    }
+
+    fn typed(base_rev_id: i64, author: &str, delta: RichTextDelta) -> Revision {
+        Revision::new(
+            "doc",
+            base_rev_id,
+            base_rev_id + 1,
+            delta.to_bytes(),
+            "user",
+            "".to_owned(),
+        )
+        .with_author(author, author)
+    }
+
+    #[test]
+    fn minimize_composes_the_runs_of_revisions() {
+        let document = RichTextDeltaBuilder::new().insert("hello\n").build();
+        let revisions = vec![
+            typed(0, "a", RichTextDeltaBuilder::new().insert("x").retain(6).build()),
+            typed(
+                1,
+                "a",
+                RichTextDeltaBuilder::new().retain(6).insert("y").retain(1).build(),
+            ),
+            typed(
+                2,
+                "a",
+                RichTextDeltaBuilder::new()
+                    .retain_with_attributes(1, RichTextAttribute::Bold(true).into())
+                    .retain(7)
+                    .build(),
+            ),
+            typed(3, "b", RichTextDeltaBuilder::new().retain(8).insert("z").build()),
+        ];
+        let expected = RichTextDelta::compose_all(
+            revisions
+                .iter()
+                .map(|revision| RichTextDelta::from_bytes(&revision.delta_data).unwrap())
+                .collect::<Vec<_>>()
+                .iter(),
+        )
+        .unwrap();
+        let apply = |revisions: &[Revision]| {
+            revisions.iter().fold(document.clone(), |document, revision| {
+                document
+                    .compose(&RichTextDelta::from_bytes(&revision.delta_data).unwrap())
+                    .unwrap()
+            })
+        };
+
+        // The revisions of "a" are composed into one, the one of "b" is left as it is.
+        let minimized = Revision::minimize(revisions.clone(), usize::MAX).unwrap();
+        assert_eq!(minimized.len(), 2);
+        assert_eq!(minimized[0].pair_rev_id(), (0, 3));
+        assert_eq!(minimized[0].author_id, "a");
+        assert_eq!(minimized[1], revisions[3]);
+        assert_eq!(
+            RichTextDelta::from_bytes(&minimized[0].delta_data).unwrap().to_json(),
+            r#"[{"insert":"x","attributes":{"bold":true}},{"retain":5},{"insert":"y"},{"retain":1}]"#
+        );
+        assert_eq!(apply(&minimized), document.compose(&expected).unwrap());
+
+        // The composed delta is split again when it's too large, and keeps the rev_ids in order.
+        let minimized = Revision::minimize(revisions, 32).unwrap();
+        assert_eq!(minimized.len(), 3);
+        assert_eq!(minimized[0].pair_rev_id(), (0, 2));
+        assert_eq!(minimized[1].pair_rev_id(), (2, 3));
+        assert_eq!(apply(&minimized), document.compose(&expected).unwrap());
+    }
+}
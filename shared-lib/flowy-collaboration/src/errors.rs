@@ -1,3 +1,5 @@
+use crate::server_document::{DocumentAction, RejectionReason, ThrottleReason};
+use lib_ot::{core::Interval, rich_text::RichTextOperation};
 use std::{fmt, fmt::Debug};
 use strum_macros::Display;
 
@@ -40,6 +42,11 @@ impl CollaborateError {
     static_doc_error!(out_of_bound, ErrorCode::OutOfBound);
     static_doc_error!(record_not_found, ErrorCode::RecordNotFound);
     static_doc_error!(revision_conflict, ErrorCode::RevisionConflict);
+    static_doc_error!(integrity, ErrorCode::DocumentIntegrity);
+    static_doc_error!(missing_trailing_newline, ErrorCode::MissingTrailingNewline);
+    static_doc_error!(quota_exceeded, ErrorCode::QuotaExceeded);
+    static_doc_error!(permission_denied, ErrorCode::PermissionDenied);
+    static_doc_error!(throttled, ErrorCode::Throttled);
 }
 
 impl fmt::Display for CollaborateError {
@@ -56,13 +63,224 @@ pub enum ErrorCode {
     RedoFail = 201,
     OutOfBound = 202,
     RevisionConflict = 203,
+    DocumentIntegrity = 204,
+    MissingTrailingNewline = 205,
+    QuotaExceeded = 206,
+    PermissionDenied = 207,
+    Throttled = 208,
+    DeltaRejected = 209,
+    ContentRejected = 210,
+    EmbedHandlerRejected = 211,
+    RegionLocked = 212,
     RecordNotFound = 300,
     InternalError = 1000,
 }
 
+/// The content of the document doesn't match the one that the revision or the snapshot was made
+/// with, the document diverged from the other participants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentIntegrityError {
+    pub rev_id: i64,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for DocumentIntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The content at the revision {} is {}, expected {}",
+            self.rev_id, self.actual, self.expected
+        )
+    }
+}
+
+impl std::convert::From<DocumentIntegrityError> for CollaborateError {
+    fn from(error: DocumentIntegrityError) -> Self {
+        CollaborateError::integrity().context(error)
+    }
+}
+
+/// The change would make the document longer than the
+/// [DocumentConfig::max_len](crate::client_document::DocumentConfig::max_len) of the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceededError {
+    pub max_len: usize,
+    /// The length of the document after the change.
+    pub len: usize,
+}
+
+impl QuotaExceededError {
+    /// The number of characters that the change is over the limit.
+    pub fn over(&self) -> usize {
+        self.len.saturating_sub(self.max_len)
+    }
+}
+
+impl fmt::Display for QuotaExceededError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The document would be {} characters long, {} over its limit of {}",
+            self.len,
+            self.over(),
+            self.max_len
+        )
+    }
+}
+
+impl std::convert::From<QuotaExceededError> for CollaborateError {
+    fn from(error: QuotaExceededError) -> Self {
+        CollaborateError::new(ErrorCode::QuotaExceeded, &error.to_string())
+    }
+}
+
+/// The user may not do `action` with the document, see
+/// [DocumentAccessControl](crate::server_document::DocumentAccessControl).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionDeniedError {
+    pub user_id: String,
+    pub doc_id: String,
+    pub action: DocumentAction,
+    /// The operation of the revision that the user may not make.
+    pub operation: Option<RichTextOperation>,
+}
+
+impl PermissionDeniedError {
+    pub fn new(user_id: &str, doc_id: &str, action: DocumentAction, operation: Option<RichTextOperation>) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            doc_id: doc_id.to_owned(),
+            action,
+            operation,
+        }
+    }
+}
+
+impl fmt::Display for PermissionDeniedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The user {} may not {} the document {}",
+            self.user_id,
+            self.action.as_str(),
+            self.doc_id
+        )?;
+        match &self.operation {
+            None => Ok(()),
+            Some(operation) => write!(f, ": {}", serde_json::to_string(operation).unwrap_or_default()),
+        }
+    }
+}
+
+impl std::convert::From<PermissionDeniedError> for CollaborateError {
+    fn from(error: PermissionDeniedError) -> Self {
+        CollaborateError::new(ErrorCode::PermissionDenied, &error.to_string())
+    }
+}
+
+/// The revisions of the client are over the limits of the server, see
+/// [RateLimitConfig](crate::server_document::RateLimitConfig).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThrottledError {
+    pub user_id: String,
+    pub reason: ThrottleReason,
+}
+
+impl fmt::Display for ThrottledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The user {} sent {}", self.user_id, self.reason)
+    }
+}
+
+impl std::convert::From<ThrottledError> for CollaborateError {
+    fn from(error: ThrottledError) -> Self {
+        CollaborateError::new(ErrorCode::Throttled, &error.to_string())
+    }
+}
+
+/// The text of a change breaks a content policy of the document, see
+/// [ContentPolicies](crate::client_document::content_policy::ContentPolicies).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentRejectedError {
+    pub policy: String,
+    /// The text that the policy matched.
+    pub text: String,
+}
+
+impl fmt::Display for ContentRejectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The text {:?} breaks the content policy {}", self.text, self.policy)
+    }
+}
+
+impl std::convert::From<ContentRejectedError> for CollaborateError {
+    fn from(error: ContentRejectedError) -> Self {
+        CollaborateError::new(ErrorCode::ContentRejected, &error.to_string())
+    }
+}
+
+/// An [EmbedHandler](crate::client_document::embed_handler::EmbedHandler) breaks a property of
+/// [check_embed_handler](crate::client_document::embed_handler::check_embed_handler).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbedHandlerError {
+    pub kind: String,
+    /// The property that failed, e.g. `transform`.
+    pub property: String,
+    pub detail: String,
+}
+
+impl fmt::Display for EmbedHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The handler of the {} embeds breaks the {} property: {}",
+            self.kind, self.property, self.detail
+        )
+    }
+}
+
+impl std::convert::From<EmbedHandlerError> for CollaborateError {
+    fn from(error: EmbedHandlerError) -> Self {
+        CollaborateError::new(ErrorCode::EmbedHandlerRejected, &error.to_string())
+    }
+}
+
+/// The change would write or format the locked text in `interval`, see
+/// [check_protection](crate::client_document::protection::check_protection).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionLockedError {
+    pub interval: Interval,
+    pub action: DocumentAction,
+}
+
+impl fmt::Display for RegionLockedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The text {}..{} is locked, the change may not {} it",
+            self.interval.start,
+            self.interval.end,
+            self.action.as_str()
+        )
+    }
+}
+
+impl std::convert::From<RegionLockedError> for CollaborateError {
+    fn from(error: RegionLockedError) -> Self {
+        CollaborateError::new(ErrorCode::RegionLocked, &error.to_string())
+    }
+}
+
+impl std::convert::From<RejectionReason> for CollaborateError {
+    fn from(reason: RejectionReason) -> Self {
+        CollaborateError::new(ErrorCode::DeltaRejected, &format!("The delta was rejected, {}", reason))
+    }
+}
+
 impl std::convert::From<lib_ot::errors::OTError> for CollaborateError {
     fn from(error: lib_ot::errors::OTError) -> Self {
-        CollaborateError::new(ErrorCode::InternalError, "").context(error)
+        CollaborateError::new(ErrorCode::InternalError, &error.display_chain())
     }
 }
 
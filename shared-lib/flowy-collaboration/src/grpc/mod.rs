@@ -0,0 +1,385 @@
+//! The [ServerDocumentManager] as a tonic service, see `document_sync.proto`. A self-hosted server
+//! serves it with the [TokenVerifier] that tells who the callers are:
+//!
+//! ```ignore
+//! let service = DocumentSyncService::new(manager, verifier).into_server();
+//! Server::builder().add_service(service).serve(addr).await?;
+//! ```
+use crate::{
+    entities::{
+        revision::RevisionRange,
+        ws_data::{ClientRevisionWSData, ClientRevisionWSDataType},
+    },
+    errors::{CollaborateError, ErrorCode},
+    protobuf::ClientRevisionWSData as ClientRevisionWSDataPB,
+    server_document::ServerDocumentManager,
+    synchronizer::{RevisionSyncResponse, RevisionUser},
+    util::repeated_revision_pb_from_revisions,
+};
+use bytes::Bytes;
+use futures::Stream;
+use lib_infra::future::BoxResultFuture;
+use protobuf::Message;
+use std::{
+    convert::{TryFrom, TryInto},
+    pin::Pin,
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+pub mod proto {
+    tonic::include_proto!("flowy.collaboration");
+}
+
+use proto::{
+    document_sync_server::{DocumentSync, DocumentSyncServer},
+    ClientRevisionPayload, DocumentPayload, DocumentRequest, PullRevisionsRequest, RevisionsPayload,
+    ServerRevisionPayload,
+};
+
+/// The metadata of the calls that holds the token of the caller, as `Bearer <token>`.
+pub const AUTHORIZATION_METADATA: &str = "authorization";
+
+/// Tells who the caller of the service is from its token, e.g. by checking the signature of a JWT
+/// or by asking the user service of the server.
+pub trait TokenVerifier: Send + Sync {
+    /// Returns the id of the user that the token was issued to, or an error if the token isn't
+    /// valid.
+    fn verify(&self, token: &str) -> BoxResultFuture<String, CollaborateError>;
+}
+
+pub struct DocumentSyncService {
+    manager: Arc<ServerDocumentManager>,
+    verifier: Arc<dyn TokenVerifier>,
+}
+
+impl DocumentSyncService {
+    pub fn new(manager: Arc<ServerDocumentManager>, verifier: Arc<dyn TokenVerifier>) -> Self {
+        Self { manager, verifier }
+    }
+
+    pub fn into_server(self) -> DocumentSyncServer<Self> {
+        DocumentSyncServer::new(self)
+    }
+}
+
+impl DocumentSyncService {
+    // The caller is the user of its token, whatever else its metadata claims.
+    async fn authenticate(&self, token: String) -> Result<String, Status> {
+        self.verifier
+            .verify(&token)
+            .await
+            .map_err(|e| Status::unauthenticated(e.msg))
+    }
+}
+
+type ServerRevisionStream = Pin<Box<dyn Stream<Item = Result<ServerRevisionPayload, Status>> + Send + 'static>>;
+
+#[tonic::async_trait]
+impl DocumentSync for DocumentSyncService {
+    async fn open_document(&self, request: Request<DocumentRequest>) -> Result<Response<DocumentPayload>, Status> {
+        let token = token_from(&request)?;
+        let user_id = self.authenticate(token).await?;
+        let doc_id = request.into_inner().doc_id;
+        let document_info = self.manager.open_document(&user_id, &doc_id).await.map_err(status)?;
+        let data: Bytes = document_info.try_into().map_err(status)?;
+        Ok(Response::new(DocumentPayload { data: data.to_vec() }))
+    }
+
+    type PushRevisionStream = ServerRevisionStream;
+
+    async fn push_revision(
+        &self,
+        request: Request<Streaming<ClientRevisionPayload>>,
+    ) -> Result<Response<Self::PushRevisionStream>, Status> {
+        let token = token_from(&request)?;
+        let user_id = self.authenticate(token).await?;
+        let mut client_stream = request.into_inner();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let user = Arc::new(StreamRevisionUser {
+            user_id,
+            sender: sender.clone(),
+        });
+        let manager = self.manager.clone();
+        tokio::spawn(async move {
+            loop {
+                let payload = match client_stream.message().await {
+                    Ok(Some(payload)) => payload,
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::warn!("The revision stream of {} failed: {}", user.user_id, e);
+                        break;
+                    }
+                };
+                if let Err(e) = handle_client_data(&manager, user.clone(), payload.data).await {
+                    let _ = sender.send(Err(status(e)));
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(UnboundedReceiverStream::new(receiver))))
+    }
+
+    async fn pull_revisions(
+        &self,
+        request: Request<PullRevisionsRequest>,
+    ) -> Result<Response<RevisionsPayload>, Status> {
+        let token = token_from(&request)?;
+        let user_id = self.authenticate(token).await?;
+        let request = request.into_inner();
+        let rev_ids = match (request.start, request.end) {
+            (0, 0) => None,
+            (start, end) => Some(RevisionRange { start, end }.iter().collect()),
+        };
+        let revisions = self
+            .manager
+            .read_revisions(&user_id, &request.doc_id, rev_ids)
+            .await
+            .map_err(status)?;
+        let data = repeated_revision_pb_from_revisions(revisions)
+            .write_to_bytes()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(RevisionsPayload { data }))
+    }
+
+    async fn snapshot(&self, request: Request<DocumentRequest>) -> Result<Response<DocumentPayload>, Status> {
+        let token = token_from(&request)?;
+        let user_id = self.authenticate(token).await?;
+        let doc_id = request.into_inner().doc_id;
+        let document_info = self.manager.read_snapshot(&user_id, &doc_id).await.map_err(status)?;
+        let data: Bytes = document_info.try_into().map_err(status)?;
+        Ok(Response::new(DocumentPayload { data: data.to_vec() }))
+    }
+}
+
+async fn handle_client_data(
+    manager: &ServerDocumentManager,
+    user: Arc<StreamRevisionUser>,
+    data: Vec<u8>,
+) -> Result<(), CollaborateError> {
+    let client_data = ClientRevisionWSData::try_from(Bytes::from(data))?;
+    let ty = client_data.ty.clone();
+    let client_data: ClientRevisionWSDataPB = client_data.try_into()?;
+    match ty {
+        ClientRevisionWSDataType::ClientPushRev => manager.handle_client_revisions(user, client_data).await,
+        ClientRevisionWSDataType::ClientPing => manager.handle_client_ping(user, client_data).await,
+    }
+}
+
+/// Sends what the server has for the client down the stream of its `PushRevision` call.
+#[derive(Debug)]
+struct StreamRevisionUser {
+    user_id: String,
+    sender: mpsc::UnboundedSender<Result<ServerRevisionPayload, Status>>,
+}
+
+impl RevisionUser for StreamRevisionUser {
+    fn user_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    fn receive(&self, resp: RevisionSyncResponse) {
+        let data = match resp {
+            RevisionSyncResponse::Pull(data) | RevisionSyncResponse::Push(data) | RevisionSyncResponse::Ack(data) => {
+                data
+            }
+        };
+        let result: Result<Bytes, CollaborateError> = data.try_into();
+        let payload = result
+            .map(|data| ServerRevisionPayload { data: data.to_vec() })
+            .map_err(status);
+        let _ = self.sender.send(payload);
+    }
+}
+
+fn token_from<T>(request: &Request<T>) -> Result<String, Status> {
+    request
+        .metadata()
+        .get(AUTHORIZATION_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.to_owned())
+        .ok_or_else(|| Status::unauthenticated(format!("Missing the bearer token of {}", AUTHORIZATION_METADATA)))
+}
+
+fn status(error: CollaborateError) -> Status {
+    match error.code {
+        ErrorCode::DocIdInvalid | ErrorCode::DeltaRejected | ErrorCode::ContentRejected => {
+            Status::invalid_argument(error.msg)
+        }
+        ErrorCode::DocNotfound | ErrorCode::RecordNotFound => Status::not_found(error.msg),
+        ErrorCode::PermissionDenied | ErrorCode::RegionLocked => Status::permission_denied(error.msg),
+        ErrorCode::Throttled | ErrorCode::QuotaExceeded => Status::resource_exhausted(error.msg),
+        _ => Status::internal(error.msg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entities::{
+            document_info::DocumentInfo,
+            revision::Revision,
+            ws_data::{ClientRevisionWSData, ServerRevisionWSData, ServerRevisionWSDataType},
+        },
+        errors::CollaborateError,
+        grpc::{
+            proto::{
+                document_sync_client::DocumentSyncClient, ClientRevisionPayload, DocumentRequest, PullRevisionsRequest,
+            },
+            DocumentSyncService, TokenVerifier, AUTHORIZATION_METADATA,
+        },
+        protobuf::RepeatedRevision as RepeatedRevisionPB,
+        server_document::{DocumentAccessControl, MemoryDocumentPersistence, ServerDocumentManager},
+    };
+    use bytes::Bytes;
+    use lib_infra::future::BoxResultFuture;
+    use lib_ot::rich_text::{RichTextAttributes, RichTextDeltaBuilder};
+    use protobuf::Message;
+    use std::{
+        convert::{TryFrom, TryInto},
+        sync::Arc,
+    };
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{
+        transport::{Channel, Server},
+        Code, Request,
+    };
+
+    // The token of a user is "<user_id>-token", alice and bob have one.
+    struct SuffixTokenVerifier;
+
+    impl TokenVerifier for SuffixTokenVerifier {
+        fn verify(&self, token: &str) -> BoxResultFuture<String, CollaborateError> {
+            let result = match token.strip_suffix("-token") {
+                Some(user_id @ ("alice" | "bob")) => Ok(user_id.to_owned()),
+                _ => Err(CollaborateError::permission_denied().context("Unknown token")),
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    // Only alice reads and writes the documents.
+    struct AliceOnly;
+
+    impl DocumentAccessControl for AliceOnly {
+        fn can_read(&self, user_id: &str, _doc_id: &str) -> bool {
+            user_id == "alice"
+        }
+
+        fn can_write(&self, user_id: &str, _doc_id: &str) -> bool {
+            user_id == "alice"
+        }
+
+        fn can_format(&self, user_id: &str, _doc_id: &str, _attributes: &RichTextAttributes) -> bool {
+            user_id == "alice"
+        }
+    }
+
+    async fn serve() -> DocumentSyncClient<Channel> {
+        let persistence = Arc::new(MemoryDocumentPersistence::default());
+        let manager = ServerDocumentManager::new(persistence).with_access_control(Arc::new(AliceOnly));
+        let service = DocumentSyncService::new(Arc::new(manager), Arc::new(SuffixTokenVerifier));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(service.into_server())
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        DocumentSyncClient::connect(format!("http://{}", addr)).await.unwrap()
+    }
+
+    // The `user-id` metadata is what the callers used to identify themselves with.
+    fn request<T>(message: T, token: Option<&str>, user_id: Option<&str>) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(token) = token {
+            let value = format!("Bearer {}", token).parse().unwrap();
+            request.metadata_mut().insert(AUTHORIZATION_METADATA, value);
+        }
+        if let Some(user_id) = user_id {
+            request.metadata_mut().insert("user-id", user_id.parse().unwrap());
+        }
+        request
+    }
+
+    fn document_request(doc_id: &str) -> DocumentRequest {
+        DocumentRequest {
+            doc_id: doc_id.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn revisions_round_trip_as_the_user_of_the_token() {
+        let mut client = serve().await;
+        let delta = RichTextDeltaBuilder::new().insert("abc").build();
+        let revision = Revision::new("a", 0, 1, delta.to_bytes(), "alice", "".to_owned());
+        let data: Bytes = ClientRevisionWSData::from_revisions("a", vec![revision])
+            .try_into()
+            .unwrap();
+        let payloads = tokio_stream::iter(vec![ClientRevisionPayload { data: data.to_vec() }]);
+        let mut responses = client
+            .push_revision(request(payloads, Some("alice-token"), None))
+            .await
+            .unwrap()
+            .into_inner();
+        let ack = responses.message().await.unwrap().unwrap();
+        let ack = ServerRevisionWSData::try_from(Bytes::from(ack.data)).unwrap();
+        assert_eq!(ack.ty, ServerRevisionWSDataType::ServerAck);
+
+        let document = client
+            .open_document(request(document_request("a"), Some("alice-token"), None))
+            .await
+            .unwrap()
+            .into_inner();
+        let document = DocumentInfo::try_from(Bytes::from(document.data)).unwrap();
+        assert_eq!(document.text, delta.to_json());
+        assert_eq!(document.rev_id, 1);
+
+        let pull = PullRevisionsRequest {
+            doc_id: "a".to_owned(),
+            start: 0,
+            end: 0,
+        };
+        let revisions = client
+            .pull_revisions(request(pull, Some("alice-token"), None))
+            .await
+            .unwrap()
+            .into_inner();
+        let revisions = RepeatedRevisionPB::parse_from_bytes(&revisions.data).unwrap();
+        assert_eq!(revisions.get_items().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn user_id_metadata_is_not_trusted() {
+        let mut client = serve().await;
+
+        // The caller that claims to be alice without a token, or with a forged one.
+        let status = client
+            .open_document(request(document_request("a"), None, Some("alice")))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        let status = client
+            .open_document(request(document_request("a"), Some("mallory-token"), Some("alice")))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        let payloads = tokio_stream::iter(Vec::<ClientRevisionPayload>::new());
+        let status = client
+            .push_revision(request(payloads, None, Some("alice")))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        // Bob is bob, whatever the metadata claims, and may not read the documents of alice.
+        let status = client
+            .open_document(request(document_request("a"), Some("bob-token"), Some("alice")))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+}
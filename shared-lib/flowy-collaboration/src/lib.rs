@@ -1,11 +1,18 @@
 pub mod client_document;
 pub mod client_folder;
+pub mod encryption;
 pub mod entities;
 pub mod errors;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod protobuf;
 pub mod server_document;
 pub mod server_folder;
+#[cfg(feature = "simulation")]
+pub mod simulation;
 pub mod synchronizer;
 pub mod util;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use lib_ot::rich_text::RichTextDelta;
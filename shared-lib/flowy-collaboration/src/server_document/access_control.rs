@@ -0,0 +1,154 @@
+use crate::{
+    errors::{CollaborateResult, PermissionDeniedError},
+    protobuf::Revision as RevisionPB,
+};
+use lib_ot::{
+    core::Operation,
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+
+/// Decides what the users may do with the documents, e.g. by their roles: the viewers read, the
+/// commenters format the text with the comments, the editors write. The
+/// [ServerDocumentManager](crate::server_document::ServerDocumentManager) consults it before it
+/// sends a document to a user or accepts the revisions of a user.
+pub trait DocumentAccessControl: Send + Sync {
+    fn can_read(&self, user_id: &str, doc_id: &str) -> bool;
+
+    /// Whether the user may insert and delete text.
+    fn can_write(&self, user_id: &str, doc_id: &str) -> bool;
+
+    /// Whether the user may give `attributes` to the text without changing the text.
+    fn can_format(&self, user_id: &str, doc_id: &str, attributes: &RichTextAttributes) -> bool;
+
+    /// Whether the user may write and format the locked text, and lock or unlock text, see
+    /// [check_protection](crate::client_document::protection::check_protection). Nobody may by
+    /// default.
+    fn can_unlock(&self, _user_id: &str, _doc_id: &str) -> bool {
+        false
+    }
+}
+
+/// What a user asks to do with a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentAction {
+    Read,
+    Write,
+    Format,
+}
+
+impl DocumentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentAction::Read => "read",
+            DocumentAction::Write => "write",
+            DocumentAction::Format => "format",
+        }
+    }
+}
+
+pub(crate) fn check_read(
+    access_control: &dyn DocumentAccessControl,
+    user_id: &str,
+    doc_id: &str,
+) -> CollaborateResult<()> {
+    match access_control.can_read(user_id, doc_id) {
+        true => Ok(()),
+        false => Err(PermissionDeniedError::new(user_id, doc_id, DocumentAction::Read, None).into()),
+    }
+}
+
+/// Checks that the user may make every operation of `revisions`, and returns the first one that
+/// the user may not make otherwise.
+pub(crate) fn check_revisions(
+    access_control: &dyn DocumentAccessControl,
+    user_id: &str,
+    doc_id: &str,
+    revisions: &[RevisionPB],
+) -> CollaborateResult<()> {
+    let _ = check_read(access_control, user_id, doc_id)?;
+    let can_write = access_control.can_write(user_id, doc_id);
+    for revision in revisions {
+        let delta = RichTextDelta::from_bytes(&revision.delta_data)?;
+        for op in delta.ops {
+            let action = match &op {
+                Operation::Insert(_) | Operation::Delete(_) if !can_write => DocumentAction::Write,
+                Operation::Retain(retain)
+                    if !retain.attributes.is_empty()
+                        && !access_control.can_format(user_id, doc_id, &retain.attributes) =>
+                {
+                    DocumentAction::Format
+                }
+                _ => continue,
+            };
+            return Err(PermissionDeniedError::new(user_id, doc_id, action, Some(op)).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entities::revision::Revision,
+        errors::ErrorCode,
+        protobuf::Revision as RevisionPB,
+        server_document::access_control::{check_revisions, DocumentAccessControl},
+    };
+    use lib_ot::rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextDeltaBuilder};
+    use std::convert::TryInto;
+
+    // The user "editor" writes, "commenter" only highlights the text, "viewer" reads.
+    struct Roles();
+    impl DocumentAccessControl for Roles {
+        fn can_read(&self, user_id: &str, _doc_id: &str) -> bool {
+            user_id != "stranger"
+        }
+
+        fn can_write(&self, user_id: &str, _doc_id: &str) -> bool {
+            user_id == "editor"
+        }
+
+        fn can_format(&self, user_id: &str, _doc_id: &str, attributes: &RichTextAttributes) -> bool {
+            match user_id {
+                "editor" => true,
+                "commenter" => attributes.keys().all(|key| key == &RichTextAttributeKey::Background),
+                _ => false,
+            }
+        }
+    }
+
+    fn revision(delta: RichTextDeltaBuilder) -> RevisionPB {
+        let revision = Revision::new("doc", 0, 1, delta.build().to_bytes(), "user", "".to_owned());
+        revision.try_into().unwrap()
+    }
+
+    #[test]
+    fn revisions_checked_against_roles() {
+        let highlight = revision(
+            RichTextDeltaBuilder::new()
+                .retain(2)
+                .retain_with_attributes(3, RichTextAttribute::Background("yellow".to_owned()).into()),
+        );
+        let bold =
+            revision(RichTextDeltaBuilder::new().retain_with_attributes(3, RichTextAttribute::Bold(true).into()));
+        let insert = revision(RichTextDeltaBuilder::new().retain(2).insert("abc"));
+        let check =
+            |user_id: &str, revision: &RevisionPB| check_revisions(&Roles(), user_id, "doc", &[revision.clone()]);
+
+        assert!(check("editor", &insert).is_ok());
+        assert!(check("commenter", &highlight).is_ok());
+        let error = check("commenter", &bold).unwrap_err();
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+        assert_eq!(
+            error.msg,
+            r#"The user commenter may not format the document doc: {"retain":3,"attributes":{"bold":true}}"#
+        );
+        let error = check("viewer", &insert).unwrap_err();
+        assert_eq!(
+            error.msg,
+            r#"The user viewer may not write the document doc: {"insert":"abc"}"#
+        );
+        let error = check("stranger", &highlight).unwrap_err();
+        assert_eq!(error.msg, "The user stranger may not read the document doc");
+    }
+}
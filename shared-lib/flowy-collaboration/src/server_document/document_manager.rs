@@ -2,19 +2,23 @@ use crate::{
     entities::{document_info::DocumentInfo, ws_data::ServerRevisionWSDataBuilder},
     errors::{internal_error, CollaborateError, CollaborateResult},
     protobuf::{ClientRevisionWSData, RepeatedRevision as RepeatedRevisionPB, Revision as RevisionPB},
-    server_document::document_pad::ServerDocument,
+    server_document::{
+        access_control::{check_read, check_revisions, DocumentAccessControl},
+        document_pad::ServerDocument,
+        rate_limit::RateLimiter,
+        session::{DocumentSessions, LeaveReason, SessionConfig, SessionEvent},
+    },
     synchronizer::{RevisionSyncPersistence, RevisionSyncResponse, RevisionSynchronizer, RevisionUser},
     util::rev_id_from_str,
 };
 use async_stream::stream;
-use dashmap::DashMap;
-use futures::stream::StreamExt;
+use futures::{future::join_all, stream::StreamExt};
 use lib_infra::future::BoxResultFuture;
-use lib_ot::rich_text::{RichTextAttributes, RichTextDelta};
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use lib_ot::rich_text::{ComposeConfig, RichTextAttributes, RichTextDelta};
+use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Instant};
 use tokio::{
-    sync::{mpsc, oneshot, RwLock},
-    task::spawn_blocking,
+    sync::{broadcast, mpsc, oneshot, RwLock},
+    task::{spawn, spawn_blocking},
 };
 
 pub trait DocumentCloudPersistence: Send + Sync + Debug {
@@ -39,6 +43,12 @@ pub trait DocumentCloudPersistence: Send + Sync + Debug {
         doc_id: &str,
         repeated_revision: RepeatedRevisionPB,
     ) -> BoxResultFuture<(), CollaborateError>;
+
+    /// Called with the document as the server has it when the document is closed, e.g. to save it
+    /// as the snapshot and drop the revisions before it. Does nothing by default.
+    fn compact_document(&self, _document_info: DocumentInfo) -> BoxResultFuture<(), CollaborateError> {
+        Box::pin(async { Ok(()) })
+    }
 }
 
 impl RevisionSyncPersistence for Arc<dyn DocumentCloudPersistence> {
@@ -66,16 +76,64 @@ impl RevisionSyncPersistence for Arc<dyn DocumentCloudPersistence> {
 pub struct ServerDocumentManager {
     document_handlers: Arc<RwLock<HashMap<String, Arc<OpenDocumentHandler>>>>,
     persistence: Arc<dyn DocumentCloudPersistence>,
+    access_control: Option<Arc<dyn DocumentAccessControl>>,
+    rate_limiter: Option<RateLimiter>,
+    session_config: SessionConfig,
+    compose_config: ComposeConfig,
+    session_notifier: broadcast::Sender<SessionEvent>,
 }
 
 impl ServerDocumentManager {
     pub fn new(persistence: Arc<dyn DocumentCloudPersistence>) -> Self {
+        let (session_notifier, _) = broadcast::channel(100);
         Self {
             document_handlers: Arc::new(RwLock::new(HashMap::new())),
             persistence,
+            access_control: None,
+            rate_limiter: None,
+            session_config: SessionConfig::default(),
+            compose_config: ComposeConfig::default(),
+            session_notifier,
         }
     }
 
+    /// Lets only the users that `access_control` allows read the documents and apply revisions to
+    /// them. The revisions of the other users are rejected with
+    /// [ErrorCode::PermissionDenied](crate::errors::ErrorCode::PermissionDenied), without an ack.
+    pub fn with_access_control(mut self, access_control: Arc<dyn DocumentAccessControl>) -> Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
+    /// Rejects the revisions of the clients that are over the limits of `rate_limiter` with
+    /// [ErrorCode::Throttled](crate::errors::ErrorCode::Throttled), without an ack. The clients send
+    /// them again later.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Times out the sessions and closes the documents after the intervals of `session_config`,
+    /// see [ServerDocumentManager::sweep].
+    pub fn with_session_config(mut self, session_config: SessionConfig) -> Self {
+        self.session_config = session_config;
+        self
+    }
+
+    /// Composes and transforms the revisions of the clients with `compose_config`, which must be the one of the
+    /// [DocumentConfig](crate::client_document::DocumentConfig) of the clients.
+    pub fn with_compose_config(mut self, compose_config: ComposeConfig) -> Self {
+        self.compose_config = compose_config;
+        self
+    }
+
+    /// Returns the receiver of the [SessionEvent]s of the documents. The host forwards the
+    /// `UserLeft` events to the other clients of the document, which remove the selection of the
+    /// user from their [Presence](crate::client_document::presence::Presence).
+    pub fn subscribe_sessions(&self) -> broadcast::Receiver<SessionEvent> {
+        self.session_notifier.subscribe()
+    }
+
     pub async fn handle_client_revisions(
         &self,
         user: Arc<dyn RevisionUser>,
@@ -85,16 +143,29 @@ impl ServerDocumentManager {
         let cloned_user = user.clone();
         let ack_id = rev_id_from_str(&client_data.data_id)?;
         let object_id = client_data.object_id;
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let _ = rate_limiter.check(&user.user_id(), repeated_revision.get_items())?;
+        }
+        if let Some(access_control) = &self.access_control {
+            let _ = check_revisions(
+                access_control.as_ref(),
+                &user.user_id(),
+                &object_id,
+                repeated_revision.get_items(),
+            )?;
+        }
 
         let result = match self.get_document_handler(&object_id).await {
             None => {
                 tracing::trace!("Can't find the document. Creating the document {}", object_id);
-                let _ = self.create_document(&object_id, repeated_revision).await.map_err(|e| {
+                let handler = self.create_document(&object_id, repeated_revision).await.map_err(|e| {
                     CollaborateError::internal().context(format!("Server create document failed: {}", e))
                 })?;
+                self.touch_session(&handler, &user.user_id());
                 Ok(())
             }
             Some(handler) => {
+                self.touch_session(&handler, &user.user_id());
                 let _ = handler.apply_revisions(user, repeated_revision).await?;
                 Ok(())
             }
@@ -108,6 +179,59 @@ impl ServerDocumentManager {
         result
     }
 
+    /// Applies the revisions of many clients at once, e.g. the revisions that the clients queued
+    /// while they were offline and send when they reconnect, like
+    /// [ServerDocumentManager::handle_client_revisions] does for each of them. The messages of a
+    /// document are applied one after another, in the order of the batch. The documents don't
+    /// depend on each other, so each one is applied on a task of its own, in parallel on the
+    /// threads of the runtime. The results are in the order of the batch.
+    pub async fn transform_batch(
+        self: &Arc<Self>,
+        batch: Vec<(Arc<dyn RevisionUser>, ClientRevisionWSData)>,
+    ) -> Vec<Result<(), CollaborateError>> {
+        let len = batch.len();
+        let mut sessions: HashMap<String, Vec<_>> = HashMap::new();
+        for (index, (user, client_data)) in batch.into_iter().enumerate() {
+            sessions
+                .entry(client_data.object_id.clone())
+                .or_insert_with(Vec::new)
+                .push((index, user, client_data));
+        }
+        let (indices, tasks): (Vec<_>, Vec<_>) = sessions
+            .into_values()
+            .map(|messages| {
+                let indices = messages.iter().map(|(index, _, _)| *index).collect::<Vec<_>>();
+                let manager = self.clone();
+                let task = spawn(async move {
+                    let mut results = Vec::with_capacity(messages.len());
+                    for (_, user, client_data) in messages {
+                        results.push(manager.handle_client_revisions(user, client_data).await);
+                    }
+                    results
+                });
+                (indices, task)
+            })
+            .unzip();
+
+        let mut results = (0..len).map(|_| Ok(())).collect::<Vec<_>>();
+        for (indices, task) in indices.into_iter().zip(join_all(tasks).await) {
+            match task {
+                Ok(task_results) => {
+                    for (index, result) in indices.into_iter().zip(task_results) {
+                        results[index] = result;
+                    }
+                }
+                Err(e) => {
+                    for index in indices {
+                        results[index] = Err(CollaborateError::internal()
+                            .context(format!("Apply the revisions of the batch failed: {}", e)));
+                    }
+                }
+            }
+        }
+        results
+    }
+
     pub async fn handle_client_ping(
         &self,
         user: Arc<dyn RevisionUser>,
@@ -115,18 +239,128 @@ impl ServerDocumentManager {
     ) -> Result<(), CollaborateError> {
         let rev_id = rev_id_from_str(&client_data.data_id)?;
         let doc_id = client_data.object_id.clone();
+        let _ = self.check_read(&user.user_id(), &doc_id)?;
         match self.get_document_handler(&doc_id).await {
             None => {
                 tracing::trace!("Document:{} doesn't exist, ignore client ping", doc_id);
                 Ok(())
             }
             Some(handler) => {
+                self.touch_session(&handler, &user.user_id());
                 let _ = handler.apply_ping(rev_id, user).await?;
                 Ok(())
             }
         }
     }
 
+    /// Adds `user_id` to the clients of the document, and returns the document like
+    /// [ServerDocumentManager::open_document]. The client stays in the document while it sends
+    /// revisions or pings, at least every `heartbeat_timeout` of the [SessionConfig].
+    pub async fn join_document(&self, user_id: &str, doc_id: &str) -> CollaborateResult<DocumentInfo> {
+        let _ = self.check_read(user_id, doc_id)?;
+        match self.get_document_handler(doc_id).await {
+            None => Err(CollaborateError::record_not_found().context(format!("Can't find the document {}", doc_id))),
+            Some(handler) => {
+                self.touch_session(&handler, user_id);
+                Ok(handler.document_info())
+            }
+        }
+    }
+
+    /// Removes `user_id` from the clients of the document.
+    pub async fn leave_document(&self, user_id: &str, doc_id: &str) {
+        let handler = match self.document_handlers.read().await.get(doc_id).cloned() {
+            None => return,
+            Some(handler) => handler,
+        };
+        if handler.sessions.remove(user_id, Instant::now()) {
+            self.notify_session(SessionEvent::UserLeft {
+                doc_id: doc_id.to_owned(),
+                user_id: user_id.to_owned(),
+                reason: LeaveReason::Left,
+            });
+        }
+    }
+
+    /// Returns the ids of the users in the document, or none if it isn't open.
+    pub async fn document_users(&self, doc_id: &str) -> Vec<String> {
+        match self.document_handlers.read().await.get(doc_id) {
+            None => vec![],
+            Some(handler) => handler.sessions.user_ids(),
+        }
+    }
+
+    /// Removes the clients that missed their heartbeats, and closes the documents that nobody was in
+    /// for the `idle_timeout` of the [SessionConfig]. A closed document is compacted with
+    /// [DocumentCloudPersistence::compact_document] and opened again from the persistence when it's
+    /// used. The clients that the [RateLimiter] doesn't hold back anymore are forgotten too. The
+    /// host calls it periodically, e.g. every few seconds.
+    pub async fn sweep(&self) {
+        self.sweep_at(Instant::now()).await
+    }
+
+    async fn sweep_at(&self, now: Instant) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.sweep_at(now);
+        }
+        let handlers = self.document_handlers.read().await.clone();
+        for (doc_id, handler) in handlers {
+            for user_id in handler.sessions.expire(self.session_config.heartbeat_timeout, now) {
+                tracing::trace!("{} timed out of the document {}", user_id, doc_id);
+                self.notify_session(SessionEvent::UserLeft {
+                    doc_id: doc_id.clone(),
+                    user_id,
+                    reason: LeaveReason::TimedOut,
+                });
+            }
+
+            let idle_timeout = self.session_config.idle_timeout;
+            if !handler.sessions.is_idle(idle_timeout, now) {
+                continue;
+            }
+            let mut write_guard = self.document_handlers.write().await;
+            // A client may have joined since.
+            if !handler.sessions.is_idle(idle_timeout, now) {
+                continue;
+            }
+            write_guard.remove(&doc_id);
+            drop(write_guard);
+
+            tracing::trace!("Close the idle document {}", doc_id);
+            if let Err(e) = self.persistence.compact_document(handler.document_info()).await {
+                tracing::error!("Compact the document {} failed: {}", doc_id, e);
+            }
+            self.notify_session(SessionEvent::DocumentClosed { doc_id });
+        }
+    }
+
+    /// Returns the document as the server has it now, with the revisions that aren't in the
+    /// snapshot of [DocumentCloudPersistence] yet, and keeps it open for the revisions of `user_id`.
+    pub async fn open_document(&self, user_id: &str, doc_id: &str) -> CollaborateResult<DocumentInfo> {
+        let _ = self.check_read(user_id, doc_id)?;
+        match self.get_document_handler(doc_id).await {
+            None => Err(CollaborateError::record_not_found().context(format!("Can't find the document {}", doc_id))),
+            Some(handler) => Ok(handler.document_info()),
+        }
+    }
+
+    /// Returns the revisions of the document whose ids are `rev_ids`, or all of them if it's `None`.
+    pub async fn read_revisions(
+        &self,
+        user_id: &str,
+        doc_id: &str,
+        rev_ids: Option<Vec<i64>>,
+    ) -> CollaborateResult<Vec<RevisionPB>> {
+        let _ = self.check_read(user_id, doc_id)?;
+        self.persistence.read_document_revisions(doc_id, rev_ids).await
+    }
+
+    /// Returns the last snapshot of the document that [DocumentCloudPersistence] saved.
+    pub async fn read_snapshot(&self, user_id: &str, doc_id: &str) -> CollaborateResult<DocumentInfo> {
+        let _ = self.check_read(user_id, doc_id)?;
+        self.persistence.read_document(doc_id).await
+    }
+
     pub async fn handle_document_reset(
         &self,
         doc_id: &str,
@@ -145,6 +379,27 @@ impl ServerDocumentManager {
         }
     }
 
+    fn touch_session(&self, handler: &OpenDocumentHandler, user_id: &str) {
+        if handler.sessions.touch(user_id, Instant::now()) {
+            self.notify_session(SessionEvent::UserJoined {
+                doc_id: handler.doc_id.clone(),
+                user_id: user_id.to_owned(),
+            });
+        }
+    }
+
+    fn notify_session(&self, event: SessionEvent) {
+        // There may be no receivers.
+        let _ = self.session_notifier.send(event);
+    }
+
+    fn check_read(&self, user_id: &str, doc_id: &str) -> CollaborateResult<()> {
+        match &self.access_control {
+            None => Ok(()),
+            Some(access_control) => check_read(access_control.as_ref(), user_id, doc_id),
+        }
+    }
+
     async fn get_document_handler(&self, doc_id: &str) -> Option<Arc<OpenDocumentHandler>> {
         if let Some(handler) = self.document_handlers.read().await.get(doc_id).cloned() {
             return Some(handler);
@@ -183,7 +438,9 @@ impl ServerDocumentManager {
 
     async fn create_document_handler(&self, doc: DocumentInfo) -> Result<Arc<OpenDocumentHandler>, CollaborateError> {
         let persistence = self.persistence.clone();
-        let handle = spawn_blocking(|| OpenDocumentHandler::new(doc, persistence))
+        let access_control = self.access_control.clone();
+        let compose_config = self.compose_config.clone();
+        let handle = spawn_blocking(|| OpenDocumentHandler::new(doc, persistence, access_control, compose_config))
             .await
             .map_err(|e| CollaborateError::internal().context(format!("Create document handler failed: {}", e)))?;
         Ok(Arc::new(handle?))
@@ -192,7 +449,7 @@ impl ServerDocumentManager {
 
 impl std::ops::Drop for ServerDocumentManager {
     fn drop(&mut self) {
-        log::trace!("ServerDocumentManager was dropped");
+        tracing::trace!("ServerDocumentManager was dropped");
     }
 }
 
@@ -201,22 +458,45 @@ type DocumentRevisionSynchronizer = RevisionSynchronizer<RichTextAttributes>;
 struct OpenDocumentHandler {
     doc_id: String,
     sender: mpsc::Sender<DocumentCommand>,
-    users: DashMap<String, Arc<dyn RevisionUser>>,
+    sessions: DocumentSessions,
+    synchronizer: Arc<DocumentRevisionSynchronizer>,
 }
 
 impl OpenDocumentHandler {
-    fn new(doc: DocumentInfo, persistence: Arc<dyn DocumentCloudPersistence>) -> Result<Self, CollaborateError> {
+    fn new(
+        doc: DocumentInfo,
+        persistence: Arc<dyn DocumentCloudPersistence>,
+        access_control: Option<Arc<dyn DocumentAccessControl>>,
+        compose_config: ComposeConfig,
+    ) -> Result<Self, CollaborateError> {
         let doc_id = doc.doc_id.clone();
         let (sender, receiver) = mpsc::channel(1000);
-        let users = DashMap::new();
+        let sessions = DocumentSessions::new(Instant::now());
 
         let delta = RichTextDelta::from_bytes(&doc.text)?;
-        let sync_object = ServerDocument::from_delta(&doc_id, delta);
+        let sync_object = ServerDocument::from_delta(&doc_id, delta)
+            .with_access_control(access_control)
+            .with_compose_config(compose_config);
         let synchronizer = Arc::new(DocumentRevisionSynchronizer::new(doc.rev_id, sync_object, persistence));
 
-        let queue = DocumentCommandRunner::new(&doc.doc_id, receiver, synchronizer);
+        let queue = DocumentCommandRunner::new(&doc.doc_id, receiver, synchronizer.clone());
         tokio::task::spawn(queue.run());
-        Ok(Self { doc_id, sender, users })
+        Ok(Self {
+            doc_id,
+            sender,
+            sessions,
+            synchronizer,
+        })
+    }
+
+    fn document_info(&self) -> DocumentInfo {
+        let rev_id = self.synchronizer.rev_id();
+        DocumentInfo {
+            doc_id: self.doc_id.clone(),
+            text: self.synchronizer.object_json(),
+            rev_id,
+            base_rev_id: rev_id,
+        }
     }
 
     #[tracing::instrument(
@@ -231,7 +511,6 @@ impl OpenDocumentHandler {
         repeated_revision: RepeatedRevisionPB,
     ) -> Result<(), CollaborateError> {
         let (ret, rx) = oneshot::channel();
-        self.users.insert(user.user_id(), user.clone());
         let msg = DocumentCommand::ApplyRevisions {
             user,
             repeated_revision,
@@ -244,7 +523,6 @@ impl OpenDocumentHandler {
 
     async fn apply_ping(&self, rev_id: i64, user: Arc<dyn RevisionUser>) -> Result<(), CollaborateError> {
         let (ret, rx) = oneshot::channel();
-        self.users.insert(user.user_id(), user.clone());
         let msg = DocumentCommand::Ping { user, rev_id, ret };
         let result = self.send(msg, rx).await?;
         result
@@ -359,3 +637,165 @@ impl std::ops::Drop for DocumentCommandRunner {
         tracing::trace!("{} DocumentCommandQueue was dropped", self.doc_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entities::{revision::Revision, ws_data::ClientRevisionWSData},
+        protobuf::ClientRevisionWSData as ClientRevisionWSDataPB,
+        server_document::{
+            LeaveReason, MemoryDocumentPersistence, ServerDocumentManager, SessionConfig, SessionEvent, SimulatedUser,
+        },
+        synchronizer::RevisionUser,
+    };
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+    use parking_lot::Mutex;
+    use std::{
+        convert::TryInto,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    // The revisions that insert the characters of `text` one after another, from `rev_id` on, into
+    // the document that has `len` characters.
+    fn typing(doc_id: &str, rev_id: i64, len: usize, text: &str) -> (Arc<dyn RevisionUser>, ClientRevisionWSDataPB) {
+        let revisions = text
+            .chars()
+            .enumerate()
+            .map(|(offset, c)| {
+                let delta = RichTextDeltaBuilder::new()
+                    .retain(len + offset)
+                    .insert(&c.to_string())
+                    .build();
+                let rev_id = rev_id + offset as i64;
+                Revision::new(doc_id, rev_id - 1, rev_id, delta.to_bytes(), "user", "".to_owned())
+            })
+            .collect();
+        let data = ClientRevisionWSData::from_revisions(doc_id, revisions);
+        let user = SimulatedUser::new("user", 0, Arc::new(Mutex::new(vec![])));
+        (Arc::new(user), data.try_into().unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batch_keeps_the_order_of_each_document() {
+        let persistence = Arc::new(MemoryDocumentPersistence::default());
+        let manager = Arc::new(ServerDocumentManager::new(persistence.clone()));
+        let batch = vec![
+            typing("a", 1, 0, "a"),
+            typing("b", 1, 0, "x"),
+            typing("a", 2, 1, "bc"),
+            typing("b", 2, 1, "y"),
+            typing("a", 4, 3, "d"),
+        ];
+        let results = manager.transform_batch(batch).await;
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        for (doc_id, text) in [("a", "abcd"), ("b", "xy")] {
+            let document_info = manager.open_document("user", doc_id).await.unwrap();
+            assert_eq!(
+                document_info.text,
+                RichTextDeltaBuilder::new().insert(text).build().to_json()
+            );
+            assert_eq!(document_info.rev_id, text.len() as i64);
+            assert_eq!(persistence.read(doc_id, None).len(), text.len());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn batch_of_many_documents_keeps_the_order_of_each_one() {
+        let persistence = Arc::new(MemoryDocumentPersistence::default());
+        let manager = Arc::new(ServerDocumentManager::new(persistence.clone()));
+        let doc_ids = (0..8).map(|n| format!("doc{}", n)).collect::<Vec<_>>();
+        let text = "abcdefghijklmnopqrst";
+        // The messages of the documents are interleaved, each one types the next character.
+        let mut batch = vec![];
+        for (offset, c) in text.chars().enumerate() {
+            for doc_id in &doc_ids {
+                batch.push(typing(doc_id, offset as i64 + 1, offset, &c.to_string()));
+            }
+        }
+        let results = manager.transform_batch(batch).await;
+        assert_eq!(results.len(), doc_ids.len() * text.len());
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        for doc_id in &doc_ids {
+            let document_info = manager.open_document("user", doc_id).await.unwrap();
+            assert_eq!(
+                document_info.text,
+                RichTextDeltaBuilder::new().insert(text).build().to_json()
+            );
+            assert_eq!(document_info.rev_id, text.len() as i64);
+            assert_eq!(persistence.read(doc_id, None).len(), text.len());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn duplicate_revisions_are_applied_once() {
+        let persistence = Arc::new(MemoryDocumentPersistence::default());
+        let manager = ServerDocumentManager::new(persistence.clone());
+        // Each batch is sent, then the document must be `text`, every revision saved once.
+        let batches = vec![
+            (typing("a", 1, 0, "abc"), "abc"),
+            // The batch is sent again, e.g. because the ack was lost.
+            (typing("a", 1, 0, "abc"), "abc"),
+            // The last revision is sent again with the ones made since then.
+            (typing("a", 3, 2, "cde"), "abcde"),
+            (typing("a", 6, 5, "f"), "abcdef"),
+        ];
+        for ((user, data), text) in batches {
+            manager.handle_client_revisions(user, data).await.unwrap();
+            let document_info = manager.open_document("user", "a").await.unwrap();
+            assert_eq!(
+                document_info.text,
+                RichTextDeltaBuilder::new().insert(text).build().to_json()
+            );
+            assert_eq!(document_info.rev_id, text.len() as i64);
+            assert_eq!(persistence.read("a", None).len(), text.len());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stale_sessions_time_out_and_idle_documents_close() {
+        let config = SessionConfig {
+            heartbeat_timeout: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(60),
+        };
+        let manager =
+            ServerDocumentManager::new(Arc::new(MemoryDocumentPersistence::default())).with_session_config(config);
+        let mut events = manager.subscribe_sessions();
+        let (user, data) = typing("a", 1, 0, "a");
+        manager.handle_client_revisions(user, data).await.unwrap();
+        let joined = SessionEvent::UserJoined {
+            doc_id: "a".to_owned(),
+            user_id: "user".to_owned(),
+        };
+        assert_eq!(events.try_recv().unwrap(), joined);
+        assert_eq!(manager.document_users("a").await, vec!["user".to_owned()]);
+
+        let now = Instant::now();
+        manager.sweep_at(now).await;
+        assert!(events.try_recv().is_err());
+        manager.sweep_at(now + Duration::from_secs(30)).await;
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SessionEvent::UserLeft {
+                doc_id: "a".to_owned(),
+                user_id: "user".to_owned(),
+                reason: LeaveReason::TimedOut,
+            }
+        );
+        assert!(manager.document_users("a").await.is_empty());
+
+        // The document closes once it was idle for the interval, and opens again when it's used.
+        manager.sweep_at(now + Duration::from_secs(60)).await;
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SessionEvent::DocumentClosed { doc_id: "a".to_owned() }
+        );
+        assert!(!manager.document_handlers.read().await.contains_key("a"));
+        let document_info = manager.join_document("user", "a").await.unwrap();
+        assert_eq!(document_info.rev_id, 1);
+        assert_eq!(events.try_recv().unwrap(), joined);
+    }
+}
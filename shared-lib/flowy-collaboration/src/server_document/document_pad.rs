@@ -1,12 +1,20 @@
-use crate::{client_document::InitialDocumentText, errors::CollaborateError, synchronizer::RevisionSyncObject};
+use crate::{
+    client_document::{protection::check_protection, InitialDocumentText},
+    errors::CollaborateError,
+    server_document::DocumentAccessControl,
+    synchronizer::RevisionSyncObject,
+};
 use lib_ot::{
     core::*,
-    rich_text::{RichTextAttributes, RichTextDelta},
+    rich_text::{ComposeConfig, RichTextAttributes, RichTextDelta},
 };
+use std::sync::Arc;
 
 pub struct ServerDocument {
     doc_id: String,
     delta: RichTextDelta,
+    access_control: Option<Arc<dyn DocumentAccessControl>>,
+    compose_config: ComposeConfig,
 }
 
 impl ServerDocument {
@@ -17,7 +25,26 @@ impl ServerDocument {
 
     pub fn from_delta(doc_id: &str, delta: RichTextDelta) -> Self {
         let doc_id = doc_id.to_owned();
-        ServerDocument { doc_id, delta }
+        ServerDocument {
+            doc_id,
+            delta,
+            access_control: None,
+            compose_config: ComposeConfig::default(),
+        }
+    }
+
+    /// Lets the users that `access_control` allows change the locked text, see
+    /// [DocumentAccessControl::can_unlock]. Nobody may without it.
+    pub fn with_access_control(mut self, access_control: Option<Arc<dyn DocumentAccessControl>>) -> Self {
+        self.access_control = access_control;
+        self
+    }
+
+    /// Composes and transforms the changes of the clients with `compose_config`, see
+    /// [ServerDocumentManager::with_compose_config](crate::server_document::ServerDocumentManager::with_compose_config).
+    pub fn with_compose_config(mut self, compose_config: ComposeConfig) -> Self {
+        self.compose_config = compose_config;
+        self
     }
 }
 
@@ -28,13 +55,17 @@ impl RevisionSyncObject<RichTextAttributes> for ServerDocument {
 
     fn compose(&mut self, other: &RichTextDelta) -> Result<(), CollaborateError> {
         // tracing::trace!("{} compose {}", &self.delta.to_json(), other.to_json());
-        let new_delta = self.delta.compose(other)?;
+        // The delta was sent by a client, it's checked before it can corrupt the document.
+        other.validate_against(self.delta.utf16_target_len)?;
+        let new_delta = self.delta.compose_with(other, &self.compose_config)?;
         self.delta = new_delta;
         Ok(())
     }
 
     fn transform(&self, other: &RichTextDelta) -> Result<(RichTextDelta, RichTextDelta), CollaborateError> {
-        let value = self.delta.transform(other)?;
+        // The revisions of the server were made before the ones of the client that it hadn't seen,
+        // so their text goes first, which is what the clients do too.
+        let value = self.delta.transform_with(other, Priority::Left, &self.compose_config)?;
         Ok(value)
     }
 
@@ -45,4 +76,15 @@ impl RevisionSyncObject<RichTextAttributes> for ServerDocument {
     fn set_delta(&mut self, new_delta: Delta<RichTextAttributes>) {
         self.delta = new_delta;
     }
+
+    fn check_delta(&self, user_id: &str, delta: &RichTextDelta) -> Result<(), CollaborateError> {
+        let can_unlock = self
+            .access_control
+            .as_ref()
+            .map_or(false, |access_control| access_control.can_unlock(user_id, &self.doc_id));
+        if !can_unlock {
+            let _ = check_protection(&self.delta, delta)?;
+        }
+        Ok(())
+    }
 }
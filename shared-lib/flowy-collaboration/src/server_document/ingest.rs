@@ -0,0 +1,283 @@
+use lib_ot::{
+    core::{Embed, Operation},
+    rich_text::{RichTextAttributeKey, RichTextAttributes, RichTextDelta},
+};
+use serde_json::Value;
+use std::fmt;
+
+/// What the server accepts from the network, see [ingest_untrusted].
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    /// The number of the operations of a delta.
+    pub max_ops: usize,
+    /// The length of an insert, in utf16 code units.
+    pub max_insert_len: usize,
+    /// The length of the document after the delta, not enforced if `None`.
+    pub max_document_len: Option<usize>,
+    /// The length of the value of an attribute. The longer values are removed.
+    pub max_attribute_len: usize,
+    /// The kinds of the embeds, e.g. "image". All the kinds are accepted if `None`.
+    pub embed_kinds: Option<Vec<String>>,
+    /// The size of the json payload of an embed, in bytes.
+    pub max_embed_len: usize,
+    /// The schemes of the urls of the links and of the embeds, the links with another scheme are
+    /// removed. The relative urls have no scheme and are kept.
+    pub url_schemes: Vec<String>,
+}
+
+impl std::default::Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            max_ops: 10_000,
+            max_insert_len: 1 << 20,
+            max_document_len: None,
+            max_attribute_len: 2048,
+            embed_kinds: None,
+            max_embed_len: 64 * 1024,
+            url_schemes: vec!["http".to_owned(), "https".to_owned(), "mailto".to_owned()],
+        }
+    }
+}
+
+/// Why an untrusted delta was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// The delta isn't canonical or doesn't apply to the document.
+    InvalidDelta {
+        msg: String,
+    },
+    TooManyOps {
+        ops: usize,
+        max: usize,
+    },
+    InsertTooLong {
+        len: usize,
+        max: usize,
+    },
+    DocumentTooLong {
+        len: usize,
+        max: usize,
+    },
+    EmbedNotAllowed {
+        kind: String,
+    },
+    EmbedTooLarge {
+        kind: String,
+        size: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectionReason::InvalidDelta { msg } => write!(f, "an invalid delta: {}", msg),
+            RejectionReason::TooManyOps { ops, max } => write!(f, "a delta of {} operations, over {}", ops, max),
+            RejectionReason::InsertTooLong { len, max } => write!(f, "an insert of {} characters, over {}", len, max),
+            RejectionReason::DocumentTooLong { len, max } => {
+                write!(f, "a document of {} characters, over {}", len, max)
+            }
+            RejectionReason::EmbedNotAllowed { kind } => write!(f, "an embed of the kind {}", kind),
+            RejectionReason::EmbedTooLarge { kind, size, max } => {
+                write!(f, "an embed {} of {} bytes, over {}", kind, size, max)
+            }
+        }
+    }
+}
+
+/// Checks the delta that arrived over the network before it's applied to a document of
+/// `document_len`. It's meant to be the one place that the deltas of the clients, the imports and
+/// the other untrusted sources go through.
+/// The delta is rejected if it's invalid or over the limits of `config`. Otherwise the delta is
+/// cleaned and returned: the attributes whose values don't parse as the type of their key, that
+/// are too long or that link to a url of another scheme are removed, and so are the urls of the
+/// embeds with another scheme. The cleaned delta has the same length as the delta, but it differs
+/// from the one that the sender applied, so the sender must be given the cleaned one.
+pub fn ingest_untrusted(
+    delta: &RichTextDelta,
+    document_len: usize,
+    config: &IngestConfig,
+) -> Result<RichTextDelta, RejectionReason> {
+    if delta.ops.len() > config.max_ops {
+        return Err(RejectionReason::TooManyOps {
+            ops: delta.ops.len(),
+            max: config.max_ops,
+        });
+    }
+    delta
+        .check_limits()
+        .and_then(|_| delta.validate_against(document_len))
+        .map_err(|e| RejectionReason::InvalidDelta { msg: e.to_string() })?;
+    if let Some(max) = config.max_document_len {
+        let len = document_len - delta.utf16_base_len + delta.utf16_target_len;
+        if len > max {
+            return Err(RejectionReason::DocumentTooLong { len, max });
+        }
+    }
+
+    let mut cleaned = RichTextDelta::new();
+    for op in &delta.ops {
+        match op {
+            Operation::Delete(n) => cleaned.delete(*n),
+            Operation::Retain(retain) => cleaned.retain(retain.n, clean_attributes(&retain.attributes, config)),
+            Operation::Insert(insert) => {
+                let attributes = clean_attributes(&insert.attributes, config);
+                match &insert.embed {
+                    Some(embed) => cleaned.insert_embed(clean_embed(embed, config)?, attributes),
+                    None if insert.utf16_size() > config.max_insert_len => {
+                        return Err(RejectionReason::InsertTooLong {
+                            len: insert.utf16_size(),
+                            max: config.max_insert_len,
+                        })
+                    }
+                    None => cleaned.insert(&insert.s, attributes),
+                }
+            }
+        }
+    }
+    Ok(cleaned)
+}
+
+// The removals of the attributes are kept, they can't do any harm.
+fn clean_attributes(attributes: &RichTextAttributes, config: &IngestConfig) -> RichTextAttributes {
+    let mut cleaned = attributes.clone();
+    cleaned.retain(|key, value| {
+        let s = match value.as_str() {
+            None => return true,
+            Some(s) => s,
+        };
+        s.len() <= config.max_attribute_len
+            && value.typed(key.value_type()).is_some()
+            && (key != &RichTextAttributeKey::Link || is_allowed_url(s, config))
+    });
+    cleaned
+}
+
+fn clean_embed(embed: &Embed, config: &IngestConfig) -> Result<Embed, RejectionReason> {
+    if let Some(kinds) = &config.embed_kinds {
+        if !kinds.contains(&embed.kind) {
+            return Err(RejectionReason::EmbedNotAllowed {
+                kind: embed.kind.clone(),
+            });
+        }
+    }
+    let size = embed.data.as_json_text().len();
+    if size > config.max_embed_len {
+        return Err(RejectionReason::EmbedTooLarge {
+            kind: embed.kind.clone(),
+            size,
+            max: config.max_embed_len,
+        });
+    }
+    let mut data = embed.data.to_value();
+    clean_urls(&mut data, config);
+    Ok(Embed::new(&embed.kind, data))
+}
+
+// Removes the strings of the payload that are urls of a scheme that isn't allowed, e.g. the
+// `javascript:` source of an image.
+fn clean_urls(data: &mut Value, config: &IngestConfig) {
+    match data {
+        Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .filter(|(_, value)| !matches!(value, Value::String(s) if !is_allowed_url(s, config)))
+                .collect();
+            map.values_mut().for_each(|value| clean_urls(value, config));
+        }
+        Value::Array(values) => {
+            values.retain(|value| !matches!(value, Value::String(s) if !is_allowed_url(s, config)));
+            values.iter_mut().for_each(|value| clean_urls(value, config));
+        }
+        _ => {}
+    }
+}
+
+// The scheme is the text before the first colon, unless a character that can't be in a scheme
+// comes first, e.g. in a relative url. Like the browsers, the tabs and the newlines are ignored.
+fn is_allowed_url(s: &str, config: &IngestConfig) -> bool {
+    let s = s
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>();
+    let scheme = match s.split_once(':') {
+        None => return true,
+        Some((scheme, _)) => scheme,
+    };
+    let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    !is_scheme
+        || config
+            .url_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server_document::ingest::{ingest_untrusted, IngestConfig, RejectionReason};
+    use lib_ot::{
+        core::Embed,
+        rich_text::{
+            RichTextAttribute, RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes, RichTextDeltaBuilder,
+        },
+    };
+    use serde_json::json;
+
+    #[test]
+    fn untrusted_delta_is_cleaned() {
+        let config = IngestConfig::default();
+        // A link to a script and a header that isn't a number.
+        let mut attributes: RichTextAttributes = RichTextAttribute::Link("javascript:alert(1)").into();
+        attributes.add_kv(
+            RichTextAttributeKey::Header,
+            RichTextAttributeValue(Some("big".to_owned())),
+        );
+        let delta = RichTextDeltaBuilder::new()
+            .retain(2)
+            .insert_with_attributes("a", attributes)
+            .insert_with_attributes("b", RichTextAttribute::Bold(true).into())
+            .insert_embed(Embed::new(
+                "image",
+                json!({ "src": "javascript:alert(1)", "alt": "a cat", "srcset": ["https://a/1.png"] }),
+            ))
+            .build();
+
+        let cleaned = ingest_untrusted(&delta, 2, &config).unwrap();
+        assert_eq!(cleaned.utf16_target_len, delta.utf16_target_len);
+        assert_eq!(
+            cleaned.to_json(),
+            r#"[{"retain":2},{"insert":"a"},{"insert":"b","attributes":{"bold":true}},{"insert":{"image":{"alt":"a cat","srcset":["https://a/1.png"]}}}]"#
+        );
+        assert!(matches!(
+            ingest_untrusted(&delta, 1, &config),
+            Err(RejectionReason::InvalidDelta { .. })
+        ));
+    }
+
+    #[test]
+    fn untrusted_delta_over_the_limits_is_rejected() {
+        let config = IngestConfig {
+            max_document_len: Some(5),
+            embed_kinds: Some(vec!["image".to_owned()]),
+            ..IngestConfig::default()
+        };
+        let delta = RichTextDeltaBuilder::new().insert("123456").build();
+        assert_eq!(
+            ingest_untrusted(&delta, 0, &config),
+            Err(RejectionReason::DocumentTooLong { len: 6, max: 5 })
+        );
+        let delta = RichTextDeltaBuilder::new()
+            .insert_embed(Embed::new("video", json!({})))
+            .build();
+        assert_eq!(
+            ingest_untrusted(&delta, 0, &config),
+            Err(RejectionReason::EmbedNotAllowed {
+                kind: "video".to_owned()
+            })
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    entities::document_info::DocumentInfo,
+    errors::{CollaborateError, CollaborateResult},
+    protobuf::{RepeatedRevision as RepeatedRevisionPB, Revision as RevisionPB},
+    server_document::DocumentCloudPersistence,
+    synchronizer::{RevisionSyncResponse, RevisionUser},
+    util::{make_delta_from_revision_pb, make_document_info_from_revisions_pb, repeated_revision_pb_from_revisions},
+};
+use lib_infra::future::BoxResultFuture;
+use lib_ot::rich_text::RichTextDelta;
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+/// Keeps the revisions of the documents in memory, for the simulation and the tests of the
+/// [ServerDocumentManager](crate::server_document::ServerDocumentManager).
+#[derive(Debug, Default)]
+pub struct MemoryDocumentPersistence {
+    documents: Mutex<HashMap<String, BTreeMap<i64, RevisionPB>>>,
+}
+
+impl MemoryDocumentPersistence {
+    pub fn save(&self, doc_id: &str, revisions: Vec<RevisionPB>) {
+        let mut write_guard = self.documents.lock();
+        let document = write_guard.entry(doc_id.to_owned()).or_insert_with(BTreeMap::new);
+        for revision in revisions {
+            document.insert(revision.rev_id, revision);
+        }
+    }
+
+    /// Reads all the revisions of the document if the rev_ids is None.
+    pub fn read(&self, doc_id: &str, rev_ids: Option<Vec<i64>>) -> Vec<RevisionPB> {
+        let read_guard = self.documents.lock();
+        let document = match read_guard.get(doc_id) {
+            None => return vec![],
+            Some(document) => document,
+        };
+        match rev_ids {
+            None => document.values().cloned().collect(),
+            Some(rev_ids) => rev_ids
+                .iter()
+                .flat_map(|rev_id| document.get(rev_id).cloned())
+                .collect(),
+        }
+    }
+
+    /// Returns the rev_id and the content of the document.
+    pub fn document(&self, doc_id: &str) -> CollaborateResult<(i64, RichTextDelta)> {
+        let revisions = self.read(doc_id, None);
+        let rev_id = revisions.last().map(|revision| revision.rev_id).unwrap_or(0);
+        let document = make_delta_from_revision_pb(revisions)?;
+        Ok((rev_id, document))
+    }
+
+    fn document_info(&self, doc_id: &str) -> CollaborateResult<Option<DocumentInfo>> {
+        let repeated_revision = repeated_revision_pb_from_revisions(self.read(doc_id, None));
+        make_document_info_from_revisions_pb(doc_id, repeated_revision)
+    }
+}
+
+impl DocumentCloudPersistence for MemoryDocumentPersistence {
+    fn read_document(&self, doc_id: &str) -> BoxResultFuture<DocumentInfo, CollaborateError> {
+        let result = self
+            .document_info(doc_id)
+            .and_then(|document_info| document_info.ok_or_else(CollaborateError::record_not_found));
+        Box::pin(async move { result })
+    }
+
+    fn create_document(
+        &self,
+        doc_id: &str,
+        mut repeated_revision: RepeatedRevisionPB,
+    ) -> BoxResultFuture<Option<DocumentInfo>, CollaborateError> {
+        self.save(doc_id, repeated_revision.take_items().into());
+        let result = self.document_info(doc_id);
+        Box::pin(async move { result })
+    }
+
+    fn read_document_revisions(
+        &self,
+        doc_id: &str,
+        rev_ids: Option<Vec<i64>>,
+    ) -> BoxResultFuture<Vec<RevisionPB>, CollaborateError> {
+        let revisions = self.read(doc_id, rev_ids);
+        Box::pin(async move { Ok(revisions) })
+    }
+
+    fn save_document_revisions(
+        &self,
+        mut repeated_revision: RepeatedRevisionPB,
+    ) -> BoxResultFuture<(), CollaborateError> {
+        if let Some(revision) = repeated_revision.get_items().first() {
+            let doc_id = revision.object_id.clone();
+            self.save(&doc_id, repeated_revision.take_items().into());
+        }
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn reset_document(
+        &self,
+        doc_id: &str,
+        mut repeated_revision: RepeatedRevisionPB,
+    ) -> BoxResultFuture<(), CollaborateError> {
+        self.documents.lock().remove(doc_id);
+        self.save(doc_id, repeated_revision.take_items().into());
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+/// A user that queues the responses of the server in the outbox, with the index of the client
+/// that it is, until the simulation or the test delivers them.
+pub struct SimulatedUser {
+    user_id: String,
+    client: usize,
+    outbox: Arc<Mutex<Vec<(usize, RevisionSyncResponse)>>>,
+}
+
+impl SimulatedUser {
+    pub fn new(user_id: &str, client: usize, outbox: Arc<Mutex<Vec<(usize, RevisionSyncResponse)>>>) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            client,
+            outbox,
+        }
+    }
+}
+
+impl fmt::Debug for SimulatedUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedUser").field("user_id", &self.user_id).finish()
+    }
+}
+
+impl RevisionUser for SimulatedUser {
+    fn user_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    fn receive(&self, resp: RevisionSyncResponse) {
+        self.outbox.lock().push((self.client, resp));
+    }
+}
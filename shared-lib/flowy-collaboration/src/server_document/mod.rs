@@ -1,4 +1,14 @@
+mod access_control;
 mod document_manager;
 mod document_pad;
+mod ingest;
+mod memory;
+mod rate_limit;
+mod session;
 
+pub use access_control::{DocumentAccessControl, DocumentAction};
 pub use document_manager::*;
+pub use ingest::{ingest_untrusted, IngestConfig, RejectionReason};
+pub use memory::{MemoryDocumentPersistence, SimulatedUser};
+pub use rate_limit::{RateLimitConfig, RateLimitMetrics, RateLimiter, ThrottleReason};
+pub use session::{LeaveReason, SessionConfig, SessionEvent};
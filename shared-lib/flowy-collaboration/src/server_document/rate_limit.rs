@@ -0,0 +1,278 @@
+use crate::{
+    errors::{CollaborateResult, ThrottledError},
+    protobuf::Revision as RevisionPB,
+};
+use dashmap::DashMap;
+use lib_ot::rich_text::RichTextDelta;
+use std::{fmt, sync::Arc, time::Instant};
+
+/// The limits of what each client may send, so that a client that sends too much doesn't hold up
+/// the others that edit the same documents. The limits that are `None` aren't enforced.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitConfig {
+    /// The revisions that a client may send per second, on average. A client may send a second's
+    /// worth of them at once, or a batch of any size if it has sent nothing for a second, e.g. the
+    /// revisions it queued while it was offline. It waits for the revisions over the second's
+    /// worth to be paid back before it sends more then.
+    pub revisions_per_sec: Option<u32>,
+    /// The size of the delta of a revision, in bytes.
+    pub max_delta_size: Option<usize>,
+    /// The number of the operations of the delta of a revision.
+    pub max_ops_per_delta: Option<usize>,
+}
+
+/// Why the revisions of a client were rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThrottleReason {
+    TooManyRevisions { limit: u32 },
+    DeltaTooLarge { size: usize, max: usize },
+    TooManyOps { ops: usize, max: usize },
+}
+
+impl fmt::Display for ThrottleReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThrottleReason::TooManyRevisions { limit } => write!(f, "over {} revisions per second", limit),
+            ThrottleReason::DeltaTooLarge { size, max } => write!(f, "a delta of {} bytes, over {}", size, max),
+            ThrottleReason::TooManyOps { ops, max } => write!(f, "a delta of {} operations, over {}", ops, max),
+        }
+    }
+}
+
+/// Lets the server export counters about the clients that are throttled, e.g. to Prometheus.
+pub trait RateLimitMetrics: Send + Sync {
+    /// Called after the revisions of a client passed the limits.
+    fn on_accept(&self, _user_id: &str, _revision_count: usize) {}
+
+    /// Called after the revisions of a client were rejected.
+    fn on_throttle(&self, _user_id: &str, _reason: &ThrottleReason) {}
+}
+
+struct TokenBucket {
+    // Negative while the client pays back a batch that was bigger than the capacity.
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    // Adds the tokens of the time since the last update.
+    fn refill(&mut self, capacity: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * capacity).min(capacity);
+        self.updated_at = now;
+    }
+}
+
+/// Enforces the [RateLimitConfig] for each client, see
+/// [ServerDocumentManager::with_rate_limiter](crate::server_document::ServerDocumentManager::with_rate_limiter).
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: DashMap<String, TokenBucket>,
+    metrics: Option<Arc<dyn RateLimitMetrics>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: DashMap::new(),
+            metrics: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<dyn RateLimitMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Checks the revisions that the client just sent against the limits. The revisions that are
+    /// rejected don't count against the rate of the client.
+    pub fn check(&self, user_id: &str, revisions: &[RevisionPB]) -> CollaborateResult<()> {
+        self.check_at(user_id, revisions, Instant::now())
+    }
+
+    fn check_at(&self, user_id: &str, revisions: &[RevisionPB], now: Instant) -> CollaborateResult<()> {
+        let result = match self.check_deltas(revisions)? {
+            Some(reason) => Err(reason),
+            None => self.take_tokens(user_id, revisions.len(), now),
+        };
+        match result {
+            Ok(()) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_accept(user_id, revisions.len());
+                }
+                Ok(())
+            }
+            Err(reason) => {
+                tracing::warn!("Throttled the revisions of {}: {}", user_id, reason);
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_throttle(user_id, &reason);
+                }
+                Err(ThrottledError {
+                    user_id: user_id.to_owned(),
+                    reason,
+                }
+                .into())
+            }
+        }
+    }
+
+    fn check_deltas(&self, revisions: &[RevisionPB]) -> CollaborateResult<Option<ThrottleReason>> {
+        for revision in revisions {
+            let size = revision.delta_data.len();
+            if let Some(max) = self.config.max_delta_size.filter(|max| size > *max) {
+                return Ok(Some(ThrottleReason::DeltaTooLarge { size, max }));
+            }
+            if let Some(max) = self.config.max_ops_per_delta {
+                let ops = RichTextDelta::from_bytes(&revision.delta_data)?.ops.len();
+                if ops > max {
+                    return Ok(Some(ThrottleReason::TooManyOps { ops, max }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn take_tokens(&self, user_id: &str, count: usize, now: Instant) -> Result<(), ThrottleReason> {
+        let limit = match self.config.revisions_per_sec {
+            None => return Ok(()),
+            Some(limit) => limit,
+        };
+        let capacity = limit as f64;
+        let mut bucket = self.buckets.entry(user_id.to_owned()).or_insert(TokenBucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        bucket.refill(capacity, now);
+        // A batch over the capacity would never fit, it's taken from a full bucket.
+        match bucket.tokens >= (count as f64).min(capacity) {
+            true => {
+                bucket.tokens -= count as f64;
+                Ok(())
+            }
+            false => Err(ThrottleReason::TooManyRevisions { limit }),
+        }
+    }
+
+    /// Forgets the clients whose bucket is full again, which is the same as a client that sent
+    /// nothing yet. Called by the sweep of the
+    /// [ServerDocumentManager](crate::server_document::ServerDocumentManager), so that the
+    /// clients that left don't add up.
+    pub(crate) fn sweep_at(&self, now: Instant) {
+        let capacity = match self.config.revisions_per_sec {
+            None => return,
+            Some(limit) => limit as f64,
+        };
+        self.buckets.retain(|_, bucket| {
+            bucket.refill(capacity, now);
+            bucket.tokens < capacity
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entities::revision::Revision,
+        errors::ErrorCode,
+        protobuf::Revision as RevisionPB,
+        server_document::rate_limit::{RateLimitConfig, RateLimitMetrics, RateLimiter, ThrottleReason},
+    };
+    use lib_ot::rich_text::RichTextDeltaBuilder;
+    use parking_lot::Mutex;
+    use std::{
+        convert::TryInto,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    #[derive(Default)]
+    struct Throttles(Mutex<Vec<ThrottleReason>>);
+    impl RateLimitMetrics for Throttles {
+        fn on_throttle(&self, _user_id: &str, reason: &ThrottleReason) {
+            self.0.lock().push(reason.clone());
+        }
+    }
+
+    fn revision(ops: usize) -> RevisionPB {
+        let delta = (0..ops).fold(RichTextDeltaBuilder::new(), |builder, i| match i % 2 {
+            0 => builder.insert("a"),
+            _ => builder.retain(1),
+        });
+        let revision = Revision::new("doc", 0, 1, delta.build().to_bytes(), "user", "".to_owned());
+        revision.try_into().unwrap()
+    }
+
+    #[test]
+    fn clients_over_the_limits_are_throttled() {
+        let throttles = Arc::new(Throttles::default());
+        let limiter = RateLimiter::new(RateLimitConfig {
+            revisions_per_sec: Some(2),
+            max_delta_size: None,
+            max_ops_per_delta: Some(3),
+        })
+        .with_metrics(throttles.clone());
+        let now = Instant::now();
+        assert!(limiter.check_at("a", &[revision(1), revision(1)], now).is_ok());
+        let error = limiter.check_at("a", &[revision(1)], now).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Throttled);
+        // The other clients have their own rate.
+        assert!(limiter.check_at("b", &[revision(1)], now).is_ok());
+        // Half a second gives the client one more revision.
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check_at("a", &[revision(1)], later).is_ok());
+
+        let later = later + Duration::from_secs(10);
+        assert!(limiter.check_at("a", &[revision(4)], later).is_err());
+        assert_eq!(
+            *throttles.0.lock(),
+            vec![
+                ThrottleReason::TooManyRevisions { limit: 2 },
+                ThrottleReason::TooManyOps { ops: 4, max: 3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_over_the_capacity_is_paid_back() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            revisions_per_sec: Some(2),
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+        // The revisions queued offline are accepted at once, then the client waits 2s: 1.5s for
+        // the 3 revisions over the capacity and 0.5s for the next one.
+        let queued = (0..5).map(|_| revision(1)).collect::<Vec<_>>();
+        assert!(limiter.check_at("a", &queued, now).is_ok());
+        assert!(limiter.check_at("a", &[revision(1)], now).is_err());
+        assert!(limiter
+            .check_at("a", &[revision(1)], now + Duration::from_secs(1))
+            .is_err());
+        assert!(limiter
+            .check_at("a", &[revision(1)], now + Duration::from_secs(2))
+            .is_ok());
+        // A batch over the capacity waits for a full bucket.
+        let later = now + Duration::from_millis(2500);
+        assert!(limiter.check_at("a", &queued, later).is_err());
+        assert!(limiter
+            .check_at("a", &queued, later + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn sweep_forgets_the_clients_whose_bucket_is_full() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            revisions_per_sec: Some(2),
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+        assert!(limiter.check_at("a", &[revision(1)], now).is_ok());
+        assert!(limiter.check_at("b", &[revision(1), revision(1)], now).is_ok());
+        let later = now + Duration::from_millis(500);
+        limiter.sweep_at(later);
+        assert_eq!(limiter.buckets.len(), 1);
+        assert!(limiter.buckets.contains_key("b"));
+        limiter.sweep_at(later + Duration::from_millis(500));
+        assert!(limiter.buckets.is_empty());
+    }
+}
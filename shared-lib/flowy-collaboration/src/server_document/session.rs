@@ -0,0 +1,100 @@
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long the sessions and the open documents of the server live without activity, see
+/// [ServerDocumentManager::sweep](crate::server_document::ServerDocumentManager::sweep).
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// A client that sends neither a revision nor a ping for this long leaves the document.
+    pub heartbeat_timeout: Duration,
+    /// A document that nobody is in for this long is closed, and compacted by
+    /// [DocumentCloudPersistence::compact_document](crate::server_document::DocumentCloudPersistence::compact_document).
+    pub idle_timeout: Duration,
+}
+
+impl std::default::Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveReason {
+    /// The client said it left.
+    Left,
+    /// The client missed its heartbeats.
+    TimedOut,
+}
+
+/// The comings and goings of the clients of the documents. The others should drop the presence,
+/// e.g. the selection, of the user that left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    UserJoined {
+        doc_id: String,
+        user_id: String,
+    },
+    UserLeft {
+        doc_id: String,
+        user_id: String,
+        reason: LeaveReason,
+    },
+    DocumentClosed {
+        doc_id: String,
+    },
+}
+
+/// The clients in a document, the last time that each user was seen by its id.
+pub(crate) struct DocumentSessions {
+    sessions: DashMap<String, Instant>,
+    // The last time that a client was seen or left.
+    last_active: Mutex<Instant>,
+}
+
+impl DocumentSessions {
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            sessions: DashMap::new(),
+            last_active: Mutex::new(now),
+        }
+    }
+
+    /// Records that the user was seen, returns true if it just joined.
+    pub(crate) fn touch(&self, user_id: &str, now: Instant) -> bool {
+        *self.last_active.lock() = now;
+        self.sessions.insert(user_id.to_owned(), now).is_none()
+    }
+
+    /// Returns true if the user was in the document.
+    pub(crate) fn remove(&self, user_id: &str, now: Instant) -> bool {
+        *self.last_active.lock() = now;
+        self.sessions.remove(user_id).is_some()
+    }
+
+    /// Removes the users that weren't seen for `timeout`, and returns their ids.
+    pub(crate) fn expire(&self, timeout: Duration, now: Instant) -> Vec<String> {
+        let expired = self
+            .sessions
+            .iter()
+            .filter(|session| now.saturating_duration_since(*session.value()) >= timeout)
+            .map(|session| session.key().clone())
+            .collect::<Vec<_>>();
+        expired.iter().for_each(|user_id| {
+            self.sessions.remove(user_id);
+        });
+        expired
+    }
+
+    /// Whether nobody was in the document for `timeout`.
+    pub(crate) fn is_idle(&self, timeout: Duration, now: Instant) -> bool {
+        self.sessions.is_empty() && now.saturating_duration_since(*self.last_active.lock()) >= timeout
+    }
+
+    pub(crate) fn user_ids(&self) -> Vec<String> {
+        self.sessions.iter().map(|session| session.key().clone()).collect()
+    }
+}
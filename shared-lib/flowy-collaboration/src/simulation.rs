@@ -0,0 +1,371 @@
+//! Simulates the clients that edit a document together through the [ServerDocumentManager]. The
+//! clients edit at random, the messages between them and the server are delayed, reordered and
+//! lost at random, and the simulation fails unless every client ends up with the document of the
+//! server. It runs in ticks instead of the time, so a seed always replays the same run.
+use crate::{
+    client_document::{
+        sync::{ClientSync, SyncMode},
+        DocumentConfigBuilder,
+    },
+    entities::{
+        revision::{RepeatedRevision, Revision},
+        ws_data::{ClientRevisionWSData, ClientRevisionWSDataType, ServerRevisionWSData, ServerRevisionWSDataType},
+    },
+    errors::{CollaborateError, CollaborateResult},
+    protobuf::ClientRevisionWSData as ClientRevisionWSDataPB,
+    server_document::{DocumentCloudPersistence, MemoryDocumentPersistence, ServerDocumentManager, SimulatedUser},
+    synchronizer::RevisionSyncResponse,
+    util::content_md5,
+};
+use bytes::Bytes;
+use lib_ot::{rich_text::RichTextDelta, test_utils::Rng};
+use parking_lot::Mutex;
+use rand::Rng as _;
+use std::{
+    convert::{TryFrom, TryInto},
+    ops::RangeInclusive,
+    sync::Arc,
+};
+
+const SIMULATION_DOC_ID: &str = "simulation";
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    /// The number of the clients that edit the document.
+    pub clients: usize,
+    /// The number of the clients, among `clients`, that are in the [SyncMode::Authoritative] mode.
+    /// The others are optimistic.
+    pub authoritative_clients: usize,
+    /// The number of the edits that each client makes.
+    pub edits_per_client: usize,
+    /// The chance that a client edits the document at a tick, until it made all its edits.
+    pub edit_probability: f64,
+    /// The length of the document that the clients start with.
+    pub initial_len: usize,
+    /// The ticks that a message takes to arrive. A message that takes longer than the one sent
+    /// after it arrives after that one.
+    pub latency: RangeInclusive<u64>,
+    /// The chance that a message is lost, in both directions.
+    pub drop_probability: f64,
+    /// Every `sync_interval` ticks, a client sends its first unconfirmed revision again and pings
+    /// the server for the revisions it misses, which is how it recovers from the lost messages.
+    pub sync_interval: u64,
+    /// The simulation fails if the clients haven't caught up with the server after `max_ticks`.
+    pub max_ticks: u64,
+    pub seed: u64,
+}
+
+impl std::default::Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            clients: 3,
+            authoritative_clients: 0,
+            edits_per_client: 20,
+            edit_probability: 0.3,
+            initial_len: 20,
+            latency: 1..=5,
+            drop_probability: 0.1,
+            sync_interval: 4,
+            max_ticks: 10_000,
+            seed: rand::random(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    pub seed: u64,
+    /// The tick at which all the clients had the document of the server.
+    pub ticks: u64,
+    pub messages_sent: usize,
+    pub messages_dropped: usize,
+    /// The rev_id of the document on the server.
+    pub rev_id: i64,
+    /// The document that all the clients and the server ended up with.
+    pub document: RichTextDelta,
+}
+
+pub struct Simulation {
+    config: SimulationConfig,
+    rng: Rng,
+    clients: Vec<SimulatedClient>,
+    network: Network,
+    persistence: Arc<MemoryDocumentPersistence>,
+    outbox: Arc<Mutex<Vec<(usize, RevisionSyncResponse)>>>,
+}
+
+impl Simulation {
+    pub fn new(config: SimulationConfig) -> CollaborateResult<Self> {
+        let mut rng = Rng::from_seed(config.seed);
+        let document: RichTextDelta = rng.gen_document(config.initial_len);
+        let initial_revision = Revision::initial_revision("", SIMULATION_DOC_ID, document.to_bytes());
+        let persistence = Arc::new(MemoryDocumentPersistence::default());
+        persistence.save(SIMULATION_DOC_ID, vec![initial_revision.try_into()?]);
+
+        let clients = (0..config.clients)
+            .map(|index| {
+                let mode = match index < config.authoritative_clients {
+                    true => SyncMode::Authoritative,
+                    false => SyncMode::Optimistic,
+                };
+                SimulatedClient::new(index, mode, document.clone(), config.edits_per_client)
+            })
+            .collect();
+        Ok(Self {
+            config,
+            rng,
+            clients,
+            network: Network::default(),
+            persistence,
+            outbox: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    /// Runs the simulation until all the clients made their edits and caught up with the server.
+    /// Fails if a client ends up with a document other than the one of the server, or if they
+    /// don't catch up in `max_ticks`.
+    pub async fn run(mut self) -> CollaborateResult<SimulationReport> {
+        let persistence: Arc<dyn DocumentCloudPersistence> = self.persistence.clone();
+        let server = ServerDocumentManager::new(persistence);
+        for tick in 0..=self.config.max_ticks {
+            for index in 0..self.clients.len() {
+                self.tick_client(tick, index)?;
+            }
+
+            for envelope in self.network.take_due(tick) {
+                match envelope {
+                    Envelope::ToServer { client, data } => self.deliver_to_server(&server, client, data).await?,
+                    Envelope::ToClient { client, data } => {
+                        if self.clients[client].receive(data)? {
+                            self.send_revision(tick, client);
+                        }
+                    }
+                }
+                let responses = std::mem::take(&mut *self.outbox.lock());
+                for (client, response) in responses {
+                    let data = match response {
+                        RevisionSyncResponse::Pull(data) => data,
+                        RevisionSyncResponse::Push(data) => data,
+                        RevisionSyncResponse::Ack(data) => data,
+                    };
+                    self.send(tick, Envelope::ToClient { client, data });
+                }
+            }
+
+            if let Some(report) = self.check_convergence(tick)? {
+                return Ok(report);
+            }
+        }
+
+        Err(CollaborateError::internal().context(format!(
+            "The clients didn't catch up with the server in {} ticks, seed: {}",
+            self.config.max_ticks, self.config.seed
+        )))
+    }
+
+    fn tick_client(&mut self, tick: u64, index: usize) -> CollaborateResult<()> {
+        let client = &mut self.clients[index];
+        if client.edits_left > 0 && self.rng.gen_bool(self.config.edit_probability) {
+            let was_synced = !client.sync.has_pending();
+            client.edit(&mut self.rng)?;
+            if was_synced {
+                self.send_revision(tick, index);
+            }
+        }
+
+        if tick % self.config.sync_interval == 0 {
+            self.send_revision(tick, index);
+            let ping = ClientRevisionWSData::ping(SIMULATION_DOC_ID, self.clients[index].sync.server_rev_id());
+            self.send(
+                tick,
+                Envelope::ToServer {
+                    client: index,
+                    data: ping,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn send_revision(&mut self, tick: u64, index: usize) {
+        if let Some(revision) = self.clients[index].sync.next_revision() {
+            let data = ClientRevisionWSData::from_revisions(SIMULATION_DOC_ID, vec![revision]);
+            self.send(tick, Envelope::ToServer { client: index, data });
+        }
+    }
+
+    fn send(&mut self, tick: u64, envelope: Envelope) {
+        if self.rng.gen_bool(self.config.drop_probability) {
+            self.network.dropped += 1;
+            return;
+        }
+        let deliver_at = tick + self.rng.gen_range(self.config.latency.clone());
+        self.network.push(deliver_at, envelope);
+    }
+
+    async fn deliver_to_server(
+        &self,
+        server: &ServerDocumentManager,
+        client: usize,
+        data: ClientRevisionWSData,
+    ) -> CollaborateResult<()> {
+        let user = Arc::new(SimulatedUser::new(
+            &self.clients[client].user_id,
+            client,
+            self.outbox.clone(),
+        ));
+        let ty = data.ty.clone();
+        let data: ClientRevisionWSDataPB = data.try_into()?;
+        match ty {
+            ClientRevisionWSDataType::ClientPushRev => server.handle_client_revisions(user, data).await,
+            ClientRevisionWSDataType::ClientPing => server.handle_client_ping(user, data).await,
+        }
+    }
+
+    fn check_convergence(&self, tick: u64) -> CollaborateResult<Option<SimulationReport>> {
+        let is_idle = self
+            .clients
+            .iter()
+            .all(|client| client.edits_left == 0 && !client.sync.has_pending());
+        if !is_idle {
+            return Ok(None);
+        }
+
+        let (rev_id, document) = self.persistence.document(SIMULATION_DOC_ID)?;
+        if self.clients.iter().any(|client| client.sync.server_rev_id() != rev_id) {
+            return Ok(None);
+        }
+
+        for client in &self.clients {
+            let client_document = client.sync.document();
+            if !client_document.content_eq(&document) || content_md5(client_document) != content_md5(&document) {
+                return Err(CollaborateError::internal().context(format!(
+                    "{} diverged from the server at rev_id {}, seed: {}\nclient: {}\nserver: {}",
+                    client.user_id, rev_id, self.config.seed, client_document, document
+                )));
+            }
+        }
+
+        Ok(Some(SimulationReport {
+            seed: self.config.seed,
+            ticks: tick,
+            messages_sent: self.network.sent,
+            messages_dropped: self.network.dropped,
+            rev_id,
+            document,
+        }))
+    }
+}
+
+/// A client that edits the document at random, see [ClientSync] for the protocol it follows.
+struct SimulatedClient {
+    user_id: String,
+    edits_left: usize,
+    sync: ClientSync,
+}
+
+impl SimulatedClient {
+    fn new(index: usize, mode: SyncMode, document: RichTextDelta, edits: usize) -> Self {
+        let user_id = format!("client_{}", index);
+        let config = DocumentConfigBuilder::new(SIMULATION_DOC_ID)
+            .author(&user_id, "")
+            .sync_mode(mode)
+            .build();
+        Self {
+            user_id,
+            edits_left: edits,
+            sync: ClientSync::new(&config, document, 0),
+        }
+    }
+
+    fn edit(&mut self, rng: &mut Rng) -> CollaborateResult<()> {
+        let delta = rng.gen_delta(self.sync.document());
+        let _ = self.sync.edit(delta)?;
+        self.edits_left -= 1;
+        Ok(())
+    }
+
+    /// Returns true if the server asks for the pending revision.
+    fn receive(&mut self, data: ServerRevisionWSData) -> CollaborateResult<bool> {
+        match data.ty {
+            ServerRevisionWSDataType::ServerPushRev => {
+                let repeated_revision = RepeatedRevision::try_from(Bytes::from(data.data))?;
+                let _ = self.sync.receive_revisions(repeated_revision.into_inner())?;
+                Ok(false)
+            }
+            ServerRevisionWSDataType::ServerPullRev => Ok(true),
+            ServerRevisionWSDataType::ServerAck | ServerRevisionWSDataType::UserConnect => Ok(false),
+        }
+    }
+}
+
+enum Envelope {
+    ToServer { client: usize, data: ClientRevisionWSData },
+    ToClient { client: usize, data: ServerRevisionWSData },
+}
+
+#[derive(Default)]
+struct Network {
+    // The messages on their way, with the tick they arrive at and the order they were sent in.
+    messages: Vec<(u64, usize, Envelope)>,
+    sent: usize,
+    dropped: usize,
+}
+
+impl Network {
+    fn push(&mut self, deliver_at: u64, envelope: Envelope) {
+        self.messages.push((deliver_at, self.sent, envelope));
+        self.sent += 1;
+    }
+
+    fn take_due(&mut self, tick: u64) -> Vec<Envelope> {
+        let (mut due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.messages)
+            .into_iter()
+            .partition(|(deliver_at, _, _)| *deliver_at <= tick);
+        self.messages = pending;
+        due.sort_by_key(|(deliver_at, seq, _)| (*deliver_at, *seq));
+        due.into_iter().map(|(_, _, envelope)| envelope).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn clients_converge() {
+        for seed in 0..20 {
+            let config = SimulationConfig {
+                seed,
+                ..SimulationConfig::default()
+            };
+            let report = Simulation::new(config).unwrap().run().await.unwrap();
+            assert!(report.rev_id > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_converge_on_a_lossy_network() {
+        let config = SimulationConfig {
+            clients: 5,
+            latency: 1..=20,
+            drop_probability: 0.4,
+            seed: 42,
+            ..SimulationConfig::default()
+        };
+        let report = Simulation::new(config).unwrap().run().await.unwrap();
+        assert!(report.messages_dropped > 0);
+    }
+
+    #[tokio::test]
+    async fn authoritative_and_optimistic_clients_converge() {
+        for seed in 0..10 {
+            let config = SimulationConfig {
+                authoritative_clients: 2,
+                seed,
+                ..SimulationConfig::default()
+            };
+            Simulation::new(config).unwrap().run().await.unwrap();
+        }
+    }
+}
@@ -7,12 +7,14 @@ use crate::{
     protobuf::{RepeatedRevision as RepeatedRevisionPB, Revision as RevisionPB},
     util::*,
 };
+use dashmap::DashMap;
 use lib_infra::future::BoxResultFuture;
-use lib_ot::core::{Attributes, Delta};
+use lib_ot::core::{Attributes, Delta, OperationTransformable};
 use parking_lot::RwLock;
 use serde::de::DeserializeOwned;
 use std::{
     cmp::Ordering,
+    collections::BTreeSet,
     fmt::Debug,
     sync::{
         atomic::{AtomicI64, Ordering::SeqCst},
@@ -21,6 +23,14 @@ use std::{
     time::Duration,
 };
 
+/// The version of the revision sync protocol. It must be bumped whenever the messages exchanged
+/// between the client and the server change in an incompatible way.
+pub const SYNC_PROTOCOL_VERSION: i64 = 1;
+
+/// The number of the applied revisions of each client that the server remembers, to recognize the
+/// ones that the client sends again.
+const APPLIED_REVISIONS_PER_CLIENT: usize = 1000;
+
 pub trait RevisionUser: Send + Sync + Debug {
     fn user_id(&self) -> String;
     fn receive(&self, resp: RevisionSyncResponse);
@@ -48,6 +58,11 @@ pub trait RevisionSyncObject<T: Attributes>: Send + Sync + 'static {
     fn transform(&self, other: &Delta<T>) -> Result<(Delta<T>, Delta<T>), CollaborateError>;
     fn to_json(&self) -> String;
     fn set_delta(&mut self, new_delta: Delta<T>);
+
+    /// Checks that the user may compose `delta` into the object, before it's composed.
+    fn check_delta(&self, _user_id: &str, _delta: &Delta<T>) -> Result<(), CollaborateError> {
+        Ok(())
+    }
 }
 
 pub enum RevisionSyncResponse {
@@ -61,6 +76,7 @@ pub struct RevisionSynchronizer<T: Attributes> {
     rev_id: AtomicI64,
     object: Arc<RwLock<dyn RevisionSyncObject<T>>>,
     persistence: Arc<dyn RevisionSyncPersistence>,
+    applied_revisions: DashMap<String, BTreeSet<i64>>,
 }
 
 impl<T> RevisionSynchronizer<T>
@@ -80,6 +96,7 @@ where
             rev_id: AtomicI64::new(rev_id),
             object,
             persistence,
+            applied_revisions: DashMap::new(),
         }
     }
 
@@ -100,21 +117,45 @@ where
         }
 
         let server_base_rev_id = self.rev_id.load(SeqCst);
+        // The client sends the revisions again when the ack was lost, with the ones it made since
+        // then after them. The ones that were applied are skipped, the others are applied.
+        let duplicates = self.duplicate_prefix_len(repeated_revision.get_items()).await;
+        let repeated_revision = match duplicates {
+            0 => repeated_revision,
+            _ => {
+                let revisions = repeated_revision.get_items();
+                let last_rev_id = revisions[duplicates - 1].rev_id;
+                tracing::debug!(
+                    "{}: ignore the duplicate revisions {}..={}, server rev_id: {}",
+                    object_id,
+                    revisions[0].rev_id,
+                    last_rev_id,
+                    server_base_rev_id
+                );
+                if duplicates == revisions.len() {
+                    // They are acked again without applying them twice, and the client gets the
+                    // revisions that came after them, so it ends up at the rev_id of the server.
+                    if server_base_rev_id > last_rev_id {
+                        let _ = self
+                            .push_revisions_to_user(user, next(last_rev_id), server_base_rev_id)
+                            .await;
+                    }
+                    return Ok(());
+                }
+                repeated_revision_pb_from_revisions(revisions[duplicates..].to_vec())
+            }
+        };
         let first_revision = repeated_revision.get_items().first().unwrap().clone();
-        if self.is_applied_before(&first_revision, &self.persistence).await {
-            // Server has received this revision before, so ignore the following revisions
-            return Ok(());
-        }
 
         match server_base_rev_id.cmp(&first_revision.rev_id) {
             Ordering::Less => {
                 let server_rev_id = next(server_base_rev_id);
                 if server_base_rev_id == first_revision.base_rev_id || server_rev_id == first_revision.rev_id {
                     // The rev is in the right order, just compose it.
-                    for revision in repeated_revision.get_items() {
-                        let _ = self.compose_revision(revision)?;
-                    }
+                    let _ = self.compose_revisions(&user.user_id(), repeated_revision.get_items())?;
+                    let applied = repeated_revision.get_items().to_vec();
                     let _ = self.persistence.save_revisions(repeated_revision).await?;
+                    applied.iter().for_each(|revision| self.mark_applied(revision));
                 } else {
                     // The server delta is outdated, pull the missing revision from the client.
                     let range = RevisionRange {
@@ -182,10 +223,22 @@ where
         self.object.read().to_json()
     }
 
-    fn compose_revision(&self, revision: &RevisionPB) -> Result<(), CollaborateError> {
-        let delta = Delta::<T>::from_bytes(&revision.delta_data)?;
+    // Composes the revisions, which follow each other, into the object at once. Their deltas are
+    // composed together first, which is cheaper than composing each of them into the whole object
+    // when a client sends the many revisions it queued while it was offline.
+    fn compose_revisions(&self, user_id: &str, revisions: &[RevisionPB]) -> Result<(), CollaborateError> {
+        let (first, rest) = match revisions.split_first() {
+            None => return Ok(()),
+            Some(split) => split,
+        };
+        let mut delta = Delta::<T>::from_bytes(&first.delta_data)?;
+        for revision in rest {
+            delta = delta.compose(&Delta::<T>::from_bytes(&revision.delta_data)?)?;
+        }
+        let _ = self.object.read().check_delta(user_id, &delta)?;
         let _ = self.compose_delta(delta)?;
-        let _ = self.rev_id.fetch_update(SeqCst, SeqCst, |_e| Some(revision.rev_id));
+        let rev_id = revisions[revisions.len() - 1].rev_id;
+        let _ = self.rev_id.fetch_update(SeqCst, SeqCst, |_e| Some(rev_id));
         Ok(())
     }
 
@@ -198,11 +251,11 @@ where
 
     fn compose_delta(&self, delta: Delta<T>) -> Result<(), CollaborateError> {
         if delta.is_empty() {
-            log::warn!("Composed delta is empty");
+            tracing::warn!("Composed delta is empty");
         }
 
         match self.object.try_write_for(Duration::from_millis(300)) {
-            None => log::error!("Failed to acquire write lock of object"),
+            None => tracing::error!("Failed to acquire write lock of object"),
             Some(mut write_guard) => {
                 let _ = write_guard.compose(&delta)?;
             }
@@ -214,6 +267,18 @@ where
         self.rev_id.load(SeqCst)
     }
 
+    // The number of the revisions at the start of `revisions` that were applied already.
+    async fn duplicate_prefix_len(&self, revisions: &[RevisionPB]) -> usize {
+        let mut len = 0;
+        for revision in revisions {
+            if !self.is_applied(revision) && !self.is_applied_before(revision, &self.persistence).await {
+                break;
+            }
+            len += 1;
+        }
+        len
+    }
+
     async fn is_applied_before(
         &self,
         new_revision: &RevisionPB,
@@ -231,6 +296,25 @@ where
         false
     }
 
+    fn is_applied(&self, revision: &RevisionPB) -> bool {
+        match self.applied_revisions.get(client_id(revision)) {
+            None => false,
+            Some(rev_ids) => rev_ids.contains(&revision.rev_id),
+        }
+    }
+
+    fn mark_applied(&self, revision: &RevisionPB) {
+        let mut rev_ids = self
+            .applied_revisions
+            .entry(client_id(revision).to_owned())
+            .or_insert_with(BTreeSet::new);
+        rev_ids.insert(revision.rev_id);
+        while rev_ids.len() > APPLIED_REVISIONS_PER_CLIENT {
+            let oldest = *rev_ids.iter().next().unwrap();
+            rev_ids.remove(&oldest);
+        }
+    }
+
     async fn push_revisions_to_user(&self, user: Arc<dyn RevisionUser>, from: i64, to: i64) {
         let rev_ids: Vec<i64> = (from..=to).collect();
         tracing::debug!("Push revision: {} -> {} to client", from, to);
@@ -259,6 +343,14 @@ where
     }
 }
 
+/// The client that made the revision: its device, or its user if the device is unknown.
+fn client_id(revision: &RevisionPB) -> &str {
+    match revision.device_id.is_empty() {
+        true => &revision.user_id,
+        false => &revision.device_id,
+    }
+}
+
 #[inline]
 fn next(rev_id: i64) -> i64 {
     rev_id + 1
@@ -4,14 +4,18 @@ use crate::{
         folder_info::{FolderDelta, FolderInfo},
         revision::{RepeatedRevision, Revision},
     },
-    errors::{CollaborateError, CollaborateResult},
+    errors::{CollaborateError, CollaborateResult, DocumentIntegrityError},
     protobuf::{
         DocumentInfo as DocumentInfoPB, FolderInfo as FolderInfoPB, RepeatedRevision as RepeatedRevisionPB,
         Revision as RevisionPB,
     },
 };
+use dissimilar::Chunk;
 use lib_ot::{
-    core::{Attributes, Delta, OperationTransformable, NEW_LINE, WHITESPACE},
+    core::{
+        count_utf16_code_units, Attributes, Delta, DeltaBuilder, FlowyStr, Interval, Operation, OperationTransformable,
+        NEW_LINE, WHITESPACE,
+    },
     rich_text::RichTextDelta,
 };
 use serde::de::DeserializeOwned;
@@ -40,12 +44,98 @@ pub fn contain_newline(s: &str) -> bool {
     s.contains(NEW_LINE)
 }
 
+/// Returns the intervals of the lines that `interval` touches, each one including the newline
+/// that ends it. The text after the last newline isn't a line.
+pub fn line_intervals(delta: &RichTextDelta, interval: Interval) -> Vec<Interval> {
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut offset = 0;
+    for op in &delta.ops {
+        let insert = match op {
+            Operation::Insert(insert) => insert,
+            _ => continue,
+        };
+        for piece in insert.s.split_inclusive(NEW_LINE) {
+            offset += count_utf16_code_units(piece);
+            if !piece.ends_with(NEW_LINE) {
+                continue;
+            }
+            let line = Interval::new(start, offset);
+            start = offset;
+            if line.end > interval.start && (line.start < interval.end || line.start == interval.start) {
+                lines.push(line);
+            }
+            if line.start >= interval.end {
+                return lines;
+            }
+        }
+    }
+    lines
+}
+
+/// Returns the interval of the line that contains `index`.
+pub fn line_at(delta: &RichTextDelta, index: usize) -> Option<Interval> {
+    line_intervals(delta, Interval::new(index, index)).pop()
+}
+
 #[inline]
 pub fn md5<T: AsRef<[u8]>>(data: T) -> String {
     let md5 = format!("{:x}", md5::compute(data));
     md5
 }
 
+/// The md5 of the content of `document`, which doesn't depend on how the document is encoded,
+/// e.g. the order of the keys of the attributes in the json.
+pub fn content_md5(document: &RichTextDelta) -> String {
+    let mut context = md5::Context::new();
+    for op in &document.ops {
+        if let Operation::Insert(insert) = op {
+            let mut attributes = insert
+                .attributes
+                .iter()
+                .filter_map(|(key, value)| value.0.as_ref().map(|value| format!("{}={}", key, value)))
+                .collect::<Vec<_>>();
+            attributes.sort();
+            let embed = insert.embed.as_ref().map(|embed| embed.to_string()).unwrap_or_default();
+            context.consume(format!(
+                "{}:{}|{}|{}\n",
+                insert.s.len(),
+                insert.s.as_str(),
+                embed,
+                attributes.join(",")
+            ));
+        }
+    }
+    format!("{:x}", context.compute())
+}
+
+/// Checks that `document`, which the revision was just applied to, has the content that its author
+/// had. The revisions that don't carry the hash pass.
+pub fn verify_content_md5(revision: &Revision, document: &RichTextDelta) -> Result<(), DocumentIntegrityError> {
+    if revision.content_md5.is_empty() {
+        return Ok(());
+    }
+    let actual = content_md5(document);
+    if actual != revision.content_md5 {
+        return Err(DocumentIntegrityError {
+            rev_id: revision.rev_id,
+            expected: revision.content_md5.clone(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Transforms the deltas of the concurrent revisions `a` and `b` against each other, and returns
+/// `(a', b')`: `a'` applies after `b` and `b'` after `a`. The priority goes to the revision that
+/// comes first in [Revision::causal_cmp], so the peers that transform the same revisions end up
+/// with the same document, whichever of them is their own.
+pub fn transform_revisions(a: &Revision, b: &Revision) -> CollaborateResult<(RichTextDelta, RichTextDelta)> {
+    let a_delta = RichTextDelta::from_bytes(&a.delta_data)?;
+    let b_delta = RichTextDelta::from_bytes(&b.delta_data)?;
+    Ok(a_delta.transform_with_priority(&b_delta, a.transform_priority(b))?)
+}
+
 #[derive(Debug)]
 pub struct RevIdCounter(pub AtomicI64);
 
@@ -253,3 +343,24 @@ pub fn rev_id_from_str(s: &str) -> Result<i64, CollaborateError> {
         .map_err(|e| CollaborateError::internal().context(format!("Parse rev_id from {} failed. {}", s, e)))?;
     Ok(rev_id)
 }
+
+/// Calculates the delta that turns `old` into `new`. The unchanged parts are retained, so composing
+/// the delta with a formatted document keeps the attributes of the text that wasn't touched.
+pub fn cal_diff<T: Attributes>(old: &str, new: &str) -> Delta<T> {
+    let chunks = dissimilar::diff(old, new);
+    let mut delta_builder = DeltaBuilder::<T>::new();
+    for chunk in &chunks {
+        match chunk {
+            Chunk::Equal(s) => {
+                delta_builder = delta_builder.retain(FlowyStr::from(*s).utf16_size());
+            }
+            Chunk::Delete(s) => {
+                delta_builder = delta_builder.delete(FlowyStr::from(*s).utf16_size());
+            }
+            Chunk::Insert(s) => {
+                delta_builder = delta_builder.insert(*s);
+            }
+        }
+    }
+    delta_builder.build()
+}
@@ -0,0 +1,249 @@
+//! The bindings that run the documents in the browser. The deltas cross the boundary as their json,
+//! either as a string or as the bytes of a `Uint8Array`, the same encoding as the revisions, so
+//! the browser and the native clients compose and transform them the same way.
+use crate::{
+    client_document::{command::EditCommand, history::UndoResult, ClientDocument, NewlineDoc},
+    errors::CollaborateError,
+};
+use lib_ot::{
+    core::{Interval, OperationTransformable},
+    errors::OTError,
+    rich_text::{RichTextAttributes, RichTextDelta},
+};
+use wasm_bindgen::prelude::*;
+
+// The errors are thrown as a js `Error` named after the code of the error.
+fn js_error<E: Into<CollaborateError>>(error: E) -> JsValue {
+    let error = error.into();
+    let js_error = js_sys::Error::new(&error.msg);
+    js_error.set_name(&error.code.to_string());
+    js_error.into()
+}
+
+fn parse_delta(bytes: &[u8]) -> Result<RichTextDelta, JsValue> {
+    RichTextDelta::from_bytes(bytes).map_err(js_error::<OTError>)
+}
+
+#[wasm_bindgen(js_name = Document)]
+pub struct WasmDocument {
+    document: ClientDocument,
+}
+
+#[wasm_bindgen(js_class = Document)]
+impl WasmDocument {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> WasmDocument {
+        Self {
+            document: ClientDocument::new::<NewlineDoc>(),
+        }
+    }
+
+    #[wasm_bindgen(js_name = fromJson)]
+    pub fn from_json(json: &str) -> Result<WasmDocument, JsValue> {
+        let document = ClientDocument::from_json(json).map_err(js_error)?;
+        Ok(Self { document })
+    }
+
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        self.document.to_json()
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.document.delta().to_bytes().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = toPlainText)]
+    pub fn to_plain_text(&self) -> String {
+        self.document.to_plain_string()
+    }
+
+    #[wasm_bindgen(js_name = revId)]
+    pub fn rev_id(&self) -> i64 {
+        self.document.rev_id()
+    }
+
+    /// Returns the json of the delta that was composed into the document, which is sent to the
+    /// server like the deltas of the native clients.
+    pub fn insert(&mut self, index: usize, text: &str) -> Result<String, JsValue> {
+        let delta = self.document.insert(index, text).map_err(js_error)?;
+        Ok(delta.to_json())
+    }
+
+    pub fn delete(&mut self, start: usize, end: usize) -> Result<String, JsValue> {
+        let delta = self.document.delete(Interval::new(start, end)).map_err(js_error)?;
+        Ok(delta.to_json())
+    }
+
+    pub fn replace(&mut self, start: usize, end: usize, text: &str) -> Result<String, JsValue> {
+        let delta = self
+            .document
+            .replace(Interval::new(start, end), text)
+            .map_err(js_error)?;
+        Ok(delta.to_json())
+    }
+
+    /// Formats the text with the attributes of `attributes`, e.g. `{"bold":true,"italic":null}`.
+    pub fn format(&mut self, start: usize, end: usize, attributes: &str) -> Result<String, JsValue> {
+        let attributes: RichTextAttributes =
+            serde_json::from_str(attributes).map_err(|e| js_error::<OTError>(e.into()))?;
+        let delta = self
+            .document
+            .format_with(Interval::new(start, end), attributes)
+            .map_err(js_error)?;
+        Ok(delta.to_json())
+    }
+
+    /// Makes the change of the json of an [EditCommand], e.g.
+    /// `{"command":"insert","index":0,"text":"hi"}`, and returns the json of its `EditResponse`.
+    pub fn handle(&mut self, command: &str) -> Result<String, JsValue> {
+        let command: EditCommand = serde_json::from_str(command).map_err(|e| js_error::<OTError>(e.into()))?;
+        let response = self.document.handle(command).map_err(js_error)?;
+        serde_json::to_string(&response).map_err(|e| js_error::<OTError>(e.into()))
+    }
+
+    /// Composes the delta made by the local user, the bytes are the json of the delta.
+    pub fn compose(&mut self, delta: &[u8]) -> Result<(), JsValue> {
+        let delta = parse_delta(delta)?;
+        self.document.compose_delta(delta).map_err(js_error)
+    }
+
+    /// Composes the delta that was received from the others, it isn't undone by [WasmDocument::undo].
+    #[wasm_bindgen(js_name = composeRemote)]
+    pub fn compose_remote(&mut self, delta: &[u8]) -> Result<(), JsValue> {
+        let delta = parse_delta(delta)?;
+        self.document.compose_remote_delta(delta).map_err(js_error)
+    }
+
+    pub fn undo(&mut self) -> Result<WasmUndoResult, JsValue> {
+        let result = self.document.undo().map_err(js_error)?;
+        Ok(result.into())
+    }
+
+    pub fn redo(&mut self) -> Result<WasmUndoResult, JsValue> {
+        let result = self.document.redo().map_err(js_error)?;
+        Ok(result.into())
+    }
+
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo(&self) -> bool {
+        self.document.can_undo()
+    }
+
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo(&self) -> bool {
+        self.document.can_redo()
+    }
+}
+
+/// See [UndoResult].
+#[wasm_bindgen(js_name = UndoResult)]
+pub struct WasmUndoResult {
+    delta: String,
+    caret: usize,
+    selection_start: usize,
+    selection_end: usize,
+}
+
+#[wasm_bindgen(js_class = UndoResult)]
+impl WasmUndoResult {
+    /// The json of the delta that was composed into the document.
+    #[wasm_bindgen(getter)]
+    pub fn delta(&self) -> String {
+        self.delta.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    #[wasm_bindgen(getter, js_name = selectionStart)]
+    pub fn selection_start(&self) -> usize {
+        self.selection_start
+    }
+
+    #[wasm_bindgen(getter, js_name = selectionEnd)]
+    pub fn selection_end(&self) -> usize {
+        self.selection_end
+    }
+}
+
+impl std::convert::From<UndoResult> for WasmUndoResult {
+    fn from(result: UndoResult) -> Self {
+        Self {
+            delta: result.delta.to_json(),
+            caret: result.caret,
+            selection_start: result.selection.start,
+            selection_end: result.selection.end,
+        }
+    }
+}
+
+/// The deltas that [transform] returns, `a.compose(b_prime)` is `b.compose(a_prime)`.
+#[wasm_bindgen]
+pub struct TransformedDeltas {
+    a_prime: Vec<u8>,
+    b_prime: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl TransformedDeltas {
+    #[wasm_bindgen(getter, js_name = aPrime)]
+    pub fn a_prime(&self) -> Vec<u8> {
+        self.a_prime.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = bPrime)]
+    pub fn b_prime(&self) -> Vec<u8> {
+        self.b_prime.clone()
+    }
+}
+
+/// Applies the delta to the plain text `text`.
+#[wasm_bindgen]
+pub fn apply(delta: &[u8], text: &str) -> Result<String, JsValue> {
+    parse_delta(delta)?.apply(text).map_err(js_error::<OTError>)
+}
+
+#[wasm_bindgen]
+pub fn compose(a: &[u8], b: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let composed = parse_delta(a)?.compose(&parse_delta(b)?).map_err(js_error::<OTError>)?;
+    Ok(composed.to_bytes().to_vec())
+}
+
+#[wasm_bindgen]
+pub fn transform(a: &[u8], b: &[u8]) -> Result<TransformedDeltas, JsValue> {
+    let (a_prime, b_prime) = parse_delta(a)?
+        .transform(&parse_delta(b)?)
+        .map_err(js_error::<OTError>)?;
+    Ok(TransformedDeltas {
+        a_prime: a_prime.to_bytes().to_vec(),
+        b_prime: b_prime.to_bytes().to_vec(),
+    })
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use crate::wasm::WasmDocument;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn document_edits_in_the_browser() {
+        let mut document = WasmDocument::new();
+        document
+            .handle(r#"{"command":"insert","index":0,"text":"hello"}"#)
+            .unwrap();
+        document
+            .handle(r#"{"command":"type_text","index":5,"text":"!"}"#)
+            .unwrap();
+        document.insert(0, "oh ").unwrap();
+        assert_eq!(document.to_plain_text(), "oh hello!\n");
+        assert_eq!(document.rev_id(), 3);
+
+        document.undo().unwrap();
+        assert!(document.can_redo());
+    }
+}
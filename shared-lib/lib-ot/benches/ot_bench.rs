@@ -0,0 +1,132 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use lib_ot::{
+    core::{DeltaBuilder, DeltaIter, OperationTransformable},
+    rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta},
+};
+
+const SIZES: [usize; 3] = [1024, 100 * 1024, 1024 * 1024];
+
+// A document with heavy formatting: every other word is bold, so it has an operation for about
+// every 5 characters.
+fn formatted_document(size: usize) -> RichTextDelta {
+    let bold: RichTextAttributes = RichTextAttribute::Bold(true).into();
+    let mut document = RichTextDelta::new();
+    let mut len = 0;
+    let mut is_bold = false;
+    while len < size {
+        let attributes = if is_bold {
+            bold.clone()
+        } else {
+            RichTextAttributes::default()
+        };
+        document.insert("lorem", attributes);
+        len += 5;
+        is_bold = !is_bold;
+    }
+    document
+}
+
+fn typing_at(index: usize) -> RichTextDelta {
+    DeltaBuilder::new().retain(index).insert("a").build()
+}
+
+fn compose_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compose");
+    for size in SIZES {
+        let document = formatted_document(size);
+        let len = document.utf16_target_len;
+        for (name, index) in [("head", 0), ("middle", len / 2), ("tail", len)] {
+            let delta = typing_at(index);
+            group.bench_with_input(BenchmarkId::new(name, size), &delta, |b, delta| {
+                b.iter(|| document.compose(black_box(delta)).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+// The document composes every keystroke into the delta it replaced last, compared to the compose
+// that allocates a new one.
+fn compose_into_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compose_into");
+    for size in SIZES {
+        let document = formatted_document(size);
+        let delta = typing_at(document.utf16_target_len / 2);
+        group.bench_with_input(BenchmarkId::new("new", size), &delta, |b, delta| {
+            b.iter(|| document.compose(black_box(delta)).unwrap())
+        });
+        let mut out = RichTextDelta::with_capacity(document.ops.len() + delta.ops.len());
+        group.bench_with_input(BenchmarkId::new("reused", size), &delta, |b, delta| {
+            b.iter(|| document.compose_into(black_box(delta), &mut out).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn transform_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transform");
+    for size in SIZES {
+        let document = formatted_document(size);
+        let len = document.utf16_target_len;
+        let a = typing_at(len / 3);
+        let b = DeltaBuilder::new()
+            .retain(len / 2)
+            .retain_with_attributes(len / 4, RichTextAttribute::Italic(true).into())
+            .build();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &(a, b), |bench, (a, b)| {
+            bench.iter(|| black_box(a).transform(black_box(b)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+// The undo of every keystroke inverts the edit against the document it was made on.
+fn invert_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("invert");
+    for size in SIZES {
+        let document = formatted_document(size);
+        let len = document.utf16_target_len;
+        let edits = [
+            ("typing", typing_at(len / 2)),
+            ("delete", DeltaBuilder::new().retain(len / 2).delete(10).build()),
+            (
+                "format",
+                DeltaBuilder::new()
+                    .retain(len / 2)
+                    .retain_with_attributes(10, RichTextAttribute::Italic(true).into())
+                    .build(),
+            ),
+        ];
+        for (name, delta) in edits {
+            group.bench_with_input(BenchmarkId::new(name, size), &delta, |b, delta| {
+                b.iter(|| black_box(delta).invert(&document))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn get_attributes_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_attributes");
+    for size in SIZES {
+        let document = formatted_document(size);
+        let index = document.utf16_target_len / 2;
+        group.bench_with_input(BenchmarkId::from_parameter(size), &index, |b, index| {
+            b.iter(|| {
+                let mut iter = DeltaIter::from_offset(&document, *index);
+                iter.next_op().map(|op| op.get_attributes())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    compose_benchmark,
+    compose_into_benchmark,
+    transform_benchmark,
+    invert_benchmark,
+    get_attributes_benchmark
+);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+use lib_ot::{core::OperationTransformable, rich_text::RichTextDelta};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|deltas: (RichTextDelta, RichTextDelta)| {
+    let (a, b) = deltas;
+    let _ = a.compose(&b);
+});
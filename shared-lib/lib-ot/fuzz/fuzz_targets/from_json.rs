@@ -0,0 +1,9 @@
+#![no_main]
+use lib_ot::rich_text::RichTextDelta;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(delta) = RichTextDelta::from_bytes(data) {
+        let _ = RichTextDelta::from_json(&delta.to_json());
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use lib_ot::{core::OperationTransformable, rich_text::RichTextDelta};
+use libfuzzer_sys::fuzz_target;
+
+// The document isn't always the one the delta was made on top of, the invert must not panic
+// when they don't fit.
+fuzz_target!(|input: (RichTextDelta, RichTextDelta)| {
+    let (delta, document) = input;
+    let _ = delta.invert(&document);
+});
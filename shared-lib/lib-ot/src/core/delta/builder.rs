@@ -1,4 +1,4 @@
-use crate::core::{trim, Attributes, Delta, PlainAttributes};
+use crate::core::{trim, Attributes, Delta, Embed, PlainAttributes};
 
 pub type PlainDeltaBuilder = DeltaBuilder<PlainAttributes>;
 
@@ -48,6 +48,11 @@ where
         self
     }
 
+    pub fn insert_embed(mut self, embed: Embed) -> Self {
+        self.delta.insert_embed(embed, T::default());
+        self
+    }
+
     pub fn trim(mut self) -> Self {
         trim(&mut self.delta);
         self
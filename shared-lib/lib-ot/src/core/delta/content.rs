@@ -0,0 +1,79 @@
+use crate::core::{delta::explain::explain_op, Attributes, Delta};
+
+impl<T> Delta<T>
+where
+    T: Attributes,
+{
+    /// Returns the delta with its operations merged the way [Delta::add] merges them, so the
+    /// deltas that make the same change have the same operations whatever they were split into,
+    /// e.g. `insert("ab")` and `insert("a") insert("b")`.
+    pub fn normalized(&self) -> Self {
+        self.ops.iter().cloned().collect()
+    }
+
+    /// Whether the two deltas make the same change, whatever their operations were split into.
+    /// The attributes are compared as sets, like `==` does.
+    pub fn content_eq(&self, other: &Self) -> bool {
+        if self.utf16_base_len != other.utf16_base_len || self.utf16_target_len != other.utf16_target_len {
+            return false;
+        }
+        self == other || self.normalized().ops == other.normalized().ops
+    }
+
+    /// Returns a hash of the change that the delta makes, which is the same for the deltas that
+    /// are [Delta::content_eq]. It's the 64 bits FNV-1a of the normalized operations, so it's the
+    /// same on all the platforms and versions, e.g. for the peers to compare their documents.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        for op in &self.normalized().ops {
+            // The text of the inserts is escaped, so the separator only ends an operation.
+            for byte in explain_op(op).bytes().chain(std::iter::once(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        hash
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{Delta, OpBuilder},
+        rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn content_ignores_the_segmentation() {
+        let bold: RichTextAttributes = RichTextAttribute::Bold(true).into();
+        let merged = RichTextDeltaBuilder::new()
+            .retain(1)
+            .insert_with_attributes("ab", bold.clone())
+            .build();
+        // The operations are pushed as they are, without being merged.
+        let mut split = RichTextDelta::new();
+        split.ops = vec![
+            OpBuilder::retain(1).build(),
+            OpBuilder::insert("a").attributes(bold.clone()).build(),
+            OpBuilder::insert("b").attributes(bold.clone()).build(),
+        ];
+        split.utf16_base_len = 1;
+        split.utf16_target_len = 3;
+
+        assert_ne!(merged, split);
+        assert!(merged.content_eq(&split));
+        assert_eq!(merged.content_hash(), split.content_hash());
+        assert_eq!(split.normalized(), merged);
+
+        let italic = RichTextDeltaBuilder::new()
+            .retain(1)
+            .insert_with_attributes("ab", RichTextAttribute::Italic(true).into())
+            .build();
+        assert!(!merged.content_eq(&italic));
+        assert_ne!(merged.content_hash(), italic.content_hash());
+        assert_ne!(Delta::<RichTextAttributes>::new().content_hash(), merged.content_hash());
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
-    core::{operation::*, DeltaIter, FlowyStr, Interval, OperationTransformable, MAX_IV_LEN},
+    core::{
+        explain_pair, operation::*, trace_step, FlowyStr, Interval, OperationTransformable, TransformStep, MAX_IV_LEN,
+    },
     errors::{ErrorBuilder, OTError, OTErrorCode},
 };
 
+#[cfg(feature = "protobuf")]
 use bytes::Bytes;
+#[cfg(feature = "json")]
 use serde::de::DeserializeOwned;
 use std::{
-    cmp::{min, Ordering},
+    cmp::{max, min, Ordering},
     fmt,
     iter::FromIterator,
     str,
@@ -15,6 +19,26 @@ use std::{
 
 pub type PlainDelta = Delta<PlainAttributes>;
 
+/// The side whose text goes first when two concurrent deltas insert at the same position, see
+/// [Delta::transform_with_priority]. Every replica has to pick the same side for the same pair of
+/// deltas, or they end up with different documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Left,
+    Right,
+}
+
+impl Priority {
+    /// Gives the priority to the side with the smaller id, e.g. the id of the user or of the
+    /// device that made the delta, which all the replicas agree on.
+    pub fn by_id<I: Ord + ?Sized>(left: &I, right: &I) -> Self {
+        match left <= right {
+            true => Priority::Left,
+            false => Priority::Right,
+        }
+    }
+}
+
 // TODO: optimize the memory usage with Arc::make_mut or Cow
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Delta<T: Attributes> {
@@ -81,10 +105,18 @@ where
         }
     }
 
+    /// Drops the operations and keeps their memory, so the delta can be filled again without
+    /// allocating, see [Delta::compose_into].
+    pub fn clear(&mut self) {
+        self.ops.clear();
+        self.utf16_base_len = 0;
+        self.utf16_target_len = 0;
+    }
+
     pub fn add(&mut self, op: Operation<T>) {
         match op {
             Operation::Delete(i) => self.delete(i),
-            Operation::Insert(i) => self.insert(&i.s, i.attributes),
+            Operation::Insert(i) => self.push_insert(i),
             Operation::Retain(r) => self.retain(r.n, r.attributes),
         }
     }
@@ -103,26 +135,43 @@ where
 
     pub fn insert(&mut self, s: &str, attributes: T) {
         let s: FlowyStr = s.into();
-        if s.is_empty() {
+        self.push_insert(Insert {
+            s,
+            attributes,
+            embed: None,
+        });
+    }
+
+    /// Inserts an object, e.g. an image, that takes the place of one character.
+    pub fn insert_embed(&mut self, embed: Embed, attributes: T) {
+        self.push_insert(Insert::embed(embed, attributes));
+    }
+
+    fn push_insert(&mut self, insert: Insert<T>) {
+        if insert.s.is_empty() {
             return;
         }
 
-        self.utf16_target_len += s.utf16_size();
+        self.utf16_target_len += insert.utf16_size();
+        let merge_or_new_op = |last: &mut Insert<T>, insert: Insert<T>| match insert.embed {
+            None => last.merge_or_new_op(&insert.s, insert.attributes),
+            Some(_) => Some(Operation::Insert(insert)),
+        };
         let new_last = match self.ops.as_mut_slice() {
-            [.., Operation::<T>::Insert(insert)] => {
+            [.., Operation::<T>::Insert(last)] => {
                 //
-                insert.merge_or_new_op(&s, attributes)
+                merge_or_new_op(last, insert)
             }
             [.., Operation::<T>::Insert(pre_insert), Operation::Delete(_)] => {
                 //
-                pre_insert.merge_or_new_op(&s, attributes)
+                merge_or_new_op(pre_insert, insert)
             }
             [.., op_last @ Operation::<T>::Delete(_)] => {
                 let new_last = op_last.clone();
-                *op_last = OpBuilder::<T>::insert(&s).attributes(attributes).build();
+                *op_last = Operation::Insert(insert);
                 Some(new_last)
             }
-            _ => Some(OpBuilder::<T>::insert(&s).attributes(attributes).build()),
+            _ => Some(Operation::Insert(insert)),
         };
 
         match new_last {
@@ -151,7 +200,14 @@ where
     pub fn apply(&self, s: &str) -> Result<String, OTError> {
         let s: FlowyStr = s.into();
         if s.utf16_size() != self.utf16_base_len {
-            return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength).build());
+            return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength)
+                .msg(format!(
+                    "The base length {} of the delta doesn't match the length {} of the text",
+                    self.utf16_base_len,
+                    s.utf16_size()
+                ))
+                .snapshot(self.explain())
+                .build());
         }
         let mut new_s = String::new();
         let code_point_iter = &mut s.utf16_code_unit_iter();
@@ -201,95 +257,78 @@ where
         inverted
     }
 
-    /// Checks if this operation has no effect.
-    #[inline]
-    pub fn is_noop(&self) -> bool {
-        matches!(self.ops.as_slice(), [] | [Operation::Retain(_)])
-    }
-
-    pub fn is_empty(&self) -> bool {
-        self.ops.is_empty()
-    }
-
-    pub fn extend(&mut self, other: Self) {
-        other.ops.into_iter().for_each(|op| self.add(op));
-    }
-}
-
-impl<T> OperationTransformable for Delta<T>
-where
-    T: Attributes,
-{
-    fn compose(&self, other: &Self) -> Result<Self, OTError>
-    where
-        Self: Sized,
-    {
-        let mut new_delta = Delta::default();
-        let mut iter = DeltaIter::new(self);
-        let mut other_iter = DeltaIter::new(other);
-
-        while iter.has_next() || other_iter.has_next() {
-            if other_iter.is_next_insert() {
-                new_delta.add(other_iter.next_op().unwrap());
-                continue;
-            }
-
-            if iter.is_next_delete() {
-                new_delta.add(iter.next_op().unwrap());
-                continue;
+    /// Returns the position that `index` is moved to after applying this delta. A position inside
+    /// a deleted range moves to the start of the range. If `priority` is true, a position stays
+    /// in front of the text inserted at the same position, otherwise it moves behind it.
+    pub fn transform_position(&self, index: usize, priority: bool) -> usize {
+        let mut index = index;
+        let mut offset = 0;
+        for op in &self.ops {
+            if offset > index {
+                break;
             }
-
-            let length = min(
-                iter.next_op_len().unwrap_or(MAX_IV_LEN),
-                other_iter.next_op_len().unwrap_or(MAX_IV_LEN),
-            );
-
-            let op = iter
-                .next_op_with_len(length)
-                .unwrap_or_else(|| OpBuilder::retain(length).build());
-            let other_op = other_iter
-                .next_op_with_len(length)
-                .unwrap_or_else(|| OpBuilder::retain(length).build());
-
-            // debug_assert_eq!(op.len(), other_op.len(), "Composing delta failed,");
-
-            match (&op, &other_op) {
-                (Operation::Retain(retain), Operation::Retain(other_retain)) => {
-                    let composed_attrs = retain.attributes.compose(&other_retain.attributes)?;
-
-                    new_delta.add(OpBuilder::retain(retain.n).attributes(composed_attrs).build())
-                }
-                (Operation::Insert(insert), Operation::Retain(other_retain)) => {
-                    let mut composed_attrs = insert.attributes.compose(&other_retain.attributes)?;
-                    composed_attrs.remove_empty();
-                    new_delta.add(OpBuilder::insert(op.get_data()).attributes(composed_attrs).build())
+            match op {
+                Operation::Delete(n) => {
+                    index -= min(*n, index - offset);
                 }
-                (Operation::Retain(_), Operation::Delete(_)) => {
-                    new_delta.add(other_op);
+                Operation::Insert(insert) => {
+                    let len = insert.utf16_size();
+                    if offset < index || !priority {
+                        index += len;
+                    }
+                    offset += len;
                 }
-                (a, b) => {
-                    debug_assert!(a.is_insert());
-                    debug_assert!(b.is_delete());
-                    continue;
+                Operation::Retain(retain) => {
+                    offset += retain.n;
                 }
             }
         }
-        Ok(new_delta)
+        index
     }
 
-    fn transform(&self, other: &Self) -> Result<(Self, Self), OTError>
-    where
-        Self: Sized,
-    {
+    /// Transforms the concurrent deltas like [OperationTransformable::transform], which gives the
+    /// priority to `self`, but with the side that goes first given by `priority`.
+    pub fn transform_with_priority(&self, other: &Self, priority: Priority) -> Result<(Self, Self), OTError> {
+        self.transform_with(other, priority, &T::Config::default())
+    }
+
+    /// Transforms like [Delta::transform_with_priority], with the attributes transformed by
+    /// `config`, e.g. the [ComposeConfig](crate::rich_text::ComposeConfig) of a document.
+    pub fn transform_with(
+        &self,
+        other: &Self,
+        priority: Priority,
+        config: &T::Config,
+    ) -> Result<(Self, Self), OTError> {
+        match priority {
+            Priority::Left => self.transform_traced(other, config, None),
+            Priority::Right => {
+                let (b_prime, a_prime) = other.transform_traced(self, config, None)?;
+                Ok((a_prime, b_prime))
+            }
+        }
+    }
+
+    // The transform of [OperationTransformable::transform], which adds a step to `trace` for
+    // every pair of operations it matches, see [crate::core::transform_explain].
+    pub(crate) fn transform_traced(
+        &self,
+        other: &Self,
+        config: &T::Config,
+        mut trace: Option<&mut Vec<TransformStep>>,
+    ) -> Result<(Self, Self), OTError> {
         if self.utf16_base_len != other.utf16_base_len {
             return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength)
                 .msg(format!(
                     "cur base length: {}, other base length: {}",
                     self.utf16_base_len, other.utf16_base_len
                 ))
+                .snapshot(explain_pair(self, other))
                 .build());
         }
 
+        let _ = self.check_limits()?;
+        let _ = other.check_limits()?;
         let mut a_prime = Delta::default();
         let mut b_prime = Delta::default();
 
@@ -298,46 +337,67 @@ where
 
         let mut next_op1 = ops1.next();
         let mut next_op2 = ops2.next();
+        // Like in the compose, every step takes at least one operation of either delta.
+        let max_steps = self.ops.len() + other.ops.len() + 1;
+        let mut steps = 0;
         loop {
+            steps += 1;
+            if steps > max_steps {
+                return Err(stuck_error("transform", self, other));
+            }
+            let before = trace.as_ref().map(|_| {
+                let lens = |delta: &Self| (delta.utf16_base_len, delta.utf16_target_len);
+                (next_op1.clone(), next_op2.clone(), lens(&a_prime), lens(&b_prime))
+            });
             match (&next_op1, &next_op2) {
                 (None, None) => break,
                 (Some(Operation::Insert(insert)), _) => {
                     // let composed_attrs = transform_attributes(&next_op1, &next_op2, true);
-                    a_prime.insert(&insert.s, insert.attributes.clone());
+                    a_prime.add(Operation::Insert(insert.clone()));
                     b_prime.retain(insert.utf16_size(), insert.attributes.clone());
                     next_op1 = ops1.next();
                 }
                 (_, Some(Operation::Insert(o_insert))) => {
-                    let composed_attrs = transform_op_attribute(&next_op1, &next_op2)?;
-                    a_prime.retain(o_insert.utf16_size(), composed_attrs.clone());
-                    b_prime.insert(&o_insert.s, composed_attrs);
+                    // The text of the other one keeps its attributes, the formatting of this one
+                    // doesn't cover the text it didn't know of.
+                    a_prime.retain(o_insert.utf16_size(), T::default());
+                    b_prime.add(Operation::Insert(o_insert.clone()));
                     next_op2 = ops2.next();
                 }
-                (None, _) => {
-                    return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength).build());
-                }
-                (_, None) => {
-                    return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength).build());
+                (None, _) | (_, None) => {
+                    return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength)
+                        .msg("One of the deltas ends before the other one")
+                        .snapshot(explain_pair(self, other))
+                        .build());
                 }
                 (Some(Operation::Retain(retain)), Some(Operation::Retain(o_retain))) => {
-                    let composed_attrs = transform_op_attribute(&next_op1, &next_op2)?;
+                    // Both format the text, each one keeps the attributes that survive the other.
+                    let (attrs, o_attrs) = retain.attributes.transform_with(&o_retain.attributes, config)?;
                     match retain.cmp(o_retain) {
                         Ordering::Less => {
-                            a_prime.retain(retain.n, composed_attrs.clone());
-                            b_prime.retain(retain.n, composed_attrs.clone());
-                            next_op2 = Some(OpBuilder::retain(o_retain.n - retain.n).build());
+                            a_prime.retain(retain.n, attrs);
+                            b_prime.retain(retain.n, o_attrs);
+                            next_op2 = Some(
+                                OpBuilder::retain(o_retain.n - retain.n)
+                                    .attributes(o_retain.attributes.clone())
+                                    .build(),
+                            );
                             next_op1 = ops1.next();
                         }
                         Ordering::Equal => {
-                            a_prime.retain(retain.n, composed_attrs.clone());
-                            b_prime.retain(retain.n, composed_attrs.clone());
+                            a_prime.retain(retain.n, attrs);
+                            b_prime.retain(retain.n, o_attrs);
                             next_op1 = ops1.next();
                             next_op2 = ops2.next();
                         }
                         Ordering::Greater => {
-                            a_prime.retain(o_retain.n, composed_attrs.clone());
-                            b_prime.retain(o_retain.n, composed_attrs.clone());
-                            next_op1 = Some(OpBuilder::retain(retain.n - o_retain.n).build());
+                            a_prime.retain(o_retain.n, attrs);
+                            b_prime.retain(o_retain.n, o_attrs);
+                            next_op1 = Some(
+                                OpBuilder::retain(retain.n - o_retain.n)
+                                    .attributes(retain.attributes.clone())
+                                    .build(),
+                            );
                             next_op2 = ops2.next();
                         }
                     };
@@ -360,7 +420,11 @@ where
                     match i.cmp(o_retain) {
                         Ordering::Less => {
                             a_prime.delete(*i);
-                            next_op2 = Some(OpBuilder::retain(o_retain.n - *i).build());
+                            next_op2 = Some(
+                                OpBuilder::retain(o_retain.n - *i)
+                                    .attributes(o_retain.attributes.clone())
+                                    .build(),
+                            );
                             next_op1 = ops1.next();
                         }
                         Ordering::Equal => {
@@ -389,45 +453,286 @@ where
                         }
                         Ordering::Greater => {
                             b_prime.delete(*j);
-                            next_op1 = Some(OpBuilder::retain(retain.n - *j).build());
+                            next_op1 = Some(
+                                OpBuilder::retain(retain.n - *j)
+                                    .attributes(retain.attributes.clone())
+                                    .build(),
+                            );
                             next_op2 = ops2.next();
                         }
                     };
                 }
             }
+            if let (Some(trace), Some((op1, op2, a_lens, b_lens))) = (trace.as_mut(), before) {
+                trace_step(trace, &op1, &op2, (a_lens, &a_prime), (b_lens, &b_prime));
+            }
         }
+        a_prime.debug_check_invariants(&[self, other]);
+        b_prime.debug_check_invariants(&[self, other]);
         Ok((a_prime, b_prime))
     }
 
+    /// Checks if this operation has no effect.
+    #[inline]
+    pub fn is_noop(&self) -> bool {
+        matches!(self.ops.as_slice(), [] | [Operation::Retain(_)])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Composes `other` into `out` like [OperationTransformable::compose], reusing the memory of
+    /// `out`. The ones that compose on every keystroke keep a delta to compose into, e.g. the
+    /// content the document replaced last, so the operations aren't allocated each time.
+    pub fn compose_into(&self, other: &Self, out: &mut Self) -> Result<(), OTError> {
+        self.compose_into_with(other, out, &T::Config::default())
+    }
+
+    /// Composes like [OperationTransformable::compose], with the attributes composed by `config`,
+    /// e.g. the [ComposeConfig](crate::rich_text::ComposeConfig) of a document.
+    pub fn compose_with(&self, other: &Self, config: &T::Config) -> Result<Self, OTError> {
+        let mut new_delta = Delta::new();
+        self.compose_into_with(other, &mut new_delta, config)?;
+        Ok(new_delta)
+    }
+
+    /// Composes into `out` like [Delta::compose_into], with the attributes composed by `config`.
+    pub fn compose_into_with(&self, other: &Self, out: &mut Self, config: &T::Config) -> Result<(), OTError> {
+        let _ = self.check_limits()?;
+        let _ = other.check_limits()?;
+        out.clear();
+        out.ops.reserve(self.ops.len() + other.ops.len());
+        let mut iter = OpStream::new(self);
+        let mut other_iter = OpStream::new(other);
+
+        // Every step takes at least one operation of either delta, a step past all of them means
+        // the iterators don't move on.
+        let max_steps = self.ops.len() + other.ops.len() + 1;
+        let mut steps = 0;
+        while iter.peek().is_some() || other_iter.peek().is_some() {
+            steps += 1;
+            if steps > max_steps {
+                return Err(stuck_error("compose", self, other));
+            }
+            if let Some(Operation::Insert(_)) = other_iter.peek() {
+                out.add(other_iter.next_op().ok_or_else(|| compose_error(self, other))?);
+                continue;
+            }
+
+            if let Some(Operation::Delete(_)) = iter.peek() {
+                out.add(iter.next_op().ok_or_else(|| compose_error(self, other))?);
+                continue;
+            }
+
+            let length = min(
+                iter.peek_len().unwrap_or(MAX_IV_LEN),
+                other_iter.peek_len().unwrap_or(MAX_IV_LEN),
+            );
+
+            let op = iter
+                .next_with_len(length)
+                .unwrap_or_else(|| OpBuilder::retain(length).build());
+            let other_op = other_iter
+                .next_with_len(length)
+                .unwrap_or_else(|| OpBuilder::retain(length).build());
+
+            // debug_assert_eq!(op.len(), other_op.len(), "Composing delta failed,");
+
+            match (op, other_op) {
+                (Operation::Retain(retain), Operation::Retain(other_retain)) => {
+                    let composed_attrs = retain.attributes.compose_with(&other_retain.attributes, config)?;
+
+                    out.add(OpBuilder::retain(retain.n).attributes(composed_attrs).build())
+                }
+                (Operation::Insert(mut insert), Operation::Retain(other_retain)) => {
+                    let mut composed_attrs = insert.attributes.compose_with(&other_retain.attributes, config)?;
+                    composed_attrs.remove_empty();
+                    insert.attributes = composed_attrs;
+                    out.add(Operation::Insert(insert))
+                }
+                (Operation::Retain(_), other_op @ Operation::Delete(_)) => {
+                    out.add(other_op);
+                }
+                (a, b) => {
+                    debug_assert!(a.is_insert());
+                    debug_assert!(b.is_delete());
+                    continue;
+                }
+            }
+        }
+        out.debug_check_invariants(&[self, other]);
+        Ok(())
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        other.ops.into_iter().for_each(|op| self.add(op));
+    }
+
+    /// The heap memory taken by the operations and the text of their inserts. The text that is
+    /// shared with other deltas is split between them, so the sum over a document and its
+    /// history is the memory they take together. The attributes aren't counted.
+    pub fn memory_usage(&self) -> usize {
+        let text: usize = self
+            .ops
+            .iter()
+            .map(|op| match op {
+                Operation::Insert(insert) => insert.s.memory_usage(),
+                _ => 0,
+            })
+            .sum();
+        self.ops.capacity() * std::mem::size_of::<Operation<T>>() + text
+    }
+
+    /// Composes the deltas one after another, e.g. the revisions after a snapshot. The error
+    /// tells which of the deltas didn't compose, counted from 0.
+    pub fn compose_all<'a, I>(deltas: I) -> Result<Self, OTError>
+    where
+        I: IntoIterator<Item = &'a Self>,
+        T: 'a,
+    {
+        let mut deltas = deltas.into_iter();
+        let mut composed = match deltas.next() {
+            None => return Ok(Self::new()),
+            Some(delta) => delta.clone(),
+        };
+        for (index, delta) in deltas.enumerate() {
+            composed = composed.compose(delta).map_err(|e| {
+                let msg = format!("The delta {} doesn't compose: {}", index + 1, e.msg);
+                OTError { msg, ..e }
+            })?;
+        }
+        Ok(composed)
+    }
+
+    /// Returns the part of the change that applies to `interval` of the document it was made on,
+    /// retaining the text before the interval. The text inserted at the end of the interval isn't
+    /// a part of it, so an empty interval has no change.
+    pub fn between(&self, interval: Interval) -> Self {
+        let mut delta = Self::new();
+        delta.retain(interval.start, T::default());
+        self.for_each_in(interval.start, interval.end, |op| delta.add(op));
+        trim(&mut delta);
+        delta
+    }
+
+    /// Splits the change at `index` of the document it was made on: the first delta is the change
+    /// before `index`, the second one the change from `index` on, after the first one. Composing
+    /// them makes the same change, the text inserted at `index` is in the second one.
+    pub fn split_at(&self, index: usize) -> (Self, Self) {
+        let before = self.between(Interval::new(0, index));
+        let mut after = Self::new();
+        after.retain(before.utf16_target_len, T::default());
+        self.for_each_in(index, usize::MAX, |op| after.add(op));
+        trim(&mut after);
+        (before, after)
+    }
+
+    // Calls `f` with the parts of the operations that apply to the text from `start` to `end`
+    // of the document, and with the inserts from `start` to before `end`.
+    fn for_each_in<F: FnMut(Operation<T>)>(&self, start: usize, end: usize, mut f: F) {
+        let mut offset = 0;
+        for op in &self.ops {
+            if offset >= end {
+                break;
+            }
+            let len = match op {
+                Operation::Insert(_) => {
+                    if offset >= start {
+                        f(op.clone());
+                    }
+                    continue;
+                }
+                Operation::Retain(retain) => retain.n,
+                Operation::Delete(n) => *n,
+            };
+            let overlap = min(offset + len, end).saturating_sub(max(offset, start));
+            if overlap > 0 {
+                f(match op {
+                    Operation::Retain(retain) => {
+                        OpBuilder::retain(overlap).attributes(retain.attributes.clone()).build()
+                    }
+                    _ => OpBuilder::delete(overlap).build(),
+                });
+            }
+            offset += len;
+        }
+    }
+}
+
+impl<T> OperationTransformable for Delta<T>
+where
+    T: Attributes,
+{
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "compose",
+            level = "trace",
+            skip_all,
+            fields(ops = self.ops.len(), other_ops = other.ops.len()),
+            err
+        )
+    )]
+    fn compose(&self, other: &Self) -> Result<Self, OTError>
+    where
+        Self: Sized,
+    {
+        self.compose_with(other, &T::Config::default())
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "transform",
+            level = "trace",
+            skip_all,
+            fields(ops = self.ops.len(), other_ops = other.ops.len()),
+            err
+        )
+    )]
+    fn transform(&self, other: &Self) -> Result<(Self, Self), OTError>
+    where
+        Self: Sized,
+    {
+        self.transform_traced(other, &T::Config::default(), None)
+    }
+
     fn invert(&self, other: &Self) -> Self {
         let mut inverted = Delta::default();
         if other.is_empty() {
             return inverted;
         }
 
-        let mut index = 0;
+        // The operations touch the base in order, so the base is walked once. The retains
+        // without attributes only skip its operations, which keeps the inverse of an edit
+        // from copying the parts of the document the edit doesn't touch.
+        let mut base = OpStream::new(other);
         for op in &self.ops {
-            let len: usize = op.len() as usize;
             match op {
                 Operation::Delete(n) => {
-                    invert_from_other(&mut inverted, other, op, index, index + *n);
-                    index += len;
+                    ot_log!(trace, "invert delete: {}", n);
+                    base.take(*n).into_iter().for_each(|base_op| inverted.add(base_op));
                 }
-                Operation::Retain(_) => {
-                    match op.has_attribute() {
-                        true => invert_from_other(&mut inverted, other, op, index, index + len),
-                        false => {
-                            // tracing::trace!("invert retain: {} by retain {} {}", op, len,
-                            // op.get_attributes());
-                            inverted.retain(len as usize, op.get_attributes())
+                Operation::Retain(retain) => match op.has_attribute() {
+                    true => {
+                        for base_op in base.take(retain.n) {
+                            ot_log!(
+                                trace,
+                                "invert attributes: {:?}, {:?}",
+                                retain.attributes,
+                                base_op.get_attributes()
+                            );
+                            let inverted_attrs = retain.attributes.invert(&base_op.get_attributes());
+                            inverted.retain(base_op.len(), inverted_attrs);
                         }
                     }
-                    index += len;
-                }
-                Operation::Insert(_) => {
-                    // tracing::trace!("invert insert: {} by delete {}", op, len);
-                    inverted.delete(len as usize);
-                }
+                    false => {
+                        base.skip(retain.n);
+                        inverted.retain(retain.n, retain.attributes.clone());
+                    }
+                },
+                Operation::Insert(_) => inverted.delete(op.len()),
             }
         }
         inverted
@@ -446,64 +751,137 @@ where
     }
 }
 
-fn invert_from_other<T: Attributes>(
-    base: &mut Delta<T>,
-    other: &Delta<T>,
-    operation: &Operation<T>,
-    start: usize,
-    end: usize,
-) {
-    tracing::trace!("invert op: {} [{}:{}]", operation, start, end);
-    let other_ops = DeltaIter::from_interval(other, Interval::new(start, end)).ops();
-    other_ops.into_iter().for_each(|other_op| match operation {
-        Operation::Delete(_n) => {
-            // tracing::trace!("invert delete: {} by add {}", n, other_op);
-            base.add(other_op);
-        }
-        Operation::Retain(_retain) => {
-            tracing::trace!(
-                "invert attributes: {:?}, {:?}",
-                operation.get_attributes(),
-                other_op.get_attributes()
-            );
-            let inverted_attrs = operation.get_attributes().invert(&other_op.get_attributes());
-            base.retain(other_op.len(), inverted_attrs);
+fn compose_error<T: Attributes>(delta: &Delta<T>, other: &Delta<T>) -> OTError {
+    ErrorBuilder::new(OTErrorCode::ComposeOperationFail)
+        .msg("The iterator ended before its next operation")
+        .snapshot(format!("{} then {}", delta.explain(), other.explain()))
+        .build()
+}
+
+fn stuck_error<T: Attributes>(name: &str, delta: &Delta<T>, other: &Delta<T>) -> OTError {
+    ErrorBuilder::new(OTErrorCode::Internal)
+        .msg(format!("The {} took more steps than the deltas have operations", name))
+        .snapshot(format!("{} and {}", delta.explain(), other.explain()))
+        .build()
+}
+
+/// Walks the operations of a delta for the compose and the invert. Unlike the [crate::core::DeltaIter], it
+/// finds the next operation without scanning the delta from its start, and it only copies the
+/// operations it splits, so both are linear in the number of operations.
+struct OpStream<'a, T: Attributes> {
+    ops: std::slice::Iter<'a, Operation<T>>,
+    next: Option<&'a Operation<T>>,
+    // The part of the last split operation that wasn't taken yet.
+    rest: Option<Operation<T>>,
+}
+
+impl<'a, T> OpStream<'a, T>
+where
+    T: Attributes,
+{
+    fn new(delta: &'a Delta<T>) -> Self {
+        let mut stream = Self {
+            ops: delta.ops.iter(),
+            next: None,
+            rest: None,
+        };
+        stream.advance();
+        stream
+    }
+
+    fn advance(&mut self) {
+        self.next = self.ops.by_ref().find(|op| !op.is_empty());
+    }
+
+    fn peek(&self) -> Option<&Operation<T>> {
+        self.rest.as_ref().or(self.next)
+    }
+
+    fn peek_len(&self) -> Option<usize> {
+        self.peek().map(|op| op.len())
+    }
+
+    fn next_op(&mut self) -> Option<Operation<T>> {
+        if let Some(rest) = self.rest.take() {
+            return Some(rest);
         }
-        Operation::Insert(_) => {
-            log::error!("Impossible to here. Insert operation should be treated as delete")
+        let op = self.next?.clone();
+        self.advance();
+        Some(op)
+    }
+
+    fn next_with_len(&mut self, len: usize) -> Option<Operation<T>> {
+        let op_len = self.peek_len()?;
+        if len >= op_len {
+            return self.next_op();
         }
-    });
-}
 
-fn transform_op_attribute<T: Attributes>(
-    left: &Option<Operation<T>>,
-    right: &Option<Operation<T>>,
-) -> Result<T, OTError> {
-    if left.is_none() {
-        if right.is_none() {
-            return Ok(T::default());
+        let (head, tail) = {
+            let op = self.peek()?;
+            (op.shrink(Interval::new(0, len)), op.shrink(Interval::new(len, op_len)))
+        };
+        if self.rest.is_none() {
+            self.advance();
+        }
+        self.rest = tail;
+        head
+    }
+
+    /// Returns the operations covering the next `len`, fewer if the delta ends before.
+    fn take(&mut self, mut len: usize) -> Vec<Operation<T>> {
+        let mut ops = vec![];
+        while len > 0 {
+            match self.next_with_len(len) {
+                None => break,
+                Some(op) => {
+                    len -= op.len();
+                    ops.push(op);
+                }
+            }
+        }
+        ops
+    }
+
+    /// Moves over the next `len` without copying the operations, except the one it splits.
+    fn skip(&mut self, mut len: usize) {
+        while let Some(op_len) = self.peek_len() {
+            if len < op_len {
+                if len > 0 {
+                    let _ = self.next_with_len(len);
+                }
+                return;
+            }
+            if self.rest.take().is_none() {
+                self.advance();
+            }
+            len -= op_len;
         }
-        return Ok(right.as_ref().unwrap().get_attributes());
     }
-    let left = left.as_ref().unwrap().get_attributes();
-    let right = right.as_ref().unwrap().get_attributes();
-    // TODO: replace with anyhow and thiserror.
-    Ok(left.transform(&right)?.0)
 }
 
+#[cfg(feature = "json")]
 impl<T> Delta<T>
 where
     T: Attributes + DeserializeOwned,
 {
     pub fn from_json(json: &str) -> Result<Self, OTError> {
-        let delta = serde_json::from_str(json).map_err(|e| {
-            tracing::trace!("Deserialize failed: {:?}", e);
-            tracing::trace!("{:?}", json);
+        let delta: Self = serde_json::from_str(json).map_err(|e| {
+            ot_log!(trace, "Deserialize failed: {:?}", e);
+            ot_log!(trace, "{:?}", json);
             e
         })?;
+        let _ = delta.check_limits()?;
         Ok(delta)
     }
+}
 
+/// The bytes of the deltas that the protobuf messages carry, e.g. the `delta_data` of the
+/// revisions, which are the utf8 of their json.
+#[cfg(feature = "protobuf")]
+impl<T> Delta<T>
+where
+    T: Attributes + DeserializeOwned,
+{
     pub fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Result<Self, OTError> {
         let json = str::from_utf8(bytes.as_ref())?.to_owned();
         let val = Self::from_json(&json)?;
@@ -511,6 +889,18 @@ where
     }
 }
 
+#[cfg(feature = "protobuf")]
+impl<T> Delta<T>
+where
+    T: Attributes + serde::Serialize,
+{
+    pub fn to_bytes(&self) -> Bytes {
+        let json = self.to_json();
+        Bytes::from(json.into_bytes())
+    }
+}
+
+#[cfg(feature = "json")]
 impl<T> Delta<T>
 where
     T: Attributes + serde::Serialize,
@@ -519,9 +909,49 @@ where
         serde_json::to_string(self).unwrap_or_else(|_| "".to_owned())
     }
 
-    pub fn to_bytes(&self) -> Bytes {
-        let json = self.to_json();
-        Bytes::from(json.into_bytes())
+    /// Returns the json of the delta in a canonical form, which is the same for the deltas that
+    /// are equal whatever the order their attributes are kept in, e.g. to be hashed:
+    /// - the keys of the objects are sorted by their utf8 bytes,
+    /// - there is no whitespace between the tokens,
+    /// - the strings are escaped like [serde_json] does: `"`, `\` and the control characters are
+    ///   escaped, with the short escapes `\n`, `\r`, `\t`, `\b` and `\f` or else as `\u00XX`,
+    ///   and all the other characters are kept as they are.
+    pub fn to_canonical_json(&self) -> String {
+        let value = serde_json::to_value(self).unwrap_or(serde_json::Value::Null);
+        let mut json = String::new();
+        write_canonical_json(&value, &mut json);
+        json
+    }
+}
+
+#[cfg(feature = "json")]
+fn write_canonical_json(value: &serde_json::Value, json: &mut String) {
+    match value {
+        serde_json::Value::Array(values) => {
+            json.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_canonical_json(value, json);
+            }
+            json.push(']');
+        }
+        serde_json::Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            json.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                json.push(':');
+                write_canonical_json(value, json);
+            }
+            json.push('}');
+        }
+        _ => json.push_str(&value.to_string()),
     }
 }
 
@@ -538,6 +968,7 @@ where
     }
 }
 
+#[cfg(feature = "protobuf")]
 impl<T> std::convert::TryFrom<Vec<u8>> for Delta<T>
 where
     T: Attributes + DeserializeOwned,
@@ -548,6 +979,7 @@ where
     }
 }
 
+#[cfg(feature = "protobuf")]
 impl<T> std::convert::TryFrom<Bytes> for Delta<T>
 where
     T: Attributes + DeserializeOwned,
@@ -558,3 +990,75 @@ where
         Delta::from_bytes(&bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{Interval, OpBuilder, OperationTransformable, MAX_OPS_PER_DELTA},
+        rich_text::{RichTextAttribute, RichTextDelta, RichTextDeltaBuilder},
+    };
+
+    #[test]
+    fn compose_into_reuses_the_delta() {
+        let document = RichTextDeltaBuilder::new().insert("abc\n").build();
+        let typing = RichTextDeltaBuilder::new().retain(3).insert("d").retain(1).build();
+        let mut out = RichTextDeltaBuilder::new().insert("xyz").build();
+        out.ops.reserve(16);
+        let capacity = out.ops.capacity();
+
+        document.compose_into(&typing, &mut out).unwrap();
+        assert_eq!(out, document.compose(&typing).unwrap());
+        assert_eq!(out.ops.capacity(), capacity);
+    }
+
+    #[test]
+    fn compose_all_tells_which_delta_failed() {
+        let deltas = vec![
+            RichTextDeltaBuilder::new().insert("abc").build(),
+            RichTextDeltaBuilder::new().retain(3).insert("d").build(),
+            RichTextDeltaBuilder::new().delete(1).build(),
+        ];
+        let composed = RichTextDelta::compose_all(&deltas).unwrap();
+        assert_eq!(composed, RichTextDeltaBuilder::new().insert("bcd").build());
+        assert!(RichTextDelta::compose_all(&[]).unwrap().is_empty());
+
+        // A delta over the limits.
+        let mut delta = RichTextDeltaBuilder::new().retain(3).build();
+        delta.ops = vec![OpBuilder::retain(1).build(); MAX_OPS_PER_DELTA + 1];
+        let error = RichTextDelta::compose_all(&[deltas[0].clone(), delta]).unwrap_err();
+        assert!(error.msg.starts_with("The delta 1 doesn't compose"), "{}", error.msg);
+    }
+
+    #[test]
+    fn change_between_and_split() {
+        let document = RichTextDeltaBuilder::new().insert("abcdef").build();
+        let change = RichTextDeltaBuilder::new()
+            .retain(1)
+            .delete(2)
+            .insert("x")
+            .retain_with_attributes(2, RichTextAttribute::Bold(true).into())
+            .insert("y")
+            .build();
+
+        assert_eq!(
+            change.between(Interval::new(2, 4)),
+            RichTextDeltaBuilder::new()
+                .retain(2)
+                .delete(1)
+                .insert("x")
+                .retain_with_attributes(1, RichTextAttribute::Bold(true).into())
+                .build()
+        );
+        assert!(change.between(Interval::new(3, 3)).is_empty());
+
+        for index in 0..=6 {
+            let (before, after) = change.split_at(index);
+            assert_eq!(
+                document.compose(&before).unwrap().compose(&after).unwrap(),
+                document.compose(&change).unwrap(),
+                "{}",
+                index
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use crate::core::{Attributes, Delta};
+use crate::core::{Attributes, Delta, Operation, MAX_IV_LEN};
 use serde::{
+    de,
     de::{SeqAccess, Visitor},
     ser::SerializeSeq,
     Deserialize, Deserializer, Serialize, Serializer,
@@ -48,7 +49,19 @@ where
                 A: SeqAccess<'de>,
             {
                 let mut o = Delta::default();
-                while let Some(op) = seq.next_element()? {
+                while let Some(op) = seq.next_element::<Operation<T>>()? {
+                    // The lengths past the ones the iterators handle come from a broken or a
+                    // malicious peer, they would overflow the lengths of the delta.
+                    let (base_len, target_len) = match &op {
+                        Operation::Delete(n) => (*n, 0),
+                        Operation::Retain(retain) => (retain.n, retain.n),
+                        Operation::Insert(insert) => (0, insert.utf16_size()),
+                    };
+                    if o.utf16_base_len.saturating_add(base_len) > MAX_IV_LEN
+                        || o.utf16_target_len.saturating_add(target_len) > MAX_IV_LEN
+                    {
+                        return Err(de::Error::custom(format!("the delta is longer than {}", MAX_IV_LEN)));
+                    }
                     o.add(op);
                 }
                 Ok(o)
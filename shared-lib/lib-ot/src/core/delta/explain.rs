@@ -0,0 +1,309 @@
+use crate::{
+    core::{Attributes, Delta, Interval, Operation},
+    errors::OTError,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::min;
+
+impl<T> Delta<T>
+where
+    T: Attributes,
+{
+    /// Returns the operations of the delta in a compact form to read in the logs and the tests,
+    /// e.g. `retain(5){bold} insert("hi") delete(3)`.
+    pub fn explain(&self) -> String {
+        self.ops.iter().map(explain_op).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Returns the operations of two deltas that apply to the same document, e.g. the two sides of a
+/// transform, on two lines with the operations that apply to the same text under each other. The
+/// retains and the deletes are split where the other delta's are, the inserts face a blank:
+///
+/// ```text
+/// retain(2) insert("x") retain(3){bold}
+/// delete(2)             retain(3)
+/// ```
+pub fn explain_pair<T: Attributes>(delta: &Delta<T>, other: &Delta<T>) -> String {
+    let mut columns: Vec<(String, String)> = vec![];
+    let mut ops = delta.ops.iter().cloned();
+    let mut other_ops = other.ops.iter().cloned();
+    let mut next_op = ops.next();
+    let mut next_other_op = other_ops.next();
+    loop {
+        match (next_op.take(), next_other_op.take()) {
+            (None, None) => break,
+            (Some(op @ Operation::Insert(_)), other_op) | (Some(op), other_op @ None) => {
+                columns.push((explain_op(&op), String::new()));
+                next_op = ops.next();
+                next_other_op = other_op;
+            }
+            (op, Some(other_op @ Operation::Insert(_))) | (op @ None, Some(other_op)) => {
+                columns.push((String::new(), explain_op(&other_op)));
+                next_op = op;
+                next_other_op = other_ops.next();
+            }
+            (Some(op), Some(other_op)) => {
+                let len = min(op.len(), other_op.len());
+                let head = |op: &Operation<T>| op.shrink(Interval::new(0, len)).as_ref().map(explain_op);
+                columns.push((head(&op).unwrap_or_default(), head(&other_op).unwrap_or_default()));
+                next_op = match len < op.len() {
+                    true => op.shrink(Interval::new(len, op.len())),
+                    false => ops.next(),
+                };
+                next_other_op = match len < other_op.len() {
+                    true => other_op.shrink(Interval::new(len, other_op.len())),
+                    false => other_ops.next(),
+                };
+            }
+        }
+    }
+
+    let (mut line, mut other_line) = (String::new(), String::new());
+    for (cell, other_cell) in columns {
+        let width = cell.chars().count().max(other_cell.chars().count());
+        line.push_str(&format!("{:width$} ", cell, width = width));
+        other_line.push_str(&format!("{:width$} ", other_cell, width = width));
+    }
+    format!("{}\n{}", line.trim_end(), other_line.trim_end())
+}
+
+/// Which pair of operations a step of the transform matched, named after the operations of the
+/// first delta and of the second one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformRule {
+    /// The text inserted by the first delta goes first, the second prime retains it.
+    Insert,
+    /// The text inserted by the second delta, the first prime retains it.
+    OtherInsert,
+    /// Both keep the text, each prime keeps the attributes that survive the other's.
+    RetainRetain,
+    /// Both delete the text, neither prime deletes it again.
+    DeleteDelete,
+    DeleteRetain,
+    RetainDelete,
+}
+
+/// A step of [transform_explain]. The operations are in the form of [Delta::explain].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransformStep {
+    pub rule: TransformRule,
+    /// Where the step is in the document that both deltas apply to.
+    pub offset: usize,
+    /// The length of the document that the step went over, 0 for the inserts.
+    pub len: usize,
+    /// The parts of the operations of the deltas that the step took, empty for the delta whose
+    /// operation waits for the insert of the other one. The rest of a split operation is taken
+    /// by the next steps.
+    pub op: String,
+    pub other_op: String,
+    /// What the step added to each prime, empty if nothing. For [TransformRule::RetainRetain]
+    /// these are the attributes each side kept.
+    pub a_prime: String,
+    pub b_prime: String,
+}
+
+/// How two deltas were transformed, step by step, to analyze a divergence from one artifact,
+/// e.g. one attached to the report of a user. It's serialized with serde, see
+/// [TransformTrace::to_json].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransformTrace {
+    pub a: String,
+    pub b: String,
+    pub steps: Vec<TransformStep>,
+    /// The primes, none if the transform failed.
+    pub a_prime: Option<String>,
+    pub b_prime: Option<String>,
+    pub error: Option<String>,
+}
+
+#[cfg(feature = "json")]
+impl TransformTrace {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, OTError> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// Transforms `a` against `b` like [OperationTransformable::transform](crate::core::OperationTransformable::transform),
+/// which it returns the result of, and traces the steps it took. Tracing doesn't change the
+/// transform, the primes are the ones the documents compose.
+pub fn transform_explain<T: Attributes>(
+    a: &Delta<T>,
+    b: &Delta<T>,
+) -> (Result<(Delta<T>, Delta<T>), OTError>, TransformTrace) {
+    let mut steps = vec![];
+    let result = a.transform_traced(b, &T::Config::default(), Some(&mut steps));
+    let (a_prime, b_prime, error) = match &result {
+        Ok((a_prime, b_prime)) => (Some(a_prime.explain()), Some(b_prime.explain()), None),
+        Err(e) => (None, None, Some(e.display_chain())),
+    };
+    let trace = TransformTrace {
+        a: a.explain(),
+        b: b.explain(),
+        steps,
+        a_prime,
+        b_prime,
+        error,
+    };
+    (result, trace)
+}
+
+type PrimeLens = (usize, usize);
+
+// Adds the step that matched `op` with `other_op` and grew the primes from the lengths they had
+// before it.
+pub(crate) fn trace_step<T: Attributes>(
+    trace: &mut Vec<TransformStep>,
+    op: &Option<Operation<T>>,
+    other_op: &Option<Operation<T>>,
+    a_prime: (PrimeLens, &Delta<T>),
+    b_prime: (PrimeLens, &Delta<T>),
+) {
+    let (rule, len) = match (op, other_op) {
+        (Some(Operation::Insert(_)), _) => (TransformRule::Insert, 0),
+        (_, Some(Operation::Insert(_))) => (TransformRule::OtherInsert, 0),
+        (Some(op), Some(other_op)) => {
+            let rule = match (op, other_op) {
+                (Operation::Retain(_), Operation::Retain(_)) => TransformRule::RetainRetain,
+                (Operation::Delete(_), Operation::Delete(_)) => TransformRule::DeleteDelete,
+                (Operation::Delete(_), _) => TransformRule::DeleteRetain,
+                _ => TransformRule::RetainDelete,
+            };
+            (rule, min(op.len(), other_op.len()))
+        }
+        // The transform stops or fails without a step.
+        _ => return,
+    };
+    let taken = |op: &Option<Operation<T>>, took: bool| match (op, took) {
+        (Some(op), true) if len == 0 => explain_op(op),
+        (Some(op), true) => op
+            .shrink(Interval::new(0, len))
+            .as_ref()
+            .map(explain_op)
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    let offset = trace.last().map(|step| step.offset + step.len).unwrap_or(0);
+    trace.push(TransformStep {
+        rule,
+        offset,
+        len,
+        op: taken(op, rule != TransformRule::OtherInsert),
+        other_op: taken(other_op, rule != TransformRule::Insert),
+        a_prime: added(a_prime),
+        b_prime: added(b_prime),
+    });
+}
+
+// The operation that the step added to the end of the prime, which it may have been merged
+// into. The inserts are kept before the deletes at the end of a delta.
+fn added<T: Attributes>(((base_len, target_len), prime): (PrimeLens, &Delta<T>)) -> String {
+    let base_growth = prime.utf16_base_len - base_len;
+    let target_growth = prime.utf16_target_len - target_len;
+    let op = match (base_growth, target_growth) {
+        (0, 0) => return String::new(),
+        (n, 0) => return format!("delete({})", n),
+        (0, n) => match prime.ops.iter().rev().find(|op| op.is_insert()) {
+            Some(op) => op.shrink(Interval::new(op.len() - n, op.len())),
+            None => None,
+        },
+        (n, _) => match prime.ops.last() {
+            Some(op) => op.shrink(Interval::new(op.len() - n, op.len())),
+            None => None,
+        },
+    };
+    op.as_ref().map(explain_op).unwrap_or_default()
+}
+
+pub(crate) fn explain_op<T: Attributes>(op: &Operation<T>) -> String {
+    let (op, attributes) = match op {
+        Operation::Delete(n) => return format!("delete({})", n),
+        Operation::Retain(retain) => (format!("retain({})", retain.n), &retain.attributes),
+        Operation::Insert(insert) => match &insert.embed {
+            Some(embed) => (format!("insert({})", embed), &insert.attributes),
+            None => (format!("insert({:?})", insert.s.as_str()), &insert.attributes),
+        },
+    };
+    match attributes.is_empty() {
+        true => op,
+        false => format!("{}{{{}}}", op, attributes.explain()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{explain_pair, transform_explain, DeltaBuilder, OperationTransformable, TransformRule, TransformTrace},
+        rich_text::{RichTextAttribute, RichTextAttributes, RichTextDelta},
+    };
+
+    #[test]
+    fn explain_deltas() {
+        let mut attributes: RichTextAttributes = RichTextAttribute::Bold(true).into();
+        attributes.add(RichTextAttribute::Header(1));
+        let delta: RichTextDelta = DeltaBuilder::new()
+            .retain(2)
+            .insert("x\n")
+            .retain_with_attributes(3, attributes)
+            .build();
+        assert_eq!(delta.explain(), r#"retain(2) insert("x\n") retain(3){bold, header: 1}"#);
+
+        let other: RichTextDelta = DeltaBuilder::new()
+            .delete(1)
+            .retain_with_attributes(4, RichTextAttribute::Italic(false).into())
+            .build();
+        assert_eq!(
+            explain_pair(&delta, &other),
+            [
+                r#"retain(1) retain(1)               insert("x\n") retain(3){bold, header: 1}"#,
+                r#"delete(1) retain(1){italic: null}               retain(3){italic: null}"#,
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn explain_transform_steps() {
+        let a: RichTextDelta = DeltaBuilder::new().retain(1).insert("x").retain(2).build();
+        let b: RichTextDelta = DeltaBuilder::new()
+            .delete(1)
+            .retain_with_attributes(2, RichTextAttribute::Bold(true).into())
+            .build();
+        let (result, trace) = transform_explain(&a, &b);
+        let (a_prime, b_prime) = result.unwrap();
+        assert_eq!((a_prime.clone(), b_prime.clone()), a.transform(&b).unwrap());
+
+        let steps = trace
+            .steps
+            .iter()
+            .map(|step| (step.rule, step.offset, step.len))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            vec![
+                (TransformRule::RetainDelete, 0, 1),
+                (TransformRule::Insert, 1, 0),
+                (TransformRule::RetainRetain, 1, 2),
+            ]
+        );
+        assert_eq!(trace.steps[0].b_prime, "delete(1)");
+        assert_eq!(trace.steps[1].a_prime, r#"insert("x")"#);
+        assert_eq!(trace.steps[2].other_op, "retain(2){bold}");
+        assert_eq!(trace.steps[2].b_prime, "retain(2){bold}");
+        assert_eq!(trace.a_prime, Some(a_prime.explain()));
+        assert_eq!(trace.b_prime, Some(b_prime.explain()));
+
+        let json = trace.to_json();
+        assert_eq!(TransformTrace::from_json(&json).unwrap(), trace);
+
+        // The trace of a failed transform keeps the steps before the failure and the error.
+        let (result, trace) = transform_explain(&a, &DeltaBuilder::new().retain(2).build());
+        assert!(result.is_err());
+        assert!(trace.steps.is_empty() && trace.error.is_some());
+    }
+}
@@ -71,7 +71,7 @@ where
     pub fn seek<M: Metric>(&mut self, index: usize) {
         match M::seek(&mut self.cursor, index) {
             Ok(_) => {}
-            Err(e) => log::error!("Seek fail: {:?}", e),
+            Err(e) => ot_log!(error, "Seek fail: {:?}", e),
         }
     }
 
@@ -79,6 +79,12 @@ where
         self.cursor.has_next()
     }
 
+    /// Returns the next operation without consuming it. If the operation was partly consumed, only
+    /// the rest of it is returned.
+    pub fn peek_op(&self) -> Option<&Operation<T>> {
+        self.cursor.next_iter_op()
+    }
+
     pub fn is_next_insert(&self) -> bool {
         match self.cursor.next_iter_op() {
             None => false,
@@ -111,20 +117,94 @@ where
     }
 }
 
-pub fn is_empty_line_at_index(delta: &Delta<RichTextAttributes>, index: usize) -> bool {
-    let mut iter = DeltaIter::new(delta);
-    let (prev, next) = (iter.next_op_with_len(index), iter.next_op());
-    if prev.is_none() {
-        return true;
+/// A part of an operation, borrowed from the delta.
+#[derive(Debug)]
+pub struct OpSlice<'a, T: Attributes> {
+    pub op: &'a Operation<T>,
+    /// The part of the operation, relative to the start of the operation.
+    pub interval: Interval,
+}
+
+impl<'a, T> OpSlice<'a, T>
+where
+    T: Attributes,
+{
+    pub fn len(&self) -> usize {
+        self.interval.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interval.is_empty()
     }
 
-    if next.is_none() {
-        return false;
+    /// Returns whether the slice covers the whole operation.
+    pub fn is_whole(&self) -> bool {
+        self.interval.start == 0 && self.interval.end == self.op.len()
     }
 
-    let prev = prev.unwrap();
-    let next = next.unwrap();
-    OpNewline::parse(&prev).is_end() && OpNewline::parse(&next).is_start()
+    /// Copies the part of the operation.
+    pub fn to_op(&self) -> Option<Operation<T>> {
+        match self.is_whole() {
+            true => Some(self.op.clone()),
+            false => self.op.shrink(self.interval),
+        }
+    }
+}
+
+/// Walks the operations of a delta that intersect an interval without copying them, see
+/// [Delta::ops_in].
+pub struct OpSlices<'a, T: Attributes> {
+    ops: std::slice::Iter<'a, Operation<T>>,
+    offset: usize,
+    interval: Interval,
+}
+
+impl<'a, T> Iterator for OpSlices<'a, T>
+where
+    T: Attributes,
+{
+    type Item = OpSlice<'a, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        for op in &mut self.ops {
+            let start = self.offset;
+            self.offset += op.len();
+            if start >= self.interval.end {
+                return None;
+            }
+
+            let intersect = Interval::new(start, self.offset).intersect(self.interval);
+            if !intersect.is_empty() {
+                return Some(OpSlice {
+                    op,
+                    interval: intersect.translate_neg(start),
+                });
+            }
+        }
+        None
+    }
+}
+
+impl<T> Delta<T>
+where
+    T: Attributes,
+{
+    /// Returns the parts of the operations that are inside `interval`, in order.
+    pub fn ops_in(&self, interval: Interval) -> OpSlices<'_, T> {
+        OpSlices {
+            ops: self.ops.iter(),
+            offset: 0,
+            interval,
+        }
+    }
+}
+
+pub fn is_empty_line_at_index(delta: &Delta<RichTextAttributes>, index: usize) -> bool {
+    let mut iter = DeltaIter::new(delta);
+    match (iter.next_op_with_len(index), iter.next_op()) {
+        (None, _) => true,
+        (_, None) => false,
+        (Some(prev), Some(next)) => OpNewline::parse(&prev).is_end() && OpNewline::parse(&next).is_start(),
+    }
 }
 
 pub struct AttributesIter<'a, T: Attributes> {
@@ -179,21 +259,20 @@ where
 {
     type Item = (usize, T);
     fn next(&mut self) -> Option<Self::Item> {
-        let next_op = self.delta_iter.next_op();
-        next_op.as_ref()?;
+        let next_op = self.delta_iter.next_op()?;
         let mut length: usize = 0;
         let mut attributes = T::default();
 
-        match next_op.unwrap() {
+        match next_op {
             Operation::<T>::Delete(_n) => {}
             Operation::<T>::Retain(retain) => {
-                tracing::trace!("extend retain attributes with {} ", &retain.attributes);
+                ot_log!(trace, "extend retain attributes with {} ", &retain.attributes);
                 attributes.extend_other(retain.attributes.clone());
 
                 length = retain.n;
             }
             Operation::<T>::Insert(insert) => {
-                tracing::trace!("extend insert attributes with {} ", &insert.attributes);
+                ot_log!(trace, "extend insert attributes with {} ", &insert.attributes);
                 attributes.extend_other(insert.attributes.clone());
                 length = insert.utf16_size();
             }
@@ -0,0 +1,92 @@
+use crate::{
+    core::{Attributes, Delta, DeltaIter, Interval, Operation, OperationTransformable, MAX_IV_LEN},
+    errors::{ErrorBuilder, OTError, OTErrorCode},
+};
+use std::cmp::min;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MergeResult<T: Attributes> {
+    /// The merged document.
+    pub delta: Delta<T>,
+    /// The ranges of the merged document that both sides formatted with different values. The
+    /// values of `theirs` are kept there.
+    pub conflicts: Vec<Interval>,
+}
+
+impl<T> Delta<T>
+where
+    T: Attributes,
+{
+    /// Merges two deltas that were made on top of the same `base` document, e.g. by two devices
+    /// that were offline. Both changes are kept: the text inserted by `ours` at the same position
+    /// as `theirs` goes first.
+    pub fn merge(base: &Delta<T>, ours: &Delta<T>, theirs: &Delta<T>) -> Result<MergeResult<T>, OTError> {
+        if ours.utf16_base_len != base.utf16_target_len || theirs.utf16_base_len != base.utf16_target_len {
+            return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength)
+                .msg(format!(
+                    "base length: {}, ours base length: {}, theirs base length: {}",
+                    base.utf16_target_len, ours.utf16_base_len, theirs.utf16_base_len
+                ))
+                .build());
+        }
+
+        let (ours_prime, _) = ours.transform(theirs)?;
+        let change = theirs.compose(&ours_prime)?;
+        let delta = base.compose(&change)?;
+        let conflicts = attribute_conflicts(ours, theirs)?
+            .into_iter()
+            .map(|interval| {
+                let start = change.transform_position(interval.start, false);
+                let end = change.transform_position(interval.end, true).max(start);
+                Interval::new(start, end)
+            })
+            .filter(|interval| !interval.is_empty())
+            .collect();
+
+        Ok(MergeResult { delta, conflicts })
+    }
+}
+
+// Returns the ranges of the base document that `ours` and `theirs` both format, with attributes
+// that give a different result depending on which side is applied last.
+fn attribute_conflicts<T: Attributes>(ours: &Delta<T>, theirs: &Delta<T>) -> Result<Vec<Interval>, OTError> {
+    let mut conflicts: Vec<Interval> = vec![];
+    let mut ours_iter = DeltaIter::new(ours);
+    let mut theirs_iter = DeltaIter::new(theirs);
+    let mut index = 0;
+    while ours_iter.has_next() && theirs_iter.has_next() {
+        // The inserted text isn't part of the base document.
+        if ours_iter.is_next_insert() {
+            ours_iter.next_op();
+            continue;
+        }
+        if theirs_iter.is_next_insert() {
+            theirs_iter.next_op();
+            continue;
+        }
+
+        let len = min(
+            ours_iter.next_op_len().unwrap_or(MAX_IV_LEN),
+            theirs_iter.next_op_len().unwrap_or(MAX_IV_LEN),
+        );
+        let (op, other_op) = match (ours_iter.next_op_with_len(len), theirs_iter.next_op_with_len(len)) {
+            (Some(op), Some(other_op)) => (op, other_op),
+            _ => break,
+        };
+
+        if let (Operation::Retain(retain), Operation::Retain(other_retain)) = (&op, &other_op) {
+            let is_conflict = !retain.attributes.is_empty()
+                && !other_retain.attributes.is_empty()
+                && retain.attributes.compose(&other_retain.attributes)?
+                    != other_retain.attributes.compose(&retain.attributes)?;
+            if is_conflict {
+                match conflicts.last_mut() {
+                    Some(last) if last.end == index => last.end += len,
+                    _ => conflicts.push(Interval::new(index, index + len)),
+                }
+            }
+        }
+        index += len;
+    }
+    Ok(conflicts)
+}
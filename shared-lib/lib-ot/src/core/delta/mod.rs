@@ -1,14 +1,21 @@
 #![allow(clippy::module_inception)]
 mod builder;
+mod content;
 mod cursor;
 mod delta;
 mod delta_serde;
+mod explain;
 mod iterator;
+mod merge;
+mod validation;
 
 pub use builder::*;
 pub use cursor::*;
 pub use delta::*;
+pub use explain::*;
 pub use iterator::*;
+pub use merge::*;
+pub use validation::*;
 
 pub const NEW_LINE: &str = "\n";
 pub const WHITESPACE: &str = " ";
@@ -0,0 +1,257 @@
+use crate::{
+    core::{Attributes, Delta, Operation, EMBED_CHAR},
+    errors::{ErrorBuilder, OTError, OTErrorCode},
+};
+use std::fmt;
+
+/// The most operations that a delta may have. The deltas are composed into the document, so it's
+/// also the most operations of a document.
+pub const MAX_OPS_PER_DELTA: usize = 1 << 20;
+
+/// The longest text that an insert may have, in utf16 code units.
+pub const MAX_INSERT_LEN: usize = 1 << 24;
+
+/// A rule of the canonical form of a delta that the delta breaks, see [Delta::invariants]. The
+/// `index` is the position of the operation in `ops`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaViolation {
+    /// The operation has no length, e.g. `retain(0)`.
+    EmptyOperation {
+        index: usize,
+    },
+    /// The insert follows a delete. The inserts come before the deletes at the same position, or
+    /// two deltas that make the same change don't compare equal.
+    InsertAfterDelete {
+        index: usize,
+    },
+    /// The delete follows another delete that it should have been merged into.
+    UnmergedDelete {
+        index: usize,
+    },
+    /// The text of an embed isn't the [EMBED_CHAR], or a text insert contains the [EMBED_CHAR], so
+    /// the positions of the other participants would be off by the difference.
+    InvalidEmbed {
+        index: usize,
+    },
+    /// The recorded length doesn't match the length of the operations.
+    BaseLenMismatch {
+        recorded: usize,
+        actual: usize,
+    },
+    TargetLenMismatch {
+        recorded: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for DeltaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeltaViolation::EmptyOperation { index } => write!(f, "the operation {} is empty", index),
+            DeltaViolation::InsertAfterDelete { index } => write!(f, "the insert {} follows a delete", index),
+            DeltaViolation::UnmergedDelete { index } => write!(f, "the delete {} follows a delete", index),
+            DeltaViolation::InvalidEmbed { index } => write!(f, "the embed character of {} is misplaced", index),
+            DeltaViolation::BaseLenMismatch { recorded, actual } => {
+                write!(
+                    f,
+                    "the base length is {} but the operations retain {}",
+                    recorded, actual
+                )
+            }
+            DeltaViolation::TargetLenMismatch { recorded, actual } => {
+                write!(
+                    f,
+                    "the target length is {} but the operations make {}",
+                    recorded, actual
+                )
+            }
+        }
+    }
+}
+
+impl<T> Delta<T>
+where
+    T: Attributes,
+{
+    /// Returns the rules of the canonical form that the delta breaks. The deltas built with
+    /// [Delta::add] or deserialized from json don't break any, the others, e.g. the ones whose
+    /// `ops` were edited, may not compose or transform correctly.
+    pub fn invariants(&self) -> Vec<DeltaViolation> {
+        let mut violations = vec![];
+        let (mut base_len, mut target_len) = (0, 0);
+        let mut prev: Option<&Operation<T>> = None;
+        for (index, op) in self.ops.iter().enumerate() {
+            if op.is_empty() {
+                violations.push(DeltaViolation::EmptyOperation { index });
+            }
+            match (prev, op) {
+                (Some(Operation::Delete(_)), Operation::Insert(_)) => {
+                    violations.push(DeltaViolation::InsertAfterDelete { index })
+                }
+                (Some(Operation::Delete(_)), Operation::Delete(_)) => {
+                    violations.push(DeltaViolation::UnmergedDelete { index })
+                }
+                _ => {}
+            }
+            match op {
+                Operation::Delete(n) => base_len += n,
+                Operation::Retain(retain) => {
+                    base_len += retain.n;
+                    target_len += retain.n;
+                }
+                Operation::Insert(insert) => {
+                    let is_valid = match insert.embed {
+                        Some(_) => insert.s.as_str() == EMBED_CHAR,
+                        None => !insert.s.contains(EMBED_CHAR),
+                    };
+                    if !is_valid {
+                        violations.push(DeltaViolation::InvalidEmbed { index });
+                    }
+                    target_len += insert.utf16_size();
+                }
+            }
+            prev = Some(op);
+        }
+
+        if base_len != self.utf16_base_len {
+            violations.push(DeltaViolation::BaseLenMismatch {
+                recorded: self.utf16_base_len,
+                actual: base_len,
+            });
+        }
+        if target_len != self.utf16_target_len {
+            violations.push(DeltaViolation::TargetLenMismatch {
+                recorded: self.utf16_target_len,
+                actual: target_len,
+            });
+        }
+        violations
+    }
+
+    /// Checks the delta against [MAX_OPS_PER_DELTA] and [MAX_INSERT_LEN], so that a delta from a
+    /// broken or a malicious peer is rejected before the engine spends the memory and the time on
+    /// it. The compose, the transform and the json decoding check their deltas with it.
+    pub fn check_limits(&self) -> Result<(), OTError> {
+        if self.ops.len() > MAX_OPS_PER_DELTA {
+            return Err(ErrorBuilder::new(OTErrorCode::LimitExceeded)
+                .msg(format!(
+                    "the delta has {} operations, over {}",
+                    self.ops.len(),
+                    MAX_OPS_PER_DELTA
+                ))
+                .build());
+        }
+        for (index, op) in self.ops.iter().enumerate() {
+            if let Operation::Insert(insert) = op {
+                if insert.utf16_size() > MAX_INSERT_LEN {
+                    return Err(ErrorBuilder::new(OTErrorCode::LimitExceeded)
+                        .msg(format!(
+                            "the insert {} has {} characters, over {}",
+                            index,
+                            insert.utf16_size(),
+                            MAX_INSERT_LEN
+                        ))
+                        .build());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Panics in the debug builds if `self`, the result of an operation on `inputs`, breaks the
+    /// rules of the canonical form that the inputs keep.
+    #[inline]
+    pub(crate) fn debug_check_invariants(&self, inputs: &[&Self]) {
+        if cfg!(debug_assertions) && inputs.iter().all(|input| input.invariants().is_empty()) {
+            let violations = self.invariants();
+            debug_assert!(
+                violations.is_empty(),
+                "The result breaks the invariants: {:?}, {}",
+                violations,
+                self.explain()
+            );
+        }
+    }
+
+    /// Checks that the delta is canonical and applies to a document of `base_len`. It's meant for
+    /// the deltas that were received from the others, before they are composed. The delta may be
+    /// shorter than the document, the rest of the document is retained when it's composed.
+    pub fn validate_against(&self, base_len: usize) -> Result<(), OTError> {
+        let violations = self.invariants();
+        if violations.is_empty() && self.utf16_base_len <= base_len {
+            return Ok(());
+        }
+
+        let mut diagnostics = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        if self.utf16_base_len > base_len {
+            diagnostics.push(format!(
+                "the delta applies to a document of {} but the document is only {}",
+                self.utf16_base_len, base_len
+            ));
+        }
+        Err(ErrorBuilder::new(OTErrorCode::InvalidDelta)
+            .msg(diagnostics.join(", "))
+            .snapshot(self.explain())
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{DeltaBuilder, DeltaViolation, Embed, EmbedData, OpBuilder, OperationTransformable, MAX_OPS_PER_DELTA},
+        errors::OTErrorCode,
+        rich_text::RichTextDelta,
+    };
+
+    #[test]
+    fn delta_invariants() {
+        let mut delta: RichTextDelta = DeltaBuilder::new()
+            .retain(1)
+            .insert("a")
+            .insert_embed(Embed::new("image", EmbedData::default()))
+            .delete(2)
+            .build();
+        assert!(delta.invariants().is_empty());
+        assert!(delta.validate_against(3).is_ok());
+        assert!(delta.validate_against(4).is_ok());
+        assert!(delta.validate_against(2).is_err());
+
+        delta.ops.insert(3, OpBuilder::delete(1).build());
+        delta.ops.push(OpBuilder::insert("\u{FFFC}").build());
+        delta.ops.push(OpBuilder::retain(0).build());
+        assert_eq!(
+            delta.invariants(),
+            vec![
+                DeltaViolation::UnmergedDelete { index: 4 },
+                DeltaViolation::InsertAfterDelete { index: 5 },
+                DeltaViolation::InvalidEmbed { index: 5 },
+                DeltaViolation::EmptyOperation { index: 6 },
+                DeltaViolation::BaseLenMismatch { recorded: 3, actual: 4 },
+                DeltaViolation::TargetLenMismatch { recorded: 3, actual: 4 },
+            ]
+        );
+        assert!(delta.validate_against(3).is_err());
+    }
+
+    #[test]
+    fn delta_over_the_limits() {
+        let mut delta: RichTextDelta = DeltaBuilder::new().insert("a").build();
+        assert!(delta.check_limits().is_ok());
+        delta.ops = vec![OpBuilder::retain(1).build(); MAX_OPS_PER_DELTA + 1];
+        delta.utf16_base_len = MAX_OPS_PER_DELTA + 1;
+        delta.utf16_target_len = MAX_OPS_PER_DELTA + 1;
+        let error = delta.check_limits().unwrap_err();
+        assert!(matches!(error.code, OTErrorCode::LimitExceeded));
+
+        let other: RichTextDelta = DeltaBuilder::new().retain(MAX_OPS_PER_DELTA + 1).build();
+        assert!(matches!(
+            delta.transform(&other).unwrap_err().code,
+            OTErrorCode::LimitExceeded
+        ));
+        assert!(matches!(
+            other.compose(&delta).unwrap_err().code,
+            OTErrorCode::LimitExceeded
+        ));
+    }
+}
@@ -1,8 +1,11 @@
 use serde::{de, de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
-use std::{fmt, fmt::Formatter};
+use std::{fmt, fmt::Formatter, sync::Arc};
 
+/// The text of an insert. The text is shared by the clones, so the deltas that are composed,
+/// inverted or kept in the history don't copy the text they take from the others. It's only
+/// copied when a shared text is changed.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct FlowyStr(pub String);
+pub struct FlowyStr(Arc<String>);
 
 impl FlowyStr {
     // https://stackoverflow.com/questions/2241348/what-is-unicode-utf-8-utf-16
@@ -33,6 +36,15 @@ impl FlowyStr {
         }
     }
 
+    /// The heap memory taken by the text, split between the inserts that share it.
+    pub fn memory_usage(&self) -> usize {
+        self.0.capacity() / Arc::strong_count(&self.0)
+    }
+
+    pub fn is_shared_with(&self, other: &FlowyStr) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
     #[allow(dead_code)]
     fn utf16_code_point_iter(&self) -> FlowyUtf16CodePointIterator {
         FlowyUtf16CodePointIterator::new(self, 0)
@@ -49,13 +61,13 @@ impl std::ops::Deref for FlowyStr {
 
 impl std::ops::DerefMut for FlowyStr {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        Arc::make_mut(&mut self.0)
     }
 }
 
 impl std::convert::From<String> for FlowyStr {
     fn from(s: String) -> Self {
-        FlowyStr(s)
+        FlowyStr(Arc::new(s))
     }
 }
 
@@ -74,15 +86,15 @@ impl std::fmt::Display for FlowyStr {
 impl std::ops::Add<&str> for FlowyStr {
     type Output = FlowyStr;
 
-    fn add(self, rhs: &str) -> FlowyStr {
-        let new_value = self.0 + rhs;
-        new_value.into()
+    fn add(mut self, rhs: &str) -> FlowyStr {
+        self += rhs;
+        self
     }
 }
 
 impl std::ops::AddAssign<&str> for FlowyStr {
     fn add_assign(&mut self, rhs: &str) {
-        self.0 += rhs;
+        Arc::make_mut(&mut self.0).push_str(rhs);
     }
 }
 
@@ -283,6 +295,20 @@ mod tests {
         assert_eq!(output, "ab");
     }
 
+    #[test]
+    fn flowy_str_shared_by_clones() {
+        let s: FlowyStr = "abc".into();
+        let mut other = s.clone();
+        assert!(other.is_shared_with(&s));
+        assert_eq!(s.memory_usage(), 1);
+
+        other += "d";
+        assert!(!other.is_shared_with(&s));
+        assert_eq!(s.as_str(), "abc");
+        assert_eq!(other.as_str(), "abcd");
+        assert_eq!(s.memory_usage(), 3);
+    }
+
     #[test]
     fn flowy_str_utf16_code_point_iter_test1() {
         let s: FlowyStr = "👋😁👋".into();
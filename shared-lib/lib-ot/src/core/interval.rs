@@ -1,6 +1,7 @@
 use std::{
     cmp::{max, min},
     fmt,
+    iter::FromIterator,
     ops::{Range, RangeInclusive, RangeTo, RangeToInclusive},
 };
 
@@ -9,7 +10,7 @@ use std::{
 ///
 /// It is an invariant that `start <= end`. An interval where `end < start` is
 /// considered empty.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Interval {
     pub start: usize,
     pub end: usize,
@@ -51,6 +52,12 @@ impl Interval {
         self.start > val
     }
 
+    /// Returns whether `other` is inside self. An empty interval is covered if it's at a position
+    /// from the start to the end of self.
+    pub fn covers(&self, other: Interval) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
     pub fn is_empty(&self) -> bool {
         self.end <= self.start
     }
@@ -107,11 +114,134 @@ impl Interval {
         Interval { start, end }
     }
 
+    // split self at the index, the index is clamped into self
+    pub fn split_at(&self, index: usize) -> (Interval, Interval) {
+        let index = index.max(self.start).min(self.end);
+        (Interval::new(self.start, index), Interval::new(index, self.end))
+    }
+
     pub fn size(&self) -> usize {
         self.end - self.start
     }
 }
 
+/// A set of positions, kept as sorted intervals that neither overlap nor touch each other.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Interval> {
+        self.intervals.iter()
+    }
+
+    /// The number of positions in the set.
+    pub fn size(&self) -> usize {
+        self.intervals.iter().map(|interval| interval.size()).sum()
+    }
+
+    pub fn contains(&self, val: usize) -> bool {
+        self.covers(Interval::new(val, val + 1))
+    }
+
+    /// Returns whether all the positions of `interval` are in the set.
+    pub fn covers(&self, interval: Interval) -> bool {
+        if interval.is_empty() {
+            return true;
+        }
+        // The interval can only be covered by the last interval that starts before it.
+        let index = self.intervals.partition_point(|other| other.start <= interval.start);
+        index > 0 && self.intervals[index - 1].covers(interval)
+    }
+
+    pub fn insert(&mut self, interval: Interval) {
+        if interval.is_empty() {
+            return;
+        }
+
+        let mut merged = interval;
+        let mut is_inserted = false;
+        let mut intervals = Vec::with_capacity(self.intervals.len() + 1);
+        for other in self.intervals.drain(..) {
+            if other.end < merged.start {
+                intervals.push(other);
+            } else if merged.end < other.start {
+                if !is_inserted {
+                    intervals.push(merged);
+                    is_inserted = true;
+                }
+                intervals.push(other);
+            } else {
+                merged = merged.union(other);
+            }
+        }
+        if !is_inserted {
+            intervals.push(merged);
+        }
+        self.intervals = intervals;
+    }
+
+    pub fn subtract(&mut self, interval: Interval) {
+        if interval.is_empty() {
+            return;
+        }
+
+        let mut intervals = Vec::with_capacity(self.intervals.len() + 1);
+        for other in self.intervals.drain(..) {
+            let prefix = other.prefix(interval);
+            let suffix = other.suffix(interval);
+            if !prefix.is_empty() {
+                intervals.push(prefix);
+            }
+            if !suffix.is_empty() {
+                intervals.push(suffix);
+            }
+        }
+        self.intervals = intervals;
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut set = self.clone();
+        other.iter().for_each(|interval| set.insert(*interval));
+        set
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut set = self.clone();
+        other.iter().for_each(|interval| set.subtract(*interval));
+        set
+    }
+
+    pub fn intersect(&self, interval: Interval) -> IntervalSet {
+        self.intervals
+            .iter()
+            .map(|other| other.intersect(interval))
+            .filter(|other| !other.is_empty())
+            .collect()
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = Interval>>(iter: I) -> Self {
+        let mut set = IntervalSet::new();
+        iter.into_iter().for_each(|interval| set.insert(interval));
+        set
+    }
+}
+
 impl std::default::Default for Interval {
     fn default() -> Self {
         Interval::new(0, 0)
@@ -157,7 +287,7 @@ impl From<RangeToInclusive<usize>> for Interval {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::Interval;
+    use crate::core::{Interval, IntervalSet};
 
     #[test]
     fn contains() {
@@ -228,4 +358,56 @@ mod tests {
         assert_eq!(0, Interval::new(1, 1).size());
         assert_eq!(1, Interval::new(1, 2).size());
     }
+
+    #[test]
+    fn covers() {
+        let i = Interval::new(2, 42);
+        assert!(i.covers(Interval::new(2, 42)));
+        assert!(i.covers(Interval::new(42, 42)));
+        assert!(!i.covers(Interval::new(1, 3)));
+        assert!(!i.covers(Interval::new(41, 43)));
+    }
+
+    #[test]
+    fn split_at() {
+        let i = Interval::new(2, 42);
+        assert_eq!((Interval::new(2, 3), Interval::new(3, 42)), i.split_at(3));
+        assert_eq!((Interval::new(2, 2), Interval::new(2, 42)), i.split_at(0));
+        assert_eq!((Interval::new(2, 42), Interval::new(42, 42)), i.split_at(50));
+    }
+
+    #[test]
+    fn interval_set() {
+        let mut set = vec![Interval::new(5, 7), Interval::new(1, 3), Interval::new(3, 4)]
+            .into_iter()
+            .collect::<IntervalSet>();
+        assert_eq!(set.intervals(), &[Interval::new(1, 4), Interval::new(5, 7)]);
+
+        set.insert(Interval::new(4, 5));
+        assert_eq!(set.intervals(), &[Interval::new(1, 7)]);
+
+        set.subtract(Interval::new(2, 3));
+        assert_eq!(set.intervals(), &[Interval::new(1, 2), Interval::new(3, 7)]);
+        assert!(set.contains(1));
+        assert!(!set.contains(2));
+        assert!(set.covers(Interval::new(3, 7)));
+        assert!(!set.covers(Interval::new(1, 4)));
+        assert_eq!(set.size(), 5);
+
+        let other = vec![Interval::new(0, 1), Interval::new(4, 5)]
+            .into_iter()
+            .collect::<IntervalSet>();
+        assert_eq!(
+            set.union(&other).intervals(),
+            &[Interval::new(0, 2), Interval::new(3, 7)]
+        );
+        assert_eq!(
+            set.difference(&other).intervals(),
+            &[Interval::new(1, 2), Interval::new(3, 4), Interval::new(5, 7)]
+        );
+        assert_eq!(
+            set.intersect(Interval::new(0, 4)).intervals(),
+            &[Interval::new(1, 2), Interval::new(3, 4)]
+        );
+    }
 }
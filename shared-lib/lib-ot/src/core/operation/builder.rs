@@ -1,5 +1,5 @@
 use crate::{
-    core::{Attributes, Operation, PlainAttributes},
+    core::{Attributes, Embed, Insert, Operation, PlainAttributes},
     rich_text::RichTextAttributes,
 };
 
@@ -34,6 +34,10 @@ where
         OpBuilder::new(Operation::Insert(s.into()))
     }
 
+    pub fn embed(embed: Embed) -> OpBuilder<T> {
+        OpBuilder::new(Operation::Insert(Insert::embed(embed, T::default())))
+    }
+
     pub fn attributes(mut self, attrs: T) -> OpBuilder<T> {
         self.attrs = attrs;
         self
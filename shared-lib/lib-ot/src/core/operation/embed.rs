@@ -0,0 +1,532 @@
+use crate::errors::{ErrorBuilder, OTError, OTErrorCode};
+use serde::{
+    de,
+    de::{MapAccess, SeqAccess, Visitor},
+    ser,
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{collections::BTreeMap, fmt, fmt::Write};
+
+/// The text that an embed takes the place of. An embed is one character long, so the positions
+/// around it are the same as the ones of the text.
+pub const EMBED_CHAR: &str = "\u{FFFC}";
+
+/// The payload of an embed, kept as its json text whether the crate is built with the `json`
+/// feature or not. The text is compact and the keys of its objects are sorted, so two payloads are
+/// equal if their json values are. It's read from any serde format, e.g. the json of a delta.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmbedData(String);
+
+impl EmbedData {
+    /// Reads the payload from its json text.
+    pub fn from_json_text(text: &str) -> Result<Self, OTError> {
+        let invalid = || {
+            ErrorBuilder::new(OTErrorCode::SerdeError)
+                .msg(format!("Invalid json of an embed: {}", text))
+                .build()
+        };
+        let (value, rest) = split_value(text).ok_or_else(invalid)?;
+        if !rest.trim().is_empty() {
+            return Err(invalid());
+        }
+        let mut json = String::new();
+        write_canonical(value, &mut json).ok_or_else(invalid)?;
+        Ok(Self(json))
+    }
+
+    pub fn as_json_text(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "json")]
+impl EmbedData {
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::from_str(&self.0).unwrap_or(serde_json::Value::Null)
+    }
+}
+
+#[cfg(feature = "json")]
+impl std::convert::From<serde_json::Value> for EmbedData {
+    fn from(value: serde_json::Value) -> Self {
+        EmbedData::deserialize(value).unwrap_or_default()
+    }
+}
+
+/// A string payload, e.g. the url of a Quill image: `{"image":"https://..."}`.
+impl std::convert::From<&str> for EmbedData {
+    fn from(s: &str) -> Self {
+        let mut json = String::new();
+        write_escaped(s, &mut json);
+        Self(json)
+    }
+}
+
+impl std::convert::From<String> for EmbedData {
+    fn from(s: String) -> Self {
+        EmbedData::from(s.as_str())
+    }
+}
+
+impl std::default::Default for EmbedData {
+    fn default() -> Self {
+        Self("null".to_owned())
+    }
+}
+
+impl fmt::Display for EmbedData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for EmbedData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        JsonText(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbedData {
+    fn deserialize<D>(deserializer: D) -> Result<EmbedData, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EmbedDataVisitor;
+        impl<'de> Visitor<'de> for EmbedDataVisitor {
+            type Value = EmbedData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("any json value")
+            }
+
+            fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+                Ok(EmbedData(value.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(EmbedData(value.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(EmbedData(value.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+                let mut json = String::new();
+                write_f64(value, &mut json);
+                Ok(EmbedData(json))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(EmbedData::from(value))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(EmbedData::default())
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(EmbedData::default())
+            }
+
+            fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+                EmbedData::deserialize(deserializer)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut elements = vec![];
+                while let Some(element) = seq.next_element::<EmbedData>()? {
+                    elements.push(element.0);
+                }
+                Ok(EmbedData(format!("[{}]", elements.join(","))))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut members = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry::<String, EmbedData>()? {
+                    members.insert(key, value.0);
+                }
+                Ok(EmbedData(write_object(members)))
+            }
+        }
+        deserializer.deserialize_any(EmbedDataVisitor)
+    }
+}
+
+// A json value in its text, serialized as the value it holds.
+struct JsonText<'a>(&'a str);
+
+impl<'a> Serialize for JsonText<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let invalid = || <S::Error as ser::Error>::custom(format!("Invalid json of an embed: {}", self.0));
+        match self.0.as_bytes().first() {
+            Some(b'{') => {
+                let members = object_members(self.0).ok_or_else(invalid)?;
+                let mut map = serializer.serialize_map(Some(members.len()))?;
+                for (key, value) in members {
+                    map.serialize_entry(&key, &JsonText(value))?;
+                }
+                map.end()
+            }
+            Some(b'[') => {
+                let elements = array_elements(self.0).ok_or_else(invalid)?;
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(&JsonText(element))?;
+                }
+                seq.end()
+            }
+            Some(b'"') => serializer.serialize_str(&unescape(self.0).ok_or_else(invalid)?),
+            _ => match self.0 {
+                "true" => serializer.serialize_bool(true),
+                "false" => serializer.serialize_bool(false),
+                "null" => serializer.serialize_unit(),
+                number => match parse_number(number).ok_or_else(invalid)? {
+                    Number::I64(n) => serializer.serialize_i64(n),
+                    Number::U64(n) => serializer.serialize_u64(n),
+                    Number::F64(n) => serializer.serialize_f64(n),
+                },
+            },
+        }
+    }
+}
+
+enum Number {
+    I64(i64),
+    U64(u64),
+    F64(f64),
+}
+
+fn parse_number(text: &str) -> Option<Number> {
+    let is_json_number = text
+        .bytes()
+        .all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'));
+    if !is_json_number {
+        return None;
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(Number::I64(n));
+    }
+    if let Ok(n) = text.parse::<u64>() {
+        return Some(Number::U64(n));
+    }
+    text.parse::<f64>().ok().filter(|n| n.is_finite()).map(Number::F64)
+}
+
+// Writes the json value of `text` in the canonical form of [EmbedData].
+fn write_canonical(text: &str, json: &mut String) -> Option<()> {
+    match text.as_bytes().first()? {
+        b'{' => {
+            let mut members = BTreeMap::new();
+            for (key, value) in object_members(text)? {
+                let mut value_json = String::new();
+                write_canonical(value, &mut value_json)?;
+                members.insert(key, value_json);
+            }
+            json.push_str(&write_object(members));
+        }
+        b'[' => {
+            json.push('[');
+            for (i, element) in array_elements(text)?.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_canonical(element, json)?;
+            }
+            json.push(']');
+        }
+        b'"' => write_escaped(&unescape(text)?, json),
+        _ => match text {
+            "true" | "false" | "null" => json.push_str(text),
+            number => match parse_number(number)? {
+                Number::I64(n) => json.push_str(&n.to_string()),
+                Number::U64(n) => json.push_str(&n.to_string()),
+                Number::F64(n) => write_f64(n, json),
+            },
+        },
+    }
+    Some(())
+}
+
+fn write_object(members: BTreeMap<String, String>) -> String {
+    let mut json = String::from("{");
+    for (i, (key, value)) in members.into_iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_escaped(&key, &mut json);
+        json.push(':');
+        json.push_str(&value);
+    }
+    json.push('}');
+    json
+}
+
+fn write_f64(n: f64, json: &mut String) {
+    match n.is_finite() {
+        true => {
+            let _ = write!(json, "{}", n);
+        }
+        false => json.push_str("null"),
+    }
+}
+
+// Escapes the string like serde_json does.
+fn write_escaped(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\u{8}' => json.push_str("\\b"),
+            '\u{c}' => json.push_str("\\f"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut s = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => s.push('\n'),
+            'r' => s.push('\r'),
+            't' => s.push('\t'),
+            'b' => s.push('\u{8}'),
+            'f' => s.push('\u{c}'),
+            'u' => {
+                let mut code = hex_code(&mut chars)?;
+                // The characters out of the basic plane are escaped as a surrogate pair.
+                if (0xD800..0xDC00).contains(&code) {
+                    if chars.next()? != '\\' || chars.next()? != 'u' {
+                        return None;
+                    }
+                    let low = hex_code(&mut chars)?;
+                    code = 0x10000 + ((code - 0xD800) << 10) + low.checked_sub(0xDC00)?;
+                }
+                s.push(char::from_u32(code)?);
+            }
+            c => s.push(c),
+        }
+    }
+    Some(s)
+}
+
+fn hex_code(chars: &mut std::str::Chars) -> Option<u32> {
+    let hex = chars.by_ref().take(4).collect::<String>();
+    match hex.len() {
+        4 => u32::from_str_radix(&hex, 16).ok(),
+        _ => None,
+    }
+}
+
+// The members of the object in `text`, with their keys unescaped.
+fn object_members(text: &str) -> Option<Vec<(String, &str)>> {
+    let mut members = vec![];
+    let mut rest = text.strip_prefix('{')?.trim_start();
+    if let Some(rest) = rest.strip_prefix('}') {
+        return rest.trim().is_empty().then(|| members);
+    }
+    loop {
+        let (key, after_key) = split_value(rest)?;
+        let after_colon = after_key.trim_start().strip_prefix(':')?;
+        let (value, after_value) = split_value(after_colon)?;
+        members.push((unescape(key)?, value));
+        let after_value = after_value.trim_start();
+        match after_value.as_bytes().first()? {
+            b',' => rest = &after_value[1..],
+            b'}' => return after_value[1..].trim().is_empty().then(|| members),
+            _ => return None,
+        }
+    }
+}
+
+// The elements of the array in `text`.
+fn array_elements(text: &str) -> Option<Vec<&str>> {
+    let mut elements = vec![];
+    let mut rest = text.strip_prefix('[')?.trim_start();
+    if let Some(rest) = rest.strip_prefix(']') {
+        return rest.trim().is_empty().then(|| elements);
+    }
+    loop {
+        let (element, after_element) = split_value(rest)?;
+        elements.push(element);
+        let after_element = after_element.trim_start();
+        match after_element.as_bytes().first()? {
+            b',' => rest = &after_element[1..],
+            b']' => return after_element[1..].trim().is_empty().then(|| elements),
+            _ => return None,
+        }
+    }
+}
+
+// Splits the json value at the start of `text` from the text after it. The value is only
+// delimited, it's read by the functions above.
+fn split_value(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let bytes = text.as_bytes();
+    let end = match *bytes.first()? {
+        b'"' => string_end(bytes, 0)?,
+        b'{' | b'[' => {
+            let mut depth = 0;
+            let mut i = 0;
+            loop {
+                match *bytes.get(i)? {
+                    b'"' => {
+                        i = string_end(bytes, i)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break i + 1;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+        }
+        _ => bytes
+            .iter()
+            .position(|b| matches!(b, b',' | b':' | b'}' | b']') || b.is_ascii_whitespace())
+            .unwrap_or(bytes.len()),
+    };
+    match end {
+        0 => None,
+        end => Some((&text[..end], &text[end..])),
+    }
+}
+
+// The index after the closing quote of the string that starts at `start`.
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    loop {
+        match *bytes.get(i)? {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+}
+
+/// An object that lives in the text, e.g. an image, a divider or a mention. It's serialized the
+/// way Quill does: `{"insert":{"image":{"src":"..."}}}`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Embed {
+    pub kind: String,
+    pub data: EmbedData,
+}
+
+impl Embed {
+    pub fn new<T: ToString, D: Into<EmbedData>>(kind: T, data: D) -> Self {
+        Self {
+            kind: kind.to_string(),
+            data: data.into(),
+        }
+    }
+}
+
+impl fmt::Display for Embed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}: {}", self.kind, self.data))
+    }
+}
+
+impl Serialize for Embed {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&self.kind, &self.data)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Embed {
+    fn deserialize<D>(deserializer: D) -> Result<Embed, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EmbedVisitor;
+        impl<'de> Visitor<'de> for EmbedVisitor {
+            type Value = Embed;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map with the kind of the embed as its only key")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let (kind, data) = match map.next_entry::<String, EmbedData>()? {
+                    None => return Err(de::Error::invalid_length(0, &self)),
+                    Some(entry) => entry,
+                };
+                Ok(Embed { kind, data })
+            }
+        }
+        deserializer.deserialize_map(EmbedVisitor)
+    }
+}
+
+/// The value of the `insert` key of an operation, which is either some text or an embed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum InsertValue {
+    Text(String),
+    Embed(Embed),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{Embed, EmbedData};
+
+    #[test]
+    fn embed_data_is_kept_in_its_canonical_json() {
+        let data = EmbedData::from_json_text(r#" { "src" : "aé.png", "size": [1.0, 2], "alt": null } "#).unwrap();
+        assert_eq!(data.as_json_text(), r#"{"alt":null,"size":[1,2],"src":"aé.png"}"#);
+        assert_eq!(EmbedData::from("a \"b\"\n").as_json_text(), r#""a \"b\"\n""#);
+        assert!(EmbedData::from_json_text(r#"{"src":}"#).is_err());
+        assert!(EmbedData::from_json_text("[1] 2").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn embed_data_reads_the_quill_embeds() {
+        let embed: Embed = serde_json::from_str(r#"{"image":{"src":"a.png","width":300}}"#).unwrap();
+        assert_eq!(embed.data.as_json_text(), r#"{"src":"a.png","width":300}"#);
+        assert_eq!(
+            embed,
+            Embed::new("image", serde_json::json!({"width": 300, "src": "a.png"}))
+        );
+        assert_eq!(embed.data.to_value()["width"], 300);
+        assert_eq!(
+            serde_json::to_string(&embed).unwrap(),
+            r#"{"image":{"src":"a.png","width":300}}"#
+        );
+    }
+}
@@ -1,8 +1,10 @@
 #![allow(clippy::module_inception)]
 mod builder;
+mod embed;
 mod operation;
 mod operation_serde;
 
 pub use builder::*;
+pub use embed::*;
 pub use operation::*;
 pub use operation_serde::*;
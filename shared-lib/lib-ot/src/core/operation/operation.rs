@@ -1,5 +1,5 @@
 use crate::{
-    core::{FlowyStr, Interval, OpBuilder, OperationTransformable},
+    core::{Embed, FlowyStr, Interval, OpBuilder, OperationTransformable, EMBED_CHAR},
     errors::OTError,
 };
 use serde::{Deserialize, Serialize, __private::Formatter};
@@ -11,12 +11,54 @@ use std::{
 };
 
 pub trait Attributes: fmt::Display + Eq + PartialEq + Default + Clone + Debug + OperationTransformable {
+    /// How the attributes are composed and transformed, see [Attributes::compose_with].
+    type Config: Default;
+
     fn is_empty(&self) -> bool;
 
     // Remove the empty attribute which value is None.
     fn remove_empty(&mut self);
 
     fn extend_other(&mut self, other: Self);
+
+    /// Returns the attributes in the compact form of [Delta::explain](crate::core::Delta::explain).
+    fn explain(&self) -> String {
+        self.to_string()
+    }
+
+    /// Returns what changes from `self` to `other`, e.g. to update only the buttons of a toolbar
+    /// whose attribute changed when the selection moves.
+    fn diff(&self, other: &Self) -> AttributeChange<Self>;
+
+    /// Composes like [OperationTransformable::compose], which uses the default config, with
+    /// `config`.
+    fn compose_with(&self, other: &Self, _config: &Self::Config) -> Result<Self, OTError> {
+        self.compose(other)
+    }
+
+    /// Transforms like [OperationTransformable::transform], which uses the default config, with
+    /// `config`.
+    fn transform_with(&self, other: &Self, _config: &Self::Config) -> Result<(Self, Self), OTError> {
+        self.transform(other)
+    }
+}
+
+/// The difference between two sets of attributes, see [Attributes::diff]. A value that removes an
+/// attribute is a value like the others, it's not the same as the attribute being absent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttributeChange<T: Attributes> {
+    /// The attributes that only the new set has, with their values.
+    pub added: T,
+    /// The attributes that only the old set has, with their old values.
+    pub removed: T,
+    /// The attributes that both sets have with different values, with their new values.
+    pub changed: T,
+}
+
+impl<T: Attributes> AttributeChange<T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -38,6 +80,14 @@ where
         }
     }
 
+    /// Returns the embed that the operation inserts, see [Embed].
+    pub fn get_embed(&self) -> Option<&Embed> {
+        match self {
+            Operation::Insert(insert) => insert.embed.as_ref(),
+            _ => None,
+        }
+    }
+
     pub fn get_attributes(&self) -> T {
         match self {
             Operation::Delete(_) => T::default(),
@@ -48,7 +98,7 @@ where
 
     pub fn set_attributes(&mut self, attributes: T) {
         match self {
-            Operation::Delete(_) => log::error!("Delete should not contains attributes"),
+            Operation::Delete(_) => ot_log!(error, "Delete should not contains attributes"),
             Operation::Retain(retain) => retain.attributes = attributes,
             Operation::Insert(insert) => insert.attributes = attributes,
         }
@@ -113,7 +163,11 @@ where
                     OpBuilder::insert("").build()
                 } else {
                     let s = insert.s.sub_str(interval).unwrap_or_else(|| "".to_owned());
-                    OpBuilder::insert(&s).attributes(insert.attributes.clone()).build()
+                    Operation::Insert(Insert {
+                        s: s.into(),
+                        attributes: insert.attributes.clone(),
+                        embed: insert.embed.clone(),
+                    })
                 }
             }
         };
@@ -260,6 +314,9 @@ pub struct Insert<T: Attributes> {
 
     // #[serde(skip_serializing_if = "is_empty")]
     pub attributes: T,
+
+    // The embedded object, the `s` is the [EMBED_CHAR] then.
+    pub embed: Option<Embed>,
 }
 
 impl<T> fmt::Display for Insert<T>
@@ -267,6 +324,10 @@ where
     T: Attributes,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(embed) = &self.embed {
+            return f.write_fmt(format_args!("insert: {{{}}}, attributes: {}", embed, self.attributes));
+        }
+
         let mut s = self.s.clone();
         if s.ends_with('\n') {
             s.pop();
@@ -291,8 +352,17 @@ where
         self.s.utf16_size()
     }
 
+    pub fn embed(embed: Embed, attributes: T) -> Self {
+        Insert {
+            s: EMBED_CHAR.into(),
+            attributes,
+            embed: Some(embed),
+        }
+    }
+
     pub fn merge_or_new_op(&mut self, s: &str, attributes: T) -> Option<Operation<T>> {
-        if self.attributes == attributes {
+        // The embeds are kept apart, each one in its own operation.
+        if self.attributes == attributes && self.embed.is_none() {
             self.s += s;
             None
         } else {
@@ -313,6 +383,7 @@ where
         Insert {
             s: s.into(),
             attributes: T::default(),
+            embed: None,
         }
     }
 }
@@ -334,6 +405,7 @@ where
         Insert {
             s,
             attributes: T::default(),
+            embed: None,
         }
     }
 }
@@ -347,6 +419,8 @@ impl fmt::Display for PlainAttributes {
 }
 
 impl Attributes for PlainAttributes {
+    type Config = ();
+
     fn is_empty(&self) -> bool {
         true
     }
@@ -354,6 +428,10 @@ impl Attributes for PlainAttributes {
     fn remove_empty(&mut self) {}
 
     fn extend_other(&mut self, _other: Self) {}
+
+    fn diff(&self, _other: &Self) -> AttributeChange<Self> {
+        AttributeChange::default()
+    }
 }
 
 impl OperationTransformable for PlainAttributes {
@@ -1,4 +1,4 @@
-use crate::core::{Attributes, FlowyStr, Insert, Operation, Retain};
+use crate::core::{operation::embed::InsertValue, Attributes, FlowyStr, Insert, Operation, Retain};
 use serde::{
     de,
     de::{MapAccess, SeqAccess, Visitor},
@@ -73,8 +73,11 @@ where
                             if operation.is_some() {
                                 return Err(de::Error::duplicate_field("operation"));
                             }
-                            let i: String = map.next_value()?;
-                            operation = Some(Operation::<T>::Insert(i.into()));
+                            let insert = match map.next_value::<InsertValue>()? {
+                                InsertValue::Text(s) => s.into(),
+                                InsertValue::Embed(embed) => Insert::embed(embed, T::default()),
+                            };
+                            operation = Some(Operation::<T>::Insert(insert));
                         }
                         "attributes" => {
                             if attributes.is_some() {
@@ -83,7 +86,7 @@ where
                             let map: T = map.next_value()?;
                             attributes = Some(map);
                         }
-                        _ => panic!(),
+                        _ => return Err(de::Error::unknown_field(key, FIELDS)),
                     }
                 }
                 match operation {
@@ -98,6 +101,7 @@ where
             }
         }
 
+        const FIELDS: &[&str] = &["delete", "retain", "insert", "attributes"];
         deserializer.deserialize_any(OperationVisitor(PhantomData))
     }
 }
@@ -183,7 +187,7 @@ where
                             }
                             attributes = Some(map.next_value()?);
                         }
-                        _ => panic!(),
+                        _ => return Err(de::Error::unknown_field(key, FIELDS)),
                     }
                 }
 
@@ -215,7 +219,10 @@ where
     {
         let len = false as usize + 1 + if self.attributes.is_empty() { 0 } else { 1 };
         let mut serde_state = serializer.serialize_struct("Insert", len)?;
-        let _ = serde::ser::SerializeStruct::serialize_field(&mut serde_state, "insert", &self.s)?;
+        match &self.embed {
+            None => serde::ser::SerializeStruct::serialize_field(&mut serde_state, "insert", &self.s)?,
+            Some(embed) => serde::ser::SerializeStruct::serialize_field(&mut serde_state, "insert", embed)?,
+        }
         if !self.attributes.is_empty() {
             let _ = serde::ser::SerializeStruct::serialize_field(&mut serde_state, "attributes", &self.attributes)?;
         }
@@ -262,7 +269,11 @@ where
                     }
                 };
 
-                Ok(Insert::<T> { s, attributes })
+                Ok(Insert::<T> {
+                    s,
+                    attributes,
+                    embed: None,
+                })
             }
 
             #[inline]
@@ -270,7 +281,7 @@ where
             where
                 V: MapAccess<'de>,
             {
-                let mut s: Option<FlowyStr> = None;
+                let mut s: Option<InsertValue> = None;
                 let mut attributes: Option<T> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -286,7 +297,7 @@ where
                             }
                             attributes = Some(map.next_value()?);
                         }
-                        _ => panic!(),
+                        _ => return Err(de::Error::unknown_field(key, FIELDS)),
                     }
                 }
 
@@ -297,10 +308,15 @@ where
                 if attributes.is_none() {
                     return Err(de::Error::missing_field("attributes"));
                 }
-                Ok(Insert::<T> {
-                    s: s.unwrap(),
-                    attributes: attributes.unwrap(),
-                })
+                let attributes = attributes.unwrap();
+                match s.unwrap() {
+                    InsertValue::Text(s) => Ok(Insert::<T> {
+                        s: s.into(),
+                        attributes,
+                        embed: None,
+                    }),
+                    InsertValue::Embed(embed) => Ok(Insert::embed(embed, attributes)),
+                }
             }
         }
         const FIELDS: &[&str] = &["insert", "attributes"];
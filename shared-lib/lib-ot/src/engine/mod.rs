@@ -0,0 +1,34 @@
+mod ot;
+#[cfg(feature = "crdt")]
+mod rga;
+
+pub use ot::*;
+#[cfg(feature = "crdt")]
+pub use rga::*;
+
+use crate::{
+    core::{Attributes, Delta},
+    errors::OTError,
+};
+use bytes::Bytes;
+
+/// The replication strategy behind a document.
+///
+/// The document keeps its content as a [Delta] no matter which engine is used, the engine
+/// decides how local changes are encoded for the other replicas and how the changes of the
+/// other replicas are merged back.
+pub trait CollaborationEngine<T: Attributes>: Send + Sync {
+    /// Applies the delta made by the local user and returns the update that should be sent to
+    /// the other replicas.
+    fn apply_local(&mut self, delta: &Delta<T>) -> Result<Bytes, OTError>;
+
+    /// Integrates the update produced by [CollaborationEngine::apply_local] on another replica
+    /// and returns the delta that brings the local content up to date.
+    fn apply_remote(&mut self, update: &[u8]) -> Result<Delta<T>, OTError>;
+
+    /// Returns the current content. The returned delta only contains insert operations.
+    fn snapshot(&self) -> Delta<T>;
+
+    /// Encodes the whole replicated state, used to bootstrap a new replica.
+    fn encode_state(&self) -> Bytes;
+}
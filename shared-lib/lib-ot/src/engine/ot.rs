@@ -0,0 +1,47 @@
+use crate::{
+    core::{Attributes, Delta, OperationTransformable},
+    engine::CollaborationEngine,
+    errors::OTError,
+};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The operational transformation engine. Updates are plain deltas, the transform against the
+/// concurrent changes happens on the server before the update reaches the replica.
+#[derive(Debug, Clone, Default)]
+pub struct OTEngine<T: Attributes> {
+    delta: Delta<T>,
+}
+
+impl<T> OTEngine<T>
+where
+    T: Attributes,
+{
+    pub fn new(delta: Delta<T>) -> Self {
+        Self { delta }
+    }
+}
+
+impl<T> CollaborationEngine<T> for OTEngine<T>
+where
+    T: Attributes + Serialize + DeserializeOwned + Send + Sync,
+{
+    fn apply_local(&mut self, delta: &Delta<T>) -> Result<Bytes, OTError> {
+        self.delta = self.delta.compose(delta)?;
+        Ok(delta.to_bytes())
+    }
+
+    fn apply_remote(&mut self, update: &[u8]) -> Result<Delta<T>, OTError> {
+        let delta = Delta::<T>::from_bytes(update)?;
+        self.delta = self.delta.compose(&delta)?;
+        Ok(delta)
+    }
+
+    fn snapshot(&self) -> Delta<T> {
+        self.delta.clone()
+    }
+
+    fn encode_state(&self) -> Bytes {
+        self.delta.to_bytes()
+    }
+}
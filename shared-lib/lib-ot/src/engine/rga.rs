@@ -0,0 +1,477 @@
+use crate::{
+    core::{count_utf16_code_units, trim, Attributes, Delta, Operation, OperationTransformable},
+    engine::CollaborationEngine,
+    errors::{ErrorBuilder, OTError, OTErrorCode},
+};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The site that creates the initial content of a document. Every replica that starts from the
+/// same delta ends up with the same element ids.
+pub const RGA_ROOT_SITE: u64 = 0;
+
+/// Identifies an element of the sequence. The ids are ordered by the lamport clock first and the
+/// site id second, which gives every replica the same total order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RgaId {
+    pub clock: u64,
+    pub site: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RgaElement<T> {
+    id: RgaId,
+    s: String,
+    // The formats applied to the element, sorted by their stamp. Folding them in order gives
+    // the same attributes on every replica regardless of the order they were received.
+    formats: Vec<(RgaId, T)>,
+    deleted: bool,
+    // The element that follows it in the sequence. The state is encoded in the order of the
+    // sequence, which gives the links back.
+    #[serde(skip)]
+    next: Option<usize>,
+}
+
+impl<T> RgaElement<T>
+where
+    T: Attributes,
+{
+    fn new(id: RgaId, s: String, attributes: T) -> Self {
+        Self {
+            id,
+            s,
+            formats: vec![(id, attributes)],
+            deleted: false,
+            next: None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        count_utf16_code_units(&self.s)
+    }
+
+    fn attributes(&self) -> T {
+        let mut attributes = self.formats.iter().fold(T::default(), |acc, (_, attributes)| {
+            acc.compose(attributes).unwrap_or_else(|_| attributes.clone())
+        });
+        attributes.remove_empty();
+        attributes
+    }
+
+    fn add_format(&mut self, stamp: RgaId, attributes: T) -> bool {
+        match self.formats.binary_search_by(|(other, _)| other.cmp(&stamp)) {
+            Ok(_) => false,
+            Err(index) => {
+                self.formats.insert(index, (stamp, attributes));
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum RgaOp<T> {
+    Insert {
+        id: RgaId,
+        origin: Option<RgaId>,
+        s: String,
+        attributes: T,
+    },
+    Delete {
+        ids: Vec<RgaId>,
+    },
+    Format {
+        ids: Vec<RgaId>,
+        stamp: RgaId,
+        attributes: T,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RgaState<T> {
+    clock: u64,
+    elements: Vec<RgaElement<T>>,
+}
+
+// The attributes of the elements before an update touched them, `None` for the ones that weren't
+// visible, by their index. The delta of the update is built from them at once.
+type Touched<T> = HashMap<usize, Option<T>>;
+
+/// A replicated growable array. Each character is an element with a unique id, deleted
+/// characters are kept as tombstones so that concurrent inserts can always find their origin.
+///
+/// Updates must be delivered in causal order, which is the case when they are broadcast in the
+/// order they were produced by each replica.
+#[derive(Debug, Clone)]
+pub struct RgaEngine<T: Attributes> {
+    site: u64,
+    clock: u64,
+    // The elements in the order they were integrated, which never moves them. The sequence is
+    // linked from `head` by the `next` of the elements.
+    elements: Vec<RgaElement<T>>,
+    head: Option<usize>,
+    indexes: HashMap<RgaId, usize>,
+}
+
+impl<T> RgaEngine<T>
+where
+    T: Attributes + Serialize + DeserializeOwned + Send + Sync,
+{
+    pub fn new(site: u64) -> Self {
+        Self {
+            site,
+            clock: 0,
+            elements: vec![],
+            head: None,
+            indexes: HashMap::new(),
+        }
+    }
+
+    /// Creates the replica from the initial content of the document. The content is created by
+    /// [RGA_ROOT_SITE], so every replica that starts from the same delta can exchange updates.
+    pub fn from_delta(site: u64, delta: &Delta<T>) -> Result<Self, OTError> {
+        let mut engine = Self::new(RGA_ROOT_SITE);
+        let _ = engine.apply_local(delta)?;
+        engine.site = site;
+        Ok(engine)
+    }
+
+    pub fn from_state(site: u64, state: &[u8]) -> Result<Self, OTError> {
+        let state: RgaState<T> = serde_json::from_slice(state)?;
+        let mut elements = state.elements;
+        let len = elements.len();
+        for (index, element) in elements.iter_mut().enumerate() {
+            element.next = Some(index + 1).filter(|next| *next < len);
+        }
+        let indexes = elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| (element.id, index))
+            .collect();
+        Ok(Self {
+            site,
+            clock: state.clock,
+            head: Some(0).filter(|_| len > 0),
+            elements,
+            indexes,
+        })
+    }
+
+    fn tick(&mut self, n: u64) -> RgaId {
+        let id = RgaId {
+            clock: self.clock + 1,
+            site: self.site,
+        };
+        self.clock += n;
+        id
+    }
+
+    fn observe(&mut self, id: &RgaId) {
+        if id.clock > self.clock {
+            self.clock = id.clock;
+        }
+    }
+
+    fn next_of(&self, index: Option<usize>) -> Option<usize> {
+        match index {
+            None => self.head,
+            Some(index) => self.elements[index].next,
+        }
+    }
+
+    // The indexes of the elements in the order of the sequence.
+    fn sequence(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.head, move |index| self.elements[*index].next)
+    }
+
+    // Adds the element to the sequence after the element at `prev`, or first.
+    fn link_after(&mut self, prev: Option<usize>, mut element: RgaElement<T>) -> usize {
+        let index = self.elements.len();
+        element.next = self.next_of(prev);
+        match prev {
+            None => self.head = Some(index),
+            Some(prev) => self.elements[prev].next = Some(index),
+        }
+        self.indexes.insert(element.id, index);
+        self.elements.push(element);
+        index
+    }
+
+    // The first element after `prev` that isn't deleted.
+    fn next_visible(&self, mut prev: Option<usize>) -> Result<usize, OTError> {
+        loop {
+            match self.next_of(prev) {
+                None => return Err(ErrorBuilder::new(OTErrorCode::IncompatibleLength).build()),
+                Some(index) if self.elements[index].deleted => prev = Some(index),
+                Some(index) => return Ok(index),
+            }
+        }
+    }
+
+    fn touch(&self, touched: &mut Touched<T>, index: usize) {
+        touched.entry(index).or_insert_with(|| {
+            let element = &self.elements[index];
+            match element.deleted {
+                true => None,
+                false => Some(element.attributes()),
+            }
+        });
+    }
+
+    fn integrate_insert(
+        &mut self,
+        id: RgaId,
+        origin: Option<RgaId>,
+        s: &str,
+        attributes: &T,
+        touched: &mut Touched<T>,
+    ) -> Result<(), OTError> {
+        let mut origin = origin;
+        for (i, c) in s.chars().enumerate() {
+            let id = RgaId {
+                clock: id.clock + i as u64,
+                site: id.site,
+            };
+            self.observe(&id);
+            if self.indexes.contains_key(&id) {
+                origin = Some(id);
+                continue;
+            }
+
+            let mut prev = match &origin {
+                None => None,
+                Some(origin) => match self.indexes.get(origin) {
+                    None => {
+                        return Err(ErrorBuilder::new(OTErrorCode::Internal)
+                            .msg(format!("The origin {:?} of {:?} doesn't exist", origin, id))
+                            .build())
+                    }
+                    Some(index) => Some(*index),
+                },
+            };
+            while let Some(next) = self.next_of(prev).filter(|next| self.elements[*next].id > id) {
+                prev = Some(next);
+            }
+
+            let index = self.link_after(prev, RgaElement::new(id, c.to_string(), attributes.clone()));
+            touched.insert(index, None);
+            origin = Some(id);
+        }
+        Ok(())
+    }
+
+    fn integrate_delete(&mut self, ids: &[RgaId], touched: &mut Touched<T>) {
+        for id in ids {
+            if let Some(index) = self.indexes.get(id).copied() {
+                self.touch(touched, index);
+                self.elements[index].deleted = true;
+            }
+        }
+    }
+
+    fn integrate_format(&mut self, ids: &[RgaId], stamp: RgaId, attributes: &T, touched: &mut Touched<T>) {
+        self.observe(&stamp);
+        for id in ids {
+            if let Some(index) = self.indexes.get(id).copied() {
+                self.touch(touched, index);
+                self.elements[index].add_format(stamp, attributes.clone());
+            }
+        }
+    }
+
+    // The delta from the content before the update to the content after it, in one pass over
+    // the sequence.
+    fn delta_of(&self, touched: &Touched<T>) -> Delta<T> {
+        let mut delta = Delta::new();
+        for index in self.sequence() {
+            let element = &self.elements[index];
+            let before = match touched.get(&index) {
+                None if element.deleted => continue,
+                None => {
+                    delta.retain(element.len(), T::default());
+                    continue;
+                }
+                Some(before) => before,
+            };
+            match (before, element.deleted) {
+                (None, true) => {}
+                (None, false) => delta.insert(&element.s, element.attributes()),
+                (Some(_), true) => delta.delete(element.len()),
+                (Some(attributes), false) => {
+                    let new_attributes = element.attributes();
+                    if attributes == &new_attributes {
+                        delta.retain(element.len(), T::default());
+                    } else {
+                        // Replacing the element keeps the delta correct no matter how the format
+                        // interleaves with the formats that were applied before.
+                        delta.delete(element.len());
+                        delta.insert(&element.s, new_attributes);
+                    }
+                }
+            }
+        }
+        trim(&mut delta);
+        delta
+    }
+}
+
+impl<T> CollaborationEngine<T> for RgaEngine<T>
+where
+    T: Attributes + Serialize + DeserializeOwned + Send + Sync,
+{
+    fn apply_local(&mut self, delta: &Delta<T>) -> Result<Bytes, OTError> {
+        let mut ops: Vec<RgaOp<T>> = vec![];
+        // The index of the last element that the delta went past.
+        let mut prev = None;
+        for op in &delta.ops {
+            match op {
+                Operation::Retain(retain) => {
+                    let mut indexes = vec![];
+                    let mut n = retain.n;
+                    while n > 0 {
+                        let index = self.next_visible(prev)?;
+                        indexes.push(index);
+                        n = n.saturating_sub(self.elements[index].len());
+                        prev = Some(index);
+                    }
+
+                    if !retain.attributes.is_empty() {
+                        let stamp = self.tick(1);
+                        let mut ids = Vec::with_capacity(indexes.len());
+                        for i in indexes {
+                            self.elements[i].add_format(stamp, retain.attributes.clone());
+                            ids.push(self.elements[i].id);
+                        }
+                        ops.push(RgaOp::Format {
+                            ids,
+                            stamp,
+                            attributes: retain.attributes.clone(),
+                        });
+                    }
+                }
+                Operation::Delete(n) => {
+                    let mut ids = vec![];
+                    let mut n = *n;
+                    while n > 0 {
+                        let index = self.next_visible(prev)?;
+                        let element = &mut self.elements[index];
+                        element.deleted = true;
+                        ids.push(element.id);
+                        n = n.saturating_sub(element.len());
+                        prev = Some(index);
+                    }
+                    ops.push(RgaOp::Delete { ids });
+                }
+                Operation::Insert(insert) => {
+                    let s: &str = &insert.s;
+                    let id = self.tick(s.chars().count() as u64);
+                    let origin = prev.map(|prev| self.elements[prev].id);
+                    for (i, c) in s.chars().enumerate() {
+                        let element_id = RgaId {
+                            clock: id.clock + i as u64,
+                            site: id.site,
+                        };
+                        let element = RgaElement::new(element_id, c.to_string(), insert.attributes.clone());
+                        prev = Some(self.link_after(prev, element));
+                    }
+                    ops.push(RgaOp::Insert {
+                        id,
+                        origin,
+                        s: s.to_owned(),
+                        attributes: insert.attributes.clone(),
+                    });
+                }
+            }
+        }
+
+        let update = serde_json::to_vec(&ops)?;
+        Ok(Bytes::from(update))
+    }
+
+    fn apply_remote(&mut self, update: &[u8]) -> Result<Delta<T>, OTError> {
+        let ops: Vec<RgaOp<T>> = serde_json::from_slice(update)?;
+        let mut touched = HashMap::new();
+        for op in ops {
+            match op {
+                RgaOp::Insert {
+                    id,
+                    origin,
+                    s,
+                    attributes,
+                } => self.integrate_insert(id, origin, &s, &attributes, &mut touched)?,
+                RgaOp::Delete { ids } => self.integrate_delete(&ids, &mut touched),
+                RgaOp::Format { ids, stamp, attributes } => {
+                    self.integrate_format(&ids, stamp, &attributes, &mut touched)
+                }
+            }
+        }
+        Ok(self.delta_of(&touched))
+    }
+
+    fn snapshot(&self) -> Delta<T> {
+        let mut delta = Delta::new();
+        self.sequence()
+            .map(|index| &self.elements[index])
+            .filter(|element| !element.deleted)
+            .for_each(|element| delta.insert(&element.s, element.attributes()));
+        delta
+    }
+
+    fn encode_state(&self) -> Bytes {
+        let state = RgaState {
+            clock: self.clock,
+            elements: self.sequence().map(|index| self.elements[index].clone()).collect(),
+        };
+        Bytes::from(serde_json::to_vec(&state).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{DeltaBuilder, OperationTransformable, PlainAttributes},
+        engine::{CollaborationEngine, RgaEngine},
+    };
+
+    #[test]
+    fn rga_concurrent_inserts_converge() {
+        let initial = DeltaBuilder::<PlainAttributes>::new().insert("\n").build();
+        let mut a = RgaEngine::from_delta(1, &initial).unwrap();
+        let mut b = RgaEngine::from_delta(2, &initial).unwrap();
+
+        let update_a = a.apply_local(&DeltaBuilder::new().insert("abc").build()).unwrap();
+        let update_b = b.apply_local(&DeltaBuilder::new().insert("123").build()).unwrap();
+        let update_a2 = a.apply_local(&DeltaBuilder::new().retain(1).delete(1).build()).unwrap();
+
+        b.apply_remote(&update_a).unwrap();
+        b.apply_remote(&update_a2).unwrap();
+        a.apply_remote(&update_b).unwrap();
+
+        assert_eq!(a.snapshot(), b.snapshot());
+        assert_eq!(a.snapshot().apply("").unwrap(), "123ac\n");
+    }
+
+    #[test]
+    fn rga_remote_update_is_one_delta_of_the_old_content() {
+        let initial = DeltaBuilder::<PlainAttributes>::new().insert("hello\n").build();
+        let mut a = RgaEngine::from_delta(1, &initial).unwrap();
+        let mut b = RgaEngine::from_delta(2, &initial).unwrap();
+        let edit = DeltaBuilder::new()
+            .retain(1)
+            .delete(2)
+            .insert("ipp")
+            .retain(2)
+            .insert(" world")
+            .build();
+        let update = a.apply_local(&edit).unwrap();
+
+        let before = b.snapshot();
+        let delta = b.apply_remote(&update).unwrap();
+        assert_eq!(before.compose(&delta).unwrap(), b.snapshot());
+        assert_eq!(b.snapshot().apply("").unwrap(), "hipplo world\n");
+
+        let c = RgaEngine::<PlainAttributes>::from_state(3, &b.encode_state()).unwrap();
+        assert_eq!(c.snapshot(), b.snapshot());
+    }
+}
@@ -1,9 +1,13 @@
-use std::{fmt, fmt::Debug, str::Utf8Error};
+use std::{error::Error, fmt, fmt::Debug, str::Utf8Error, sync::Arc};
 
-#[derive(thiserror::Error, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct OTError {
     pub code: OTErrorCode,
     pub msg: String,
+    source: Option<Arc<dyn Error + Send + Sync>>,
+    /// The delta or the interval that the operation failed on. It's only kept in the debug builds
+    /// because it can contain the text of the document.
+    pub snapshot: Option<String>,
 }
 
 macro_rules! static_ot_error {
@@ -17,10 +21,7 @@ macro_rules! static_ot_error {
 
 impl std::convert::From<OTErrorCode> for OTError {
     fn from(code: OTErrorCode) -> Self {
-        OTError {
-            code: code.clone(),
-            msg: format!("{:?}", code),
-        }
+        OTError::new(code.clone(), &format!("{:?}", code))
     }
 }
 
@@ -29,6 +30,8 @@ impl OTError {
         Self {
             code,
             msg: msg.to_owned(),
+            source: None,
+            snapshot: None,
         }
     }
 
@@ -37,6 +40,32 @@ impl OTError {
         self
     }
 
+    /// Keeps `error` as the cause of this error, see [Error::source].
+    pub fn with_source<E: Error + Send + Sync + 'static>(mut self, error: E) -> Self {
+        self.source = Some(Arc::new(error));
+        self
+    }
+
+    /// Keeps what the operation failed on, in the debug builds only.
+    pub fn with_snapshot<T: fmt::Display>(mut self, snapshot: T) -> Self {
+        if cfg!(debug_assertions) {
+            self.snapshot = Some(snapshot.to_string());
+        }
+        self
+    }
+
+    /// Formats the error followed by the errors that caused it, e.g. `SerdeError: ...: caused by
+    /// expected value at line 1 column 1`.
+    pub fn display_chain(&self) -> String {
+        let mut chain = self.to_string();
+        let mut source = self.source();
+        while let Some(error) = source {
+            chain.push_str(&format!(": caused by {}", error));
+            source = error.source();
+        }
+        chain
+    }
+
     static_ot_error!(duplicate_revision, OTErrorCode::DuplicatedRevision);
     static_ot_error!(revision_id_conflict, OTErrorCode::RevisionIDConflict);
     static_ot_error!(internal, OTErrorCode::Internal);
@@ -44,19 +73,36 @@ impl OTError {
 
 impl fmt::Display for OTError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "incompatible lengths")
+        write!(f, "{:?}: {}", self.code, self.msg)?;
+        if let Some(snapshot) = &self.snapshot {
+            write!(f, " (at {})", snapshot)?;
+        }
+        Ok(())
     }
 }
 
+impl Error for OTError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|error| error as &(dyn Error + 'static))
+    }
+}
+
+#[cfg(feature = "json")]
 impl std::convert::From<serde_json::Error> for OTError {
     fn from(error: serde_json::Error) -> Self {
-        ErrorBuilder::new(OTErrorCode::SerdeError).error(error).build()
+        ErrorBuilder::new(OTErrorCode::SerdeError)
+            .error(&error)
+            .source(error)
+            .build()
     }
 }
 
 impl std::convert::From<Utf8Error> for OTError {
     fn from(error: Utf8Error) -> Self {
-        ErrorBuilder::new(OTErrorCode::SerdeError).error(error).build()
+        ErrorBuilder::new(OTErrorCode::SerdeError)
+            .error(error)
+            .source(error)
+            .build()
     }
 }
 
@@ -73,17 +119,27 @@ pub enum OTErrorCode {
     SerdeError,
     DuplicatedRevision,
     RevisionIDConflict,
+    InvalidDelta,
+    /// The delta is over [crate::core::MAX_OPS_PER_DELTA] or [crate::core::MAX_INSERT_LEN].
+    LimitExceeded,
     Internal,
 }
 
 pub struct ErrorBuilder {
     pub code: OTErrorCode,
     pub msg: Option<String>,
+    source: Option<Arc<dyn Error + Send + Sync>>,
+    snapshot: Option<String>,
 }
 
 impl ErrorBuilder {
     pub fn new(code: OTErrorCode) -> Self {
-        ErrorBuilder { code, msg: None }
+        ErrorBuilder {
+            code,
+            msg: None,
+            source: None,
+            snapshot: None,
+        }
     }
 
     pub fn msg<T>(mut self, msg: T) -> Self
@@ -102,7 +158,53 @@ impl ErrorBuilder {
         self
     }
 
+    /// See [OTError::with_source].
+    pub fn source<E: Error + Send + Sync + 'static>(mut self, error: E) -> Self {
+        self.source = Some(Arc::new(error));
+        self
+    }
+
+    /// See [OTError::with_snapshot].
+    pub fn snapshot<T: fmt::Display>(mut self, snapshot: T) -> Self {
+        if cfg!(debug_assertions) {
+            self.snapshot = Some(snapshot.to_string());
+        }
+        self
+    }
+
     pub fn build(mut self) -> OTError {
-        OTError::new(self.code, &self.msg.take().unwrap_or_else(|| "".to_owned()))
+        let mut error = OTError::new(self.code, &self.msg.take().unwrap_or_else(|| "".to_owned()));
+        error.source = self.source;
+        error.snapshot = self.snapshot;
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{DeltaBuilder, OperationTransformable},
+        errors::{OTError, OTErrorCode},
+        rich_text::RichTextDelta,
+    };
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn ot_error_keeps_its_cause() {
+        use std::error::Error;
+
+        let error = RichTextDelta::from_json("[{").unwrap_err();
+        assert!(matches!(error.code, OTErrorCode::SerdeError));
+        assert!(error.source().is_some());
+        assert!(error.display_chain().contains(": caused by "));
+    }
+
+    #[test]
+    fn ot_error_keeps_its_snapshot() {
+        let a: RichTextDelta = DeltaBuilder::new().insert("a").build();
+        let b: RichTextDelta = DeltaBuilder::new().retain(1).insert("b").build();
+        let error: OTError = a.transform(&b).unwrap_err();
+        assert!(matches!(error.code, OTErrorCode::IncompatibleLength));
+        assert_eq!(error.snapshot.is_some(), cfg!(debug_assertions));
     }
 }
@@ -0,0 +1,71 @@
+//! The [Arbitrary] implementations that the fuzz targets in `lib-ot/fuzz` build their deltas
+//! with. The lengths go up to `u32::MAX`, so the deltas may not fit each other, the same as the
+//! ones that a misbehaving peer sends.
+use crate::{
+    core::{Attributes, Delta, Embed, Insert, Interval, OpBuilder, Operation, PlainAttributes},
+    rich_text::{RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes},
+};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+impl<'a> Arbitrary<'a> for Interval {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let a = u.arbitrary::<u32>()? as usize;
+        let b = u.arbitrary::<u32>()? as usize;
+        Ok(Interval::new(a.min(b), a.max(b)))
+    }
+}
+
+impl<'a, T> Arbitrary<'a> for Operation<T>
+where
+    T: Attributes + Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let op = match u.int_in_range(0..=3)? {
+            0 => OpBuilder::delete(u.arbitrary::<u32>()? as usize).build(),
+            1 => OpBuilder::retain(u.arbitrary::<u32>()? as usize)
+                .attributes(u.arbitrary()?)
+                .build(),
+            2 => {
+                let s: String = u.arbitrary()?;
+                OpBuilder::insert(&s).attributes(u.arbitrary()?).build()
+            }
+            _ => {
+                let kind: String = u.arbitrary()?;
+                let data: String = u.arbitrary()?;
+                Operation::Insert(Insert::embed(Embed::new(kind, data), u.arbitrary()?))
+            }
+        };
+        Ok(op)
+    }
+}
+
+impl<'a, T> Arbitrary<'a> for Delta<T>
+where
+    T: Attributes + Arbitrary<'a>,
+{
+    /// Builds the delta with [Delta::add], the same as the deserialization does, so it's as
+    /// canonical as the deltas that come from the others.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let ops: Vec<Operation<T>> = u.arbitrary()?;
+        Ok(ops.into_iter().collect())
+    }
+}
+
+impl<'a> Arbitrary<'a> for PlainAttributes {
+    fn arbitrary(_u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(PlainAttributes::default())
+    }
+}
+
+impl<'a> Arbitrary<'a> for RichTextAttributes {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let keys = RichTextAttributeKey::all();
+        let mut attributes = RichTextAttributes::new();
+        for _ in 0..u.int_in_range(0..=3)? {
+            let key = u.choose(&keys)?.clone();
+            let value: Option<String> = u.arbitrary()?;
+            attributes.add_kv(key, RichTextAttributeValue(value));
+        }
+        Ok(attributes)
+    }
+}
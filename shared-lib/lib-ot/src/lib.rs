@@ -1,3 +1,20 @@
+// Logs through tracing with the `tracing` feature. Without it the arguments are only type checked, so
+// the core builds without the logging dependencies.
+macro_rules! ot_log {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
 pub mod core;
+#[cfg(feature = "protobuf")]
+pub mod engine;
 pub mod errors;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
 pub mod rich_text;
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
@@ -1,9 +1,10 @@
 #![allow(non_snake_case)]
 use crate::{
     block_attribute,
-    core::{Attributes, Operation, OperationTransformable},
-    errors::OTError,
+    core::{AttributeChange, Attributes, Operation, OperationTransformable},
+    errors::{ErrorBuilder, OTError, OTErrorCode},
     ignore_attribute, inline_attribute, list_attribute,
+    rich_text::compose_rule::{compose_with_rules, transform_with_rules, ComposeConfig},
 };
 use lazy_static::lazy_static;
 use std::{
@@ -81,6 +82,14 @@ impl RichTextAttributes {
         }
     }
 
+    /// Returns the attributes that remove all the attributes of `self` when they are composed,
+    /// the inverse of these is `self` again.
+    pub fn removed_all(&self) -> RichTextAttributes {
+        let mut attributes = self.clone();
+        attributes.mark_all_as_removed_except(None);
+        attributes
+    }
+
     pub fn remove(&mut self, key: RichTextAttributeKey) {
         self.inner.retain(|k, _| k != &key);
     }
@@ -112,6 +121,8 @@ impl RichTextAttributes {
 }
 
 impl Attributes for RichTextAttributes {
+    type Config = ComposeConfig;
+
     fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
@@ -123,6 +134,54 @@ impl Attributes for RichTextAttributes {
     fn extend_other(&mut self, other: Self) {
         self.inner.extend(other.inner);
     }
+
+    /// Returns the attributes sorted by their keys, e.g. `bold, header: 1, italic: null`. The
+    /// attributes that are on are only named.
+    fn explain(&self) -> String {
+        let mut attributes = self
+            .inner
+            .iter()
+            .map(|(key, value)| {
+                let key = key.to_string().to_lowercase();
+                match value.0.as_deref() {
+                    Some("true") => key,
+                    Some(value) => format!("{}: {}", key, value),
+                    None => format!("{}: null", key),
+                }
+            })
+            .collect::<Vec<_>>();
+        attributes.sort();
+        attributes.join(", ")
+    }
+
+    fn diff(&self, other: &Self) -> AttributeChange<Self> {
+        let mut change = AttributeChange::default();
+        for (key, value) in self.iter() {
+            match other.get(key) {
+                None => change.removed.add_kv(key.clone(), value.clone()),
+                Some(other_value) if other_value != value => change.changed.add_kv(key.clone(), other_value.clone()),
+                Some(_) => {}
+            }
+        }
+        for (key, value) in other.iter() {
+            if !self.contains_key(key) {
+                change.added.add_kv(key.clone(), value.clone());
+            }
+        }
+        change
+    }
+
+    fn compose_with(&self, other: &Self, config: &ComposeConfig) -> Result<Self, OTError> {
+        let mut attributes = self.clone();
+        compose_with_rules(&mut attributes, other, config);
+        Ok(attributes)
+    }
+
+    fn transform_with(&self, other: &Self, config: &ComposeConfig) -> Result<(Self, Self), OTError> {
+        let a = transform_with_rules(self, other, true, config);
+        let b = transform_with_rules(other, self, false, config);
+        Ok((a, b))
+    }
 }
 
 impl OperationTransformable for RichTextAttributes {
@@ -130,52 +189,25 @@ impl OperationTransformable for RichTextAttributes {
     where
         Self: Sized,
     {
-        let mut attributes = self.clone();
-        attributes.extend_other(other.clone());
-        Ok(attributes)
+        self.compose_with(other, &ComposeConfig::default())
     }
 
     fn transform(&self, other: &Self) -> Result<(Self, Self), OTError>
     where
         Self: Sized,
     {
-        let a = self
-            .iter()
-            .fold(RichTextAttributes::new(), |mut new_attributes, (k, v)| {
-                if !other.contains_key(k) {
-                    new_attributes.insert(k.clone(), v.clone());
-                }
-                new_attributes
-            });
-
-        let b = other
-            .iter()
-            .fold(RichTextAttributes::new(), |mut new_attributes, (k, v)| {
-                if !self.contains_key(k) {
-                    new_attributes.insert(k.clone(), v.clone());
-                }
-                new_attributes
-            });
-
-        Ok((a, b))
+        self.transform_with(other, &ComposeConfig::default())
     }
 
     fn invert(&self, other: &Self) -> Self {
-        let base_inverted = other.iter().fold(RichTextAttributes::new(), |mut attributes, (k, v)| {
-            if other.get(k) != self.get(k) && self.contains_key(k) {
-                attributes.insert(k.clone(), v.clone());
-            }
-            attributes
-        });
-
-        let inverted = self.iter().fold(base_inverted, |mut attributes, (k, _)| {
-            if other.get(k) != self.get(k) && !other.contains_key(k) {
-                attributes.delete(k);
-            }
-            attributes
-        });
-
-        inverted
+        // The attributes that `other` had before are restored, the ones it didn't have are removed.
+        let AttributeChange {
+            removed, mut changed, ..
+        } = self.diff(other);
+        for key in removed.keys() {
+            changed.delete(key);
+        }
+        changed
     }
 }
 
@@ -207,6 +239,17 @@ pub struct RichTextAttribute {
 }
 
 impl RichTextAttribute {
+    pub fn new(key: RichTextAttributeKey, value: RichTextAttributeValue) -> Self {
+        let scope = key.scope();
+        Self { key, value, scope }
+    }
+
+    /// Builds the attribute from a typed value, e.g. a font size or a link. The `false` of the
+    /// toggles is the same as removing the attribute, like `RichTextAttribute::Bold(false)`.
+    pub fn typed(key: RichTextAttributeKey, value: TypedAttributeValue) -> Self {
+        Self::new(key, value.into())
+    }
+
     // inline
     inline_attribute!(Bold, bool);
     inline_attribute!(Italic, bool);
@@ -214,18 +257,25 @@ impl RichTextAttribute {
     inline_attribute!(StrikeThrough, bool);
     inline_attribute!(Link, &str);
     inline_attribute!(Color, String);
-    inline_attribute!(Font, usize);
+    inline_attribute!(Font, &str);
     inline_attribute!(Size, usize);
     inline_attribute!(Background, String);
     inline_attribute!(InlineCode, bool);
+    inline_attribute!(Suggestion, String);
+    inline_attribute!(Redacted, String);
+    inline_attribute!(Protected, bool);
 
     // block
     block_attribute!(Header, usize);
     block_attribute!(Indent, usize);
     block_attribute!(Align, String);
     block_attribute!(List, &str);
+    block_attribute!(ListNumber, usize);
     block_attribute!(CodeBlock, bool);
+    block_attribute!(CodeLanguage, &str);
     block_attribute!(BlockQuote, bool);
+    block_attribute!(LineHeight, usize);
+    block_attribute!(Direction, &str);
 
     // ignore
     ignore_attribute!(Width, usize);
@@ -237,11 +287,12 @@ impl RichTextAttribute {
     list_attribute!(Checked, "checked");
     list_attribute!(UnChecked, "unchecked");
 
+    #[cfg(feature = "json")]
     pub fn to_json(&self) -> String {
         match serde_json::to_string(self) {
             Ok(json) => json,
             Err(e) => {
-                log::error!("Attribute serialize to str failed: {}", e);
+                ot_log!(error, "Attribute serialize to str failed: {}", e);
                 "".to_owned()
             }
         }
@@ -255,6 +306,21 @@ impl fmt::Display for RichTextAttribute {
     }
 }
 
+impl RichTextAttributes {
+    /// Returns the value of `key`, parsed with the type of the key.
+    pub fn get_typed(&self, key: &RichTextAttributeKey) -> Option<TypedAttributeValue> {
+        self.inner.get(key)?.typed(key.value_type())
+    }
+
+    /// Returns the attributes one by one, the removed ones have an empty value.
+    pub fn to_attributes(&self) -> Vec<RichTextAttribute> {
+        self.inner
+            .iter()
+            .map(|(k, v)| RichTextAttribute::new(k.clone(), v.clone()))
+            .collect()
+    }
+}
+
 impl std::convert::From<RichTextAttribute> for RichTextAttributes {
     fn from(attr: RichTextAttribute) -> Self {
         let mut attributes = RichTextAttributes::new();
@@ -291,24 +357,173 @@ pub enum RichTextAttributeKey {
     Align,
     #[serde(rename = "code_block")]
     CodeBlock,
+    /// The language of the code in a code block, e.g. "rust".
+    #[serde(rename = "code_language")]
+    CodeLanguage,
     #[serde(rename = "code")]
     InlineCode,
     #[serde(rename = "list")]
     List,
+    /// The number of an item of an ordered list, kept up to date by the document.
+    #[serde(rename = "list_number")]
+    ListNumber,
     #[serde(rename = "blockquote")]
     BlockQuote,
+    /// The spacing of the lines, in percents of the font size.
+    #[serde(rename = "line_height")]
+    LineHeight,
+    /// The direction of the text of a line: "ltr", "rtl" or "auto" to follow its first strong
+    /// character.
+    #[serde(rename = "direction")]
+    Direction,
     #[serde(rename = "width")]
     Width,
     #[serde(rename = "height")]
     Height,
     #[serde(rename = "header")]
     Header,
+    #[serde(rename = "suggestion")]
+    Suggestion,
+    /// The placeholder of the text that a content policy redacted, the value is the name of the
+    /// policy.
+    #[serde(rename = "redacted")]
+    Redacted,
+    /// The text that is locked, e.g. the header of a template. It's only changed by the ones who
+    /// may unlock it.
+    #[serde(rename = "protected")]
+    Protected,
+}
+
+impl RichTextAttributeKey {
+    /// All the keys that the rich text supports.
+    pub fn all() -> Vec<RichTextAttributeKey> {
+        vec![
+            RichTextAttributeKey::Bold,
+            RichTextAttributeKey::Italic,
+            RichTextAttributeKey::Underline,
+            RichTextAttributeKey::StrikeThrough,
+            RichTextAttributeKey::Font,
+            RichTextAttributeKey::Size,
+            RichTextAttributeKey::Link,
+            RichTextAttributeKey::Color,
+            RichTextAttributeKey::Background,
+            RichTextAttributeKey::Indent,
+            RichTextAttributeKey::Align,
+            RichTextAttributeKey::CodeBlock,
+            RichTextAttributeKey::CodeLanguage,
+            RichTextAttributeKey::InlineCode,
+            RichTextAttributeKey::List,
+            RichTextAttributeKey::ListNumber,
+            RichTextAttributeKey::BlockQuote,
+            RichTextAttributeKey::LineHeight,
+            RichTextAttributeKey::Direction,
+            RichTextAttributeKey::Width,
+            RichTextAttributeKey::Height,
+            RichTextAttributeKey::Header,
+            RichTextAttributeKey::Suggestion,
+            RichTextAttributeKey::Redacted,
+            RichTextAttributeKey::Protected,
+        ]
+    }
+
+    /// The type of the values of the key. The values are kept as strings, the type decides how
+    /// they are parsed and serialized.
+    pub fn value_type(&self) -> AttributeValueType {
+        match self {
+            RichTextAttributeKey::Bold
+            | RichTextAttributeKey::Italic
+            | RichTextAttributeKey::Underline
+            | RichTextAttributeKey::StrikeThrough
+            | RichTextAttributeKey::CodeBlock
+            | RichTextAttributeKey::InlineCode
+            | RichTextAttributeKey::BlockQuote
+            | RichTextAttributeKey::Protected => AttributeValueType::Bool,
+            RichTextAttributeKey::Size
+            | RichTextAttributeKey::Header
+            | RichTextAttributeKey::Indent
+            | RichTextAttributeKey::ListNumber
+            | RichTextAttributeKey::LineHeight
+            | RichTextAttributeKey::Width
+            | RichTextAttributeKey::Height => AttributeValueType::Int,
+            RichTextAttributeKey::Link
+            | RichTextAttributeKey::Font
+            | RichTextAttributeKey::Color
+            | RichTextAttributeKey::Background
+            | RichTextAttributeKey::Align
+            | RichTextAttributeKey::CodeLanguage
+            | RichTextAttributeKey::List
+            | RichTextAttributeKey::Direction
+            | RichTextAttributeKey::Suggestion
+            | RichTextAttributeKey::Redacted => AttributeValueType::String,
+        }
+    }
+
+    pub fn scope(&self) -> AttributeScope {
+        if BLOCK_KEYS.contains(self) {
+            AttributeScope::Block
+        } else if INGORE_KEYS.contains(self) {
+            AttributeScope::Ignore
+        } else {
+            AttributeScope::Inline
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeValueType {
+    Bool,
+    Int,
+    String,
+}
+
+/// The value of an attribute, parsed with the type of its key. The values are kept as strings in
+/// [RichTextAttributeValue], the types only apply when the values are read, built and serialized.
+/// Compose, transform and invert replace or remove the value of each key as a whole, without
+/// looking into it, so they work on the strings and don't depend on the types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedAttributeValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
 }
 
 // pub trait AttributeValueData<'a>: Serialize + Deserialize<'a> {}
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RichTextAttributeValue(pub Option<String>);
 
+impl RichTextAttributeValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        self.0.as_ref()?.parse().ok()
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        self.0.as_ref()?.parse().ok()
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// Returns `None` if the value is removed or can't be parsed as `value_type`.
+    pub fn typed(&self, value_type: AttributeValueType) -> Option<TypedAttributeValue> {
+        match value_type {
+            AttributeValueType::Bool => self.as_bool().map(TypedAttributeValue::Bool),
+            AttributeValueType::Int => self.as_int().map(TypedAttributeValue::Int),
+            AttributeValueType::String => self.as_str().map(|s| TypedAttributeValue::String(s.to_owned())),
+        }
+    }
+}
+
+impl std::convert::From<TypedAttributeValue> for RichTextAttributeValue {
+    fn from(value: TypedAttributeValue) -> Self {
+        match value {
+            TypedAttributeValue::Bool(value) => value.into(),
+            TypedAttributeValue::Int(value) => RichTextAttributeValue(Some(format!("{}", value))),
+            TypedAttributeValue::String(value) => value.into(),
+        }
+    }
+}
+
 impl std::convert::From<&usize> for RichTextAttributeValue {
     fn from(val: &usize) -> Self {
         RichTextAttributeValue::from(*val)
@@ -357,6 +572,53 @@ impl std::convert::From<bool> for RichTextAttributeValue {
     }
 }
 
+/// An attribute of a whole line. It's kept in the attributes of the newline that ends the line,
+/// the same as Quill does.
+#[derive(Debug, Clone)]
+pub struct BlockAttribute(RichTextAttribute);
+
+impl BlockAttribute {
+    pub fn attribute(&self) -> &RichTextAttribute {
+        &self.0
+    }
+
+    /// The attributes to compose into the newline of a line. The header, the list, the code block
+    /// and the quote decide what kind of line it is, so setting one of them removes the others
+    /// instead of merging with them. The language of a code block goes with it.
+    pub fn to_line_attributes(&self) -> RichTextAttributes {
+        let mut attributes = RichTextAttributes::new();
+        if is_line_kind(&self.0.key) {
+            LINE_KIND_KEYS
+                .iter()
+                .filter(|key| *key != &self.0.key)
+                .for_each(|key| attributes.delete(key));
+            if self.0.key != RichTextAttributeKey::CodeBlock {
+                attributes.delete(&RichTextAttributeKey::CodeLanguage);
+            }
+        }
+        attributes.add(self.0.clone());
+        attributes
+    }
+}
+
+impl std::convert::TryFrom<RichTextAttribute> for BlockAttribute {
+    type Error = OTError;
+
+    fn try_from(attribute: RichTextAttribute) -> Result<Self, Self::Error> {
+        match attribute.scope {
+            AttributeScope::Block => Ok(BlockAttribute(attribute)),
+            _ => Err(ErrorBuilder::new(OTErrorCode::ApplyFormatFail)
+                .msg(format!("{} isn't a block attribute", attribute.key))
+                .build()),
+        }
+    }
+}
+
+/// Returns true if the key decides what kind of line it is, see [BlockAttribute::to_line_attributes].
+pub fn is_line_kind(k: &RichTextAttributeKey) -> bool {
+    LINE_KIND_KEYS.contains(k)
+}
+
 pub fn is_block_except_header(k: &RichTextAttributeKey) -> bool {
     if k == &RichTextAttributeKey::Header {
         return false;
@@ -370,9 +632,19 @@ lazy_static! {
         RichTextAttributeKey::Indent,
         RichTextAttributeKey::Align,
         RichTextAttributeKey::CodeBlock,
+        RichTextAttributeKey::CodeLanguage,
         RichTextAttributeKey::List,
+        RichTextAttributeKey::ListNumber,
         RichTextAttributeKey::BlockQuote,
+        RichTextAttributeKey::LineHeight,
+        RichTextAttributeKey::Direction,
     ]);
+    static ref LINE_KIND_KEYS: Vec<RichTextAttributeKey> = vec![
+        RichTextAttributeKey::Header,
+        RichTextAttributeKey::List,
+        RichTextAttributeKey::CodeBlock,
+        RichTextAttributeKey::BlockQuote,
+    ];
     static ref INLINE_KEYS: HashSet<RichTextAttributeKey> = HashSet::from_iter(vec![
         RichTextAttributeKey::Bold,
         RichTextAttributeKey::Italic,
@@ -384,6 +656,9 @@ lazy_static! {
         RichTextAttributeKey::Size,
         RichTextAttributeKey::Background,
         RichTextAttributeKey::InlineCode,
+        RichTextAttributeKey::Suggestion,
+        RichTextAttributeKey::Redacted,
+        RichTextAttributeKey::Protected,
     ]);
     static ref INGORE_KEYS: HashSet<RichTextAttributeKey> =
         HashSet::from_iter(vec![RichTextAttributeKey::Width, RichTextAttributeKey::Height,]);
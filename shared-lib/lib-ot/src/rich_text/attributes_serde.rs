@@ -1,5 +1,5 @@
 #[rustfmt::skip]
-use crate::rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextAttributeValue};
+use crate::rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes, RichTextAttributeValue, TypedAttributeValue};
 use serde::{
     de,
     de::{MapAccess, Visitor},
@@ -45,39 +45,14 @@ where
     S: SerializeMap,
     E: From<<S as SerializeMap>::Error>,
 {
-    if let Some(v) = &value.0 {
-        match key {
-            RichTextAttributeKey::Bold
-            | RichTextAttributeKey::Italic
-            | RichTextAttributeKey::Underline
-            | RichTextAttributeKey::StrikeThrough
-            | RichTextAttributeKey::CodeBlock
-            | RichTextAttributeKey::InlineCode
-            | RichTextAttributeKey::BlockQuote => match &v.parse::<bool>() {
-                Ok(value) => map_serializer.serialize_entry(&key, value)?,
-                Err(e) => log::error!("Serial {:?} failed. {:?}", &key, e),
-            },
-
-            RichTextAttributeKey::Font
-            | RichTextAttributeKey::Size
-            | RichTextAttributeKey::Header
-            | RichTextAttributeKey::Indent
-            | RichTextAttributeKey::Width
-            | RichTextAttributeKey::Height => match &v.parse::<i32>() {
-                Ok(value) => map_serializer.serialize_entry(&key, value)?,
-                Err(e) => log::error!("Serial {:?} failed. {:?}", &key, e),
-            },
-
-            RichTextAttributeKey::Link
-            | RichTextAttributeKey::Color
-            | RichTextAttributeKey::Background
-            | RichTextAttributeKey::Align
-            | RichTextAttributeKey::List => {
-                map_serializer.serialize_entry(&key, v)?;
-            }
-        }
-    } else {
-        map_serializer.serialize_entry(&key, "")?;
+    match value.typed(key.value_type()) {
+        Some(TypedAttributeValue::Bool(value)) => map_serializer.serialize_entry(&key, &value)?,
+        Some(TypedAttributeValue::Int(value)) => map_serializer.serialize_entry(&key, &value)?,
+        Some(TypedAttributeValue::String(value)) => map_serializer.serialize_entry(&key, &value)?,
+        None => match &value.0 {
+            None => map_serializer.serialize_entry(&key, "")?,
+            Some(v) => ot_log!(error, "Serial {:?} failed, invalid value: {}", &key, v),
+        },
     }
     Ok(())
 }
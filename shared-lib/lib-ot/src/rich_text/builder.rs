@@ -1,6 +1,6 @@
 #![allow(non_snake_case)]
 #![allow(clippy::derivable_impls)]
-use crate::rich_text::{RichTextAttribute, RichTextAttributes};
+use crate::rich_text::{RichTextAttribute, RichTextAttributeKey, RichTextAttributes};
 
 pub struct AttributeBuilder {
     inner: RichTextAttributes,
@@ -24,6 +24,12 @@ impl AttributeBuilder {
         self
     }
 
+    /// Removes the attribute from the text that the attributes are applied to.
+    pub fn remove_attr(mut self, key: RichTextAttributeKey) -> Self {
+        self.inner.delete(&key);
+        self
+    }
+
     pub fn build(self) -> RichTextAttributes {
         self.inner
     }
@@ -0,0 +1,455 @@
+use crate::{
+    core::Attributes,
+    rich_text::{RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Decides how the value that a change gives to an attribute is composed into the attributes of
+/// the text, and whether it's kept against a concurrent change, e.g. a header that clears the bold
+/// of its line or a link that overrides the color. The attributes without a rule use their
+/// built-in one: [ReplaceComposeRule] for the direction of the lines, [DefaultComposeRule] for the
+/// others.
+///
+/// The rules are given to a [ComposeConfig]: all the participants of a document must use the same
+/// ones, or their documents diverge.
+pub trait AttributeComposeRule: Send + Sync {
+    /// Composes `value`, which a change gives to `key`, into `attributes`. A rule may change the
+    /// other attributes too, a `None` value removes the attribute.
+    fn compose(&self, attributes: &mut RichTextAttributes, key: &RichTextAttributeKey, value: &RichTextAttributeValue) {
+        attributes.insert(key.clone(), value.clone());
+    }
+
+    /// Returns whether the value that a change gives to `key` is kept once the change is
+    /// transformed against `other`, the attributes of the concurrent change that doesn't give
+    /// another value to `key`. The concurrent values of `key` are resolved by
+    /// [AttributeComposeRule::conflict_policy].
+    fn keep_on_transform(&self, key: &RichTextAttributeKey, other: &RichTextAttributes) -> bool {
+        !other.contains_key(key)
+    }
+
+    /// Returns how the concurrent changes that give different values to `key` are resolved, the
+    /// policy of `config` by default.
+    fn conflict_policy(&self, _key: &RichTextAttributeKey, config: &ComposeConfig) -> AttributeConflictPolicy {
+        config.conflict_policy
+    }
+
+    /// Returns whether `value`, which a change gives to `key`, is kept against `other_value`, the
+    /// value of the concurrent change, or `None` if neither is and the attribute is removed. The
+    /// change has the `priority` or not, see [AttributeConflictPolicy]. The rules that look at
+    /// the values must keep exactly one of them, or remove both, for the documents to converge.
+    fn keep_on_conflict(
+        &self,
+        key: &RichTextAttributeKey,
+        _value: &RichTextAttributeValue,
+        _other_value: &RichTextAttributeValue,
+        priority: bool,
+        config: &ComposeConfig,
+    ) -> Option<bool> {
+        self.conflict_policy(key, config).keep(priority)
+    }
+}
+
+/// The attribute follows the [AttributeConflictPolicy] of the [ComposeConfig].
+pub struct DefaultComposeRule();
+impl AttributeComposeRule for DefaultComposeRule {}
+
+/// The checkboxes are the lines whose list is `checked` or `unchecked`. The concurrent toggles of a
+/// checkbox follow the [CheckboxConflictPolicy], instead of removing the list like the default
+/// [AttributeConflictPolicy] does. The other values of the list follow the policy of the
+/// [ComposeConfig].
+pub struct CheckboxComposeRule();
+impl AttributeComposeRule for CheckboxComposeRule {
+    fn keep_on_conflict(
+        &self,
+        key: &RichTextAttributeKey,
+        value: &RichTextAttributeValue,
+        other_value: &RichTextAttributeValue,
+        priority: bool,
+        config: &ComposeConfig,
+    ) -> Option<bool> {
+        let checked = match (is_checkbox(value), is_checkbox(other_value)) {
+            (Some(checked), Some(_)) => checked,
+            _ => return self.conflict_policy(key, config).keep(priority),
+        };
+        match config.checkbox_conflict_policy {
+            CheckboxConflictPolicy::CheckedWins => Some(checked),
+            CheckboxConflictPolicy::LastToggleWins => Some(!priority),
+        }
+    }
+}
+
+// Whether the list value is a checked checkbox, none if it isn't a checkbox.
+fn is_checkbox(value: &RichTextAttributeValue) -> Option<bool> {
+    match value.0.as_deref() {
+        Some("checked") => Some(true),
+        Some("unchecked") => Some(false),
+        _ => None,
+    }
+}
+
+/// How the concurrent toggles of a checkbox are resolved, e.g. one user checks a task while another
+/// one unchecks it. Like the [AttributeConflictPolicy], it must be the same for all the
+/// participants of a document, see [ComposeConfig].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckboxConflictPolicy {
+    /// The checkbox is checked if one of the changes checks it.
+    CheckedWins,
+    /// The toggle that comes last in the order of the revisions wins.
+    LastToggleWins,
+}
+
+impl std::default::Default for CheckboxConflictPolicy {
+    fn default() -> Self {
+        CheckboxConflictPolicy::CheckedWins
+    }
+}
+
+/// The value of the change that has the priority replaces the concurrent one, whatever the
+/// [AttributeConflictPolicy], so the participants end up with one of the values, e.g. one direction
+/// for a line.
+pub struct ReplaceComposeRule();
+impl AttributeComposeRule for ReplaceComposeRule {
+    fn conflict_policy(&self, _key: &RichTextAttributeKey, _config: &ComposeConfig) -> AttributeConflictPolicy {
+        AttributeConflictPolicy::ServerWins
+    }
+}
+
+/// How the concurrent changes that give different values to the same attribute of the same text
+/// are resolved, e.g. one user makes it red and another one blue. Like the rules, the policy must
+/// be the same for all the participants of a document, see [ComposeConfig].
+///
+/// The policies decide by the side of the changes in the transform, not by the time or the order
+/// the changes were made in: the change that has the priority is the left one of
+/// [crate::core::Delta::transform_with_priority]. The server gives it to the revisions it already
+/// had, the clients to the revisions of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeConflictPolicy {
+    /// The value of the change that doesn't have the priority wins, i.e. the change of the client
+    /// that the server transforms, which the server receives after the other one.
+    LastWriterWins,
+    /// The value of the change that has the priority wins, i.e. the one the server already had.
+    ServerWins,
+    /// Neither value wins and the attribute is removed, only the attributes that one change sets
+    /// and the other doesn't are merged.
+    MergeNonOverlapping,
+}
+
+impl AttributeConflictPolicy {
+    // Whether the value of the change on the side of `priority` is kept, none if it's removed.
+    fn keep(&self, priority: bool) -> Option<bool> {
+        match self {
+            AttributeConflictPolicy::LastWriterWins => Some(!priority),
+            AttributeConflictPolicy::ServerWins => Some(priority),
+            AttributeConflictPolicy::MergeNonOverlapping => None,
+        }
+    }
+}
+
+impl std::default::Default for AttributeConflictPolicy {
+    fn default() -> Self {
+        AttributeConflictPolicy::MergeNonOverlapping
+    }
+}
+
+/// How the attributes of the changes of a document are composed and transformed, e.g. the rules of
+/// the attributes and the policy of their concurrent values. It's given to
+/// [crate::core::Delta::compose_with] and [crate::core::Delta::transform_with], the composes and
+/// the transforms without one use the default. All the participants of a document must use the
+/// same one, or their documents diverge.
+///
+/// Two configs are equal if they have the same policies and the same rules, i.e. the same
+/// instances of the rules.
+#[derive(Clone, Default)]
+pub struct ComposeConfig {
+    /// See [AttributeConflictPolicy], the rules may override it for their attribute.
+    pub conflict_policy: AttributeConflictPolicy,
+    /// See [CheckboxConflictPolicy], unless a rule replaces the [CheckboxComposeRule] of the list.
+    pub checkbox_conflict_policy: CheckboxConflictPolicy,
+    rules: HashMap<RichTextAttributeKey, Arc<dyn AttributeComposeRule>>,
+}
+
+impl ComposeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_conflict_policy(mut self, conflict_policy: AttributeConflictPolicy) -> Self {
+        self.conflict_policy = conflict_policy;
+        self
+    }
+
+    pub fn with_checkbox_conflict_policy(mut self, checkbox_conflict_policy: CheckboxConflictPolicy) -> Self {
+        self.checkbox_conflict_policy = checkbox_conflict_policy;
+        self
+    }
+
+    /// Makes `rule` decide how `key` is composed and transformed, instead of its built-in rule or
+    /// the rule that was given for it before.
+    pub fn with_rule(mut self, key: RichTextAttributeKey, rule: Arc<dyn AttributeComposeRule>) -> Self {
+        self.rules.insert(key, rule);
+        self
+    }
+
+    // The rule that was given for `key`, or its built-in one.
+    fn rule(&self, key: &RichTextAttributeKey) -> &dyn AttributeComposeRule {
+        match self.rules.get(key) {
+            None => builtin_rule(key),
+            Some(rule) => rule.as_ref(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ComposeConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut keys = self.rules.keys().map(|key| key.to_string()).collect::<Vec<_>>();
+        keys.sort();
+        f.debug_struct("ComposeConfig")
+            .field("conflict_policy", &self.conflict_policy)
+            .field("checkbox_conflict_policy", &self.checkbox_conflict_policy)
+            .field("rules", &keys)
+            .finish()
+    }
+}
+
+impl PartialEq for ComposeConfig {
+    fn eq(&self, other: &Self) -> bool {
+        let same_rule = |rule: &Arc<dyn AttributeComposeRule>, other_rule: &Arc<dyn AttributeComposeRule>| {
+            Arc::as_ptr(rule) as *const u8 == Arc::as_ptr(other_rule) as *const u8
+        };
+        self.conflict_policy == other.conflict_policy
+            && self.checkbox_conflict_policy == other.checkbox_conflict_policy
+            && self.rules.len() == other.rules.len()
+            && self.rules.iter().all(|(key, rule)| {
+                other
+                    .rules
+                    .get(key)
+                    .map_or(false, |other_rule| same_rule(rule, other_rule))
+            })
+    }
+}
+
+impl Eq for ComposeConfig {}
+
+static DEFAULT_RULE: DefaultComposeRule = DefaultComposeRule();
+static REPLACE_RULE: ReplaceComposeRule = ReplaceComposeRule();
+static CHECKBOX_RULE: CheckboxComposeRule = CheckboxComposeRule();
+
+fn builtin_rule(key: &RichTextAttributeKey) -> &'static dyn AttributeComposeRule {
+    match key {
+        RichTextAttributeKey::Direction => &REPLACE_RULE,
+        RichTextAttributeKey::List => &CHECKBOX_RULE,
+        _ => &DEFAULT_RULE,
+    }
+}
+
+pub(crate) fn compose_with_rules(
+    attributes: &mut RichTextAttributes,
+    other: &RichTextAttributes,
+    config: &ComposeConfig,
+) {
+    // The built-in rules all compose like the default one.
+    if config.rules.is_empty() {
+        attributes.extend(other.iter().map(|(k, v)| (k.clone(), v.clone())));
+        return;
+    }
+    for (key, value) in other.iter() {
+        config.rule(key).compose(attributes, key, value);
+    }
+}
+
+// Keeps the attributes of `attributes` that survive the concurrent `other`. The concurrent values
+// of the same attribute are resolved for the side that has the `priority` or for the other one.
+pub(crate) fn transform_with_rules(
+    attributes: &RichTextAttributes,
+    other: &RichTextAttributes,
+    priority: bool,
+    config: &ComposeConfig,
+) -> RichTextAttributes {
+    let conflicts = attributes.diff(other).changed;
+    let mut transformed = RichTextAttributes::new();
+    for (key, value) in attributes.iter() {
+        let rule = config.rule(key);
+        let keep = if let Some(other_value) = conflicts.get(key) {
+            match rule.keep_on_conflict(key, value, other_value, priority, config) {
+                Some(keep) => keep,
+                None => {
+                    transformed.delete(key);
+                    continue;
+                }
+            }
+        } else {
+            rule.keep_on_transform(key, other)
+        };
+        if keep {
+            transformed.insert(key.clone(), value.clone());
+        }
+    }
+    transformed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{Attributes, OperationTransformable, Priority},
+        rich_text::{
+            AttributeBuilder, AttributeComposeRule, AttributeConflictPolicy, CheckboxConflictPolicy, ComposeConfig,
+            RichTextAttribute, RichTextAttributeKey, RichTextAttributeValue, RichTextAttributes, RichTextDeltaBuilder,
+        },
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn diff_attributes() {
+        let before = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Bold(true))
+            .add_attr(RichTextAttribute::Color("red".to_owned()))
+            .build();
+        let after = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Color("blue".to_owned()))
+            .add_attr(RichTextAttribute::Italic(true))
+            .build();
+        let change = before.diff(&after);
+        assert_eq!(change.added.explain(), "italic");
+        assert_eq!(change.removed.explain(), "bold");
+        assert_eq!(change.changed.explain(), "color: blue");
+        assert!(after.diff(&after).is_empty());
+        // Inverting `after` on `before` restores the old color and removes the italic.
+        assert_eq!(after.invert(&before).explain(), "color: red, italic: null");
+    }
+
+    // The font clears the background of the text.
+    struct FontClearsBackground();
+    impl AttributeComposeRule for FontClearsBackground {
+        fn compose(
+            &self,
+            attributes: &mut RichTextAttributes,
+            key: &RichTextAttributeKey,
+            value: &RichTextAttributeValue,
+        ) {
+            attributes.insert(key.clone(), value.clone());
+            attributes.delete(&RichTextAttributeKey::Background);
+        }
+    }
+
+    #[test]
+    fn rule_of_the_config_is_consulted() {
+        let text = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Background("red".to_owned()))
+            .add_attr(RichTextAttribute::Bold(true))
+            .build();
+        let font = AttributeBuilder::new()
+            .add_attr(RichTextAttribute::Font("serif"))
+            .build();
+
+        let config = ComposeConfig::new().with_rule(RichTextAttributeKey::Font, Arc::new(FontClearsBackground()));
+        let mut composed = text.compose_with(&font, &config).unwrap();
+        composed.retain(|_, value| value.0.is_some());
+        assert_eq!(
+            composed,
+            AttributeBuilder::new()
+                .add_attr(RichTextAttribute::Bold(true))
+                .add_attr(RichTextAttribute::Font("serif"))
+                .build()
+        );
+        // The composes with another config keep the built-in rule.
+        assert_eq!(text.compose(&font).unwrap().len(), 3);
+        assert_ne!(config, ComposeConfig::default());
+        assert_eq!(config.clone(), config);
+    }
+
+    #[test]
+    fn concurrent_values_converge_under_each_policy() {
+        let attributes = |attributes: Vec<RichTextAttribute>| {
+            attributes
+                .into_iter()
+                .fold(AttributeBuilder::new(), |builder, attribute| {
+                    builder.add_attr(attribute)
+                })
+                .build()
+        };
+        let red = || RichTextAttribute::Color("red".to_owned());
+        let blue = || RichTextAttribute::Color("blue".to_owned());
+        let document = RichTextDeltaBuilder::new().insert("abcdef").build();
+        // The changes overlap on "cd", where they give different colors to the text.
+        let a = RichTextDeltaBuilder::new()
+            .retain_with_attributes(4, attributes(vec![red(), RichTextAttribute::Bold(true)]))
+            .retain(2)
+            .build();
+        let b = RichTextDeltaBuilder::new()
+            .retain(2)
+            .retain_with_attributes(4, attributes(vec![blue(), RichTextAttribute::Italic(true)]))
+            .build();
+
+        let policies = vec![
+            (AttributeConflictPolicy::LastWriterWins, Some(blue())),
+            (AttributeConflictPolicy::ServerWins, Some(red())),
+            (AttributeConflictPolicy::MergeNonOverlapping, None),
+        ];
+        for (policy, color) in policies {
+            let config = ComposeConfig::new().with_conflict_policy(policy);
+            let (a_prime, b_prime) = a.transform_with(&b, Priority::Left, &config).unwrap();
+            let left = document.compose(&a).unwrap().compose(&b_prime).unwrap();
+            let right = document.compose(&b).unwrap().compose(&a_prime).unwrap();
+
+            let mut overlap = vec![RichTextAttribute::Bold(true), RichTextAttribute::Italic(true)];
+            overlap.extend(color);
+            let expected = RichTextDeltaBuilder::new()
+                .insert_with_attributes("ab", attributes(vec![red(), RichTextAttribute::Bold(true)]))
+                .insert_with_attributes("cd", attributes(overlap))
+                .insert_with_attributes("ef", attributes(vec![blue(), RichTextAttribute::Italic(true)]))
+                .build();
+            assert_eq!(left, expected, "{:?}", policy);
+            assert_eq!(right, expected, "{:?}", policy);
+        }
+        // The transforms without a config keep resolving by the default policy.
+        assert_eq!(
+            a.transform(&b).unwrap(),
+            a.transform_with(&b, Priority::Left, &ComposeConfig::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn concurrent_toggles_of_a_checkbox_converge() {
+        let document = RichTextDeltaBuilder::new()
+            .insert("task")
+            .insert_with_attributes("\n", RichTextAttribute::UnChecked(true).into())
+            .build();
+        let toggle = |attribute: RichTextAttribute| {
+            RichTextDeltaBuilder::new()
+                .retain(4)
+                .retain_with_attributes(1, attribute.into())
+                .build()
+        };
+        // One user checks the task and unchecks it again, while the other one checks it.
+        let a = toggle(RichTextAttribute::UnChecked(true));
+        let b = toggle(RichTextAttribute::Checked(true));
+
+        let policies = vec![
+            (CheckboxConflictPolicy::CheckedWins, RichTextAttribute::Checked(true)),
+            (CheckboxConflictPolicy::LastToggleWins, RichTextAttribute::Checked(true)),
+        ];
+        for (policy, checkbox) in policies {
+            let config = ComposeConfig::new().with_checkbox_conflict_policy(policy);
+            let (a_prime, b_prime) = a.transform_with(&b, Priority::Left, &config).unwrap();
+            let left = document.compose(&a).unwrap().compose(&b_prime).unwrap();
+            let right = document.compose(&b).unwrap().compose(&a_prime).unwrap();
+
+            let expected = RichTextDeltaBuilder::new()
+                .insert("task")
+                .insert_with_attributes("\n", checkbox.into())
+                .build();
+            assert_eq!(left, expected, "{:?}", policy);
+            assert_eq!(right, expected, "{:?}", policy);
+        }
+
+        // The other way around, the last toggle unchecks it.
+        let config = ComposeConfig::new().with_checkbox_conflict_policy(CheckboxConflictPolicy::LastToggleWins);
+        let (b_prime, a_prime) = b.transform_with(&a, Priority::Left, &config).unwrap();
+        let expected = RichTextDeltaBuilder::new()
+            .insert("task")
+            .insert_with_attributes("\n", RichTextAttribute::UnChecked(true).into())
+            .build();
+        assert_eq!(document.compose(&b).unwrap().compose(&a_prime).unwrap(), expected);
+        assert_eq!(document.compose(&a).unwrap().compose(&b_prime).unwrap(), expected);
+    }
+}
@@ -1,6 +1,7 @@
 mod attributes;
 mod attributes_serde;
 mod builder;
+mod compose_rule;
 
 #[macro_use]
 mod macros;
@@ -8,4 +9,5 @@ mod delta;
 
 pub use attributes::*;
 pub use builder::*;
+pub use compose_rule::*;
 pub use delta::*;
@@ -0,0 +1,284 @@
+//! Random deltas and the properties that the OT must keep for them. A new kind of operation or
+//! attribute can be checked against thousands of generated cases with [check_ot_properties],
+//! after implementing [RandomAttributes] for its attributes.
+use crate::{
+    core::{Attributes, Delta, OperationTransformable, PlainAttributes},
+    rich_text::{AttributeValueType, RichTextAttribute, RichTextAttributeKey, RichTextAttributes, TypedAttributeValue},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng as _, SeedableRng};
+use std::fmt::Write;
+
+/// The random generator of the deltas. It keeps its seed, so a failing case can be run again
+/// with [Rng::from_seed].
+pub struct Rng {
+    inner: StdRng,
+    seed: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::from_seed(rand::random())
+    }
+}
+
+impl Rng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            inner: StdRng::seed_from_u64(seed),
+            seed,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns `len` random hex digits, so that every char is one utf16 code unit.
+    pub fn gen_string(&mut self, len: usize) -> String {
+        let mut s = String::with_capacity(len);
+        for _ in 0..len {
+            let _ = write!(s, "{:x}", self.inner.gen_range(0..16));
+        }
+        s
+    }
+
+    /// Returns a document, a delta of inserts only, with `len` utf16 code units.
+    pub fn gen_document<T: RandomAttributes>(&mut self, len: usize) -> Delta<T> {
+        let mut document = Delta::default();
+        while document.utf16_target_len < len {
+            let n = self.gen_len(len - document.utf16_target_len);
+            let attributes = T::gen_insert(self);
+            document.insert(&self.gen_string(n), attributes);
+        }
+        document
+    }
+
+    /// Returns a delta that can be composed with `base`, i.e. its base length is the length of
+    /// the text `base` produces.
+    pub fn gen_delta<T: RandomAttributes>(&mut self, base: &Delta<T>) -> Delta<T> {
+        let len = base.utf16_target_len;
+        let mut delta = Delta::default();
+        while delta.utf16_base_len < len {
+            let n = self.gen_len(len - delta.utf16_base_len);
+            match self.inner.gen_range(0.0..1.0) {
+                f if f < 0.2 => {
+                    let attributes = T::gen_insert(self);
+                    delta.insert(&self.gen_string(n), attributes);
+                }
+                f if f < 0.4 => delta.delete(n),
+                f if f < 0.6 => {
+                    let attributes = T::gen_retain(self);
+                    delta.retain(n, attributes);
+                }
+                _ => delta.retain(n, T::default()),
+            }
+        }
+        if self.inner.gen_bool(0.3) {
+            let n = 1 + self.inner.gen_range(0..10);
+            let attributes = T::gen_insert(self);
+            delta.insert(&self.gen_string(n), attributes);
+        }
+        delta
+    }
+
+    fn gen_len(&mut self, left: usize) -> usize {
+        if left <= 1 {
+            left
+        } else {
+            1 + self.inner.gen_range(0..std::cmp::min(left - 1, 20))
+        }
+    }
+}
+
+impl std::ops::Deref for Rng {
+    type Target = StdRng;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl std::ops::DerefMut for Rng {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// The attributes that [Rng] can put in the deltas it generates.
+pub trait RandomAttributes: Attributes {
+    /// Returns the attributes of an insert.
+    fn gen_insert(rng: &mut Rng) -> Self;
+
+    /// Returns the attributes of a retain, which may also remove attributes.
+    fn gen_retain(rng: &mut Rng) -> Self {
+        Self::gen_insert(rng)
+    }
+}
+
+impl RandomAttributes for PlainAttributes {
+    fn gen_insert(_rng: &mut Rng) -> Self {
+        PlainAttributes::default()
+    }
+}
+
+impl RandomAttributes for RichTextAttributes {
+    fn gen_insert(rng: &mut Rng) -> Self {
+        gen_rich_text_attributes(rng, false)
+    }
+
+    fn gen_retain(rng: &mut Rng) -> Self {
+        gen_rich_text_attributes(rng, true)
+    }
+}
+
+fn gen_rich_text_attributes(rng: &mut Rng, removable: bool) -> RichTextAttributes {
+    let mut attributes = RichTextAttributes::new();
+    let count = rng.gen_range(0..3);
+    for key in RichTextAttributeKey::all().choose_multiple(&mut rng.inner, count) {
+        if removable && rng.gen_bool(0.3) {
+            attributes.delete(key);
+            continue;
+        }
+        let value = match key.value_type() {
+            AttributeValueType::Bool => TypedAttributeValue::Bool(true),
+            AttributeValueType::Int => TypedAttributeValue::Int(rng.gen_range(1..8)),
+            AttributeValueType::String => TypedAttributeValue::String(rng.gen_string(6)),
+        };
+        attributes.add(RichTextAttribute::typed(key.clone(), value));
+    }
+    attributes
+}
+
+/// Checks that `a` and `b`, both made on top of `document`, reach the same document whichever
+/// of them is applied first: `document + a + b' = document + b + a'`.
+pub fn check_transform_converges<T: Attributes>(document: &Delta<T>, a: &Delta<T>, b: &Delta<T>) -> Result<(), String> {
+    let (a_prime, b_prime) = a.transform(b).map_err(|e| format!("transform failed: {:?}", e))?;
+    let left = compose_all(document, &[a, &b_prime])?;
+    let right = compose_all(document, &[b, &a_prime])?;
+    if !left.content_eq(&right) {
+        return Err(format!(
+            "transform doesn't converge\ndocument: {}\na: {}\nb: {}\na': {}\nb': {}\ndocument + a + b': {}\ndocument + b + a': {}",
+            document, a, b, a_prime, b_prime, left, right
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `(a + b) + c = a + (b + c)`, where `a` is made on top of `document`, `b` on top
+/// of `document + a` and `c` on top of `document + a + b`.
+pub fn check_compose_associative<T: Attributes>(
+    document: &Delta<T>,
+    a: &Delta<T>,
+    b: &Delta<T>,
+    c: &Delta<T>,
+) -> Result<(), String> {
+    let compose = |x: &Delta<T>, y: &Delta<T>| x.compose(y).map_err(|e| format!("compose failed: {:?}", e));
+    let left = compose(&compose(a, b)?, c)?;
+    let right = compose(a, &compose(b, c)?)?;
+    let left_document = compose(document, &left)?;
+    let right_document = compose(document, &right)?;
+    if left_document != right_document {
+        return Err(format!(
+            "compose isn't associative\ndocument: {}\na: {}\nb: {}\nc: {}\n(a + b) + c: {}\na + (b + c): {}",
+            document, a, b, c, left, right
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the inverse of `delta` restores `document`: `document + delta + undo = document`.
+pub fn check_invert_restores<T: Attributes>(document: &Delta<T>, delta: &Delta<T>) -> Result<(), String> {
+    let undo = delta.invert(document);
+    let restored = compose_all(document, &[delta, &undo])?;
+    if &restored != document {
+        return Err(format!(
+            "invert doesn't restore the document\ndocument: {}\ndelta: {}\nundo: {}\nrestored: {}",
+            document, delta, undo, restored
+        ));
+    }
+    Ok(())
+}
+
+pub fn assert_transform_converges<T: Attributes>(document: &Delta<T>, a: &Delta<T>, b: &Delta<T>) {
+    if let Err(msg) = check_transform_converges(document, a, b) {
+        panic!("{}", msg);
+    }
+}
+
+pub fn assert_compose_associative<T: Attributes>(document: &Delta<T>, a: &Delta<T>, b: &Delta<T>, c: &Delta<T>) {
+    if let Err(msg) = check_compose_associative(document, a, b, c) {
+        panic!("{}", msg);
+    }
+}
+
+pub fn assert_invert_restores<T: Attributes>(document: &Delta<T>, delta: &Delta<T>) {
+    if let Err(msg) = check_invert_restores(document, delta) {
+        panic!("{}", msg);
+    }
+}
+
+/// Runs the checks of the transform, the compose and the invert against `cases` random
+/// documents and deltas. Panics with the seed of the failing case, which
+/// [check_ot_properties_with_seed] runs again.
+pub fn check_ot_properties<T: RandomAttributes>(cases: usize) {
+    let mut seeds = Rng::default();
+    for _ in 0..cases {
+        check_ot_properties_with_seed::<T>(seeds.gen());
+    }
+}
+
+pub fn check_ot_properties_with_seed<T: RandomAttributes>(seed: u64) {
+    let mut rng = Rng::from_seed(seed);
+    let len = rng.gen_range(1..100);
+    let document: Delta<T> = rng.gen_document(len);
+
+    let a = rng.gen_delta(&document);
+    let b = rng.gen_delta(&document);
+    let after_a = compose_all(&document, &[&a]).unwrap_or_default();
+    let b_after_a = rng.gen_delta(&after_a);
+    let after_b = compose_all(&after_a, &[&b_after_a]).unwrap_or_default();
+    let c = rng.gen_delta(&after_b);
+
+    let result = check_transform_converges(&document, &a, &b)
+        .and_then(|_| check_compose_associative(&document, &a, &b_after_a, &c))
+        .and_then(|_| check_invert_restores(&document, &a));
+    if let Err(msg) = result {
+        panic!("{}\nseed: {}", msg, seed);
+    }
+}
+
+fn compose_all<T: Attributes>(document: &Delta<T>, deltas: &[&Delta<T>]) -> Result<Delta<T>, String> {
+    deltas.iter().try_fold(document.clone(), |document, delta| {
+        document
+            .compose(delta)
+            .map_err(|e| format!("compose failed: {:?}\ndocument: {}\ndelta: {}", e, document, delta))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rich_text::RichTextDelta;
+
+    #[test]
+    fn generated_delta_fits_the_document() {
+        let mut rng = Rng::from_seed(7);
+        for _ in 0..100 {
+            let len = rng.gen_range(0..50);
+            let document: RichTextDelta = rng.gen_document(len);
+            assert_eq!(document.utf16_target_len, len);
+            let delta = rng.gen_delta(&document);
+            assert_eq!(delta.utf16_base_len, len);
+        }
+    }
+
+    #[test]
+    fn plain_text_ot_properties() {
+        check_ot_properties::<PlainAttributes>(1000);
+    }
+
+    #[test]
+    fn rich_text_ot_properties() {
+        check_ot_properties::<RichTextAttributes>(1000);
+    }
+}